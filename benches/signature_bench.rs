@@ -0,0 +1,81 @@
+//! Signature verification benchmarks
+//!
+//! Compares verifying the 1000 input signatures of a block one at a time with
+//! verifying them in batches over all cores.
+
+use consensus::consensus::validation::signature::{transaction_signature_checks, verify_batch, verify_transaction_signatures};
+use consensus_core::hashing::{calc_sighash, SigHashReusedValues, SigHashType};
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput, UtxoEntry};
+use consensus_core::utxo::{UtxoCollection, UtxoView};
+use consensus_core::Hash;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ripemd::Ripemd160;
+use secp256k1::{Message, Secp256k1, SecretKey};
+use sha2::{Digest, Sha256};
+
+const TRANSACTIONS: u8 = 100;
+const INPUTS: u32 = 10;
+
+/// Transactions of `INPUTS` signed inputs each and the outputs they spend
+fn signed_block() -> (Vec<Transaction>, UtxoCollection) {
+    let secp = Secp256k1::new();
+    let mut utxos = UtxoCollection::new();
+    let txs = (1..=TRANSACTIONS)
+        .map(|n| {
+            let secret = SecretKey::from_slice(&[n; 32]).unwrap();
+            let public_key = secret.public_key(&secp).serialize();
+            let hash = Ripemd160::digest(Sha256::digest(public_key));
+            let script = ScriptPublicKey::from_vec(0, [&[0x76, 0xa9, 0x14][..], hash.as_slice(), &[0x88, 0xac]].concat());
+            let inputs = (0..INPUTS)
+                .map(|i| {
+                    let outpoint = TransactionOutpoint::new(Hash::from_bytes([n; 32]), i);
+                    utxos.insert(outpoint, UtxoEntry::new(1_000, script.clone(), 0, false));
+                    TransactionInput::new(outpoint, vec![], 0, 1)
+                })
+                .collect();
+            let output = TransactionOutput::new(900 * INPUTS as u64, script.clone());
+            let mut tx = Transaction::new(0, inputs, vec![output], 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+            let mut reused = SigHashReusedValues::new();
+            for i in 0..tx.inputs.len() {
+                let entry = utxos.get(&tx.inputs[i].previous_outpoint).unwrap();
+                let sighash = calc_sighash(&tx, i, entry, SigHashType::SighashAll, &mut reused);
+                let message = Message::from_slice(&sighash.as_bytes()).unwrap();
+                let mut signature = secp.sign_ecdsa(&message, &secret).serialize_der().to_vec();
+                signature.push(SigHashType::SighashAll.to_u8());
+                tx.inputs[i].signature_script = [&[signature.len() as u8][..], &signature, &[33], &public_key].concat();
+            }
+            tx.finalize();
+            tx
+        })
+        .collect();
+    (txs, utxos)
+}
+
+fn bench_block_signatures(c: &mut Criterion) {
+    let (txs, utxos) = signed_block();
+    let view = UtxoView::new(&utxos);
+    let checks: Vec<_> = txs.iter().flat_map(|tx| transaction_signature_checks(tx, &view).unwrap()).collect();
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+
+    let mut group = c.benchmark_group("block_signatures");
+    group.throughput(Throughput::Elements(checks.len() as u64));
+    group.bench_function("individual", |b| {
+        b.iter(|| {
+            for tx in &txs {
+                verify_transaction_signatures(black_box(tx), &view).unwrap();
+            }
+        })
+    });
+    group.bench_function("batched", |b| b.iter(|| verify_batch(black_box(&checks), threads).unwrap()));
+    group.bench_function("collect_and_batch", |b| {
+        b.iter(|| {
+            let checks: Vec<_> = txs.iter().flat_map(|tx| transaction_signature_checks(tx, &view).unwrap()).collect();
+            verify_batch(&checks, threads).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_block_signatures);
+criterion_main!(benches);
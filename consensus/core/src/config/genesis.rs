@@ -2,6 +2,7 @@ use crate::{block::Block, header::Header, subnets::SUBNETWORK_ID_COINBASE, tx::{
 use crate::{Hash, ZERO_HASH};
 use crate::merkle::MerkleTree;
 use crate::constants::{INITIAL_BLOCK_REWARD, SOMPI_PER_JIO};
+use crate::network::NetworkType;
 
 /// The constants uniquely representing the genesis block
 #[derive(Clone, Debug)]
@@ -70,50 +71,39 @@ impl From<(&Header, &'static [u8])> for GenesisBlock {
     }
 }
 
-// A simple default genesis for mainnet/dev purposes.
-// Update these values to match the canonical genesis for each network.
+/// Genesis of mainnet
 pub fn default_genesis() -> GenesisBlock {
-    // Deterministic canonical genesis generation
-    static COINBASE_PAYLOAD: &[u8] = b"Jio deterministic genesis - 2025-11-12";
-
-    // Build the coinbase transaction used for merkle root calculation
-    let reward = INITIAL_BLOCK_REWARD * SOMPI_PER_JIO;
-    let coinbase_tx = Transaction::new(
-        0,
-        Vec::new(),
-        vec![TransactionOutput::new(reward, ScriptPublicKey::from_vec(0, Vec::new()))],
-        0,
-        SUBNETWORK_ID_COINBASE,
-        0,
-        COINBASE_PAYLOAD.to_vec(),
-    );
-
-    // Compute merkle root from the coinbase transaction hash
-    let tx_hash = coinbase_tx.id();
-    let merkle_root = MerkleTree::from_hashes(vec![tx_hash]).root();
-
-    // Use the tx hash as a simple utxo commitment placeholder
-    let utxo_commitment = tx_hash;
-
-    use crate::header::Header;
-    use crate::BlueWorkType;
+    genesis(NetworkType::Mainnet)
+}
 
-    // Use the canonical testnet genesis values (timestamp/nonce/bits) so the
-    // serialized genesis is stable for testnet and matches pre-mined values.
-    let header = Header::new_finalized(
-        1,                    // version
-        Vec::new(),           // parents_by_level
-        merkle_root,          // hash_merkle_root
-        ZERO_HASH,            // accepted_id_merkle_root
-        utxo_commitment,      // utxo_commitment
-        1762971421786u64,     // timestamp (ms since epoch) - user-specified
-        0x1f00_ffff,          // bits
-        38922u64,             // nonce (user-specified)
-        0,                    // daa_score
-        BlueWorkType::from(0u64),
-        0,
-        ZERO_HASH,
-    );
+/// Genesis of `network`. Networks differ in the coinbase payload, so each one
+/// has its own genesis hash and nodes of different networks never share a DAG.
+/// Only mainnet's nonce was mined; genesis is trusted by its hash, not its
+/// proof of work.
+pub fn genesis(network: NetworkType) -> GenesisBlock {
+    let (coinbase_payload, nonce): (&'static [u8], u64) = match network {
+        NetworkType::Mainnet => (b"Jio deterministic genesis - 2025-11-12", 38922),
+        NetworkType::Testnet => (b"Jio testnet genesis - 2025-11-12", 0),
+        NetworkType::Devnet => (b"Jio devnet genesis - 2025-11-12", 0),
+        NetworkType::Simnet => (b"Jio simnet genesis - 2025-11-12", 0),
+    };
+    let mut genesis = GenesisBlock {
+        hash: ZERO_HASH,
+        version: 1,
+        hash_merkle_root: ZERO_HASH,
+        utxo_commitment: ZERO_HASH,
+        timestamp: 1762971421786,
+        bits: 0x1f00_ffff,
+        nonce,
+        daa_score: 0,
+        coinbase_payload,
+    };
 
-    GenesisBlock::from((&header, COINBASE_PAYLOAD))
+    // The merkle root commits to the coinbase, whose id also stands in for the
+    // UTXO commitment
+    let tx_hash = genesis.build_genesis_transactions()[0].id();
+    genesis.hash_merkle_root = MerkleTree::from_hashes(vec![tx_hash]).root();
+    genesis.utxo_commitment = tx_hash;
+    genesis.hash = Header::from(&genesis).hash;
+    genesis
 }
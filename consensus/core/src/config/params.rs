@@ -37,4 +37,8 @@ pub struct Params {
     pub mass_per_sig_op: u64,
     /// Storage mass parameter (storm parameter)
     pub storage_mass_parameter: u64,
+    /// DAA score from which transactions must commit to their storage mass
+    /// (KIP-0009); never enforced when unset
+    #[serde(default)]
+    pub storage_mass_activation: Option<u64>,
 }
\ No newline at end of file
@@ -0,0 +1,641 @@
+//! Canonical binary encoding of blocks, headers and transactions.
+//!
+//! Every encoding that leaves the node (P2P frames, hex over RPC, files handed to
+//! other tools) uses this format rather than a derived serde/bincode one, so
+//! the bytes don't depend on struct layout or serializer configuration and all
+//! node versions agree on them. Local database records may use anything.
+//!
+//! Integers are little-endian, lengths and counts are u64, hashes are 32 raw
+//! bytes. Version 1 writes:
+//!
+//! - transaction: version (u16), input count, per input [outpoint transaction
+//!   id, outpoint index (u32), signature script length + bytes, sequence (u64),
+//!   sig op count (u8)], output count, per output [value (u64), script version
+//!   (u16), script length + bytes], lock_time (u64), subnetwork id (20 bytes),
+//!   gas (u64), payload length + bytes, storage mass commitment (u64)
+//! - header: version (u16), level count, per level [parent count, parents],
+//!   hash merkle root, accepted id merkle root, utxo commitment, timestamp
+//!   (u64), bits (u32), nonce (u64), daa score (u64), blue work (24 bytes),
+//!   blue score (u64), pruning point
+//! - block: header, transaction count, transactions
+//! - utxo: outpoint transaction id, outpoint index (u32), amount (u64), block
+//!   daa score (u64), is coinbase (u8), script version (u16), script length +
+//!   bytes
+//!
+//! Cached hashes are not encoded: decoding recomputes the header hash and the
+//! transaction id. Decoding is strict and rejects trailing bytes, so each value
+//! has exactly one encoding. Any change to this layout breaks compatibility
+//! between nodes; the golden tests below pin version 1.
+//!
+//! [`crate::mass::transaction_estimated_serialized_size`] predates this format
+//! and stays as it is since mass is consensus. It counts a 32-byte payload hash
+//! the encoding doesn't carry, and leaves out the sig op count of each input and
+//! the mass commitment, so it is exactly
+//! `transaction_size(tx) + 24 - tx.inputs.len()`.
+
+use crate::block::Block;
+use crate::header::Header;
+use crate::subnets::{SubnetworkId, SUBNETWORK_ID_SIZE};
+use crate::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput, UtxoEntry};
+use crate::{BlueWorkType, Hash, HASH_SIZE};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("unexpected end of input reading {0}")]
+    UnexpectedEnd(&'static str),
+
+    #[error("{0} trailing bytes after the encoded value")]
+    TrailingBytes(usize),
+
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn hash(&mut self, hash: Hash) {
+        self.0.extend_from_slice(&hash.as_bytes());
+    }
+
+    fn var_bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn transaction(&mut self, tx: &Transaction) {
+        self.u16(tx.version);
+        self.len(tx.inputs.len());
+        for input in &tx.inputs {
+            self.hash(input.previous_outpoint.transaction_id);
+            self.u32(input.previous_outpoint.index);
+            self.var_bytes(&input.signature_script);
+            self.u64(input.sequence);
+            self.u8(input.sig_op_count);
+        }
+        self.len(tx.outputs.len());
+        for output in &tx.outputs {
+            self.u64(output.value);
+            self.u16(output.script_public_key.version());
+            self.var_bytes(output.script_public_key.script());
+        }
+        self.u64(tx.lock_time);
+        self.0.extend_from_slice(tx.subnetwork_id.as_bytes());
+        self.u64(tx.gas);
+        self.var_bytes(&tx.payload);
+        self.u64(tx.mass());
+    }
+
+    fn header(&mut self, header: &Header) {
+        self.u16(header.version);
+        self.len(header.parents_by_level.len());
+        for level in &header.parents_by_level {
+            self.len(level.len());
+            level.iter().for_each(|parent| self.hash(*parent));
+        }
+        self.hash(header.hash_merkle_root);
+        self.hash(header.accepted_id_merkle_root);
+        self.hash(header.utxo_commitment);
+        self.u64(header.timestamp);
+        self.u32(header.bits);
+        self.u64(header.nonce);
+        self.u64(header.daa_score);
+        self.0.extend_from_slice(&header.blue_work.to_bytes());
+        self.u64(header.blue_score);
+        self.hash(header.pruning_point);
+    }
+
+    fn utxo(&mut self, outpoint: &TransactionOutpoint, entry: &UtxoEntry) {
+        self.hash(outpoint.transaction_id);
+        self.u32(outpoint.index);
+        self.u64(entry.amount);
+        self.u64(entry.block_daa_score);
+        self.u8(entry.is_coinbase as u8);
+        self.u16(entry.script_public_key.version());
+        self.var_bytes(entry.script_public_key.script());
+    }
+}
+
+/// Smallest encodings of the counted elements, bounding how many of each the
+/// remaining input can hold
+const MIN_INPUT_SIZE: usize = HASH_SIZE + 4 + 8 + 8 + 1;
+const MIN_OUTPUT_SIZE: usize = 8 + 2 + 8;
+const MIN_LEVEL_SIZE: usize = 8;
+const MIN_TRANSACTION_SIZE: usize = 2 + 8 + 8 + 8 + SUBNETWORK_ID_SIZE + 8 + 8 + 8;
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize, what: &'static str) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::UnexpectedEnd(what));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, what: &'static str) -> Result<[u8; N], DecodeError> {
+        Ok(self.bytes(N, what)?.try_into().expect("slice has length N"))
+    }
+
+    fn u8(&mut self, what: &'static str) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>(what)?[0])
+    }
+
+    fn u16(&mut self, what: &'static str) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.array(what)?))
+    }
+
+    fn u32(&mut self, what: &'static str) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array(what)?))
+    }
+
+    fn u64(&mut self, what: &'static str) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.array(what)?))
+    }
+
+    /// A length or count; every counted element takes at least one byte, so
+    /// one larger than the remaining input can't be satisfied
+    fn len(&mut self, what: &'static str) -> Result<usize, DecodeError> {
+        let len = self.u64(what)?;
+        usize::try_from(len).ok().filter(|len| *len <= self.0.len()).ok_or(DecodeError::UnexpectedEnd(what))
+    }
+
+    /// A count of elements each encoded in at least `min_size` bytes, refused
+    /// when the remaining input can't hold that many
+    fn count(&mut self, what: &'static str, min_size: usize) -> Result<usize, DecodeError> {
+        let count = self.u64(what)?;
+        usize::try_from(count).ok().filter(|count| *count <= self.0.len() / min_size).ok_or(DecodeError::UnexpectedEnd(what))
+    }
+
+    fn hash(&mut self, what: &'static str) -> Result<Hash, DecodeError> {
+        Ok(Hash::from_bytes(self.array::<HASH_SIZE>(what)?))
+    }
+
+    fn var_bytes(&mut self, what: &'static str) -> Result<Vec<u8>, DecodeError> {
+        let len = self.len(what)?;
+        Ok(self.bytes(len, what)?.to_vec())
+    }
+
+    fn transaction(&mut self) -> Result<Transaction, DecodeError> {
+        let version = self.u16("transaction version")?;
+        let input_count = self.count("input count", MIN_INPUT_SIZE)?;
+        let mut inputs = Vec::with_capacity(input_count);
+        for _ in 0..input_count {
+            let previous_outpoint = TransactionOutpoint::new(self.hash("outpoint transaction id")?, self.u32("outpoint index")?);
+            let signature_script = self.var_bytes("signature script")?;
+            let sequence = self.u64("sequence")?;
+            inputs.push(TransactionInput::new(previous_outpoint, signature_script, sequence, self.u8("sig op count")?));
+        }
+        let output_count = self.count("output count", MIN_OUTPUT_SIZE)?;
+        let mut outputs = Vec::with_capacity(output_count);
+        for _ in 0..output_count {
+            let value = self.u64("output value")?;
+            let script_version = self.u16("script version")?;
+            outputs.push(TransactionOutput::new(value, ScriptPublicKey::from_vec(script_version, self.var_bytes("script public key")?)));
+        }
+        let lock_time = self.u64("lock time")?;
+        let subnetwork_id = SubnetworkId::new(self.array::<SUBNETWORK_ID_SIZE>("subnetwork id")?);
+        let gas = self.u64("gas")?;
+        let payload = self.var_bytes("payload")?;
+        let tx = Transaction::new(version, inputs, outputs, lock_time, subnetwork_id, gas, payload);
+        Ok(tx.with_mass(self.u64("mass commitment")?))
+    }
+
+    fn header(&mut self) -> Result<Header, DecodeError> {
+        let version = self.u16("header version")?;
+        let level_count = self.count("parent level count", MIN_LEVEL_SIZE)?;
+        let mut parents_by_level = Vec::with_capacity(level_count);
+        for _ in 0..level_count {
+            let parent_count = self.count("parent count", HASH_SIZE)?;
+            parents_by_level.push((0..parent_count).map(|_| self.hash("parent")).collect::<Result<Vec<_>, _>>()?);
+        }
+        Ok(Header::new_finalized(
+            version,
+            parents_by_level,
+            self.hash("hash merkle root")?,
+            self.hash("accepted id merkle root")?,
+            self.hash("utxo commitment")?,
+            self.u64("timestamp")?,
+            self.u32("bits")?,
+            self.u64("nonce")?,
+            self.u64("daa score")?,
+            BlueWorkType::from_le_bytes(self.array("blue work")?),
+            self.u64("blue score")?,
+            self.hash("pruning point")?,
+        ))
+    }
+
+    fn block(&mut self) -> Result<Block, DecodeError> {
+        let header = self.header()?;
+        let tx_count = self.count("transaction count", MIN_TRANSACTION_SIZE)?;
+        let mut transactions = Vec::with_capacity(tx_count);
+        for _ in 0..tx_count {
+            transactions.push(self.transaction()?);
+        }
+        Ok(Block::new(header, transactions))
+    }
+
+    fn utxo(&mut self) -> Result<(TransactionOutpoint, UtxoEntry), DecodeError> {
+        let outpoint = TransactionOutpoint::new(self.hash("outpoint transaction id")?, self.u32("outpoint index")?);
+        let amount = self.u64("amount")?;
+        let block_daa_score = self.u64("block daa score")?;
+        let is_coinbase = match self.u8("is coinbase")? {
+            0 => false,
+            1 => true,
+            _ => return Err(DecodeError::Invalid("is coinbase flag")),
+        };
+        let script_version = self.u16("script version")?;
+        let script_public_key = ScriptPublicKey::from_vec(script_version, self.var_bytes("script public key")?);
+        Ok((outpoint, UtxoEntry::new(amount, script_public_key, block_daa_score, is_coinbase)))
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, DecodeError> {
+        match self.0.len() {
+            0 => Ok(value),
+            trailing => Err(DecodeError::TrailingBytes(trailing)),
+        }
+    }
+}
+
+pub fn encode_transaction(tx: &Transaction) -> Vec<u8> {
+    let mut writer = Writer(Vec::with_capacity(transaction_size(tx) as usize));
+    writer.transaction(tx);
+    writer.0
+}
+
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, DecodeError> {
+    let mut reader = Reader(bytes);
+    let tx = reader.transaction()?;
+    reader.finish(tx)
+}
+
+pub fn encode_header(header: &Header) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.header(header);
+    writer.0
+}
+
+pub fn decode_header(bytes: &[u8]) -> Result<Header, DecodeError> {
+    let mut reader = Reader(bytes);
+    let header = reader.header()?;
+    reader.finish(header)
+}
+
+pub fn encode_block(block: &Block) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.header(&block.header);
+    writer.len(block.transactions.len());
+    block.transactions.iter().for_each(|tx| writer.transaction(tx));
+    writer.0
+}
+
+pub fn decode_block(bytes: &[u8]) -> Result<Block, DecodeError> {
+    let mut reader = Reader(bytes);
+    let block = reader.block()?;
+    reader.finish(block)
+}
+
+pub fn encode_utxo(outpoint: &TransactionOutpoint, entry: &UtxoEntry) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.utxo(outpoint, entry);
+    writer.0
+}
+
+pub fn decode_utxo(bytes: &[u8]) -> Result<(TransactionOutpoint, UtxoEntry), DecodeError> {
+    let mut reader = Reader(bytes);
+    let utxo = reader.utxo()?;
+    reader.finish(utxo)
+}
+
+/// Length of the canonical encoding of `tx`, without encoding it
+pub fn transaction_size(tx: &Transaction) -> u64 {
+    let inputs: u64 = tx.inputs.iter().map(|input| HASH_SIZE as u64 + 4 + 8 + input.signature_script.len() as u64 + 8 + 1).sum();
+    let outputs: u64 = tx.outputs.iter().map(|output| 8 + 2 + 8 + output.script_public_key.script().len() as u64).sum();
+    2 + 8 + inputs + 8 + outputs + 8 + SUBNETWORK_ID_SIZE as u64 + 8 + 8 + tx.payload.len() as u64 + 8
+}
+
+/// Length of the canonical encoding of `header`, without encoding it
+pub fn header_size(header: &Header) -> u64 {
+    let parents: u64 = header.parents_by_level.iter().map(|level| 8 + (HASH_SIZE * level.len()) as u64).sum();
+    2 + 8 + parents + 3 * HASH_SIZE as u64 + 8 + 4 + 8 + 8 + 24 + 8 + HASH_SIZE as u64
+}
+
+/// Length of the canonical encoding of `block`, without encoding it
+pub fn block_size(block: &Block) -> u64 {
+    header_size(&block.header) + 8 + block.transactions.iter().map(transaction_size).sum::<u64>()
+}
+
+/// Serde adapters carrying values as their canonical encoding, for use with
+/// `#[serde(with = "...")]` in messages serialized by other means
+pub mod serde_canonical {
+    use super::DecodeError;
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    struct BytesVisitor<T>(fn(&[u8]) -> Result<T, DecodeError>, PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for BytesVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("canonically encoded bytes")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+            (self.0)(bytes).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    macro_rules! adapter {
+        ($name:ident, $type:ty, $encode:path, $decode:path) => {
+            pub mod $name {
+                use super::*;
+
+                pub fn serialize<S: Serializer>(value: &$type, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_bytes(&$encode(value))
+                }
+
+                pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<$type, D::Error> {
+                    deserializer.deserialize_bytes(BytesVisitor($decode, PhantomData))
+                }
+            }
+        };
+    }
+
+    adapter!(block, crate::block::Block, super::encode_block, super::decode_block);
+    adapter!(transaction, crate::tx::Transaction, super::encode_transaction, super::decode_transaction);
+    adapter!(header, crate::header::Header, super::encode_header, super::decode_header);
+
+    /// Headers carried as a sequence of their canonical encodings
+    pub mod headers {
+        use crate::header::Header;
+        use serde::ser::SerializeSeq;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        struct Encoded<'a>(&'a Header);
+
+        impl Serialize for Encoded<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                super::header::serialize(self.0, serializer)
+            }
+        }
+
+        struct Decoded(Header);
+
+        impl<'de> Deserialize<'de> for Decoded {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                super::header::deserialize(deserializer).map(Decoded)
+            }
+        }
+
+        pub fn serialize<S: Serializer>(headers: &[Header], serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(headers.len()))?;
+            for header in headers {
+                seq.serialize_element(&Encoded(header))?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Header>, D::Error> {
+            Ok(Vec::<Decoded>::deserialize(deserializer)?.into_iter().map(|decoded| decoded.0).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mass::transaction_estimated_serialized_size;
+    use crate::subnets::SUBNETWORK_ID_COINBASE;
+
+    /// The transaction pinned by the id golden tests in `hashing::tx`
+    fn golden_tx() -> Transaction {
+        Transaction::new(
+            1,
+            vec![
+                TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x11; 32]), 0), vec![0x41; 3], 0, 1),
+                TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x22; 32]), 7), vec![], u64::MAX, 1),
+            ],
+            vec![TransactionOutput::new(5_000_000_000, ScriptPublicKey::from_vec(0, vec![0x20, 0xab, 0xac]))],
+            123,
+            SubnetworkId::default(),
+            0,
+            vec![0xde, 0xad],
+        )
+        .with_mass(1_000)
+    }
+
+    fn golden_header() -> Header {
+        Header::new_finalized(
+            1,
+            vec![vec![Hash::from_bytes([0x01; 32]), Hash::from_bytes([0x02; 32])], vec![Hash::from_bytes([0x03; 32])]],
+            Hash::from_bytes([0x04; 32]),
+            Hash::from_bytes([0x05; 32]),
+            Hash::from_bytes([0x06; 32]),
+            1_700_000_000_000,
+            0x1f00ffff,
+            42,
+            1_000,
+            BlueWorkType::from(0x0102u64),
+            900,
+            Hash::from_bytes([0x07; 32]),
+        )
+    }
+
+    #[test]
+    fn test_transaction_golden_bytes() {
+        let expected = [
+            "0100",
+            "0200000000000000",
+            "1111111111111111111111111111111111111111111111111111111111111111",
+            "00000000",
+            "0300000000000000414141",
+            "0000000000000000",
+            "01",
+            "2222222222222222222222222222222222222222222222222222222222222222",
+            "07000000",
+            "0000000000000000",
+            "ffffffffffffffff",
+            "01",
+            "0100000000000000",
+            "00f2052a01000000",
+            "0000",
+            "030000000000000020abac",
+            "7b00000000000000",
+            "0000000000000000000000000000000000000000",
+            "0000000000000000",
+            "0200000000000000dead",
+            "e803000000000000",
+        ]
+        .concat();
+        let tx = golden_tx();
+        let bytes = encode_transaction(&tx);
+        assert_eq!(hex::encode(&bytes), expected);
+        assert_eq!(transaction_size(&tx), bytes.len() as u64);
+
+        let decoded = decode_transaction(&bytes).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(decoded.id(), tx.id());
+        assert_eq!(decoded.mass(), 1_000);
+    }
+
+    #[test]
+    fn test_header_golden_bytes() {
+        let expected = [
+            "0100",
+            "0200000000000000",
+            "0200000000000000",
+            "0101010101010101010101010101010101010101010101010101010101010101",
+            "0202020202020202020202020202020202020202020202020202020202020202",
+            "0100000000000000",
+            "0303030303030303030303030303030303030303030303030303030303030303",
+            "0404040404040404040404040404040404040404040404040404040404040404",
+            "0505050505050505050505050505050505050505050505050505050505050505",
+            "0606060606060606060606060606060606060606060606060606060606060606",
+            "0068e5cf8b010000",
+            "ffff001f",
+            "2a00000000000000",
+            "e803000000000000",
+            "020100000000000000000000000000000000000000000000",
+            "8403000000000000",
+            "0707070707070707070707070707070707070707070707070707070707070707",
+        ]
+        .concat();
+        let header = golden_header();
+        let bytes = encode_header(&header);
+        assert_eq!(hex::encode(&bytes), expected);
+
+        let decoded = decode_header(&bytes).unwrap();
+        assert_eq!(decoded.hash, header.hash);
+        assert_eq!(encode_header(&decoded), bytes);
+    }
+
+    #[test]
+    fn test_block_is_header_then_transactions() {
+        let coinbase = Transaction::new(0, vec![], vec![], 0, SUBNETWORK_ID_COINBASE, 0, vec![0x01]);
+        let block = Block::new(golden_header(), vec![coinbase.clone(), golden_tx()]);
+        let bytes = encode_block(&block);
+        assert_eq!(header_size(&block.header), encode_header(&block.header).len() as u64);
+        assert_eq!(block_size(&block), bytes.len() as u64);
+        let expected = [encode_header(&block.header), 2u64.to_le_bytes().to_vec(), encode_transaction(&coinbase), encode_transaction(&golden_tx())].concat();
+        assert_eq!(bytes, expected);
+
+        let decoded = decode_block(&bytes).unwrap();
+        assert_eq!(decoded.header.hash, block.header.hash);
+        assert_eq!(decoded.transactions, block.transactions);
+    }
+
+    #[test]
+    fn test_utxo_golden_bytes() {
+        let outpoint = TransactionOutpoint::new(Hash::from_bytes([0x01; 32]), 2);
+        let entry = UtxoEntry::new(5_000, ScriptPublicKey::from_vec(0, vec![0xac]), 7, true);
+        let expected = [
+            "0101010101010101010101010101010101010101010101010101010101010101",
+            "02000000",
+            "8813000000000000",
+            "0700000000000000",
+            "01",
+            "0000",
+            "0100000000000000",
+            "ac",
+        ]
+        .concat();
+        let bytes = encode_utxo(&outpoint, &entry);
+        assert_eq!(hex::encode(&bytes), expected);
+        assert_eq!(decode_utxo(&bytes).unwrap(), (outpoint, entry));
+
+        let mut bad_flag = bytes.clone();
+        bad_flag[32 + 4 + 8 + 8] = 2;
+        assert_eq!(decode_utxo(&bad_flag), Err(DecodeError::Invalid("is coinbase flag")));
+    }
+
+    #[test]
+    fn test_decoding_is_strict() {
+        let bytes = encode_transaction(&golden_tx());
+        for len in 0..bytes.len() {
+            assert!(matches!(decode_transaction(&bytes[..len]), Err(DecodeError::UnexpectedEnd(_))), "prefix of {} bytes", len);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode_transaction(&trailing), Err(DecodeError::TrailingBytes(1)));
+
+        // A count beyond the input is refused before allocating for it
+        let mut huge = bytes[..2].to_vec();
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(decode_transaction(&huge), Err(DecodeError::UnexpectedEnd("input count")));
+
+        // So is one the rest of the input holds bytes for but not that many elements
+        let mut short = bytes[..2].to_vec();
+        short.extend_from_slice(&2u64.to_le_bytes());
+        short.extend_from_slice(&[0; 2 * MIN_INPUT_SIZE - 1]);
+        assert_eq!(decode_transaction(&short), Err(DecodeError::UnexpectedEnd("input count")));
+        let mut block = encode_header(&golden_header());
+        block.extend_from_slice(&1_000u64.to_le_bytes());
+        block.extend_from_slice(&[0; 1_000]);
+        assert_eq!(decode_block(&block).err(), Some(DecodeError::UnexpectedEnd("transaction count")));
+    }
+
+    #[test]
+    fn test_minimum_sizes_match_the_encoding() {
+        let empty = Transaction::new(0, vec![], vec![], 0, SubnetworkId::default(), 0, vec![]);
+        assert_eq!(transaction_size(&empty), MIN_TRANSACTION_SIZE as u64);
+        let input = TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0; 32]), 0), vec![], 0, 0);
+        let output = TransactionOutput::new(0, ScriptPublicKey::from_vec(0, vec![]));
+        let one_each = Transaction::new(0, vec![input], vec![output], 0, SubnetworkId::default(), 0, vec![]);
+        assert_eq!(transaction_size(&one_each), (MIN_TRANSACTION_SIZE + MIN_INPUT_SIZE + MIN_OUTPUT_SIZE) as u64);
+        let mut header = golden_header();
+        let levels = header.parents_by_level.len();
+        let parents: usize = header.parents_by_level.iter().map(Vec::len).sum();
+        let with_parents = header_size(&header);
+        header.parents_by_level.clear();
+        assert_eq!(with_parents - header_size(&header), (levels * MIN_LEVEL_SIZE + parents * HASH_SIZE) as u64);
+    }
+
+    #[test]
+    fn test_estimated_size_matches_canonical_size() {
+        let mut txs = vec![golden_tx(), Transaction::new(0, vec![], vec![], 0, SUBNETWORK_ID_COINBASE, 0, vec![])];
+        for inputs in [1usize, 3, 10] {
+            let input = |n: usize| TransactionInput::new(TransactionOutpoint::new(Hash::from_u64_word(n as u64), n as u32), vec![0x30; 66 + n], 0, 1);
+            let output = |n: usize| TransactionOutput::new(n as u64, ScriptPublicKey::from_vec(0, vec![0x20; 34 + n]));
+            txs.push(Transaction::new(0, (0..inputs).map(input).collect(), (0..inputs + 1).map(output).collect(), 0, SubnetworkId::default(), 0, vec![0; inputs * 7]));
+        }
+        for tx in &txs {
+            let canonical = encode_transaction(tx).len() as u64;
+            assert_eq!(transaction_size(tx), canonical);
+            assert_eq!(transaction_estimated_serialized_size(tx), canonical + 24 - tx.inputs.len() as u64);
+        }
+    }
+}
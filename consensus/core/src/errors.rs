@@ -29,6 +29,9 @@ pub enum ConsensusError {
     #[error("Invalid signature")]
     InvalidSignature,
 
+    #[error("Input {input_index} of transaction {transaction} has an invalid signature")]
+    InvalidInputSignature { transaction: crate::Hash, input_index: usize },
+
     #[error("Double spend attempt")]
     DoubleSpend,
 
@@ -47,9 +50,69 @@ pub enum ConsensusError {
     #[error("Invalid difficulty target")]
     InvalidDifficultyTarget,
 
+    #[error("Header declares bits {declared:08x}, its selected parent's difficulty window gives {expected:08x}")]
+    WrongDifficulty { declared: u32, expected: u32 },
+
     #[error("Invalid timestamp")]
     InvalidTimestamp,
 
+    #[error("Header commits to blue score {declared}, GHOSTDAG gives {expected}")]
+    InvalidBlueScore { declared: u64, expected: u64 },
+
+    #[error("Header commits to blue work {declared}, GHOSTDAG gives {expected}")]
+    InvalidBlueWork { declared: crate::BlueWorkType, expected: crate::BlueWorkType },
+
+    #[error("Header commits to DAA score {declared}, expected {expected}")]
+    InvalidDaaScore { declared: u64, expected: u64 },
+
+    #[error("Block {found} passes DAA score {daa_score} without checkpoint {expected} in its past")]
+    CheckpointMismatch { daa_score: u64, expected: crate::Hash, found: crate::Hash },
+
+    #[error("Block at DAA score {daa_score} is too far below the pruning point at {pruning_point_daa_score}")]
+    BlockTooOld { daa_score: u64, pruning_point_daa_score: u64 },
+
+    #[error("Transaction commits to storage mass {committed}, below the computed {expected}")]
+    InvalidStorageMass { committed: u64, expected: u64 },
+
+    #[error("Transaction storage mass is too high to compute")]
+    StorageMassTooHigh,
+
+    #[error("Transaction subnetwork {0} is not enabled")]
+    DisabledSubnetwork(crate::subnets::SubnetworkId),
+
+    #[error("Transaction of subnetwork {subnetwork} carries gas {gas}; only experimental subnetworks use gas")]
+    UnexpectedGas { subnetwork: crate::subnets::SubnetworkId, gas: u64 },
+
+    #[error("Header lists parent {0} more than once")]
+    DuplicateParent(crate::Hash),
+
+    #[error("Header lists itself as a parent")]
+    SelfParent,
+
+    #[error("Parent level {0} is empty")]
+    EmptyParentLevel(usize),
+
+    #[error("Header lists parents at {levels} levels, its block level is {block_level}")]
+    TooManyParentLevels { levels: usize, block_level: crate::BlockLevel },
+
+    #[error("Parent {parent} at level {level} only reaches level {parent_level}")]
+    ParentBelowLevel { parent: crate::Hash, level: usize, parent_level: crate::BlockLevel },
+
+    #[error("Block has {count} parents, the limit is {limit}")]
+    TooManyParents { count: usize, limit: usize },
+
+    #[error("Block uses gas {gas}, the limit is {limit}")]
+    ExceedsMaxBlockGas { gas: u64, limit: u64 },
+
+    #[error("Malformed coinbase payload: {0}")]
+    InvalidCoinbasePayload(String),
+
+    #[error("Coinbase commits to height {declared}, the block is at {expected}")]
+    InvalidCoinbaseHeight { declared: u64, expected: u64 },
+
+    #[error("Coinbase commits to subsidy {declared}, the schedule gives {expected}")]
+    InvalidCoinbaseSubsidy { declared: u64, expected: u64 },
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -59,6 +122,15 @@ pub enum ConsensusError {
     #[error("Database error: {0}")]
     DatabaseError(String),
 
+    #[error("Block {0} not found")]
+    BlockNotFound(crate::Hash),
+
+    #[error("Block {0} has been pruned")]
+    BlockPruned(crate::Hash),
+
+    #[error("Database holds genesis {stored}, expected {expected}")]
+    GenesisMismatch { stored: crate::Hash, expected: crate::Hash },
+
     #[error("Other error: {0}")]
     Other(String),
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+//! Fee totals of accepted blocks, the samples the fee estimator works from
+
+use crate::block::Block;
+use crate::Hash;
+use serde::{Deserialize, Serialize};
+
+/// Fee totals of one accepted block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFeeRecord {
+    pub hash: Hash,
+    pub daa_score: u64,
+    /// Sum of the fees paid by the block's transactions
+    pub total_fees: u64,
+    /// Mass of the block's non-coinbase transactions
+    pub mass: u64,
+}
+
+impl BlockFeeRecord {
+    pub fn from_block(block: &Block, total_fees: u64) -> Self {
+        let mass = block.transactions.iter().filter(|tx| !tx.is_coinbase()).map(|tx| tx.calculate_mass()).sum();
+        Self { hash: block.header.hash, daa_score: block.header.daa_score, total_fees, mass }
+    }
+
+    /// Fee per gram of mass, or `None` for a block without transactions
+    pub fn feerate(&self) -> Option<f64> {
+        (self.mass > 0).then(|| self.total_fees as f64 / self.mass as f64)
+    }
+}
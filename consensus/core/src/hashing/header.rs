@@ -16,35 +16,71 @@ fn bits_to_target(bits: u32) -> U256 {
     }
 }
 
-/// Computes the hash of a block header
-pub fn calculate_header_hash(header: &Header) -> Hash {
-    // Serialize the header without the hash field
-    let mut bytes = Vec::new();
-    bytes.extend_from_slice(&header.version.to_le_bytes());
-    
-    // Serialize parents
-    for parent_level in &header.parents_by_level {
-        for parent in parent_level {
-            bytes.extend_from_slice(parent.as_bytes());
+/// Builds the canonical header hash preimage.
+///
+/// Fields are always written in this fixed order, integers little-endian:
+/// version (u16), parents of every level concatenated, hash_merkle_root,
+/// accepted_id_merkle_root, utxo_commitment, timestamp (u64), bits (u32),
+/// nonce (u64, omitted for the pre-pow hash), daa_score (u64), blue_work
+/// (24 bytes), blue_score (u64), pruning_point. The cached `hash` field is
+/// never included. The result is double SHA256 of the preimage.
+///
+/// Changing this order or encoding changes every block hash and is a consensus
+/// break; the golden tests below pin it.
+pub struct HeaderHashBuilder<'a> {
+    header: &'a Header,
+    include_nonce: bool,
+}
+
+impl<'a> HeaderHashBuilder<'a> {
+    pub fn new(header: &'a Header) -> Self {
+        Self { header, include_nonce: true }
+    }
+
+    /// Leave the nonce out, producing the pre-pow hash
+    pub fn without_nonce(mut self) -> Self {
+        self.include_nonce = false;
+        self
+    }
+
+    /// The serialized preimage
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = self.header;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&header.version.to_le_bytes());
+        for parent_level in &header.parents_by_level {
+            for parent in parent_level {
+                bytes.extend_from_slice(parent.as_bytes());
+            }
+        }
+        bytes.extend_from_slice(header.hash_merkle_root.as_bytes());
+        bytes.extend_from_slice(header.accepted_id_merkle_root.as_bytes());
+        bytes.extend_from_slice(header.utxo_commitment.as_bytes());
+        bytes.extend_from_slice(&header.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&header.bits.to_le_bytes());
+        if self.include_nonce {
+            bytes.extend_from_slice(&header.nonce.to_le_bytes());
         }
+        bytes.extend_from_slice(&header.daa_score.to_le_bytes());
+        bytes.extend_from_slice(&header.blue_work.to_bytes());
+        bytes.extend_from_slice(&header.blue_score.to_le_bytes());
+        bytes.extend_from_slice(header.pruning_point.as_bytes());
+        bytes
     }
-    
-    // Add other header fields
-    bytes.extend_from_slice(header.hash_merkle_root.as_bytes());
-    bytes.extend_from_slice(header.accepted_id_merkle_root.as_bytes());
-    bytes.extend_from_slice(header.utxo_commitment.as_bytes());
-    bytes.extend_from_slice(&header.timestamp.to_le_bytes());
-    bytes.extend_from_slice(&header.bits.to_le_bytes());
-    bytes.extend_from_slice(&header.nonce.to_le_bytes());
-    bytes.extend_from_slice(&header.daa_score.to_le_bytes());
-    bytes.extend_from_slice(&header.blue_work.to_bytes());
-    bytes.extend_from_slice(&header.blue_score.to_le_bytes());
-    bytes.extend_from_slice(header.pruning_point.as_bytes());
 
-    // Double SHA256 the entire header
-    let hash = super::double_sha256(&bytes);
-    // `double_sha256` already returns a `Hash`
-    hash
+    pub fn finalize(&self) -> Hash {
+        super::double_sha256(&self.to_bytes())
+    }
+}
+
+/// Computes the hash of a block header
+pub fn calculate_header_hash(header: &Header) -> Hash {
+    HeaderHashBuilder::new(header).finalize()
+}
+
+/// Computes the pre-pow hash: the header hash with the nonce left out
+pub fn calculate_pre_pow_hash(header: &Header) -> Hash {
+    HeaderHashBuilder::new(header).without_nonce().finalize()
 }
 
 /// Computes the proof of work hash for a block header based on its version
@@ -52,26 +88,7 @@ pub fn calculate_pow_hash(header: &Header) -> Hash {
     match header.version {
         v if v == BLOCK_VERSION_KHASHV1 => {
             // For KHASHV1, use PowB3Hash
-            // First calculate the pre-pow hash (header hash without nonce)
-            let mut pre_pow_bytes = Vec::new();
-            pre_pow_bytes.extend_from_slice(&header.version.to_le_bytes());
-            for parent_level in &header.parents_by_level {
-                for parent in parent_level {
-                    pre_pow_bytes.extend_from_slice(parent.as_bytes());
-                }
-            }
-            pre_pow_bytes.extend_from_slice(header.hash_merkle_root.as_bytes());
-            pre_pow_bytes.extend_from_slice(header.accepted_id_merkle_root.as_bytes());
-            pre_pow_bytes.extend_from_slice(header.utxo_commitment.as_bytes());
-            pre_pow_bytes.extend_from_slice(&header.timestamp.to_le_bytes());
-            pre_pow_bytes.extend_from_slice(&header.bits.to_le_bytes());
-            // Note: nonce is not included in pre-pow hash
-            pre_pow_bytes.extend_from_slice(&header.daa_score.to_le_bytes());
-            pre_pow_bytes.extend_from_slice(&header.blue_work.to_bytes());
-            pre_pow_bytes.extend_from_slice(&header.blue_score.to_le_bytes());
-            pre_pow_bytes.extend_from_slice(header.pruning_point.as_bytes());
-            
-            let pre_pow_hash = super::double_sha256(&pre_pow_bytes);
+            let pre_pow_hash = calculate_pre_pow_hash(header);
             
             // Create PowB3Hash with pre-pow hash and timestamp
             let mut pow_hasher = PowB3Hash::new(pre_pow_hash, header.timestamp);
@@ -136,6 +153,58 @@ mod tests {
         assert_ne!(hash, Hash::default());
     }
 
+    /// Fixed header whose hash is pinned by the golden tests below
+    fn golden_header() -> Header {
+        Header::new_finalized(
+            1,
+            vec![vec![Hash::from_bytes([0x11; 32]), Hash::from_bytes([0x22; 32])], vec![Hash::from_bytes([0x33; 32])]],
+            Hash::from_bytes([0x44; 32]),
+            Hash::from_bytes([0x55; 32]),
+            Hash::from_bytes([0x66; 32]),
+            1699545600000,
+            0x1e7fffff,
+            0x0123456789abcdef,
+            1000,
+            BlueWorkType::from(0x1234567u64),
+            900,
+            Hash::from_bytes([0x77; 32]),
+        )
+    }
+
+    #[test]
+    fn test_header_hash_golden() {
+        let header = golden_header();
+        let expected: Hash = "d6d38626d8b024f782a9858cd2c2b06f623c51f1094350a2b3327cdfb90cb6ca".parse().unwrap();
+        assert_eq!(calculate_header_hash(&header), expected);
+        assert_eq!(header.hash, expected);
+    }
+
+    #[test]
+    fn test_pre_pow_hash_golden() {
+        let expected: Hash = "2a85380ca50740689ee87f17902b86817a034816af4fbcf25124b8022ddaa929".parse().unwrap();
+        assert_eq!(calculate_pre_pow_hash(&golden_header()), expected);
+    }
+
+    #[test]
+    fn test_header_preimage_layout() {
+        let header = golden_header();
+        let bytes = HeaderHashBuilder::new(&header).to_bytes();
+        // 2 + 3 parents + 3 roots + 8 + 4 + 8 + 8 + 24 + 8 + pruning point
+        assert_eq!(bytes.len(), 2 + 3 * 32 + 3 * 32 + 8 + 4 + 8 + 8 + 24 + 8 + 32);
+        assert_eq!(&bytes[..2], &1u16.to_le_bytes());
+        assert_eq!(&bytes[194..202], &1699545600000u64.to_le_bytes());
+        assert_eq!(&bytes[206..214], &0x0123456789abcdefu64.to_le_bytes());
+        assert_eq!(HeaderHashBuilder::new(&header).without_nonce().to_bytes().len(), bytes.len() - 8);
+    }
+
+    #[test]
+    fn test_header_hash_ignores_cached_hash() {
+        let mut header = golden_header();
+        let expected = header.hash;
+        header.hash = Hash::from_bytes([0xff; 32]);
+        assert_eq!(calculate_header_hash(&header), expected);
+    }
+
     #[test]
     fn test_pow_hash_v1() {
         let header = Header::new_finalized(
@@ -7,10 +7,10 @@ pub mod sighash;
 pub mod sighash_type;
 pub mod tx;
 
-pub use header::calculate_header_hash;
-pub use sighash::calc_transaction_sighash;
+pub use header::{calculate_header_hash, calculate_pre_pow_hash, HeaderHashBuilder};
+pub use sighash::{calc_sighash, calc_transaction_sighash, SigHashReusedValues};
 pub use sighash_type::SigHashType;
-pub use tx::calc_transaction_hash;
+pub use tx::{calc_transaction_hash, calc_transaction_hash_with, calc_transaction_witness_hash, TxHashScheme, CURRENT_TX_HASH_SCHEME};
 
 /// Performs a double SHA256 hash on input bytes
 pub fn double_sha256(input: &[u8]) -> Hash {
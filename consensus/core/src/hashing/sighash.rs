@@ -1,7 +1,264 @@
-use crate::tx::Transaction;
+//! Signature hash calculation.
+//!
+//! The sighash of an input commits to the transaction fields selected by its
+//! [`SigHashType`] and to the amount and script public key of the output the
+//! input spends, so a signature is only valid against that exact output. The
+//! parts that are shared by all inputs (hashes of all previous outpoints,
+//! sequences, sig-op counts, outputs and the payload) are computed once and
+//! kept in [`SigHashReusedValues`], so signing or verifying N inputs is O(N)
+//! instead of O(N²).
+
+use super::{HasherExtensions, SigHashType};
+use crate::subnets::SubnetworkId;
+use crate::tx::{Transaction, TransactionOutpoint, TransactionOutput, UtxoEntry};
 use crate::Hash;
+use crate::ZERO_HASH;
+use sha2::{Digest, Sha256};
+
+/// Per-transaction cache of the sighash parts that do not depend on the input being signed
+#[derive(Debug, Default, Clone)]
+pub struct SigHashReusedValues {
+    previous_outputs_hash: Option<Hash>,
+    sequences_hash: Option<Hash>,
+    sig_op_counts_hash: Option<Hash>,
+    outputs_hash: Option<Hash>,
+    payload_hash: Option<Hash>,
+}
+
+impl SigHashReusedValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn finalize(hasher: Sha256) -> Hash {
+    Hash::try_from_slice(&hasher.finalize()).expect("SHA256 output has correct length")
+}
+
+fn write_outpoint(hasher: &mut Sha256, outpoint: &TransactionOutpoint) {
+    hasher.update(outpoint.transaction_id.as_bytes());
+    hasher.write_u32(outpoint.index);
+}
+
+fn write_output(hasher: &mut Sha256, output: &TransactionOutput) {
+    hasher.write_u64(output.value);
+    hasher.write_u16(output.script_public_key.version());
+    hasher.write_var_bytes(output.script_public_key.script());
+}
+
+fn write_spent_output(hasher: &mut Sha256, entry: &UtxoEntry) {
+    hasher.write_u64(entry.amount);
+    hasher.write_u16(entry.script_public_key.version());
+    hasher.write_var_bytes(entry.script_public_key.script());
+}
+
+fn previous_outputs_hash(tx: &Transaction, hash_type: SigHashType, reused: &mut SigHashReusedValues) -> Hash {
+    if hash_type.is_anyone_can_pay() {
+        return ZERO_HASH;
+    }
+    *reused.previous_outputs_hash.get_or_insert_with(|| {
+        let mut hasher = Sha256::new();
+        for input in tx.inputs.iter() {
+            write_outpoint(&mut hasher, &input.previous_outpoint);
+        }
+        finalize(hasher)
+    })
+}
+
+fn sequences_hash(tx: &Transaction, hash_type: SigHashType, reused: &mut SigHashReusedValues) -> Hash {
+    if hash_type.is_anyone_can_pay() || hash_type.is_sighash_single() || hash_type.is_sighash_none() {
+        return ZERO_HASH;
+    }
+    *reused.sequences_hash.get_or_insert_with(|| {
+        let mut hasher = Sha256::new();
+        for input in tx.inputs.iter() {
+            hasher.write_u64(input.sequence);
+        }
+        finalize(hasher)
+    })
+}
+
+fn sig_op_counts_hash(tx: &Transaction, hash_type: SigHashType, reused: &mut SigHashReusedValues) -> Hash {
+    if hash_type.is_anyone_can_pay() {
+        return ZERO_HASH;
+    }
+    *reused.sig_op_counts_hash.get_or_insert_with(|| {
+        let mut hasher = Sha256::new();
+        for input in tx.inputs.iter() {
+            hasher.write_u8(input.sig_op_count);
+        }
+        finalize(hasher)
+    })
+}
+
+fn outputs_hash(tx: &Transaction, hash_type: SigHashType, input_index: usize, reused: &mut SigHashReusedValues) -> Hash {
+    if hash_type.is_sighash_single() {
+        // Only the output at the same index is committed to, so there is nothing to reuse
+        return match tx.outputs.get(input_index) {
+            Some(output) => {
+                let mut hasher = Sha256::new();
+                write_output(&mut hasher, output);
+                finalize(hasher)
+            }
+            None => ZERO_HASH,
+        };
+    }
+    if hash_type.is_sighash_none() {
+        return ZERO_HASH;
+    }
+    *reused.outputs_hash.get_or_insert_with(|| {
+        let mut hasher = Sha256::new();
+        for output in tx.outputs.iter() {
+            write_output(&mut hasher, output);
+        }
+        finalize(hasher)
+    })
+}
+
+fn payload_hash(tx: &Transaction, reused: &mut SigHashReusedValues) -> Hash {
+    if tx.subnetwork_id == SubnetworkId::default() && tx.payload.is_empty() {
+        return ZERO_HASH;
+    }
+    *reused.payload_hash.get_or_insert_with(|| {
+        let mut hasher = Sha256::new();
+        hasher.write_var_bytes(&tx.payload);
+        finalize(hasher)
+    })
+}
+
+/// Calculates the signature hash of input `input_index`, which spends `entry`,
+/// reusing the shared parts cached in `reused` by previous calls for the same
+/// transaction.
+///
+/// # Panics
+/// Panics if `input_index` is out of bounds.
+pub fn calc_sighash(tx: &Transaction, input_index: usize, entry: &UtxoEntry, hash_type: SigHashType, reused: &mut SigHashReusedValues) -> Hash {
+    let input = &tx.inputs[input_index];
+    let mut hasher = Sha256::new();
+    hasher.write_u16(tx.version);
+    hasher.update(previous_outputs_hash(tx, hash_type, reused).as_bytes());
+    hasher.update(sequences_hash(tx, hash_type, reused).as_bytes());
+    hasher.update(sig_op_counts_hash(tx, hash_type, reused).as_bytes());
+    write_outpoint(&mut hasher, &input.previous_outpoint);
+    write_spent_output(&mut hasher, entry);
+    hasher.write_u64(input.sequence);
+    hasher.write_u8(input.sig_op_count);
+    hasher.update(outputs_hash(tx, hash_type, input_index, reused).as_bytes());
+    hasher.write_u64(tx.lock_time);
+    hasher.update(tx.subnetwork_id.as_bytes());
+    hasher.write_u64(tx.gas);
+    hasher.update(payload_hash(tx, reused).as_bytes());
+    hasher.write_u8(hash_type.to_u8());
+    finalize(hasher)
+}
+
+/// Calculates the SIGHASH_ALL signature hash of the first input, spending
+/// `entry`, without any reuse
+pub fn calc_transaction_sighash(tx: &Transaction, entry: &UtxoEntry) -> Hash {
+    if tx.inputs.is_empty() {
+        return Hash::default();
+    }
+    calc_sighash(tx, 0, entry, SigHashType::SighashAll, &mut SigHashReusedValues::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subnets::SubnetworkId;
+    use crate::tx::{ScriptPublicKey, TransactionInput};
+
+    /// Output spent by input `i` of `multi_input_tx`
+    fn spent(i: usize) -> UtxoEntry {
+        UtxoEntry::new(2000 * (i as u64 + 1), ScriptPublicKey::from_vec(0, vec![0xac; 34]), 0, false)
+    }
+
+    fn multi_input_tx(inputs: u64, outputs: u64) -> Transaction {
+        Transaction::new(
+            1,
+            (0..inputs)
+                .map(|i| TransactionInput::new(TransactionOutpoint::new(Hash::from_u64_word(i + 1), i as u32), vec![], i, 1))
+                .collect(),
+            (0..outputs).map(|i| TransactionOutput::new(1000 * (i + 1), ScriptPublicKey::from_vec(0, vec![0xac; 34]))).collect(),
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![1, 2, 3],
+        )
+    }
+
+    #[test]
+    fn test_cached_matches_uncached() {
+        let tx = multi_input_tx(20, 5);
+        for hash_type in [
+            SigHashType::SighashAll,
+            SigHashType::SighashNone,
+            SigHashType::SighashSingle,
+            SigHashType::SighashAnyoneCanPay,
+        ] {
+            let mut reused = SigHashReusedValues::new();
+            for i in 0..tx.inputs.len() {
+                let cached = calc_sighash(&tx, i, &spent(i), hash_type, &mut reused);
+                let uncached = calc_sighash(&tx, i, &spent(i), hash_type, &mut SigHashReusedValues::new());
+                assert_eq!(cached, uncached, "input {} with {:?}", i, hash_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sighash_differs_per_input() {
+        let tx = multi_input_tx(3, 2);
+        let mut reused = SigHashReusedValues::new();
+        let first = calc_sighash(&tx, 0, &spent(0), SigHashType::SighashAll, &mut reused);
+        let second = calc_sighash(&tx, 1, &spent(0), SigHashType::SighashAll, &mut reused);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_sighash_commits_to_spent_output() {
+        let tx = multi_input_tx(2, 1);
+        let mut reused = SigHashReusedValues::new();
+        let signed = calc_sighash(&tx, 0, &spent(0), SigHashType::SighashAll, &mut reused);
+
+        let mut amount = spent(0);
+        amount.amount += 1;
+        assert_ne!(calc_sighash(&tx, 0, &amount, SigHashType::SighashAll, &mut reused), signed);
+        let mut script = spent(0);
+        script.script_public_key = ScriptPublicKey::from_vec(0, vec![0x51]);
+        assert_ne!(calc_sighash(&tx, 0, &script, SigHashType::SighashAll, &mut reused), signed);
+        let mut version = spent(0);
+        version.script_public_key = ScriptPublicKey::from_vec(1, vec![0xac; 34]);
+        assert_ne!(calc_sighash(&tx, 0, &version, SigHashType::SighashAll, &mut reused), signed);
+        // Under ANYONECANPAY too, as only the input's own output is committed to
+        let anyone_can_pay = calc_sighash(&tx, 0, &spent(0), SigHashType::SighashAnyoneCanPay, &mut reused);
+        assert_ne!(calc_sighash(&tx, 0, &amount, SigHashType::SighashAnyoneCanPay, &mut reused), anyone_can_pay);
+    }
+
+    #[test]
+    fn test_sighash_golden() {
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x11; 32]), 2), vec![], 7, 1)],
+            vec![TransactionOutput::new(4_000, ScriptPublicKey::from_vec(0, vec![0xac]))],
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![],
+        );
+        let entry = UtxoEntry::new(5_000, ScriptPublicKey::from_vec(0, vec![0x51, 0xac]), 10, false);
+        let expected: Hash = "e97efa3632eaaa806b6c50952e8c189ae7f1d782f4d3b105ee87d08444c8dda2".parse().unwrap();
+        assert_eq!(calc_sighash(&tx, 0, &entry, SigHashType::SighashAll, &mut SigHashReusedValues::new()), expected);
+        assert_eq!(calc_transaction_sighash(&tx, &entry), expected);
+    }
 
-pub fn calc_transaction_sighash(_tx: &Transaction) -> Hash {
-    // TODO: Implement real sighash calculation
-    Hash::default()
-}
\ No newline at end of file
+    #[test]
+    fn test_signature_scripts_not_committed() {
+        let tx = multi_input_tx(2, 1);
+        let mut signed = tx.clone();
+        signed.inputs[1].signature_script = vec![0x41; 66];
+        let mut reused = SigHashReusedValues::new();
+        assert_eq!(
+            calc_sighash(&tx, 0, &spent(0), SigHashType::SighashAll, &mut reused),
+            calc_sighash(&signed, 0, &spent(0), SigHashType::SighashAll, &mut SigHashReusedValues::new())
+        );
+    }
+}
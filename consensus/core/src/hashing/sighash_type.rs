@@ -13,4 +13,34 @@ impl Default for SigHashType {
     fn default() -> Self {
         SigHashType::SighashAll
     }
-}
\ No newline at end of file
+}
+impl SigHashType {
+    /// Byte committed to in the sighash and appended to signatures
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Parses the trailing sighash byte of a signature
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(SigHashType::SighashAll),
+            2 => Some(SigHashType::SighashNone),
+            3 => Some(SigHashType::SighashSingle),
+            0x80 => Some(SigHashType::SighashAnyoneCanPay),
+            _ => None,
+        }
+    }
+
+    /// Only the signed input is committed to (outputs are committed as with SIGHASH_ALL)
+    pub fn is_anyone_can_pay(self) -> bool {
+        self == SigHashType::SighashAnyoneCanPay
+    }
+
+    pub fn is_sighash_none(self) -> bool {
+        self == SigHashType::SighashNone
+    }
+
+    pub fn is_sighash_single(self) -> bool {
+        self == SigHashType::SighashSingle
+    }
+}
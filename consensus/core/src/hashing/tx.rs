@@ -1,11 +1,176 @@
+//! Transaction hashing.
+//!
+//! The transaction id is the SHA256 of an explicit, field-by-field preimage rather
+//! than of a derived serialization, so refactoring the `Transaction` struct or its
+//! serde/borsh encoding cannot silently change ids.
+//!
+//! Scheme v1 writes, integers little-endian, lengths as u64:
+//! version (u16), input count, per input [outpoint transaction id, outpoint index
+//! (u32), signature script length + bytes, sequence (u64), sig op count (u8)],
+//! output count, per output [value (u64), script version (u16), script length +
+//! bytes], lock_time (u64), subnetwork id (20 bytes), gas (u64), payload length +
+//! bytes.
+//!
+//! Scheme v2 writes the v1 layout with every signature script as empty, so that
+//! signing (or re-signing) a transaction never changes its id. Transaction ids,
+//! mempool dedup and outpoints use v2.
+//!
+//! The storage mass commitment and the cached id are not part of either preimage.
+//! Any change to these layouts is a consensus change and requires a new scheme
+//! version; the golden tests below pin v1 and v2.
+//!
+//! The witness hash is the full v1 preimage, signature scripts included, and
+//! identifies one exact signed instance of the transaction.
+
+use super::HasherExtensions;
+use crate::tx::{Transaction, TransactionInput, TransactionOutput};
 use crate::Hash;
-use crate::tx::Transaction;
 use sha2::{Digest, Sha256};
-use borsh::BorshSerialize;
 
+/// Transaction hashing scheme versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxHashScheme {
+    /// Every field, signature scripts included
+    V1,
+    /// Every field, signature scripts written as empty
+    V2,
+}
+
+/// Scheme used for transaction ids
+pub const CURRENT_TX_HASH_SCHEME: TxHashScheme = TxHashScheme::V2;
+
+fn write_input(hasher: &mut Sha256, input: &TransactionInput, signature_script: &[u8]) {
+    hasher.update(input.previous_outpoint.transaction_id.as_bytes());
+    hasher.write_u32(input.previous_outpoint.index);
+    hasher.write_var_bytes(signature_script);
+    hasher.write_u64(input.sequence);
+    hasher.write_u8(input.sig_op_count);
+}
+
+fn write_output(hasher: &mut Sha256, output: &TransactionOutput) {
+    hasher.write_u64(output.value);
+    hasher.write_u16(output.script_public_key.version());
+    hasher.write_var_bytes(output.script_public_key.script());
+}
+
+/// SHA256 of the v1 layout of `tx`, with signature scripts only if `with_signature_scripts`
+fn hash_v1_layout(tx: &Transaction, with_signature_scripts: bool) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.write_u16(tx.version);
+    hasher.write_len(tx.inputs.len());
+    for input in tx.inputs.iter() {
+        write_input(&mut hasher, input, if with_signature_scripts { &input.signature_script } else { &[] });
+    }
+    hasher.write_len(tx.outputs.len());
+    for output in tx.outputs.iter() {
+        write_output(&mut hasher, output);
+    }
+    hasher.write_u64(tx.lock_time);
+    hasher.update(tx.subnetwork_id.as_bytes());
+    hasher.write_u64(tx.gas);
+    hasher.write_var_bytes(&tx.payload);
+    Hash::try_from_slice(&hasher.finalize()).expect("SHA256 output has correct length")
+}
+
+/// Hashes `tx` with the given scheme
+pub fn calc_transaction_hash_with(tx: &Transaction, scheme: TxHashScheme) -> Hash {
+    match scheme {
+        TxHashScheme::V1 => hash_v1_layout(tx, true),
+        TxHashScheme::V2 => hash_v1_layout(tx, false),
+    }
+}
+
+/// Hashes `tx` with the current scheme; this is the transaction id
 pub fn calc_transaction_hash(tx: &Transaction) -> Hash {
-    // Serialize transaction using Borsh then hash the bytes
-    let ser = tx.try_to_vec().expect("transaction serialization");
-    let result = Sha256::digest(&ser);
-    Hash::try_from_slice(&result).expect("SHA256 output has correct length")
-}
\ No newline at end of file
+    calc_transaction_hash_with(tx, CURRENT_TX_HASH_SCHEME)
+}
+
+/// Hashes all of `tx`, signature scripts included
+pub fn calc_transaction_witness_hash(tx: &Transaction) -> Hash {
+    hash_v1_layout(tx, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subnets::SubnetworkId;
+    use crate::tx::{ScriptPublicKey, TransactionOutpoint};
+
+    /// Fixed transaction whose id is pinned by the golden tests below
+    fn golden_tx() -> Transaction {
+        Transaction::new(
+            1,
+            vec![
+                TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x11; 32]), 0), vec![0x41; 3], 0, 1),
+                TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x22; 32]), 7), vec![], u64::MAX, 1),
+            ],
+            vec![TransactionOutput::new(5_000_000_000, ScriptPublicKey::from_vec(0, vec![0x20, 0xab, 0xac]))],
+            123,
+            SubnetworkId::default(),
+            0,
+            vec![0xde, 0xad],
+        )
+    }
+
+    #[test]
+    fn test_transaction_hash_v1_golden() {
+        let expected: Hash = "32bdf332c28b92c35101ac0fbbfcecd16a5c750731975f62edd4215c4d1b7e14".parse().unwrap();
+        assert_eq!(calc_transaction_hash_with(&golden_tx(), TxHashScheme::V1), expected);
+    }
+
+    #[test]
+    fn test_transaction_id_golden() {
+        let expected: Hash = "67c09b340f28ade74ca19e2b5f98ccadcf1c9ac171bfbdcbb908134a52603e7a".parse().unwrap();
+        assert_eq!(calc_transaction_hash_with(&golden_tx(), TxHashScheme::V2), expected);
+        assert_eq!(golden_tx().id(), expected);
+    }
+
+    #[test]
+    fn test_witness_hash_golden() {
+        let expected: Hash = "32bdf332c28b92c35101ac0fbbfcecd16a5c750731975f62edd4215c4d1b7e14".parse().unwrap();
+        assert_eq!(golden_tx().witness_hash(), expected);
+    }
+
+    #[test]
+    fn test_signing_changes_witness_hash_only() {
+        let unsigned = golden_tx();
+        let mut signed = unsigned.clone();
+        signed.inputs[1].signature_script = vec![0x47; 72];
+        signed.finalize();
+        assert_eq!(signed.id(), unsigned.id());
+        assert_ne!(signed.witness_hash(), unsigned.witness_hash());
+    }
+
+    #[test]
+    fn test_transaction_id_ignores_mass() {
+        let tx = golden_tx();
+        let id = tx.id();
+        let mut tx = tx.with_mass(42_000);
+        tx.finalize();
+        assert_eq!(tx.id(), id);
+    }
+
+    #[test]
+    fn test_finalize_is_idempotent() {
+        let mut tx = golden_tx();
+        let id = tx.id();
+        tx.finalize();
+        tx.finalize();
+        assert_eq!(tx.id(), id);
+        assert_eq!(tx.hash(), id);
+    }
+
+    #[test]
+    fn test_transaction_id_commits_to_fields() {
+        let base = golden_tx().id();
+        let mut tx = golden_tx();
+        tx.lock_time += 1;
+        tx.finalize();
+        assert_ne!(tx.id(), base);
+
+        let mut tx = golden_tx();
+        tx.outputs[0].value -= 1;
+        tx.finalize();
+        assert_ne!(tx.id(), base);
+    }
+}
@@ -27,7 +27,9 @@ pub mod coinbase;
 pub mod config;
 pub mod constants;
 pub mod daa_score_timestamp;
+pub mod encoding;
 pub mod errors;
+pub mod fees;
 pub mod hashing;
 pub mod header;
 pub mod mass;
@@ -35,9 +37,11 @@ pub mod merkle;
 pub mod mining_rules;
 pub mod muhash;
 pub mod network;
+pub mod provenance;
 pub mod pruning;
 pub mod sign;
 pub mod subnets;
+pub mod time;
 pub mod trusted;
 pub mod tx;
 pub mod utxo;
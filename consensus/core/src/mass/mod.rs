@@ -80,6 +80,39 @@ pub fn utxo_plurality(spk: &ScriptPublicKey) -> u64 {
     (UTXO_CONST_STORAGE + spk.script().len()).div_ceil(UTXO_UNIT_SIZE) as u64
 }
 
+/// Estimated serialized size of an input spending a standard output: the outpoint,
+/// the signature script length, a signature script pushing a DER signature with its
+/// sighash type and a compressed public key, and the sequence
+pub const STANDARD_INPUT_ESTIMATED_SERIALIZED_SIZE: u64 = outpoint_estimated_serialized_size() + 8 + (1 + 73 + 1 + 33) + 8;
+
+/// Smallest amount in sompi that an output locked by a script of the given plurality
+/// must hold to not be dust at `min_relay_feerate` (sompi per gram).
+///
+/// An output is dust when its value doesn't cover the fee of creating and spending it:
+/// the storage mass `C·p²/v` it adds to the transaction creating it, plus the mass of
+/// the standard input spending it. The threshold is the smallest `v` with
+/// `v ≥ f·(m + C·p²/v)`, i.e. the positive root of `v² − f·m·v − f·C·p²`.
+pub fn dust_threshold(plurality: u64, storage_mass_parameter: u64, min_relay_feerate: u64) -> u64 {
+    let spend = min_relay_feerate as u128 * STANDARD_INPUT_ESTIMATED_SERIALIZED_SIZE as u128;
+    let storage = min_relay_feerate as u128 * storage_mass_parameter as u128 * plurality as u128 * plurality as u128;
+    let covers = |v: u128| v * v >= spend * v + storage;
+
+    // Start from the floating point root and settle on the exact integer boundary
+    let mut threshold = ((spend as f64 + ((spend * spend + 4 * storage) as f64).sqrt()) / 2.0) as u128;
+    while !covers(threshold) {
+        threshold += 1;
+    }
+    while threshold > 0 && covers(threshold - 1) {
+        threshold -= 1;
+    }
+    threshold as u64
+}
+
+/// Whether `output` is worth less than the [`dust_threshold`] of its script
+pub fn is_dust(output: &TransactionOutput, storage_mass_parameter: u64, min_relay_feerate: u64) -> bool {
+    output.value < dust_threshold(utxo_plurality(&output.script_public_key), storage_mass_parameter, min_relay_feerate)
+}
+
 pub trait UtxoPlurality {
     /// Returns the UTXO storage plurality for the script public key associated with this object.
     fn plurality(&self) -> u64;
@@ -410,3 +443,23 @@ pub fn calc_storage_mass(
     // max(0, harmonic_outs - arithmetic_ins)
     Some(harmonic_outs.saturating_sub(arithmetic_ins))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{MIN_TRANSACTION_FEE_RATE, STORAGE_MASS_PARAMETER};
+
+    #[test]
+    fn test_dust_threshold() {
+        assert_eq!(STANDARD_INPUT_ESTIMATED_SERIALIZED_SIZE, 160);
+        // Pinned for the default parameters; changing them changes relay policy
+        assert_eq!(dust_threshold(1, STORAGE_MASS_PARAMETER, MIN_TRANSACTION_FEE_RATE), 161);
+        assert_eq!(dust_threshold(3, STORAGE_MASS_PARAMETER, MIN_TRANSACTION_FEE_RATE), 166);
+        assert_eq!(dust_threshold(1, STORAGE_MASS_PARAMETER, 10), 1_601);
+        assert_eq!(dust_threshold(1, 0, 0), 0);
+
+        let p2pkh = ScriptPublicKey::from_vec(0, [&[0x76, 0xa9, 0x14][..], &[0; 20], &[0x88, 0xac]].concat());
+        assert!(is_dust(&TransactionOutput::new(160, p2pkh.clone()), STORAGE_MASS_PARAMETER, MIN_TRANSACTION_FEE_RATE));
+        assert!(!is_dust(&TransactionOutput::new(161, p2pkh), STORAGE_MASS_PARAMETER, MIN_TRANSACTION_FEE_RATE));
+    }
+}
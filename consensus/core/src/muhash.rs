@@ -1,33 +1,289 @@
-use borsh::{BorshDeserialize, BorshSerialize};
-use serde::{Deserialize, Serialize};
+//! MuHash3072 commitments to UTXO sets
+//!
+//! Each UTXO is hashed to a number modulo the prime 2^3072 - 1103717 and the
+//! set commits to the product of its numbers, so the commitment does not
+//! depend on the order UTXOs are added in. Removing a UTXO multiplies a
+//! separate denominator, which is only divided out when the commitment is
+//! finalized into a 32-byte digest. Unlike a sum or XOR of the elements, a set
+//! with a given product can't be built by solving linear equations, and adding
+//! a UTXO twice doesn't cancel out.
 
-/// The size of a MuHash in bytes
-pub const MUHASH_SIZE: usize = 32;
+use crate::tx::{TransactionOutpoint, UtxoEntry};
+use crate::Hash;
+use sha2::{Digest, Sha256};
+use std::fmt;
 
-/// Represents a empty MuHash value
-pub const EMPTY_MUHASH: MuHash = MuHash([0; MUHASH_SIZE]);
+/// 64-bit limbs of a number modulo the MuHash prime
+const LIMBS: usize = 48;
 
-/// MuHash implementation for efficient set membership verification
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
-pub struct MuHash([u8; MUHASH_SIZE]);
+/// Bytes of a number modulo the MuHash prime
+const NUM_SIZE: usize = LIMBS * 8;
+
+/// The MuHash prime is 2^3072 - PRIME_DIFF
+const PRIME_DIFF: u64 = 1103717;
+
+/// The size of a serialized MuHash state, numerator then denominator, in bytes
+pub const MUHASH_SIZE: usize = 2 * NUM_SIZE;
+
+/// MuHash of the empty set
+pub const EMPTY_MUHASH: MuHash = MuHash { numerator: Num3072::ONE, denominator: Num3072::ONE };
+
+/// A number below 2^3072, reduced modulo the MuHash prime only where it matters
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Num3072([u64; LIMBS]);
+
+impl Num3072 {
+    const ONE: Self = {
+        let mut limbs = [0; LIMBS];
+        limbs[0] = 1;
+        Self(limbs)
+    };
+
+    /// `self * other` modulo the prime; 2^3072 is congruent to PRIME_DIFF
+    fn mul(&self, other: &Self) -> Self {
+        let mut wide = [0u64; 2 * LIMBS];
+        for i in 0..LIMBS {
+            let mut carry = 0u128;
+            for j in 0..LIMBS {
+                let t = self.0[i] as u128 * other.0[j] as u128 + wide[i + j] as u128 + carry;
+                wide[i + j] = t as u64;
+                carry = t >> 64;
+            }
+            wide[i + LIMBS] = carry as u64;
+        }
+        let mut limbs = [0u64; LIMBS];
+        let mut carry = 0u128;
+        for i in 0..LIMBS {
+            let t = wide[i] as u128 + wide[i + LIMBS] as u128 * PRIME_DIFF as u128 + carry;
+            limbs[i] = t as u64;
+            carry = t >> 64;
+        }
+        let mut product = Self(limbs);
+        product.fold(carry * PRIME_DIFF as u128);
+        product
+    }
+
+    /// Add `extra`, folding whatever overflows 2^3072 back in
+    fn fold(&mut self, mut extra: u128) {
+        while extra > 0 {
+            let mut carry = extra;
+            for limb in self.0.iter_mut() {
+                let t = *limb as u128 + carry;
+                *limb = t as u64;
+                carry = t >> 64;
+                if carry == 0 {
+                    break;
+                }
+            }
+            extra = carry * PRIME_DIFF as u128;
+        }
+    }
+
+    /// The unique representative below the prime
+    fn reduced(mut self) -> Self {
+        let overflows = self.0[1..].iter().all(|limb| *limb == u64::MAX) && self.0[0] >= PRIME_DIFF.wrapping_neg();
+        if overflows {
+            // Subtracting the prime is adding PRIME_DIFF and dropping 2^3072
+            self.0[0] = self.0[0].wrapping_add(PRIME_DIFF);
+            self.0[1..].iter_mut().for_each(|limb| *limb = 0);
+        }
+        self
+    }
+
+    /// Inverse modulo the prime, as `self^(prime - 2)`
+    fn inverse(&self) -> Self {
+        let mut exponent = [u64::MAX; LIMBS];
+        exponent[0] = (PRIME_DIFF + 2).wrapping_neg();
+        let mut result = Self::ONE;
+        for limb in exponent.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.mul(&result);
+                if limb >> bit & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    fn to_bytes(self) -> [u8; NUM_SIZE] {
+        let mut bytes = [0u8; NUM_SIZE];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.0) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0u64; LIMBS];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().expect("chunk of 8 bytes"));
+        }
+        Self(limbs).reduced()
+    }
+}
+
+/// MuHash accumulator for efficient set membership verification
+#[derive(Clone, Copy)]
+pub struct MuHash {
+    numerator: Num3072,
+    denominator: Num3072,
+}
 
 impl MuHash {
-    /// Creates a new MuHash from bytes
-    pub fn new(bytes: [u8; MUHASH_SIZE]) -> Self {
-        Self(bytes)
+    /// Restores a MuHash from its serialized state
+    pub fn from_bytes(bytes: [u8; MUHASH_SIZE]) -> Self {
+        Self { numerator: Num3072::from_bytes(&bytes[..NUM_SIZE]), denominator: Num3072::from_bytes(&bytes[NUM_SIZE..]) }
     }
 
-    /// Returns the bytes of the MuHash
-    pub fn as_bytes(&self) -> &[u8; MUHASH_SIZE] {
-        &self.0
+    /// Serializes the state, from which [`Self::from_bytes`] restores it
+    pub fn to_bytes(&self) -> [u8; MUHASH_SIZE] {
+        let mut bytes = [0u8; MUHASH_SIZE];
+        bytes[..NUM_SIZE].copy_from_slice(&self.numerator.reduced().to_bytes());
+        bytes[NUM_SIZE..].copy_from_slice(&self.denominator.reduced().to_bytes());
+        bytes
     }
 
-    /// Combines this MuHash with another one
+    /// Combines this MuHash with another one, committing to the union of their sets
     pub fn combine(&mut self, other: &MuHash) {
-        // TODO: Implement actual MuHash combining logic
-        // This is just a placeholder that XORs the bytes
-        for i in 0..MUHASH_SIZE {
-            self.0[i] ^= other.0[i];
+        self.numerator = self.numerator.mul(&other.numerator);
+        self.denominator = self.denominator.mul(&other.denominator);
+    }
+
+    /// The number a UTXO contributes to a UTXO set commitment: its hash,
+    /// expanded to 3072 bits by hashing it with a block counter
+    fn utxo_element(outpoint: &TransactionOutpoint, entry: &UtxoEntry) -> Num3072 {
+        let mut hasher = Sha256::new();
+        hasher.update(outpoint.transaction_id.as_bytes());
+        hasher.update(outpoint.index.to_le_bytes());
+        hasher.update(entry.amount.to_le_bytes());
+        hasher.update(entry.block_daa_score.to_le_bytes());
+        hasher.update([entry.is_coinbase as u8]);
+        hasher.update(entry.script_public_key.version().to_le_bytes());
+        hasher.update(entry.script_public_key.script());
+        let seed = hasher.finalize();
+
+        let mut bytes = [0u8; NUM_SIZE];
+        for (counter, block) in bytes.chunks_exact_mut(32).enumerate() {
+            block.copy_from_slice(&Sha256::new().chain_update(seed).chain_update([counter as u8]).finalize());
         }
+        Num3072::from_bytes(&bytes)
+    }
+
+    /// Adds a UTXO to the committed set
+    pub fn add_utxo(&mut self, outpoint: &TransactionOutpoint, entry: &UtxoEntry) {
+        self.numerator = self.numerator.mul(&Self::utxo_element(outpoint, entry));
+    }
+
+    /// Removes a UTXO from the committed set
+    pub fn remove_utxo(&mut self, outpoint: &TransactionOutpoint, entry: &UtxoEntry) {
+        self.denominator = self.denominator.mul(&Self::utxo_element(outpoint, entry));
+    }
+
+    /// The 32-byte digest of the committed set
+    pub fn finalize(&self) -> Hash {
+        let product = self.numerator.mul(&self.denominator.inverse()).reduced();
+        Hash::from_bytes(Sha256::digest(product.to_bytes()).into())
+    }
+}
+
+/// Equal when committing to the same set, however it was reached
+impl PartialEq for MuHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.numerator.mul(&other.denominator).reduced() == other.numerator.mul(&self.denominator).reduced()
+    }
+}
+
+impl Eq for MuHash {}
+
+impl fmt::Debug for MuHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MuHash({})", self.finalize())
+    }
+}
+
+impl fmt::Display for MuHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.finalize(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx::ScriptPublicKey;
+
+    fn utxo(n: u64) -> (TransactionOutpoint, UtxoEntry) {
+        let outpoint = TransactionOutpoint::new(Hash::from_u64_word(n), 0);
+        (outpoint, UtxoEntry::new(n * 100, ScriptPublicKey::from_vec(0, vec![n as u8; 34]), n, false))
+    }
+
+    #[test]
+    fn test_utxo_commitment_is_order_independent() {
+        let (a, b) = (utxo(1), utxo(2));
+
+        let mut forward = EMPTY_MUHASH;
+        forward.add_utxo(&a.0, &a.1);
+        forward.add_utxo(&b.0, &b.1);
+        let mut backward = EMPTY_MUHASH;
+        backward.add_utxo(&b.0, &b.1);
+        backward.add_utxo(&a.0, &a.1);
+        assert_eq!(forward, backward);
+        assert_eq!(forward.finalize(), backward.finalize());
+
+        forward.remove_utxo(&b.0, &b.1);
+        let mut only_a = EMPTY_MUHASH;
+        only_a.add_utxo(&a.0, &a.1);
+        assert_eq!(forward, only_a);
+        assert_eq!(forward.finalize(), only_a.finalize());
+
+        forward.remove_utxo(&a.0, &a.1);
+        assert_eq!(forward, EMPTY_MUHASH);
+    }
+
+    #[test]
+    fn test_duplicates_do_not_cancel() {
+        let a = utxo(1);
+        let mut twice = EMPTY_MUHASH;
+        twice.add_utxo(&a.0, &a.1);
+        twice.add_utxo(&a.0, &a.1);
+        assert_ne!(twice, EMPTY_MUHASH);
+        let mut once = EMPTY_MUHASH;
+        once.add_utxo(&a.0, &a.1);
+        assert_ne!(twice, once);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut muhash = EMPTY_MUHASH;
+        let (a, b) = (utxo(1), utxo(2));
+        muhash.add_utxo(&a.0, &a.1);
+        muhash.add_utxo(&b.0, &b.1);
+        muhash.remove_utxo(&a.0, &a.1);
+        let restored = MuHash::from_bytes(muhash.to_bytes());
+        assert_eq!(restored, muhash);
+        assert_eq!(restored.to_bytes(), muhash.to_bytes());
+
+        let mut other = EMPTY_MUHASH;
+        other.add_utxo(&b.0, &b.1);
+        let mut combined = EMPTY_MUHASH;
+        combined.combine(&muhash);
+        assert_eq!(combined.finalize(), other.finalize());
+    }
+
+    #[test]
+    fn test_arithmetic_modulo_the_prime() {
+        // (prime - 1)^2 = 1
+        let mut minus_one = Num3072([u64::MAX; LIMBS]);
+        minus_one.0[0] = (PRIME_DIFF + 1).wrapping_neg();
+        assert!(minus_one.mul(&minus_one).reduced() == Num3072::ONE);
+
+        // The prime itself reduces to zero
+        let mut prime = Num3072([u64::MAX; LIMBS]);
+        prime.0[0] = PRIME_DIFF.wrapping_neg();
+        assert!(prime.reduced() == Num3072([0; LIMBS]));
+
+        let element = MuHash::utxo_element(&utxo(3).0, &utxo(3).1);
+        assert!(element.mul(&element.inverse()).reduced() == Num3072::ONE);
     }
-}
\ No newline at end of file
+}
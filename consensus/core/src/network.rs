@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
 /// Network type identifies the network a node is operating on
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -36,4 +37,12 @@ impl NetworkType {
         ]
         .into_iter()
     }
-}
\ No newline at end of file
+}
+
+impl FromStr for NetworkType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NetworkType::iter().find(|network| network.to_string() == s).ok_or_else(|| format!("Unknown network: {}", s))
+    }
+}
@@ -0,0 +1,71 @@
+//! Where the node first got a block from
+//!
+//! Kept per block to investigate forks and odd blocks after the fact, and to
+//! hold peers to account for invalid blocks that only fail validation later,
+//! e.g. once their missing parents arrive.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Who delivered a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockSource {
+    /// A P2P peer at this address
+    Peer(SocketAddr),
+    /// Submitted over RPC
+    Rpc,
+    /// Found by the node's built-in miner
+    Miner,
+}
+
+impl BlockSource {
+    /// Address of the delivering peer, if any
+    pub fn peer(&self) -> Option<SocketAddr> {
+        match self {
+            BlockSource::Peer(address) => Some(*address),
+            BlockSource::Rpc | BlockSource::Miner => None,
+        }
+    }
+}
+
+impl fmt::Display for BlockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockSource::Peer(address) => write!(f, "{}", address),
+            BlockSource::Rpc => f.write_str("rpc"),
+            BlockSource::Miner => f.write_str("miner"),
+        }
+    }
+}
+
+/// First delivery of a block and how its validation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockProvenance {
+    pub source: BlockSource,
+    /// Milliseconds since the unix epoch
+    pub first_seen: u64,
+    /// The block failed validation
+    pub invalid: bool,
+}
+
+impl BlockProvenance {
+    pub fn new(source: BlockSource, first_seen: u64) -> Self {
+        Self { source, first_seen, invalid: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_display() {
+        let address: SocketAddr = "10.0.0.1:16111".parse().unwrap();
+        assert_eq!(BlockSource::Peer(address).to_string(), "10.0.0.1:16111");
+        assert_eq!(BlockSource::Rpc.to_string(), "rpc");
+        assert_eq!(BlockSource::Miner.to_string(), "miner");
+        assert_eq!(BlockSource::Peer(address).peer(), Some(address));
+        assert_eq!(BlockSource::Rpc.peer(), None);
+    }
+}
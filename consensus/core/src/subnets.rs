@@ -9,6 +9,13 @@ pub const SUBNETWORK_ID_SIZE: usize = 20;
 /// Subnetwork ID for coinbase transactions
 pub const SUBNETWORK_ID_COINBASE: SubnetworkId = SubnetworkId([0; SUBNETWORK_ID_SIZE]);
 
+/// Subnetwork ID for regular transactions
+pub const SUBNETWORK_ID_NATIVE: SubnetworkId = {
+    let mut bytes = [0; SUBNETWORK_ID_SIZE];
+    bytes[0] = 1;
+    SubnetworkId(bytes)
+};
+
 /// Represents a unique identifier for a subnetwork
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct SubnetworkId([u8; SUBNETWORK_ID_SIZE]);
@@ -23,6 +30,12 @@ impl SubnetworkId {
     pub fn as_bytes(&self) -> &[u8; SUBNETWORK_ID_SIZE] {
         &self.0
     }
+
+    /// Whether this is the native or the coinbase subnetwork, the only ones
+    /// consensus knows. Any other is experimental and pays for gas.
+    pub fn is_builtin(&self) -> bool {
+        *self == SUBNETWORK_ID_NATIVE || *self == SUBNETWORK_ID_COINBASE
+    }
 }
 
 impl Default for SubnetworkId {
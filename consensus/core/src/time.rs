@@ -0,0 +1,226 @@
+//! Time sources for consensus-relevant timestamps
+//!
+//! Everything that compares block timestamps against the present reads it from
+//! an injected [`Clock`], so tests can fix the time and nodes can correct a
+//! skewed local clock with the times their peers report.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Largest correction the peers' clocks may apply to ours (70 minutes in milliseconds)
+pub const DEFAULT_MAX_TIME_OFFSET: u64 = 70 * 60 * 1000;
+
+/// Peer samples needed before the local clock is adjusted at all
+pub const MIN_TIME_SAMPLES: usize = 5;
+
+/// Peer samples kept; later peers are ignored
+pub const MAX_TIME_SAMPLES: usize = 200;
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the unix epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// The local system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+/// Clock that only moves when told to
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(now_millis: u64) -> Self {
+        Self { now: AtomicU64::new(now_millis) }
+    }
+
+    pub fn set(&self, now_millis: u64) {
+        self.now.store(now_millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// Local clock corrected by the median offset of the clocks peers report in
+/// their version messages. One sample is taken per peer address, the offset is
+/// applied once [`MIN_TIME_SAMPLES`] peers reported and is clamped to the
+/// maximum offset, so peers can't move our time arbitrarily far.
+pub struct NetworkAdjustedClock {
+    local: Arc<dyn Clock>,
+    max_offset: u64,
+    samples: Mutex<TimeSamples>,
+    offset: AtomicI64,
+}
+
+#[derive(Default)]
+struct TimeSamples {
+    sources: HashSet<IpAddr>,
+    offsets: Vec<i64>,
+}
+
+impl NetworkAdjustedClock {
+    pub fn new(local: Arc<dyn Clock>) -> Self {
+        Self { local, max_offset: DEFAULT_MAX_TIME_OFFSET, samples: Default::default(), offset: AtomicI64::new(0) }
+    }
+
+    pub fn with_max_offset(self, max_offset: u64) -> Self {
+        Self { max_offset, ..self }
+    }
+
+    /// Time of the local clock, without the peers' correction
+    pub fn local_millis(&self) -> u64 {
+        self.local.now_millis()
+    }
+
+    /// Milliseconds currently added to the local clock
+    pub fn offset(&self) -> i64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    /// Number of peers whose clocks were sampled
+    pub fn sample_count(&self) -> usize {
+        self.samples.lock().unwrap().offsets.len()
+    }
+
+    /// Record that `source` reported `remote_millis` as its time just now. Returns
+    /// the median offset of the peers when it exceeds the maximum offset, a sign
+    /// that the local clock is wrong and the operator should be warned.
+    pub fn add_sample(&self, source: IpAddr, remote_millis: u64) -> Option<i64> {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.offsets.len() >= MAX_TIME_SAMPLES || !samples.sources.insert(source) {
+            return None;
+        }
+        samples.offsets.push(remote_millis as i64 - self.local.now_millis() as i64);
+        if samples.offsets.len() < MIN_TIME_SAMPLES {
+            return None;
+        }
+
+        let mut offsets = samples.offsets.clone();
+        offsets.sort_unstable();
+        let median = offsets[offsets.len() / 2];
+        let max_offset = self.max_offset as i64;
+        self.offset.store(median.clamp(-max_offset, max_offset), Ordering::SeqCst);
+        (median.abs() > max_offset).then_some(median)
+    }
+}
+
+impl Clock for NetworkAdjustedClock {
+    fn now_millis(&self) -> u64 {
+        self.local.now_millis().saturating_add_signed(self.offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+    const MINUTE: i64 = 60 * 1000;
+
+    fn peer(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    fn adjusted() -> (Arc<MockClock>, NetworkAdjustedClock) {
+        let local = Arc::new(MockClock::new(NOW));
+        (local.clone(), NetworkAdjustedClock::new(local))
+    }
+
+    fn adjusted_with_max_offset(max_offset: u64) -> (Arc<MockClock>, NetworkAdjustedClock) {
+        let (local, clock) = adjusted();
+        (local, clock.with_max_offset(max_offset))
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(5);
+        clock.advance(10);
+        assert_eq!(clock.now_millis(), 15);
+        clock.set(3);
+        assert_eq!(clock.now_millis(), 3);
+        assert!(SystemClock.now_millis() > NOW);
+    }
+
+    #[test]
+    fn test_offset_is_the_median_of_peer_samples() {
+        let (local, clock) = adjusted();
+        for (n, skew) in [3, -1, 2, 1].into_iter().enumerate() {
+            assert_eq!(clock.add_sample(peer(n as u8), (NOW as i64 + skew * MINUTE) as u64), None);
+        }
+        // Too few peers to trust
+        assert_eq!(clock.offset(), 0);
+        assert_eq!(clock.now_millis(), NOW);
+
+        clock.add_sample(peer(4), NOW + 10 * MINUTE as u64);
+        assert_eq!(clock.offset(), 2 * MINUTE);
+        local.advance(1000);
+        assert_eq!(clock.now_millis(), NOW + 1000 + 2 * MINUTE as u64);
+
+        // A peer counts once however often it reconnects
+        for _ in 0..5 {
+            clock.add_sample(peer(1), NOW - 30 * MINUTE as u64);
+        }
+        assert_eq!(clock.sample_count(), 5);
+        assert_eq!(clock.offset(), 2 * MINUTE);
+    }
+
+    #[test]
+    fn test_skewed_peers_are_clamped_and_reported() {
+        let (_, clock) = adjusted();
+        let max = DEFAULT_MAX_TIME_OFFSET as i64;
+        let ahead = NOW as i64 + 3 * 60 * MINUTE;
+        let mut warning = None;
+        for n in 0..MIN_TIME_SAMPLES as u8 {
+            warning = clock.add_sample(peer(n), ahead as u64);
+        }
+        // Our clock looks three hours slow, but it is moved by 70 minutes only
+        assert_eq!(warning, Some(3 * 60 * MINUTE));
+        assert_eq!(clock.offset(), max);
+        assert_eq!(clock.now_millis(), NOW + DEFAULT_MAX_TIME_OFFSET);
+
+        let (_, clock) = adjusted_with_max_offset(10 * MINUTE as u64);
+        for n in 0..MIN_TIME_SAMPLES as u8 {
+            warning = clock.add_sample(peer(n), NOW - 15 * MINUTE as u64);
+        }
+        assert_eq!(warning, Some(-15 * MINUTE));
+        assert_eq!(clock.offset(), -10 * MINUTE);
+
+        // Within the bound nothing is reported
+        let (_, clock) = adjusted();
+        for n in 0..MIN_TIME_SAMPLES as u8 {
+            warning = clock.add_sample(peer(n), NOW + 5 * MINUTE as u64);
+        }
+        assert_eq!(warning, None);
+        assert_eq!(clock.offset(), 5 * MINUTE);
+    }
+
+    #[test]
+    fn test_samples_are_capped() {
+        let (_, clock) = adjusted();
+        for n in 0..MAX_TIME_SAMPLES as u32 + 10 {
+            clock.add_sample(IpAddr::from(n.to_be_bytes()), NOW + MINUTE as u64);
+        }
+        assert_eq!(clock.sample_count(), MAX_TIME_SAMPLES);
+        assert_eq!(clock.offset(), MINUTE);
+    }
+}
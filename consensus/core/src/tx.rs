@@ -6,6 +6,7 @@
 
 #![allow(non_snake_case)]
 
+pub mod script_class;
 mod script_public_key;
 
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -247,19 +248,27 @@ impl Transaction {
         // Add mass for payload
         mass += (self.payload.len() as u64 + 31) / 32 * 10;
 
-        // Add gas cost to mass if it's a subnetwork transaction
-        if self.subnetwork_id != SubnetworkId::default() {
+        // Only experimental subnetworks use gas; the native and coinbase ones carry none
+        if !self.subnetwork_id.is_builtin() {
             mass += self.gas;
         }
 
         mass
     }
 
+    /// Computes the transaction id from the current fields (`id()` returns the value cached by `finalize`).
+    /// Signature scripts are excluded, so signing does not change it.
     pub fn hash(&self) -> Hash {
         use crate::hashing::tx::calc_transaction_hash;
         calc_transaction_hash(self)
     }
 
+    /// Hash committing to the whole transaction including signature scripts.
+    /// Unlike the id, it changes whenever the transaction is (re-)signed.
+    pub fn witness_hash(&self) -> Hash {
+        hashing::tx::calc_transaction_witness_hash(self)
+    }
+
     /// Determines whether or not a transaction is a coinbase transaction. A coinbase
     /// transaction is a special transaction created by miners that distributes fees and block subsidy
     /// to the previous blocks' miners, and specifies the script_pub_key that will be used to pay the current
@@ -0,0 +1,285 @@
+//!
+//! Standard script public key templates and the addresses paying to them.
+//!
+//! Addresses are base58check encoded: a version byte identifying the script
+//! class, the key or hash payload, and the first four bytes of the payload's
+//! double SHA256.
+//!
+
+use super::ScriptPublicKey;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+const OP_DUP: u8 = 0x76;
+const OP_EQUAL: u8 = 0x87;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_HASH160: u8 = 0xa9;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKMULTISIG: u8 = 0xae;
+
+/// Address version of [`ScriptClass::PubKeyHash`] scripts
+pub const PUBKEY_HASH_ADDRESS_VERSION: u8 = 0x00;
+/// Address version of [`ScriptClass::ScriptHash`] scripts
+pub const SCRIPT_HASH_ADDRESS_VERSION: u8 = 0x05;
+/// Address version of [`ScriptClass::PubKey`] scripts
+pub const PUBKEY_ADDRESS_VERSION: u8 = 0x10;
+/// Address version of [`ScriptClass::PubKeyECDSA`] scripts
+pub const PUBKEY_ECDSA_ADDRESS_VERSION: u8 = 0x11;
+
+/// Standard script templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptClass {
+    /// `<32 byte x-only key> OP_CHECKSIG`
+    PubKey,
+    /// `<33 byte compressed key> OP_CHECKSIG`
+    PubKeyECDSA,
+    /// `OP_DUP OP_HASH160 <20 byte key hash> OP_EQUALVERIFY OP_CHECKSIG`
+    PubKeyHash,
+    /// `OP_HASH160 <20 byte script hash> OP_EQUAL`
+    ScriptHash,
+    /// `OP_m <compressed or uncompressed key>... OP_n OP_CHECKMULTISIG`; has no address
+    Multisig,
+    /// Anything else, including scripts of unknown versions
+    NonStandard,
+}
+
+impl ScriptClass {
+    fn address_version(self) -> Option<u8> {
+        match self {
+            ScriptClass::PubKey => Some(PUBKEY_ADDRESS_VERSION),
+            ScriptClass::PubKeyECDSA => Some(PUBKEY_ECDSA_ADDRESS_VERSION),
+            ScriptClass::PubKeyHash => Some(PUBKEY_HASH_ADDRESS_VERSION),
+            ScriptClass::ScriptHash => Some(SCRIPT_HASH_ADDRESS_VERSION),
+            ScriptClass::Multisig | ScriptClass::NonStandard => None,
+        }
+    }
+}
+
+/// Why an address could not be decoded
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("The address is not valid base58")]
+    InvalidEncoding,
+
+    #[error("The address checksum is invalid")]
+    BadChecksum,
+
+    #[error("The address has an unknown version {0}")]
+    UnknownVersion(u8),
+
+    #[error("The address payload has an invalid length {0}")]
+    BadPayloadLength(usize),
+
+    #[error("The address payload is not a valid public key")]
+    InvalidPublicKey,
+}
+
+/// Class of the script template `spk` follows
+pub fn classify(spk: &ScriptPublicKey) -> ScriptClass {
+    if spk.version() != 0 {
+        return ScriptClass::NonStandard;
+    }
+    match spk.script() {
+        [0x20, key @ .., OP_CHECKSIG] if key.len() == 32 => ScriptClass::PubKey,
+        [0x21, key @ .., OP_CHECKSIG] if key.len() == 33 && matches!(key[0], 0x02 | 0x03) => ScriptClass::PubKeyECDSA,
+        [OP_DUP, OP_HASH160, 0x14, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] if hash.len() == 20 => ScriptClass::PubKeyHash,
+        [OP_HASH160, 0x14, hash @ .., OP_EQUAL] if hash.len() == 20 => ScriptClass::ScriptHash,
+        script if is_multisig(script) => ScriptClass::Multisig,
+        _ => ScriptClass::NonStandard,
+    }
+}
+
+fn is_multisig(script: &[u8]) -> bool {
+    let [required @ OP_1..=OP_16, keys @ .., total @ OP_1..=OP_16, OP_CHECKMULTISIG] = script else {
+        return false;
+    };
+    let mut keys = keys;
+    let mut count = 0;
+    while let [len @ (0x21 | 0x41), rest @ ..] = keys {
+        match rest.get(*len as usize..) {
+            Some(next) => keys = next,
+            None => return false,
+        }
+        count += 1;
+    }
+    keys.is_empty() && required <= total && count == (total - OP_1 + 1) as usize
+}
+
+/// Address `spk` pays to, `None` for scripts without one
+pub fn extract_address(spk: &ScriptPublicKey) -> Option<String> {
+    let class = classify(spk);
+    let script = spk.script();
+    let payload = match class {
+        ScriptClass::PubKey | ScriptClass::PubKeyECDSA => &script[1..script.len() - 1],
+        ScriptClass::PubKeyHash => &script[3..23],
+        ScriptClass::ScriptHash => &script[2..22],
+        ScriptClass::Multisig | ScriptClass::NonStandard => return None,
+    };
+
+    let mut bytes = Vec::with_capacity(payload.len() + 5);
+    bytes.push(class.address_version()?);
+    bytes.extend_from_slice(payload);
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(&checksum);
+    Some(bs58::encode(bytes).into_string())
+}
+
+/// Script paying to `address`, the inverse of [`extract_address`]
+pub fn pay_to_address_script(address: &str) -> Result<ScriptPublicKey, AddressError> {
+    let bytes = bs58::decode(address).into_vec().map_err(|_| AddressError::InvalidEncoding)?;
+    if bytes.len() < 5 {
+        return Err(AddressError::BadPayloadLength(bytes.len().saturating_sub(5)));
+    }
+    let (versioned, check) = bytes.split_at(bytes.len() - 4);
+    if checksum(versioned) != check {
+        return Err(AddressError::BadChecksum);
+    }
+
+    let (version, payload) = (versioned[0], &versioned[1..]);
+    let expected_len = match version {
+        PUBKEY_ADDRESS_VERSION => 32,
+        PUBKEY_ECDSA_ADDRESS_VERSION => 33,
+        PUBKEY_HASH_ADDRESS_VERSION | SCRIPT_HASH_ADDRESS_VERSION => 20,
+        _ => return Err(AddressError::UnknownVersion(version)),
+    };
+    if payload.len() != expected_len {
+        return Err(AddressError::BadPayloadLength(payload.len()));
+    }
+
+    let script = match version {
+        PUBKEY_ADDRESS_VERSION | PUBKEY_ECDSA_ADDRESS_VERSION => {
+            [&[payload.len() as u8][..], payload, &[OP_CHECKSIG]].concat()
+        }
+        PUBKEY_HASH_ADDRESS_VERSION => [&[OP_DUP, OP_HASH160, 0x14][..], payload, &[OP_EQUALVERIFY, OP_CHECKSIG]].concat(),
+        _ => [&[OP_HASH160, 0x14][..], payload, &[OP_EQUAL]].concat(),
+    };
+    let spk = ScriptPublicKey::from_vec(0, script);
+    // A compressed key address must carry a valid key prefix to classify as standard
+    if classify(&spk) == ScriptClass::NonStandard {
+        return Err(AddressError::InvalidPublicKey);
+    }
+    Ok(spk)
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 4] {
+    let hash = Sha256::digest(Sha256::digest(bytes));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spk(script: Vec<u8>) -> ScriptPublicKey {
+        ScriptPublicKey::from_vec(0, script)
+    }
+
+    fn multisig(required: u8, keys: &[Vec<u8>]) -> Vec<u8> {
+        let mut script = vec![OP_1 + required - 1];
+        for key in keys {
+            script.push(key.len() as u8);
+            script.extend_from_slice(key);
+        }
+        script.extend_from_slice(&[OP_1 + keys.len() as u8 - 1, OP_CHECKMULTISIG]);
+        script
+    }
+
+    #[test]
+    fn test_address_round_trip() {
+        let cases = [
+            (ScriptClass::PubKey, [&[0x20][..], &[7; 32], &[OP_CHECKSIG]].concat()),
+            (ScriptClass::PubKeyECDSA, [&[0x21, 0x02][..], &[7; 32], &[OP_CHECKSIG]].concat()),
+            (ScriptClass::PubKeyHash, [&[OP_DUP, OP_HASH160, 0x14][..], &[7; 20], &[OP_EQUALVERIFY, OP_CHECKSIG]].concat()),
+            (ScriptClass::ScriptHash, [&[OP_HASH160, 0x14][..], &[7; 20], &[OP_EQUAL]].concat()),
+        ];
+        for (class, script) in cases {
+            let script = spk(script);
+            assert_eq!(classify(&script), class);
+            let address = extract_address(&script).unwrap();
+            assert_eq!(pay_to_address_script(&address).unwrap(), script, "{:?}", class);
+        }
+
+        // Existing wallet addresses keep their meaning
+        let script = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+        assert_eq!(classify(&script), ScriptClass::PubKeyHash);
+        assert_eq!(extract_address(&script).unwrap(), "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2");
+    }
+
+    #[test]
+    fn test_multisig_and_non_standard_have_no_address() {
+        let compressed = [&[0x03][..], &[1; 32]].concat();
+        let uncompressed = [&[0x04][..], &[2; 64]].concat();
+        let script = spk(multisig(2, &[compressed.clone(), uncompressed, compressed.clone()]));
+        assert_eq!(classify(&script), ScriptClass::Multisig);
+        assert_eq!(extract_address(&script), None);
+
+        let non_standard = [
+            // More signatures required than keys
+            multisig(2, &[compressed.clone()]),
+            // Key count disagrees with OP_n
+            [&multisig(1, &[compressed.clone()])[..2 + 33], &[0x52, OP_CHECKMULTISIG]].concat(),
+            // Key length not a public key
+            multisig(1, &[vec![1; 20]]),
+            // Truncated key push
+            vec![OP_1, 0x21, 1, 2, OP_1, OP_CHECKMULTISIG],
+            // Compressed key with a bad prefix
+            [&[0x21, 0x05][..], &[7; 32], &[OP_CHECKSIG]].concat(),
+            [&[OP_DUP, OP_HASH160, 0x14][..], &[7; 19], &[OP_EQUALVERIFY, OP_CHECKSIG]].concat(),
+            vec![OP_CHECKSIG],
+            vec![],
+        ];
+        for script in non_standard {
+            assert_eq!(classify(&spk(script.clone())), ScriptClass::NonStandard, "{}", hex::encode(&script));
+            assert_eq!(extract_address(&spk(script)), None);
+        }
+
+        // Only version 0 scripts are standard
+        let hash = [&[OP_HASH160, 0x14][..], &[7; 20], &[OP_EQUAL]].concat();
+        assert_eq!(classify(&ScriptPublicKey::from_vec(1, hash)), ScriptClass::NonStandard);
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert_eq!(pay_to_address_script("invalid"), Err(AddressError::InvalidEncoding));
+        assert_eq!(pay_to_address_script(""), Err(AddressError::BadPayloadLength(0)));
+        assert_eq!(pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"), Err(AddressError::BadChecksum));
+
+        let encode = |payload: &[u8]| {
+            let checksum = checksum(payload);
+            bs58::encode([payload, &checksum].concat()).into_string()
+        };
+        assert_eq!(pay_to_address_script(&encode(&[0x42; 21])), Err(AddressError::UnknownVersion(0x42)));
+        assert_eq!(pay_to_address_script(&encode(&[0; 33])), Err(AddressError::BadPayloadLength(32)));
+        let bad_prefix = [&[PUBKEY_ECDSA_ADDRESS_VERSION, 0x05][..], &[0; 32]].concat();
+        assert_eq!(pay_to_address_script(&encode(&bad_prefix)), Err(AddressError::InvalidPublicKey));
+    }
+
+    #[test]
+    fn test_classify_arbitrary_scripts() {
+        // xorshift, so that failures reproduce
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let templates = [0x20, 0x21, OP_DUP, OP_HASH160, OP_1, OP_16, OP_CHECKSIG, OP_CHECKMULTISIG];
+        for _ in 0..20_000 {
+            let len = (next() % 80) as usize;
+            let mut script: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Bias toward template bytes so that near misses get exercised
+            if let Some(first) = script.first_mut() {
+                *first = templates[(next() % templates.len() as u64) as usize];
+            }
+            let script = spk(script);
+            let class = classify(&script);
+            match extract_address(&script) {
+                Some(address) => assert_eq!(pay_to_address_script(&address).unwrap(), script),
+                None => assert!(matches!(class, ScriptClass::Multisig | ScriptClass::NonStandard)),
+            }
+        }
+    }
+}
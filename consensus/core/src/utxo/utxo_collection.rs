@@ -51,6 +51,11 @@ impl UtxoCollection {
         self.utxos.is_empty()
     }
 
+    /// Iterate over all entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&TransactionOutpoint, &UtxoEntry)> {
+        self.utxos.iter()
+    }
+
     /// Returns total supply implied by UTXOs (sum of all utxo amounts)
     pub fn total_supply(&self) -> u128 {
         self.utxos.values().map(|e| e.amount as u128).sum()
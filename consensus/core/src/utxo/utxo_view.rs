@@ -1,25 +1,135 @@
-use crate::tx::TransactionOutpoint;
+use std::collections::HashSet;
+
+use crate::tx::{Transaction, TransactionOutpoint, UtxoEntry};
 use crate::utxo::UtxoCollection;
 
-/// Read-only view on an existing UTXO collection
+/// View on an existing UTXO collection with an in-memory overlay of outputs
+/// added and spent on top of it, such as by pending transactions. The
+/// underlying collection is never modified.
 pub struct UtxoView<'a> {
     inner: &'a UtxoCollection,
+    added: UtxoCollection,
+    removed: HashSet<TransactionOutpoint>,
 }
 
 impl<'a> UtxoView<'a> {
     pub fn new(inner: &'a UtxoCollection) -> Self {
-        Self { inner }
+        Self { inner, added: UtxoCollection::new(), removed: HashSet::new() }
     }
 
     pub fn contains(&self, outpoint: &TransactionOutpoint) -> bool {
-        self.inner.contains(outpoint)
+        self.get(outpoint).is_some()
+    }
+
+    pub fn get(&self, outpoint: &TransactionOutpoint) -> Option<&UtxoEntry> {
+        if self.removed.contains(outpoint) {
+            return None;
+        }
+        self.added.get(outpoint).or_else(|| self.inner.get(outpoint))
+    }
+
+    /// Make `entry` visible at `outpoint`
+    pub fn add(&mut self, outpoint: TransactionOutpoint, entry: UtxoEntry) {
+        self.removed.remove(&outpoint);
+        self.added.insert(outpoint, entry);
     }
 
-    pub fn get(&self, outpoint: &TransactionOutpoint) -> Option<&crate::tx::UtxoEntry> {
-        self.inner.get(outpoint)
+    /// Hide `outpoint`, returning the entry it had in the view
+    pub fn remove(&mut self, outpoint: &TransactionOutpoint) -> Option<UtxoEntry> {
+        if self.removed.contains(outpoint) {
+            return None;
+        }
+        let entry = self.added.remove(outpoint).or_else(|| self.inner.get(outpoint).cloned())?;
+        if self.inner.contains(outpoint) {
+            self.removed.insert(*outpoint);
+        }
+        Some(entry)
+    }
+
+    /// Spend the inputs of `tx` and add its outputs, as of `block_daa_score`.
+    /// Inputs missing from the view are skipped; validating them is the caller's job.
+    pub fn apply_transaction(&mut self, tx: &Transaction, block_daa_score: u64) {
+        if !tx.is_coinbase() {
+            for input in &tx.inputs {
+                self.remove(&input.previous_outpoint);
+            }
+        }
+        let id = tx.id();
+        for (index, output) in tx.outputs.iter().enumerate() {
+            let entry = UtxoEntry::new(output.value, output.script_public_key.clone(), block_daa_score, tx.is_coinbase());
+            self.add(TransactionOutpoint::new(id, index as u32), entry);
+        }
     }
 
     pub fn total_supply(&self) -> u128 {
-        self.inner.total_supply()
+        // Underlying entries that are spent or replaced by the overlay
+        let hidden: u128 = self
+            .removed
+            .iter()
+            .chain(self.added.iter().map(|(outpoint, _)| outpoint))
+            .filter_map(|outpoint| self.inner.get(outpoint))
+            .map(|e| e.amount as u128)
+            .sum();
+        self.inner.total_supply() - hidden + self.added.total_supply()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subnets::SUBNETWORK_ID_NATIVE;
+    use crate::tx::{ScriptPublicKey, TransactionInput, TransactionOutput};
+    use crate::Hash;
+
+    fn spend(outpoints: &[TransactionOutpoint], values: &[u64]) -> Transaction {
+        Transaction::new(
+            1,
+            outpoints.iter().map(|outpoint| TransactionInput::new(*outpoint, vec![], 0, 1)).collect(),
+            values.iter().map(|value| TransactionOutput::new(*value, ScriptPublicKey::default())).collect(),
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_overlay_over_base_set() {
+        let funding = TransactionOutpoint::new(Hash::from_bytes([1; 32]), 0);
+        let other = TransactionOutpoint::new(Hash::from_bytes([2; 32]), 0);
+        let mut base = UtxoCollection::new();
+        base.insert(funding, UtxoEntry::new(100, ScriptPublicKey::default(), 5, false));
+        base.insert(other, UtxoEntry::new(50, ScriptPublicKey::default(), 5, false));
+
+        // A pending transaction and a child spending its change
+        let parent = spend(&[funding], &[60, 40]);
+        let child = spend(&[TransactionOutpoint::new(parent.id(), 1)], &[30]);
+
+        let mut view = UtxoView::new(&base);
+        assert_eq!(view.total_supply(), 150);
+        view.apply_transaction(&parent, 10);
+        assert!(!view.contains(&funding));
+        assert!(view.contains(&other));
+        let change = view.get(&TransactionOutpoint::new(parent.id(), 1)).unwrap();
+        assert_eq!((change.amount, change.block_daa_score, change.is_coinbase), (40, 10, false));
+        assert_eq!(view.total_supply(), 150);
+
+        view.apply_transaction(&child, 10);
+        assert!(!view.contains(&TransactionOutpoint::new(parent.id(), 1)));
+        assert!(view.contains(&TransactionOutpoint::new(parent.id(), 0)));
+        assert!(view.contains(&TransactionOutpoint::new(child.id(), 0)));
+        assert_eq!(view.total_supply(), 140);
+
+        // Outputs re-added over a spent base entry become visible again
+        let entry = view.remove(&other).unwrap();
+        assert!(view.get(&other).is_none());
+        assert!(view.remove(&other).is_none());
+        view.add(other, entry);
+        assert_eq!(view.get(&other).unwrap().amount, 50);
+        assert_eq!(view.total_supply(), 140);
+
+        // The base set is untouched
+        assert_eq!(base.len(), 2);
+        assert!(base.contains(&funding));
     }
 }
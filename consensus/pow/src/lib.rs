@@ -1,6 +1,7 @@
 // public for benchmarks
 #[doc(hidden)]
 pub mod matrix;
+pub mod target;
 #[cfg(feature = "wasm32-sdk")]
 pub mod wasm;
 #[doc(hidden)]
@@ -25,16 +26,7 @@ pub struct State {
 impl State {
     #[inline]
     pub fn new(header: &Header) -> Self {
-        // Convert compact bits to full target U256
-        let target = {
-            let size = (header.bits >> 24) as usize;
-            let word = header.bits & 0x007fffff;
-            if size <= 3 {
-                U256::from(word >> (8 * (3 - size)))
-            } else {
-                U256::from(word) << (8 * (size - 3))
-            }
-        };
+        let target = target::target_from_bits(header.bits);
 
         // Zero out the time and nonce to produce pre-pow hash.
         let pre_pow_hash = hashing::header::hash_override_nonce_time(header, 0, 0);
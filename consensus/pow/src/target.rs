@@ -0,0 +1,185 @@
+//! Conversions between compact difficulty bits, 256-bit targets and difficulty.
+//!
+//! A pow hash meets a target when, read as a big-endian 256-bit number, it is
+//! less than or equal to the target.
+
+use consensus_core::BlueWorkType;
+use primitive_types::U256;
+
+/// Compact bits of the easiest target, which has difficulty 1
+pub const MAX_TARGET_BITS: u32 = 0x207fffff;
+
+/// Expands compact bits: `[1 byte exponent][3 bytes mantissa]`, value = mantissa * 256^(exponent - 3).
+///
+/// A mantissa with the sign bit (0x00800000) set is a negative target that no
+/// hash can meet, so it expands to zero. Exponents too large for 256 bits
+/// saturate instead of losing their high bits.
+pub fn target_from_bits(bits: u32) -> U256 {
+    let exponent = bits >> 24;
+    let mantissa = bits & 0x00ffffff;
+    if mantissa > 0x007fffff {
+        return U256::zero();
+    }
+    if exponent <= 3 {
+        return U256::from(mantissa >> (8 * (3 - exponent)));
+    }
+    let shift = 8 * (exponent as usize - 3);
+    let mantissa = U256::from(mantissa);
+    if mantissa.bits() + shift > 256 {
+        U256::MAX
+    } else {
+        mantissa << shift
+    }
+}
+
+/// Compact bits of `target`, keeping the 3 most significant bytes
+pub fn target_to_bits(target: U256) -> u32 {
+    let mut size = target.bits().div_ceil(8);
+    let mut mantissa = if size <= 3 {
+        target.low_u32() << (8 * (3 - size))
+    } else {
+        (target >> (8 * (size - 3))).low_u32()
+    };
+    // Keep the sign bit clear by moving the mantissa down a byte
+    if mantissa & 0x00800000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    ((size as u32) << 24) | mantissa
+}
+
+/// Parses a target given as exactly 64 big-endian hex characters
+pub fn target_from_hex(hex: &str) -> Result<U256, String> {
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Target must be 64 hex characters, got {:?}", hex));
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(U256::from_big_endian(&bytes))
+}
+
+/// `target` as 64 big-endian hex characters
+pub fn target_to_hex(target: U256) -> String {
+    let mut bytes = [0u8; 32];
+    target.to_big_endian(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Difficulty of `target`: how many times harder it is to meet than the
+/// [`MAX_TARGET_BITS`] target
+pub fn target_to_difficulty(target: U256) -> f64 {
+    if target.is_zero() {
+        return f64::INFINITY;
+    }
+    u256_to_f64(target_from_bits(MAX_TARGET_BITS)) / u256_to_f64(target)
+}
+
+/// Target with difficulty `difficulty`. Difficulties of at most 1 give the
+/// [`MAX_TARGET_BITS`] target.
+pub fn difficulty_to_target(difficulty: f64) -> U256 {
+    let max = target_from_bits(MAX_TARGET_BITS);
+    if difficulty.is_nan() || difficulty <= 1.0 {
+        return max;
+    }
+    f64_to_u256(u256_to_f64(max) / difficulty)
+}
+
+/// Expected number of hashes to meet the target of `bits`, 2^256 / (target + 1),
+/// as blue work. Zero for bits no hash can meet; saturates past 192 bits.
+pub fn work_from_bits(bits: u32) -> BlueWorkType {
+    let target = target_from_bits(bits);
+    if target.is_zero() {
+        return BlueWorkType::from(0u64);
+    }
+    // 2^256 / (target + 1) without overflowing: (2^256 - 1 - target) / (target + 1) + 1
+    let work = (!target / (target + U256::one())) + U256::one();
+    if work.bits() > 192 {
+        return BlueWorkType::from_le_bytes([0xff; 24]);
+    }
+    let mut bytes = [0u8; 32];
+    work.to_little_endian(&mut bytes);
+    BlueWorkType::from_le_bytes(bytes[..24].try_into().unwrap())
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, &limb| acc * 18446744073709551616.0 + limb as f64)
+}
+
+/// Integer part of a finite, non-negative `value` below 2^256
+fn f64_to_u256(value: f64) -> U256 {
+    if value < 1.0 {
+        return U256::zero();
+    }
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1075;
+    let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+    if exponent >= 0 {
+        U256::from(mantissa) << exponent as usize
+    } else {
+        U256::from(mantissa >> -exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_work_grows_with_difficulty() {
+        // The easiest target is met by about every other hash
+        assert_eq!(work_from_bits(MAX_TARGET_BITS), BlueWorkType::from(2u64));
+        assert_eq!(work_from_bits(0x1f00ffff), BlueWorkType::from(0x10001u64));
+        assert_eq!(work_from_bits(0x1d00ffff), BlueWorkType::from(0x100010001u64));
+        assert_eq!(work_from_bits(0), BlueWorkType::from(0u64));
+    }
+
+    #[test]
+    fn test_bits_round_trip() {
+        for bits in [MAX_TARGET_BITS, 0x1f00ffff, 0x1e7fffff, 0x1d00ffff, 0x03123456, 0x02008000] {
+            assert_eq!(target_to_bits(target_from_bits(bits)), bits, "bits {:08x}", bits);
+        }
+        assert_eq!(target_from_bits(MAX_TARGET_BITS), U256::from(0x7fffff) << 232);
+        assert_eq!(target_from_bits(0x02008000), U256::from(0x80));
+    }
+
+    #[test]
+    fn test_bits_edge_cases() {
+        // Sign bit set: negative, nothing meets it
+        assert!(target_from_bits(0x1f800000).is_zero());
+        // Oversized exponents saturate
+        assert_eq!(target_from_bits(0x22010000), U256::MAX);
+        assert_eq!(target_to_bits(U256::zero()), 0);
+        // A leading byte with the high bit set moves into a longer encoding
+        assert_eq!(target_to_bits(U256::from(0x80)), 0x02008000);
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let target = target_from_bits(0x1f00ffff);
+        let hex = target_to_hex(target);
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex, format!("0000{}{}", "ffff", "0".repeat(56)));
+        assert_eq!(target_from_hex(&hex).unwrap(), target);
+        assert_eq!(target_from_hex(&hex.to_uppercase()).unwrap(), target);
+    }
+
+    #[test]
+    fn test_hex_rejects_compact_and_malformed() {
+        assert!(target_from_hex("1f00ffff").is_err());
+        assert!(target_from_hex(&"g".repeat(64)).is_err());
+        assert!(target_from_hex(&format!("+{}", "0".repeat(63))).is_err());
+    }
+
+    #[test]
+    fn test_difficulty_conversion() {
+        let max = target_from_bits(MAX_TARGET_BITS);
+        assert_eq!(target_to_difficulty(max), 1.0);
+        assert_eq!(target_to_difficulty(max >> 4), 16.0);
+        assert_eq!(difficulty_to_target(16.0), max >> 4);
+        assert_eq!(difficulty_to_target(0.5), max);
+        assert_eq!(difficulty_to_target(f64::NAN), max);
+        assert!(target_to_difficulty(U256::zero()).is_infinite());
+    }
+}
@@ -13,4 +13,4 @@ mod integration_test;
 
 pub use relations::BlockRelations;
 pub use reachability::{ReachabilityStore, Interval};
-pub use topology::DagTopology;
+pub use topology::{DagTopology, TraversalError};
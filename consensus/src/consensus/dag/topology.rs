@@ -1,8 +1,62 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
 use consensus_core::Hash;
 use super::relations::BlockRelations;
 use super::reachability::ReachabilityStore;
 
+/// Why a bounded traversal of the DAG stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraversalError {
+    /// A block whose parents or order are not known
+    UnknownBlock(Hash),
+    /// Completing the traversal would collect more than `limit` blocks
+    LimitExceeded { limit: usize },
+}
+
+impl fmt::Display for TraversalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraversalError::UnknownBlock(hash) => write!(f, "Block {} is not in the DAG", hash),
+            TraversalError::LimitExceeded { limit } => write!(f, "Traversal exceeds {} blocks", limit),
+        }
+    }
+}
+
+/// Blocks queued by [`DagTopology::mergeset`], highest order first. The walk
+/// ends once every queued block is known to be in the selected parent's past.
+#[derive(Default)]
+struct MergesetWalk {
+    queue: BinaryHeap<(u64, Hash)>,
+    in_selected_past: HashMap<Hash, bool>,
+    /// Queued blocks not known to be in the selected parent's past
+    outside: usize,
+}
+
+impl MergesetWalk {
+    fn enqueue<F>(&mut self, hash: Hash, selected: bool, order: &F) -> Result<(), TraversalError>
+    where
+        F: Fn(&Hash) -> Option<u64>,
+    {
+        match self.in_selected_past.get_mut(&hash) {
+            Some(flag) => {
+                if selected && !*flag {
+                    *flag = true;
+                    self.outside -= 1;
+                }
+            }
+            None => {
+                self.queue.push((order(&hash).ok_or(TraversalError::UnknownBlock(hash))?, hash));
+                self.in_selected_past.insert(hash, selected);
+                if !selected {
+                    self.outside += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct DagTopology {
     relations: Arc<BlockRelations>,
     reachability: Arc<ReachabilityStore>,
@@ -13,6 +67,15 @@ impl DagTopology {
         Self { relations, reachability }
     }
 
+    /// Record `hash` and its parents in the relations and reachability stores,
+    /// keeping any reachability interval it already has, such as genesis'
+    pub fn add_block(&self, hash: Hash, parents: Vec<Hash>, height: u64) {
+        if self.reachability.get_interval(hash).is_none() {
+            self.reachability.add_block(hash, parents.clone());
+        }
+        self.relations.add_block(hash, parents, height);
+    }
+
     pub fn get_tips(&self) -> Vec<Hash> {
         self.relations.get_tips()
     }
@@ -21,33 +84,85 @@ impl DagTopology {
         self.get_tips().contains(hash)
     }
 
-    pub fn get_anticone(&self, hash: &Hash, max_traversal: usize) -> Vec<Hash> {
-        let all_hashes = self.get_all_hashes();
-        // Special-case: if the queried block has no parents (e.g. genesis), treat
-        // the anticone as all other known blocks. This matches existing tests
-        // which expect genesis' anticone to include other blocks.
-        if let Some(parents) = self.relations.get_parents(hash) {
-            if parents.is_empty() {
-                return all_hashes.into_iter().filter(|h| h != hash).take(max_traversal).collect();
+    /// Whether `ancestor` is in the past of `descendant`. `order` numbers blocks
+    /// so that no block is below its parents, such as by blue score; only blocks
+    /// of the past ordered at or above `ancestor` are visited.
+    pub fn is_ancestor_of<F>(&self, ancestor: &Hash, descendant: &Hash, order: &F) -> Result<bool, TraversalError>
+    where
+        F: Fn(&Hash) -> Option<u64>,
+    {
+        let floor = order(ancestor).ok_or(TraversalError::UnknownBlock(*ancestor))?;
+        let mut visited = HashSet::new();
+        let mut stack = vec![*descendant];
+        while let Some(current) = stack.pop() {
+            for parent in self.parents_of(&current)? {
+                if parent == *ancestor {
+                    return Ok(true);
+                }
+                if visited.insert(parent) && order(&parent).ok_or(TraversalError::UnknownBlock(parent))? >= floor {
+                    stack.push(parent);
+                }
             }
         }
+        Ok(false)
+    }
 
-        let mut anticone = vec![];
-        for other in all_hashes {
-            if &other != hash && !self.reachability.is_ancestor_of(*hash, other) && !self.reachability.is_ancestor_of(other, *hash) {
-                anticone.push(other);
-                if anticone.len() >= max_traversal {
-                    break;
+    /// Mergeset of a block with `parents` besides `selected_parent`: the blocks
+    /// in its past that are neither the selected parent nor in its past. They are
+    /// returned by `order`, which must grow from parents to children, then hash,
+    /// so every block comes after its parents in the mergeset. Fails instead of
+    /// collecting more than `limit` blocks.
+    pub fn mergeset<F>(&self, parents: &[Hash], selected_parent: &Hash, order: &F, limit: usize) -> Result<Vec<Hash>, TraversalError>
+    where
+        F: Fn(&Hash) -> Option<u64>,
+    {
+        // Blocks are visited from the highest order down, so each one is visited
+        // after all of its children on the way here and it is settled by then
+        // whether it is in the past of the selected parent
+        let mut walk = MergesetWalk::default();
+        walk.enqueue(*selected_parent, true, order)?;
+        for parent in parents {
+            walk.enqueue(*parent, false, order)?;
+        }
+
+        let mut mergeset = Vec::new();
+        while walk.outside > 0 {
+            let (block_order, current) = walk.queue.pop().expect("blocks outside the selected past are queued");
+            let selected = walk.in_selected_past[&current];
+            if !selected {
+                // Settled; marking it keeps it from being counted again
+                walk.in_selected_past.insert(current, true);
+                walk.outside -= 1;
+                mergeset.push((block_order, current));
+                if mergeset.len() > limit {
+                    return Err(TraversalError::LimitExceeded { limit });
                 }
             }
+            for parent in self.parents_of(&current)? {
+                walk.enqueue(parent, selected, order)?;
+            }
         }
+        mergeset.sort();
+        Ok(mergeset.into_iter().map(|(_, hash)| hash).collect())
+    }
 
-        anticone
+    /// Blocks of `within` in the anticone of `hash`: neither `hash` itself nor in
+    /// its past or future. Ancestry is decided as by [`Self::is_ancestor_of`].
+    pub fn get_anticone<F>(&self, hash: &Hash, within: &[Hash], order: &F) -> Result<Vec<Hash>, TraversalError>
+    where
+        F: Fn(&Hash) -> Option<u64>,
+    {
+        let mut anticone = Vec::new();
+        for other in within {
+            if other != hash && !self.is_ancestor_of(other, hash, order)? && !self.is_ancestor_of(hash, other, order)? {
+                anticone.push(*other);
+            }
+        }
+        Ok(anticone)
     }
 
-    fn get_all_hashes(&self) -> Vec<Hash> {
-        // Use the public accessor on BlockRelations instead of reading internal fields.
-        self.relations.get_all_hashes()
+    fn parents_of(&self, hash: &Hash) -> Result<Vec<Hash>, TraversalError> {
+        self.relations.get_parents(hash).ok_or(TraversalError::UnknownBlock(*hash))
     }
 
     pub fn topological_sort(&self, from: &Hash) -> Vec<Hash> {
@@ -116,31 +231,61 @@ mod tests {
         assert_eq!(tips, vec![block2]);
     }
 
+    /// genesis <- block1 <- block3, genesis <- block2 <- block3, and block4 on
+    /// block1 alone, with blue scores as orders
+    fn fork() -> (DagTopology, HashMap<Hash, u64>, [Hash; 5]) {
+        let topology = DagTopology::new(Arc::new(BlockRelations::new()), Arc::new(ReachabilityStore::new()));
+        let blocks = [0, 1, 2, 3, 4].map(|n| Hash::from_le_u64([n, 0, 0, 0]));
+        let [genesis, block1, block2, block3, block4] = blocks;
+        topology.add_block(genesis, vec![], 0);
+        topology.add_block(block1, vec![genesis], 1);
+        topology.add_block(block2, vec![genesis], 1);
+        topology.add_block(block3, vec![block1, block2], 2);
+        topology.add_block(block4, vec![block1], 2);
+        let scores = HashMap::from([(genesis, 0), (block1, 1), (block2, 1), (block3, 3), (block4, 2)]);
+        (topology, scores, blocks)
+    }
+
     #[test]
     fn test_get_anticone_fork_scenario() {
-        let relations = Arc::new(BlockRelations::new());
-        let reachability = Arc::new(ReachabilityStore::new());
-        let topology = DagTopology::new(relations.clone(), reachability.clone());
+        let (topology, scores, blocks) = fork();
+        let [genesis, block1, block2, block3, block4] = blocks;
+        let order = |hash: &Hash| scores.get(hash).copied();
 
-        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
-        let block1 = Hash::from_le_u64([1, 0, 0, 0]);
-        let block2 = Hash::from_le_u64([2, 0, 0, 0]);
-        let block3 = Hash::from_le_u64([3, 0, 0, 0]);
+        // Everything descends from genesis
+        assert_eq!(topology.get_anticone(&genesis, &blocks, &order).unwrap(), vec![]);
+        assert_eq!(topology.get_anticone(&block1, &blocks, &order).unwrap(), vec![block2]);
+        assert_eq!(topology.get_anticone(&block2, &blocks, &order).unwrap(), vec![block1, block4]);
+        assert_eq!(topology.get_anticone(&block4, &blocks, &order).unwrap(), vec![block2, block3]);
+        // Only blocks of `within` are considered
+        assert_eq!(topology.get_anticone(&block4, &[block3], &order).unwrap(), vec![block3]);
 
-        relations.add_block(genesis, vec![], 0);
-        reachability.init_genesis(genesis);
-        relations.add_block(block1, vec![genesis], 1);
-        reachability.add_block(block1, vec![genesis]);
-        relations.add_block(block2, vec![genesis], 1);
-        reachability.add_block(block2, vec![genesis]);
-        relations.add_block(block3, vec![block1, block2], 2);
-        reachability.add_block(block3, vec![block1, block2]);
-
-        // anticone of genesis: block1, block2, block3
-        let anticone = topology.get_anticone(&genesis, 10);
-        assert!(anticone.contains(&block1));
-        assert!(anticone.contains(&block2));
-        assert!(anticone.contains(&block3));
+        assert!(topology.is_ancestor_of(&genesis, &block3, &order).unwrap());
+        assert!(!topology.is_ancestor_of(&block3, &genesis, &order).unwrap());
+        assert!(!topology.is_ancestor_of(&block3, &block3, &order).unwrap());
+        let unknown = Hash::from_le_u64([9, 0, 0, 0]);
+        assert_eq!(topology.is_ancestor_of(&genesis, &unknown, &order), Err(TraversalError::UnknownBlock(unknown)));
+    }
+
+    #[test]
+    fn test_mergeset_is_past_outside_the_selected_past() {
+        let (topology, scores, blocks) = fork();
+        let [genesis, block1, block2, block3, block4] = blocks;
+        let order = |hash: &Hash| scores.get(hash).copied();
+
+        assert_eq!(topology.mergeset(&[block3, block4], &block3, &order, 10).unwrap(), vec![block4]);
+        // block2 is only in the past of block3
+        assert_eq!(topology.mergeset(&[block3, block4], &block4, &order, 10).unwrap(), vec![block2, block3]);
+        // A parent in the past of the selected parent merges nothing
+        assert_eq!(topology.mergeset(&[block3, block1], &block3, &order, 10).unwrap(), vec![]);
+        assert_eq!(topology.mergeset(&[block1], &block1, &order, 10).unwrap(), vec![]);
+        assert_eq!(topology.mergeset(&[block1, block2], &block1, &order, 10).unwrap(), vec![block2]);
+        assert_eq!(topology.mergeset(&[genesis], &genesis, &order, 0).unwrap(), vec![]);
+
+        assert_eq!(
+            topology.mergeset(&[block3, block4], &block4, &order, 1),
+            Err(TraversalError::LimitExceeded { limit: 1 })
+        );
     }
 
     #[test]
@@ -2,9 +2,17 @@
 //!
 //! This module manages difficulty adjustment calculations based on
 //! block timestamps and target block time.
+//!
+//! The bits a block must declare follow the difficulty window of its selected
+//! parent: the parent and its selected ancestors, `window_size` blocks at most.
+//! A window crossing genesis, holding fewer blocks than that, is padded with
+//! the initial difficulty: such blocks declare `initial_bits`.
 
 use consensus_core::header::Header;
 use consensus_core::constants::{MIN_DIFFICULTY_BITS, TARGET_BLOCK_TIME, DIFFICULTY_WINDOW};
+use consensus_core::Hash;
+use consensus_pow::target::{target_from_bits, target_to_bits};
+use super::store::DifficultyWindowStore;
 use super::window::DifficultyWindow;
 use std::sync::Arc;
 
@@ -13,6 +21,9 @@ pub struct DifficultyManager {
     target_time_per_block: u64,
     window_size: usize,
     window: Arc<std::sync::Mutex<DifficultyWindow>>,
+    /// Bits of blocks whose selected parent's window is not full
+    initial_bits: u32,
+    windows: DifficultyWindowStore,
 }
 
 impl DifficultyManager {
@@ -24,6 +35,8 @@ impl DifficultyManager {
             window: Arc::new(std::sync::Mutex::new(DifficultyWindow::new(
                 DIFFICULTY_WINDOW as usize,
             ))),
+            initial_bits: MIN_DIFFICULTY_BITS,
+            windows: DifficultyWindowStore::default(),
         }
     }
 
@@ -33,7 +46,80 @@ impl DifficultyManager {
             target_time_per_block: target_time_per_block * 1000, // Convert to milliseconds
             window_size,
             window: Arc::new(std::sync::Mutex::new(DifficultyWindow::new(window_size))),
+            initial_bits: MIN_DIFFICULTY_BITS,
+            windows: DifficultyWindowStore::default(),
+        }
+    }
+
+    /// Bits declared while the selected chain below a block is shorter than a window
+    pub fn with_initial_bits(self, initial_bits: u32) -> Self {
+        Self { initial_bits, ..self }
+    }
+
+    pub fn initial_bits(&self) -> u32 {
+        self.initial_bits
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// Window ending at `block`, extending its selected parent's window when
+    /// that one is kept and otherwise walking back through the selected
+    /// parents. `header` and `selected_parent` look blocks up; the walk stops
+    /// at a block that is unknown or is its own selected parent, as genesis is.
+    pub fn window_ending_at(
+        &self,
+        block: Hash,
+        header: impl Fn(&Hash) -> Option<Header>,
+        selected_parent: impl Fn(&Hash) -> Option<Hash>,
+    ) -> Arc<DifficultyWindow> {
+        if let Some(window) = self.windows.get(&block) {
+            return window;
+        }
+        let Some(block_header) = header(&block) else {
+            return Arc::new(DifficultyWindow::new(self.window_size));
+        };
+        let parent = selected_parent(&block).filter(|parent| *parent != block);
+
+        let window = match parent.and_then(|parent| self.windows.get(&parent)) {
+            Some(parent_window) => {
+                let mut window = (*parent_window).clone();
+                window.add_block(&block_header);
+                window
+            }
+            None => {
+                let mut chain = vec![block_header];
+                let mut current = parent;
+                while let Some(hash) = current.filter(|_| chain.len() < self.window_size) {
+                    let Some(ancestor) = header(&hash) else { break };
+                    chain.push(ancestor);
+                    current = selected_parent(&hash).filter(|parent| *parent != hash);
+                }
+                let mut window = DifficultyWindow::new(self.window_size);
+                for ancestor in chain.iter().rev() {
+                    window.add_block(ancestor);
+                }
+                window
+            }
+        };
+        let window = Arc::new(window);
+        self.windows.insert(block, window.clone());
+        window
+    }
+
+    /// Bits a block must declare when `window` is its selected parent's
+    pub fn expected_bits(&self, window: &DifficultyWindow) -> Result<u32, String> {
+        if !window.is_full() || window.len() < 2 {
+            return Ok(self.initial_bits);
         }
+        let time_span = window.time_span().ok_or("Cannot calculate time span".to_string())?;
+        let current_bits = window.last_bits().ok_or("Empty difficulty window".to_string())?;
+        let new_target = self.adjust_target(target_from_bits(current_bits), time_span, window.len())?;
+
+        // Never easier than the minimum difficulty or the initial one
+        let easiest = target_from_bits(MIN_DIFFICULTY_BITS).max(target_from_bits(self.initial_bits));
+        Ok(target_to_bits(new_target.min(easiest)))
     }
 
     /// Calculate next difficulty based on current window
@@ -53,30 +139,7 @@ impl DifficultyManager {
             .time_span()
             .ok_or("Cannot calculate time span".to_string())?;
 
-        // Get target time span
-        let target_time_span = self.target_time_per_block * (window.len() as u64 - 1);
-
-        // Get current difficulty
-        let current_bits = current_header.bits;
-        let current_target = self.bits_to_target(current_bits);
-
-        // Calculate new target
-        let time_span_u256: primitive_types::U256 = time_span.into();
-        let target_time_span_u256: primitive_types::U256 = target_time_span.into();
-        
-        let new_target = if time_span < target_time_span {
-            // Blocks are coming too fast, increase difficulty
-            current_target
-                .checked_mul(time_span_u256)
-                .and_then(|x| x.checked_div(target_time_span_u256))
-                .ok_or("Difficulty calculation overflow")?
-        } else {
-            // Blocks are coming too slow, decrease difficulty
-            current_target
-                .checked_mul(time_span_u256)
-                .and_then(|x| x.checked_div(target_time_span_u256))
-                .ok_or("Difficulty calculation overflow")?
-        };
+        let new_target = self.adjust_target(self.bits_to_target(current_header.bits), time_span, window.len())?;
 
         // Clamp to minimum difficulty
         let min_target = self.bits_to_target(MIN_DIFFICULTY_BITS);
@@ -89,6 +152,21 @@ impl DifficultyManager {
         Ok(self.target_to_bits(clamped_target))
     }
 
+    /// Scale `current_target` by how far the `blocks` blocks spanning
+    /// `time_span` are from the target time per block
+    fn adjust_target(&self, current_target: primitive_types::U256, time_span: u64, blocks: usize) -> Result<primitive_types::U256, String> {
+        // Get target time span
+        let target_time_span = self.target_time_per_block * (blocks as u64 - 1);
+
+        // Faster blocks shrink the target, raising the difficulty; slower ones
+        // grow it, saturating rather than overflowing
+        if target_time_span == 0 {
+            return Err("Difficulty window spans no target time".to_string());
+        }
+        let scaled = current_target.full_mul(time_span.into()) / primitive_types::U512::from(target_time_span);
+        Ok(primitive_types::U256::try_from(scaled).unwrap_or(primitive_types::U256::MAX))
+    }
+
     /// Convert compact bits representation to target (U256)
     fn bits_to_target(&self, bits: u32) -> primitive_types::U256 {
         let size = (bits >> 24) as usize;
@@ -202,5 +280,57 @@ mod tests {
         // With only one block, should return current bits
         assert_eq!(result.unwrap(), 0x1f00ffff);
     }
-}
 
+    #[test]
+    fn test_window_crossing_genesis_keeps_initial_bits() {
+        // A chain genesis <- 1 <- 2 <- 3, a block per second
+        let hash = |n: u64| Hash::from_le_u64([n, 0, 0, 0]);
+        let headers: Vec<Header> = (0..4)
+            .map(|n| Header::from_precomputed_hash(hash(n), Vec::new()))
+            .map(|mut header| {
+                header.timestamp = 1000 * header.hash.to_le_u64()[0];
+                header.bits = 0x1e00ffff;
+                header
+            })
+            .collect();
+        let header = |block: &Hash| headers.iter().find(|header| header.hash == *block).cloned();
+        let selected_parent = |block: &Hash| Some(hash(block.to_le_u64()[0].saturating_sub(1)));
+        let manager = DifficultyManager::with_params(1, 3).with_initial_bits(0x1f00ffff);
+
+        // Windows of up to two blocks stop at genesis, its own selected parent
+        for (tip, len) in [(0, 1), (1, 2)] {
+            let window = manager.window_ending_at(hash(tip), header, selected_parent);
+            assert_eq!(window.len(), len);
+            assert_eq!(manager.expected_bits(&window).unwrap(), 0x1f00ffff);
+        }
+
+        // A full window at the target pace keeps its newest block's bits; the
+        // next one extends the kept window rather than walking the chain again
+        let window = manager.window_ending_at(hash(2), header, selected_parent);
+        assert_eq!(window.timestamps(), vec![0, 1000, 2000]);
+        assert_eq!(manager.expected_bits(&window).unwrap(), 0x1e00ffff);
+        let window = manager.window_ending_at(hash(3), |block| (*block == hash(3)).then(|| headers[3].clone()), selected_parent);
+        assert_eq!(window.timestamps(), vec![1000, 2000, 3000]);
+
+        // Unknown blocks have empty windows
+        assert_eq!(manager.window_ending_at(hash(9), header, selected_parent).len(), 0);
+    }
+
+    #[test]
+    fn test_expected_bits_follow_the_window_pace() {
+        let manager = DifficultyManager::with_params(1, 3).with_initial_bits(0x1f00ffff);
+        let window = |spacing: u64| {
+            let mut window = DifficultyWindow::new(3);
+            for n in 0..3 {
+                window.add_block(&create_test_header(Hash::from_le_u64([n, 0, 0, 0]), n * spacing, 0x1e00ffff));
+            }
+            window
+        };
+        // Twice as fast as the target halves the target, twice as slow doubles it
+        let target = target_from_bits(0x1e00ffff);
+        assert_eq!(manager.expected_bits(&window(500)).unwrap(), target_to_bits(target / 2));
+        assert_eq!(manager.expected_bits(&window(2000)).unwrap(), target_to_bits(target * 2));
+        // Never easier than the initial difficulty
+        assert_eq!(manager.expected_bits(&window(1_000_000)).unwrap(), 0x1f00ffff);
+    }
+}
@@ -4,8 +4,10 @@
 //! block timestamps and target block time.
 
 pub mod manager;
+pub mod store;
 pub mod window;
 
 pub use manager::DifficultyManager;
+pub use store::DifficultyWindowStore;
 pub use window::DifficultyWindow;
 
@@ -0,0 +1,74 @@
+//! Difficulty windows of recently processed blocks
+//!
+//! The window ending at a block holds that block and its selected ancestors,
+//! oldest first. A block's window is its selected parent's extended by the
+//! block, so windows are kept for the blocks new ones are likely to build on.
+
+use consensus_core::Hash;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use super::window::DifficultyWindow;
+
+/// Windows kept unless configured otherwise
+pub const DEFAULT_WINDOW_CACHE_SIZE: usize = 256;
+
+/// Bounded cache of windows by the block they end at, oldest evicted first
+pub struct DifficultyWindowStore {
+    capacity: usize,
+    windows: RwLock<(HashMap<Hash, Arc<DifficultyWindow>>, VecDeque<Hash>)>,
+}
+
+impl DifficultyWindowStore {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), windows: RwLock::new((HashMap::new(), VecDeque::new())) }
+    }
+
+    /// Window ending at `block`, if still kept
+    pub fn get(&self, block: &Hash) -> Option<Arc<DifficultyWindow>> {
+        self.windows.read().unwrap().0.get(block).cloned()
+    }
+
+    pub fn insert(&self, block: Hash, window: Arc<DifficultyWindow>) {
+        let mut guard = self.windows.write().unwrap();
+        let (windows, order) = &mut *guard;
+        if windows.insert(block, window).is_none() {
+            order.push_back(block);
+        }
+        while order.len() > self.capacity {
+            if let Some(evicted) = order.pop_front() {
+                windows.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.read().unwrap().0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for DifficultyWindowStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_CACHE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_windows_are_evicted() {
+        let store = DifficultyWindowStore::new(2);
+        let hash = |n: u64| Hash::from_le_u64([n, 0, 0, 0]);
+        for n in 1..=3 {
+            store.insert(hash(n), Arc::new(DifficultyWindow::new(4)));
+        }
+        assert_eq!(store.len(), 2);
+        assert!(store.get(&hash(1)).is_none());
+        assert!(store.get(&hash(2)).is_some() && store.get(&hash(3)).is_some());
+    }
+}
@@ -56,6 +56,11 @@ impl DifficultyWindow {
         self.blocks.iter().map(|(_, _, bits)| *bits).collect()
     }
 
+    /// Get the bits of the newest block in the window
+    pub fn last_bits(&self) -> Option<u32> {
+        self.blocks.back().map(|(_, _, bits)| *bits)
+    }
+
     /// Get the first timestamp in the window
     pub fn first_timestamp(&self) -> Option<u64> {
         self.blocks.front().map(|(_, timestamp, _)| *timestamp)
@@ -0,0 +1,157 @@
+//! Block acceptance along the selected chain
+//!
+//! A chain block accepts its own transactions. A block off the selected chain is
+//! accepted by the first chain block that merges it as blue; if that chain block
+//! merges it as red instead, its transactions are never accepted. A block that no
+//! chain block has merged yet is still pending.
+
+use consensus_core::Hash;
+use super::stores::GhostdagStore;
+
+/// Acceptance state of a block relative to a selected chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockAcceptance {
+    /// Accepted by `accepting_block`, which has `accepting_blue_score`
+    Accepted { accepting_block: Hash, accepting_blue_score: u64 },
+    /// Merged as red by `merging_block`; its transactions are not accepted
+    Red { merging_block: Hash },
+    /// Not merged by any chain block yet
+    Pending,
+}
+
+impl BlockAcceptance {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, BlockAcceptance::Accepted { .. })
+    }
+}
+
+/// Number of confirmations of something accepted at `accepting_blue_score`, the
+/// accepting block itself counting as the first one
+pub fn confirmations(virtual_blue_score: u64, accepting_blue_score: u64) -> u64 {
+    virtual_blue_score.saturating_sub(accepting_blue_score) + 1
+}
+
+/// Selected chain ending at `tip`, genesis first
+pub fn selected_chain(store: &GhostdagStore, tip: Hash) -> Vec<Hash> {
+    let mut chain = vec![tip];
+    let mut current = tip;
+    while let Some(data) = store.get(&current) {
+        if data.selected_parent == current {
+            break;
+        }
+        current = data.selected_parent;
+        chain.push(current);
+    }
+    chain.reverse();
+    chain
+}
+
+/// Acceptance of `block` relative to `chain` (genesis first, as returned by `selected_chain`)
+pub fn block_acceptance(store: &GhostdagStore, chain: &[Hash], block: &Hash) -> BlockAcceptance {
+    let block_score = match store.get(block) {
+        Some(data) => data.blue_score,
+        None => return BlockAcceptance::Pending,
+    };
+    for chain_hash in chain {
+        let chain_data = match store.get(chain_hash) {
+            Some(data) => data,
+            None => continue,
+        };
+        if chain_hash == block {
+            return BlockAcceptance::Accepted { accepting_block: *chain_hash, accepting_blue_score: chain_data.blue_score };
+        }
+        // Only chain blocks above the block can have it in their past
+        if chain_data.blue_score <= block_score {
+            continue;
+        }
+        if chain_data.blue_set.contains(block) {
+            return BlockAcceptance::Accepted { accepting_block: *chain_hash, accepting_blue_score: chain_data.blue_score };
+        }
+        if chain_data.red_set.contains(block) {
+            return BlockAcceptance::Red { merging_block: *chain_hash };
+        }
+    }
+    BlockAcceptance::Pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::ghostdag::GhostdagData;
+
+    fn h(n: u64) -> Hash {
+        Hash::from_le_u64([n, 0, 0, 0])
+    }
+
+    fn insert(store: &GhostdagStore, hash: Hash, selected_parent: Hash, blue: &[Hash], red: &[Hash]) {
+        let mut data = GhostdagData::new(selected_parent);
+        data.blue_set = blue.iter().copied().collect();
+        data.red_set = red.iter().copied().collect();
+        data.blue_score = data.blue_set.len() as u64;
+        store.insert(hash, data);
+    }
+
+    /// genesis(1) <- a(2) <- c(4) <- d(5), with b(3) parallel to a and merged
+    /// blue by c, and r(6) parallel to c and merged red by d
+    fn build_dag() -> GhostdagStore {
+        let store = GhostdagStore::new();
+        insert(&store, h(1), h(1), &[h(1)], &[]);
+        insert(&store, h(2), h(1), &[h(1)], &[]);
+        insert(&store, h(3), h(1), &[h(1)], &[]);
+        insert(&store, h(4), h(2), &[h(1), h(2), h(3)], &[]);
+        insert(&store, h(6), h(2), &[h(1), h(2)], &[]);
+        insert(&store, h(5), h(4), &[h(1), h(2), h(3), h(4)], &[h(6)]);
+        store
+    }
+
+    #[test]
+    fn test_selected_chain() {
+        let store = build_dag();
+        assert_eq!(selected_chain(&store, h(5)), vec![h(1), h(2), h(4), h(5)]);
+        assert_eq!(selected_chain(&store, h(1)), vec![h(1)]);
+    }
+
+    #[test]
+    fn test_chain_block_accepts_itself() {
+        let store = build_dag();
+        let chain = selected_chain(&store, h(5));
+        assert_eq!(
+            block_acceptance(&store, &chain, &h(4)),
+            BlockAcceptance::Accepted { accepting_block: h(4), accepting_blue_score: 3 }
+        );
+    }
+
+    #[test]
+    fn test_blue_merged_block_is_accepted_by_merging_chain_block() {
+        let store = build_dag();
+        let chain = selected_chain(&store, h(5));
+        assert_eq!(
+            block_acceptance(&store, &chain, &h(3)),
+            BlockAcceptance::Accepted { accepting_block: h(4), accepting_blue_score: 3 }
+        );
+    }
+
+    #[test]
+    fn test_red_block_is_not_accepted() {
+        let store = build_dag();
+        let chain = selected_chain(&store, h(5));
+        let acceptance = block_acceptance(&store, &chain, &h(6));
+        assert_eq!(acceptance, BlockAcceptance::Red { merging_block: h(5) });
+        assert!(!acceptance.is_accepted());
+    }
+
+    #[test]
+    fn test_unmerged_block_is_pending() {
+        let store = build_dag();
+        let chain = selected_chain(&store, h(4));
+        assert_eq!(block_acceptance(&store, &chain, &h(6)), BlockAcceptance::Pending);
+        assert_eq!(block_acceptance(&store, &chain, &h(99)), BlockAcceptance::Pending);
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(confirmations(10, 10), 1);
+        assert_eq!(confirmations(10, 7), 4);
+        assert_eq!(confirmations(3, 7), 1);
+    }
+}
@@ -60,8 +60,9 @@ mod integration_tests {
     store.insert(merge, merge_data.clone());
 
         // Verify merge
-        assert!(merge_data.selected_parent == block2 || merge_data.selected_parent == block3);
+        assert_eq!(merge_data.selected_parent, block2);
         assert_eq!(merge_data.merge_set_size, 2);
-        assert_eq!(merge_data.height, 2);
+        assert_eq!(merge_data.height, 3);
+        assert!(merge_data.blue_set.contains(&block3));
     }
 }
@@ -2,6 +2,7 @@ use std::sync::Arc;
 use consensus_core::{Hash, BlueWorkType};
 use super::stores::{GhostdagData, GhostdagStore};
 use super::protocol::GhostdagProtocol;
+use super::acceptance::{self, BlockAcceptance};
 
 pub struct GhostdagManager {
     protocol: Arc<GhostdagProtocol>,
@@ -13,12 +14,16 @@ impl GhostdagManager {
         Self { protocol, store }
     }
 
-    pub fn init_genesis(&self, genesis_hash: Hash) {
+    /// Start the DAG at `genesis`, whose bits count as work for the blocks merging it
+    pub fn init_genesis(&self, genesis: &consensus_core::header::Header) {
+        let genesis_hash = genesis.hash;
         let mut data = GhostdagData::new(genesis_hash);
         data.blue_score = 0;
         data.blue_work = BlueWorkType::from(0u64);
+        data.work = consensus_pow::target::work_from_bits(genesis.bits);
         data.selected_parent = genesis_hash;
         data.height = 0;
+        self.protocol.register_block(genesis_hash, vec![], 0);
         self.store.insert(genesis_hash, data);
     }
 
@@ -26,12 +31,28 @@ impl GhostdagManager {
         self.store.get(hash)
     }
 
+    /// Store holding the GHOSTDAG data of every block
+    pub fn store(&self) -> Arc<GhostdagStore> {
+        self.store.clone()
+    }
+
     pub fn add_block(&self, header: &consensus_core::header::Header) -> Result<GhostdagData, String> {
-        let data = self.protocol.calculate_ghostdag(header)?;
-        self.store.insert(header.hash, data.clone());
+        let data = self.calculate_ghostdag(header)?;
+        self.insert_ghostdag_data(header, data.clone());
         Ok(data)
     }
 
+    /// GHOSTDAG data of `header` computed from its parents, without storing it
+    pub fn calculate_ghostdag(&self, header: &consensus_core::header::Header) -> Result<GhostdagData, String> {
+        self.protocol.calculate_ghostdag(header)
+    }
+
+    /// Store the GHOSTDAG data of `header` and record it in the DAG topology
+    pub fn insert_ghostdag_data(&self, header: &consensus_core::header::Header, data: GhostdagData) {
+        self.protocol.register_block(header.hash, header.direct_parents().to_vec(), data.height);
+        self.store.insert(header.hash, data);
+    }
+
     pub fn get_blue_score(&self, hash: &Hash) -> Option<u64> {
         self.store.get(hash).map(|d| d.blue_score)
     }
@@ -40,6 +61,42 @@ impl GhostdagManager {
         self.store.get(hash).map(|d| d.selected_parent)
     }
 
+    /// Number of blue and red blocks merged by `hash`. Blue sets are cumulative,
+    /// so the mergeset blues are the blues not already in the selected parent's blue set.
+    pub fn get_mergeset_sizes(&self, hash: &Hash) -> Option<(u64, u64)> {
+        let data = self.store.get(hash)?;
+        let inherited = match self.store.get(&data.selected_parent) {
+            Some(parent) if data.selected_parent != *hash => parent.blue_set.len(),
+            _ => 0,
+        };
+        Some((data.blue_set.len().saturating_sub(inherited) as u64, data.red_set.len() as u64))
+    }
+
+    /// Blocks other than the selected parent that `hash` merges as blue
+    pub fn get_mergeset_blues(&self, hash: &Hash) -> Vec<Hash> {
+        let Some(data) = self.store.get(hash) else {
+            return Vec::new();
+        };
+        let inherited = match self.store.get(&data.selected_parent) {
+            Some(parent) if data.selected_parent != *hash => parent.blue_set,
+            _ => Default::default(),
+        };
+        let mut blues: Vec<Hash> =
+            data.blue_set.into_iter().filter(|blue| *blue != data.selected_parent && !inherited.contains(blue)).collect();
+        blues.sort();
+        blues
+    }
+
+    /// Selected chain ending at `tip`, genesis first
+    pub fn get_selected_chain(&self, tip: Hash) -> Vec<Hash> {
+        acceptance::selected_chain(&self.store, tip)
+    }
+
+    /// Acceptance of `block` relative to `chain`, as returned by `get_selected_chain`
+    pub fn get_block_acceptance(&self, chain: &[Hash], block: &Hash) -> BlockAcceptance {
+        acceptance::block_acceptance(&self.store, chain, block)
+    }
+
     pub fn get_virtual_ghostdag_data(&self, tips: Vec<Hash>) -> Result<GhostdagData, String> {
         let virtual_hash = Self::calculate_virtual_hash(&tips);
         let virtual_header = consensus_core::header::Header::from_precomputed_hash(virtual_hash, tips);
@@ -6,9 +6,11 @@
 pub mod protocol;
 pub mod stores;
 pub mod manager;
+pub mod acceptance;
 #[cfg(test)]
 mod integration_test;
 
 pub use protocol::GhostdagProtocol;
 pub use stores::{GhostdagData, GhostdagStore};
 pub use manager::GhostdagManager;
+pub use acceptance::BlockAcceptance;
@@ -1,22 +1,26 @@
 use super::stores::{GhostdagData, GhostdagStore};
-use crate::consensus::dag::{DagTopology, BlockRelations};
+use crate::consensus::dag::{DagTopology, BlockRelations, TraversalError};
 use consensus_core::{Hash, BlueWorkType, header::Header};
-use consensus_pow;
+use consensus_pow::target::work_from_bits;
 use crypto_hashes::{
     builders::BlockHashBuilder,
     HashWriter,
 };
-use primitive_types::U256;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::io::Write;
 
+/// Most blocks a mergeset may hold; blocks merging more are rejected rather
+/// than colored from a truncated mergeset
+pub const DEFAULT_MERGESET_SIZE_LIMIT: usize = 180;
+
 pub struct GhostdagProtocol {
     k: u32,  // anticone size parameter
     topology: Arc<DagTopology>,
     relations: Arc<BlockRelations>, 
     store: Arc<GhostdagStore>,
     hash_builder: BlockHashBuilder,
+    mergeset_size_limit: usize,
 }
 
 impl GhostdagProtocol {
@@ -27,9 +31,20 @@ impl GhostdagProtocol {
             relations, 
             store,
             hash_builder: BlockHashBuilder::new(),
+            mergeset_size_limit: DEFAULT_MERGESET_SIZE_LIMIT,
         }
     }
 
+    pub fn with_mergeset_size_limit(self, mergeset_size_limit: usize) -> Self {
+        Self { mergeset_size_limit, ..self }
+    }
+
+    /// Record a block whose GHOSTDAG data is stored in the DAG topology, so
+    /// later blocks can walk its past
+    pub fn register_block(&self, hash: Hash, parents: Vec<Hash>, height: u64) {
+        self.topology.add_block(hash, parents, height);
+    }
+
     fn hash_block_data(&self, data: &GhostdagData) -> Hash {
         let mut writer = HashWriter::new();
 
@@ -69,8 +84,9 @@ impl GhostdagProtocol {
             // Genesis block
             let mut data = GhostdagData::new(header.hash); // Self-selected for genesis
             data.blue_score = 1;
-            // Use header.blue_work if available, otherwise unit work
-            data.blue_work = header.blue_work;
+            // No blues yet, so no blue work, whatever the header claims
+            data.blue_work = BlueWorkType::from(0u64);
+            data.work = work_from_bits(header.bits);
             data.merge_set_size = 1;
             data.height = 0;
             // genesis has empty sets
@@ -80,191 +96,149 @@ impl GhostdagProtocol {
         // Select parent with highest blue score
         let selected_parent = self.select_parent(&parents)?;
 
-        // Calculate blue set and score using k-cluster algorithm
-        let (blue_set, red_set) = self.calculate_blue_set(&header.hash, &parents, &selected_parent)?;
+        // Blocks this one merges besides its selected parent, parents first
+        let order = |hash: &Hash| self.store.get_blue_score(hash);
+        let mergeset = self.topology.mergeset(&parents, &selected_parent, &order, self.mergeset_size_limit)
+            .map_err(|e| match e {
+                TraversalError::LimitExceeded { limit } => format!("Mergeset of {} exceeds {} blocks", header.hash, limit),
+                e => e.to_string(),
+            })?;
 
-        // Calculate blue score: number of blue blocks 
-        let blue_score = blue_set.len() as u64;
+        // Color the mergeset using the k-cluster rule
+        let mut data = self.color_mergeset(selected_parent, &mergeset)?;
 
-        // Calculate blue work by summing known work
-        let blue_work = self.calculate_blue_work(&blue_set, header)?;
+        // Calculate blue score: number of blue blocks 
+        data.blue_score = data.blue_set.len() as u64;
 
-        // Calculate merge set size
-        let merge_set_size = parents.len() as u64;
+        data.blue_work = self.calculate_blue_work(&data)?;
+        data.work = work_from_bits(header.bits);
 
-        // Calculate blues anticone sizes
-        let blues_anticone_sizes = self.calculate_blues_anticone_sizes(&blue_set)?;
+        data.merge_set_size = 1 + mergeset.len() as u64;
 
         // Get height
-        let height = self.relations.get_height(&selected_parent).unwrap_or(0) + 1;
-
-        // Create and hash GhostDAG data
-        let mut data = GhostdagData::new(selected_parent);
-        data.blue_set = blue_set.clone();
-        data.red_set = red_set.clone();
-        data.blue_score = blue_score;
-        data.blue_work = blue_work;
-        data.merge_set_size = merge_set_size;
-        data.blues_anticone_sizes = blues_anticone_sizes;
-        data.height = height;
+        data.height = self.relations.get_height(&selected_parent).unwrap_or(0) + 1;
 
         Ok(data)
     }
 
-    /// Calculate total accumulated proof of work for a set of blue blocks.
-    /// For each block, work is calculated as follows:
-    /// - For blocks in store: Use stored blue_work
-    /// - For current block: Calculate actual PoW work based on target and hash
-    /// - For unknown blocks: Estimate work based on target difficulty
-    fn calculate_blue_work(&self, blue_set: &HashSet<Hash>, header: &Header) -> Result<BlueWorkType, String> {
-        use consensus_pow::State;
-
-        let mut blue_work = BlueWorkType::from(0u64);
-
-        for block in blue_set {
-            let work = if let Some(data) = self.store.get(block) {
-                data.blue_work
-            } else if block == &header.hash {
-                // Calculate actual PoW work for this block
-                // convert compact bits -> U256 target (same logic as used elsewhere)
-                let target = {
-                    let bits = header.bits;
-                    let size = (bits >> 24) as usize;
-                    let word = bits & 0x007fffff;
-                    if size <= 3 {
-                        U256::from(word >> (8 * (3 - size)))
-                    } else {
-                        U256::from(word) << (8 * (size - 3))
-                    }
-                };
-
-                if target.is_zero() {
-                    return Err("Invalid target (zero)".to_string());
-                }
-
-                let state = State::new(header);
-                let pow_hash = state.calculate_pow(header.nonce);
-
-                // Convert PoW difficulty to BlueWork
-                if pow_hash <= target {
-                    // Work is proportional to 2^256-1 / target
-                    let max_bytes = [0xffu8; 32];
-                    let max_val = U256::from_big_endian(&max_bytes);
-                    let work_amount = max_val / target;
-                    BlueWorkType::from(work_amount.low_u64())
-                } else {
-                    return Err("Invalid proof of work".to_string());
-                }
-            } else {
-                // For unknown blocks, use target-based work estimation
-                let default_target = {
-                    let bits = header.bits;
-                    let size = (bits >> 24) as usize;
-                    let word = bits & 0x007fffff;
-                    if size <= 3 {
-                        U256::from(word >> (8 * (3 - size)))
-                    } else {
-                        U256::from(word) << (8 * (size - 3))
-                    }
-                };
-
-                if default_target.is_zero() {
-                    return Err("Invalid target (zero)".to_string());
-                }
-
-                let max_bytes = [0xffu8; 32];
-                let max_val = U256::from_big_endian(&max_bytes);
-                let work_estimate = max_val / default_target;
-                BlueWorkType::from(work_estimate.low_u64())
-            };
-            blue_work += work;
+    /// Blue work of a block colored as `data`: its selected parent's blue work
+    /// plus the work of each block it merges as blue, the selected parent included
+    fn calculate_blue_work(&self, data: &GhostdagData) -> Result<BlueWorkType, String> {
+        let mut blue_work = self.store.get(&data.selected_parent)
+            .ok_or_else(|| format!("Parent {} not found in store", data.selected_parent))?
+            .blue_work;
+        for blue in &data.mergeset_blues {
+            let blue_data = self.store.get(blue).ok_or_else(|| format!("Mergeset block {} not found in store", blue))?;
+            blue_work += blue_data.work;
         }
-
         Ok(blue_work)
     }
 
     fn select_parent(&self, parents: &[Hash]) -> Result<Hash, String> {
-        let mut max_score = 0;
-        let mut selected = None;
+        let mut selected: Option<(u64, Hash)> = None;
 
         for parent in parents {
             if let Some(data) = self.store.get(parent) {
-                if data.blue_score > max_score {
-                    max_score = data.blue_score;
-                    selected = Some(*parent);
+                // Genesis has blue score 0, so the first parent seen wins ties
+                if selected.map_or(true, |(max_score, _)| data.blue_score > max_score) {
+                    selected = Some((data.blue_score, *parent));
                 }
             } else {
                 return Err(format!("Parent {} not found in store", parent));
             }
         }
 
-        selected.ok_or("No parents found".to_string())
+        selected.map(|(_, parent)| parent).ok_or("No parents found".to_string())
     }
 
-    fn calculate_blue_set(&self, hash: &Hash, parents: &[Hash], selected_parent: &Hash) -> Result<(HashSet<Hash>, HashSet<Hash>), String> {
-        // New: perform k-cluster style coloring to build blue and red sets
-        let mut blue_set: HashSet<Hash> = HashSet::new();
-        let mut red_set: HashSet<Hash> = HashSet::new();
-
-        // Start with selected parent and its known blue set (if present)
-        if let Some(selected_data) = self.store.get(selected_parent) {
-            for b in &selected_data.blue_set {
-                blue_set.insert(*b);
+    /// GHOSTDAG data of a block with `selected_parent` and `mergeset` but for its
+    /// scores and height. Mergeset blocks are colored parents first: a block is
+    /// blue if the blue set stays a k-cluster with it, meaning neither it nor any
+    /// blue in its anticone would have more than k blues in its anticone.
+    fn color_mergeset(&self, selected_parent: Hash, mergeset: &[Hash]) -> Result<GhostdagData, String> {
+        let selected_data = self.store.get(&selected_parent)
+            .ok_or_else(|| format!("Parent {} not found in store", selected_parent))?;
+        let mut data = GhostdagData::new(selected_parent);
+        data.blue_set = selected_data.blue_set;
+        data.blue_set.insert(selected_parent);
+        data.mergeset_blues.push(selected_parent);
+        data.blues_anticone_sizes.insert(selected_parent, 0);
+
+        for candidate in mergeset {
+            match self.blue_anticone(&data, candidate)? {
+                Some(anticone_blues) => {
+                    data.mergeset_blues.push(*candidate);
+                    data.blue_set.insert(*candidate);
+                    data.blues_anticone_sizes.insert(*candidate, anticone_blues.len() as u32);
+                    for (blue, size) in anticone_blues {
+                        data.blues_anticone_sizes.insert(blue, size + 1);
+                    }
+                }
+                None => {
+                    data.red_set.insert(*candidate);
+                }
             }
-            blue_set.insert(*selected_parent);
-        } else {
-            blue_set.insert(*selected_parent);
         }
+        Ok(data)
+    }
 
-        // Consider other parents as candidates as well
-        for parent in parents {
-            if parent == selected_parent {
-                continue;
-            }
-            // If we have data for the parent, try to include it deterministically
-            if let Some(_) = self.store.get(parent) {
-                // will be processed via candidates below
-            } else {
-                // unknown parent -> treat conservatively as red (skip)
-            }
+    /// Blues of `new_block` in the anticone of `candidate`, with their blue
+    /// anticone sizes, or `None` if coloring `candidate` blue would give it or
+    /// one of them more than k blues in its anticone. Blues are found by walking
+    /// the selected chain down from the new block: once a chain block is in the
+    /// past of `candidate`, so is everything it merged, and each chain block on
+    /// the way that is not counts itself, so at most k + 1 of them are visited.
+    fn blue_anticone(&self, new_block: &GhostdagData, candidate: &Hash) -> Result<Option<HashMap<Hash, u32>>, String> {
+        // All mergeset blues are in the anticone of the selected parent
+        if new_block.mergeset_blues.len() > self.k as usize {
+            return Ok(None);
         }
-
-        // Get anticone candidates for the new header (blocks neither in past nor future)
-        let mut candidates = self.topology.get_anticone(&hash, 10000);
-        // Ensure deterministic ordering
-        candidates.sort();
-
-        for candidate in candidates {
-            // skip if already known
-            if blue_set.contains(&candidate) || red_set.contains(&candidate) {
-                continue;
+        let order = |hash: &Hash| self.store.get_blue_score(hash);
+        let is_ancestor = |ancestor: &Hash| self.topology.is_ancestor_of(ancestor, candidate, &order).map_err(|e| e.to_string());
+
+        let mut anticone_blues = HashMap::new();
+        let mut mergeset_blues = new_block.mergeset_blues.clone();
+        let mut chain_block = new_block.selected_parent;
+        loop {
+            for blue in mergeset_blues {
+                if is_ancestor(&blue)? {
+                    continue;
+                }
+                let size = self.blue_anticone_size(new_block, &blue)?;
+                anticone_blues.insert(blue, size);
+                if anticone_blues.len() > self.k as usize || size == self.k {
+                    return Ok(None);
+                }
             }
-
-            // get candidate's anticone and count intersection with current blue_set
-            let candidate_anticone = self.topology.get_anticone(&candidate, 10000);
-            let anticone_size = candidate_anticone.iter().filter(|b| blue_set.contains(b)).count() as u32;
-
-            if anticone_size <= self.k {
-                blue_set.insert(candidate);
-            } else {
-                red_set.insert(candidate);
+            if is_ancestor(&chain_block)? {
+                return Ok(Some(anticone_blues));
             }
+            let selected_parent = self.store.get_selected_parent(&chain_block)
+                .ok_or_else(|| format!("Chain block {} not found in store", chain_block))?;
+            if selected_parent == chain_block {
+                return Ok(Some(anticone_blues));
+            }
+            mergeset_blues = self.store.get_mergeset_blues(&chain_block).unwrap_or_default();
+            chain_block = selected_parent;
         }
-
-        Ok((blue_set, red_set))
     }
 
-    fn calculate_blues_anticone_sizes(&self, blue_set: &HashSet<Hash>) -> Result<HashMap<Hash, u32>, String> {
-        let mut sizes = HashMap::new();
-        
-        for block in blue_set {
-            if let Some(data) = self.store.get(block) {
-                let anticone_size = data.blue_set.intersection(blue_set).count() as u32;
-                sizes.insert(*block, anticone_size);
+    /// Number of blues in the anticone of `blue` as seen by `new_block`: the
+    /// latest count recorded along its selected chain
+    fn blue_anticone_size(&self, new_block: &GhostdagData, blue: &Hash) -> Result<u32, String> {
+        if let Some(size) = new_block.blues_anticone_sizes.get(blue) {
+            return Ok(*size);
+        }
+        let mut current = new_block.selected_parent;
+        loop {
+            if let Some(size) = self.store.get_blue_anticone_size(&current, blue) {
+                return Ok(size);
+            }
+            match self.store.get_selected_parent(&current) {
+                Some(parent) if parent != current => current = parent,
+                _ => return Err(format!("Blue anticone size of {} not found on the selected chain", blue)),
             }
         }
-
-        Ok(sizes)
     }
 }
 
@@ -272,6 +246,8 @@ impl GhostdagProtocol {
 mod tests {
     use super::*;
     use crate::consensus::dag::{BlockRelations, ReachabilityStore};
+    use crate::consensus::ghostdag::manager::GhostdagManager;
+    use std::collections::HashSet;
 
     #[test]
     fn test_genesis_calculation() {
@@ -320,16 +296,19 @@ mod tests {
         let genesis = Hash::from_le_u64([0, 0, 0, 0]);
         let genesis_header = Header::from_precomputed_hash(genesis, vec![]);
         let genesis_data = protocol.calculate_ghostdag(&genesis_header).unwrap();
+        protocol.register_block(genesis, vec![], 0);
         protocol.store.insert(genesis, genesis_data);
 
         let parent1 = Hash::from_le_u64([1, 0, 0, 0]);
         let parent1_header = Header::from_precomputed_hash(parent1, vec![genesis]);
         let parent1_data = protocol.calculate_ghostdag(&parent1_header).unwrap();
+        protocol.register_block(parent1, vec![genesis], parent1_data.height);
         protocol.store.insert(parent1, parent1_data);
 
         let parent2 = Hash::from_le_u64([2, 0, 0, 0]);
         let parent2_header = Header::from_precomputed_hash(parent2, vec![genesis]);
         let parent2_data = protocol.calculate_ghostdag(&parent2_header).unwrap();
+        protocol.register_block(parent2, vec![genesis], parent2_data.height);
         protocol.store.insert(parent2, parent2_data);
 
         let child = Hash::from_le_u64([3, 0, 0, 0]);
@@ -340,55 +319,32 @@ mod tests {
     }
 
     #[test]
-    fn test_pow_work_calculation() {
-        use consensus_pow::State;
-        let relations = Arc::new(BlockRelations::new());
-        let reachability = Arc::new(ReachabilityStore::new());
-        let topology = Arc::new(DagTopology::new(relations.clone(), reachability.clone()));
-        let store = Arc::new(GhostdagStore::new());
-        let protocol = GhostdagProtocol::new(18, topology, relations, store);
-
-        // Create a test block with known valid PoW
-        let parent = Hash::from_le_u64([0, 0, 0, 0]);
-        let mut header = Header::from_precomputed_hash(Hash::from_le_u64([1, 0, 0, 0]), vec![parent]);
-        
-        // Set target difficulty (relatively easy for test)
-        header.bits = 0x1f00ffff; // Very low difficulty
-        header.nonce = 42; // Would be solved in real mining
-
-        // Create test PoW state
-        let state = State::new(&header);
-        let pow_hash = state.calculate_pow(header.nonce);
-        // convert compact bits -> U256 target
-        let target = {
-            let bits = header.bits;
-            let size = (bits >> 24) as usize;
-            let word = bits & 0x007fffff;
-            if size <= 3 {
-                U256::from(word >> (8 * (3 - size)))
-            } else {
-                U256::from(word) << (8 * (size - 3))
-            }
+    fn test_blue_work_adds_the_work_of_mergeset_blues() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let manager = manager(18, genesis);
+        let header = |n: u64, parents: Vec<Hash>, bits: u32| {
+            let mut header = Header::from_precomputed_hash(Hash::from_le_u64([n, 0, 0, 0]), parents);
+            header.bits = bits;
+            header
         };
-        
-        // Only continue test if we have valid PoW
-        if pow_hash <= target {
-            let blue_set = {
-                let mut set = HashSet::new();
-                set.insert(header.hash);
-                set
-            };
-
-            // Calculate blue work
-            let blue_work = protocol.calculate_blue_work(&blue_set, &header).unwrap();
-            
-            // Work should be non-zero
-            assert!(blue_work > BlueWorkType::from(0u64));
-            
-            // Work should be proportional to difficulty
-            let expected_min = BlueWorkType::from(1u64);
-            assert!(blue_work >= expected_min);
-        }
+
+        // genesis <- a, b <- c, with a harder than b
+        let a = header(1, vec![genesis], 0x1f00ffff);
+        let b = header(2, vec![genesis], 0x207fffff);
+        let a_data = manager.add_block(&a).unwrap();
+        let b_data = manager.add_block(&b).unwrap();
+        assert_eq!(a_data.blue_work, BlueWorkType::from(0u64));
+        assert_eq!(b_data.blue_work, BlueWorkType::from(0u64));
+
+        let c = header(3, vec![a.hash, b.hash], 0x207fffff);
+        let c_data = manager.add_block(&c).unwrap();
+        assert_eq!(c_data.mergeset_blues.len(), 2);
+        assert_eq!(c_data.blue_work, work_from_bits(a.bits) + work_from_bits(b.bits));
+
+        // Each chain block adds the work of its selected parent
+        let d = header(4, vec![c.hash], 0x207fffff);
+        let d_data = manager.add_block(&d).unwrap();
+        assert_eq!(d_data.blue_work, c_data.blue_work + work_from_bits(c.bits));
     }
 
     #[test]
@@ -419,4 +375,168 @@ mod tests {
         let hash3 = protocol.hash_block_data(&data2);
         assert_ne!(hash1, hash3);
     }
+
+    /// Manager with `genesis` initialized, over fresh stores
+    fn manager(k: u32, genesis: Hash) -> GhostdagManager {
+        let relations = Arc::new(BlockRelations::new());
+        let reachability = Arc::new(ReachabilityStore::new());
+        let topology = Arc::new(DagTopology::new(relations.clone(), reachability.clone()));
+        let store = Arc::new(GhostdagStore::new());
+        let protocol = Arc::new(GhostdagProtocol::new(k, topology, relations, store.clone()).with_mergeset_size_limit(1000));
+        let manager = GhostdagManager::new(protocol, store);
+        reachability.init_genesis(genesis);
+        manager.init_genesis(&Header::from_precomputed_hash(genesis, vec![]));
+        manager
+    }
+
+    /// Xorshift generator, so failures reproduce from the seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// Random DAG of `size` blocks after genesis, each on one to three of the
+    /// latest `width` blocks, as (hash, parents) from genesis on
+    fn random_dag(seed: u64, size: usize, width: usize) -> Vec<(Hash, Vec<Hash>)> {
+        let mut rng = Rng(seed);
+        let mut blocks = vec![(Hash::from_le_u64([0, 0, 0, 0]), vec![])];
+        for i in 1..=size as u64 {
+            let recent = blocks.len().min(width);
+            let mut parents = Vec::new();
+            for _ in 0..1 + rng.next(3) {
+                let parent = blocks[blocks.len() - 1 - rng.next(recent)].0;
+                if !parents.contains(&parent) {
+                    parents.push(parent);
+                }
+            }
+            blocks.push((Hash::from_le_u64([i, seed, 0, 0]), parents));
+        }
+        blocks
+    }
+
+    /// GHOSTDAG computed from explicit past sets: the mergeset is everything in
+    /// the past but the selected parent and its past, and candidates are colored
+    /// greedily against the whole blue set
+    struct Reference {
+        k: usize,
+        past: HashMap<Hash, HashSet<Hash>>,
+        blue_score: HashMap<Hash, u64>,
+        blues: HashMap<Hash, HashSet<Hash>>,
+        reds: HashMap<Hash, HashSet<Hash>>,
+        selected_parent: HashMap<Hash, Hash>,
+    }
+
+    impl Reference {
+        fn new(k: usize, genesis: Hash) -> Self {
+            Self {
+                k,
+                past: HashMap::from([(genesis, HashSet::new())]),
+                blue_score: HashMap::from([(genesis, 0)]),
+                blues: HashMap::from([(genesis, HashSet::new())]),
+                reds: HashMap::from([(genesis, HashSet::new())]),
+                selected_parent: HashMap::from([(genesis, genesis)]),
+            }
+        }
+
+        fn in_anticone(&self, a: &Hash, b: &Hash) -> bool {
+            a != b && !self.past[a].contains(b) && !self.past[b].contains(a)
+        }
+
+        fn add(&mut self, hash: Hash, parents: &[Hash]) {
+            let mut past = HashSet::new();
+            for parent in parents {
+                past.insert(*parent);
+                past.extend(self.past[parent].iter().copied());
+            }
+            let mut selected_parent = parents[0];
+            for parent in parents {
+                if self.blue_score[parent] > self.blue_score[&selected_parent] {
+                    selected_parent = *parent;
+                }
+            }
+            let mut mergeset: Vec<_> = past
+                .iter()
+                .filter(|block| **block != selected_parent && !self.past[&selected_parent].contains(block))
+                .copied()
+                .collect();
+            mergeset.sort_by_key(|block| (self.blue_score[block], *block));
+
+            let mut blues = self.blues[&selected_parent].clone();
+            blues.insert(selected_parent);
+            let mut reds = HashSet::new();
+            for candidate in mergeset {
+                let anticone: Vec<_> = blues.iter().filter(|blue| self.in_anticone(blue, &candidate)).collect();
+                let fits = anticone.len() <= self.k
+                    && anticone.iter().all(|blue| blues.iter().filter(|other| self.in_anticone(blue, other)).count() < self.k);
+                if fits {
+                    blues.insert(candidate);
+                } else {
+                    reds.insert(candidate);
+                }
+            }
+            self.blue_score.insert(hash, blues.len() as u64);
+            self.past.insert(hash, past);
+            self.blues.insert(hash, blues);
+            self.reds.insert(hash, reds);
+            self.selected_parent.insert(hash, selected_parent);
+        }
+    }
+
+    #[test]
+    fn test_coloring_matches_reference() {
+        for k in [0, 1, 2, 3, 5, 18] {
+            for seed in 1..=3u64 {
+                let blocks = random_dag(seed * 7919 + k as u64, 200, 8);
+                let genesis = blocks[0].0;
+                let manager = manager(k, genesis);
+                let mut reference = Reference::new(k as usize, genesis);
+                for (hash, parents) in &blocks[1..] {
+                    let data = manager.add_block(&Header::from_precomputed_hash(*hash, parents.clone())).unwrap();
+                    reference.add(*hash, parents);
+                    assert_eq!(data.selected_parent, reference.selected_parent[hash], "k={} seed={} block {}", k, seed, hash);
+                    assert_eq!(data.blue_set, reference.blues[hash], "k={} seed={} block {}", k, seed, hash);
+                    assert_eq!(data.red_set, reference.reds[hash], "k={} seed={} block {}", k, seed, hash);
+                    assert_eq!(data.blue_score, reference.blue_score[hash]);
+                }
+
+                // The tip's blue set is a k-cluster
+                let tip = blocks.last().unwrap().0;
+                let blues: Vec<_> = reference.blues[&tip].iter().chain([&tip]).collect();
+                for blue in &blues {
+                    assert!(blues.iter().filter(|other| reference.in_anticone(blue, other)).count() <= k as usize);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mergeset_over_limit_is_rejected() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let relations = Arc::new(BlockRelations::new());
+        let reachability = Arc::new(ReachabilityStore::new());
+        let topology = Arc::new(DagTopology::new(relations.clone(), reachability.clone()));
+        let store = Arc::new(GhostdagStore::new());
+        let protocol = Arc::new(GhostdagProtocol::new(18, topology, relations, store.clone()).with_mergeset_size_limit(3));
+        let manager = GhostdagManager::new(protocol, store);
+        reachability.init_genesis(genesis);
+        manager.init_genesis(&Header::from_precomputed_hash(genesis, vec![]));
+
+        // Five side blocks on genesis, merged by one block
+        let side: Vec<_> = (1..=5).map(|i| Hash::from_le_u64([i, 0, 0, 0])).collect();
+        for hash in &side {
+            manager.add_block(&Header::from_precomputed_hash(*hash, vec![genesis])).unwrap();
+        }
+        let merging = Header::from_precomputed_hash(Hash::from_le_u64([6, 0, 0, 0]), side.clone());
+        let error = manager.add_block(&merging).unwrap_err();
+        assert!(error.contains("exceeds 3 blocks"), "{}", error);
+
+        let merging = Header::from_precomputed_hash(Hash::from_le_u64([7, 0, 0, 0]), side[..4].to_vec());
+        assert_eq!(manager.add_block(&merging).unwrap().merge_set_size, 4);
+    }
 }
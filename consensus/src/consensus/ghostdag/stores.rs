@@ -9,21 +9,29 @@ use std::collections::HashSet;
 pub struct GhostdagData {
     /// Blue set - blocks considered blue for this block
     pub blue_set: HashSet<Hash>,
-    /// Red set - blocks considered red for this block
+    /// Red set - blocks of the mergeset colored red by this block
     pub red_set: HashSet<Hash>,
+    /// Blues of the mergeset in the order they were colored, selected parent first
+    #[serde(default)]
+    pub mergeset_blues: Vec<Hash>,
     /// Blue score - number of blue blocks in the past
     pub blue_score: u64,
 
     /// Blue work - cumulative difficulty of blue blocks
     pub blue_work: BlueWorkType,
 
+    /// Work of the block's own bits, added to the blue work of the blocks merging it as blue
+    #[serde(default)]
+    pub work: BlueWorkType,
+
     /// Selected parent - parent with highest blue score
     pub selected_parent: Hash,
 
-    /// Merge set size - number of parents
+    /// Merge set size - the selected parent and the mergeset
     pub merge_set_size: u64,
 
-    /// Blues anticone sizes - for ordering
+    /// Number of blues in the anticone of each mergeset blue, and of older blues
+    /// whose count this block raised
     pub blues_anticone_sizes: HashMap<Hash, u32>,
 
     /// Block height
@@ -35,8 +43,10 @@ impl GhostdagData {
         Self {
             blue_set: HashSet::new(),
             red_set: HashSet::new(),
+            mergeset_blues: Vec::new(),
             blue_score: 0,
             blue_work: BlueWorkType::from(0u64),
+            work: BlueWorkType::from(0u64),
             selected_parent,
             merge_set_size: 0,
             blues_anticone_sizes: HashMap::new(),
@@ -72,6 +82,23 @@ impl GhostdagStore {
         store.get(hash).cloned()
     }
 
+    pub fn get_blue_score(&self, hash: &Hash) -> Option<u64> {
+        self.data.read().unwrap().get(hash).map(|data| data.blue_score)
+    }
+
+    pub fn get_selected_parent(&self, hash: &Hash) -> Option<Hash> {
+        self.data.read().unwrap().get(hash).map(|data| data.selected_parent)
+    }
+
+    pub fn get_mergeset_blues(&self, hash: &Hash) -> Option<Vec<Hash>> {
+        self.data.read().unwrap().get(hash).map(|data| data.mergeset_blues.clone())
+    }
+
+    /// Blue anticone size of `blue` recorded by `hash`, if `hash` merged or updated it
+    pub fn get_blue_anticone_size(&self, hash: &Hash, blue: &Hash) -> Option<u32> {
+        self.data.read().unwrap().get(hash).and_then(|data| data.blues_anticone_sizes.get(blue).copied())
+    }
+
     pub fn contains(&self, hash: &Hash) -> bool {
         let store = self.data.read().unwrap();
         store.contains_key(hash)
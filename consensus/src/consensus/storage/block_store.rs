@@ -1,92 +1,164 @@
 //! Block store for consensus
 //!
-//! This module provides block storage and retrieval functionality.
+//! This module provides block storage and retrieval functionality. Headers and
+//! bodies are kept apart, so header reads never load transactions: a stored
+//! block has a header and a body, a pruned or header-only block just a header.
 
 use consensus_core::block::Block;
 use consensus_core::header::Header;
+use consensus_core::tx::Transaction;
 use consensus_core::Hash;
 use consensus_core::errors::ConsensusError;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
 use std::sync::{Arc, RwLock};
 use database::stores::BlockStore as DbBlockStore;
 use database::stores::HeaderStore as DbHeaderStore;
+use database::stores::IndexStore as DbIndexStore;
 use std::sync::Arc as StdArc;
 
 /// Block store for consensus storage
 pub struct BlockStore {
-    blocks: Arc<RwLock<HashMap<Hash, Block>>>,
+    /// Transactions of each block whose body is stored
+    bodies: Arc<RwLock<HashMap<Hash, Vec<Transaction>>>>,
+    /// Headers of all stored blocks, with or without bodies
     headers: Arc<RwLock<HashMap<Hash, Header>>>,
     db_store: Option<StdArc<DbBlockStore>>,
     db_header_store: Option<StdArc<DbHeaderStore>>,
+    /// Persistent transaction and children indexes, kept with the DB-backed stores
+    db_index: Option<StdArc<DbIndexStore>>,
+    /// Reverse index from a block to its direct children. Kept in memory and
+    /// filled as headers and blocks are stored.
+    children: Arc<RwLock<HashMap<Hash, Vec<Hash>>>>,
+    /// DAA score and hash of stored headers, used without a header database
+    daa_index: Arc<RwLock<BTreeSet<(u64, Hash)>>>,
+    /// Blue score to selected chain block, used without a header database
+    chain_index: Arc<RwLock<BTreeMap<u64, Hash>>>,
 }
 
 impl BlockStore {
     /// Create a new block store
     pub fn new() -> Self {
         Self {
-            blocks: Arc::new(RwLock::new(HashMap::new())),
+            bodies: Arc::new(RwLock::new(HashMap::new())),
             headers: Arc::new(RwLock::new(HashMap::new())),
             db_store: None,
             db_header_store: None,
+            db_index: None,
+            children: Arc::new(RwLock::new(HashMap::new())),
+            daa_index: Arc::new(RwLock::new(BTreeSet::new())),
+            chain_index: Arc::new(RwLock::new(BTreeMap::new())),
         }
     }
 
-    /// Create a new block store backed by a database store
+    /// Create a new block store backed by a database store. Headers are read
+    /// from `header_store`, or from the block store's own header store.
     pub fn new_with_db(db_store: StdArc<DbBlockStore>, header_store: Option<StdArc<DbHeaderStore>>) -> Self {
+        let header_store = header_store.unwrap_or_else(|| db_store.headers());
         Self {
-            blocks: Arc::new(RwLock::new(HashMap::new())),
+            bodies: Arc::new(RwLock::new(HashMap::new())),
             headers: Arc::new(RwLock::new(HashMap::new())),
             db_store: Some(db_store),
-            db_header_store: header_store,
+            db_header_store: Some(header_store),
+            db_index: None,
+            children: Arc::new(RwLock::new(HashMap::new())),
+            daa_index: Arc::new(RwLock::new(BTreeSet::new())),
+            chain_index: Arc::new(RwLock::new(BTreeMap::new())),
         }
     }
 
+    /// Maintain the transaction and children indexes of `index_store` as blocks are stored
+    pub fn with_index(self, index_store: StdArc<DbIndexStore>) -> Self {
+        Self { db_index: Some(index_store), ..self }
+    }
+
+    /// Record `header` as a child of each of its direct parents
+    fn index_children(&self, header: &Header) {
+        let mut children = self.children.write().unwrap();
+        for parent in header.direct_parents() {
+            let entry = children.entry(*parent).or_default();
+            if !entry.contains(&header.hash) {
+                entry.push(header.hash);
+            }
+        }
+    }
+
+    /// Get the direct children of a block
+    pub fn get_children(&self, hash: &Hash) -> Vec<Hash> {
+        if let Some(index) = &self.db_index {
+            match index.children(hash) {
+                Ok(children) => return children,
+                Err(e) => eprintln!("DB children index error: {}", e),
+            }
+        }
+        let children = self.children.read().unwrap();
+        children.get(hash).cloned().unwrap_or_default()
+    }
+
     /// Check if this store is backed by a database
     pub fn has_db(&self) -> bool {
         self.db_store.is_some()
     }
 
-    /// Store a block
+    /// Store a block: its header and its body
     pub fn store_block(&self, block: Block) -> Result<(), ConsensusError> {
-        let hash = block.header.hash;
+        self.index_children(&block.header);
         if let Some(db) = &self.db_store {
             db.put_block(&block).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            if let Some(index) = &self.db_index {
+                index.index_block(&block).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            }
             return Ok(());
         }
-        let mut blocks = self.blocks.write().unwrap();
-        blocks.insert(hash, block);
+        let Block { header, transactions } = block;
+        self.bodies.write().unwrap().insert(header.hash, transactions);
+        self.insert_header(header);
         Ok(())
     }
 
     /// Store a header only
     pub fn store_header(&self, header: Header) -> Result<(), ConsensusError> {
-        let hash = header.hash;
+        self.index_children(&header);
         if let Some(hdb) = &self.db_header_store {
             hdb.put_header(&header).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            if let Some(index) = &self.db_index {
+                index.index_header(&header).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            }
             return Ok(());
         }
-        let mut headers = self.headers.write().unwrap();
-        headers.insert(hash, header);
+        self.insert_header(header);
         Ok(())
     }
 
-    /// Get a block by hash
+    fn insert_header(&self, header: Header) {
+        self.daa_index.write().unwrap().insert((header.daa_score, header.hash));
+        self.headers.write().unwrap().insert(header.hash, header);
+    }
+
+    /// Get a block by hash, header and body
     pub fn get_block(&self, hash: &Hash) -> Option<Block> {
+        let transactions = self.get_block_transactions_lazy(hash)?;
+        Some(Block::new(self.get_header(hash)?, transactions))
+    }
+
+    /// Transactions of a stored block, read from the body keyspace alone. Lazy in
+    /// that nothing is loaded for a block until its transactions are asked for.
+    pub fn get_block_transactions_lazy(&self, hash: &Hash) -> Option<Vec<Transaction>> {
         if let Some(db) = &self.db_store {
-            match db.get_block(hash) {
-                Ok(opt) => return opt,
+            match db.get_transactions(hash) {
+                Ok(transactions) => return transactions,
                 Err(e) => {
                     // On DB error return None for now and log
-                    eprintln!("DB get_block error: {}", e);
+                    eprintln!("DB get_transactions error: {}", e);
                     return None;
                 }
             }
         }
-        let blocks = self.blocks.read().unwrap();
-        blocks.get(hash).cloned()
+        let bodies = self.bodies.read().unwrap();
+        bodies.get(hash).cloned()
     }
 
-    /// Get a header by hash
+    /// Get a header by hash, without loading the block body
     pub fn get_header(&self, hash: &Hash) -> Option<Header> {
         if let Some(hdb) = &self.db_header_store {
             match hdb.get_header(hash) {
@@ -98,7 +170,7 @@ impl BlockStore {
         headers.get(hash).cloned()
     }
 
-    /// Check if a block exists
+    /// Check if the body of a block is stored
     pub fn has_block(&self, hash: &Hash) -> bool {
         if let Some(db) = &self.db_store {
             match db.has_block(hash) {
@@ -109,11 +181,11 @@ impl BlockStore {
                 }
             }
         }
-        let blocks = self.blocks.read().unwrap();
-        blocks.contains_key(hash)
+        let bodies = self.bodies.read().unwrap();
+        bodies.contains_key(hash)
     }
 
-    /// Check if a header exists
+    /// Check if a header exists, with or without its body
     pub fn has_header(&self, hash: &Hash) -> bool {
         if let Some(hdb) = &self.db_header_store {
             match hdb.has_header(hash) {
@@ -125,7 +197,7 @@ impl BlockStore {
         headers.contains_key(hash)
     }
 
-    /// Remove a block
+    /// Remove the body of a block. The header and the block's index entries stay.
     pub fn remove_block(&self, hash: &Hash) -> Option<Block> {
         if let Some(db) = &self.db_store {
             if let Err(e) = db.delete_block(hash) {
@@ -133,20 +205,138 @@ impl BlockStore {
             }
             return None;
         }
-        let mut blocks = self.blocks.write().unwrap();
-        blocks.remove(hash)
+        let transactions = self.bodies.write().unwrap().remove(hash)?;
+        Some(Block::new(self.get_header(hash)?, transactions))
     }
 
-    /// Remove a header
+    /// Remove a header along with any body, dropping the block's index entries
     pub fn remove_header(&self, hash: &Hash) -> Option<Header> {
+        if let Some(db) = &self.db_store {
+            if let Err(e) = db.delete_block(hash) {
+                eprintln!("DB delete_block error: {}", e);
+            }
+        }
         if let Some(hdb) = &self.db_header_store {
             if let Err(e) = hdb.delete_header(hash) {
                 eprintln!("DB delete_header error: {}", e);
             }
             return None;
         }
-        let mut headers = self.headers.write().unwrap();
-        headers.remove(hash)
+        self.bodies.write().unwrap().remove(hash);
+        let header = self.headers.write().unwrap().remove(hash)?;
+        self.daa_index.write().unwrap().remove(&(header.daa_score, header.hash));
+        let mut chain_index = self.chain_index.write().unwrap();
+        if chain_index.get(&header.blue_score) == Some(&header.hash) {
+            chain_index.remove(&header.blue_score);
+        }
+        Some(header)
+    }
+
+    /// The selected chain block with blue score `blue_score`
+    pub fn get_chain_block_by_blue_score(&self, blue_score: u64) -> Option<Hash> {
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_chain_block_by_blue_score(blue_score) {
+                Ok(hash) => return hash,
+                Err(e) => { eprintln!("DB chain index error: {}", e); return None; }
+            }
+        }
+        self.chain_index.read().unwrap().get(&blue_score).copied()
+    }
+
+    /// Blue score and hash of the highest indexed chain block
+    pub fn get_chain_tip(&self) -> Option<(u64, Hash)> {
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_chain_tip() {
+                Ok(tip) => return tip,
+                Err(e) => { eprintln!("DB chain index error: {}", e); return None; }
+            }
+        }
+        self.chain_index.read().unwrap().iter().next_back().map(|(score, hash)| (*score, *hash))
+    }
+
+    /// Replace the indexed chain from `blue_score` up with `chain`, returning the
+    /// replaced entries, lowest score first
+    pub fn replace_chain_from(&self, blue_score: u64, chain: &[(u64, Hash)]) -> Result<Vec<(u64, Hash)>, ConsensusError> {
+        if let Some(hdb) = &self.db_header_store {
+            return hdb.replace_chain_from(blue_score, chain).map_err(|e| ConsensusError::DatabaseError(e.to_string()));
+        }
+        let mut chain_index = self.chain_index.write().unwrap();
+        let replaced = chain_index.split_off(&blue_score);
+        chain_index.extend(chain.iter().copied());
+        Ok(replaced.into_iter().collect())
+    }
+
+    /// Blocks and headers with a DAA score in `from..=to`, by DAA score, then hash
+    pub fn get_blocks_by_daa_range(&self, from: u64, to: u64) -> Vec<Hash> {
+        if from > to {
+            return Vec::new();
+        }
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_blocks_by_daa_range(from, to) {
+                Ok(hashes) => return hashes,
+                Err(e) => { eprintln!("DB DAA index error: {}", e); return Vec::new(); }
+            }
+        }
+        let daa_index = self.daa_index.read().unwrap();
+        daa_index
+            .range((from, Hash::from_bytes([0; 32]))..=(to, Hash::from_bytes([0xff; 32])))
+            .map(|(_, hash)| *hash)
+            .collect()
+    }
+
+    /// Up to `limit` stored blocks following `after` in (DAA score, hash) order,
+    /// skipping those whose body was pruned
+    pub fn get_blocks_after_daa(&self, mut after: Option<(u64, Hash)>, limit: usize) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        while blocks.len() < limit {
+            let entries = self.daa_entries_after(after, limit - blocks.len());
+            let Some(last) = entries.last().copied() else { break };
+            blocks.extend(entries.iter().filter_map(|(_, hash)| self.get_block(hash)));
+            after = Some(last);
+        }
+        blocks
+    }
+
+    /// Up to `limit` stored headers following `after` in (DAA score, hash) order
+    pub fn get_headers_after_daa(&self, after: Option<(u64, Hash)>, limit: usize) -> Vec<Header> {
+        self.daa_entries_after(after, limit).iter().filter_map(|(_, hash)| self.get_header(hash)).collect()
+    }
+
+    /// Up to `count` stored blocks with the highest DAA scores, highest first,
+    /// skipping those whose body was pruned
+    pub fn get_recent_blocks(&self, count: usize) -> Vec<Block> {
+        let (mut blocks, mut before) = (Vec::new(), None);
+        while blocks.len() < count {
+            let entries = self.daa_entries_before(before, count - blocks.len());
+            let Some(last) = entries.last().copied() else { break };
+            blocks.extend(entries.iter().filter_map(|(_, hash)| self.get_block(hash)));
+            before = Some(last);
+        }
+        blocks
+    }
+
+    fn daa_entries_after(&self, after: Option<(u64, Hash)>, limit: usize) -> Vec<(u64, Hash)> {
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_daa_entries_after(after, limit) {
+                Ok(entries) => return entries,
+                Err(e) => { eprintln!("DB DAA index error: {}", e); return Vec::new(); }
+            }
+        }
+        let daa_index = self.daa_index.read().unwrap();
+        let lower = after.map_or(Bound::Unbounded, Bound::Excluded);
+        daa_index.range((lower, Bound::Unbounded)).take(limit).copied().collect()
+    }
+
+    fn daa_entries_before(&self, before: Option<(u64, Hash)>, limit: usize) -> Vec<(u64, Hash)> {
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_daa_entries_before(before, limit) {
+                Ok(entries) => return entries,
+                Err(e) => { eprintln!("DB DAA index error: {}", e); return Vec::new(); }
+            }
+        }
+        let daa_index = self.daa_index.read().unwrap();
+        let upper = before.map_or(Bound::Unbounded, Bound::Excluded);
+        daa_index.range((Bound::Unbounded, upper)).rev().take(limit).copied().collect()
     }
 
     /// Get number of stored blocks
@@ -157,23 +347,55 @@ impl BlockStore {
                 Err(e) => eprintln!("DB count error: {}", e),
             }
         }
-        let blocks = self.blocks.read().unwrap();
-        blocks.len()
+        let bodies = self.bodies.read().unwrap();
+        bodies.len()
     }
 
-    /// Get all blocks (for height-based lookup)
-    pub fn get_all_blocks(&self) -> Vec<Block> {
+    /// Hashes of the blocks whose bodies are stored
+    pub fn block_hashes(&self) -> Vec<Hash> {
         if let Some(db) = &self.db_store {
-            match db.get_all_blocks() {
-                Ok(blocks) => return blocks,
+            match db.block_hashes() {
+                Ok(hashes) => return hashes,
                 Err(e) => {
-                    eprintln!("DB get_all_blocks error: {}", e);
+                    eprintln!("DB block_hashes error: {}", e);
                     return vec![];
                 }
             }
         }
-        let blocks = self.blocks.read().unwrap();
-        blocks.values().cloned().collect()
+        let bodies = self.bodies.read().unwrap();
+        bodies.keys().copied().collect()
+    }
+
+    /// All stored headers, with or without bodies. Reads no transactions.
+    pub fn get_all_headers(&self) -> Vec<Header> {
+        if let Some(hdb) = &self.db_header_store {
+            match hdb.get_all_headers() {
+                Ok(headers) => return headers,
+                Err(e) => {
+                    eprintln!("DB get_all_headers error: {}", e);
+                    return vec![];
+                }
+            }
+        }
+        let headers = self.headers.read().unwrap();
+        headers.values().cloned().collect()
+    }
+
+    /// Blocks containing the transaction `transaction_id`, from the transaction
+    /// index if there is one, otherwise by scanning the stored blocks
+    pub fn blocks_containing_transaction(&self, transaction_id: &Hash) -> Vec<Hash> {
+        if let Some(index) = &self.db_index {
+            match index.blocks_containing(transaction_id) {
+                Ok(blocks) => return blocks,
+                Err(e) => eprintln!("DB transaction index error: {}", e),
+            }
+        }
+        self.block_hashes()
+            .into_iter()
+            .filter(|hash| {
+                self.get_block_transactions_lazy(hash).is_some_and(|transactions| transactions.iter().any(|tx| tx.hash() == *transaction_id))
+            })
+            .collect()
     }
 
     /// Get number of stored headers
@@ -241,6 +463,27 @@ mod tests {
         assert_eq!(retrieved.hash, hash);
     }
 
+    #[test]
+    fn test_header_and_body_apart() {
+        let store = BlockStore::new();
+        let block = create_test_block();
+        let hash = block.header.hash;
+        store.store_block(block).unwrap();
+
+        assert_eq!(store.get_header(&hash).unwrap().hash, hash);
+        assert!(store.get_block_transactions_lazy(&hash).unwrap().is_empty());
+        assert_eq!(store.get_all_headers().len(), 1);
+        assert_eq!(store.block_hashes(), vec![hash]);
+
+        // Without its body the block is gone but the header remains
+        store.remove_block(&hash).unwrap();
+        assert!(!store.has_block(&hash));
+        assert!(store.get_block(&hash).is_none());
+        assert!(store.get_block_transactions_lazy(&hash).is_none());
+        assert!(store.has_header(&hash));
+        assert!(store.block_hashes().is_empty());
+    }
+
     #[test]
     fn test_has_block() {
         let store = BlockStore::new();
@@ -251,5 +494,105 @@ mod tests {
         store.store_block(block).unwrap();
         assert!(store.has_block(&hash));
     }
-}
 
+    #[test]
+    fn test_children_index() {
+        let store = BlockStore::new();
+        let parent = Hash::from_le_u64([1, 0, 0, 0]);
+        let child_a = Header::from_precomputed_hash(Hash::from_le_u64([2, 0, 0, 0]), vec![parent]);
+        let child_b = Header::from_precomputed_hash(Hash::from_le_u64([3, 0, 0, 0]), vec![parent]);
+
+        store.store_header(child_a.clone()).unwrap();
+        store.store_block(Block::new(child_a.clone(), Vec::new())).unwrap();
+        store.store_header(child_b.clone()).unwrap();
+
+        assert_eq!(store.get_children(&parent), vec![child_a.hash, child_b.hash]);
+        assert!(store.get_children(&child_a.hash).is_empty());
+    }
+
+    fn scored_header(n: u64, blue_score: u64, daa_score: u64) -> Header {
+        let mut header = Header::from_precomputed_hash(Hash::from_u64_word(n), vec![]);
+        header.blue_score = blue_score;
+        header.daa_score = daa_score;
+        header
+    }
+
+    #[test]
+    fn test_daa_index() {
+        let store = BlockStore::new();
+        // Stored out of DAA order, two of them sharing DAA score 7
+        let headers = [scored_header(1, 4, 9), scored_header(2, 1, 2), scored_header(3, 3, 7), scored_header(4, 3, 7)];
+        for header in &headers {
+            store.store_block(Block::new(header.clone(), Vec::new())).unwrap();
+        }
+
+        assert_eq!(store.get_blocks_by_daa_range(0, 100), vec![headers[1].hash, headers[2].hash, headers[3].hash, headers[0].hash]);
+        assert_eq!(store.get_blocks_by_daa_range(7, 7), vec![headers[2].hash, headers[3].hash]);
+        assert!(store.get_blocks_by_daa_range(3, 6).is_empty());
+        let page: Vec<Hash> = store.get_blocks_after_daa(Some((7, headers[2].hash)), 10).iter().map(|b| b.header.hash).collect();
+        assert_eq!(page, vec![headers[3].hash, headers[0].hash]);
+
+        // A pruned body keeps its entry, the header being still stored
+        store.remove_block(&headers[2].hash);
+        assert_eq!(store.get_blocks_by_daa_range(7, 7), vec![headers[2].hash, headers[3].hash]);
+        let page: Vec<Hash> = store.get_blocks_after_daa(None, 10).iter().map(|b| b.header.hash).collect();
+        assert_eq!(page, vec![headers[1].hash, headers[3].hash, headers[0].hash]);
+        let page: Vec<Hash> = store.get_headers_after_daa(Some((2, headers[1].hash)), 2).iter().map(|h| h.hash).collect();
+        assert_eq!(page, vec![headers[2].hash, headers[3].hash]);
+        let recent: Vec<Hash> = store.get_recent_blocks(2).iter().map(|b| b.header.hash).collect();
+        assert_eq!(recent, vec![headers[0].hash, headers[3].hash]);
+        assert_eq!(store.get_recent_blocks(10).len(), 3);
+
+        store.remove_header(&headers[2].hash);
+        store.remove_header(&headers[3].hash);
+        assert!(store.get_blocks_by_daa_range(7, 7).is_empty());
+    }
+
+    #[test]
+    fn test_chain_index_reorg() {
+        let store = BlockStore::new();
+        let hash = Hash::from_u64_word;
+        store.replace_chain_from(0, &[(3, hash(3)), (1, hash(1)), (0, hash(0)), (2, hash(2))]).unwrap();
+        assert_eq!(store.get_chain_block_by_blue_score(2), Some(hash(2)));
+        assert_eq!(store.get_chain_tip(), Some((3, hash(3))));
+
+        // Reorg from score 2 onto a chain skipping score 3
+        let replaced = store.replace_chain_from(2, &[(4, hash(14)), (2, hash(12))]).unwrap();
+        assert_eq!(replaced, vec![(2, hash(2)), (3, hash(3))]);
+        assert_eq!(store.get_chain_block_by_blue_score(1), Some(hash(1)));
+        assert_eq!(store.get_chain_block_by_blue_score(2), Some(hash(12)));
+        assert_eq!(store.get_chain_block_by_blue_score(3), None);
+        assert_eq!(store.get_chain_tip(), Some((4, hash(14))));
+
+        // Pruning a chain block's body keeps its entry, removing the block drops it
+        store.store_block(Block::new(scored_header(14, 4, 4), Vec::new())).unwrap();
+        store.remove_block(&hash(14));
+        assert_eq!(store.get_chain_block_by_blue_score(4), Some(hash(14)));
+        store.remove_header(&hash(14));
+        assert_eq!(store.get_chain_block_by_blue_score(4), None);
+        assert_eq!(store.get_chain_tip(), Some((2, hash(12))));
+    }
+
+    /// Loading every block loads the whole chain into memory; lookups go
+    /// through the DAA and chain indexes instead
+    #[test]
+    fn test_no_whole_store_block_scans() {
+        fn sources(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap().flatten() {
+                let path = entry.path();
+                let name = entry.file_name();
+                if path.is_dir() && name != "target" && !name.to_string_lossy().starts_with('.') {
+                    sources(&path, files);
+                } else if path.extension().is_some_and(|extension| extension == "rs") {
+                    files.push(path);
+                }
+            }
+        }
+        let symbol = concat!("get_all", "_blocks");
+        let mut files = Vec::new();
+        sources(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap(), &mut files);
+        let offenders: Vec<_> =
+            files.into_iter().filter(|path| std::fs::read_to_string(path).is_ok_and(|source| source.contains(symbol))).collect();
+        assert!(offenders.is_empty(), "{} is referenced in {:?}", symbol, offenders);
+    }
+}
@@ -7,14 +7,22 @@ use consensus_core::block::Block;
 use consensus_core::header::Header;
 use consensus_core::Hash;
 use consensus_core::errors::ConsensusError;
+use consensus_core::provenance::{BlockProvenance, BlockSource};
 use super::block_store::BlockStore;
+use super::fee_store::BlockFeeStore;
 use super::utxo_set::UtxoSet;
-use std::sync::Arc;
+use database::stores::ProvenanceStore;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
 /// Consensus storage coordinator
 pub struct ConsensusStorage {
     block_store: Arc<BlockStore>,
     utxo_set: Arc<UtxoSet>,
+    /// First-seen source of each block delivered to the processor
+    provenance: Arc<RwLock<HashMap<Hash, BlockProvenance>>>,
+    provenance_store: Option<Arc<ProvenanceStore>>,
+    fee_store: Arc<BlockFeeStore>,
 }
 
 impl ConsensusStorage {
@@ -23,6 +31,9 @@ impl ConsensusStorage {
         Self {
             block_store: Arc::new(BlockStore::new()),
             utxo_set: Arc::new(UtxoSet::new()),
+            provenance: Arc::new(RwLock::new(HashMap::new())),
+            provenance_store: None,
+            fee_store: Arc::new(BlockFeeStore::default()),
         }
     }
 
@@ -31,9 +42,22 @@ impl ConsensusStorage {
         Self {
             block_store,
             utxo_set,
+            provenance: Arc::new(RwLock::new(HashMap::new())),
+            provenance_store: None,
+            fee_store: Arc::new(BlockFeeStore::default()),
         }
     }
 
+    /// Persist block provenance in `store`
+    pub fn with_provenance_store(self, store: Arc<ProvenanceStore>) -> Self {
+        Self { provenance_store: Some(store), ..self }
+    }
+
+    /// Keep block fee records in `store`
+    pub fn with_fee_store(self, store: Arc<BlockFeeStore>) -> Self {
+        Self { fee_store: store, ..self }
+    }
+
     /// Get block store reference
     pub fn block_store(&self) -> Arc<BlockStore> {
         self.block_store.clone()
@@ -44,6 +68,11 @@ impl ConsensusStorage {
         self.utxo_set.clone()
     }
 
+    /// Get block fee store reference
+    pub fn fee_store(&self) -> Arc<BlockFeeStore> {
+        self.fee_store.clone()
+    }
+
     /// Store a block
     pub fn store_block(&self, block: Block) -> Result<(), ConsensusError> {
         self.block_store.store_block(block)
@@ -83,10 +112,86 @@ impl ConsensusStorage {
         self.utxo_set.apply_block(block, block_daa_score)
     }
 
+    /// Store and apply `genesis` to an empty database, or check that the
+    /// database was built on it: a database without `genesis` must not start
+    /// with a block without parents, which has the lowest DAA score of all.
+    /// A database imported from a snapshot keeps no genesis and is accepted.
+    pub fn init_genesis(&self, genesis: &Block) -> Result<(), ConsensusError> {
+        if self.block_store.block_count() == 0 && self.block_store.header_count() == 0 {
+            return self.apply_block(genesis, genesis.header.daa_score);
+        }
+        let expected = genesis.header.hash;
+        if self.block_store.has_header(&expected) {
+            return Ok(());
+        }
+        match self.block_store.get_headers_after_daa(None, 1).into_iter().next() {
+            Some(stored) if stored.direct_parents().is_empty() => Err(ConsensusError::GenesisMismatch { stored: stored.hash, expected }),
+            _ => Ok(()),
+        }
+    }
+
     /// Get UTXO set
     pub fn utxo_set_ref(&self) -> Arc<UtxoSet> {
         self.utxo_set.clone()
     }
+
+    /// Record that `hash` was delivered by `source` at `now` (ms), unless an
+    /// earlier delivery is already recorded. Returns whether this was the first.
+    pub fn record_first_seen(&self, hash: Hash, source: BlockSource, now: u64) -> Result<bool, ConsensusError> {
+        if self.get_provenance(&hash).is_some() {
+            return Ok(false);
+        }
+        let provenance = BlockProvenance::new(source, now);
+        self.put_provenance(hash, provenance)?;
+        Ok(true)
+    }
+
+    /// Flag the block as having failed validation. Returns its provenance, if recorded.
+    pub fn mark_invalid(&self, hash: &Hash) -> Result<Option<BlockProvenance>, ConsensusError> {
+        let Some(mut provenance) = self.get_provenance(hash) else { return Ok(None) };
+        if !provenance.invalid {
+            provenance.invalid = true;
+            self.put_provenance(*hash, provenance)?;
+        }
+        Ok(Some(provenance))
+    }
+
+    /// Where and when the block was first seen, and whether it failed validation
+    pub fn get_provenance(&self, hash: &Hash) -> Option<BlockProvenance> {
+        if let Some(provenance) = self.provenance.read().unwrap().get(hash) {
+            return Some(*provenance);
+        }
+        let provenance = self.provenance_store.as_ref()?.get(hash).ok().flatten()?;
+        self.provenance.write().unwrap().insert(*hash, provenance);
+        Some(provenance)
+    }
+
+    fn put_provenance(&self, hash: Hash, provenance: BlockProvenance) -> Result<(), ConsensusError> {
+        if let Some(store) = &self.provenance_store {
+            store.put(&hash, &provenance).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+        }
+        self.provenance.write().unwrap().insert(hash, provenance);
+        Ok(())
+    }
+
+    /// Returns up to `limit` blocks in DAA score order (then hash), starting right
+    /// after `low_hash`, or from the lowest block (genesis) when `None`. Pages are
+    /// read from the DAA score index.
+    ///
+    /// Any stored block can be used as anchor, chain block or not. A `low_hash`
+    /// whose header is known but whose body is gone yields `BlockPruned`, an
+    /// unknown one `BlockNotFound`.
+    pub fn get_blocks_after(&self, low_hash: Option<&Hash>, limit: usize) -> Result<Vec<Block>, ConsensusError> {
+        let anchor = match low_hash {
+            Some(hash) => match self.get_header(hash) {
+                Some(header) if self.has_block(hash) => Some((header.daa_score, *hash)),
+                Some(_) => return Err(ConsensusError::BlockPruned(*hash)),
+                None => return Err(ConsensusError::BlockNotFound(*hash)),
+            },
+            None => None,
+        };
+        Ok(self.block_store.get_blocks_after_daa(anchor, limit))
+    }
 }
 
 impl Default for ConsensusStorage {
@@ -128,6 +233,84 @@ mod tests {
         assert!(storage.has_block(&hash));
     }
 
+    /// 100 blocks where every blue score is shared by two sibling blocks
+    fn create_test_dag(storage: &ConsensusStorage) -> Vec<Hash> {
+        let mut hashes = Vec::new();
+        for i in 0..100u64 {
+            let parents = if i < 2 { vec![] } else { vec![vec![hashes[(i as usize / 2 - 1) * 2]]] };
+            let header = Header::new_finalized(
+                1,
+                parents,
+                ZERO_HASH,
+                ZERO_HASH,
+                ZERO_HASH,
+                1000 + i,
+                0x1f00ffff,
+                i,
+                i / 2,
+                BlueWorkType::from(i / 2),
+                i / 2,
+                ZERO_HASH,
+            );
+            hashes.push(header.hash);
+            storage.store_block(Block::new(header, Vec::new())).unwrap();
+        }
+        hashes
+    }
+
+    #[test]
+    fn test_get_blocks_pagination() {
+        let storage = ConsensusStorage::new();
+        let hashes = create_test_dag(&storage);
+
+        let mut seen = Vec::new();
+        let mut low_hash = None;
+        loop {
+            let page = storage.get_blocks_after(low_hash.as_ref(), 7).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 7);
+            low_hash = Some(page.last().unwrap().header.hash);
+            seen.extend(page.into_iter().map(|b| (b.header.daa_score, b.header.hash)));
+        }
+
+        assert_eq!(seen.len(), hashes.len());
+        assert!(seen.windows(2).all(|w| w[0] < w[1]), "pages must be strictly ordered without duplicates");
+        let mut sorted: Vec<Hash> = hashes.clone();
+        sorted.sort();
+        let mut seen_hashes: Vec<Hash> = seen.iter().map(|(_, h)| *h).collect();
+        seen_hashes.sort();
+        assert_eq!(seen_hashes, sorted);
+    }
+
+    #[test]
+    fn test_get_blocks_from_non_chain_block() {
+        let storage = ConsensusStorage::new();
+        let hashes = create_test_dag(&storage);
+
+        // Anchor on each sibling of blue score 10; both must continue past it
+        let all: Vec<Hash> = storage.get_blocks_after(None, 100).unwrap().iter().map(|b| b.header.hash).collect();
+        for hash in &hashes[20..22] {
+            let position = all.iter().position(|h| h == hash).unwrap();
+            let page: Vec<Hash> = storage.get_blocks_after(Some(hash), 100).unwrap().iter().map(|b| b.header.hash).collect();
+            assert_eq!(page, all[position + 1..]);
+        }
+    }
+
+    #[test]
+    fn test_get_blocks_unknown_and_pruned_anchor() {
+        let storage = ConsensusStorage::new();
+        let hashes = create_test_dag(&storage);
+
+        let unknown = Hash::from_u64_word(u64::MAX);
+        assert!(matches!(storage.get_blocks_after(Some(&unknown), 10), Err(ConsensusError::BlockNotFound(h)) if h == unknown));
+
+        let pruned = storage.block_store().remove_block(&hashes[5]).unwrap();
+        storage.store_header(pruned.header.clone()).unwrap();
+        assert!(matches!(storage.get_blocks_after(Some(&hashes[5]), 10), Err(ConsensusError::BlockPruned(h)) if h == hashes[5]));
+    }
+
     #[test]
     fn test_apply_block() {
         let storage = ConsensusStorage::new();
@@ -136,5 +319,41 @@ mod tests {
         storage.apply_block(&block, 100).unwrap();
         assert!(storage.has_block(&block.header.hash));
     }
-}
 
+    #[test]
+    fn test_provenance_first_seen() {
+        let storage = ConsensusStorage::new();
+        let hash = create_test_block().header.hash;
+        let peer: std::net::SocketAddr = "10.0.0.1:16111".parse().unwrap();
+
+        assert!(storage.record_first_seen(hash, BlockSource::Peer(peer), 1_000).unwrap());
+        assert!(!storage.record_first_seen(hash, BlockSource::Rpc, 2_000).unwrap());
+        assert_eq!(storage.get_provenance(&hash), Some(BlockProvenance::new(BlockSource::Peer(peer), 1_000)));
+
+        let marked = storage.mark_invalid(&hash).unwrap().unwrap();
+        assert!(marked.invalid);
+        assert!(storage.get_provenance(&hash).unwrap().invalid);
+        assert_eq!(storage.mark_invalid(&Hash::from_u64_word(1)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_init_genesis() {
+        let storage = ConsensusStorage::new();
+        let genesis = create_test_block();
+        storage.init_genesis(&genesis).unwrap();
+        assert!(storage.has_block(&genesis.header.hash));
+        // Restarting on the same genesis is fine
+        storage.init_genesis(&genesis).unwrap();
+        assert_eq!(storage.block_store().block_count(), 1);
+
+        let mut other = genesis.header.clone();
+        other.timestamp += 1;
+        other.finalize();
+        let other = Block::new(other, Vec::new());
+        assert!(matches!(
+            storage.init_genesis(&other),
+            Err(ConsensusError::GenesisMismatch { stored, expected }) if stored == genesis.header.hash && expected == other.header.hash
+        ));
+        assert!(!storage.has_block(&other.header.hash));
+    }
+}
@@ -0,0 +1,123 @@
+//! Per-block fee totals
+//!
+//! This module keeps the fees and mass of recently accepted blocks, which the
+//! fee estimator turns into feerate samples.
+
+use consensus_core::errors::ConsensusError;
+use database::stores::FeeStore as DbFeeStore;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+pub use consensus_core::fees::BlockFeeRecord;
+
+/// Number of blocks kept by default
+pub const DEFAULT_FEE_WINDOW: usize = 1000;
+
+/// Bounded store of the most recent block fee records, each with its
+/// position in acceptance order
+pub struct BlockFeeStore {
+    records: RwLock<VecDeque<(u64, BlockFeeRecord)>>,
+    capacity: usize,
+    db_store: Option<Arc<DbFeeStore>>,
+}
+
+impl BlockFeeStore {
+    pub fn new(capacity: usize) -> Self {
+        Self { records: RwLock::new(VecDeque::with_capacity(capacity)), capacity, db_store: None }
+    }
+
+    /// Store backed by `db_store`, loaded with the newest `capacity` records it holds
+    pub fn new_with_db(capacity: usize, db_store: Arc<DbFeeStore>) -> Self {
+        let mut records = match db_store.get_all() {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("DB get_all fee records error: {}", e);
+                Vec::new()
+            }
+        };
+        // Left over from a larger window
+        let excess = records.len().saturating_sub(capacity);
+        for (seq, _) in records.drain(..excess) {
+            if let Err(e) = db_store.delete(seq) {
+                eprintln!("DB delete fee record error: {}", e);
+            }
+        }
+        let mut loaded = VecDeque::with_capacity(capacity);
+        loaded.extend(records);
+        Self { records: RwLock::new(loaded), capacity, db_store: Some(db_store) }
+    }
+
+    /// Record a block, evicting the oldest record when full
+    pub fn insert(&self, record: BlockFeeRecord) -> Result<(), ConsensusError> {
+        let mut records = self.records.write().unwrap();
+        if records.iter().any(|(_, r)| r.hash == record.hash) {
+            return Ok(());
+        }
+        let seq = records.back().map_or(0, |(seq, _)| seq + 1);
+        let evicted = (records.len() >= self.capacity).then(|| records.front().map(|(seq, _)| *seq)).flatten();
+        if let Some(db) = &self.db_store {
+            db.insert(seq, &record, evicted).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+        }
+        if evicted.is_some() {
+            records.pop_front();
+        }
+        records.push_back((seq, record));
+        Ok(())
+    }
+
+    /// Up to `count` most recent records, newest first
+    pub fn recent(&self, count: usize) -> Vec<BlockFeeRecord> {
+        let records = self.records.read().unwrap();
+        records.iter().rev().take(count).map(|(_, record)| record.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for BlockFeeStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_FEE_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::Hash;
+
+    fn record(n: u64, total_fees: u64, mass: u64) -> BlockFeeRecord {
+        BlockFeeRecord { hash: Hash::from_le_u64([n, 0, 0, 0]), daa_score: n, total_fees, mass }
+    }
+
+    #[test]
+    fn test_recent_is_newest_first_and_bounded() {
+        let store = BlockFeeStore::new(3);
+        for n in 1..=5 {
+            store.insert(record(n, n * 100, 100)).unwrap();
+        }
+        assert_eq!(store.len(), 3);
+        let recent: Vec<u64> = store.recent(10).iter().map(|r| r.daa_score).collect();
+        assert_eq!(recent, vec![5, 4, 3]);
+        assert_eq!(store.recent(1)[0].daa_score, 5);
+    }
+
+    #[test]
+    fn test_duplicate_block_is_ignored() {
+        let store = BlockFeeStore::new(3);
+        store.insert(record(1, 100, 100)).unwrap();
+        store.insert(record(1, 100, 100)).unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_feerate() {
+        assert_eq!(record(1, 500, 250).feerate(), Some(2.0));
+        assert_eq!(record(1, 0, 0).feerate(), None);
+    }
+}
@@ -5,8 +5,10 @@
 pub mod consensus_db;
 pub mod utxo_set;
 pub mod block_store;
+pub mod fee_store;
 
 pub use consensus_db::ConsensusStorage;
-pub use utxo_set::UtxoSet;
+pub use utxo_set::{UtxoChangeListener, UtxoChanges, UtxoCommitmentCheck, UtxoSet};
 pub use block_store::BlockStore;
+pub use fee_store::{BlockFeeRecord, BlockFeeStore};
 
@@ -1,23 +1,69 @@
 //! UTXO set management for consensus
 //!
 //! This module provides UTXO set management including adding, removing,
-//! and querying UTXOs.
+//! and querying UTXOs. A MuHash commitment to the set is updated with every
+//! change and, for a DB-backed set, stored in the same write, so it can later
+//! be checked against the set recomputed from scratch.
 
 use consensus_core::block::Block;
 use consensus_core::tx::{
-    TransactionOutpoint, UtxoEntry,
+    ScriptPublicKey, TransactionOutpoint, UtxoEntry,
 };
 use consensus_core::errors::ConsensusError;
+use consensus_core::muhash::{MuHash, EMPTY_MUHASH};
+use consensus_core::Hash;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use database::stores::IndexStore as DbIndexStore;
 use database::stores::UtxoStore as DbUtxoStore;
 use std::sync::Arc as StdArc;
 
+/// Scripts starting with OP_RETURN can never be spent
+const OP_RETURN: u8 = 0x6a;
+
+/// Amount of `entry` counted as burned
+fn burned(entry: &UtxoEntry) -> u128 {
+    if entry.script_public_key.script().first() == Some(&OP_RETURN) { entry.amount as u128 } else { 0 }
+}
+
+/// Outcome of checking the UTXO set against its commitment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoCommitmentCheck {
+    /// Commitment maintained as the set changed
+    pub stored: MuHash,
+    /// Commitment recomputed over every UTXO
+    pub computed: MuHash,
+    pub utxos: usize,
+}
+
+impl UtxoCommitmentCheck {
+    pub fn is_ok(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+/// UTXOs created and spent by one applied block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtxoChanges {
+    pub block_hash: Hash,
+    pub added: Vec<(TransactionOutpoint, UtxoEntry)>,
+    pub removed: Vec<(TransactionOutpoint, UtxoEntry)>,
+}
+
+/// Called with the changes of every block applied to the set
+pub type UtxoChangeListener = Arc<dyn Fn(&UtxoChanges) + Send + Sync>;
+
 /// UTXO set for consensus storage
 pub struct UtxoSet {
     utxos: Arc<RwLock<HashMap<TransactionOutpoint, UtxoEntry>>>,
     current_daa_score: Arc<RwLock<u64>>,
+    commitment: Arc<RwLock<MuHash>>,
     db_store: Option<StdArc<DbUtxoStore>>,
+    /// Total amount of the set's OP_RETURN outputs, kept as UTXOs come and go
+    burned: Arc<RwLock<u128>>,
+    /// Unspent outpoints by script, kept with the DB-backed set
+    address_index: Option<StdArc<DbIndexStore>>,
+    listeners: Arc<RwLock<Vec<UtxoChangeListener>>>,
 }
 
 impl UtxoSet {
@@ -26,27 +72,80 @@ impl UtxoSet {
         Self {
             utxos: Arc::new(RwLock::new(HashMap::new())),
             current_daa_score: Arc::new(RwLock::new(0)),
+            commitment: Arc::new(RwLock::new(EMPTY_MUHASH)),
             db_store: None,
+            burned: Arc::new(RwLock::new(0)),
+            address_index: None,
+            listeners: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
-    /// Create a new UTXO set backed by a DB-backed UtxoStore
+    /// Create a new UTXO set backed by a DB-backed UtxoStore. A database written
+    /// before commitments were stored gets one computed from its UTXOs.
     pub fn new_with_db(db_store: StdArc<DbUtxoStore>) -> Self {
-        Self {
+        let set = Self {
             utxos: Arc::new(RwLock::new(HashMap::new())),
             current_daa_score: Arc::new(RwLock::new(0)),
-            db_store: Some(db_store),
+            commitment: Arc::new(RwLock::new(EMPTY_MUHASH)),
+            db_store: Some(db_store.clone()),
+            burned: Arc::new(RwLock::new(0)),
+            address_index: None,
+            listeners: Arc::new(RwLock::new(Vec::new())),
+        };
+        match db_store.get_commitment() {
+            Ok(Some(commitment)) => *set.commitment.write().unwrap() = commitment,
+            Ok(None) => {
+                let commitment = set.compute_commitment().0;
+                if let Err(e) = db_store.put_commitment(&commitment) {
+                    eprintln!("DB put_commitment error: {}", e);
+                }
+                *set.commitment.write().unwrap() = commitment;
+            }
+            Err(e) => eprintln!("DB get_commitment error: {}", e),
+        }
+        match db_store.sum_amounts_matching(|e| burned(e) > 0) {
+            Ok(total) => *set.burned.write().unwrap() = total,
+            Err(e) => eprintln!("DB sum_amounts error: {}", e),
         }
+        set
+    }
+
+    /// Maintain the address index of `index_store` as outputs are added and spent
+    pub fn with_address_index(self, index_store: StdArc<DbIndexStore>) -> Self {
+        Self { address_index: Some(index_store), ..self }
+    }
+
+    /// Call `listener` with the changes of each block applied from now on
+    pub fn subscribe_changes(&self, listener: UtxoChangeListener) {
+        self.listeners.write().unwrap().push(listener);
     }
 
     /// Add a UTXO entry
     pub fn add_utxo(&self, outpoint: TransactionOutpoint, entry: UtxoEntry) -> Result<(), ConsensusError> {
+        let mut commitment = self.commitment.write().unwrap();
+        let mut updated = *commitment;
+        updated.add_utxo(&outpoint, &entry);
+        let mut total_burned = self.burned.write().unwrap();
         if let Some(db) = &self.db_store {
-            db.put_utxo(&outpoint, &entry).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            let replaced = db.get_utxo(&outpoint).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            if let Some(replaced) = &replaced {
+                updated.remove_utxo(&outpoint, replaced);
+            }
+            db.put_utxo(&outpoint, &entry, &updated).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            *commitment = updated;
+            *total_burned = *total_burned + burned(&entry) - replaced.as_ref().map_or(0, burned);
+            if let Some(index) = &self.address_index {
+                index.add_unspent(&outpoint, &entry.script_public_key).map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            }
             return Ok(());
         }
         let mut utxos = self.utxos.write().unwrap();
-        utxos.insert(outpoint, entry);
+        *total_burned += burned(&entry);
+        if let Some(replaced) = utxos.insert(outpoint, entry) {
+            updated.remove_utxo(&outpoint, &replaced);
+            *total_burned -= burned(&replaced);
+        }
+        *commitment = updated;
         Ok(())
     }
 
@@ -57,9 +156,21 @@ impl UtxoSet {
             // return the removed UTXO (callers expect Some on success).
             match db.get_utxo(outpoint) {
                 Ok(opt) => {
-                    if opt.is_some() {
-                        if let Err(e) = db.delete_utxo(outpoint) {
-                            eprintln!("DB delete_utxo error: {}", e);
+                    if let Some(entry) = &opt {
+                        let mut commitment = self.commitment.write().unwrap();
+                        let mut updated = *commitment;
+                        updated.remove_utxo(outpoint, entry);
+                        match db.delete_utxo(outpoint, &updated) {
+                            Ok(()) => {
+                                *commitment = updated;
+                                *self.burned.write().unwrap() -= burned(entry);
+                            }
+                            Err(e) => eprintln!("DB delete_utxo error: {}", e),
+                        }
+                        if let Some(index) = &self.address_index {
+                            if let Err(e) = index.remove_unspent(outpoint, &entry.script_public_key) {
+                                eprintln!("DB address index error: {}", e);
+                            }
                         }
                     }
                     return opt;
@@ -70,8 +181,14 @@ impl UtxoSet {
                 }
             }
         }
+        let mut commitment = self.commitment.write().unwrap();
         let mut utxos = self.utxos.write().unwrap();
-        utxos.remove(outpoint)
+        let removed = utxos.remove(outpoint);
+        if let Some(entry) = &removed {
+            commitment.remove_utxo(outpoint, entry);
+            *self.burned.write().unwrap() -= burned(entry);
+        }
+        removed
     }
 
     /// Get a UTXO entry
@@ -104,14 +221,16 @@ impl UtxoSet {
         let mut current_daa_score = self.current_daa_score.write().unwrap();
         *current_daa_score = block_daa_score;
 
+        let mut changes = UtxoChanges { block_hash: block.header.hash, ..UtxoChanges::default() };
         // Process all transactions in the block
         for tx in block.transactions.iter() {
             // Remove inputs (spent UTXOs)
             if !tx.is_coinbase() {
                 for input in &tx.inputs {
                     // If DB-backed, let remove_utxo attempt deletion; otherwise, operate on in-memory map
-                    if self.remove_utxo(&input.previous_outpoint).is_none() {
-                        return Err(ConsensusError::InvalidUtxoReference);
+                    match self.remove_utxo(&input.previous_outpoint) {
+                        Some(entry) => changes.removed.push((input.previous_outpoint, entry)),
+                        None => return Err(ConsensusError::InvalidUtxoReference),
                     }
                 }
             }
@@ -125,10 +244,15 @@ impl UtxoSet {
                     block_daa_score,
                     tx.is_coinbase(),
                 );
-                self.add_utxo(outpoint, entry)?;
+                self.add_utxo(outpoint, entry.clone())?;
+                changes.added.push((outpoint, entry));
             }
         }
 
+        drop(current_daa_score);
+        for listener in self.listeners.read().unwrap().iter() {
+            listener(&changes);
+        }
         Ok(())
     }
 
@@ -167,6 +291,11 @@ impl UtxoSet {
         utxos.values().map(|e| e.amount as u128).sum()
     }
 
+    /// Total amount locked in provably unspendable (OP_RETURN) outputs
+    pub fn burned_amount(&self) -> u128 {
+        *self.burned.read().unwrap()
+    }
+
     /// Get number of UTXOs
     pub fn len(&self) -> usize {
         if let Some(db) = &self.db_store {
@@ -191,6 +320,32 @@ impl UtxoSet {
         utxos.is_empty()
     }
 
+    /// Commitment to the current UTXO set, as maintained through its changes
+    pub fn utxo_commitment(&self) -> MuHash {
+        *self.commitment.read().unwrap()
+    }
+
+    /// Recompute the commitment over the entire UTXO set and compare it with the
+    /// stored one, read back from the database for a DB-backed set
+    pub fn verify_commitment(&self) -> Result<UtxoCommitmentCheck, ConsensusError> {
+        let stored = match &self.db_store {
+            Some(db) => db.get_commitment().map_err(|e| ConsensusError::DatabaseError(e.to_string()))?.unwrap_or(EMPTY_MUHASH),
+            None => self.utxo_commitment(),
+        };
+        let (computed, utxos) = self.compute_commitment();
+        Ok(UtxoCommitmentCheck { stored, computed, utxos })
+    }
+
+    /// Commitment over every UTXO in the set, and their number
+    fn compute_commitment(&self) -> (MuHash, usize) {
+        let mut commitment = EMPTY_MUHASH;
+        let snapshot = self.snapshot();
+        for (outpoint, entry) in &snapshot {
+            commitment.add_utxo(outpoint, entry);
+        }
+        (commitment, snapshot.len())
+    }
+
     /// Get current DAA score
     pub fn current_daa_score(&self) -> u64 {
         let current_daa_score = self.current_daa_score.read().unwrap();
@@ -201,10 +356,39 @@ impl UtxoSet {
     /// Note: This clones all UTXOs, so it should be used sparingly
     /// This allows UtxoSet to be used with validators that require UtxoView trait
     pub fn snapshot(&self) -> std::collections::HashMap<TransactionOutpoint, UtxoEntry> {
-        // If DB-backed, we cannot cheaply clone all UTXOs; fall back to in-memory snapshot
+        if let Some(db) = &self.db_store {
+            match db.entries_matching(|_| true) {
+                Ok(entries) => return entries.into_iter().collect(),
+                Err(e) => eprintln!("DB entries error: {}", e),
+            }
+        }
         let utxos = self.utxos.read().unwrap();
         utxos.clone()
     }
+
+    /// Unspent outputs locked by `script_public_key`
+    pub fn get_utxos_by_script(&self, script_public_key: &ScriptPublicKey) -> Vec<(TransactionOutpoint, UtxoEntry)> {
+        let matches = |e: &UtxoEntry| &e.script_public_key == script_public_key;
+        if let (Some(db), Some(index)) = (&self.db_store, &self.address_index) {
+            match index.unspent_by_script(script_public_key) {
+                Ok(outpoints) => {
+                    return outpoints
+                        .into_iter()
+                        .filter_map(|outpoint| Some((outpoint, db.get_utxo(&outpoint).ok()??)))
+                        .collect()
+                }
+                Err(e) => eprintln!("DB address index error: {}", e),
+            }
+        }
+        if let Some(db) = &self.db_store {
+            match db.entries_matching(matches) {
+                Ok(entries) => return entries,
+                Err(e) => eprintln!("DB entries error: {}", e),
+            }
+        }
+        let utxos = self.utxos.read().unwrap();
+        utxos.iter().filter(|(_, e)| matches(e)).map(|(o, e)| (o.clone(), e.clone())).collect()
+    }
 }
 
 impl Default for UtxoSet {
@@ -255,6 +439,69 @@ mod tests {
         assert_eq!(retrieved.amount, entry.amount);
     }
 
+    #[test]
+    fn test_burned_amount() {
+        let utxo_set = UtxoSet::new();
+        let spendable = UtxoEntry::new(5000, ScriptPublicKey::from_vec(0, vec![0x20, 0xac]), 100, false);
+        let burned = UtxoEntry::new(700, ScriptPublicKey::from_vec(0, vec![OP_RETURN, 0x01]), 100, false);
+        utxo_set.add_utxo(TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0), spendable).unwrap();
+        let burned_outpoint = TransactionOutpoint::new(Hash::from_le_u64([2, 0, 0, 0]), 0);
+        utxo_set.add_utxo(burned_outpoint, burned.clone()).unwrap();
+
+        assert_eq!(utxo_set.burned_amount(), 700);
+        assert_eq!(utxo_set.total_supply(), 5700);
+
+        // Replacing or removing the output takes its amount back out
+        let reburned = UtxoEntry::new(300, ScriptPublicKey::from_vec(0, vec![OP_RETURN]), 101, false);
+        utxo_set.add_utxo(burned_outpoint, reburned).unwrap();
+        assert_eq!(utxo_set.burned_amount(), 300);
+        utxo_set.remove_utxo(&burned_outpoint).unwrap();
+        assert_eq!(utxo_set.burned_amount(), 0);
+        utxo_set.add_utxo(burned_outpoint, burned).unwrap();
+        assert_eq!(utxo_set.burned_amount(), 700);
+    }
+
+    #[test]
+    fn test_get_utxos_by_script() {
+        let utxo_set = UtxoSet::new();
+        let mine = ScriptPublicKey::from_vec(0, vec![0x20, 0xac]);
+        let outpoint = TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0);
+        utxo_set.add_utxo(outpoint, UtxoEntry::new(5000, mine.clone(), 100, false)).unwrap();
+        utxo_set
+            .add_utxo(
+                TransactionOutpoint::new(Hash::from_le_u64([2, 0, 0, 0]), 0),
+                UtxoEntry::new(700, ScriptPublicKey::from_vec(0, vec![0x21, 0xac]), 100, false),
+            )
+            .unwrap();
+
+        let utxos = utxo_set.get_utxos_by_script(&mine);
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].0, outpoint);
+        assert_eq!(utxos[0].1.amount, 5000);
+    }
+
+    #[test]
+    fn test_commitment_diverges_from_mutated_utxo() {
+        let utxo_set = UtxoSet::new();
+        let outpoints: Vec<_> = (1..=3).map(|n| TransactionOutpoint::new(Hash::from_le_u64([n, 0, 0, 0]), 0)).collect();
+        for (i, outpoint) in outpoints.iter().enumerate() {
+            let entry = UtxoEntry::new(1000 * (i as u64 + 1), ScriptPublicKey::from_vec(0, vec![0x20, 0xac]), 100, false);
+            utxo_set.add_utxo(*outpoint, entry).unwrap();
+        }
+        utxo_set.remove_utxo(&outpoints[0]).unwrap();
+
+        let check = utxo_set.verify_commitment().unwrap();
+        assert!(check.is_ok());
+        assert_eq!(check.utxos, 2);
+        assert_ne!(check.stored, EMPTY_MUHASH);
+
+        // Change one amount behind the set's back
+        utxo_set.utxos.write().unwrap().get_mut(&outpoints[1]).unwrap().amount += 1;
+        let check = utxo_set.verify_commitment().unwrap();
+        assert!(!check.is_ok());
+        assert_eq!(check.stored, utxo_set.utxo_commitment());
+    }
+
     #[test]
     fn test_apply_block() {
         let utxo_set = UtxoSet::new();
@@ -275,5 +522,38 @@ mod tests {
         utxo_set.apply_block(&block, 100).unwrap();
         assert_eq!(utxo_set.len(), 1);
     }
+
+    #[test]
+    fn test_apply_block_reports_changes() {
+        use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+        use consensus_core::tx::TransactionInput;
+
+        let utxo_set = UtxoSet::new();
+        let reported = Arc::new(RwLock::new(Vec::new()));
+        let sink = reported.clone();
+        utxo_set.subscribe_changes(Arc::new(move |changes: &UtxoChanges| sink.write().unwrap().push(changes.clone())));
+
+        let script = ScriptPublicKey::from_vec(0, vec![1]);
+        let coinbase = Transaction::new(1, Vec::new(), vec![TransactionOutput::new(50, script.clone())], 0, SUBNETWORK_ID_COINBASE, 0, Vec::new());
+        let created = TransactionOutpoint::new(coinbase.id(), 0);
+        let first = create_test_block(vec![coinbase]);
+        utxo_set.apply_block(&first, 100).unwrap();
+
+        let input = TransactionInput::new(created, Vec::new(), 0, 0);
+        let spend = Transaction::new(1, vec![input], vec![TransactionOutput::new(40, script.clone())], 0, SUBNETWORK_ID_NATIVE, 0, Vec::new());
+        let spent_to = TransactionOutpoint::new(spend.id(), 0);
+        let mut second = create_test_block(vec![spend]);
+        second.header.timestamp += 1;
+        second.header.finalize();
+        utxo_set.apply_block(&second, 101).unwrap();
+
+        let reported = reported.read().unwrap();
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[0].block_hash, first.header.hash);
+        assert_eq!(reported[0].added, vec![(created, UtxoEntry::new(50, script.clone(), 100, true))]);
+        assert!(reported[0].removed.is_empty());
+        assert_eq!(reported[1].removed, vec![(created, UtxoEntry::new(50, script.clone(), 100, true))]);
+        assert_eq!(reported[1].added, vec![(spent_to, UtxoEntry::new(40, script, 101, false))]);
+    }
 }
 
@@ -2,7 +2,13 @@
 //!
 //! This module defines types used throughout the consensus module.
 
+use consensus_core::block::Block;
+use consensus_core::config::genesis;
+use consensus_core::constants::SUBSIDY_HALVING_INTERVAL;
+use consensus_core::network::NetworkType;
 use consensus_core::Hash;
+use crate::process::coinbase::INITIAL_BLOCK_REWARD_SOMPI;
+use std::collections::BTreeMap;
 
 /// Block status in the consensus pipeline
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -69,6 +75,8 @@ impl BlockProcessingResult {
 /// Consensus configuration
 #[derive(Debug, Clone)]
 pub struct ConsensusConfig {
+    /// Network whose genesis the DAG is rooted at
+    pub network: NetworkType,
     /// GHOSTDAG K parameter
     pub ghostdag_k: u32,
     /// Maximum number of parents per block
@@ -81,18 +89,35 @@ pub struct ConsensusConfig {
     pub max_block_size: u64,
     /// Coinbase maturity (blocks)
     pub coinbase_maturity: u64,
+    /// Block subsidy before the first halving, in sompi
+    pub initial_subsidy: u64,
+    /// Blocks between halvings of the subsidy; at least 1
+    pub subsidy_halving_interval: u64,
+    /// Hashes the blocks at these DAA scores must have
+    pub checkpoints: BTreeMap<u64, Hash>,
 }
 
 impl Default for ConsensusConfig {
     fn default() -> Self {
         Self {
+            network: NetworkType::Mainnet,
             ghostdag_k: 18,
             max_block_parents: 10,
             target_time_per_block: 1,
             difficulty_window_size: 2641,
             max_block_size: 1_000_000,
             coinbase_maturity: 100,
+            initial_subsidy: INITIAL_BLOCK_REWARD_SOMPI,
+            subsidy_halving_interval: SUBSIDY_HALVING_INTERVAL,
+            checkpoints: BTreeMap::new(),
         }
     }
 }
 
+impl ConsensusConfig {
+    /// The genesis block of the network, with its coinbase
+    pub fn genesis_block(&self) -> Block {
+        (&genesis::genesis(self.network)).into()
+    }
+}
+
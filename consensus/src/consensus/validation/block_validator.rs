@@ -18,6 +18,9 @@ use std::sync::Arc;
 pub struct BlockValidator {
     header_validator: Arc<HeaderValidator>,
     transaction_validator: Arc<TransactionValidator>,
+    max_block_mass: u64,
+    /// Gas the transactions of experimental subnetworks may use per block
+    max_block_gas: u64,
 }
 
 impl BlockValidator {
@@ -29,9 +32,26 @@ impl BlockValidator {
         Self {
             header_validator,
             transaction_validator,
+            max_block_mass: MAX_BLOCK_MASS,
+            max_block_gas: 0,
         }
     }
 
+    /// Refuse blocks heavier than `max_block_mass`
+    pub fn with_max_block_mass(self, max_block_mass: u64) -> Self {
+        Self { max_block_mass, ..self }
+    }
+
+    /// Refuse blocks whose transactions use more than `max_block_gas` in total
+    pub fn with_max_block_gas(self, max_block_gas: u64) -> Self {
+        Self { max_block_gas, ..self }
+    }
+
+    /// Validator used for the block's header
+    pub fn header_validator(&self) -> &Arc<HeaderValidator> {
+        &self.header_validator
+    }
+
     /// Validate complete block
     pub fn validate_block(&self, block: &Block) -> Result<(), ConsensusError> {
         self.validate_block_internal(block, true)
@@ -70,9 +90,15 @@ impl BlockValidator {
             self.transaction_validator.validate_transaction(tx)?;
         }
 
+        // Gas of experimental subnetwork transactions is bounded per block
+        let gas = block.transactions.iter().fold(0u64, |gas, tx| gas.saturating_add(tx.gas));
+        if gas > self.max_block_gas {
+            return Err(ConsensusError::ExceedsMaxBlockGas { gas, limit: self.max_block_gas });
+        }
+
         // Validate block mass
         let mass = block.calculate_mass();
-        if mass > MAX_BLOCK_MASS {
+        if mass > self.max_block_mass {
             return Err(ConsensusError::ExceedsMaxBlockMass);
         }
 
@@ -232,5 +258,28 @@ mod tests {
         let result = block_validator.validate_coinbase(&block);
         assert!(result.is_err());
     }
-}
 
+    #[test]
+    fn test_block_gas_is_bounded() {
+        use consensus_core::subnets::{SubnetworkId, SUBNETWORK_ID_COINBASE};
+        use consensus_core::tx::{TransactionInput, TransactionOutpoint};
+        use consensus_core::Hash;
+        let script = ScriptPublicKey::from_vec(0, Vec::new());
+        let coinbase = Transaction::new(1, Vec::new(), vec![TransactionOutput::new(5000000000, script.clone())], 0, SUBNETWORK_ID_COINBASE, 0, Vec::new());
+        let experimental = |n: u64, gas| {
+            let input = TransactionInput::new(TransactionOutpoint::new(Hash::from_le_u64([n, 0, 0, 0]), 0), Vec::new(), 0, 0);
+            Transaction::new(1, vec![input], vec![TransactionOutput::new(1000, script.clone())], 0, SubnetworkId::from(2u64), gas, Vec::new())
+        };
+        let block = create_test_block(vec![coinbase, experimental(1, 600), experimental(2, 600)]);
+        let tx_validator = Arc::new(TransactionValidator::new().with_experimental_subnetworks(true));
+        let validator = |max_block_gas| BlockValidator::new(Arc::new(HeaderValidator::new()), tx_validator.clone()).with_max_block_gas(max_block_gas);
+
+        assert!(matches!(
+            validator(1000).validate_block_without_pow(&block),
+            Err(ConsensusError::ExceedsMaxBlockGas { gas: 1200, limit: 1000 })
+        ));
+        assert!(validator(1200).validate_block_without_pow(&block).is_ok());
+        // Without a gas limit no gas is allowed at all
+        assert!(validator(0).validate_block_without_pow(&block).is_err());
+    }
+}
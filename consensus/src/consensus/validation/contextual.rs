@@ -5,10 +5,12 @@
 //! dependency checks.
 
 use consensus_core::block::Block;
-use consensus_core::tx::Transaction;
+use consensus_core::config::params::Params;
+use consensus_core::tx::{PopulatedTransaction, Transaction};
 use consensus_core::errors::ConsensusError;
-use consensus_core::constants::COINBASE_MATURITY;
+use consensus_core::mass::MassCalculator;
 use super::block_validator::BlockValidator;
+use super::signature::{transaction_signature_checks, verify_batch};
 use super::transaction_validator::{TransactionValidator, UtxoView};
 use std::sync::Arc;
 
@@ -16,6 +18,11 @@ use std::sync::Arc;
 pub struct ContextualValidator {
     block_validator: Arc<BlockValidator>,
     transaction_validator: Arc<TransactionValidator>,
+    mass_calculator: MassCalculator,
+    /// DAA score from which storage mass commitments are checked
+    storage_mass_activation: Option<u64>,
+    /// Threads verifying the signatures of a block, if they are checked
+    signature_threads: Option<usize>,
 }
 
 impl ContextualValidator {
@@ -27,6 +34,25 @@ impl ContextualValidator {
         Self {
             block_validator,
             transaction_validator,
+            mass_calculator: MassCalculator::new_with_consensus_params(&Params::default()),
+            storage_mass_activation: None,
+            signature_threads: None,
+        }
+    }
+
+    /// Check the signatures of inputs spending key locked outputs, verifying
+    /// those of a block in batches on up to `threads` threads
+    pub fn with_signature_verification(self, threads: usize) -> Self {
+        Self { signature_threads: Some(threads.max(1)), ..self }
+    }
+
+    /// Compute storage masses with the parameters of `params` and enforce
+    /// commitments to them from its activation on
+    pub fn with_params(self, params: &Params) -> Self {
+        Self {
+            mass_calculator: MassCalculator::new_with_consensus_params(params),
+            storage_mass_activation: params.storage_mass_activation,
+            ..self
         }
     }
 
@@ -40,8 +66,9 @@ impl ContextualValidator {
         // First do context-free validation
         self.block_validator.validate_block(block)?;
 
-        // Validate all transactions with UTXO context
+        // Validate all transactions with UTXO context, collecting their signatures
         let mut total_fees = 0u64;
+        let mut signatures = Vec::new();
         for (idx, tx) in block.transactions.iter().enumerate() {
             if idx == 0 {
                 // Coinbase doesn't need UTXO validation
@@ -51,12 +78,49 @@ impl ContextualValidator {
             let fee = self
                 .transaction_validator
                 .validate_transaction_with_utxo(tx, utxo_view, current_daa_score)?;
+            self.validate_storage_mass(tx, utxo_view, current_daa_score)?;
+            if self.signature_threads.is_some() {
+                signatures.extend(transaction_signature_checks(tx, utxo_view)?);
+            }
             total_fees += fee;
         }
+        if let Some(threads) = self.signature_threads {
+            verify_batch(&signatures, threads)?;
+        }
 
         Ok(total_fees)
     }
 
+    /// Validate that `tx` commits to at least the storage mass computed from the
+    /// entries it spends, once the commitment is active at `current_daa_score`
+    pub fn validate_storage_mass(
+        &self,
+        tx: &Transaction,
+        utxo_view: &dyn UtxoView,
+        current_daa_score: u64,
+    ) -> Result<(), ConsensusError> {
+        if tx.is_coinbase() || !self.storage_mass_activation.is_some_and(|activation| current_daa_score >= activation) {
+            return Ok(());
+        }
+
+        let entries = tx
+            .inputs
+            .iter()
+            .map(|input| utxo_view.get(&input.previous_outpoint).cloned().ok_or(ConsensusError::InvalidUtxoReference))
+            .collect::<Result<Vec<_>, _>>()?;
+        // A mass too high to compute is over any limit
+        let expected = self
+            .mass_calculator
+            .calc_contextual_masses(&PopulatedTransaction::new(tx, entries))
+            .ok_or(ConsensusError::StorageMassTooHigh)?
+            .storage_mass;
+        if tx.mass() < expected {
+            return Err(ConsensusError::InvalidStorageMass { committed: tx.mass(), expected });
+        }
+
+        Ok(())
+    }
+
     /// Validate transaction dependencies
     pub fn validate_transaction_dependencies(
         &self,
@@ -94,7 +158,7 @@ impl ContextualValidator {
             if let Some(utxo) = utxo_view.get(&input.previous_outpoint) {
                 if utxo.is_coinbase {
                     let maturity_age = current_daa_score.saturating_sub(utxo.block_daa_score);
-                    if maturity_age < COINBASE_MATURITY {
+                    if maturity_age < self.transaction_validator.coinbase_maturity() {
                         return Err(ConsensusError::InvalidUtxoReference);
                     }
                 }
@@ -208,5 +272,54 @@ mod tests {
         let result = contextual_validator.validate_coinbase_maturity(&tx, &utxo_view, 250);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_validate_storage_mass_commitment() {
+        use consensus_core::constants::STORAGE_MASS_PARAMETER;
+        use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+        use consensus_core::tx::{TransactionInput, TransactionOutput, ScriptPublicKey};
+        use consensus_core::Hash;
+        use crate::consensus::validation::header_validator::HeaderValidator;
+
+        let tx_validator = Arc::new(TransactionValidator::new());
+        let block_validator = Arc::new(BlockValidator::new(Arc::new(HeaderValidator::new()), tx_validator.clone()));
+        let params = Params { storage_mass_parameter: STORAGE_MASS_PARAMETER, storage_mass_activation: Some(1000), ..Default::default() };
+        let contextual_validator = ContextualValidator::new(block_validator, tx_validator).with_params(&params);
+
+        let mut utxo_view = TestUtxoView::new();
+        let outpoint = TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0);
+        utxo_view.add_utxo(outpoint, UtxoEntry::new(100, ScriptPublicKey::default(), 10, false));
+        let spend = |mass: u64| {
+            Transaction::new(
+                1,
+                vec![TransactionInput::new(outpoint, Vec::new(), 0, 1)],
+                vec![TransactionOutput::new(40, ScriptPublicKey::default()), TransactionOutput::new(50, ScriptPublicKey::default())],
+                0,
+                SUBNETWORK_ID_NATIVE,
+                0,
+                Vec::new(),
+            )
+            .with_mass(mass)
+        };
+
+        // C/40 + C/50 - C/100 with C = 100; committing to more is allowed
+        let committed = spend(3);
+        assert!(contextual_validator.validate_storage_mass(&committed, &utxo_view, 1000).is_ok());
+        assert!(contextual_validator.validate_storage_mass(&spend(4), &utxo_view, 1000).is_ok());
+
+        let mismatched = spend(2);
+        assert!(matches!(
+            contextual_validator.validate_storage_mass(&mismatched, &utxo_view, 1000),
+            Err(ConsensusError::InvalidStorageMass { committed: 2, expected: 3 })
+        ));
+
+        // Before activation the commitment isn't checked
+        assert!(contextual_validator.validate_storage_mass(&mismatched, &utxo_view, 999).is_ok());
+        let inactive = ContextualValidator::new(
+            Arc::new(BlockValidator::new(Arc::new(HeaderValidator::new()), Arc::new(TransactionValidator::new()))),
+            Arc::new(TransactionValidator::new()),
+        );
+        assert!(inactive.validate_storage_mass(&mismatched, &utxo_view, 1000).is_ok());
+    }
 }
 
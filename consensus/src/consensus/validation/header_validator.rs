@@ -5,25 +5,38 @@
 //! - Timestamp validation
 //! - Parent existence checks
 //! - Merkle root verification
+//! - Checkpoints and blocks far below the pruning point
 
 use consensus_core::header::Header;
 use consensus_core::Hash;
 use consensus_core::errors::ConsensusError;
 use consensus_core::constants::BLOCK_VERSION;
-use consensus_core::hashing::header::validate_pow;
-use std::collections::HashSet;
-use std::time::{SystemTime, UNIX_EPOCH};
+use consensus_core::hashing::header::{calculate_pow_hash, validate_pow};
+use consensus_core::BlockLevel;
+use consensus_pow::calc_level_from_pow;
+use primitive_types::U256;
+use crate::consensus::ghostdag::GhostdagData;
+use consensus_core::time::{Clock, SystemClock};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
 
 /// Maximum number of parents per block
 pub const MAX_BLOCK_PARENTS: usize = 10;
 
+/// Highest block level; a header's level grows with the zero bits leading its proof of work hash
+pub const MAX_BLOCK_LEVEL: BlockLevel = 225;
+
 /// Maximum timestamp future offset (2 hours in milliseconds)
 pub const MAX_TIMESTAMP_FUTURE_OFFSET: u64 = 2 * 3600 * 1000;
 
 /// Header validator for consensus rules
 pub struct HeaderValidator {
     max_block_parents: usize,
+    max_block_level: BlockLevel,
     max_timestamp_future_offset: u64,
+    clock: Arc<dyn Clock>,
+    checkpoints: BTreeMap<u64, Hash>,
+    pruning_depth: Option<u64>,
 }
 
 impl HeaderValidator {
@@ -31,7 +44,11 @@ impl HeaderValidator {
     pub fn new() -> Self {
         Self {
             max_block_parents: MAX_BLOCK_PARENTS,
+            max_block_level: MAX_BLOCK_LEVEL,
             max_timestamp_future_offset: MAX_TIMESTAMP_FUTURE_OFFSET,
+            clock: Arc::new(SystemClock),
+            checkpoints: BTreeMap::new(),
+            pruning_depth: None,
         }
     }
 
@@ -39,10 +56,41 @@ impl HeaderValidator {
     pub fn with_params(max_block_parents: usize, max_timestamp_future_offset: u64) -> Self {
         Self {
             max_block_parents,
+            max_block_level: MAX_BLOCK_LEVEL,
             max_timestamp_future_offset,
+            clock: Arc::new(SystemClock),
+            checkpoints: BTreeMap::new(),
+            pruning_depth: None,
         }
     }
 
+    /// Reject headers with more than `max_block_parents` direct parents
+    pub fn with_max_block_parents(self, max_block_parents: usize) -> Self {
+        Self { max_block_parents, ..self }
+    }
+
+    /// Compute block levels up to `max_block_level`
+    pub fn with_max_block_level(self, max_block_level: BlockLevel) -> Self {
+        Self { max_block_level, ..self }
+    }
+
+    /// Judge how far in the future timestamps are by `clock`
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Require chains passing the DAA scores of `checkpoints` to merge the
+    /// checkpoint blocks; see [`Self::check_checkpoints`]
+    pub fn with_checkpoints(self, checkpoints: BTreeMap<u64, Hash>) -> Self {
+        Self { checkpoints, ..self }
+    }
+
+    /// Reject blocks more than `pruning_depth` below the pruning point, itself
+    /// `pruning_depth` below the selected tip; see [`Self::check_depth`]
+    pub fn with_pruning_depth(self, pruning_depth: u64) -> Self {
+        Self { pruning_depth: Some(pruning_depth), ..self }
+    }
+
     /// Validate header with context-free checks
     pub fn validate_header(&self, header: &Header) -> Result<(), ConsensusError> {
         self.validate_header_internal(header, true)
@@ -61,23 +109,12 @@ impl HeaderValidator {
             return Err(ConsensusError::InvalidBlockVersion);
         }
 
-        // Check parents count
-        let direct_parents = header.direct_parents();
-        if !direct_parents.is_empty() && direct_parents.len() > self.max_block_parents {
-            return Err(ConsensusError::InvalidBlockParent);
-        }
-
-        // Check no duplicate parents
-        let parent_set: HashSet<Hash> = direct_parents.iter().copied().collect();
-        if parent_set.len() != direct_parents.len() {
-            return Err(ConsensusError::InvalidBlockParent);
-        }
+        // Check parents count and no duplicate parents
+        self.check_parents(header)?;
+        self.check_parent_levels(header)?;
 
         // Check timestamp is reasonable (not too far in future)
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        let now = self.clock.now_millis();
         if header.timestamp > now + self.max_timestamp_future_offset {
             return Err(ConsensusError::InvalidTimestamp);
         }
@@ -123,6 +160,62 @@ impl HeaderValidator {
         Ok(())
     }
 
+    /// Check the header's blue score, blue work and DAA score against the
+    /// GHOSTDAG data computed for it from its parents. There is no DAA window
+    /// yet, so a block's DAA score is its blue score.
+    pub fn validate_ghostdag_commitments(&self, header: &Header, ghostdag_data: &GhostdagData) -> Result<(), ConsensusError> {
+        if header.blue_score != ghostdag_data.blue_score {
+            return Err(ConsensusError::InvalidBlueScore { declared: header.blue_score, expected: ghostdag_data.blue_score });
+        }
+        if header.blue_work != ghostdag_data.blue_work {
+            return Err(ConsensusError::InvalidBlueWork { declared: header.blue_work, expected: ghostdag_data.blue_work });
+        }
+        if header.daa_score != ghostdag_data.blue_score {
+            return Err(ConsensusError::InvalidDaaScore { declared: header.daa_score, expected: ghostdag_data.blue_score });
+        }
+        Ok(())
+    }
+
+    /// Check that the header declares the bits its selected parent's
+    /// difficulty window gives, see [`crate::consensus::difficulty::DifficultyManager::expected_bits`]
+    pub fn check_difficulty(&self, header: &Header, expected_bits: u32) -> Result<(), ConsensusError> {
+        if header.bits != expected_bits {
+            return Err(ConsensusError::WrongDifficulty { declared: header.bits, expected: expected_bits });
+        }
+        Ok(())
+    }
+
+    /// Check that a header whose selected chain passes a checkpoint's DAA score,
+    /// its selected parent being at or below that score, has the checkpoint block
+    /// as selected parent or in its mergeset. Every chain through the header then
+    /// has the checkpoint in its past, while other blocks at the checkpoint's
+    /// score are accepted beside it.
+    pub fn check_checkpoints(&self, header: &Header, ghostdag_data: &GhostdagData, selected_parent_daa_score: u64) -> Result<(), ConsensusError> {
+        if header.daa_score <= selected_parent_daa_score {
+            return Ok(());
+        }
+        for (&daa_score, &expected) in self.checkpoints.range(selected_parent_daa_score..header.daa_score) {
+            if !ghostdag_data.mergeset_blues.contains(&expected) && !ghostdag_data.red_set.contains(&expected) {
+                return Err(ConsensusError::CheckpointMismatch { daa_score, expected, found: header.hash });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the header is not far below the pruning point, given the blue
+    /// score of the selected tip. Such a block can't change the selected chain
+    /// and only serves rewriting old history.
+    pub fn check_depth(&self, header: &Header, tip_blue_score: u64) -> Result<(), ConsensusError> {
+        let Some(pruning_depth) = self.pruning_depth else {
+            return Ok(());
+        };
+        let pruning_point_daa_score = tip_blue_score.saturating_sub(pruning_depth);
+        if header.daa_score.saturating_add(pruning_depth) < pruning_point_daa_score {
+            return Err(ConsensusError::BlockTooOld { daa_score: header.daa_score, pruning_point_daa_score });
+        }
+        Ok(())
+    }
+
     /// Check proof of work
     pub fn check_pow(&self, header: &Header) -> Result<(), ConsensusError> {
         if validate_pow(header) {
@@ -135,10 +228,7 @@ impl HeaderValidator {
     /// Check timestamp validity
     pub fn check_timestamp(&self, header: &Header, parents: Option<&[Header]>) -> Result<(), ConsensusError> {
         // Check not too far in future
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        let now = self.clock.now_millis();
         if header.timestamp > now + self.max_timestamp_future_offset {
             return Err(ConsensusError::InvalidTimestamp);
         }
@@ -162,15 +252,63 @@ impl HeaderValidator {
         
         // Check count in valid range (0 is allowed for genesis)
         if direct_parents.len() > self.max_block_parents {
-            return Err(ConsensusError::InvalidBlockParent);
+            return Err(ConsensusError::TooManyParents { count: direct_parents.len(), limit: self.max_block_parents });
         }
 
-        // Check no duplicates
-        let parent_set: HashSet<Hash> = direct_parents.iter().copied().collect();
-        if parent_set.len() != direct_parents.len() {
-            return Err(ConsensusError::InvalidBlockParent);
+        // No parent twice, within a level or across levels, and not the header itself
+        let mut parent_set = HashSet::new();
+        for parent in header.parents_by_level.iter().flatten() {
+            if *parent == header.hash {
+                return Err(ConsensusError::SelfParent);
+            }
+            if !parent_set.insert(*parent) {
+                return Err(ConsensusError::DuplicateParent(*parent));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Level of the block, from its proof of work hash. A block without
+    /// parents, the genesis, has the highest level.
+    pub fn block_level(&self, header: &Header) -> BlockLevel {
+        if header.direct_parents().is_empty() {
+            return self.max_block_level;
         }
+        calc_level_from_pow(U256::from_big_endian(calculate_pow_hash(header).as_bytes()), self.max_block_level)
+    }
+
+    /// Check the level structure of the parents: no empty level, and no levels
+    /// above the header's own
+    pub fn check_parent_levels(&self, header: &Header) -> Result<(), ConsensusError> {
+        let levels = &header.parents_by_level;
+        // Only the genesis has no parents, and then no levels beyond an empty first one
+        if let Some(level) = levels.iter().position(Vec::is_empty).filter(|&level| level > 0 || levels.len() > 1) {
+            return Err(ConsensusError::EmptyParentLevel(level));
+        }
+        let block_level = self.block_level(header);
+        if levels.len() > block_level as usize + 1 {
+            return Err(ConsensusError::TooManyParentLevels { levels: levels.len(), block_level });
+        }
+        Ok(())
+    }
 
+    /// Check that every parent reaches the level it is listed at, looking
+    /// parents up by `parent_header`; parents not found are left to the caller
+    pub fn check_parents_reach_levels(
+        &self,
+        header: &Header,
+        parent_header: impl Fn(&Hash) -> Option<Header>,
+    ) -> Result<(), ConsensusError> {
+        for (level, parents) in header.parents_by_level.iter().enumerate().skip(1) {
+            for parent in parents {
+                let Some(parent_header) = parent_header(parent) else { continue };
+                let parent_level = self.block_level(&parent_header);
+                if (parent_level as usize) < level {
+                    return Err(ConsensusError::ParentBelowLevel { parent: *parent, level, parent_level });
+                }
+            }
+        }
         Ok(())
     }
 
@@ -241,6 +379,18 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_configured_parent_limit() {
+        let validator = HeaderValidator::new().with_max_block_parents(3);
+        let parents = |count: u64| (1..=count).map(|i| Hash::from_le_u64([i, 0, 0, 0])).collect::<Vec<_>>();
+        let hash = Hash::from_le_u64([9, 0, 0, 0]);
+        assert!(validator.validate_header_without_pow(&create_test_header(hash, parents(3), 1000, 0x1f00ffff)).is_ok());
+        assert!(matches!(
+            validator.validate_header_without_pow(&create_test_header(hash, parents(4), 1000, 0x1f00ffff)),
+            Err(ConsensusError::TooManyParents { count: 4, limit: 3 })
+        ));
+    }
+
     #[test]
     fn test_duplicate_parents_fails() {
         let validator = HeaderValidator::new();
@@ -249,6 +399,73 @@ mod tests {
         let header = create_test_header(hash, vec![parent, parent], 1000, 0x1f00ffff);
         let result = validator.check_parents(&header);
         assert!(result.is_err());
+
+        // Repeated in a higher level
+        let mut header = create_test_header(hash, vec![parent], 1000, 0x1f00ffff);
+        header.parents_by_level.push(vec![Hash::from_le_u64([3, 0, 0, 0]), parent]);
+        assert!(matches!(validator.validate_header_without_pow(&header), Err(ConsensusError::DuplicateParent(p)) if p == parent));
+        header.parents_by_level[1].pop();
+        assert!(validator.check_parents(&header).is_ok());
+    }
+
+    /// Header on `parents_by_level` whose block level is at least `level` under `validator`
+    fn header_at_level(validator: &HeaderValidator, parents_by_level: Vec<Vec<Hash>>, level: BlockLevel) -> Header {
+        let mut header = create_test_header(ZERO_HASH, Vec::new(), 1000, 0x1f00ffff);
+        header.parents_by_level = parents_by_level;
+        (0u64..)
+            .map(|nonce| {
+                header.nonce = nonce;
+                header.hash = consensus_core::hashing::header::calculate_header_hash(&header);
+                header.clone()
+            })
+            .find(|header| validator.block_level(header) >= level)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parent_levels() {
+        // The highest level makes levels 1 and 2 reachable by a few leading zero bits
+        let validator = HeaderValidator::new().with_max_block_level(BlockLevel::MAX);
+        let hash = |n: u64| Hash::from_le_u64([n, 0, 0, 0]);
+        let high = header_at_level(&validator, vec![vec![hash(1)]], 2);
+        let low = (0u64..)
+            .map(|n| create_test_header(ZERO_HASH, vec![hash(100 + n)], 1000, 0x1f00ffff))
+            .find(|header| validator.block_level(header) == 0)
+            .unwrap();
+        let parents = |hash: &Hash| [&high, &low].into_iter().find(|header| header.hash == *hash).cloned();
+
+        // Consistent: a level 2 header lists a level 2 parent at levels 0 to 2
+        let header = header_at_level(&validator, vec![vec![low.hash], vec![high.hash], vec![hash(7)]], 2);
+        assert!(validator.validate_header_without_pow(&header).is_ok());
+        assert!(validator.check_parents_reach_levels(&header, parents).is_ok());
+
+        // A level 0 parent listed at level 1
+        let header = header_at_level(&validator, vec![vec![high.hash], vec![low.hash]], 1);
+        assert!(matches!(
+            validator.check_parents_reach_levels(&header, parents),
+            Err(ConsensusError::ParentBelowLevel { parent, level: 1, parent_level: 0 }) if parent == low.hash
+        ));
+
+        // More levels than the header's own level, which is always 0 when capped there
+        let capped = HeaderValidator::new().with_max_block_level(0);
+        let mut header = low.clone();
+        header.parents_by_level.push(vec![hash(8)]);
+        assert!(matches!(capped.check_parent_levels(&header), Err(ConsensusError::TooManyParentLevels { levels: 2, block_level: 0 })));
+
+        // Empty levels
+        let header = header_at_level(&validator, vec![vec![hash(9)], vec![]], 1);
+        assert!(matches!(validator.validate_header_without_pow(&header), Err(ConsensusError::EmptyParentLevel(1))));
+        let mut header = high.clone();
+        header.parents_by_level = vec![vec![], vec![hash(9)]];
+        assert!(matches!(validator.check_parent_levels(&header), Err(ConsensusError::EmptyParentLevel(0))));
+    }
+
+    #[test]
+    fn test_self_parent_fails() {
+        let validator = HeaderValidator::new();
+        let mut header = create_test_header(Hash::from_le_u64([1, 0, 0, 0]), vec![Hash::from_le_u64([2, 0, 0, 0])], 1000, 0x1f00ffff);
+        header.parents_by_level[0].push(header.hash);
+        assert!(matches!(validator.validate_header_without_pow(&header), Err(ConsensusError::SelfParent)));
     }
 
     #[test]
@@ -262,5 +479,121 @@ mod tests {
         let median = validator.median_timestamp(&headers);
         assert_eq!(median, 2000);
     }
-}
 
+    #[test]
+    fn test_future_timestamps_are_judged_by_the_adjusted_clock() {
+        use consensus_core::time::{MockClock, NetworkAdjustedClock, DEFAULT_MAX_TIME_OFFSET, MIN_TIME_SAMPLES};
+
+        let now = 1_700_000_000_000;
+        let local = Arc::new(MockClock::new(now));
+        let clock = Arc::new(NetworkAdjustedClock::new(local.clone()));
+        let validator = HeaderValidator::new().with_clock(clock.clone());
+        let at = |timestamp| create_test_header(Hash::from_le_u64([1, 0, 0, 0]), vec![], timestamp, 0x1f00ffff);
+
+        assert!(validator.check_timestamp(&at(now + MAX_TIMESTAMP_FUTURE_OFFSET), None).is_ok());
+        assert!(validator.check_timestamp(&at(now + MAX_TIMESTAMP_FUTURE_OFFSET + 1), None).is_err());
+
+        // Peers five hours ahead move our clock by the maximum offset only
+        for n in 0..MIN_TIME_SAMPLES as u8 {
+            let skewed = clock.add_sample(std::net::IpAddr::from([10, 0, 0, n]), now + 5 * 3600 * 1000);
+            assert_eq!(skewed.is_some(), n as usize + 1 == MIN_TIME_SAMPLES);
+        }
+        let limit = now + DEFAULT_MAX_TIME_OFFSET + MAX_TIMESTAMP_FUTURE_OFFSET;
+        assert!(validator.check_timestamp(&at(limit), None).is_ok());
+        assert!(validator.check_timestamp(&at(limit + 1), None).is_err());
+
+        local.advance(1000);
+        assert!(validator.check_timestamp(&at(limit + 1000), None).is_ok());
+    }
+
+    #[test]
+    fn test_ghostdag_commitments() {
+        let validator = HeaderValidator::new();
+        let mut header = create_test_header(ZERO_HASH, vec![Hash::from_le_u64([1, 0, 0, 0])], 1000, 0x207fffff);
+        let mut data = GhostdagData::new(Hash::from_le_u64([1, 0, 0, 0]));
+        data.blue_score = 3;
+        data.blue_work = BlueWorkType::from(500u64);
+
+        header.blue_score = 3;
+        header.daa_score = 3;
+        header.blue_work = BlueWorkType::from(500u64);
+        assert!(validator.validate_ghostdag_commitments(&header, &data).is_ok());
+
+        header.blue_work = BlueWorkType::from(501u64);
+        assert!(matches!(validator.validate_ghostdag_commitments(&header, &data), Err(ConsensusError::InvalidBlueWork { .. })));
+        header.blue_work = data.blue_work;
+        header.blue_score = 4;
+        assert!(matches!(
+            validator.validate_ghostdag_commitments(&header, &data),
+            Err(ConsensusError::InvalidBlueScore { declared: 4, expected: 3 })
+        ));
+        header.blue_score = 3;
+        header.daa_score = 0;
+        assert!(matches!(
+            validator.validate_ghostdag_commitments(&header, &data),
+            Err(ConsensusError::InvalidDaaScore { declared: 0, expected: 3 })
+        ));
+    }
+
+    fn header_at(daa_score: u64, nonce: u64) -> Header {
+        let mut header = create_test_header(ZERO_HASH, vec![Hash::from_le_u64([1, 0, 0, 0])], 1000, 0x207fffff);
+        header.daa_score = daa_score;
+        header.nonce = nonce;
+        header.finalize();
+        header
+    }
+
+    /// GHOSTDAG data with `selected_parent` and the rest of `blues` and `reds` merged
+    fn merging(selected_parent: &Header, blues: &[&Header], reds: &[&Header]) -> GhostdagData {
+        let mut data = GhostdagData::new(selected_parent.hash);
+        data.mergeset_blues = std::iter::once(selected_parent).chain(blues.iter().copied()).map(|header| header.hash).collect();
+        data.red_set = reds.iter().map(|header| header.hash).collect();
+        data
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let parent = header_at(99, 0);
+        let checkpoint = header_at(100, 1);
+        let rival = header_at(100, 2);
+        let validator = HeaderValidator::new().with_checkpoints(BTreeMap::from([(100, checkpoint.hash)]));
+
+        // Blocks at the checkpoint's DAA score are accepted beside it
+        assert!(validator.check_checkpoints(&checkpoint, &merging(&parent, &[], &[]), 99).is_ok());
+        assert!(validator.check_checkpoints(&rival, &merging(&parent, &[], &[]), 99).is_ok());
+
+        // Chains passing the score must have the checkpoint in their past
+        let child = header_at(101, 3);
+        assert!(validator.check_checkpoints(&child, &merging(&checkpoint, &[&rival], &[]), 100).is_ok());
+        assert!(validator.check_checkpoints(&child, &merging(&rival, &[&checkpoint], &[]), 100).is_ok());
+        assert!(validator.check_checkpoints(&child, &merging(&rival, &[], &[&checkpoint]), 100).is_ok());
+        match validator.check_checkpoints(&child, &merging(&rival, &[], &[]), 100) {
+            Err(ConsensusError::CheckpointMismatch { daa_score, expected, found }) => {
+                assert_eq!((daa_score, expected, found), (100, checkpoint.hash, child.hash));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // Also when the chain skips the checkpoint's score
+        let skipping = header_at(102, 4);
+        assert!(validator.check_checkpoints(&skipping, &merging(&parent, &[], &[]), 99).is_err());
+        assert!(validator.check_checkpoints(&skipping, &merging(&parent, &[&checkpoint], &[]), 99).is_ok());
+
+        // Chains already past the score are not checked again
+        assert!(validator.check_checkpoints(&header_at(102, 5), &merging(&child, &[], &[]), 101).is_ok());
+        assert!(HeaderValidator::new().check_checkpoints(&child, &merging(&rival, &[], &[]), 100).is_ok());
+    }
+
+    #[test]
+    fn test_blocks_far_below_the_pruning_point_are_rejected() {
+        let validator = HeaderValidator::new().with_pruning_depth(100);
+        // The pruning point is at 400, blocks down to 300 are accepted
+        assert!(validator.check_depth(&header_at(300, 0), 500).is_ok());
+        assert!(matches!(
+            validator.check_depth(&header_at(299, 0), 500),
+            Err(ConsensusError::BlockTooOld { daa_score: 299, pruning_point_daa_score: 400 })
+        ));
+        // Early in the chain nothing is too old
+        assert!(validator.check_depth(&header_at(0, 0), 200).is_ok());
+        assert!(HeaderValidator::new().check_depth(&header_at(0, 0), u64::MAX).is_ok());
+    }
+}
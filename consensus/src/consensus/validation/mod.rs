@@ -7,6 +7,7 @@ pub mod block_validator;
 pub mod header_validator;
 pub mod transaction_validator;
 pub mod contextual;
+pub mod signature;
 
 pub use block_validator::BlockValidator;
 pub use header_validator::HeaderValidator;
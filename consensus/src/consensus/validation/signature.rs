@@ -0,0 +1,283 @@
+//! ECDSA input signatures
+//!
+//! Inputs spending pay-to-pubkey-hash and ECDSA pay-to-pubkey outputs carry a
+//! DER signature followed by its sighash type byte, and for pay-to-pubkey-hash
+//! the compressed public key after it. The signatures of a block are collected
+//! from all of its transactions and verified in batches on several threads;
+//! libsecp256k1 has no ECDSA batch verification, so a batch is verified
+//! signature by signature and a failed one reports its first invalid input.
+//! Single transactions, as the mempool receives them, are verified input by input.
+
+use super::transaction_validator::UtxoView;
+use consensus_core::errors::ConsensusError;
+use consensus_core::hashing::{calc_sighash, SigHashReusedValues, SigHashType};
+use consensus_core::tx::script_class::{classify, ScriptClass};
+use consensus_core::tx::{Transaction, UtxoEntry};
+use consensus_core::Hash;
+use ripemd::Ripemd160;
+use secp256k1::{ecdsa, Message, PublicKey, Secp256k1, VerifyOnly};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+/// Signatures a thread verifies at a time
+pub const SIGNATURE_BATCH_SIZE: usize = 64;
+
+fn secp() -> &'static Secp256k1<VerifyOnly> {
+    static SECP: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
+    SECP.get_or_init(Secp256k1::verification_only)
+}
+
+/// A signature an input must carry, parsed and ready to verify
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    pub transaction: Hash,
+    pub input_index: usize,
+    public_key: PublicKey,
+    message: Message,
+    signature: ecdsa::Signature,
+}
+
+impl SignatureCheck {
+    pub fn verify(&self) -> bool {
+        secp().verify_ecdsa(&self.message, &self.signature, &self.public_key).is_ok()
+    }
+
+    fn error(&self) -> ConsensusError {
+        invalid(self.transaction, self.input_index)
+    }
+}
+
+fn invalid(transaction: Hash, input_index: usize) -> ConsensusError {
+    ConsensusError::InvalidInputSignature { transaction, input_index }
+}
+
+/// The data pushes `script` consists of, `None` if it holds anything else
+fn pushes(script: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut pushes = Vec::new();
+    let mut rest = script;
+    while let [len @ 1..=75, tail @ ..] = rest {
+        let data = tail.get(..*len as usize)?;
+        pushes.push(data);
+        rest = &tail[data.len()..];
+    }
+    rest.is_empty().then_some(pushes)
+}
+
+/// The signature input `input_index` of `tx` needs to spend `entry`, `None`
+/// if the output is not locked to a key. `reused` caches the sighash parts
+/// shared by the inputs of `tx`.
+pub fn input_signature_check(
+    tx: &Transaction,
+    input_index: usize,
+    entry: &UtxoEntry,
+    reused: &mut SigHashReusedValues,
+) -> Result<Option<SignatureCheck>, ConsensusError> {
+    let class = classify(&entry.script_public_key);
+    if !matches!(class, ScriptClass::PubKeyHash | ScriptClass::PubKeyECDSA) {
+        return Ok(None);
+    }
+    let error = || invalid(tx.id(), input_index);
+    let spk = entry.script_public_key.script();
+    let pushes = pushes(&tx.inputs[input_index].signature_script).ok_or_else(error)?;
+    let (signature, public_key) = match (class, pushes.as_slice()) {
+        (ScriptClass::PubKeyHash, [signature, public_key]) => {
+            if Ripemd160::digest(Sha256::digest(public_key)).as_slice() != &spk[3..23] {
+                return Err(error());
+            }
+            (*signature, *public_key)
+        }
+        (ScriptClass::PubKeyECDSA, [signature]) => (*signature, &spk[1..34]),
+        _ => return Err(error()),
+    };
+
+    let (&hash_type, der) = signature.split_last().ok_or_else(error)?;
+    let hash_type = SigHashType::from_u8(hash_type).ok_or_else(error)?;
+    let sighash = calc_sighash(tx, input_index, entry, hash_type, reused);
+    Ok(Some(SignatureCheck {
+        transaction: tx.id(),
+        input_index,
+        public_key: PublicKey::from_slice(public_key).map_err(|_| error())?,
+        message: Message::from_slice(&sighash.as_bytes()).map_err(|_| error())?,
+        signature: ecdsa::Signature::from_der(der).map_err(|_| error())?,
+    }))
+}
+
+/// The signatures the inputs of `tx` need to spend their entries in `utxo_view`
+pub fn transaction_signature_checks(tx: &Transaction, utxo_view: &dyn UtxoView) -> Result<Vec<SignatureCheck>, ConsensusError> {
+    if tx.is_coinbase() {
+        return Ok(Vec::new());
+    }
+    let mut reused = SigHashReusedValues::new();
+    let mut checks = Vec::new();
+    for (input_index, input) in tx.inputs.iter().enumerate() {
+        let entry = utxo_view.get(&input.previous_outpoint).ok_or(ConsensusError::InvalidUtxoReference)?;
+        checks.extend(input_signature_check(tx, input_index, entry, &mut reused)?);
+    }
+    Ok(checks)
+}
+
+/// Verify the signatures of `tx` one input at a time
+pub fn verify_transaction_signatures(tx: &Transaction, utxo_view: &dyn UtxoView) -> Result<(), ConsensusError> {
+    for check in transaction_signature_checks(tx, utxo_view)? {
+        if !check.verify() {
+            return Err(check.error());
+        }
+    }
+    Ok(())
+}
+
+/// Verify `checks` in batches of [`SIGNATURE_BATCH_SIZE`] on up to `threads`
+/// threads, which stop once a batch fails. The error names the first invalid
+/// signature of the failed batches.
+pub fn verify_batch(checks: &[SignatureCheck], threads: usize) -> Result<(), ConsensusError> {
+    let batches: Vec<&[SignatureCheck]> = checks.chunks(SIGNATURE_BATCH_SIZE).collect();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let first_invalid = Mutex::new(None);
+
+    let worker = || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        if i >= batches.len() || failed.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(offset) = batches[i].iter().position(|check| !check.verify()) {
+            failed.store(true, Ordering::Relaxed);
+            let index = i * SIGNATURE_BATCH_SIZE + offset;
+            let mut first = first_invalid.lock().unwrap();
+            *first = Some(first.map_or(index, |first: usize| first.min(index)));
+        }
+    };
+    thread::scope(|scope| {
+        for _ in 1..threads.min(batches.len()) {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+
+    match first_invalid.into_inner().unwrap() {
+        Some(index) => Err(checks[index].error()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput};
+    use secp256k1::SecretKey;
+    use std::collections::HashMap;
+
+    struct TestUtxoView(HashMap<TransactionOutpoint, UtxoEntry>);
+
+    impl UtxoView for TestUtxoView {
+        fn get(&self, outpoint: &TransactionOutpoint) -> Option<&UtxoEntry> {
+            self.0.get(outpoint)
+        }
+    }
+
+    fn key(n: u8) -> (SecretKey, PublicKey) {
+        let secret = SecretKey::from_slice(&[n; 32]).unwrap();
+        (secret, secret.public_key(&Secp256k1::new()))
+    }
+
+    fn pubkey_hash_script(public_key: &PublicKey) -> ScriptPublicKey {
+        let hash = Ripemd160::digest(Sha256::digest(public_key.serialize()));
+        ScriptPublicKey::from_vec(0, [&[0x76, 0xa9, 0x14][..], hash.as_slice(), &[0x88, 0xac]].concat())
+    }
+
+    /// A transaction spending one output paid to the key of `seed` per signer,
+    /// input `i` signed with the key of `signers[i]`
+    fn signed_tx(seed: u8, signers: &[u8], view: &mut TestUtxoView) -> Transaction {
+        let inputs = (0..signers.len())
+            .map(|i| {
+                let outpoint = TransactionOutpoint::new(Hash::from_bytes([seed; 32]), i as u32);
+                view.0.insert(outpoint, UtxoEntry::new(1_000, pubkey_hash_script(&key(seed).1), 0, false));
+                TransactionInput::new(outpoint, vec![], 0, 1)
+            })
+            .collect();
+        let output = TransactionOutput::new(900 * signers.len() as u64, ScriptPublicKey::from_vec(0, vec![0xac]));
+        let mut tx = Transaction::new(0, inputs, vec![output], 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+
+        let secp = Secp256k1::new();
+        let mut reused = SigHashReusedValues::new();
+        for (i, signer) in signers.iter().enumerate() {
+            let entry = &view.0[&tx.inputs[i].previous_outpoint];
+            let sighash = calc_sighash(&tx, i, entry, SigHashType::SighashAll, &mut reused);
+            let (secret, _) = key(*signer);
+            let mut signature = secp.sign_ecdsa(&Message::from_slice(&sighash.as_bytes()).unwrap(), &secret).serialize_der().to_vec();
+            signature.push(SigHashType::SighashAll.to_u8());
+            let public_key = key(seed).1.serialize();
+            tx.inputs[i].signature_script = [&[signature.len() as u8][..], &signature, &[33], &public_key].concat();
+        }
+        tx.finalize();
+        tx
+    }
+
+    #[test]
+    fn test_signed_inputs_verify() {
+        let mut view = TestUtxoView(HashMap::new());
+        let tx = signed_tx(1, &[1, 1], &mut view);
+        assert_eq!(transaction_signature_checks(&tx, &view).unwrap().len(), 2);
+        verify_transaction_signatures(&tx, &view).unwrap();
+
+        // The second input is signed with another key
+        let forged = signed_tx(2, &[2, 3], &mut view);
+        match verify_transaction_signatures(&forged, &view) {
+            Err(ConsensusError::InvalidInputSignature { transaction, input_index }) => {
+                assert_eq!((transaction, input_index), (forged.id(), 1));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Changing what was signed invalidates the signatures
+        let mut tampered = tx.clone();
+        tampered.outputs[0].value -= 1;
+        tampered.finalize();
+        assert!(verify_transaction_signatures(&tampered, &view).is_err());
+        // So does the spent output being worth something else
+        let mut revalued = TestUtxoView(view.0.clone());
+        revalued.0.get_mut(&tx.inputs[0].previous_outpoint).unwrap().amount += 1;
+        assert!(verify_transaction_signatures(&tx, &revalued).is_err());
+
+        // Outputs not locked to a key need no signature, those that are need one
+        let mut unsigned = tx.clone();
+        unsigned.inputs[0].signature_script.clear();
+        assert!(verify_transaction_signatures(&unsigned, &view).is_err());
+        let outpoint = unsigned.inputs[0].previous_outpoint;
+        view.0.get_mut(&outpoint).unwrap().script_public_key = ScriptPublicKey::from_vec(0, vec![0xac]);
+        assert_eq!(transaction_signature_checks(&unsigned, &view).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_one_bad_signature_in_large_batch() {
+        let mut view = TestUtxoView(HashMap::new());
+        // 100 transactions of 10 inputs each; input 3 of transaction 57 is signed with the wrong key
+        let txs: Vec<Transaction> = (0..100u8)
+            .map(|n| {
+                let mut signers = [n + 1; 10];
+                if n == 57 {
+                    signers[3] = 200;
+                }
+                signed_tx(n + 1, &signers, &mut view)
+            })
+            .collect();
+        let checks: Vec<SignatureCheck> =
+            txs.iter().flat_map(|tx| transaction_signature_checks(tx, &view).unwrap()).collect();
+        assert_eq!(checks.len(), 1000);
+
+        for threads in [1, 4] {
+            match verify_batch(&checks, threads) {
+                Err(ConsensusError::InvalidInputSignature { transaction, input_index }) => {
+                    assert_eq!((transaction, input_index), (txs[57].id(), 3));
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        let valid: Vec<SignatureCheck> = checks.iter().filter(|check| check.transaction != txs[57].id()).cloned().collect();
+        verify_batch(&valid, 4).unwrap();
+        verify_batch(&[], 4).unwrap();
+    }
+}
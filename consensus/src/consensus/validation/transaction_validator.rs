@@ -24,6 +24,8 @@ pub struct TransactionValidator {
     max_tx_size: u64,
     max_money: u64,
     coinbase_maturity: u64,
+    /// Accept transactions of subnetworks other than the native and coinbase ones
+    experimental_subnetworks: bool,
 }
 
 impl TransactionValidator {
@@ -33,6 +35,7 @@ impl TransactionValidator {
             max_tx_size: MAX_TRANSACTION_SIZE,
             max_money: MAX_MONEY,
             coinbase_maturity: COINBASE_MATURITY,
+            experimental_subnetworks: false,
         }
     }
 
@@ -42,9 +45,21 @@ impl TransactionValidator {
             max_tx_size,
             max_money,
             coinbase_maturity,
+            experimental_subnetworks: false,
         }
     }
 
+    /// Accept transactions of experimental subnetworks, whose gas the block
+    /// validator then bounds per block
+    pub fn with_experimental_subnetworks(self, experimental_subnetworks: bool) -> Self {
+        Self { experimental_subnetworks, ..self }
+    }
+
+    /// DAA score age at which coinbase outputs become spendable
+    pub fn coinbase_maturity(&self) -> u64 {
+        self.coinbase_maturity
+    }
+
     /// Validate transaction with context-free checks
     pub fn validate_transaction(&self, tx: &Transaction) -> Result<(), ConsensusError> {
         // Check version >= 1
@@ -52,6 +67,8 @@ impl TransactionValidator {
             return Err(ConsensusError::InvalidTransaction);
         }
 
+        self.validate_subnetwork(tx)?;
+
         // Coinbase transactions are allowed to have empty inputs
         if tx.is_coinbase() {
             // Coinbase must have at least one output
@@ -102,6 +119,21 @@ impl TransactionValidator {
         Ok(())
     }
 
+    /// Only the native and coinbase subnetworks are valid unless experimental
+    /// ones are enabled, and only experimental subnetworks use gas
+    fn validate_subnetwork(&self, tx: &Transaction) -> Result<(), ConsensusError> {
+        if !tx.subnetwork_id.is_builtin() {
+            return match self.experimental_subnetworks {
+                true => Ok(()),
+                false => Err(ConsensusError::DisabledSubnetwork(tx.subnetwork_id)),
+            };
+        }
+        if tx.gas != 0 {
+            return Err(ConsensusError::UnexpectedGas { subnetwork: tx.subnetwork_id, gas: tx.gas });
+        }
+        Ok(())
+    }
+
     /// Validate transaction with UTXO context
     pub fn validate_transaction_with_utxo(
         &self,
@@ -350,5 +382,64 @@ mod tests {
         let fee = validator.calculate_fee(&tx, &utxo_view).unwrap();
         assert_eq!(fee, 2000);
     }
-}
 
+    #[test]
+    fn test_chained_transaction_validates_against_pending_overlay() {
+        let validator = TransactionValidator::new();
+        let script = ScriptPublicKey::from_vec(0, Vec::new());
+        let funding = TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0);
+        let mut confirmed = consensus_core::utxo::UtxoCollection::new();
+        confirmed.insert(funding, UtxoEntry::new(5000, script.clone(), 100, false));
+
+        let (parent, parent_id) = create_test_tx_with_hash(
+            vec![TransactionInput::new(funding, Vec::new(), 0, 0)],
+            vec![TransactionOutput::new(4000, script.clone())],
+        );
+        let child = create_test_tx(
+            vec![TransactionInput::new(TransactionOutpoint::new(parent_id, 0), Vec::new(), 0, 0)],
+            vec![TransactionOutput::new(3500, script.clone())],
+        );
+
+        // The confirmed set alone doesn't know the parent's output
+        let mut view = consensus_core::utxo::UtxoView::new(&confirmed);
+        assert!(validator.validate_transaction_with_utxo(&child, &view, 200).is_err());
+
+        // With the accepted parent layered on top the child is valid
+        assert_eq!(validator.validate_transaction_with_utxo(&parent, &view, 200).unwrap(), 1000);
+        view.apply_transaction(&parent, 200);
+        assert_eq!(validator.validate_transaction_with_utxo(&child, &view, 200).unwrap(), 500);
+
+        // and the parent can't be spent twice
+        assert!(validator.validate_transaction_with_utxo(&parent, &view, 200).is_err());
+        assert!(confirmed.contains(&funding));
+    }
+
+    #[test]
+    fn test_subnetwork_and_gas_rules() {
+        use consensus_core::subnets::{SubnetworkId, SUBNETWORK_ID_COINBASE};
+        let script = ScriptPublicKey::from_vec(0, Vec::new());
+        let input = TransactionInput::new(TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0), Vec::new(), 0, 0);
+        let tx = |subnetwork_id, gas| Transaction::new(1, vec![input.clone()], vec![TransactionOutput::new(1000, script.clone())], 0, subnetwork_id, gas, Vec::new());
+        let experimental = SubnetworkId::from(2u64);
+        let validator = TransactionValidator::new();
+
+        // Native transactions carry no gas
+        let mut native = create_test_tx(vec![input.clone()], vec![TransactionOutput::new(1000, script.clone())]);
+        assert!(validator.validate_transaction(&native).is_ok());
+        native.gas = 5;
+        assert!(matches!(validator.validate_transaction(&native), Err(ConsensusError::UnexpectedGas { gas: 5, .. })));
+
+        // Nor do coinbases
+        let coinbase = Transaction::new(1, vec![], vec![TransactionOutput::new(1000, script.clone())], 0, SUBNETWORK_ID_COINBASE, 1, Vec::new());
+        assert!(matches!(validator.validate_transaction(&coinbase), Err(ConsensusError::UnexpectedGas { gas: 1, .. })));
+
+        // Other subnetworks need experimental subnetworks enabled, and then may use gas
+        assert!(matches!(validator.validate_transaction(&tx(experimental, 0)), Err(ConsensusError::DisabledSubnetwork(id)) if id == experimental));
+        let validator = validator.with_experimental_subnetworks(true);
+        assert!(validator.validate_transaction(&tx(experimental, 700)).is_ok());
+        assert!(validator.validate_transaction(&native).is_err());
+
+        // Gas counts toward the mass of experimental transactions only
+        assert_eq!(tx(experimental, 700).calculate_mass(), tx(experimental, 0).calculate_mass() + 700);
+    }
+}
@@ -6,16 +6,17 @@
 pub mod consensus;
 pub mod pipeline;
 pub mod process;
+pub mod light;
 
 // Re-export key types for easier access
 pub use consensus_core::Hash;
 pub use consensus::dag::{BlockRelations, ReachabilityStore, DagTopology};
-pub use consensus::ghostdag::{GhostdagData, GhostdagStore, GhostdagProtocol, GhostdagManager};
+pub use consensus::ghostdag::{GhostdagData, GhostdagStore, GhostdagProtocol, GhostdagManager, BlockAcceptance};
 pub use consensus::validation::{
     BlockValidator, HeaderValidator, TransactionValidator, ContextualValidator,
 };
 pub use consensus::difficulty::{DifficultyManager, DifficultyWindow};
-pub use consensus::storage::{ConsensusStorage, UtxoSet, BlockStore};
+pub use consensus::storage::{ConsensusStorage, UtxoSet, BlockStore, BlockFeeRecord};
 pub use consensus::types::{BlockStatus, ConsensusConfig, BlockProcessingResult, ValidationResult};
 
 // Re-export pipeline types
@@ -23,3 +24,4 @@ pub use pipeline::{
     BlockProcessor, HeaderProcessor, BodyProcessor, VirtualProcessor, DepsManager,
 };
 pub use pipeline::flow::{ProcessQueue, ValidationFlow};
+pub use light::{LightChain, LightChainError};
@@ -0,0 +1,324 @@
+//! Header-only verification for light clients
+//!
+//! A [`LightChain`] starts from a trusted anchor header and accepts the headers
+//! that follow it along the selected chain, as served by the `getHeaders` RPC.
+//! Each header must hash to its declared hash, meet the proof of work its bits
+//! claim, name the previous header as a direct parent, declare the bits the
+//! [`DifficultyManager`] derives from the headers before it, and carry a
+//! timestamp past the median of the recent ones and not too far in the future.
+//! Nothing is stored: the chain keeps only what the next header is checked
+//! against.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use consensus_core::hashing::header::{calculate_header_hash, validate_pow};
+use consensus_core::header::Header;
+use consensus_core::time::{Clock, SystemClock};
+use consensus_core::Hash;
+use crate::consensus::difficulty::DifficultyManager;
+use crate::consensus::validation::header_validator::MAX_TIMESTAMP_FUTURE_OFFSET;
+
+/// Headers whose timestamps a new header's must exceed the median of
+pub const MEDIAN_TIME_WINDOW: usize = 11;
+
+/// Why a header was rejected; `index` is its position in the verified slice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightChainError {
+    /// The header's contents hash to something other than its declared hash
+    HashMismatch { index: usize, declared: Hash, computed: Hash },
+    /// The header's pow hash misses the target of its bits
+    InvalidProofOfWork { index: usize, hash: Hash },
+    /// The header does not name the previous header as a direct parent
+    BrokenLink { index: usize, hash: Hash, previous: Hash },
+    /// The header's bits are not the ones the difficulty rules give
+    WrongDifficulty { index: usize, hash: Hash, declared: u32, expected: u32 },
+    /// The difficulty rules could not be applied to the previous headers
+    Difficulty { index: usize, message: String },
+    /// The timestamp is not past the median of the recent headers
+    TimestampTooOld { index: usize, timestamp: u64, median: u64 },
+    /// The timestamp is further in the future than allowed
+    TimestampTooNew { index: usize, timestamp: u64, limit: u64 },
+}
+
+impl LightChainError {
+    /// Position of the rejected header
+    pub fn index(&self) -> usize {
+        match self {
+            LightChainError::HashMismatch { index, .. }
+            | LightChainError::InvalidProofOfWork { index, .. }
+            | LightChainError::BrokenLink { index, .. }
+            | LightChainError::WrongDifficulty { index, .. }
+            | LightChainError::Difficulty { index, .. }
+            | LightChainError::TimestampTooOld { index, .. }
+            | LightChainError::TimestampTooNew { index, .. } => *index,
+        }
+    }
+}
+
+impl fmt::Display for LightChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightChainError::HashMismatch { index, declared, computed } => {
+                write!(f, "Header {} declares hash {} but hashes to {}", index, declared, computed)
+            }
+            LightChainError::InvalidProofOfWork { index, hash } => write!(f, "Header {} ({}) fails proof of work", index, hash),
+            LightChainError::BrokenLink { index, hash, previous } => {
+                write!(f, "Header {} ({}) does not build on {}", index, hash, previous)
+            }
+            LightChainError::WrongDifficulty { index, hash, declared, expected } => {
+                write!(f, "Header {} ({}) declares bits {:08x}, expected {:08x}", index, hash, declared, expected)
+            }
+            LightChainError::Difficulty { index, message } => write!(f, "Difficulty for header {} unavailable: {}", index, message),
+            LightChainError::TimestampTooOld { index, timestamp, median } => {
+                write!(f, "Header {} timestamp {} is not past the median time {}", index, timestamp, median)
+            }
+            LightChainError::TimestampTooNew { index, timestamp, limit } => {
+                write!(f, "Header {} timestamp {} is past the limit {}", index, timestamp, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LightChainError {}
+
+/// A verified header chain, holding only the context the next header needs
+pub struct LightChain {
+    difficulty: DifficultyManager,
+    /// Bits the next header must declare
+    next_bits: u32,
+    /// Latest headers, tip last
+    recent: VecDeque<Header>,
+    /// Headers accepted after the anchor
+    verified: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl LightChain {
+    /// Chain starting at the trusted `anchor`, whose hash and proof of work are still checked
+    pub fn new(anchor: Header) -> Result<Self, LightChainError> {
+        Self::with_difficulty(anchor, DifficultyManager::new())
+    }
+
+    /// Chain whose difficulty follows `difficulty`, which must not have seen any header yet
+    pub fn with_difficulty(anchor: Header, difficulty: DifficultyManager) -> Result<Self, LightChainError> {
+        check_header(0, &anchor)?;
+        let next_bits = difficulty.calculate_next_difficulty(&anchor).map_err(|message| LightChainError::Difficulty { index: 1, message })?;
+        Ok(Self { difficulty, next_bits, recent: VecDeque::from([anchor]), verified: 0, clock: Arc::new(SystemClock) })
+    }
+
+    /// Judge how far in the future timestamps are by `clock`
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn tip(&self) -> &Header {
+        self.recent.back().expect("the anchor is never removed before a successor")
+    }
+
+    /// Headers accepted after the anchor
+    pub fn verified(&self) -> u64 {
+        self.verified
+    }
+
+    /// Accept `headers` in order, each building on the tip. Stops at the
+    /// first invalid one, whose index in `headers` the error carries; the
+    /// headers before it stay accepted.
+    pub fn extend(&mut self, headers: &[Header]) -> Result<(), LightChainError> {
+        for (index, header) in headers.iter().enumerate() {
+            self.accept(index, header)?;
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self, index: usize, header: &Header) -> Result<(), LightChainError> {
+        check_header(index, header)?;
+
+        let previous = self.tip().hash;
+        if !header.direct_parents().contains(&previous) {
+            return Err(LightChainError::BrokenLink { index, hash: header.hash, previous });
+        }
+        if header.bits != self.next_bits {
+            return Err(LightChainError::WrongDifficulty { index, hash: header.hash, declared: header.bits, expected: self.next_bits });
+        }
+
+        let median = median_time(&self.recent);
+        if header.timestamp <= median {
+            return Err(LightChainError::TimestampTooOld { index, timestamp: header.timestamp, median });
+        }
+        let limit = self.clock.now_millis() + MAX_TIMESTAMP_FUTURE_OFFSET;
+        if header.timestamp > limit {
+            return Err(LightChainError::TimestampTooNew { index, timestamp: header.timestamp, limit });
+        }
+
+        self.next_bits =
+            self.difficulty.calculate_next_difficulty(header).map_err(|message| LightChainError::Difficulty { index: index + 1, message })?;
+        if self.recent.len() >= MEDIAN_TIME_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(header.clone());
+        self.verified += 1;
+        Ok(())
+    }
+}
+
+/// Verify `headers` standalone, trusting only that the first one is the intended start
+pub fn verify_header_chain(headers: &[Header]) -> Result<(), LightChainError> {
+    let Some((anchor, rest)) = headers.split_first() else {
+        return Ok(());
+    };
+    let mut chain = LightChain::new(anchor.clone())?;
+    chain.extend(rest).map_err(|e| shift_index(e, 1))
+}
+
+/// Checks needing nothing but the header itself
+fn check_header(index: usize, header: &Header) -> Result<(), LightChainError> {
+    let computed = calculate_header_hash(header);
+    if computed != header.hash {
+        return Err(LightChainError::HashMismatch { index, declared: header.hash, computed });
+    }
+    if !validate_pow(header) {
+        return Err(LightChainError::InvalidProofOfWork { index, hash: header.hash });
+    }
+    Ok(())
+}
+
+fn median_time(headers: &VecDeque<Header>) -> u64 {
+    let mut timestamps: Vec<u64> = headers.iter().map(|header| header.timestamp).collect();
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
+}
+
+fn shift_index(error: LightChainError, by: usize) -> LightChainError {
+    match error {
+        LightChainError::HashMismatch { index, declared, computed } => LightChainError::HashMismatch { index: index + by, declared, computed },
+        LightChainError::InvalidProofOfWork { index, hash } => LightChainError::InvalidProofOfWork { index: index + by, hash },
+        LightChainError::BrokenLink { index, hash, previous } => LightChainError::BrokenLink { index: index + by, hash, previous },
+        LightChainError::WrongDifficulty { index, hash, declared, expected } => {
+            LightChainError::WrongDifficulty { index: index + by, hash, declared, expected }
+        }
+        LightChainError::Difficulty { index, message } => LightChainError::Difficulty { index: index + by, message },
+        LightChainError::TimestampTooOld { index, timestamp, median } => LightChainError::TimestampTooOld { index: index + by, timestamp, median },
+        LightChainError::TimestampTooNew { index, timestamp, limit } => LightChainError::TimestampTooNew { index: index + by, timestamp, limit },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::constants::BLOCK_VERSION;
+    use consensus_core::time::MockClock;
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+
+    const START: u64 = 1_700_000_000_000;
+    const EASY_BITS: u32 = 0x207fffff;
+
+    /// Header on `parent` whose proof of work is `valid` or not
+    fn header(parent: Option<&Header>, timestamp: u64, bits: u32, valid: bool) -> Header {
+        let parents = parent.map(|parent| vec![vec![parent.hash]]).unwrap_or_default();
+        let mut header = Header::new_finalized(
+            BLOCK_VERSION,
+            parents,
+            ZERO_HASH,
+            ZERO_HASH,
+            ZERO_HASH,
+            timestamp,
+            bits,
+            0,
+            0,
+            BlueWorkType::from(0u64),
+            0,
+            ZERO_HASH,
+        );
+        while validate_pow(&header) != valid {
+            header.nonce += 1;
+            header.finalize();
+        }
+        header
+    }
+
+    /// An anchor and three headers a minute apart, each declaring the bits the difficulty rules give
+    fn chain() -> Vec<Header> {
+        let difficulty = DifficultyManager::new();
+        let mut headers = vec![header(None, START, EASY_BITS, true)];
+        let mut bits = difficulty.calculate_next_difficulty(&headers[0]).unwrap();
+        for i in 1..=3u64 {
+            let next = header(headers.last(), START + i * 60_000, bits, true);
+            bits = difficulty.calculate_next_difficulty(&next).unwrap();
+            headers.push(next);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_valid_chain_is_accepted() {
+        let headers = chain();
+        // The anchor's easy bits tighten to the minimum difficulty
+        assert_eq!(headers[1].bits, EASY_BITS);
+        assert_ne!(headers[2].bits, EASY_BITS);
+        assert_eq!(verify_header_chain(&headers), Ok(()));
+
+        let clock = Arc::new(MockClock::new(START));
+        let mut light = LightChain::new(headers[0].clone()).unwrap().with_clock(clock);
+        light.extend(&headers[1..]).unwrap();
+        assert_eq!(light.verified(), 3);
+        assert_eq!(light.tip().hash, headers[3].hash);
+    }
+
+    #[test]
+    fn test_bad_pow_header_is_rejected() {
+        let mut headers = chain();
+        let bad = header(Some(&headers[1]), headers[2].timestamp, headers[2].bits, false);
+        headers[2] = bad.clone();
+        assert_eq!(verify_header_chain(&headers), Err(LightChainError::InvalidProofOfWork { index: 2, hash: bad.hash }));
+
+        // The headers before the bad one stay accepted
+        let mut light = LightChain::new(headers[0].clone()).unwrap();
+        assert_eq!(light.extend(&headers[1..]).unwrap_err().index(), 1);
+        assert_eq!(light.verified(), 1);
+        assert_eq!(light.tip().hash, headers[1].hash);
+
+        // A nonce changed after hashing is caught before the proof of work
+        let mut tampered = headers[1].clone();
+        tampered.nonce += 1;
+        headers[1] = tampered;
+        assert!(matches!(verify_header_chain(&headers), Err(LightChainError::HashMismatch { index: 1, .. })));
+    }
+
+    #[test]
+    fn test_wrong_difficulty_header_is_rejected() {
+        let mut headers = chain();
+        let expected = headers[3].bits;
+        let easy = header(Some(&headers[2]), headers[3].timestamp, EASY_BITS, true);
+        headers[3] = easy.clone();
+        assert_eq!(
+            verify_header_chain(&headers),
+            Err(LightChainError::WrongDifficulty { index: 3, hash: easy.hash, declared: EASY_BITS, expected })
+        );
+
+        // Not building on the tip is reported as such, even with valid bits
+        let orphan = header(Some(&headers[0]), headers[3].timestamp, expected, true);
+        headers[3] = orphan.clone();
+        assert_eq!(
+            verify_header_chain(&headers),
+            Err(LightChainError::BrokenLink { index: 3, hash: orphan.hash, previous: headers[2].hash })
+        );
+    }
+
+    #[test]
+    fn test_timestamps_are_checked() {
+        let headers = chain();
+        let clock = Arc::new(MockClock::new(START));
+        let mut light = LightChain::new(headers[0].clone()).unwrap().with_clock(clock.clone());
+
+        // Not past the anchor's time
+        let stale = header(Some(&headers[0]), START, headers[1].bits, true);
+        assert!(matches!(light.extend(&[stale]), Err(LightChainError::TimestampTooOld { index: 0, median: START, .. })));
+
+        // Accepted only once the clock catches up
+        clock.set(headers[1].timestamp - MAX_TIMESTAMP_FUTURE_OFFSET - 1);
+        assert!(matches!(light.extend(&headers[1..2]), Err(LightChainError::TimestampTooNew { index: 0, .. })));
+        clock.set(headers[1].timestamp - MAX_TIMESTAMP_FUTURE_OFFSET);
+        assert_eq!(light.extend(&headers[1..2]), Ok(()));
+    }
+}
@@ -6,14 +6,31 @@
 use consensus_core::block::Block;
 use consensus_core::Hash;
 use consensus_core::errors::ConsensusError;
+use consensus_core::provenance::{BlockProvenance, BlockSource};
+use consensus_core::time::{Clock, SystemClock};
 use crate::consensus::types::BlockStatus;
 use crate::pipeline::header_processor::HeaderProcessor;
-use crate::pipeline::body_processor::BodyProcessor;
+use crate::pipeline::body_processor::{BodyProcessingResult, BodyProcessor};
 use crate::pipeline::virtual_processor::VirtualProcessor;
 use crate::pipeline::deps_manager::DepsManager;
+use crate::pipeline::timings::{BlockTimings, ProcessingStage, ProcessingTimings};
 use crate::consensus::ghostdag::GhostdagManager;
 use crate::consensus::storage::ConsensusStorage;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+
+/// Blocks leaving and joining the selected chain when a block becomes its tip
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainChanges {
+    /// Chain blocks reorged away, highest first
+    pub removed: Vec<Hash>,
+    /// New chain blocks, lowest first; the last one is the new tip
+    pub added: Vec<Hash>,
+    /// Blue score of the new tip
+    pub blue_score: u64,
+}
+
+/// Called with the changes of each selected chain update
+pub type ChainChangeListener = Arc<dyn Fn(&ChainChanges) + Send + Sync>;
 
 /// Block processor for consensus
 pub struct BlockProcessor {
@@ -23,6 +40,10 @@ pub struct BlockProcessor {
     ghostdag_manager: Arc<GhostdagManager>,
     storage: Arc<ConsensusStorage>,
     deps_manager: Arc<DepsManager>,
+    timings: Arc<ProcessingTimings>,
+    body_threads: usize,
+    clock: Arc<dyn Clock>,
+    chain_listeners: Arc<RwLock<Vec<ChainChangeListener>>>,
 }
 
 impl BlockProcessor {
@@ -42,57 +63,229 @@ impl BlockProcessor {
             ghostdag_manager,
             storage,
             deps_manager,
+            timings: Arc::new(ProcessingTimings::new()),
+            body_threads: std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+            clock: Arc::new(SystemClock),
+            chain_listeners: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
+    /// Number of threads [`Self::process_blocks`] validates bodies on
+    pub fn with_body_threads(self, body_threads: usize) -> Self {
+        Self { body_threads: body_threads.max(1), ..self }
+    }
+
+    /// Stamp first-seen block provenance with `clock`
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Call `listener` with each change of the selected chain from now on
+    pub fn subscribe_chain_changes(&self, listener: ChainChangeListener) {
+        self.chain_listeners.write().unwrap().push(listener);
+    }
+
     /// Process a complete block
     pub fn process_block(&self, block: Block) -> Result<BlockProcessingResult, ConsensusError> {
+        self.process_block_inner(block, false)
+    }
+
+    /// Process a block delivered by `source`. The first source of each block is
+    /// recorded and flagged when the block fails validation; the returned
+    /// provenance lets callers hold that source to account.
+    pub fn process_block_from(&self, block: Block, source: BlockSource) -> (Result<BlockProcessingResult, ConsensusError>, Option<BlockProvenance>) {
+        let hash = block.header.hash;
+        if let Err(e) = self.storage.record_first_seen(hash, source, self.clock.now_millis()) {
+            tracing::warn!(%hash, "failed to record block provenance: {}", e);
+        }
+        let result = self.process_block(block);
+        let invalid = match &result {
+            Ok(result) => result.status == BlockStatus::Invalid,
+            Err(e) => !matches!(e, ConsensusError::DatabaseError(_) | ConsensusError::IoError(_) | ConsensusError::SerializationError(_)),
+        };
+        let provenance = if invalid {
+            self.storage.mark_invalid(&hash).unwrap_or_else(|e| {
+                tracing::warn!(%hash, "failed to flag block provenance: {}", e);
+                None
+            })
+        } else {
+            self.storage.get_provenance(&hash)
+        };
+        (result, provenance)
+    }
+
+    /// Process a block whose header already passed the context-free checks,
+    /// see [`crate::pipeline::flow::ValidationFlow::process_batch`]
+    pub(crate) fn process_prevalidated_block(&self, block: Block) -> Result<BlockProcessingResult, ConsensusError> {
+        self.process_block_inner(block, true)
+    }
+
+    fn process_block_inner(&self, block: Block, header_prevalidated: bool) -> Result<BlockProcessingResult, ConsensusError> {
+        let hash = block.header.hash;
+        let mut timings = BlockTimings::default();
+        if let Some(result) = self.process_block_header(&block, &mut timings, header_prevalidated)? {
+            return Ok(result);
+        }
+
+        // Step 2: Process body (transactions)
+        // Use DAA score from header, or calculate from ghostdag data
+        let body_result = timings.time(ProcessingStage::BodyValidation, &hash, || {
+            self.body_processor.process_body(&block, block.header.daa_score)
+        })?;
+        self.finish_block(&block, timings, body_result)
+    }
+
+    /// Process `blocks` in order, validating bodies concurrently on up to
+    /// `body_threads` threads. Headers go through GHOSTDAG one at a time first;
+    /// the [`DepsManager`] then releases bodies to the threads once no body
+    /// queued before them spends or creates any of the same outpoints. Results,
+    /// in input order, match calling [`Self::process_block`] on each block in turn.
+    pub fn process_blocks(&self, blocks: Vec<Block>) -> Vec<Result<BlockProcessingResult, ConsensusError>> {
+        let mut results: Vec<Option<Result<BlockProcessingResult, ConsensusError>>> = Vec::with_capacity(blocks.len());
+        let mut bodies = Vec::new();
+        let mut body_timings = Vec::new();
+        for block in blocks {
+            let mut timings = BlockTimings::default();
+            match self.process_block_header(&block, &mut timings, false) {
+                Ok(None) => {
+                    results.push(None);
+                    bodies.push(block);
+                    body_timings.push(timings);
+                }
+                result => results.push(Some(result.map(|result| result.expect("header stage finished the block")))),
+            }
+        }
+
+        let body_results = self.deps_manager.process_bodies(&bodies, self.body_threads, |block| {
+            let mut timings = BlockTimings::default();
+            let result = timings.time(ProcessingStage::BodyValidation, &block.header.hash, || {
+                self.body_processor.process_body(block, block.header.daa_score)
+            });
+            (result, timings.body_validation)
+        });
+
+        // Chain index updates stay in input order
+        let mut finished = bodies.iter().zip(body_timings).zip(body_results).map(|((block, mut timings), (body_result, elapsed))| {
+            timings.body_validation = elapsed;
+            body_result.and_then(|body_result| self.finish_block(block, timings, body_result))
+        });
+        results.into_iter().map(|result| result.unwrap_or_else(|| finished.next().expect("one result per body"))).collect()
+    }
+
+    /// Header stage of [`Self::process_block`], finishing the block unless it
+    /// should go on to body processing
+    fn process_block_header(
+        &self,
+        block: &Block,
+        timings: &mut BlockTimings,
+        header_prevalidated: bool,
+    ) -> Result<Option<BlockProcessingResult>, ConsensusError> {
         let hash = block.header.hash;
 
         // Check if block already exists
         if self.storage.has_block(&hash) {
-            return Ok(BlockProcessingResult::already_exists(hash));
+            return Ok(Some(BlockProcessingResult::already_exists(hash)));
         }
 
         // Step 1: Process header
-        let header_result = self.header_processor.process_header(block.header.clone())?;
-        
+        let header_result = if header_prevalidated {
+            self.header_processor.process_prevalidated_header(block.header.clone(), timings)?
+        } else {
+            self.header_processor.process_header_timed(block.header.clone(), timings)?
+        };
+
         match header_result {
             crate::pipeline::header_processor::HeaderProcessingResult::Orphan(_) => {
                 // Header is orphaned, store block as orphan
-                self.deps_manager.add_orphan_block(block);
-                return Ok(BlockProcessingResult::orphan(hash));
+                self.deps_manager.add_orphan_block(block.clone());
+                return Ok(Some(BlockProcessingResult::orphan(hash)));
             }
             crate::pipeline::header_processor::HeaderProcessingResult::Invalid(hash, msg) => {
-                return Ok(BlockProcessingResult::invalid(hash, msg));
+                return Ok(Some(BlockProcessingResult::invalid(hash, msg)));
             }
             crate::pipeline::header_processor::HeaderProcessingResult::AlreadyExists(_) => {
-                return Ok(BlockProcessingResult::already_exists(hash));
+                return Ok(Some(BlockProcessingResult::already_exists(hash)));
             }
             crate::pipeline::header_processor::HeaderProcessingResult::Accepted { .. } => {
                 // Header is valid, continue to body processing
             }
         }
 
-        // Step 2: Process body (transactions)
-        // Get DAA score from header (or calculate from GHOSTDAG data)
-        let _ghostdag_data = self.ghostdag_manager.get_ghostdag_data(&hash)
+        self.ghostdag_manager.get_ghostdag_data(&hash)
             .ok_or_else(|| ConsensusError::Other("GHOSTDAG data not found".to_string()))?;
-        
-        // Use DAA score from header, or calculate from ghostdag data
-        let daa_score = block.header.daa_score;
-        
-        let body_result = self.body_processor.process_body(&block, daa_score)?;
+        Ok(None)
+    }
 
+    /// Virtual stage of [`Self::process_block`] for a processed body
+    fn finish_block(
+        &self,
+        block: &Block,
+        mut timings: BlockTimings,
+        body_result: BodyProcessingResult,
+    ) -> Result<BlockProcessingResult, ConsensusError> {
+        let hash = block.header.hash;
         match body_result {
-            crate::pipeline::body_processor::BodyProcessingResult::AlreadyExists(_) => {
-                return Ok(BlockProcessingResult::already_exists(hash));
+            BodyProcessingResult::AlreadyExists(_) => {
+                Ok(BlockProcessingResult::already_exists(hash))
             }
-            crate::pipeline::body_processor::BodyProcessingResult::Accepted { total_fees, .. } => {
+            BodyProcessingResult::Accepted { total_fees, .. } => {
                 // Block successfully processed
-                Ok(BlockProcessingResult::valid(hash, total_fees))
+                timings.time(ProcessingStage::VirtualUpdate, &hash, || {
+                    self.virtual_processor.record_block_fees(block, total_fees)?;
+                    self.update_chain_index(hash)
+                })?;
+                self.timings.observe(&timings);
+                tracing::debug!(
+                    %hash,
+                    header_validation_us = timings.header_validation.as_micros() as u64,
+                    ghostdag_us = timings.ghostdag.as_micros() as u64,
+                    body_validation_us = timings.body_validation.as_micros() as u64,
+                    virtual_update_us = timings.virtual_update.as_micros() as u64,
+                    "block accepted"
+                );
+                Ok(BlockProcessingResult { timings, ..BlockProcessingResult::valid(hash, total_fees) })
+            }
+        }
+    }
+
+    /// Make `hash` the tip of the chain index if its blue score is above the
+    /// current tip's. The new selected chain is walked down to where it meets the
+    /// indexed one, and everything indexed above that point is replaced.
+    fn update_chain_index(&self, hash: Hash) -> Result<(), ConsensusError> {
+        let block_store = self.storage.block_store();
+        let Some(data) = self.ghostdag_manager.get_ghostdag_data(&hash) else {
+            return Ok(());
+        };
+        if block_store.get_chain_tip().map_or(false, |(blue_score, _)| blue_score >= data.blue_score) {
+            return Ok(());
+        }
+
+        let mut chain = Vec::new();
+        let mut from = data.blue_score;
+        let mut current = hash;
+        while let Some(data) = self.ghostdag_manager.get_ghostdag_data(&current) {
+            if block_store.get_chain_block_by_blue_score(data.blue_score) == Some(current) {
+                from = data.blue_score + 1;
+                break;
+            }
+            chain.push((data.blue_score, current));
+            from = data.blue_score;
+            if data.selected_parent == current {
+                break;
             }
+            current = data.selected_parent;
+        }
+        let replaced = block_store.replace_chain_from(from, &chain)?;
+
+        let changes = ChainChanges {
+            removed: replaced.into_iter().rev().map(|(_, hash)| hash).collect(),
+            added: chain.into_iter().rev().map(|(_, hash)| hash).collect(),
+            blue_score: data.blue_score,
+        };
+        for listener in self.chain_listeners.read().unwrap().iter() {
+            listener(&changes);
         }
+        Ok(())
     }
 
     /// Process header only (for fast sync)
@@ -137,6 +330,7 @@ impl BlockProcessor {
                         BlockStatus::Invalid => {
                             // Invalid, remove from orphans
                             self.deps_manager.remove_orphan_block(&hash);
+                            let _ = self.storage.mark_invalid(&hash);
                             results.push(result);
                         }
                         BlockStatus::HeaderOnly => {
@@ -159,6 +353,23 @@ impl BlockProcessor {
         self.virtual_processor.get_virtual_block_data(max_parents)
     }
 
+    /// Bits a block on `parents` must declare, given by the difficulty window
+    /// of the selected parent GHOSTDAG picks among them
+    pub fn expected_bits(&self, parents: &[Hash]) -> Result<u32, ConsensusError> {
+        let selected_parent = self.ghostdag_manager.get_virtual_ghostdag_data(parents.to_vec()).map_err(ConsensusError::Other)?.selected_parent;
+        self.header_processor.expected_bits(selected_parent)
+    }
+
+    /// Fee totals of up to `count` most recently accepted blocks, newest first
+    pub fn recent_block_fees(&self, count: usize) -> Vec<crate::consensus::storage::BlockFeeRecord> {
+        self.virtual_processor.recent_block_fees(count)
+    }
+
+    /// Per-stage processing time histograms of accepted blocks
+    pub fn timings(&self) -> Arc<ProcessingTimings> {
+        self.timings.clone()
+    }
+
     /// Get ghostdag manager reference
     pub fn ghostdag_manager(&self) -> Arc<GhostdagManager> {
         self.ghostdag_manager.clone()
@@ -181,6 +392,8 @@ pub struct BlockProcessingResult {
     pub total_fees: Option<u64>,
     /// Error message (if invalid)
     pub error: Option<String>,
+    /// Time spent in each pipeline stage
+    pub timings: BlockTimings,
 }
 
 impl BlockProcessingResult {
@@ -191,6 +404,7 @@ impl BlockProcessingResult {
             hash,
             total_fees: Some(total_fees),
             error: None,
+            timings: BlockTimings::default(),
         }
     }
 
@@ -201,6 +415,7 @@ impl BlockProcessingResult {
             hash,
             total_fees: None,
             error: Some(error),
+            timings: BlockTimings::default(),
         }
     }
 
@@ -211,6 +426,7 @@ impl BlockProcessingResult {
             hash,
             total_fees: None,
             error: None,
+            timings: BlockTimings::default(),
         }
     }
 
@@ -221,6 +437,7 @@ impl BlockProcessingResult {
             hash,
             total_fees: None,
             error: None,
+            timings: BlockTimings::default(),
         }
     }
 
@@ -235,3 +452,430 @@ impl BlockProcessingResult {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::dag::{BlockRelations, DagTopology, ReachabilityStore};
+    use crate::consensus::difficulty::DifficultyManager;
+    use crate::consensus::ghostdag::{stores::GhostdagStore, GhostdagProtocol};
+    use crate::consensus::types::ConsensusConfig;
+    use crate::consensus::validation::{BlockValidator, ContextualValidator, HeaderValidator, TransactionValidator};
+    use crate::process::coinbase::CoinbaseProcessor;
+    use consensus_core::constants::BLOCK_VERSION;
+    use consensus_core::hashing::header::validate_pow;
+    use consensus_core::header::Header;
+    use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+    use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+    use consensus_pow::target::work_from_bits;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn create_processor(genesis: Hash) -> BlockProcessor {
+        create_processor_with(genesis, |body_processor| body_processor)
+    }
+
+    /// Like [`create_processor`] with `configure` applied to the body processor
+    fn create_processor_with(genesis: Hash, configure: impl FnOnce(BodyProcessor) -> BodyProcessor) -> BlockProcessor {
+        create_processor_with_difficulty(genesis, DifficultyManager::new().with_initial_bits(EASIEST_BITS), configure)
+    }
+
+    /// Bits of the blocks tests mine unless their difficulty window is full
+    const EASIEST_BITS: u32 = 0x207fffff;
+
+    fn create_processor_with_difficulty(
+        genesis: Hash,
+        difficulty: DifficultyManager,
+        configure: impl FnOnce(BodyProcessor) -> BodyProcessor,
+    ) -> BlockProcessor {
+        let storage = Arc::new(ConsensusStorage::new());
+        storage.store_header(Header::from_precomputed_hash(genesis, vec![])).unwrap();
+
+        let relations = Arc::new(BlockRelations::new());
+        let reachability = Arc::new(ReachabilityStore::new());
+        let topology = Arc::new(DagTopology::new(relations.clone(), reachability.clone()));
+        let ghostdag_store = Arc::new(GhostdagStore::new());
+        let protocol = Arc::new(GhostdagProtocol::new(18, topology, relations.clone(), ghostdag_store.clone()));
+        let ghostdag_manager = Arc::new(GhostdagManager::new(protocol, ghostdag_store));
+        relations.add_block(genesis, vec![], 0);
+        reachability.init_genesis(genesis);
+        ghostdag_manager.init_genesis(&Header::from_precomputed_hash(genesis, vec![]));
+
+        let header_validator = Arc::new(HeaderValidator::new());
+        let transaction_validator = Arc::new(TransactionValidator::new());
+        let block_validator = Arc::new(BlockValidator::new(header_validator.clone(), transaction_validator.clone()));
+        let contextual_validator = Arc::new(ContextualValidator::new(block_validator.clone(), transaction_validator));
+        let deps_manager = Arc::new(DepsManager::new());
+
+        let header_processor = Arc::new(HeaderProcessor::new(
+            header_validator,
+            ghostdag_manager.clone(),
+            storage.block_store(),
+            Arc::new(difficulty),
+            deps_manager.clone(),
+        ));
+        let body_processor = Arc::new(configure(BodyProcessor::new(
+            block_validator,
+            contextual_validator,
+            storage.block_store(),
+            storage.utxo_set(),
+        )));
+        let virtual_processor = Arc::new(VirtualProcessor::new(ghostdag_manager.clone(), storage.block_store()));
+        BlockProcessor::new(header_processor, body_processor, virtual_processor, ghostdag_manager, storage, deps_manager)
+    }
+
+    /// Block on top of `parent` with a coinbase and a nonce satisfying the easiest target
+    fn mine_block(parent: Hash) -> Block {
+        mine_block_at(parent, 1, Vec::new())
+    }
+
+    /// Like [`mine_block`] for a block with blue score `blue_score` on a chain
+    /// of such blocks, with `payload` telling coinbases of siblings apart
+    fn mine_block_at(parent: Hash, blue_score: u64, payload: Vec<u8>) -> Block {
+        let coinbase = Transaction::new(
+            0,
+            Vec::new(),
+            vec![TransactionOutput::new(5000000000, ScriptPublicKey::from_vec(0, Vec::new()))],
+            0,
+            SUBNETWORK_ID_COINBASE,
+            0,
+            payload,
+        );
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        // Genesis has no bits here, so only the mined ancestors add work
+        let blue_work = (1..blue_score).fold(BlueWorkType::from(0u64), |work, _| work + work_from_bits(EASIEST_BITS));
+        let mut header = Header::new_finalized(
+            BLOCK_VERSION,
+            vec![vec![parent]],
+            ZERO_HASH,
+            ZERO_HASH,
+            ZERO_HASH,
+            timestamp,
+            EASIEST_BITS,
+            0,
+            blue_score,
+            blue_work,
+            blue_score,
+            ZERO_HASH,
+        );
+        while !validate_pow(&header) {
+            header.nonce += 1;
+            header.finalize();
+        }
+        Block::new(header, vec![coinbase])
+    }
+
+    #[test]
+    fn test_process_block_records_stage_timings() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let processor = create_processor(genesis);
+
+        let result = processor.process_block(mine_block(genesis)).unwrap();
+        assert!(result.is_valid(), "block rejected: {:?}", result.error);
+        for stage in ProcessingStage::ALL {
+            assert!(result.timings.get(stage) > std::time::Duration::ZERO, "{} not timed", stage.name());
+        }
+
+        // Accepted blocks land in every stage histogram
+        for stage in ProcessingStage::ALL {
+            let snapshot = processor.timings().snapshot(stage);
+            assert_eq!(snapshot.count, 1);
+            assert!(snapshot.sum > 0.0);
+            assert!(snapshot.buckets.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        }
+
+        // The block extends the selected chain
+        assert_eq!(processor.storage().block_store().get_chain_tip().map(|(_, hash)| hash), Some(result.hash));
+
+        // Blocks already known are not timed again
+        let block = processor.storage().get_block(&result.hash).unwrap();
+        let again = processor.process_block(block).unwrap();
+        assert_eq!(again.timings, BlockTimings::default());
+        assert_eq!(processor.timings().snapshot(ProcessingStage::Ghostdag).count, 1);
+    }
+
+    #[test]
+    fn test_chain_changes_report_reorgs() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let processor = create_processor(genesis);
+        let changes = Arc::new(RwLock::new(Vec::new()));
+        let sink = changes.clone();
+        processor.subscribe_chain_changes(Arc::new(move |change: &ChainChanges| sink.write().unwrap().push(change.clone())));
+
+        let a1 = mine_block_at(genesis, 1, vec![1]);
+        let b1 = mine_block_at(genesis, 1, vec![2]);
+        let b2 = mine_block_at(b1.header.hash, 2, vec![3]);
+        for block in [&a1, &b1, &b2] {
+            assert!(processor.process_block(block.clone()).unwrap().is_valid());
+        }
+
+        // The first block indexes genesis along with it, b1 ties with a1 and
+        // leaves the chain alone, and b2 reorgs a1 away
+        let (a1, b1, b2) = (a1.header.hash, b1.header.hash, b2.header.hash);
+        assert_eq!(
+            *changes.read().unwrap(),
+            vec![
+                ChainChanges { removed: vec![], added: vec![genesis, a1], blue_score: 1 },
+                ChainChanges { removed: vec![a1], added: vec![b1, b2], blue_score: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_block_from_records_provenance() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let processor = create_processor(genesis);
+        let peer: std::net::SocketAddr = "10.0.0.1:16111".parse().unwrap();
+
+        let block = mine_block(genesis);
+        let (result, provenance) = processor.process_block_from(block.clone(), BlockSource::Peer(peer));
+        assert!(result.unwrap().is_valid());
+        let provenance = provenance.unwrap();
+        assert_eq!(provenance.source, BlockSource::Peer(peer));
+        assert!(!provenance.invalid);
+
+        // Later deliveries keep the first source
+        let (_, again) = processor.process_block_from(block, BlockSource::Rpc);
+        assert_eq!(again, Some(provenance));
+
+        let mut bad_pow = mine_block_at(genesis, 1, vec![1]);
+        while validate_pow(&bad_pow.header) {
+            bad_pow.header.nonce += 1;
+            bad_pow.header.finalize();
+        }
+        let (_, provenance) = processor.process_block_from(bad_pow.clone(), BlockSource::Peer(peer));
+        assert!(provenance.unwrap().invalid);
+        assert!(processor.storage().get_provenance(&bad_pow.header.hash).unwrap().invalid);
+    }
+
+    #[test]
+    fn test_process_batch_matches_serial_processing() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let first = mine_block(genesis);
+        let second = mine_block_at(first.header.hash, 2, Vec::new());
+        let mut bad_pow = mine_block_at(first.header.hash, 2, Vec::new());
+        while validate_pow(&bad_pow.header) {
+            bad_pow.header.nonce += 1;
+            bad_pow.header.finalize();
+        }
+        let orphan = mine_block(Hash::from_le_u64([9, 9, 9, 9]));
+        let blocks = vec![first, bad_pow, second, orphan];
+
+        let serial = create_processor(genesis);
+        let expected: Vec<_> = blocks.iter().map(|block| serial.process_block(block.clone()).map(|r| (r.hash, r.status))).collect();
+
+        let batched = create_processor(genesis);
+        let flow = crate::pipeline::flow::ValidationFlow::new(Arc::new(BlockValidator::new(
+            Arc::new(HeaderValidator::new()),
+            Arc::new(TransactionValidator::new()),
+        )))
+        .with_threads(4);
+        let results = flow.process_batch(&batched, blocks);
+        let results: Vec<_> = results.into_iter().map(|result| result.map(|r| (r.hash, r.status))).collect();
+
+        assert_eq!(format!("{:?}", results), format!("{:?}", expected));
+        assert!(matches!(results[1], Err(ConsensusError::InvalidProofOfWork)));
+        let statuses: Vec<_> = results.iter().filter_map(|result| result.as_ref().ok().map(|r| r.1)).collect();
+        assert_eq!(statuses, vec![BlockStatus::Valid, BlockStatus::Valid, BlockStatus::Orphan]);
+        assert_eq!(batched.storage().block_store().get_chain_tip(), serial.storage().block_store().get_chain_tip());
+    }
+
+    #[test]
+    fn test_process_blocks_matches_serial_processing() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let siblings: Vec<Block> = (1..=3u8).map(|tag| mine_block_at(genesis, 1, vec![tag])).collect();
+        let child = mine_block_at(siblings[0].header.hash, 2, vec![4]);
+        let orphan = mine_block_at(Hash::from_le_u64([9, 9, 9, 9]), 1, vec![5]);
+        let mut blocks = siblings;
+        blocks.extend([child, orphan]);
+        blocks.push(blocks[1].clone());
+
+        let serial = create_processor(genesis);
+        let expected: Vec<_> = blocks.iter().map(|block| serial.process_block(block.clone()).map(|r| (r.hash, r.status))).collect();
+
+        let batched = create_processor(genesis).with_body_threads(4);
+        let results: Vec<_> = batched.process_blocks(blocks).into_iter().map(|result| result.map(|r| (r.hash, r.status))).collect();
+        assert_eq!(format!("{:?}", results), format!("{:?}", expected));
+
+        let statuses: Vec<_> = results.into_iter().map(|result| result.unwrap().1).collect();
+        assert_eq!(statuses[..4], [BlockStatus::Valid; 4]);
+        assert_eq!(statuses[4], BlockStatus::Orphan);
+        assert_eq!(batched.storage().block_store().get_chain_tip(), serial.storage().block_store().get_chain_tip());
+        assert_eq!(batched.storage().utxo_set().len(), serial.storage().utxo_set().len());
+        assert_eq!(batched.timings().snapshot(ProcessingStage::BodyValidation).count, 4);
+    }
+
+    /// Re-mine `block` after `tamper` changes its header
+    fn remine(mut block: Block, tamper: impl Fn(&mut Header)) -> Block {
+        tamper(&mut block.header);
+        block.header.finalize();
+        while !validate_pow(&block.header) {
+            block.header.nonce += 1;
+            block.header.finalize();
+        }
+        block
+    }
+
+    #[test]
+    fn test_coinbase_must_commit_to_the_block_height() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let coinbase_processor = || CoinbaseProcessor::new(ConsensusConfig::default());
+        let processor = create_processor_with(genesis, |body| body.with_coinbase_processor(coinbase_processor()));
+
+        // Blocks at height 1 committing to height 2, or to no height at all;
+        // headers don't commit to the payload, so their timestamps tell them apart
+        let wrong_height = mine_block_at(genesis, 1, coinbase_processor().coinbase_payload(2, Vec::new()).to_bytes());
+        let timestamp = wrong_height.header.timestamp;
+        assert!(matches!(
+            processor.process_block(wrong_height),
+            Err(ConsensusError::InvalidCoinbaseHeight { declared: 2, expected: 1 })
+        ));
+        let legacy = remine(mine_block_at(genesis, 1, b"Block 1".to_vec()), |header| header.timestamp = timestamp + 1);
+        assert!(matches!(processor.process_block(legacy), Err(ConsensusError::InvalidCoinbasePayload(_))));
+
+        let payload = coinbase_processor().coinbase_payload(1, b"pool/42".to_vec()).to_bytes();
+        let valid = remine(mine_block_at(genesis, 1, payload), |header| header.timestamp = timestamp + 2);
+        assert!(processor.process_block(valid).unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_headers_must_commit_to_their_ghostdag_data() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let processor = create_processor(genesis);
+        let first = processor.process_block(mine_block(genesis)).unwrap();
+        assert!(first.is_valid());
+
+        let honest = mine_block_at(first.hash, 2, Vec::new());
+        let inflated = remine(honest.clone(), |header| header.blue_work = BlueWorkType::from(1u64 << 40));
+        assert!(matches!(
+            processor.process_block(inflated.clone()),
+            Err(ConsensusError::InvalidBlueWork { declared, expected }) if declared == inflated.header.blue_work && expected == work_from_bits(EASIEST_BITS)
+        ));
+        let blue_score = remine(honest.clone(), |header| header.blue_score = 7);
+        assert!(matches!(
+            processor.process_block(blue_score),
+            Err(ConsensusError::InvalidBlueScore { declared: 7, expected: 2 })
+        ));
+        let daa_score = remine(honest.clone(), |header| header.daa_score = 1);
+        assert!(matches!(
+            processor.process_block(daa_score),
+            Err(ConsensusError::InvalidDaaScore { declared: 1, expected: 2 })
+        ));
+
+        // Rejected headers leave no GHOSTDAG data behind
+        assert!(processor.ghostdag_manager().get_ghostdag_data(&inflated.header.hash).is_none());
+        let honest_hash = honest.header.hash;
+        assert!(processor.process_block(honest).unwrap().is_valid());
+
+        // Blue work grows with each block merging another as blue
+        let third = mine_block_at(honest_hash, 3, Vec::new());
+        assert!(processor.process_block(third.clone()).unwrap().is_valid());
+        let works: Vec<BlueWorkType> = [first.hash, honest_hash, third.header.hash]
+            .iter()
+            .map(|hash| processor.ghostdag_manager().get_ghostdag_data(hash).unwrap().blue_work)
+            .collect();
+        assert_eq!(works[1], work_from_bits(EASIEST_BITS));
+        assert!(works[0] < works[1] && works[1] < works[2]);
+        assert_eq!(third.header.blue_work, works[2]);
+    }
+
+    #[test]
+    fn test_blocks_built_from_virtual_block_data_validate() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let processor = create_processor(genesis);
+        for height in 1..=3 {
+            // Fill the header in as block templates do
+            let virtual_data = processor.get_virtual_block_data(4).unwrap();
+            let block = remine(mine_block_at(genesis, 0, vec![height]), |header| {
+                header.parents_by_level = vec![virtual_data.parents.clone()];
+                header.blue_score = virtual_data.ghostdag_data.blue_score;
+                header.daa_score = virtual_data.ghostdag_data.blue_score;
+                header.blue_work = virtual_data.ghostdag_data.blue_work;
+            });
+            let blue_score = block.header.blue_score;
+            let result = processor.process_block(block).unwrap();
+            assert!(result.is_valid(), "block rejected: {:?}", result.error);
+            assert_eq!(processor.ghostdag_manager().get_blue_score(&result.hash), Some(blue_score));
+        }
+    }
+
+    /// Block on `parent` at `timestamp` declaring `bits`, committing to the GHOSTDAG data `processor` gives it
+    fn mine_block_on(processor: &BlockProcessor, parent: Hash, timestamp: u64, bits: u32) -> Block {
+        let ghostdag = processor.ghostdag_manager().get_virtual_ghostdag_data(vec![parent]).unwrap();
+        let coinbase = Transaction::new(
+            0,
+            Vec::new(),
+            vec![TransactionOutput::new(5000000000, ScriptPublicKey::from_vec(0, Vec::new()))],
+            0,
+            SUBNETWORK_ID_COINBASE,
+            0,
+            timestamp.to_le_bytes().to_vec(),
+        );
+        let mut header = Header::new_finalized(
+            BLOCK_VERSION,
+            vec![vec![parent]],
+            ZERO_HASH,
+            ZERO_HASH,
+            ZERO_HASH,
+            timestamp,
+            bits,
+            0,
+            ghostdag.blue_score,
+            ghostdag.blue_work,
+            ghostdag.blue_score,
+            ZERO_HASH,
+        );
+        while !validate_pow(&header) {
+            header.nonce += 1;
+            header.finalize();
+        }
+        Block::new(header, vec![coinbase])
+    }
+
+    #[test]
+    fn test_difficulty_follows_the_selected_parent_window() {
+        // Windows of 3 blocks, one second apart at the target pace
+        const INITIAL_BITS: u32 = 0x2000ffff;
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let difficulty = DifficultyManager::with_params(1, 3).with_initial_bits(INITIAL_BITS);
+        let processor = create_processor_with_difficulty(genesis, difficulty, |body_processor| body_processor);
+        let start = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 - 3_600_000;
+
+        // Two chains off genesis, one mined four times faster than the target pace
+        // and one twice slower. Their first blocks' windows cross genesis, so
+        // they keep the initial bits.
+        let mut tips = Vec::new();
+        for (spacing, salt) in [(250, 0), (2000, 1)] {
+            let mut tip = genesis;
+            for n in 1..=3 {
+                let block = mine_block_on(&processor, tip, start + n * spacing + salt, INITIAL_BITS);
+                let result = processor.process_block(block).unwrap();
+                assert!(result.is_valid(), "block rejected: {:?}", result.error);
+                tip = result.hash;
+            }
+            tips.push(tip);
+        }
+        let (fast, slow) = (tips[0], tips[1]);
+
+        // The fast chain's full window quarters its target; the slow one's would
+        // double it but is held at the initial, easiest allowed, difficulty
+        let fast_bits = processor.expected_bits(&[fast]).unwrap();
+        let expected_target = consensus_pow::target::target_from_bits(INITIAL_BITS) / 4;
+        assert_eq!(fast_bits, consensus_pow::target::target_to_bits(expected_target));
+        assert_eq!(processor.expected_bits(&[slow]).unwrap(), INITIAL_BITS);
+
+        // Each tip's children are validated against their own selected parent's window
+        let timestamp = start + 10_000;
+        let wrong = mine_block_on(&processor, fast, timestamp, INITIAL_BITS);
+        assert!(matches!(
+            processor.process_block(wrong),
+            Err(ConsensusError::WrongDifficulty { declared: INITIAL_BITS, expected }) if expected == fast_bits
+        ));
+        let wrong = mine_block_on(&processor, slow, timestamp, fast_bits);
+        assert!(matches!(processor.process_block(wrong), Err(ConsensusError::WrongDifficulty { .. })));
+        for (tip, bits, salt) in [(fast, fast_bits, 0), (slow, INITIAL_BITS, 1)] {
+            let result = processor.process_block(mine_block_on(&processor, tip, timestamp + salt, bits)).unwrap();
+            assert!(result.is_valid(), "block rejected: {:?}", result.error);
+        }
+    }
+}
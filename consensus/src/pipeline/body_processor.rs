@@ -7,6 +7,7 @@ use consensus_core::Hash;
 use consensus_core::errors::ConsensusError;
 use crate::consensus::validation::{BlockValidator, ContextualValidator};
 use crate::consensus::storage::{BlockStore, UtxoSet};
+use crate::process::coinbase::CoinbaseProcessor;
 use crate::consensus::validation::transaction_validator::UtxoView;
 use std::sync::Arc;
 use std::collections::HashMap;
@@ -17,6 +18,8 @@ pub struct BodyProcessor {
     contextual_validator: Arc<ContextualValidator>,
     block_store: Arc<BlockStore>,
     utxo_set: Arc<UtxoSet>,
+    /// Checks coinbase payloads against the subsidy schedule, when set
+    coinbase_processor: Option<CoinbaseProcessor>,
 }
 
 impl BodyProcessor {
@@ -32,9 +35,26 @@ impl BodyProcessor {
             contextual_validator,
             block_store,
             utxo_set,
+            coinbase_processor: None,
         }
     }
 
+    /// Require coinbase payloads to commit to the block height and the
+    /// subsidy `coinbase_processor` gives there
+    pub fn with_coinbase_processor(self, coinbase_processor: CoinbaseProcessor) -> Self {
+        Self { coinbase_processor: Some(coinbase_processor), ..self }
+    }
+
+    /// Validate the payload of the coinbase of `block`, at height `block_daa_score`
+    fn validate_coinbase_payload(&self, block: &Block, block_daa_score: u64) -> Result<(), ConsensusError> {
+        let Some(coinbase_processor) = &self.coinbase_processor else {
+            return Ok(());
+        };
+        let coinbase = block.transactions.first().ok_or(ConsensusError::EmptyTransactionList)?;
+        coinbase_processor.validate_coinbase_payload(coinbase, block_daa_score)?;
+        Ok(())
+    }
+
     /// Process block body (transactions)
     pub fn process_body(&self, block: &Block, block_daa_score: u64) -> Result<BodyProcessingResult, ConsensusError> {
         let hash = block.header.hash;
@@ -46,6 +66,7 @@ impl BodyProcessor {
 
         // Validate block structure
         self.block_validator.validate_block(block)?;
+        self.validate_coinbase_payload(block, block_daa_score)?;
 
         // Create UTXO view from current UTXO set snapshot
         let utxo_snapshot = self.utxo_set.snapshot();
@@ -74,6 +95,7 @@ impl BodyProcessor {
     pub fn validate_body(&self, block: &Block, block_daa_score: u64) -> Result<u64, ConsensusError> {
         // Validate block structure
         self.block_validator.validate_block(block)?;
+        self.validate_coinbase_payload(block, block_daa_score)?;
 
         // Create UTXO view from current UTXO set snapshot
         let utxo_snapshot = self.utxo_set.snapshot();
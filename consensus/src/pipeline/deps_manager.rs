@@ -1,13 +1,18 @@
 //! Dependency manager for orphan block handling
 //!
 //! This module manages orphan blocks (blocks with missing parents) and
-//! resolves dependencies when parents become available.
+//! resolves dependencies when parents become available. It also schedules
+//! body validation: bodies touching disjoint outpoints run concurrently,
+//! while bodies sharing one run in the order they were queued.
 
 use consensus_core::block::Block;
 use consensus_core::header::Header;
+use consensus_core::tx::TransactionOutpoint;
 use consensus_core::Hash;
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
 
 /// Dependency manager for orphan blocks
 pub struct DepsManager {
@@ -17,6 +22,17 @@ pub struct DepsManager {
     orphan_headers: Arc<RwLock<HashMap<Hash, Header>>>,
     /// Blocks waiting for specific parent hashes
     waiting_for_parents: Arc<RwLock<HashMap<Hash, Vec<Hash>>>>,
+    /// Bodies queued for validation by the outpoints they touch
+    outpoint_queues: Arc<RwLock<OutpointQueues>>,
+}
+
+/// Bodies queued per outpoint they spend or create, oldest first. A body may
+/// run once it is at the front of every one of its queues.
+#[derive(Default)]
+struct OutpointQueues {
+    queues: HashMap<TransactionOutpoint, VecDeque<Hash>>,
+    /// Outpoints of each queued body and how many of their queues it still waits in
+    bodies: HashMap<Hash, (Vec<TransactionOutpoint>, usize)>,
 }
 
 impl DepsManager {
@@ -26,6 +42,7 @@ impl DepsManager {
             orphans: Arc::new(RwLock::new(HashMap::new())),
             orphan_headers: Arc::new(RwLock::new(HashMap::new())),
             waiting_for_parents: Arc::new(RwLock::new(HashMap::new())),
+            outpoint_queues: Arc::new(RwLock::new(OutpointQueues::default())),
         }
     }
 
@@ -152,6 +169,125 @@ impl DepsManager {
         orphan_headers.len()
     }
 
+    /// Queue `block`'s body behind every queued body spending or creating an
+    /// outpoint it also touches. Returns whether it may be validated right away;
+    /// otherwise [`Self::release_body`] hands it out once it may.
+    pub fn queue_body(&self, block: &Block) -> bool {
+        let hash = block.header.hash;
+        let mut outpoints = HashSet::new();
+        for tx in &block.transactions {
+            if !tx.is_coinbase() {
+                outpoints.extend(tx.inputs.iter().map(|input| input.previous_outpoint));
+            }
+            let id = tx.id();
+            outpoints.extend((0..tx.outputs.len()).map(|index| TransactionOutpoint::new(id, index as u32)));
+        }
+
+        let mut state = self.outpoint_queues.write().unwrap();
+        let mut waiting = 0;
+        for outpoint in &outpoints {
+            let queue = state.queues.entry(*outpoint).or_default();
+            if !queue.is_empty() {
+                waiting += 1;
+            }
+            queue.push_back(hash);
+        }
+        state.bodies.insert(hash, (outpoints.into_iter().collect(), waiting));
+        waiting == 0
+    }
+
+    /// Remove a validated body from its queues, returning the bodies that may
+    /// now be validated in the order they were queued
+    pub fn release_body(&self, hash: &Hash) -> Vec<Hash> {
+        let mut state = self.outpoint_queues.write().unwrap();
+        let Some((outpoints, _)) = state.bodies.remove(hash) else {
+            return Vec::new();
+        };
+
+        let mut released = Vec::new();
+        for outpoint in outpoints {
+            let queue = state.queues.get_mut(&outpoint).expect("queued body has a queue per outpoint");
+            queue.retain(|queued| queued != hash);
+            let next = queue.front().copied();
+            if queue.is_empty() {
+                state.queues.remove(&outpoint);
+            }
+            if let Some(next) = next {
+                let entry = state.bodies.get_mut(&next).expect("queued body is tracked");
+                entry.1 -= 1;
+                if entry.1 == 0 {
+                    released.push(next);
+                }
+            }
+        }
+        released
+    }
+
+    /// Number of bodies queued and not yet released
+    pub fn queued_body_count(&self) -> usize {
+        self.outpoint_queues.read().unwrap().bodies.len()
+    }
+
+    /// Run `process` on every body in `blocks` across up to `threads` threads,
+    /// scheduling them with [`Self::queue_body`] and [`Self::release_body`].
+    /// Results are in input order.
+    pub fn process_bodies<R, F>(&self, blocks: &[Block], threads: usize, process: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(&Block) -> R + Sync,
+    {
+        let index: HashMap<Hash, usize> = blocks.iter().enumerate().map(|(i, block)| (block.header.hash, i)).collect();
+        assert_eq!(index.len(), blocks.len(), "bodies processed together must be distinct");
+
+        struct Progress<R> {
+            ready: VecDeque<usize>,
+            results: Vec<Option<thread::Result<R>>>,
+            remaining: usize,
+        }
+        let ready = blocks.iter().enumerate().filter(|(_, block)| self.queue_body(block)).map(|(i, _)| i).collect();
+        let progress = Mutex::new(Progress { ready, results: blocks.iter().map(|_| None).collect(), remaining: blocks.len() });
+        let wakeup = Condvar::new();
+
+        let worker = || loop {
+            let i = {
+                let mut progress = progress.lock().unwrap();
+                loop {
+                    if let Some(i) = progress.ready.pop_front() {
+                        break i;
+                    }
+                    if progress.remaining == 0 {
+                        return;
+                    }
+                    progress = wakeup.wait(progress).unwrap();
+                }
+            };
+            // Bodies waiting on a panicking one must still be released
+            let result = panic::catch_unwind(AssertUnwindSafe(|| process(&blocks[i])));
+            let released = self.release_body(&blocks[i].header.hash);
+
+            let mut progress = progress.lock().unwrap();
+            progress.results[i] = Some(result);
+            progress.remaining -= 1;
+            progress.ready.extend(released.iter().map(|hash| index[hash]));
+            wakeup.notify_all();
+        };
+        thread::scope(|scope| {
+            for _ in 1..threads.min(blocks.len()) {
+                scope.spawn(worker);
+            }
+            worker();
+        });
+
+        let results = progress.into_inner().unwrap().results;
+        results
+            .into_iter()
+            .map(|result| match result.expect("every body is processed") {
+                Ok(result) => result,
+                Err(payload) => panic::resume_unwind(payload),
+            })
+            .collect()
+    }
+
     /// Clear all orphans (for testing)
     pub fn clear(&self) {
         let mut orphans = self.orphans.write().unwrap();
@@ -172,7 +308,10 @@ impl Default for DepsManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutput};
     use consensus_core::{ZERO_HASH, BlueWorkType};
+    use std::time::{Duration, Instant};
 
     fn create_test_block(parents: Vec<Hash>) -> Block {
         let header = Header::new_finalized(
@@ -235,5 +374,91 @@ mod tests {
         known_blocks.insert(parent2);
         assert!(deps.all_parents_exist(&block, &|h| known_blocks.contains(h)));
     }
-}
 
+    /// Block `tag` whose one transaction spends `spends` and creates one output
+    fn create_body(tag: u64, spends: Vec<TransactionOutpoint>) -> Block {
+        let inputs = spends.into_iter().map(|outpoint| TransactionInput::new(outpoint, Vec::new(), 0, 0)).collect();
+        let outputs = vec![TransactionOutput::new(tag, ScriptPublicKey::from_vec(0, Vec::new()))];
+        let tx = Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, tag.to_le_bytes().to_vec());
+        let mut block = create_test_block(vec![Hash::from_le_u64([tag, 0, 0, 0])]);
+        block.header.timestamp = tag;
+        block.header.finalize();
+        block.transactions.push(tx);
+        block
+    }
+
+    fn output_of(block: &Block) -> TransactionOutpoint {
+        TransactionOutpoint::new(block.transactions[0].id(), 0)
+    }
+
+    #[test]
+    fn test_bodies_touching_the_same_outpoint_are_released_in_order() {
+        let deps = DepsManager::new();
+        let funding = TransactionOutpoint::new(Hash::from_le_u64([7, 0, 0, 0]), 0);
+        let a = create_body(1, vec![funding]);
+        let b = create_body(2, vec![output_of(&a)]);
+        let c = create_body(3, vec![]);
+        let d = create_body(4, vec![output_of(&a), funding]);
+
+        assert!(deps.queue_body(&a));
+        assert!(!deps.queue_body(&b));
+        assert!(deps.queue_body(&c));
+        assert!(!deps.queue_body(&d));
+        assert_eq!(deps.queued_body_count(), 4);
+
+        assert!(deps.release_body(&c.header.hash).is_empty());
+        // `d` also waits behind `b` on `a`'s output
+        assert_eq!(deps.release_body(&a.header.hash), vec![b.header.hash]);
+        assert_eq!(deps.release_body(&b.header.hash), vec![d.header.hash]);
+        assert!(deps.release_body(&d.header.hash).is_empty());
+        assert_eq!(deps.queued_body_count(), 0);
+        assert!(deps.release_body(&d.header.hash).is_empty());
+    }
+
+    #[test]
+    fn test_process_bodies_serializes_dependent_blocks() {
+        let deps = DepsManager::new();
+        let mut chain = vec![create_body(1, vec![])];
+        for tag in 2..=4 {
+            let parent = output_of(chain.last().unwrap());
+            chain.push(create_body(tag, vec![parent]));
+        }
+
+        let order = Mutex::new(Vec::new());
+        let start = Instant::now();
+        let results = deps.process_bodies(&chain, 4, |block| {
+            thread::sleep(Duration::from_millis(20));
+            order.lock().unwrap().push(block.header.hash);
+            block.header.timestamp
+        });
+        assert!(start.elapsed() >= Duration::from_millis(80));
+        assert_eq!(results, vec![1, 2, 3, 4]);
+        assert_eq!(order.into_inner().unwrap(), chain.iter().map(|block| block.header.hash).collect::<Vec<_>>());
+        assert_eq!(deps.queued_body_count(), 0);
+    }
+
+    #[test]
+    fn test_process_bodies_runs_independent_blocks_concurrently() {
+        let deps = DepsManager::new();
+        let blocks: Vec<Block> = (1..=8).map(|tag| create_body(tag, vec![])).collect();
+        let work = Duration::from_millis(50);
+
+        let start = Instant::now();
+        let results = deps.process_bodies(&blocks, 1, |block| {
+            thread::sleep(work);
+            block.header.timestamp
+        });
+        let serial = start.elapsed();
+        assert_eq!(results, (1..=8).collect::<Vec<_>>());
+
+        let start = Instant::now();
+        let results = deps.process_bodies(&blocks, 4, |block| {
+            thread::sleep(work);
+            block.header.timestamp
+        });
+        let parallel = start.elapsed();
+        assert_eq!(results, (1..=8).collect::<Vec<_>>());
+        assert!(serial >= work * 8);
+        assert!(parallel < work * 4, "4 threads took {:?}", parallel);
+    }
+}
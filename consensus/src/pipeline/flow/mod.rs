@@ -6,6 +6,6 @@
 pub mod process_queue;
 pub mod validation_flow;
 
-pub use process_queue::ProcessQueue;
+pub use process_queue::{EnqueueResult, OverflowPolicy, ProcessQueue, ProcessQueueStats};
 pub use validation_flow::ValidationFlow;
 
@@ -1,77 +1,146 @@
 //! Process queue for block processing
 //!
-//! This module provides a queue for managing block processing order.
+//! This module provides a queue for managing block processing order. The queue
+//! is bounded so that a peer flooding blocks (during IBD, say) can't grow it
+//! without limit: once it holds `capacity` blocks, producers either wait for
+//! the consumer to make room or have their block dropped, per [`OverflowPolicy`].
 
 use consensus_core::block::Block;
 use consensus_core::Hash;
-use std::collections::VecDeque;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// Blocks a queue holds unless configured otherwise
+pub const DEFAULT_PROCESS_QUEUE_CAPACITY: usize = 1024;
+
+/// What `enqueue` does when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Block the producer until a block is dequeued
+    Wait,
+    /// Drop the incoming block
+    Drop,
+}
+
+/// Outcome of `enqueue`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnqueueResult {
+    Queued,
+    /// The block was already queued
+    Duplicate,
+    /// The queue was full and the policy is [`OverflowPolicy::Drop`]
+    Dropped,
+}
+
+/// Queue depth and drops, for metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessQueueStats {
+    pub depth: usize,
+    pub capacity: usize,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    queue: VecDeque<Block>,
+    pending: HashSet<Hash>,
+}
 
 /// Process queue for blocks
 pub struct ProcessQueue {
-    queue: Arc<RwLock<VecDeque<Block>>>,
-    pending: Arc<RwLock<std::collections::HashSet<Hash>>>,
+    inner: Mutex<Inner>,
+    /// Signalled whenever room is made
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicU64,
 }
 
 impl ProcessQueue {
-    /// Create a new process queue
+    /// Create a new process queue holding up to [`DEFAULT_PROCESS_QUEUE_CAPACITY`] blocks
     pub fn new() -> Self {
         Self {
-            queue: Arc::new(RwLock::new(VecDeque::new())),
-            pending: Arc::new(RwLock::new(std::collections::HashSet::new())),
+            inner: Mutex::new(Inner::default()),
+            not_full: Condvar::new(),
+            capacity: DEFAULT_PROCESS_QUEUE_CAPACITY,
+            policy: OverflowPolicy::Wait,
+            dropped: AtomicU64::new(0),
         }
     }
 
-    /// Add a block to the queue
-    pub fn enqueue(&self, block: Block) {
+    /// Hold up to `capacity` blocks, at least one
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), ..self }
+    }
+
+    pub fn with_overflow_policy(self, policy: OverflowPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Add a block to the queue. When full, waits for room or drops the block,
+    /// depending on the overflow policy.
+    pub fn enqueue(&self, block: Block) -> EnqueueResult {
         let hash = block.header.hash;
-        let mut queue = self.queue.write().unwrap();
-        let mut pending = self.pending.write().unwrap();
-        
-        if !pending.contains(&hash) {
-            queue.push_back(block);
-            pending.insert(hash);
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if inner.pending.contains(&hash) {
+                return EnqueueResult::Duplicate;
+            }
+            if inner.queue.len() < self.capacity {
+                break;
+            }
+            match self.policy {
+                OverflowPolicy::Wait => inner = self.not_full.wait(inner).unwrap(),
+                OverflowPolicy::Drop => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return EnqueueResult::Dropped;
+                }
+            }
         }
+        inner.queue.push_back(block);
+        inner.pending.insert(hash);
+        EnqueueResult::Queued
     }
 
     /// Remove and return the next block from the queue
     pub fn dequeue(&self) -> Option<Block> {
-        let mut queue = self.queue.write().unwrap();
-        let mut pending = self.pending.write().unwrap();
-        
-        if let Some(block) = queue.pop_front() {
-            let hash = block.header.hash;
-            pending.remove(&hash);
-            Some(block)
-        } else {
-            None
-        }
+        let mut inner = self.inner.lock().unwrap();
+        let block = inner.queue.pop_front()?;
+        inner.pending.remove(&block.header.hash);
+        self.not_full.notify_one();
+        Some(block)
     }
 
     /// Check if the queue is empty
     pub fn is_empty(&self) -> bool {
-        let queue = self.queue.read().unwrap();
-        queue.is_empty()
+        self.inner.lock().unwrap().queue.is_empty()
     }
 
     /// Get the number of blocks in the queue
     pub fn len(&self) -> usize {
-        let queue = self.queue.read().unwrap();
-        queue.len()
+        self.inner.lock().unwrap().queue.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> ProcessQueueStats {
+        ProcessQueueStats { depth: self.len(), capacity: self.capacity, dropped: self.dropped.load(Ordering::Relaxed) }
     }
 
     /// Check if a block is pending
     pub fn is_pending(&self, hash: &Hash) -> bool {
-        let pending = self.pending.read().unwrap();
-        pending.contains(hash)
+        self.inner.lock().unwrap().pending.contains(hash)
     }
 
     /// Clear the queue
     pub fn clear(&self) {
-        let mut queue = self.queue.write().unwrap();
-        let mut pending = self.pending.write().unwrap();
-        queue.clear();
-        pending.clear();
+        let mut inner = self.inner.lock().unwrap();
+        inner.queue.clear();
+        inner.pending.clear();
+        self.not_full.notify_all();
     }
 }
 
@@ -85,15 +154,23 @@ impl Default for ProcessQueue {
 mod tests {
     use super::*;
     use consensus_core::{ZERO_HASH, BlueWorkType};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
 
     fn create_test_block() -> Block {
+        create_block_at(1000)
+    }
+
+    fn create_block_at(timestamp: u64) -> Block {
         let header = consensus_core::header::Header::new_finalized(
             1,
             vec![],
             ZERO_HASH,
             ZERO_HASH,
             ZERO_HASH,
-            1000,
+            timestamp,
             0x1f00ffff,
             0,
             0,
@@ -131,5 +208,50 @@ mod tests {
 
         assert_eq!(queue.len(), 1);
     }
-}
 
+    #[test]
+    fn test_full_queue_drops() {
+        let queue = ProcessQueue::new().with_capacity(2).with_overflow_policy(OverflowPolicy::Drop);
+        let blocks: Vec<Block> = (0..5).map(|i| create_block_at(1000 + i)).collect();
+
+        assert_eq!(queue.enqueue(blocks[0].clone()), EnqueueResult::Queued);
+        assert_eq!(queue.enqueue(blocks[1].clone()), EnqueueResult::Queued);
+        for block in &blocks[2..] {
+            assert_eq!(queue.enqueue(block.clone()), EnqueueResult::Dropped);
+        }
+        assert_eq!(queue.enqueue(blocks[0].clone()), EnqueueResult::Duplicate);
+        assert_eq!(queue.stats(), ProcessQueueStats { depth: 2, capacity: 2, dropped: 3 });
+        assert!(!queue.is_pending(&blocks[2].header.hash));
+
+        // Room again once the consumer catches up
+        queue.dequeue().unwrap();
+        assert_eq!(queue.enqueue(blocks[2].clone()), EnqueueResult::Queued);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_full_queue_makes_producer_wait() {
+        let queue = Arc::new(ProcessQueue::new().with_capacity(2));
+        queue.enqueue(create_block_at(1000));
+        queue.enqueue(create_block_at(1001));
+
+        let done = Arc::new(AtomicBool::new(false));
+        let producer = {
+            let (queue, done) = (queue.clone(), done.clone());
+            thread::spawn(move || {
+                let result = queue.enqueue(create_block_at(1002));
+                done.store(true, Ordering::SeqCst);
+                result
+            })
+        };
+
+        // The producer is held back rather than growing the queue
+        thread::sleep(Duration::from_millis(100));
+        assert!(!done.load(Ordering::SeqCst));
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.dequeue().unwrap().header.timestamp, 1000);
+        assert_eq!(producer.join().unwrap(), EnqueueResult::Queued);
+        assert_eq!(queue.stats(), ProcessQueueStats { depth: 2, capacity: 2, dropped: 0 });
+    }
+}
@@ -1,25 +1,43 @@
 //! Validation flow for block processing
 //!
 //! This module provides validation flow orchestration for blocks.
+//! Context-free checks, proof of work above all, depend on nothing but the
+//! block itself, so a batch is split across threads for them. Anything that
+//! touches GHOSTDAG or the virtual state stays serial and in batch order.
 
 use consensus_core::block::Block;
 use consensus_core::errors::ConsensusError;
+use consensus_core::header::Header;
 use crate::consensus::validation::BlockValidator;
+use crate::pipeline::block_processor::{BlockProcessingResult, BlockProcessor};
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::thread;
 
 /// Validation flow for blocks
 pub struct ValidationFlow {
     block_validator: Arc<BlockValidator>,
+    threads: usize,
 }
 
 impl ValidationFlow {
-    /// Create a new validation flow
+    /// Create a new validation flow using one thread per available CPU
     pub fn new(block_validator: Arc<BlockValidator>) -> Self {
         Self {
             block_validator,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
         }
     }
 
+    /// Number of threads batches are validated on; 1 validates serially
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self { threads: threads.max(1), ..self }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
     /// Validate a block
     pub fn validate(&self, block: &Block) -> Result<(), ConsensusError> {
         self.block_validator.validate_block(block)
@@ -31,12 +49,56 @@ impl ValidationFlow {
         self.block_validator.validate_block_without_pow(block)
     }
 
-    /// Validate multiple blocks
+    /// Validate multiple blocks concurrently; results are in input order
     pub fn validate_batch(&self, blocks: &[Block]) -> Vec<Result<(), ConsensusError>> {
-        blocks.iter()
-            .map(|block| self.validate(block))
+        self.run(blocks, |block| self.validate(block))
+    }
+
+    /// Run the context-free header checks, proof of work included, on
+    /// `headers` concurrently; results are in input order
+    pub fn validate_headers(&self, headers: &[&Header]) -> Vec<Result<(), ConsensusError>> {
+        let header_validator = self.block_validator.header_validator();
+        self.run(headers, |header| header_validator.validate_header(header))
+    }
+
+    /// Process `blocks` through `processor`: headers are checked in parallel
+    /// first, then the blocks that passed go through GHOSTDAG, body and
+    /// virtual processing one at a time in the order given. Results match
+    /// calling [`BlockProcessor::process_block`] on each block in turn.
+    pub fn process_batch(&self, processor: &BlockProcessor, blocks: Vec<Block>) -> Vec<Result<BlockProcessingResult, ConsensusError>> {
+        let headers: Vec<&Header> = blocks.iter().map(|block| &block.header).collect();
+        let checks = self.validate_headers(&headers);
+        blocks
+            .into_iter()
+            .zip(checks)
+            .map(|(block, check)| check.and_then(|()| processor.process_prevalidated_block(block)))
             .collect()
     }
+
+    /// Apply `check` to every item, splitting `items` into one contiguous
+    /// chunk per thread so results can be concatenated back in order
+    fn run<T, F>(&self, items: &[T], check: F) -> Vec<Result<(), ConsensusError>>
+    where
+        T: Sync,
+        F: Fn(&T) -> Result<(), ConsensusError> + Sync,
+    {
+        let threads = self.threads.min(items.len());
+        if threads <= 1 {
+            return items.iter().map(check).collect();
+        }
+
+        let check = &check;
+        thread::scope(|scope| {
+            let workers: Vec<_> = items
+                .chunks(items.len().div_ceil(threads))
+                .map(|chunk| scope.spawn(move || chunk.iter().map(check).collect::<Vec<_>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("validation thread panicked"))
+                .collect()
+        })
+    }
 }
 
 #[cfg(test)]
@@ -44,11 +106,36 @@ mod tests {
     use super::*;
     use crate::consensus::validation::{HeaderValidator, TransactionValidator};
     use consensus_core::{ZERO_HASH, BlueWorkType};
+    use consensus_core::hashing::header::validate_pow;
     use consensus_core::tx::{Transaction, TransactionOutput, ScriptPublicKey};
     use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
     use consensus_core::constants::BLOCK_VERSION;
+    use std::time::{Duration, Instant};
 
-    fn create_test_block() -> Block {
+    fn create_flow() -> ValidationFlow {
+        let header_validator = Arc::new(HeaderValidator::new());
+        let tx_validator = Arc::new(TransactionValidator::new());
+        ValidationFlow::new(Arc::new(BlockValidator::new(header_validator, tx_validator)))
+    }
+
+    fn create_test_header(bits: u32, nonce: u64) -> Header {
+        consensus_core::header::Header::new_finalized(
+            BLOCK_VERSION,
+            vec![],
+            ZERO_HASH,
+            ZERO_HASH,
+            ZERO_HASH,
+            1000,
+            bits,
+            nonce,
+            0,
+            BlueWorkType::from(0u64),
+            0,
+            ZERO_HASH,
+        )
+    }
+
+    fn create_block(header: Header) -> Block {
         // Create a coinbase transaction
         let coinbase = Transaction::new(
             1,
@@ -62,31 +149,26 @@ mod tests {
             0,
             Vec::new(),
         );
-
-        let header = consensus_core::header::Header::new_finalized(
-            BLOCK_VERSION,
-            vec![],
-            ZERO_HASH,
-            ZERO_HASH,
-            ZERO_HASH,
-            1000,
-            0x1f00ffff,
-            0,
-            0,
-            BlueWorkType::from(0u64),
-            0,
-            ZERO_HASH,
-        );
         Block::new(header, vec![coinbase])
     }
 
+    fn create_test_block() -> Block {
+        create_block(create_test_header(0x1f00ffff, 0))
+    }
+
+    /// Header with the first nonce from `start` whose proof of work is `valid` or not
+    fn header_with_pow(bits: u32, start: u64, valid: bool) -> Header {
+        let mut header = create_test_header(bits, start);
+        while validate_pow(&header) != valid {
+            header.nonce += 1;
+            header.finalize();
+        }
+        header
+    }
+
     #[test]
     fn test_validation_flow() {
-        let header_validator = Arc::new(HeaderValidator::new());
-        let tx_validator = Arc::new(TransactionValidator::new());
-        let block_validator = Arc::new(BlockValidator::new(header_validator, tx_validator));
-        
-        let flow = ValidationFlow::new(block_validator);
+        let flow = create_flow();
         let block = create_test_block();
 
         // Should validate successfully for a basic block with coinbase
@@ -94,5 +176,68 @@ mod tests {
         let result = flow.validate_without_pow(&block);
         assert!(result.is_ok(), "Block validation failed: {:?}", result.err());
     }
-}
 
+    #[test]
+    fn test_parallel_and_serial_validation_agree() {
+        let mut blocks = Vec::new();
+        for i in 0..40u64 {
+            let header = match i % 4 {
+                0 | 1 => header_with_pow(0x207fffff, i * 1000, true),
+                2 => header_with_pow(0x1f00ffff, i * 1000, false),
+                _ => {
+                    let mut header = header_with_pow(0x207fffff, i * 1000, true);
+                    header.version = 0;
+                    header
+                }
+            };
+            let mut block = create_block(header);
+            if i % 5 == 0 {
+                // No coinbase
+                block.transactions.clear();
+            }
+            blocks.push(block);
+        }
+
+        let expected: Vec<String> = blocks.iter().map(|block| format!("{:?}", create_flow().validate(block))).collect();
+        assert!(expected.iter().any(|result| result == "Ok(())"));
+        assert!(expected.iter().any(|result| result != "Ok(())"));
+
+        let headers: Vec<&Header> = blocks.iter().map(|block| &block.header).collect();
+        let serial_headers = create_flow().with_threads(1).validate_headers(&headers);
+        for threads in [1, 2, 3, 8, 64] {
+            let flow = create_flow().with_threads(threads);
+            let results: Vec<String> = flow.validate_batch(&blocks).iter().map(|result| format!("{:?}", result)).collect();
+            assert_eq!(results, expected, "{} threads", threads);
+
+            let header_results = flow.validate_headers(&headers);
+            assert_eq!(format!("{:?}", header_results), format!("{:?}", serial_headers), "{} threads", threads);
+        }
+        assert!(create_flow().validate_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_pow_checking_scales_with_threads() {
+        let cpus = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        assert_eq!(create_flow().threads(), cpus);
+        assert_eq!(create_flow().with_threads(0).threads(), 1);
+        if cpus < 2 {
+            return;
+        }
+
+        let headers: Vec<Header> = (0..20_000).map(|nonce| create_test_header(0x1f00ffff, nonce)).collect();
+        let headers: Vec<&Header> = headers.iter().collect();
+        let best_of = |flow: &ValidationFlow| {
+            (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    assert_eq!(flow.validate_headers(&headers).len(), headers.len());
+                    start.elapsed()
+                })
+                .min()
+                .unwrap_or(Duration::MAX)
+        };
+        let serial = best_of(&create_flow().with_threads(1));
+        let parallel = best_of(&create_flow().with_threads(cpus.min(4)));
+        assert!(parallel < serial, "{} threads took {:?}, one took {:?}", cpus.min(4), parallel, serial);
+    }
+}
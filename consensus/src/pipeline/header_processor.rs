@@ -11,6 +11,7 @@ use crate::consensus::ghostdag::GhostdagManager;
 use crate::consensus::storage::BlockStore;
 use crate::consensus::difficulty::DifficultyManager;
 use crate::pipeline::deps_manager::DepsManager;
+use crate::pipeline::timings::{BlockTimings, ProcessingStage};
 use std::sync::Arc;
 
 /// Header processor for header-only processing
@@ -42,6 +43,22 @@ impl HeaderProcessor {
 
     /// Process a header
     pub fn process_header(&self, header: Header) -> Result<HeaderProcessingResult, ConsensusError> {
+        self.process_header_timed(header, &mut BlockTimings::default())
+    }
+
+    /// Process a header, adding the time spent validating it and computing
+    /// its GHOSTDAG data to `timings`
+    pub fn process_header_timed(&self, header: Header, timings: &mut BlockTimings) -> Result<HeaderProcessingResult, ConsensusError> {
+        self.process_header_inner(header, timings, false)
+    }
+
+    /// Like [`Self::process_header_timed`] for a header that already passed
+    /// [`HeaderValidator::validate_header`], so its proof of work is not checked again
+    pub(crate) fn process_prevalidated_header(&self, header: Header, timings: &mut BlockTimings) -> Result<HeaderProcessingResult, ConsensusError> {
+        self.process_header_inner(header, timings, true)
+    }
+
+    fn process_header_inner(&self, header: Header, timings: &mut BlockTimings, prevalidated: bool) -> Result<HeaderProcessingResult, ConsensusError> {
         let hash = header.hash;
 
         // Check if header already exists
@@ -49,23 +66,31 @@ impl HeaderProcessor {
             return Ok(HeaderProcessingResult::AlreadyExists(hash));
         }
 
-        // Validate header
-        self.header_validator.validate_header(&header)?;
-
-        // Check if all parents exist
-        let all_parents_exist = self.check_parents_exist(&header);
+        // Validate header and check if all parents exist
+        let all_parents_exist = timings.time(ProcessingStage::HeaderValidation, &hash, || {
+            if !prevalidated {
+                self.header_validator.validate_header(&header)?;
+            }
+            if let Some((tip_blue_score, _)) = self.block_store.get_chain_tip() {
+                self.header_validator.check_depth(&header, tip_blue_score)?;
+            }
+            Ok::<_, ConsensusError>(self.check_parents_exist(&header))
+        })?;
         if !all_parents_exist {
             // Add as orphan header
             self.deps_manager.add_orphan_header(header);
             return Ok(HeaderProcessingResult::Orphan(hash));
         }
+        self.header_validator.check_parents_reach_levels(&header, |parent| self.block_store.get_header(parent))?;
 
-        // Calculate GHOSTDAG data
-        let ghostdag_data = self.ghostdag_manager.add_block(&header)
+        // Calculate GHOSTDAG data and hold the header to what it commits to
+        let ghostdag_data = timings.time(ProcessingStage::Ghostdag, &hash, || self.ghostdag_manager.calculate_ghostdag(&header))
             .map_err(|e| ConsensusError::Other(format!("GHOSTDAG calculation failed: {}", e)))?;
-
-        // Update difficulty window (calculate_next_difficulty adds block to window)
-        let _ = self.difficulty_manager.calculate_next_difficulty(&header);
+        self.header_validator.validate_ghostdag_commitments(&header, &ghostdag_data)?;
+        let selected_parent_daa_score = self.block_store.get_header(&ghostdag_data.selected_parent).map_or(0, |parent| parent.daa_score);
+        self.header_validator.check_checkpoints(&header, &ghostdag_data, selected_parent_daa_score)?;
+        self.header_validator.check_difficulty(&header, self.expected_bits(ghostdag_data.selected_parent)?)?;
+        self.ghostdag_manager.insert_ghostdag_data(&header, ghostdag_data.clone());
 
         // Store header
         self.block_store.store_header(header.clone())?;
@@ -82,6 +107,16 @@ impl HeaderProcessor {
         })
     }
 
+    /// Bits a block whose selected parent is `selected_parent` must declare
+    pub fn expected_bits(&self, selected_parent: Hash) -> Result<u32, ConsensusError> {
+        let window = self.difficulty_manager.window_ending_at(
+            selected_parent,
+            |hash| self.block_store.get_header(hash),
+            |hash| self.ghostdag_manager.get_ghostdag_data(hash).map(|data| data.selected_parent),
+        );
+        self.difficulty_manager.expected_bits(&window).map_err(ConsensusError::Other)
+    }
+
     /// Check if all parents of a header exist
     fn check_parents_exist(&self, header: &Header) -> bool {
         for parent_level in &header.parents_by_level {
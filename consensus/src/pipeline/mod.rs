@@ -8,12 +8,14 @@ pub mod header_processor;
 pub mod body_processor;
 pub mod virtual_processor;
 pub mod deps_manager;
+pub mod timings;
 
 pub mod flow;
 
-pub use block_processor::BlockProcessor;
+pub use block_processor::{BlockProcessor, ChainChangeListener, ChainChanges};
 pub use header_processor::HeaderProcessor;
 pub use body_processor::BodyProcessor;
 pub use virtual_processor::VirtualProcessor;
 pub use deps_manager::DepsManager;
+pub use timings::{BlockTimings, ProcessingStage, ProcessingTimings};
 
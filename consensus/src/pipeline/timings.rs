@@ -0,0 +1,160 @@
+//! Block processing stage timings
+//!
+//! [`BlockProcessor`](super::BlockProcessor) times every pipeline stage of the
+//! blocks it processes, inside a `tracing` span per stage. The durations of one
+//! block are returned in its [`BlockTimings`], and those of accepted blocks are
+//! accumulated in a histogram per stage by [`ProcessingTimings`].
+
+use consensus_core::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bounds in seconds of the stage histogram buckets
+pub const STAGE_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Stage of the block processing pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingStage {
+    HeaderValidation,
+    Ghostdag,
+    BodyValidation,
+    VirtualUpdate,
+}
+
+impl ProcessingStage {
+    pub const ALL: [ProcessingStage; 4] = [
+        ProcessingStage::HeaderValidation,
+        ProcessingStage::Ghostdag,
+        ProcessingStage::BodyValidation,
+        ProcessingStage::VirtualUpdate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessingStage::HeaderValidation => "header_validation",
+            ProcessingStage::Ghostdag => "ghostdag",
+            ProcessingStage::BodyValidation => "body_validation",
+            ProcessingStage::VirtualUpdate => "virtual_update",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Time spent in each stage while processing one block. Stages the block
+/// didn't reach stay zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockTimings {
+    pub header_validation: Duration,
+    pub ghostdag: Duration,
+    pub body_validation: Duration,
+    pub virtual_update: Duration,
+}
+
+impl BlockTimings {
+    pub fn get(&self, stage: ProcessingStage) -> Duration {
+        match stage {
+            ProcessingStage::HeaderValidation => self.header_validation,
+            ProcessingStage::Ghostdag => self.ghostdag,
+            ProcessingStage::BodyValidation => self.body_validation,
+            ProcessingStage::VirtualUpdate => self.virtual_update,
+        }
+    }
+
+    fn get_mut(&mut self, stage: ProcessingStage) -> &mut Duration {
+        match stage {
+            ProcessingStage::HeaderValidation => &mut self.header_validation,
+            ProcessingStage::Ghostdag => &mut self.ghostdag,
+            ProcessingStage::BodyValidation => &mut self.body_validation,
+            ProcessingStage::VirtualUpdate => &mut self.virtual_update,
+        }
+    }
+
+    /// Time spent in all stages
+    pub fn total(&self) -> Duration {
+        ProcessingStage::ALL.iter().map(|stage| self.get(*stage)).sum()
+    }
+
+    /// Run `f` as `stage` of block `hash`, adding its duration to the stage
+    pub(crate) fn time<T>(&mut self, stage: ProcessingStage, hash: &Hash, f: impl FnOnce() -> T) -> T {
+        let _span = tracing::debug_span!("block_stage", stage = stage.name(), hash = %hash).entered();
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        *self.get_mut(stage) += elapsed;
+        tracing::trace!(elapsed_us = elapsed.as_micros() as u64, "stage finished");
+        result
+    }
+}
+
+/// Cumulative histogram of one stage, as exported to metrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Upper bound in seconds and number of observations at or below it
+    pub buckets: Vec<(f64, u64)>,
+    /// Sum of all observations in seconds
+    pub sum: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Default)]
+struct StageHistogram {
+    buckets: [AtomicU64; STAGE_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl StageHistogram {
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = STAGE_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = STAGE_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)).as_secs_f64(),
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-stage histograms of the processing times of accepted blocks
+#[derive(Debug, Default)]
+pub struct ProcessingTimings {
+    stages: [StageHistogram; 4],
+}
+
+impl ProcessingTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the stage durations of one block
+    pub fn observe(&self, timings: &BlockTimings) {
+        for stage in ProcessingStage::ALL {
+            self.stages[stage.index()].observe(timings.get(stage));
+        }
+    }
+
+    pub fn snapshot(&self, stage: ProcessingStage) -> HistogramSnapshot {
+        self.stages[stage.index()].snapshot()
+    }
+}
@@ -3,15 +3,19 @@
 //! This module calculates virtual state for mining, including virtual
 //! GHOSTDAG data based on current DAG tips.
 
+use consensus_core::block::Block;
+use consensus_core::errors::ConsensusError;
 use consensus_core::Hash;
 use crate::consensus::ghostdag::{GhostdagManager, GhostdagData};
-use crate::consensus::storage::BlockStore;
+use crate::consensus::storage::{BlockFeeRecord, BlockFeeStore, BlockStore};
+use crate::process::parents_builder::{ParentSelectionStrategy, ParentsBuilder};
 use std::sync::Arc;
 
 /// Virtual processor for virtual state calculation
 pub struct VirtualProcessor {
     ghostdag_manager: Arc<GhostdagManager>,
     block_store: Arc<BlockStore>,
+    fee_store: Arc<BlockFeeStore>,
 }
 
 impl VirtualProcessor {
@@ -23,9 +27,25 @@ impl VirtualProcessor {
         Self {
             ghostdag_manager,
             block_store,
+            fee_store: Arc::new(BlockFeeStore::default()),
         }
     }
 
+    /// Keep block fee records in `fee_store`
+    pub fn with_fee_store(self, fee_store: Arc<BlockFeeStore>) -> Self {
+        Self { fee_store, ..self }
+    }
+
+    /// Record the fee totals of a newly accepted block
+    pub fn record_block_fees(&self, block: &Block, total_fees: u64) -> Result<(), ConsensusError> {
+        self.fee_store.insert(BlockFeeRecord::from_block(block, total_fees))
+    }
+
+    /// Fee totals of up to `count` most recently accepted blocks, newest first
+    pub fn recent_block_fees(&self, count: usize) -> Vec<BlockFeeRecord> {
+        self.fee_store.recent(count)
+    }
+
     /// Get current DAG tips (blocks with no children)
     pub fn get_tips(&self) -> Vec<Hash> {
         // Find all blocks that have no children (are tips)
@@ -68,33 +88,16 @@ impl VirtualProcessor {
         // Calculate virtual GHOSTDAG data (for validation, but not used in selection yet)
         let _virtual_data = self.calculate_virtual_ghostdag_data(&tips)?;
 
-        // Select parents from tips based on blue score and blue work
-        // For simplicity, we'll select up to max_parents from tips
-        // In a real implementation, we'd use more sophisticated selection
-        let parents = tips;
-        
-        // Sort by blue score (descending) and take top max_parents
-        let mut parent_data: Vec<(Hash, u64)> = parents
+        // Up to max_parents tips with the highest blue scores
+        let blue_scores: Vec<(Hash, u64)> = tips
             .iter()
-            .filter_map(|tip| {
-                self.ghostdag_manager.get_blue_score(tip)
-                    .map(|score| (*tip, score))
-            })
-            .collect();
-
-        parent_data.sort_by(|a, b| b.1.cmp(&a.1));
-        
-        let selected_parents: Vec<Hash> = parent_data
-            .into_iter()
-            .take(max_parents)
-            .map(|(hash, _)| hash)
+            .filter_map(|tip| self.ghostdag_manager.get_blue_score(tip).map(|score| (*tip, score)))
             .collect();
-
-        if selected_parents.is_empty() {
+        let builder = ParentsBuilder::with_config(max_parents, ParentSelectionStrategy::TopByBlueScore(max_parents));
+        match builder.build_parents(&tips, &blue_scores) {
+            Ok(parents) if !parents.is_empty() => Ok(parents),
             // Fallback: use first tip if no blue score data available
-            Ok(vec![parents[0]])
-        } else {
-            Ok(selected_parents)
+            _ => Ok(vec![tips[0]]),
         }
     }
 
@@ -2,11 +2,89 @@
 //!
 //! This module handles coinbase transaction creation, validation,
 //! and reward calculation for the consensus process.
+//!
+//! A coinbase payload commits to the block it pays: the block height (its
+//! DAA score) as a little-endian u64, then the subsidy as a little-endian
+//! u64, then the miner's extra data, extranonce included.
 
+use consensus_core::errors::ConsensusError;
 use consensus_core::tx::{Transaction, TransactionOutput, ScriptPublicKey};
 use consensus_core::subnets;
 use crate::consensus::types::ConsensusConfig;
 
+/// Default block reward before the first halving, in sompi
+pub const INITIAL_BLOCK_REWARD_SOMPI: u64 = 50_000_000;
+
+/// The reward shifts to zero after this many halvings
+const MAX_HALVINGS: u64 = 64;
+
+/// Longest extra data a miner may add to the coinbase payload, in bytes
+pub const MAX_COINBASE_EXTRA_DATA_LEN: usize = 150;
+
+/// Largest extranonce a template may reserve in the coinbase payload, in bytes
+pub const MAX_EXTRANONCE_SIZE: usize = 32;
+
+/// Bytes of the height and subsidy at the start of a coinbase payload
+pub const COINBASE_PAYLOAD_HEADER_LEN: usize = 16;
+
+/// Contents of a coinbase payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinbasePayload {
+    /// Height (DAA score) of the block paying the coinbase
+    pub height: u64,
+    /// Subsidy the schedule gives at `height`, fees excluded
+    pub subsidy: u64,
+    /// Miner's extra data, followed by any extranonce
+    pub extra_data: Vec<u8>,
+}
+
+impl CoinbasePayload {
+    /// Serialize the payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(COINBASE_PAYLOAD_HEADER_LEN + self.extra_data.len());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.subsidy.to_le_bytes());
+        bytes.extend_from_slice(&self.extra_data);
+        bytes
+    }
+
+    /// Parse a coinbase payload
+    pub fn parse(bytes: &[u8]) -> Result<Self, ConsensusError> {
+        if bytes.len() < COINBASE_PAYLOAD_HEADER_LEN {
+            return Err(ConsensusError::InvalidCoinbasePayload(format!(
+                "{} bytes, the height and subsidy take {}",
+                bytes.len(),
+                COINBASE_PAYLOAD_HEADER_LEN
+            )));
+        }
+        let extra_data = &bytes[COINBASE_PAYLOAD_HEADER_LEN..];
+        if extra_data.len() > MAX_COINBASE_EXTRA_DATA_LEN + MAX_EXTRANONCE_SIZE {
+            return Err(ConsensusError::InvalidCoinbasePayload(format!("{} bytes of extra data", extra_data.len())));
+        }
+        Ok(Self {
+            height: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            subsidy: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            extra_data: extra_data.to_vec(),
+        })
+    }
+}
+
+/// Write `extranonce` over the bytes reserved for it at the end of the
+/// coinbase payload, refreshing the transaction id
+pub fn splice_extranonce(coinbase: &mut Transaction, extranonce: &[u8]) -> Result<(), String> {
+    if !coinbase.is_coinbase() {
+        return Err("Only a coinbase transaction carries an extranonce".to_string());
+    }
+    let start = coinbase
+        .payload
+        .len()
+        .checked_sub(extranonce.len())
+        .ok_or_else(|| format!("Coinbase payload is shorter than the {} byte extranonce", extranonce.len()))?;
+    coinbase.payload[start..].copy_from_slice(extranonce);
+    coinbase.finalize();
+    Ok(())
+}
+
 /// Coinbase transaction processor
 pub struct CoinbaseProcessor {
     config: ConsensusConfig,
@@ -39,21 +117,102 @@ impl CoinbaseProcessor {
             0,
             consensus_core::subnets::SUBNETWORK_ID_COINBASE,
             0,
-            format!("Block {}", block_height).into_bytes(),
+            self.coinbase_payload(block_height, Vec::new()).to_bytes(),
         )
     }
 
+    /// Payload of the coinbase of a block at `block_height`
+    pub fn coinbase_payload(&self, block_height: u64, extra_data: Vec<u8>) -> CoinbasePayload {
+        CoinbasePayload { height: block_height, subsidy: self.calculate_block_reward(block_height), extra_data }
+    }
+
+    /// Create a coinbase transaction whose payload carries `extra_data` after the
+    /// height and subsidy, such as a pool identifier or extra nonce space
+    pub fn create_coinbase_transaction_with_extra_data(
+        &self,
+        miner_address: &ScriptPublicKey,
+        block_height: u64,
+        fees: u64,
+        extra_data: &[u8],
+    ) -> Result<Transaction, String> {
+        if extra_data.len() > MAX_COINBASE_EXTRA_DATA_LEN {
+            return Err(format!(
+                "Extra data is {} bytes, at most {} are allowed",
+                extra_data.len(),
+                MAX_COINBASE_EXTRA_DATA_LEN
+            ));
+        }
+
+        let mut coinbase = self.create_coinbase_transaction(miner_address, block_height, fees);
+        if !extra_data.is_empty() {
+            coinbase.payload.extend_from_slice(extra_data);
+            coinbase.finalize();
+        }
+        Ok(coinbase)
+    }
+
+    /// Create a coinbase transaction whose payload ends with `extranonce_size`
+    /// zero bytes after the extra data, for pools to hand out to their miners
+    /// as additional search space
+    pub fn create_coinbase_transaction_with_extranonce(
+        &self,
+        miner_address: &ScriptPublicKey,
+        block_height: u64,
+        fees: u64,
+        extra_data: &[u8],
+        extranonce_size: usize,
+    ) -> Result<Transaction, String> {
+        if extranonce_size > MAX_EXTRANONCE_SIZE {
+            return Err(format!(
+                "Extranonce is {} bytes, at most {} are allowed",
+                extranonce_size, MAX_EXTRANONCE_SIZE
+            ));
+        }
+
+        let mut coinbase =
+            self.create_coinbase_transaction_with_extra_data(miner_address, block_height, fees, extra_data)?;
+        if extranonce_size > 0 {
+            coinbase.payload.resize(coinbase.payload.len() + extranonce_size, 0);
+            coinbase.finalize();
+        }
+        Ok(coinbase)
+    }
+
+    /// Blocks between halvings of the configured schedule
+    fn halving_interval(&self) -> u64 {
+        self.config.subsidy_halving_interval.max(1)
+    }
+
     /// Calculate block reward based on block height
     pub fn calculate_block_reward(&self, block_height: u64) -> u64 {
-        // Simple halving every 210,000 blocks (like Bitcoin)
-        let halvings = block_height / 210_000;
-        let initial_reward = 50_000_000; // 50 coins in smallest unit
+        // The subsidy halves every `subsidy_halving_interval` blocks
+        let halvings = block_height / self.halving_interval();
 
-        if halvings >= 64 {
+        if halvings >= MAX_HALVINGS {
             0 // No more rewards after 64 halvings
         } else {
-            initial_reward >> halvings // Divide by 2^halvings
+            self.config.initial_subsidy >> halvings // Divide by 2^halvings
+        }
+    }
+
+    /// Total subsidy emitted by the schedule for heights `0..=block_height`
+    pub fn calculate_emission(&self, block_height: u64) -> u64 {
+        let interval = self.halving_interval();
+        let mut emitted = 0u64;
+        for halvings in 0..MAX_HALVINGS {
+            let start = halvings.saturating_mul(interval);
+            if start > block_height {
+                break;
+            }
+            let end = block_height.min(start.saturating_add(interval - 1));
+            emitted = emitted.saturating_add((end - start + 1).saturating_mul(self.config.initial_subsidy >> halvings));
         }
+        emitted
+    }
+
+    /// Total subsidy the schedule will ever emit
+    pub fn max_supply(&self) -> u64 {
+        self.calculate_emission(MAX_HALVINGS.saturating_mul(self.halving_interval()) - 1)
     }
 
     /// Validate coinbase transaction
@@ -84,6 +243,20 @@ impl CoinbaseProcessor {
         Ok(())
     }
 
+    /// Validate that the payload of `coinbase` commits to the height of the
+    /// block at `block_height` and to the subsidy the schedule gives there
+    pub fn validate_coinbase_payload(&self, coinbase: &Transaction, block_height: u64) -> Result<CoinbasePayload, ConsensusError> {
+        let payload = CoinbasePayload::parse(&coinbase.payload)?;
+        if payload.height != block_height {
+            return Err(ConsensusError::InvalidCoinbaseHeight { declared: payload.height, expected: block_height });
+        }
+        let expected = self.calculate_block_reward(block_height);
+        if payload.subsidy != expected {
+            return Err(ConsensusError::InvalidCoinbaseSubsidy { declared: payload.subsidy, expected });
+        }
+        Ok(payload)
+    }
+
     /// Get coinbase maturity period
     pub fn get_coinbase_maturity(&self) -> u64 {
         self.config.coinbase_maturity
@@ -115,6 +288,70 @@ mod tests {
         assert_eq!(processor.calculate_block_reward(13_440_000), 0);
     }
 
+    #[test]
+    fn test_calculate_emission() {
+        let processor = CoinbaseProcessor::new(ConsensusConfig::default());
+
+        assert_eq!(processor.calculate_emission(0), 50_000_000);
+        assert_eq!(processor.calculate_emission(209_999), 10_500_000_000_000);
+        assert_eq!(processor.calculate_emission(210_000), 10_500_025_000_000);
+        assert_eq!(processor.calculate_emission(500_000), 16_750_012_500_000);
+
+        // Matches summing the per-block reward
+        let summed: u64 = (0..=1_000).map(|h| processor.calculate_block_reward(h)).sum();
+        assert_eq!(processor.calculate_emission(1_000), summed);
+    }
+
+    #[test]
+    fn test_max_supply() {
+        let processor = CoinbaseProcessor::new(ConsensusConfig::default());
+        assert_eq!(processor.max_supply(), 20_999_997_480_000);
+        assert_eq!(processor.calculate_emission(u64::MAX), processor.max_supply());
+    }
+
+    /// Schedule of 1_000 sompi halving every 10 blocks, with coinbases
+    /// spendable after 5
+    fn custom_schedule() -> ConsensusConfig {
+        ConsensusConfig {
+            initial_subsidy: 1_000,
+            subsidy_halving_interval: 10,
+            coinbase_maturity: 5,
+            ..ConsensusConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_configured_subsidy_schedule() {
+        let processor = CoinbaseProcessor::new(custom_schedule());
+
+        // Genesis
+        assert_eq!(processor.calculate_block_reward(0), 1_000);
+        assert_eq!(processor.calculate_block_reward(9), 1_000);
+
+        // One halving
+        assert_eq!(processor.calculate_block_reward(10), 500);
+        assert_eq!(processor.calculate_block_reward(19), 500);
+
+        // Far in the future the subsidy has halved away
+        assert_eq!(processor.calculate_block_reward(100), 0);
+        assert_eq!(processor.calculate_block_reward(u64::MAX), 0);
+
+        assert_eq!(processor.calculate_emission(19), 15_000);
+        let summed: u64 = (0..=200).map(|h| processor.calculate_block_reward(h)).sum();
+        assert_eq!(processor.max_supply(), summed);
+        assert_eq!(processor.get_coinbase_maturity(), 5);
+    }
+
+    #[test]
+    fn test_coinbase_pays_the_configured_subsidy() {
+        let processor = CoinbaseProcessor::new(custom_schedule());
+        let miner_address = ScriptPublicKey::new(0, vec![1, 2, 3, 4].into());
+
+        assert_eq!(processor.create_coinbase_transaction(&miner_address, 0, 7).outputs[0].value, 1_007);
+        assert_eq!(processor.create_coinbase_transaction(&miner_address, 10, 7).outputs[0].value, 507);
+        assert_eq!(processor.create_coinbase_transaction(&miner_address, 1_000_000, 7).outputs[0].value, 7);
+    }
+
     #[test]
     fn test_create_coinbase_transaction() {
         let config = ConsensusConfig::default();
@@ -128,7 +365,48 @@ mod tests {
         assert_eq!(coinbase.outputs[0].value, 50_000_000 + 1000); // reward + fees
         assert_eq!(coinbase.outputs[0].script_public_key, miner_address);
         assert_eq!(coinbase.subnetwork_id, SUBNETWORK_ID_COINBASE);
-        assert_eq!(coinbase.payload, b"Block 100");
+        assert_eq!(coinbase.payload, processor.coinbase_payload(100, Vec::new()).to_bytes());
+    }
+
+    #[test]
+    fn test_coinbase_extra_data() {
+        let processor = CoinbaseProcessor::new(ConsensusConfig::default());
+        let miner_address = ScriptPublicKey::new(0, vec![1, 2, 3, 4].into());
+
+        let coinbase = processor.create_coinbase_transaction_with_extra_data(&miner_address, 100, 0, b"pool/42").unwrap();
+        assert_eq!(CoinbasePayload::parse(&coinbase.payload).unwrap().extra_data, b"pool/42");
+        assert_eq!(coinbase.id(), coinbase.hash());
+        let plain = processor.create_coinbase_transaction_with_extra_data(&miner_address, 100, 0, b"").unwrap();
+        assert_eq!(plain.payload.len(), COINBASE_PAYLOAD_HEADER_LEN);
+
+        let longest = [7u8; MAX_COINBASE_EXTRA_DATA_LEN];
+        assert!(processor.create_coinbase_transaction_with_extra_data(&miner_address, 100, 0, &longest).is_ok());
+        let too_long = [7u8; MAX_COINBASE_EXTRA_DATA_LEN + 1];
+        assert!(processor.create_coinbase_transaction_with_extra_data(&miner_address, 100, 0, &too_long).is_err());
+    }
+
+    #[test]
+    fn test_coinbase_extranonce() {
+        let processor = CoinbaseProcessor::new(ConsensusConfig::default());
+        let miner_address = ScriptPublicKey::new(0, vec![1, 2, 3, 4].into());
+
+        let mut coinbase =
+            processor.create_coinbase_transaction_with_extranonce(&miner_address, 100, 0, b"pool", 4).unwrap();
+        assert_eq!(coinbase.payload[COINBASE_PAYLOAD_HEADER_LEN..], *b"pool\0\0\0\0");
+        let unspliced = coinbase.id();
+
+        splice_extranonce(&mut coinbase, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(coinbase.payload[COINBASE_PAYLOAD_HEADER_LEN..], *b"pool\x01\x02\x03\x04");
+        assert_eq!(processor.validate_coinbase_payload(&coinbase, 100).unwrap().extra_data, b"pool\x01\x02\x03\x04");
+        assert_ne!(coinbase.id(), unspliced);
+        assert_eq!(coinbase.id(), coinbase.hash());
+
+        let plain = processor.create_coinbase_transaction_with_extranonce(&miner_address, 100, 0, b"", 0).unwrap();
+        assert_eq!(plain.payload, processor.coinbase_payload(100, Vec::new()).to_bytes());
+        assert!(processor
+            .create_coinbase_transaction_with_extranonce(&miner_address, 100, 0, b"", MAX_EXTRANONCE_SIZE + 1)
+            .is_err());
+        assert!(splice_extranonce(&mut coinbase, &[0; 64]).is_err());
     }
 
     #[test]
@@ -145,4 +423,54 @@ mod tests {
         // Wrong reward should fail
         assert!(processor.validate_coinbase(&coinbase, 50_000_000).is_err());
     }
+
+    #[test]
+    fn test_coinbase_payload_round_trip() {
+        let processor = CoinbaseProcessor::new(custom_schedule());
+        let payload = processor.coinbase_payload(12, b"pool/42".to_vec());
+        assert_eq!(payload, CoinbasePayload { height: 12, subsidy: 500, extra_data: b"pool/42".to_vec() });
+        let bytes = payload.to_bytes();
+        assert_eq!(bytes[..8], 12u64.to_le_bytes());
+        assert_eq!(bytes[8..16], 500u64.to_le_bytes());
+        assert_eq!(CoinbasePayload::parse(&bytes).unwrap(), payload);
+
+        let empty = CoinbasePayload { height: u64::MAX, subsidy: 0, extra_data: Vec::new() };
+        assert_eq!(CoinbasePayload::parse(&empty.to_bytes()).unwrap(), empty);
+
+        assert!(matches!(CoinbasePayload::parse(&bytes[..15]), Err(ConsensusError::InvalidCoinbasePayload(_))));
+        let too_long = CoinbasePayload { extra_data: vec![0; MAX_COINBASE_EXTRA_DATA_LEN + MAX_EXTRANONCE_SIZE + 1], ..payload };
+        assert!(CoinbasePayload::parse(&too_long.to_bytes()).is_err());
+
+        // Coinbases of successive heights differ even when paying the same output
+        let miner_address = ScriptPublicKey::new(0, vec![1, 2, 3, 4].into());
+        let first = processor.create_coinbase_transaction(&miner_address, 20, 0);
+        let second = processor.create_coinbase_transaction(&miner_address, 21, 0);
+        assert_eq!(first.outputs, second.outputs);
+        assert_ne!(first.id(), second.id());
+    }
+
+    #[test]
+    fn test_coinbase_at_wrong_height_is_rejected() {
+        let processor = CoinbaseProcessor::new(custom_schedule());
+        let miner_address = ScriptPublicKey::new(0, vec![1, 2, 3, 4].into());
+        let coinbase = processor.create_coinbase_transaction(&miner_address, 9, 0);
+        assert_eq!(processor.validate_coinbase_payload(&coinbase, 9).unwrap().height, 9);
+
+        assert!(matches!(
+            processor.validate_coinbase_payload(&coinbase, 10),
+            Err(ConsensusError::InvalidCoinbaseHeight { declared: 9, expected: 10 })
+        ));
+
+        // The right height with the subsidy of another one
+        let mut inflated = coinbase.clone();
+        inflated.payload = CoinbasePayload { height: 10, subsidy: 1_000, extra_data: Vec::new() }.to_bytes();
+        assert!(matches!(
+            processor.validate_coinbase_payload(&inflated, 10),
+            Err(ConsensusError::InvalidCoinbaseSubsidy { declared: 1_000, expected: 500 })
+        ));
+
+        let mut legacy = coinbase;
+        legacy.payload = b"Block 9".to_vec();
+        assert!(processor.validate_coinbase_payload(&legacy, 9).is_err());
+    }
 }
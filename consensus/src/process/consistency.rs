@@ -0,0 +1,222 @@
+//! Cross-store consistency checks
+//!
+//! Blocks, headers, the chain index and the UTXO set are written one after the
+//! other, so a crash between writes can leave them disagreeing: a body without
+//! its header, a chain block whose GHOSTDAG data could not be rebuilt, or UTXOs
+//! of a block that was never stored. The checker reports such findings; repair
+//! rolls the chain index back below the first inconsistent chain block and
+//! discards bodies without headers. A damaged UTXO set cannot be repaired here.
+
+use crate::consensus::ghostdag::GhostdagManager;
+use crate::consensus::storage::ConsensusStorage;
+use consensus_core::errors::ConsensusError;
+use consensus_core::header::Header;
+use consensus_core::Hash;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// Chain blocks below the chain tip covered by a quick check
+pub const QUICK_CHECK_DEPTH: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Harmless for consensus, but worth fixing
+    Warning,
+    /// Stores disagree in a way consensus depends on
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// One broken invariant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub hash: Option<Hash>,
+    pub message: String,
+}
+
+/// Outcome of a consistency check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// Chain index entries checked
+    pub chain_blocks: usize,
+    /// Headers checked, zero for a quick check
+    pub headers: usize,
+    pub findings: Vec<Finding>,
+    /// Blue score of the lowest inconsistent chain block; repair drops the chain index from here up
+    pub rollback_from: Option<u64>,
+    /// Bodies stored without a header; repair deletes them
+    pub bodies_without_headers: Vec<Hash>,
+}
+
+impl ConsistencyReport {
+    /// Whether no finding is an error
+    pub fn is_ok(&self) -> bool {
+        self.findings.iter().all(|finding| finding.severity < Severity::Error)
+    }
+
+    /// Whether repair has anything to do
+    pub fn is_repairable(&self) -> bool {
+        self.rollback_from.is_some() || !self.bodies_without_headers.is_empty()
+    }
+
+    fn push(&mut self, severity: Severity, hash: Option<Hash>, message: String) {
+        self.findings.push(Finding { severity, hash, message });
+    }
+}
+
+/// What a repair changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairOutcome {
+    /// Chain index entries dropped, lowest blue score first
+    pub rolled_back: Vec<(u64, Hash)>,
+    /// Chain tip after the rollback
+    pub chain_tip: Option<(u64, Hash)>,
+    pub bodies_removed: usize,
+}
+
+pub struct ConsistencyChecker {
+    storage: Arc<ConsensusStorage>,
+    ghostdag_manager: Arc<GhostdagManager>,
+}
+
+impl ConsistencyChecker {
+    pub fn new(storage: Arc<ConsensusStorage>, ghostdag_manager: Arc<GhostdagManager>) -> Self {
+        Self { storage, ghostdag_manager }
+    }
+
+    /// Check the chain index, and unless `quick` also every header, body and the
+    /// UTXO set. A quick check covers the chain blocks within [`QUICK_CHECK_DEPTH`]
+    /// of the chain tip.
+    pub fn check(&self, quick: bool) -> ConsistencyReport {
+        let mut report = ConsistencyReport::default();
+        self.check_chain(quick, &mut report);
+        if !quick {
+            let headers = self.storage.block_store().get_all_headers();
+            self.check_blocks(&headers, &mut report);
+            self.check_utxo_set(headers.iter().map(|header| header.daa_score).max(), &mut report);
+        }
+        report
+    }
+
+    /// Drop the chain index from `report.rollback_from` up, leaving the last fully
+    /// consistent chain block as the tip, and delete bodies without headers
+    pub fn repair(&self, report: &ConsistencyReport) -> Result<RepairOutcome, ConsensusError> {
+        let block_store = self.storage.block_store();
+        let rolled_back = match report.rollback_from {
+            Some(blue_score) => block_store.replace_chain_from(blue_score, &[])?,
+            None => Vec::new(),
+        };
+        for hash in &report.bodies_without_headers {
+            block_store.remove_block(hash);
+        }
+        Ok(RepairOutcome { rolled_back, chain_tip: block_store.get_chain_tip(), bodies_removed: report.bodies_without_headers.len() })
+    }
+
+    /// Every chain block has a header and GHOSTDAG data matching its index entry,
+    /// and its selected parent is the chain block below it
+    fn check_chain(&self, quick: bool, report: &mut ConsistencyReport) {
+        let block_store = self.storage.block_store();
+        let Some((tip_score, _)) = block_store.get_chain_tip() else {
+            return;
+        };
+        let lowest = if quick { tip_score.saturating_sub(QUICK_CHECK_DEPTH) } else { 0 };
+        let chain: Vec<_> = (lowest..=tip_score).filter_map(|score| block_store.get_chain_block_by_blue_score(score).map(|hash| (score, hash))).collect();
+
+        let mut below: Option<Hash> = None;
+        for (score, hash) in chain {
+            report.chain_blocks += 1;
+            if let Err(message) = self.check_chain_block(score, hash, below) {
+                report.push(Severity::Error, Some(hash), message);
+                report.rollback_from.get_or_insert(score);
+            }
+            below = Some(hash);
+        }
+    }
+
+    fn check_chain_block(&self, score: u64, hash: Hash, below: Option<Hash>) -> Result<(), String> {
+        if !self.storage.has_header(&hash) {
+            return Err(format!("chain block at blue score {} has no header", score));
+        }
+        let Some(data) = self.ghostdag_manager.get_ghostdag_data(&hash) else {
+            return Err(format!("chain block at blue score {} has no GHOSTDAG data", score));
+        };
+        if data.blue_score != score {
+            return Err(format!("chain block indexed at blue score {} has GHOSTDAG blue score {}", score, data.blue_score));
+        }
+        match below {
+            Some(below) if data.selected_parent != below && data.selected_parent != hash => {
+                Err(format!("selected parent {} is not the chain block below, {}", data.selected_parent, below))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Every body has a header, every parent is stored and the tips found from
+    /// the parents match those found from the children index
+    fn check_blocks(&self, headers: &[Header], report: &mut ConsistencyReport) {
+        let block_store = self.storage.block_store();
+        for hash in block_store.block_hashes() {
+            if !block_store.has_header(&hash) {
+                report.push(Severity::Error, Some(hash), "block body has no header".to_string());
+                report.bodies_without_headers.push(hash);
+            }
+        }
+
+        report.headers = headers.len();
+        let stored: HashSet<Hash> = headers.iter().map(|header| header.hash).collect();
+        let mut referenced = HashSet::new();
+        for header in headers {
+            for parent in header.direct_parents() {
+                referenced.insert(*parent);
+                // Headers imported from a snapshot start without their parents
+                if !stored.contains(parent) {
+                    report.push(Severity::Warning, Some(header.hash), format!("parent {} is not stored", parent));
+                }
+            }
+        }
+        for hash in &stored {
+            let tip_by_parents = !referenced.contains(hash);
+            let tip_by_children = block_store.get_children(hash).iter().all(|child| !stored.contains(child));
+            if tip_by_parents != tip_by_children {
+                report.push(Severity::Warning, Some(*hash), "children index disagrees with the parents of stored headers; reindex".to_string());
+            }
+        }
+    }
+
+    /// The UTXO set matches its stored commitment and holds no outputs of blocks
+    /// above `highest`, the highest DAA score of a stored header
+    fn check_utxo_set(&self, highest: Option<u64>, report: &mut ConsistencyReport) {
+        let utxo_set = self.storage.utxo_set();
+        match utxo_set.verify_commitment() {
+            Ok(check) if !check.is_ok() => report.push(
+                Severity::Error,
+                None,
+                format!("UTXO commitment {} does not match the stored {}; resync or import a snapshot", check.computed, check.stored),
+            ),
+            Ok(_) => {}
+            Err(e) => report.push(Severity::Error, None, format!("failed to read the UTXO set: {}", e)),
+        }
+
+        let Some(highest) = highest else {
+            return;
+        };
+        let ahead = utxo_set.snapshot().values().filter(|entry| entry.block_daa_score > highest).count();
+        if ahead > 0 {
+            report.push(
+                Severity::Error,
+                None,
+                format!("{} UTXOs were created above the highest stored DAA score {}; resync or import a snapshot", ahead, highest),
+            );
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Windowed block body download
+//!
+//! During IBD bodies are requested from each sync peer within a window bounded
+//! by the number of blocks and by the bytes in flight. A request reserves the
+//! size declared for the body alongside its header, or else an estimate from
+//! the bodies received so far, and the actual size is counted once it arrives.
+//! The window advances as bodies arrive; a request the peer doesn't answer in
+//! time goes back to the queue and is handed to another peer.
+
+use consensus_core::Hash;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Limits of the body requests outstanding with one peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadConfig {
+    /// Bodies requested from a peer and not received yet
+    pub max_blocks_in_flight: usize,
+    /// Bytes reserved for the bodies in flight from a peer
+    pub max_bytes_in_flight: u64,
+    /// Time a peer has to deliver a body before it is requested elsewhere
+    pub request_timeout: Duration,
+    /// Bytes reserved for a body of undeclared size until the first body
+    /// arrives; the average size of the received bodies after that
+    pub initial_body_estimate: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            max_blocks_in_flight: 32,
+            max_bytes_in_flight: 8 * 1024 * 1024,
+            request_timeout: Duration::from_secs(30),
+            initial_body_estimate: 64 * 1024,
+        }
+    }
+}
+
+/// Body download progress
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SyncProgress {
+    pub downloaded_blocks: u64,
+    pub downloaded_bytes: u64,
+    /// Bodies queued or in flight
+    pub remaining_blocks: u64,
+    pub remaining_bytes: u64,
+    /// Bytes received per second since the first request
+    pub bytes_per_second: f64,
+    /// Time until the remaining bodies arrive at that speed
+    pub eta: Option<Duration>,
+}
+
+impl SyncProgress {
+    /// Share of the bytes downloaded, the remaining ones as estimated, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        let total = self.downloaded_bytes + self.remaining_bytes;
+        if total == 0 {
+            1.0
+        } else {
+            self.downloaded_bytes as f64 / total as f64
+        }
+    }
+}
+
+/// A body waiting to be requested
+struct Queued {
+    /// Position in the download order, kept when a request is re-queued
+    seq: u64,
+    hash: Hash,
+    /// Size declared for the body, if any
+    size: Option<u64>,
+    /// Peers that let a request for the body time out
+    timed_out: HashSet<SocketAddr>,
+}
+
+struct InFlight {
+    peer: SocketAddr,
+    /// Unix milliseconds after which the request is given up
+    deadline: u64,
+    /// Bytes the request holds in the peer's window until the body arrives
+    reserved: u64,
+    body: Queued,
+}
+
+/// Schedules body requests over the sync peers
+pub struct DownloadWindow {
+    config: DownloadConfig,
+    queue: VecDeque<Queued>,
+    in_flight: HashMap<Hash, InFlight>,
+    next_seq: u64,
+    downloaded_blocks: u64,
+    downloaded_bytes: u64,
+    /// Unix milliseconds of the first request
+    started: Option<u64>,
+}
+
+impl DownloadWindow {
+    pub fn new(config: DownloadConfig) -> Self {
+        Self {
+            config,
+            queue: VecDeque::new(),
+            in_flight: HashMap::new(),
+            next_seq: 0,
+            downloaded_blocks: 0,
+            downloaded_bytes: 0,
+            started: None,
+        }
+    }
+
+    pub fn config(&self) -> DownloadConfig {
+        self.config
+    }
+
+    /// Queue the body of `hash`, declared to be `size` bytes if known. Returns
+    /// false if it is already queued or in flight.
+    pub fn enqueue(&mut self, hash: Hash, size: Option<u64>) -> bool {
+        if self.in_flight.contains_key(&hash) || self.queue.iter().any(|queued| queued.hash == hash) {
+            return false;
+        }
+        self.queue.push_back(Queued { seq: self.next_seq, hash, size, timed_out: HashSet::new() });
+        self.next_seq += 1;
+        true
+    }
+
+    /// Number and reserved bytes of the bodies in flight from `peer`
+    pub fn in_flight(&self, peer: SocketAddr) -> (usize, u64) {
+        self.in_flight
+            .values()
+            .filter(|request| request.peer == peer)
+            .fold((0, 0), |(blocks, bytes), request| (blocks + 1, bytes + request.reserved))
+    }
+
+    /// Bytes to reserve for a body of undeclared size: the average size of the
+    /// bodies received so far, or the configured estimate before any arrived
+    pub fn body_estimate(&self) -> u64 {
+        match self.downloaded_blocks {
+            0 => self.config.initial_body_estimate,
+            blocks => self.downloaded_bytes / blocks,
+        }
+    }
+
+    /// Bodies to request from `peer` at `now` (unix milliseconds), in queue
+    /// order, as far as its window has room. A body larger than the byte limit
+    /// is still requested once the window is empty. Bodies the peer failed to
+    /// deliver before are left for other peers.
+    pub fn next_request(&mut self, peer: SocketAddr, now: u64) -> Vec<Hash> {
+        let (mut blocks, mut bytes) = self.in_flight(peer);
+        let mut request = Vec::new();
+        let mut skipped = Vec::new();
+        while blocks < self.config.max_blocks_in_flight {
+            let Some(body) = self.queue.pop_front() else { break };
+            if body.timed_out.contains(&peer) {
+                skipped.push(body);
+                continue;
+            }
+            let reserved = body.size.unwrap_or_else(|| self.body_estimate());
+            if blocks > 0 && bytes + reserved > self.config.max_bytes_in_flight {
+                self.queue.push_front(body);
+                break;
+            }
+
+            blocks += 1;
+            bytes += reserved;
+            request.push(body.hash);
+            let deadline = now + self.config.request_timeout.as_millis() as u64;
+            self.in_flight.insert(body.hash, InFlight { peer, deadline, reserved, body });
+        }
+        for body in skipped.into_iter().rev() {
+            self.queue.push_front(body);
+        }
+
+        if !request.is_empty() {
+            self.started.get_or_insert(now);
+        }
+        request
+    }
+
+    /// Record the arrival of the body of `hash`, `size` bytes, freeing the
+    /// room reserved for it in the window. Returns false if it wasn't expected.
+    pub fn received(&mut self, hash: &Hash, size: u64) -> bool {
+        if self.in_flight.remove(hash).is_none() {
+            // A late answer to a request that was re-queued
+            let Some(index) = self.queue.iter().position(|queued| queued.hash == *hash) else {
+                return false;
+            };
+            self.queue.remove(index);
+        }
+        self.downloaded_blocks += 1;
+        self.downloaded_bytes += size;
+        true
+    }
+
+    /// Give up the requests past their deadline at `now`, queueing their bodies
+    /// first for other peers. Returns the peers that timed out, each with the
+    /// bodies it didn't deliver.
+    pub fn expire(&mut self, now: u64) -> Vec<(SocketAddr, Vec<Hash>)> {
+        let expired: Vec<Hash> =
+            self.in_flight.iter().filter(|(_, request)| request.deadline <= now).map(|(hash, _)| *hash).collect();
+        let mut by_peer: HashMap<SocketAddr, Vec<Hash>> = HashMap::new();
+        let mut requeued = Vec::with_capacity(expired.len());
+        for hash in expired {
+            let InFlight { peer, mut body, .. } = self.in_flight.remove(&hash).unwrap();
+            body.timed_out.insert(peer);
+            by_peer.entry(peer).or_default().push(hash);
+            requeued.push(body);
+        }
+        self.requeue(requeued);
+        by_peer.into_iter().collect()
+    }
+
+    /// Queue the bodies in flight from `peer` again for the other peers
+    pub fn peer_disconnected(&mut self, peer: SocketAddr) {
+        let hashes: Vec<Hash> =
+            self.in_flight.iter().filter(|(_, request)| request.peer == peer).map(|(hash, _)| *hash).collect();
+        let bodies = hashes.iter().map(|hash| self.in_flight.remove(hash).unwrap().body).collect();
+        self.requeue(bodies);
+    }
+
+    /// Put `bodies` at the front of the queue in their original order
+    fn requeue(&mut self, mut bodies: Vec<Queued>) {
+        bodies.sort_by_key(|body| body.seq);
+        for body in bodies.into_iter().rev() {
+            self.queue.push_front(body);
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.queue.is_empty() && self.in_flight.is_empty()
+    }
+
+    /// Progress as of `now` (unix milliseconds)
+    pub fn progress(&self, now: u64) -> SyncProgress {
+        let estimate = self.body_estimate();
+        let remaining_bytes = self
+            .queue
+            .iter()
+            .map(|body| body.size.unwrap_or(estimate))
+            .chain(self.in_flight.values().map(|request| request.reserved))
+            .sum();
+        let elapsed = self.started.map_or(0.0, |started| now.saturating_sub(started) as f64 / 1000.0);
+        let bytes_per_second = if elapsed > 0.0 { self.downloaded_bytes as f64 / elapsed } else { 0.0 };
+        let eta = (remaining_bytes > 0 && bytes_per_second > 0.0)
+            .then(|| Duration::from_secs_f64(remaining_bytes as f64 / bytes_per_second));
+        SyncProgress {
+            downloaded_blocks: self.downloaded_blocks,
+            downloaded_bytes: self.downloaded_bytes,
+            remaining_blocks: (self.queue.len() + self.in_flight.len()) as u64,
+            remaining_bytes,
+            bytes_per_second,
+            eta,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(n: u64) -> Hash {
+        Hash::from_le_u64([n, 0, 0, 0])
+    }
+
+    fn peer(n: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, n], 16111))
+    }
+
+    #[test]
+    fn test_window_limits_blocks_and_bytes() {
+        let config = DownloadConfig { max_blocks_in_flight: 3, max_bytes_in_flight: 1_000, request_timeout: Duration::from_secs(5), ..DownloadConfig::default() };
+        let mut window = DownloadWindow::new(config);
+        for (n, size) in [400, 400, 400, 100, 2_000, 50].into_iter().enumerate() {
+            assert!(window.enqueue(h(n as u64), Some(size)));
+        }
+        assert!(!window.enqueue(h(0), Some(400)));
+
+        // Two bodies fill 800 of the 1000 bytes; the third would overflow them
+        assert_eq!(window.next_request(peer(1), 0), vec![h(0), h(1)]);
+        assert_eq!(window.in_flight(peer(1)), (2, 800));
+        assert!(window.next_request(peer(1), 0).is_empty());
+        // Each peer has a window of its own
+        assert_eq!(window.next_request(peer(2), 0), vec![h(2), h(3)]);
+
+        assert!(window.received(&h(0), 400));
+        assert!(!window.received(&h(0), 400));
+        assert!(window.next_request(peer(1), 0).is_empty());
+        assert!(window.received(&h(1), 400));
+        // An oversized body goes out alone
+        assert_eq!(window.next_request(peer(1), 0), vec![h(4)]);
+        assert_eq!(window.in_flight(peer(1)), (1, 2_000));
+        assert!(window.next_request(peer(1), 0).is_empty());
+    }
+
+    #[test]
+    fn test_timed_out_requests_go_to_another_peer() {
+        let config = DownloadConfig { max_blocks_in_flight: 2, max_bytes_in_flight: 10_000, request_timeout: Duration::from_secs(5), ..DownloadConfig::default() };
+        let mut window = DownloadWindow::new(config);
+        for n in 0..4 {
+            window.enqueue(h(n), Some(100));
+        }
+
+        assert_eq!(window.next_request(peer(1), 0), vec![h(0), h(1)]);
+        assert!(window.expire(4_999).is_empty());
+        let mut expired = window.expire(5_000);
+        assert_eq!(expired.len(), 1);
+        let (timed_out, mut hashes) = expired.pop().unwrap();
+        hashes.sort();
+        let mut expected = vec![h(0), h(1)];
+        expected.sort();
+        assert_eq!((timed_out, hashes), (peer(1), expected));
+
+        // The peer that timed out gets the rest, the expired range waits for another
+        assert_eq!(window.next_request(peer(1), 5_000), vec![h(2), h(3)]);
+        assert_eq!(window.next_request(peer(2), 5_000), vec![h(0), h(1)]);
+
+        // A late body from the first peer still counts
+        window.peer_disconnected(peer(2));
+        assert!(window.received(&h(0), 100));
+        assert_eq!(window.next_request(peer(3), 5_000), vec![h(1)]);
+        for n in 1..4 {
+            assert!(window.received(&h(n), 100));
+        }
+        assert!(window.is_complete());
+    }
+
+    #[test]
+    fn test_download_with_slow_and_unresponsive_peers() {
+        let config = DownloadConfig { max_blocks_in_flight: 4, max_bytes_in_flight: 3_000, request_timeout: Duration::from_secs(2), ..DownloadConfig::default() };
+        let mut window = DownloadWindow::new(config);
+        let sizes: Vec<u64> = (0..40).map(|n| 200 + (n * 137) % 900).collect();
+        for (n, size) in sizes.iter().enumerate() {
+            window.enqueue(h(n as u64), Some(*size));
+        }
+        let total: u64 = sizes.iter().sum();
+
+        // Peer 1 answers each request after 500 ms, peer 2 never does
+        let (slow, silent) = (peer(1), peer(2));
+        let mut pending: Vec<(u64, u64)> = Vec::new();
+        let mut reassigned = Vec::new();
+        let mut now = 0;
+        while !window.is_complete() {
+            assert!(now < 120_000, "download stalled");
+            for (peer, hashes) in window.expire(now) {
+                assert_eq!(peer, silent);
+                reassigned.extend(hashes);
+            }
+            for peer in [slow, silent] {
+                let request = window.next_request(peer, now);
+                if peer == slow {
+                    pending.extend(request.into_iter().map(|hash| (now + 500, hash.to_le_u64()[0])));
+                }
+                let (blocks, bytes) = window.in_flight(peer);
+                assert!(blocks <= config.max_blocks_in_flight);
+                assert!(bytes <= config.max_bytes_in_flight);
+            }
+
+            now += 100;
+            pending.retain(|(due, n)| {
+                if *due <= now {
+                    assert!(window.received(&h(*n), sizes[*n as usize]));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        // Everything the silent peer was asked for came from the slow one
+        assert!(!reassigned.is_empty());
+        let progress = window.progress(now);
+        assert_eq!(progress.downloaded_blocks, 40);
+        assert_eq!(progress.downloaded_bytes, total);
+        assert_eq!((progress.remaining_blocks, progress.remaining_bytes), (0, 0));
+        assert_eq!(progress.fraction(), 1.0);
+        assert!(progress.bytes_per_second > 0.0);
+        assert_eq!(progress.eta, None);
+    }
+
+    #[test]
+    fn test_progress_reports_speed_and_eta() {
+        let mut window = DownloadWindow::new(DownloadConfig::default());
+        window.enqueue(h(0), Some(1_000));
+        window.enqueue(h(1), Some(3_000));
+        assert_eq!(window.progress(0).eta, None);
+
+        window.next_request(peer(1), 10_000);
+        window.received(&h(0), 1_000);
+        let progress = window.progress(12_000);
+        assert_eq!(progress.bytes_per_second, 500.0);
+        assert_eq!(progress.eta, Some(Duration::from_secs(6)));
+        assert_eq!(progress.fraction(), 0.25);
+    }
+
+    #[test]
+    fn test_undeclared_bodies_reserve_an_estimate_reconciled_on_arrival() {
+        let config = DownloadConfig {
+            max_blocks_in_flight: 10,
+            max_bytes_in_flight: 1_000,
+            request_timeout: Duration::from_secs(5),
+            initial_body_estimate: 400,
+        };
+        let mut window = DownloadWindow::new(config);
+        for n in 0..8 {
+            window.enqueue(h(n), None);
+        }
+
+        // Before any body arrived each one reserves the configured estimate
+        assert_eq!(window.next_request(peer(1), 0), vec![h(0), h(1)]);
+        assert_eq!(window.in_flight(peer(1)), (2, 800));
+        assert_eq!(window.progress(0).remaining_bytes, 8 * 400);
+
+        // The bodies turn out smaller; later requests reserve their average
+        assert!(window.received(&h(0), 100));
+        assert!(window.received(&h(1), 200));
+        assert_eq!(window.body_estimate(), 150);
+        assert_eq!(window.progress(0).downloaded_bytes, 300);
+        assert_eq!(window.next_request(peer(1), 0), (2..8).map(h).collect::<Vec<_>>());
+        assert_eq!(window.in_flight(peer(1)), (6, 900));
+        assert_eq!(window.progress(0).remaining_bytes, 900);
+    }
+}
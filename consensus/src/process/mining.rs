@@ -10,6 +10,7 @@ use crate::consensus::difficulty::DifficultyManager;
 use crate::process::coinbase::CoinbaseProcessor;
 use consensus_core::block::Block;
 use consensus_core::header::Header as BlockHeader;
+use consensus_core::provenance::BlockSource;
 use consensus_core::tx::{Transaction, ScriptPublicKey};
 use consensus_core::Hash;
 use std::sync::Arc;
@@ -57,22 +58,15 @@ impl MiningProcess {
             self.select_parents(&tips)?
         };
 
-        // Calculate difficulty using the difficulty manager
-        let current_daa_score = self.virtual_processor.get_tips().len() as u64; // Simple DAA score based on tip count
-        let difficulty = self.difficulty_manager.calculate_next_difficulty(&BlockHeader::new_finalized(
-            1,
-            vec![],
-            Hash::from_le_u64([0, 0, 0, 0]),
-            Hash::from_le_u64([0, 0, 0, 0]),
-            Hash::from_le_u64([0, 0, 0, 0]),
-            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-            0x1f00ffff, // Current difficulty
-            0,
-            current_daa_score,
-            consensus_core::BlueWorkType::from(0u64),
-            0,
-            Hash::from_le_u64([0, 0, 0, 0]),
-        )).unwrap_or(0x1f00ffff); // Fallback to default difficulty
+        // The header must commit to the GHOSTDAG data its parents give it
+        let (blue_score, blue_work) = self
+            .ghostdag
+            .get_virtual_ghostdag_data(parents.clone())
+            .map_or((0, consensus_core::BlueWorkType::from(0u64)), |data| (data.blue_score, data.blue_work));
+        let current_daa_score = blue_score;
+
+        // Bits of the selected parent's difficulty window
+        let difficulty = self.processor.expected_bits(&parents).unwrap_or_else(|_| self.difficulty_manager.initial_bits());
 
         // Get current block height for reward calculation
         let block_height = current_daa_score;
@@ -101,8 +95,8 @@ impl MiningProcess {
             difficulty,
             0,
             block_height,
-            0.into(), // Will be calculated by miner
-            0, // Will be calculated by miner
+            blue_work,
+            blue_score,
             Hash::from_le_u64([0, 0, 0, 0]), // Placeholder
         );
 
@@ -117,7 +111,7 @@ impl MiningProcess {
 
     /// Submit a mined block to the consensus pipeline
     pub fn submit_block(&self, block: Block) -> Result<BlockStatus, String> {
-        match self.processor.process_block(block) {
+        match self.processor.process_block_from(block, BlockSource::Miner).0 {
             Ok(result) => Ok(result.status),
             Err(e) => Err(format!("Block processing failed: {:?}", e)),
         }
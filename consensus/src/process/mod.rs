@@ -8,14 +8,19 @@ pub mod sync;
 pub mod relay;
 
 pub mod coinbase;
+pub mod consistency;
+pub mod download;
 
 pub mod parents_builder;
 pub mod past_median_time;
 pub mod pruning;
 pub mod pruning_proof;
+pub mod snapshot;
+pub mod verify;
 
 
 
 pub use mining::MiningProcess;
 pub use sync::SyncProcess;
+pub use download::{DownloadConfig, DownloadWindow, SyncProgress};
 pub use relay::RelayProcess;
@@ -3,6 +3,7 @@
 //! This module implements block pruning logic to manage blockchain storage
 //! by removing old blocks that are no longer needed for consensus.
 
+use crate::consensus::storage::BlockStore;
 use consensus_core::block::Block;
 use consensus_core::header::Header as BlockHeader;
 use consensus_core::Hash;
@@ -154,6 +155,42 @@ impl PruningManager {
         }
     }
 
+    /// Whether every block body is kept forever
+    pub fn is_archival(&self) -> bool {
+        !self.config.enabled
+    }
+
+    /// Delete the bodies of unprotected blocks whose blue score is more than
+    /// `pruning_depth` below the virtual's, keeping their headers. At most
+    /// `max_pruning_batch` bodies go per call; nothing does in archival mode.
+    pub fn prune_bodies<F>(&self, block_store: &BlockStore, virtual_blue_score: u64, blue_score: F) -> Vec<Hash>
+    where
+        F: Fn(&Hash) -> Option<u64>,
+    {
+        if self.is_archival() {
+            return vec![];
+        }
+        let Some(horizon) = virtual_blue_score.checked_sub(self.config.pruning_depth) else {
+            return vec![];
+        };
+
+        let protected = self.protected_blocks.read().unwrap().clone();
+        let mut pruned = Vec::new();
+        for hash in block_store.block_hashes() {
+            if pruned.len() >= self.config.max_pruning_batch {
+                break;
+            }
+            if protected.contains(&hash) || !blue_score(&hash).is_some_and(|score| score < horizon) {
+                continue;
+            }
+            // Only the body goes; the header stays so the block can still be located in the DAG
+            block_store.remove_block(&hash);
+            pruned.push(hash);
+        }
+        self.mark_blocks_pruned(&pruned);
+        pruned
+    }
+
     /// Clear all pruning candidates (useful for reset operations)
     pub fn clear_candidates(&self) {
         let mut candidates = self.pruning_candidates.write().unwrap();
@@ -306,6 +343,79 @@ mod tests {
         assert!(manager.get_blocks_to_prune().is_empty());
     }
 
+    /// Chain of `count` blocks where block `i` has blue score `i`
+    fn store_chain(store: &BlockStore, count: u64) -> Vec<Hash> {
+        let mut hashes: Vec<Hash> = Vec::new();
+        for i in 0..count {
+            let parents = hashes.last().map(|parent| vec![*parent]).unwrap_or_default();
+            let header = BlockHeader::new_finalized(
+                1,
+                vec![parents],
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                i,
+                0x207fffff,
+                i,
+                i,
+                Default::default(),
+                i,
+                Default::default(),
+            );
+            hashes.push(header.hash);
+            store.store_block(Block::new(header, vec![])).unwrap();
+        }
+        hashes
+    }
+
+    fn blue_scores(hashes: &[Hash]) -> impl Fn(&Hash) -> Option<u64> + '_ {
+        move |hash| hashes.iter().position(|h| h == hash).map(|i| i as u64)
+    }
+
+    #[test]
+    fn test_prune_bodies_deletes_old_bodies() {
+        let store = BlockStore::new();
+        let hashes = store_chain(&store, 20);
+        let manager = PruningManager::new(PruningConfig { pruning_depth: 5, max_pruning_batch: 100, enabled: true });
+        manager.mark_block_protected(hashes[0]);
+
+        let pruned = manager.prune_bodies(&store, 20, blue_scores(&hashes));
+
+        // Blue scores 1..15 are deeper than the pruning depth; genesis is protected
+        assert_eq!(pruned.len(), 14);
+        assert!(store.has_block(&hashes[0]));
+        for hash in &hashes[1..15] {
+            assert!(!store.has_block(hash));
+            assert!(store.has_header(hash));
+        }
+        for hash in &hashes[15..] {
+            assert!(store.has_block(hash));
+        }
+        assert_eq!(store.block_count(), 6);
+    }
+
+    #[test]
+    fn test_prune_bodies_respects_batch_limit() {
+        let store = BlockStore::new();
+        let hashes = store_chain(&store, 20);
+        let manager = PruningManager::new(PruningConfig { pruning_depth: 5, max_pruning_batch: 4, enabled: true });
+
+        assert_eq!(manager.prune_bodies(&store, 20, blue_scores(&hashes)).len(), 4);
+        assert_eq!(store.block_count(), 16);
+    }
+
+    #[test]
+    fn test_archival_keeps_old_bodies() {
+        let store = BlockStore::new();
+        let hashes = store_chain(&store, 20);
+        let manager = PruningManager::new(PruningConfig { pruning_depth: 5, max_pruning_batch: 100, enabled: false });
+
+        assert!(manager.is_archival());
+        assert!(manager.prune_bodies(&store, 20, blue_scores(&hashes)).is_empty());
+        assert_eq!(store.block_count(), 20);
+        assert!(hashes.iter().all(|hash| store.has_block(hash)));
+    }
+
     #[test]
     fn test_clear_candidates() {
         let manager = PruningManager::new(PruningConfig::default());
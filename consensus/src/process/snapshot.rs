@@ -0,0 +1,434 @@
+//! UTXO set snapshots for fast bootstrapping
+//!
+//! A snapshot carries the UTXO set of a node together with every header it
+//! stores, checkpointed at its selected chain tip, so a new node can start from
+//! the checkpoint instead of processing each block body from genesis. Block
+//! bodies are left out, and so is genesis, which every node has of its own. GHOSTDAG data is not carried either: headers commit to
+//! their blue score and blue work, so the importer recomputes it from them and
+//! rejects any header whose commitments do not match.
+//!
+//! Nothing in the file proves its UTXO set: an exporter can add any UTXO and
+//! recompute a matching commitment. An import therefore takes a
+//! [`TrustedCheckpoint`], the checkpoint hash and the commitment to the UTXO
+//! set at it as published by a source the operator trusts, and the UTXOs must
+//! commit to exactly that.
+//!
+//! A snapshot file is the magic bytes `JIOSNAP`, the version (u32), the
+//! checkpoint hash, a header count followed by each header's canonical encoding
+//! (length + bytes), a UTXO count followed by each UTXO's canonical encoding
+//! (length + bytes), and the 32-byte digest of the UTXOs' MuHash. Integers are
+//! little-endian and counts and lengths are u64, as in
+//! [`consensus_core::encoding`].
+
+use crate::consensus::dag::{BlockRelations, DagTopology, ReachabilityStore};
+use crate::consensus::ghostdag::{GhostdagManager, GhostdagProtocol, GhostdagStore};
+use crate::consensus::storage::ConsensusStorage;
+use crate::consensus::validation::HeaderValidator;
+use consensus_core::encoding::{decode_header, decode_utxo, encode_header, encode_utxo};
+use consensus_core::hashing::header::calculate_header_hash;
+use consensus_core::header::Header;
+use consensus_core::muhash::EMPTY_MUHASH;
+use consensus_core::tx::{TransactionOutpoint, UtxoEntry};
+use consensus_core::{Hash, HASH_SIZE};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Magic bytes every snapshot file starts with
+pub const SNAPSHOT_MAGIC: &[u8; 7] = b"JIOSNAP";
+
+/// Format version written after the magic bytes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct UtxoSnapshot {
+    pub version: u32,
+    /// Selected chain tip the UTXO set was taken at
+    pub checkpoint: Hash,
+    /// Every header of the exporting node but genesis, parents before children
+    pub headers: Vec<Header>,
+    /// UTXOs sorted by outpoint
+    pub utxos: Vec<(TransactionOutpoint, UtxoEntry)>,
+    /// Finalized MuHash of `utxos`
+    pub commitment: Hash,
+}
+
+/// What an importer trusts a snapshot to be taken at, from outside the snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedCheckpoint {
+    pub hash: Hash,
+    /// Finalized MuHash of the UTXO set at `hash`
+    pub utxo_commitment: Hash,
+}
+
+/// What an import wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotImport {
+    pub checkpoint: Hash,
+    /// Blue score of the checkpoint
+    pub blue_score: u64,
+    pub headers: usize,
+    pub utxos: usize,
+}
+
+impl UtxoSnapshot {
+    /// Take a snapshot of `storage` at its selected chain tip. The UTXO set is
+    /// checked against its commitment first, so a corrupted set is not passed on.
+    pub fn export(storage: &ConsensusStorage) -> Result<Self, String> {
+        let block_store = storage.block_store();
+        let (_, checkpoint) = block_store.get_chain_tip().ok_or("No selected chain to checkpoint at")?;
+
+        let check = storage.utxo_set().verify_commitment().map_err(|e| format!("Failed to read the UTXO set: {}", e))?;
+        if !check.is_ok() {
+            return Err(format!("UTXO set does not match its commitment: stored {}, computed {}", check.stored, check.computed));
+        }
+        let mut utxos: Vec<_> = storage.utxo_set().snapshot().into_iter().collect();
+        utxos.sort_by_key(|(outpoint, _)| *outpoint);
+
+        // Blue scores grow from parent to child, and DAA scores equal blue scores
+        let mut headers: Vec<_> = block_store.get_all_headers().into_iter().filter(|header| !header.direct_parents().is_empty()).collect();
+        headers.sort_by_key(|header| (header.daa_score, header.hash));
+
+        Ok(Self { version: SNAPSHOT_VERSION, checkpoint, headers, utxos, commitment: check.computed.finalize() })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.checkpoint.as_bytes());
+        bytes.extend_from_slice(&(self.headers.len() as u64).to_le_bytes());
+        for header in &self.headers {
+            push_var_bytes(&mut bytes, &encode_header(header));
+        }
+        bytes.extend_from_slice(&(self.utxos.len() as u64).to_le_bytes());
+        for (outpoint, entry) in &self.utxos {
+            push_var_bytes(&mut bytes, &encode_utxo(outpoint, entry));
+        }
+        bytes.extend_from_slice(self.commitment.as_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+        if reader.bytes(SNAPSHOT_MAGIC.len(), "magic")? != SNAPSHOT_MAGIC {
+            return Err("Not a snapshot file".to_string());
+        }
+        let version = u32::from_le_bytes(reader.array("version")?);
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Unsupported snapshot version {}", version));
+        }
+        let checkpoint = Hash::from_bytes(reader.array::<HASH_SIZE>("checkpoint")?);
+
+        let header_count = reader.count("header count")?;
+        let mut headers = Vec::with_capacity(header_count);
+        for _ in 0..header_count {
+            headers.push(decode_header(reader.var_bytes("header")?).map_err(|e| format!("Invalid header in snapshot: {}", e))?);
+        }
+        let utxo_count = reader.count("UTXO count")?;
+        let mut utxos = Vec::with_capacity(utxo_count);
+        for _ in 0..utxo_count {
+            utxos.push(decode_utxo(reader.var_bytes("UTXO")?).map_err(|e| format!("Invalid UTXO in snapshot: {}", e))?);
+        }
+        let commitment = Hash::from_bytes(reader.array::<HASH_SIZE>("commitment")?);
+        if !reader.0.is_empty() {
+            return Err(format!("{} trailing bytes after the snapshot", reader.0.len()));
+        }
+
+        Ok(Self { version, checkpoint, headers, utxos, commitment })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.encode()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::decode(&bytes)
+    }
+
+    /// Check the snapshot is at `trusted` with the UTXO set committed to there,
+    /// and each header's hash and proof of work
+    pub fn verify(&self, trusted: &TrustedCheckpoint) -> Result<(), String> {
+        if trusted.hash != self.checkpoint {
+            return Err(format!("Snapshot is at {}, not the trusted checkpoint {}", self.checkpoint, trusted.hash));
+        }
+        if !self.headers.iter().any(|header| header.hash == self.checkpoint) {
+            return Err(format!("Snapshot has no header for its checkpoint {}", self.checkpoint));
+        }
+
+        let mut commitment = EMPTY_MUHASH;
+        for (outpoint, entry) in &self.utxos {
+            commitment.add_utxo(outpoint, entry);
+        }
+        let commitment = commitment.finalize();
+        if commitment != trusted.utxo_commitment {
+            return Err(format!("UTXOs commit to {}, not the trusted commitment {}", commitment, trusted.utxo_commitment));
+        }
+        if commitment != self.commitment {
+            return Err(format!("UTXOs commit to {}, the snapshot claims {}", commitment, self.commitment));
+        }
+
+        let header_validator = HeaderValidator::new();
+        for header in &self.headers {
+            if calculate_header_hash(header) != header.hash {
+                return Err(format!("Header {} does not hash to its block hash", header.hash));
+            }
+            if header.direct_parents().is_empty() {
+                return Err(format!("Header {} has no parents; snapshots do not carry genesis", header.hash));
+            }
+            header_validator.validate_header(header).map_err(|e| format!("Header {}: {}", header.hash, e))?;
+        }
+        Ok(())
+    }
+
+    /// Verify the snapshot, then write its headers, selected chain and UTXO set
+    /// into `storage`, which must be empty. GHOSTDAG data is recomputed on top of
+    /// `genesis`, the genesis header of the importing node.
+    pub fn import(
+        &self,
+        storage: &ConsensusStorage,
+        genesis: &Header,
+        ghostdag_k: u32,
+        trusted: &TrustedCheckpoint,
+    ) -> Result<SnapshotImport, String> {
+        let block_store = storage.block_store();
+        if block_store.header_count() > 0 || block_store.block_count() > 0 || !storage.utxo_set().is_empty() {
+            return Err("Snapshots can only be imported into an empty database".to_string());
+        }
+        self.verify(trusted)?;
+        let ghostdag = replay_ghostdag(&self.headers, genesis, ghostdag_k)?;
+
+        for header in &self.headers {
+            block_store.store_header(header.clone()).map_err(|e| format!("Failed to store header {}: {}", header.hash, e))?;
+        }
+
+        // Selected chain from the checkpoint down to genesis, tip first
+        let mut chain = Vec::new();
+        let mut current = self.checkpoint;
+        while let Some(data) = ghostdag.get_ghostdag_data(&current) {
+            chain.push((data.blue_score, current));
+            if data.selected_parent == current {
+                break;
+            }
+            current = data.selected_parent;
+        }
+        block_store.replace_chain_from(0, &chain).map_err(|e| format!("Failed to write the chain index: {}", e))?;
+
+        let utxo_set = storage.utxo_set();
+        for (outpoint, entry) in &self.utxos {
+            utxo_set.add_utxo(*outpoint, entry.clone()).map_err(|e| format!("Failed to store UTXO {}: {}", outpoint, e))?;
+        }
+        let check = utxo_set.verify_commitment().map_err(|e| format!("Failed to read the UTXO set back: {}", e))?;
+        if !check.is_ok() || check.stored.finalize() != self.commitment {
+            return Err(format!("Imported UTXO set commits to {}, expected {}", check.stored, self.commitment));
+        }
+
+        Ok(SnapshotImport {
+            checkpoint: self.checkpoint,
+            blue_score: chain.first().map_or(0, |(blue_score, _)| *blue_score),
+            headers: self.headers.len(),
+            utxos: self.utxos.len(),
+        })
+    }
+}
+
+fn push_var_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.0.len() < len {
+            return Err(format!("Snapshot ends while reading its {}", what));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, what: &str) -> Result<[u8; N], String> {
+        Ok(self.bytes(N, what)?.try_into().expect("slice has length N"))
+    }
+
+    /// A count or length; each counted item takes at least one byte
+    fn count(&mut self, what: &str) -> Result<usize, String> {
+        let count = u64::from_le_bytes(self.array(what)?);
+        usize::try_from(count).ok().filter(|count| *count <= self.0.len()).ok_or_else(|| format!("Snapshot ends while reading its {}", what))
+    }
+
+    fn var_bytes(&mut self, what: &str) -> Result<&'a [u8], String> {
+        let len = self.count(what)?;
+        self.bytes(len, what)
+    }
+}
+
+/// GHOSTDAG data of `headers`, given parents first, holding each header to the
+/// blue score, blue work and DAA score it commits to
+fn replay_ghostdag(headers: &[Header], genesis: &Header, ghostdag_k: u32) -> Result<GhostdagManager, String> {
+    let relations = Arc::new(BlockRelations::new());
+    let reachability = Arc::new(ReachabilityStore::new());
+    let topology = Arc::new(DagTopology::new(relations.clone(), reachability.clone()));
+    let store = Arc::new(GhostdagStore::new());
+    let protocol = Arc::new(GhostdagProtocol::new(ghostdag_k, topology, relations, store.clone()));
+    let ghostdag = GhostdagManager::new(protocol, store);
+    reachability.init_genesis(genesis.hash);
+    ghostdag.init_genesis(genesis);
+
+    let header_validator = HeaderValidator::new();
+    for header in headers {
+        if let Some(parent) = header.direct_parents().iter().find(|parent| ghostdag.get_ghostdag_data(parent).is_none()) {
+            return Err(format!("Header {} comes before its parent {}, or the parent is not this network's genesis", header.hash, parent));
+        }
+        let data = ghostdag.calculate_ghostdag(header).map_err(|e| format!("Header {}: {}", header.hash, e))?;
+        header_validator.validate_ghostdag_commitments(header, &data).map_err(|e| format!("Header {}: {}", header.hash, e))?;
+        ghostdag.insert_ghostdag_data(header, data);
+    }
+    Ok(ghostdag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::block::Block;
+    use consensus_core::constants::BLOCK_VERSION;
+    use consensus_core::hashing::header::validate_pow;
+    use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+    use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+    use consensus_pow::target::work_from_bits;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Chain of `length` blocks on `genesis`, each with a coinbase and a valid nonce
+    fn mine_chain(genesis: Hash, length: u64) -> Vec<Block> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let mut blocks: Vec<Block> = Vec::new();
+        for blue_score in 1..=length {
+            let parent = blocks.last().map_or(genesis, |block| block.header.hash);
+            // Genesis has no bits here, so only the mined blocks add work
+            let blue_work = blocks.iter().fold(BlueWorkType::from(0u64), |work, block| work + work_from_bits(block.header.bits));
+            let coinbase = Transaction::new(
+                0,
+                Vec::new(),
+                vec![TransactionOutput::new(blue_score * 1_000, ScriptPublicKey::from_vec(0, vec![blue_score as u8]))],
+                0,
+                SUBNETWORK_ID_COINBASE,
+                0,
+                blue_score.to_le_bytes().to_vec(),
+            );
+            let mut header = Header::new_finalized(
+                BLOCK_VERSION,
+                vec![vec![parent]],
+                ZERO_HASH,
+                ZERO_HASH,
+                ZERO_HASH,
+                timestamp,
+                0x207fffff,
+                0,
+                blue_score,
+                blue_work,
+                blue_score,
+                ZERO_HASH,
+            );
+            while !validate_pow(&header) {
+                header.nonce += 1;
+                header.finalize();
+            }
+            blocks.push(Block::new(header, vec![coinbase]));
+        }
+        blocks
+    }
+
+    fn exporting_storage(genesis: Hash, blocks: &[Block]) -> ConsensusStorage {
+        let storage = ConsensusStorage::new();
+        storage.store_header(Header::from_precomputed_hash(genesis, vec![])).unwrap();
+        for block in blocks {
+            storage.apply_block(block, block.header.daa_score).unwrap();
+        }
+        let tip = blocks.last().unwrap();
+        let chain: Vec<_> = blocks.iter().rev().map(|block| (block.header.blue_score, block.header.hash)).collect();
+        storage.block_store().replace_chain_from(1, &chain).unwrap();
+        assert_eq!(storage.block_store().get_chain_tip(), Some((tip.header.blue_score, tip.header.hash)));
+        storage
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let blocks = mine_chain(genesis, 3);
+        let source = exporting_storage(genesis, &blocks);
+        let snapshot = UtxoSnapshot::export(&source).unwrap();
+        assert_eq!(snapshot.checkpoint, blocks[2].header.hash);
+        assert_eq!(snapshot.headers.len(), 3);
+        assert_eq!(snapshot.utxos.len(), 3);
+
+        let dir = std::env::temp_dir().join(format!("jio_snapshot_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("utxo.snapshot");
+        snapshot.save(&path).unwrap();
+        let loaded = UtxoSnapshot::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let target = ConsensusStorage::new();
+        let trusted = TrustedCheckpoint { hash: blocks[2].header.hash, utxo_commitment: source.utxo_set().utxo_commitment().finalize() };
+        let imported = loaded.import(&target, &Header::from_precomputed_hash(genesis, vec![]), 18, &trusted).unwrap();
+        assert_eq!(imported, SnapshotImport { checkpoint: blocks[2].header.hash, blue_score: 3, headers: 3, utxos: 3 });
+        assert_eq!(target.utxo_set().utxo_commitment(), source.utxo_set().utxo_commitment());
+        assert_eq!(target.block_store().get_chain_tip(), Some((3, blocks[2].header.hash)));
+        assert_eq!(target.block_store().get_chain_block_by_blue_score(1), Some(blocks[0].header.hash));
+        assert!(target.has_header(&blocks[1].header.hash));
+        assert!(!target.has_block(&blocks[1].header.hash));
+
+        // Only into an empty database
+        assert!(loaded.import(&target, &Header::from_precomputed_hash(genesis, vec![]), 18, &trusted).unwrap_err().contains("empty"));
+    }
+
+    #[test]
+    fn test_tampered_snapshots_are_rejected() {
+        let genesis = Hash::from_le_u64([0, 0, 0, 0]);
+        let blocks = mine_chain(genesis, 3);
+        let snapshot = UtxoSnapshot::export(&exporting_storage(genesis, &blocks)).unwrap();
+        let trusted = TrustedCheckpoint { hash: snapshot.checkpoint, utxo_commitment: snapshot.commitment };
+        assert!(snapshot.verify(&trusted).is_ok());
+
+        let mut inflated = snapshot.clone();
+        inflated.utxos[0].1.amount += 1;
+        assert!(inflated.import(&ConsensusStorage::new(), &Header::from_precomputed_hash(genesis, vec![]), 18, &trusted).unwrap_err().contains("commit"));
+
+        // An exporter adding a UTXO can recompute the commitment in the file, but not the trusted one
+        let mut padded = snapshot.clone();
+        padded.utxos.push((TransactionOutpoint::new(Hash::from_le_u64([7, 0, 0, 0]), 0), UtxoEntry::new(1_000_000, ScriptPublicKey::from_vec(0, vec![7]), 1, false)));
+        let mut commitment = EMPTY_MUHASH;
+        padded.utxos.iter().for_each(|(outpoint, entry)| commitment.add_utxo(outpoint, entry));
+        padded.commitment = commitment.finalize();
+        assert!(padded.verify(&trusted).unwrap_err().contains("trusted commitment"));
+
+        let untrusted = TrustedCheckpoint { hash: blocks[1].header.hash, ..trusted };
+        assert!(snapshot.import(&ConsensusStorage::new(), &Header::from_precomputed_hash(genesis, vec![]), 18, &untrusted).unwrap_err().contains("trusted"));
+
+        let other_genesis = Hash::from_le_u64([1, 0, 0, 0]);
+        assert!(snapshot.import(&ConsensusStorage::new(), &Header::from_precomputed_hash(other_genesis, vec![]), 18, &trusted).unwrap_err().contains("genesis"));
+
+        // A checkpoint claiming more blue work than its parents give it
+        let mut forged = snapshot.clone();
+        let header = forged.headers.last_mut().unwrap();
+        header.blue_work = BlueWorkType::from(1u64 << 40);
+        header.finalize();
+        while !validate_pow(header) {
+            header.nonce += 1;
+            header.finalize();
+        }
+        forged.checkpoint = header.hash;
+        let forged_trusted = TrustedCheckpoint { hash: forged.checkpoint, ..trusted };
+        assert!(forged.verify(&forged_trusted).is_ok());
+        assert!(forged.import(&ConsensusStorage::new(), &Header::from_precomputed_hash(genesis, vec![]), 18, &forged_trusted).unwrap_err().contains("blue work"));
+
+        let bytes = snapshot.encode();
+        assert_eq!(UtxoSnapshot::decode(&bytes).unwrap().encode(), bytes);
+        assert!(UtxoSnapshot::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(UtxoSnapshot::decode(&[bytes.clone(), vec![0]].concat()).is_err());
+        assert_eq!(UtxoSnapshot::decode(b"NOTSNAP").unwrap_err(), "Not a snapshot file");
+    }
+}
@@ -6,17 +6,20 @@
 use crate::pipeline::BlockProcessor;
 use crate::consensus::storage::BlockStore;
 use crate::consensus::types::BlockStatus;
+use crate::process::download::{DownloadConfig, DownloadWindow, SyncProgress};
 use consensus_core::block::Block;
+use consensus_core::encoding::block_size;
+use consensus_core::time::{Clock, SystemClock};
 use consensus_core::Hash;
-use std::collections::{HashSet, VecDeque};
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 
 /// Block synchronization process
 pub struct SyncProcess {
     processor: Arc<BlockProcessor>,
     block_store: Arc<BlockStore>,
-    requested_blocks: std::sync::RwLock<HashSet<Hash>>,
-    sync_queue: std::sync::RwLock<VecDeque<Hash>>,
+    window: Mutex<DownloadWindow>,
+    clock: Arc<dyn Clock>,
 }
 
 impl SyncProcess {
@@ -25,35 +28,63 @@ impl SyncProcess {
         Self {
             processor,
             block_store,
-            requested_blocks: std::sync::RwLock::new(HashSet::new()),
-            sync_queue: std::sync::RwLock::new(VecDeque::new()),
+            window: Mutex::new(DownloadWindow::new(DownloadConfig::default())),
+            clock: Arc::new(SystemClock),
         }
     }
 
-    /// Start initial block download
-    pub fn start_ibd(&self, target_hashes: Vec<Hash>) -> Result<(), String> {
-        let mut queue = self.sync_queue.write().unwrap();
-        let mut requested = self.requested_blocks.write().unwrap();
+    /// Limit the body requests outstanding with each peer
+    pub fn with_download_config(self, config: DownloadConfig) -> Self {
+        Self { window: Mutex::new(DownloadWindow::new(config)), ..self }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
 
-        for hash in target_hashes {
-            if !self.block_store.has_block(&hash) && !requested.contains(&hash) {
-                queue.push_back(hash);
-                requested.insert(hash);
+    pub fn download_config(&self) -> DownloadConfig {
+        self.window.lock().unwrap().config()
+    }
+
+    /// Start initial block download of the bodies of `targets`, given as block
+    /// hashes with the sizes declared for them alongside the headers, if any
+    pub fn start_ibd(&self, targets: Vec<(Hash, Option<u64>)>) -> Result<(), String> {
+        let mut window = self.window.lock().unwrap();
+        for (hash, size) in targets {
+            if !self.block_store.has_block(&hash) {
+                window.enqueue(hash, size);
             }
         }
 
         Ok(())
     }
 
+    /// Bodies to request from `peer` now, as far as its window has room
+    pub fn next_request(&self, peer: SocketAddr) -> Vec<Hash> {
+        self.window.lock().unwrap().next_request(peer, self.clock.now_millis())
+    }
+
+    /// Give up the requests that timed out so other peers get them. Returns the
+    /// peers that timed out with the blocks they didn't deliver.
+    pub fn expire_requests(&self) -> Vec<(SocketAddr, Vec<Hash>)> {
+        self.window.lock().unwrap().expire(self.clock.now_millis())
+    }
+
+    /// Hand the requests outstanding with `peer` to the other peers
+    pub fn peer_disconnected(&self, peer: SocketAddr) {
+        self.window.lock().unwrap().peer_disconnected(peer);
+    }
+
+    /// Free the room of a received body of `size` bytes in its peer's window.
+    /// Returns false if the body was not queued or requested.
+    pub fn body_received(&self, hash: &Hash, size: u64) -> bool {
+        self.window.lock().unwrap().received(hash, size)
+    }
+
     /// Process received block during sync
     pub fn process_sync_block(&self, block: Block) -> Result<BlockStatus, String> {
-        let hash = block.header.hash;
-
-        // Remove from requested set
-        {
-            let mut requested = self.requested_blocks.write().unwrap();
-            requested.remove(&hash);
-        }
+        // Free its room in the window before handing it to the pipeline
+        self.body_received(&block.header.hash, block_size(&block));
 
         // Process the block
         let result = self.processor.process_block(block).map_err(|e| format!("{:?}", e))?;
@@ -66,24 +97,9 @@ impl SyncProcess {
         Ok(result.status)
     }
 
-    /// Get next blocks to request
-    pub fn get_blocks_to_request(&self, max_count: usize) -> Vec<Hash> {
-        let mut queue = self.sync_queue.write().unwrap();
-        let mut result = Vec::new();
-
-        while result.len() < max_count && !queue.is_empty() {
-            if let Some(hash) = queue.pop_front() {
-                result.push(hash);
-            }
-        }
-
-        result
-    }
-
     /// Check if sync is complete
     pub fn is_sync_complete(&self) -> bool {
-        self.sync_queue.read().unwrap().is_empty() &&
-        self.requested_blocks.read().unwrap().is_empty()
+        self.window.lock().unwrap().is_complete()
     }
 
     /// Request next blocks based on newly processed blocks
@@ -94,31 +110,22 @@ impl SyncProcess {
         Ok(())
     }
 
-    /// Handle missing block during sync
-    pub fn handle_missing_block(&self, hash: Hash) -> Result<(), String> {
-        let mut requested = self.requested_blocks.write().unwrap();
-
-        if !requested.contains(&hash) && !self.block_store.has_block(&hash) {
-            let mut queue = self.sync_queue.write().unwrap();
-            queue.push_back(hash);
-            requested.insert(hash);
+    /// Handle missing block during sync, declared to be `size` bytes if known
+    pub fn handle_missing_block(&self, hash: Hash, size: Option<u64>) -> Result<(), String> {
+        if !self.block_store.has_block(&hash) {
+            self.window.lock().unwrap().enqueue(hash, size);
         }
 
         Ok(())
     }
 
+    /// Downloaded and remaining bodies, download speed and ETA
+    pub fn sync_progress(&self) -> SyncProgress {
+        self.window.lock().unwrap().progress(self.clock.now_millis())
+    }
+
     /// Get sync progress (0.0 to 1.0)
     pub fn get_sync_progress(&self) -> f64 {
-        // Placeholder progress calculation
-        // In real implementation, this would track downloaded vs total blocks
-        let queue_len = self.sync_queue.read().unwrap().len();
-        let requested_len = self.requested_blocks.read().unwrap().len();
-
-        if queue_len + requested_len == 0 {
-            1.0
-        } else {
-            // Simple heuristic: assume requested blocks are 50% complete
-            0.5 / (queue_len + requested_len) as f64
-        }
+        self.sync_progress().fraction()
     }
 }
@@ -0,0 +1,184 @@
+//! Re-validation of stored blocks
+//!
+//! Runs the header validator (including proof of work) over stored blocks and,
+//! optionally, the block validator over their bodies, to find blocks corrupted
+//! on disk. A header that no longer hashes to the key it is stored under is
+//! reported as well, since the validators only see the header's contents.
+
+use crate::consensus::storage::BlockStore;
+use crate::consensus::validation::{BlockValidator, HeaderValidator, TransactionValidator};
+use consensus_core::block::Block;
+use consensus_core::hashing::header::calculate_header_hash;
+use consensus_core::header::Header;
+use consensus_core::Hash;
+use std::sync::Arc;
+
+/// A stored block that failed re-validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyFailure {
+    pub hash: Hash,
+    pub daa_score: u64,
+    pub error: String,
+}
+
+/// Outcome of a verification run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Headers checked
+    pub checked: usize,
+    /// Bodies checked, zero unless bodies were asked for
+    pub bodies_checked: usize,
+    pub failures: Vec<VerifyFailure>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub struct ChainVerifier {
+    block_store: Arc<BlockStore>,
+    header_validator: Arc<HeaderValidator>,
+    block_validator: BlockValidator,
+    check_bodies: bool,
+}
+
+impl ChainVerifier {
+    pub fn new(block_store: Arc<BlockStore>) -> Self {
+        let header_validator = Arc::new(HeaderValidator::new());
+        let block_validator = BlockValidator::new(header_validator.clone(), Arc::new(TransactionValidator::new()));
+        Self { block_store, header_validator, block_validator, check_bodies: false }
+    }
+
+    /// Also validate the bodies of blocks that still have them
+    pub fn with_bodies(self, check_bodies: bool) -> Self {
+        Self { check_bodies, ..self }
+    }
+
+    /// Verify the `depth` blocks with the highest DAA scores, or all of them when
+    /// `None`, lowest first. `progress` gets the blocks checked so far and their total.
+    pub fn verify(&self, depth: Option<usize>, mut progress: impl FnMut(usize, usize)) -> VerifyReport {
+        let mut hashes = self.block_store.get_blocks_by_daa_range(0, u64::MAX);
+        if let Some(depth) = depth {
+            hashes.drain(..hashes.len().saturating_sub(depth));
+        }
+
+        let mut report = VerifyReport::default();
+        for (i, hash) in hashes.iter().enumerate() {
+            self.verify_block(hash, &mut report);
+            progress(i + 1, hashes.len());
+        }
+        report
+    }
+
+    fn verify_block(&self, hash: &Hash, report: &mut VerifyReport) {
+        let Some(header) = self.block_store.get_header(hash) else {
+            return;
+        };
+        report.checked += 1;
+        // Bodies are only loaded when they are checked
+        let block = match self.check_bodies {
+            true => self.block_store.get_block_transactions_lazy(hash).map(|transactions| Block::new(header.clone(), transactions)),
+            false => None,
+        };
+        if block.is_some() {
+            report.bodies_checked += 1;
+        }
+        if let Err(error) = self.check(hash, &header, block.as_ref()) {
+            report.failures.push(VerifyFailure { hash: *hash, daa_score: header.daa_score, error });
+        }
+    }
+
+    fn check(&self, hash: &Hash, header: &Header, block: Option<&Block>) -> Result<(), String> {
+        if header.hash != *hash || calculate_header_hash(header) != *hash {
+            return Err("header does not hash to its block hash".to_string());
+        }
+        self.header_validator.validate_header(header).map_err(|e| e.to_string())?;
+        if let Some(block) = block {
+            self.block_validator.validate_block(block).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::constants::BLOCK_VERSION;
+    use consensus_core::hashing::header::validate_pow;
+    use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+    use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Block at DAA score `daa_score` with a coinbase and a nonce satisfying the easiest target
+    fn mine_block(daa_score: u64) -> Block {
+        let coinbase = Transaction::new(
+            0,
+            Vec::new(),
+            vec![TransactionOutput::new(5000000000, ScriptPublicKey::from_vec(0, Vec::new()))],
+            0,
+            SUBNETWORK_ID_COINBASE,
+            0,
+            daa_score.to_le_bytes().to_vec(),
+        );
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let mut header = Header::new_finalized(
+            BLOCK_VERSION,
+            vec![],
+            ZERO_HASH,
+            ZERO_HASH,
+            ZERO_HASH,
+            timestamp,
+            0x207fffff,
+            0,
+            daa_score,
+            BlueWorkType::from(0u64),
+            daa_score,
+            ZERO_HASH,
+        );
+        while !validate_pow(&header) {
+            header.nonce += 1;
+            header.finalize();
+        }
+        Block::new(header, vec![coinbase])
+    }
+
+    #[test]
+    fn test_verify_flags_corrupted_blocks() {
+        let store = Arc::new(BlockStore::new());
+        let blocks: Vec<Block> = (1..=4).map(mine_block).collect();
+        for block in &blocks {
+            store.store_block(block.clone()).unwrap();
+        }
+        let verifier = ChainVerifier::new(store.clone()).with_bodies(true);
+        let report = verifier.verify(None, |_, _| {});
+        assert!(report.is_ok(), "{:?}", report.failures);
+        assert_eq!((report.checked, report.bodies_checked), (4, 4));
+
+        // A flipped nonce and a body missing its coinbase
+        let mut corrupted = blocks[1].clone();
+        corrupted.header.nonce ^= 1;
+        store.store_block(corrupted).unwrap();
+        let mut emptied = blocks[3].clone();
+        emptied.transactions.clear();
+        store.store_block(emptied).unwrap();
+
+        let mut progress = Vec::new();
+        let report = verifier.verify(None, |done, total| progress.push((done, total)));
+        let failed: Vec<Hash> = report.failures.iter().map(|f| f.hash).collect();
+        assert_eq!(failed, vec![blocks[1].header.hash, blocks[3].header.hash]);
+        assert_eq!(progress.last(), Some(&(4, 4)));
+
+        // Headers only: the body problem goes unnoticed
+        let report = ChainVerifier::new(store.clone()).verify(None, |_, _| {});
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.bodies_checked, 0);
+
+        // Depth limits the check to the highest DAA scores
+        let report = verifier.verify(Some(2), |_, _| {});
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.failures.iter().map(|f| f.hash).collect::<Vec<_>>(), vec![blocks[3].header.hash]);
+    }
+}
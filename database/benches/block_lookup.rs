@@ -0,0 +1,44 @@
+// Looking a block up by height (DAA score) as getBlockByHeight does, over
+// chains of 1k, 10k and 100k blocks. The DAA index makes it a point query, so
+// latency should not grow with the chain.
+// Run with: cargo bench --bench block_lookup
+
+use consensus_core::block::Block;
+use consensus_core::header::Header;
+use consensus_core::Hash;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use database::stores::BlockStore;
+use database::Database;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// A chain of `blocks` empty blocks at DAA scores 0..blocks; the directory must outlive it
+fn populated_store(blocks: u64) -> (TempDir, BlockStore) {
+    let tmp = TempDir::new().unwrap();
+    let store = BlockStore::new(Arc::new(Database::open(tmp.path()).unwrap()), 1);
+    for n in 0..blocks {
+        let mut header = Header::from_precomputed_hash(Hash::from_u64_word(n + 1), vec![]);
+        header.daa_score = n;
+        store.put_block(&Block::new(header, Vec::new())).unwrap();
+    }
+    (tmp, store)
+}
+
+fn bench_block_by_height(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_by_height");
+    for blocks in [1_000, 10_000, 100_000] {
+        let (_tmp, store) = populated_store(blocks);
+        let headers = store.headers();
+        let height = blocks / 2;
+        group.bench_with_input(BenchmarkId::from_parameter(blocks), &height, |b, &height| {
+            b.iter(|| {
+                let hashes = headers.get_blocks_by_daa_range(height, height).unwrap();
+                black_box(store.get_block(&hashes[0]).unwrap())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_block_by_height);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+// Header iteration over stores of 10k blocks whose bodies hold 1 or 1k transactions.
+// Headers and bodies live in separate keyspaces, so both should take about as long.
+// Run with: cargo bench --bench header_iteration
+
+use consensus_core::block::Block;
+use consensus_core::header::Header;
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+use consensus_core::Hash;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use database::stores::BlockStore;
+use database::Database;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const BLOCKS: u64 = 10_000;
+
+/// A store of `BLOCKS` blocks with `transactions` transactions each; the directory must outlive it
+fn populated_store(transactions: usize) -> (TempDir, BlockStore) {
+    let tmp = TempDir::new().unwrap();
+    let store = BlockStore::new(Arc::new(Database::open(tmp.path()).unwrap()), 1);
+    let tx = Transaction::new(
+        0,
+        vec![],
+        vec![TransactionOutput::new(1_000, ScriptPublicKey::from_vec(0, vec![1; 34]))],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    );
+    let body = vec![tx; transactions];
+    for n in 0..BLOCKS {
+        let mut header = Header::from_precomputed_hash(Hash::from_u64_word(n + 1), vec![]);
+        header.daa_score = n;
+        store.put_block(&Block::new(header, body.clone())).unwrap();
+    }
+    (tmp, store)
+}
+
+fn bench_header_iteration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header_iteration");
+    group.sample_size(10);
+    for transactions in [1, 1_000] {
+        let (_tmp, store) = populated_store(transactions);
+        let headers = store.headers();
+        group.bench_with_input(BenchmarkId::new("get_all_headers", transactions), &transactions, |b, _| {
+            b.iter(|| black_box(headers.get_all_headers().unwrap().len()))
+        });
+        group.bench_with_input(BenchmarkId::new("get_blocks_by_daa_range", transactions), &transactions, |b, _| {
+            b.iter(|| black_box(headers.get_blocks_by_daa_range(0, u64::MAX).unwrap().len()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_header_iteration);
+criterion_main!(benches);
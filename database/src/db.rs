@@ -4,7 +4,9 @@ use std::path::Path;
 use std::sync::Arc;
 use parking_lot::RwLock;
 
+/// Whole blocks as written before headers and bodies were split, see `BlockStore::migrate_legacy_blocks`
 pub const CF_BLOCKS: &str = "blocks";
+pub const CF_BLOCK_BODIES: &str = "block_bodies";
 pub const CF_HEADERS: &str = "headers";
 pub const CF_TRANSACTIONS: &str = "transactions";
 pub const CF_UTXOS: &str = "utxos";
@@ -12,6 +14,11 @@ pub const CF_GHOSTDAG: &str = "ghostdag";
 pub const CF_REACHABILITY: &str = "reachability";
 pub const CF_METADATA: &str = "metadata";
 pub const CF_BLOCK_RELATIONS: &str = "block_relations";
+pub const CF_ADDRESS_INDEX: &str = "address_index";
+pub const CF_CHAIN_INDEX: &str = "chain_index";
+pub const CF_DAA_INDEX: &str = "daa_index";
+pub const CF_BLOCK_PROVENANCE: &str = "block_provenance";
+pub const CF_BLOCK_FEES: &str = "block_fees";
 
 pub struct Database {
     db: Arc<DB>,
@@ -34,6 +41,7 @@ impl Database {
 
         let cf_names = vec![
             CF_BLOCKS,
+            CF_BLOCK_BODIES,
             CF_HEADERS,
             CF_TRANSACTIONS,
             CF_UTXOS,
@@ -41,6 +49,11 @@ impl Database {
             CF_REACHABILITY,
             CF_METADATA,
             CF_BLOCK_RELATIONS,
+            CF_ADDRESS_INDEX,
+            CF_CHAIN_INDEX,
+            CF_DAA_INDEX,
+            CF_BLOCK_PROVENANCE,
+            CF_BLOCK_FEES,
         ];
 
         let cf_descriptors: Vec<_> = cf_names
@@ -92,6 +105,18 @@ impl Database {
 
     pub fn write_batch(&self, batch: WriteBatch) -> DbResult<()> { self.check_closed()?; self.db.write(batch)?; Ok(()) }
 
+    pub fn batch_put(&self, batch: &mut WriteBatch, cf_name: &str, key: &[u8], value: &[u8]) -> DbResult<()> {
+        let cf = self.get_cf_handle(cf_name)?;
+        batch.put_cf(cf, key, value);
+        Ok(())
+    }
+
+    pub fn batch_delete(&self, batch: &mut WriteBatch, cf_name: &str, key: &[u8]) -> DbResult<()> {
+        let cf = self.get_cf_handle(cf_name)?;
+        batch.delete_cf(cf, key);
+        Ok(())
+    }
+
     pub fn iterator(&self, cf_name: &str, mode: IteratorMode) -> DbResult<rocksdb::DBIteratorWithThreadMode<'_, DB>> {
         self.check_closed()?;
         let cf = self.get_cf_handle(cf_name)?;
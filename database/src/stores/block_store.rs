@@ -1,62 +1,174 @@
+//! Block storage split into headers and bodies
+//!
+//! Headers go to the header keyspace through [`HeaderStore`], bodies (the
+//! transaction lists) to `CF_BLOCK_BODIES`, so header reads never deserialize
+//! transactions. Removing a block deletes its body only; the header stays
+//! until it is deleted from the header store.
+
 use crate::{Database, DbResult};
 use crate::cache::WriteThroughCache;
+use crate::db::{CF_BLOCKS, CF_BLOCK_BODIES};
+use crate::stores::HeaderStore;
 use consensus_core::block::Block;
+use consensus_core::tx::Transaction;
 use consensus_core::Hash;
+use rocksdb::IteratorMode;
 use std::sync::Arc;
 
 pub struct BlockStore {
     db: Arc<Database>,
-    cache: WriteThroughCache<Hash, Block>,
+    headers: Arc<HeaderStore>,
+    cache: WriteThroughCache<Hash, Vec<Transaction>>,
 }
 
 impl BlockStore {
     pub fn new(db: Arc<Database>, cache_size: usize) -> Self {
-        Self { db, cache: WriteThroughCache::new(cache_size) }
+        let headers = Arc::new(HeaderStore::new(db.clone(), cache_size));
+        Self { db, headers, cache: WriteThroughCache::new(cache_size) }
+    }
+
+    /// Header store holding the headers of the stored blocks
+    pub fn headers(&self) -> Arc<HeaderStore> {
+        self.headers.clone()
     }
 
+    /// Store the header and body of `block` in one write
     pub fn put_block(&self, block: &Block) -> DbResult<()> {
         let hash = block.header.hash;
-        let serialized = bincode::serialize(block)?;
-        self.db.put(crate::db::CF_BLOCKS, hash.as_bytes(), &serialized)?;
-        self.cache.insert(hash, block.clone());
+        let mut batch = self.db.batch();
+        self.headers.batch_put_header(&mut batch, &block.header)?;
+        self.db.batch_put(&mut batch, CF_BLOCK_BODIES, &hash.as_bytes(), &bincode::serialize(&block.transactions)?)?;
+        self.db.write_batch(batch)?;
+        self.headers.cache_header(&block.header);
+        self.cache.insert(hash, block.transactions.clone());
         Ok(())
     }
 
     pub fn get_block(&self, hash: &Hash) -> DbResult<Option<Block>> {
-        if let Some(b) = self.cache.get(hash) { return Ok(Some(b)); }
-        if let Some(data) = self.db.get(crate::db::CF_BLOCKS, hash.as_bytes())? {
-            let block: Block = bincode::deserialize(&data)?;
-            self.cache.insert(*hash, block.clone());
-            Ok(Some(block))
+        let Some(transactions) = self.get_transactions(hash)? else { return Ok(None) };
+        Ok(self.headers.get_header(hash)?.map(|header| Block::new(header, transactions)))
+    }
+
+    /// Body of a stored block, without reading its header
+    pub fn get_transactions(&self, hash: &Hash) -> DbResult<Option<Vec<Transaction>>> {
+        if let Some(transactions) = self.cache.get(hash) { return Ok(Some(transactions)); }
+        if let Some(data) = self.db.get(CF_BLOCK_BODIES, &hash.as_bytes())? {
+            let transactions: Vec<Transaction> = bincode::deserialize(&data)?;
+            self.cache.insert(*hash, transactions.clone());
+            Ok(Some(transactions))
         } else { Ok(None) }
     }
 
+    /// Whether the body of the block is stored
     pub fn has_block(&self, hash: &Hash) -> DbResult<bool> {
         if self.cache.get(hash).is_some() { return Ok(true); }
-        self.db.exists(crate::db::CF_BLOCKS, hash.as_bytes())
+        self.db.exists(CF_BLOCK_BODIES, &hash.as_bytes())
     }
 
+    /// Delete the body of a block, keeping its header
     pub fn delete_block(&self, hash: &Hash) -> DbResult<()> {
-        self.db.delete(crate::db::CF_BLOCKS, hash.as_bytes())?;
+        self.db.delete(CF_BLOCK_BODIES, &hash.as_bytes())?;
         self.cache.remove(hash);
         Ok(())
     }
 
     pub fn count(&self) -> DbResult<usize> {
-        let mut count = 0usize;
-        let iter = self.db.iterator(crate::db::CF_BLOCKS, rocksdb::IteratorMode::Start)?;
-        for _ in iter { count += 1; }
-        Ok(count)
+        Ok(self.db.iterator(CF_BLOCK_BODIES, IteratorMode::Start)?.count())
     }
 
-    pub fn get_all_blocks(&self) -> DbResult<Vec<Block>> {
-        let mut blocks = Vec::new();
-        let iter = self.db.iterator(crate::db::CF_BLOCKS, rocksdb::IteratorMode::Start)?;
-        for item in iter {
-            let (_, data) = item?;
+    /// Hashes of the blocks whose bodies are stored, read from the keys alone
+    pub fn block_hashes(&self) -> DbResult<Vec<Hash>> {
+        let mut hashes = Vec::new();
+        for item in self.db.iterator(CF_BLOCK_BODIES, IteratorMode::Start)? {
+            let (key, _) = item?;
+            hashes.push(Hash::from_slice(&key));
+        }
+        Ok(hashes)
+    }
+
+    /// Split whole blocks written by earlier versions to `CF_BLOCKS` into
+    /// headers and bodies. Returns the number of blocks moved.
+    pub fn migrate_legacy_blocks(&self) -> DbResult<usize> {
+        let mut batch = self.db.batch();
+        let mut moved = 0;
+        for item in self.db.iterator(CF_BLOCKS, IteratorMode::Start)? {
+            let (key, data) = item?;
             let block: Block = bincode::deserialize(&data)?;
-            blocks.push(block);
+            self.headers.batch_put_header(&mut batch, &block.header)?;
+            self.db.batch_put(&mut batch, CF_BLOCK_BODIES, &key, &bincode::serialize(&block.transactions)?)?;
+            self.db.batch_delete(&mut batch, CF_BLOCKS, &key)?;
+            moved += 1;
+        }
+        if moved > 0 {
+            self.db.write_batch(batch)?;
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::header::Header;
+    use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+    use consensus_core::tx::{ScriptPublicKey, TransactionOutput};
+    use tempfile::TempDir;
+
+    fn block(n: u64) -> Block {
+        let coinbase = Transaction::new(
+            0,
+            vec![],
+            vec![TransactionOutput::new(n, ScriptPublicKey::from_vec(0, vec![1; 34]))],
+            0,
+            SUBNETWORK_ID_COINBASE,
+            0,
+            vec![],
+        );
+        Block::new(Header::from_precomputed_hash(Hash::from_u64_word(n), vec![]), vec![coinbase])
+    }
+
+    #[test]
+    fn test_header_and_body_are_stored_apart() {
+        let tmp = TempDir::new().unwrap();
+        let db = Arc::new(Database::open(tmp.path()).unwrap());
+        let store = BlockStore::new(db.clone(), 16);
+        let block = block(1);
+        let hash = block.header.hash;
+        store.put_block(&block).unwrap();
+
+        // A fresh store reads from disk, not the caches
+        let store = BlockStore::new(db, 16);
+        assert_eq!(store.headers().get_header(&hash).unwrap().unwrap().hash, hash);
+        assert_eq!(store.get_transactions(&hash).unwrap().unwrap().len(), 1);
+        assert_eq!(store.get_block(&hash).unwrap().unwrap().header.hash, hash);
+        assert_eq!(store.block_hashes().unwrap(), vec![hash]);
+
+        // Deleting the block leaves its header
+        store.delete_block(&hash).unwrap();
+        assert!(!store.has_block(&hash).unwrap());
+        assert!(store.get_block(&hash).unwrap().is_none());
+        assert!(store.headers().has_header(&hash).unwrap());
+        assert_eq!(store.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_migrate_legacy_blocks() {
+        let tmp = TempDir::new().unwrap();
+        let db = Arc::new(Database::open(tmp.path()).unwrap());
+        let blocks = [block(1), block(2)];
+        for block in &blocks {
+            db.put(CF_BLOCKS, &block.header.hash.as_bytes(), &bincode::serialize(block).unwrap()).unwrap();
+        }
+
+        let store = BlockStore::new(db.clone(), 16);
+        assert_eq!(store.migrate_legacy_blocks().unwrap(), 2);
+        assert_eq!(store.migrate_legacy_blocks().unwrap(), 0);
+        assert_eq!(store.count().unwrap(), 2);
+        for block in &blocks {
+            let stored = store.get_block(&block.header.hash).unwrap().unwrap();
+            assert_eq!(stored.transactions[0].outputs[0].value, block.transactions[0].outputs[0].value);
         }
-        Ok(blocks)
+        assert_eq!(store.headers().get_blocks_by_daa_range(0, 0).unwrap().len(), 2);
+        assert!(db.iterator(CF_BLOCKS, IteratorMode::Start).unwrap().next().is_none());
     }
 }
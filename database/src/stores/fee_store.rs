@@ -0,0 +1,70 @@
+//! Fee totals of recently accepted blocks, see `consensus_core::fees`
+
+use crate::db::CF_BLOCK_FEES;
+use crate::{Database, DbError, DbResult};
+use consensus_core::fees::BlockFeeRecord;
+use rocksdb::IteratorMode;
+use std::sync::Arc;
+
+/// Records keyed by their position in acceptance order, big-endian so they
+/// iterate oldest first
+pub struct FeeStore {
+    db: Arc<Database>,
+}
+
+impl FeeStore {
+    pub fn new(db: Arc<Database>) -> Self { Self { db } }
+
+    /// Store `record` as the `seq`th accepted block, deleting the `evicted`
+    /// record in the same write
+    pub fn insert(&self, seq: u64, record: &BlockFeeRecord, evicted: Option<u64>) -> DbResult<()> {
+        let mut batch = self.db.batch();
+        self.db.batch_put(&mut batch, CF_BLOCK_FEES, &seq.to_be_bytes(), &bincode::serialize(record)?)?;
+        if let Some(evicted) = evicted {
+            self.db.batch_delete(&mut batch, CF_BLOCK_FEES, &evicted.to_be_bytes())?;
+        }
+        self.db.write_batch(batch)
+    }
+
+    pub fn delete(&self, seq: u64) -> DbResult<()> {
+        self.db.delete(CF_BLOCK_FEES, &seq.to_be_bytes())
+    }
+
+    /// Every stored record with its position, oldest first
+    pub fn get_all(&self) -> DbResult<Vec<(u64, BlockFeeRecord)>> {
+        let mut records = Vec::new();
+        for item in self.db.iterator(CF_BLOCK_FEES, IteratorMode::Start)? {
+            let (key, data) = item?;
+            let seq = u64::from_be_bytes(key.as_ref().try_into().map_err(|_| DbError::InvalidData("fee record key".to_string()))?);
+            records.push((seq, bincode::deserialize(&data)?));
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::Hash;
+    use tempfile::TempDir;
+
+    fn record(n: u64) -> BlockFeeRecord {
+        BlockFeeRecord { hash: Hash::from_le_u64([n, 0, 0, 0]), daa_score: n, total_fees: n * 100, mass: 100 }
+    }
+
+    #[test]
+    fn test_records_iterate_in_acceptance_order() {
+        let tmp = TempDir::new().unwrap();
+        let store = FeeStore::new(Arc::new(Database::open(tmp.path()).unwrap()));
+        // Past 255 the order of the big-endian keys still follows the positions
+        for seq in [1, 300, 2] {
+            store.insert(seq, &record(seq), None).unwrap();
+        }
+        store.insert(301, &record(301), Some(1)).unwrap();
+        let seqs: Vec<u64> = store.get_all().unwrap().into_iter().map(|(seq, _)| seq).collect();
+        assert_eq!(seqs, vec![2, 300, 301]);
+
+        store.delete(2).unwrap();
+        assert_eq!(store.get_all().unwrap()[0], (300, record(300)));
+    }
+}
@@ -1,7 +1,15 @@
+//! Header storage with two secondary indexes
+//!
+//! - blue score to the selected chain block with that score (`CF_CHAIN_INDEX`)
+//! - DAA score and hash of every stored header (`CF_DAA_INDEX`), keyed big-endian
+//!   so a range scan yields blocks by DAA score, then hash
+
 use crate::{Database, DbResult};
 use crate::cache::WriteThroughCache;
+use crate::db::{CF_CHAIN_INDEX, CF_DAA_INDEX, CF_HEADERS};
 use consensus_core::header::Header as BlockHeader;
 use consensus_core::Hash;
+use rocksdb::{Direction, IteratorMode, WriteBatch};
 use std::sync::Arc;
 
 pub struct HeaderStore {
@@ -15,13 +23,23 @@ impl HeaderStore {
     }
 
     pub fn put_header(&self, header: &BlockHeader) -> DbResult<()> {
-        let hash = header.hash;
-        let serialized = bincode::serialize(header)?;
-        self.db.put(crate::db::CF_HEADERS, hash.as_bytes(), &serialized)?;
-        self.cache.insert(hash, header.clone());
+        let mut batch = self.db.batch();
+        self.batch_put_header(&mut batch, header)?;
+        self.db.write_batch(batch)?;
+        self.cache_header(header);
         Ok(())
     }
 
+    /// Add the header and its DAA index entry to `batch`; call `cache_header` once written
+    pub(crate) fn batch_put_header(&self, batch: &mut WriteBatch, header: &BlockHeader) -> DbResult<()> {
+        self.db.batch_put(batch, CF_HEADERS, &header.hash.as_bytes(), &bincode::serialize(header)?)?;
+        self.db.batch_put(batch, CF_DAA_INDEX, &Self::daa_key(header.daa_score, &header.hash), &[])
+    }
+
+    pub(crate) fn cache_header(&self, header: &BlockHeader) {
+        self.cache.insert(header.hash, header.clone());
+    }
+
     pub fn get_header(&self, hash: &Hash) -> DbResult<Option<BlockHeader>> {
         if let Some(h) = self.cache.get(hash) { return Ok(Some(h)); }
         if let Some(data) = self.db.get(crate::db::CF_HEADERS, hash.as_bytes())? {
@@ -36,8 +54,14 @@ impl HeaderStore {
         self.db.exists(crate::db::CF_HEADERS, hash.as_bytes())
     }
 
+    /// Delete a header with its index entries
     pub fn delete_header(&self, hash: &Hash) -> DbResult<()> {
-        self.db.delete(crate::db::CF_HEADERS, hash.as_bytes())?;
+        let mut batch = self.db.batch();
+        if let Some(header) = self.get_header(hash)? {
+            self.unindex_into(&mut batch, &header)?;
+        }
+        self.db.batch_delete(&mut batch, CF_HEADERS, &hash.as_bytes())?;
+        self.db.write_batch(batch)?;
         self.cache.remove(hash);
         Ok(())
     }
@@ -51,4 +75,189 @@ impl HeaderStore {
         }
         Ok(count)
     }
+
+    /// Every stored header, without touching block bodies
+    pub fn get_all_headers(&self) -> DbResult<Vec<BlockHeader>> {
+        let mut headers = Vec::new();
+        for item in self.db.iterator(CF_HEADERS, IteratorMode::Start)? {
+            let (_, data) = item?;
+            headers.push(bincode::deserialize(&data)?);
+        }
+        Ok(headers)
+    }
+
+    fn unindex_into(&self, batch: &mut WriteBatch, header: &BlockHeader) -> DbResult<()> {
+        self.db.batch_delete(batch, CF_DAA_INDEX, &Self::daa_key(header.daa_score, &header.hash))?;
+        if self.get_chain_block_by_blue_score(header.blue_score)? == Some(header.hash) {
+            self.db.batch_delete(batch, CF_CHAIN_INDEX, &header.blue_score.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Blocks with a DAA score in `from..=to`, by DAA score, then hash
+    pub fn get_blocks_by_daa_range(&self, from: u64, to: u64) -> DbResult<Vec<Hash>> {
+        let mut hashes = Vec::new();
+        for item in self.db.iterator(CF_DAA_INDEX, IteratorMode::From(&from.to_be_bytes(), Direction::Forward))? {
+            let (key, _) = item?;
+            let (daa_score, hash) = Self::parse_daa_key(&key);
+            if daa_score > to {
+                break;
+            }
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Up to `limit` (DAA score, hash) entries following `after`, or from the lowest one when `None`
+    pub fn get_daa_entries_after(&self, after: Option<(u64, Hash)>, limit: usize) -> DbResult<Vec<(u64, Hash)>> {
+        let start = after.map(|(daa_score, hash)| Self::daa_key(daa_score, &hash)).unwrap_or_default();
+        let mut entries = Vec::new();
+        for item in self.db.iterator(CF_DAA_INDEX, IteratorMode::From(&start, Direction::Forward))? {
+            if entries.len() >= limit {
+                break;
+            }
+            let (key, _) = item?;
+            if *key == *start {
+                continue;
+            }
+            entries.push(Self::parse_daa_key(&key));
+        }
+        Ok(entries)
+    }
+
+    /// Up to `limit` (DAA score, hash) entries preceding `before`, highest first,
+    /// or from the highest one when `None`
+    pub fn get_daa_entries_before(&self, before: Option<(u64, Hash)>, limit: usize) -> DbResult<Vec<(u64, Hash)>> {
+        let start = before.map(|(daa_score, hash)| Self::daa_key(daa_score, &hash));
+        let mode = match &start {
+            Some(key) => IteratorMode::From(key, Direction::Reverse),
+            None => IteratorMode::End,
+        };
+        let mut entries = Vec::new();
+        for item in self.db.iterator(CF_DAA_INDEX, mode)? {
+            if entries.len() >= limit {
+                break;
+            }
+            let (key, _) = item?;
+            if start.as_deref() == Some(&*key) {
+                continue;
+            }
+            entries.push(Self::parse_daa_key(&key));
+        }
+        Ok(entries)
+    }
+
+    /// The selected chain block with blue score `blue_score`
+    pub fn get_chain_block_by_blue_score(&self, blue_score: u64) -> DbResult<Option<Hash>> {
+        Ok(self.db.get(CF_CHAIN_INDEX, &blue_score.to_be_bytes())?.map(|data| Hash::from_slice(&data)))
+    }
+
+    /// Blue score and hash of the highest indexed chain block
+    pub fn get_chain_tip(&self) -> DbResult<Option<(u64, Hash)>> {
+        match self.db.iterator(CF_CHAIN_INDEX, IteratorMode::End)?.next() {
+            Some(item) => {
+                let (key, value) = item?;
+                Ok(Some((Self::parse_score(&key), Hash::from_slice(&value))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the chain from `blue_score` up with `chain`, in one write. Entries of
+    /// a chain reorged away are dropped even where `chain` has no block of that score.
+    /// Returns the replaced entries, lowest score first.
+    pub fn replace_chain_from(&self, blue_score: u64, chain: &[(u64, Hash)]) -> DbResult<Vec<(u64, Hash)>> {
+        let mut batch = self.db.batch();
+        let mut replaced = Vec::new();
+        for item in self.db.iterator(CF_CHAIN_INDEX, IteratorMode::From(&blue_score.to_be_bytes(), Direction::Forward))? {
+            let (key, value) = item?;
+            self.db.batch_delete(&mut batch, CF_CHAIN_INDEX, &key)?;
+            replaced.push((Self::parse_score(&key), Hash::from_slice(&value)));
+        }
+        for (score, hash) in chain {
+            self.db.batch_put(&mut batch, CF_CHAIN_INDEX, &score.to_be_bytes(), &hash.as_bytes())?;
+        }
+        self.db.write_batch(batch)?;
+        Ok(replaced)
+    }
+
+    pub(crate) fn daa_key(daa_score: u64, hash: &Hash) -> Vec<u8> {
+        let mut key = daa_score.to_be_bytes().to_vec();
+        key.extend_from_slice(&hash.as_bytes());
+        key
+    }
+
+    fn parse_daa_key(key: &[u8]) -> (u64, Hash) {
+        (Self::parse_score(key), Hash::from_slice(&key[8..]))
+    }
+
+    fn parse_score(key: &[u8]) -> u64 {
+        let mut score = [0u8; 8];
+        score.copy_from_slice(&key[..8]);
+        u64::from_be_bytes(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn header(n: u64, blue_score: u64, daa_score: u64) -> BlockHeader {
+        let mut header = BlockHeader::from_precomputed_hash(Hash::from_u64_word(n), vec![]);
+        header.blue_score = blue_score;
+        header.daa_score = daa_score;
+        header
+    }
+
+    #[test]
+    fn test_daa_index_out_of_order() {
+        let tmp = TempDir::new().unwrap();
+        let store = HeaderStore::new(Arc::new(Database::open(tmp.path()).unwrap()), 16);
+
+        // DAA scores 300, 1, 256, 2, 256 stored in that order
+        let headers = [header(1, 3, 300), header(2, 1, 1), header(3, 2, 256), header(4, 1, 2), header(5, 2, 256)];
+        for header in &headers {
+            store.put_header(header).unwrap();
+        }
+
+        let hashes = |range: &[usize]| range.iter().map(|&i| headers[i].hash).collect::<Vec<_>>();
+        assert_eq!(store.get_blocks_by_daa_range(0, u64::MAX).unwrap(), hashes(&[1, 3, 2, 4, 0]));
+        assert_eq!(store.get_blocks_by_daa_range(2, 256).unwrap(), hashes(&[3, 2, 4]));
+        assert!(store.get_blocks_by_daa_range(3, 255).unwrap().is_empty());
+
+        let after = store.get_daa_entries_after(Some((256, headers[2].hash)), 2).unwrap();
+        assert_eq!(after, vec![(256, headers[4].hash), (300, headers[0].hash)]);
+        let before = store.get_daa_entries_before(Some((256, headers[4].hash)), 2).unwrap();
+        assert_eq!(before, vec![(256, headers[2].hash), (2, headers[3].hash)]);
+        assert_eq!(store.get_daa_entries_before(None, 1).unwrap(), vec![(300, headers[0].hash)]);
+
+        store.delete_header(&headers[2].hash).unwrap();
+        assert_eq!(store.get_blocks_by_daa_range(256, 256).unwrap(), hashes(&[4]));
+    }
+
+    #[test]
+    fn test_chain_index_reorg() {
+        let tmp = TempDir::new().unwrap();
+        let store = HeaderStore::new(Arc::new(Database::open(tmp.path()).unwrap()), 16);
+        let hash = Hash::from_u64_word;
+
+        store.replace_chain_from(0, &[(2, hash(2)), (0, hash(0)), (1, hash(1)), (3, hash(3))]).unwrap();
+        assert_eq!(store.get_chain_block_by_blue_score(2).unwrap(), Some(hash(2)));
+        assert_eq!(store.get_chain_tip().unwrap(), Some((3, hash(3))));
+
+        // A reorg below score 2 onto a chain that skips score 3
+        let replaced = store.replace_chain_from(2, &[(4, hash(14)), (2, hash(12))]).unwrap();
+        assert_eq!(replaced, vec![(2, hash(2)), (3, hash(3))]);
+        assert_eq!(store.get_chain_block_by_blue_score(1).unwrap(), Some(hash(1)));
+        assert_eq!(store.get_chain_block_by_blue_score(2).unwrap(), Some(hash(12)));
+        assert_eq!(store.get_chain_block_by_blue_score(3).unwrap(), None);
+        assert_eq!(store.get_chain_tip().unwrap(), Some((4, hash(14))));
+
+        // Removing a chain block's header drops its entry
+        store.put_header(&header(12, 2, 2)).unwrap();
+        store.delete_header(&hash(12)).unwrap();
+        assert_eq!(store.get_chain_block_by_blue_score(2).unwrap(), None);
+        assert_eq!(store.get_chain_block_by_blue_score(4).unwrap(), Some(hash(14)));
+    }
 }
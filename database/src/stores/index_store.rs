@@ -0,0 +1,241 @@
+//! Secondary indexes derived from the stored blocks and UTXO set
+//!
+//! - transaction id to the blocks containing it (`CF_TRANSACTIONS`)
+//! - block hash to its direct children (`CF_BLOCK_RELATIONS`)
+//! - script public key to the outpoints of its unspent outputs (`CF_ADDRESS_INDEX`)
+//! - DAA score and hash of every stored header and block (`CF_DAA_INDEX`, see
+//!   [`HeaderStore`](crate::stores::HeaderStore))
+//!
+//! None of them holds data of its own, so [`IndexStore::rebuild`] can recreate
+//! them from the headers, block bodies and UTXOs.
+
+use crate::db::{CF_ADDRESS_INDEX, CF_BLOCK_BODIES, CF_BLOCK_RELATIONS, CF_DAA_INDEX, CF_HEADERS, CF_TRANSACTIONS, CF_UTXOS};
+use crate::stores::{HeaderStore, UtxoStore};
+use crate::{Database, DbResult};
+use consensus_core::block::Block;
+use consensus_core::header::Header;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutpoint, UtxoEntry};
+use consensus_core::Hash;
+use parking_lot::Mutex;
+use rocksdb::{Direction, IteratorMode, WriteBatch};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// One of the secondary indexes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryIndex {
+    Transactions,
+    Children,
+    Addresses,
+    DaaScores,
+}
+
+impl SecondaryIndex {
+    pub const ALL: [SecondaryIndex; 4] =
+        [SecondaryIndex::Transactions, SecondaryIndex::Children, SecondaryIndex::Addresses, SecondaryIndex::DaaScores];
+
+    fn column_family(self) -> &'static str {
+        match self {
+            SecondaryIndex::Transactions => CF_TRANSACTIONS,
+            SecondaryIndex::Children => CF_BLOCK_RELATIONS,
+            SecondaryIndex::Addresses => CF_ADDRESS_INDEX,
+            SecondaryIndex::DaaScores => CF_DAA_INDEX,
+        }
+    }
+}
+
+impl fmt::Display for SecondaryIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecondaryIndex::Transactions => write!(f, "transaction index"),
+            SecondaryIndex::Children => write!(f, "children index"),
+            SecondaryIndex::Addresses => write!(f, "address index"),
+            SecondaryIndex::DaaScores => write!(f, "DAA score index"),
+        }
+    }
+}
+
+/// Entries written by a rebuild
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReindexStats {
+    /// Headers with a stored body
+    pub blocks: usize,
+    /// Headers whose body is pruned or was never stored
+    pub headers: usize,
+    pub transactions: usize,
+    pub unspent_outputs: usize,
+}
+
+pub struct IndexStore {
+    db: Arc<Database>,
+    /// Serializes the read-modify-write of hash list entries
+    write_lock: Mutex<()>,
+}
+
+impl IndexStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db, write_lock: Mutex::new(()) }
+    }
+
+    /// Index the transactions of `block` and its parent links
+    pub fn index_block(&self, block: &Block) -> DbResult<()> {
+        let _guard = self.write_lock.lock();
+        for tx in &block.transactions {
+            self.append(CF_TRANSACTIONS, &tx.hash(), block.header.hash)?;
+        }
+        self.index_parents(&block.header)
+    }
+
+    /// Index the parent links of a header stored without its body
+    pub fn index_header(&self, header: &Header) -> DbResult<()> {
+        let _guard = self.write_lock.lock();
+        self.index_parents(header)
+    }
+
+    fn index_parents(&self, header: &Header) -> DbResult<()> {
+        for parent in header.direct_parents() {
+            self.append(CF_BLOCK_RELATIONS, parent, header.hash)?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, cf: &str, key: &Hash, hash: Hash) -> DbResult<()> {
+        let mut hashes = self.hashes(cf, key)?;
+        if !hashes.contains(&hash) {
+            hashes.push(hash);
+            self.db.put(cf, &key.as_bytes(), &bincode::serialize(&hashes)?)?;
+        }
+        Ok(())
+    }
+
+    fn hashes(&self, cf: &str, key: &Hash) -> DbResult<Vec<Hash>> {
+        match self.db.get(cf, &key.as_bytes())? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Blocks containing the transaction `transaction_id`
+    pub fn blocks_containing(&self, transaction_id: &Hash) -> DbResult<Vec<Hash>> {
+        self.hashes(CF_TRANSACTIONS, transaction_id)
+    }
+
+    /// Direct children of the block `hash`
+    pub fn children(&self, hash: &Hash) -> DbResult<Vec<Hash>> {
+        self.hashes(CF_BLOCK_RELATIONS, hash)
+    }
+
+    pub fn add_unspent(&self, outpoint: &TransactionOutpoint, script_public_key: &ScriptPublicKey) -> DbResult<()> {
+        self.db.put(CF_ADDRESS_INDEX, &Self::address_key(script_public_key, outpoint), &[])
+    }
+
+    pub fn remove_unspent(&self, outpoint: &TransactionOutpoint, script_public_key: &ScriptPublicKey) -> DbResult<()> {
+        self.db.delete(CF_ADDRESS_INDEX, &Self::address_key(script_public_key, outpoint))
+    }
+
+    /// Outpoints of the unspent outputs locked by `script_public_key`
+    pub fn unspent_by_script(&self, script_public_key: &ScriptPublicKey) -> DbResult<Vec<TransactionOutpoint>> {
+        let prefix = Self::script_prefix(script_public_key);
+        let mut outpoints = Vec::new();
+        for item in self.db.iterator(CF_ADDRESS_INDEX, IteratorMode::From(&prefix, Direction::Forward))? {
+            let (key, _) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            outpoints.push(UtxoStore::key_to_outpoint(&key[prefix.len()..])?);
+        }
+        Ok(outpoints)
+    }
+
+    /// Delete every entry of `index`
+    pub fn clear(&self, index: SecondaryIndex) -> DbResult<()> {
+        let mut batch = self.db.batch();
+        self.clear_into(&mut batch, index)?;
+        self.db.write_batch(batch)
+    }
+
+    fn clear_into(&self, batch: &mut WriteBatch, index: SecondaryIndex) -> DbResult<()> {
+        let cf = index.column_family();
+        for item in self.db.iterator(cf, IteratorMode::Start)? {
+            let (key, _) = item?;
+            self.db.batch_delete(batch, cf, &key)?;
+        }
+        Ok(())
+    }
+
+    /// Recreate all indexes from the stored headers, block bodies and UTXOs,
+    /// replacing them in one write. `progress` is called with the headers read
+    /// so far and their total.
+    pub fn rebuild(&self, mut progress: impl FnMut(usize, usize)) -> DbResult<ReindexStats> {
+        let _guard = self.write_lock.lock();
+        let total = self.count(CF_HEADERS)?;
+        let mut stats = ReindexStats::default();
+        let mut transactions: BTreeMap<Hash, Vec<Hash>> = BTreeMap::new();
+        let mut children: BTreeMap<Hash, BTreeSet<Hash>> = BTreeMap::new();
+        let mut daa_scores: BTreeSet<(u64, Hash)> = BTreeSet::new();
+
+        for item in self.db.iterator(CF_HEADERS, IteratorMode::Start)? {
+            let (_, data) = item?;
+            let header: Header = bincode::deserialize(&data)?;
+            for parent in header.direct_parents() {
+                children.entry(*parent).or_default().insert(header.hash);
+            }
+            daa_scores.insert((header.daa_score, header.hash));
+            match self.db.get(CF_BLOCK_BODIES, &header.hash.as_bytes())? {
+                Some(body) => {
+                    for tx in bincode::deserialize::<Vec<Transaction>>(&body)? {
+                        transactions.entry(tx.hash()).or_default().push(header.hash);
+                    }
+                    stats.blocks += 1;
+                }
+                None => stats.headers += 1,
+            }
+            progress(stats.headers + stats.blocks, total);
+        }
+        stats.transactions = transactions.len();
+
+        let mut batch = self.db.batch();
+        for index in SecondaryIndex::ALL {
+            self.clear_into(&mut batch, index)?;
+        }
+        for (transaction_id, blocks) in transactions {
+            self.db.batch_put(&mut batch, CF_TRANSACTIONS, &transaction_id.as_bytes(), &bincode::serialize(&blocks)?)?;
+        }
+        for (parent, hashes) in children {
+            let hashes: Vec<Hash> = hashes.into_iter().collect();
+            self.db.batch_put(&mut batch, CF_BLOCK_RELATIONS, &parent.as_bytes(), &bincode::serialize(&hashes)?)?;
+        }
+        for (daa_score, hash) in daa_scores {
+            self.db.batch_put(&mut batch, CF_DAA_INDEX, &HeaderStore::daa_key(daa_score, &hash), &[])?;
+        }
+        for item in self.db.iterator(CF_UTXOS, IteratorMode::Start)? {
+            let (key, data) = item?;
+            let entry: UtxoEntry = bincode::deserialize(&data)?;
+            let outpoint = UtxoStore::key_to_outpoint(&key)?;
+            self.db.batch_put(&mut batch, CF_ADDRESS_INDEX, &Self::address_key(&entry.script_public_key, &outpoint), &[])?;
+            stats.unspent_outputs += 1;
+        }
+        self.db.write_batch(batch)?;
+        Ok(stats)
+    }
+
+    fn count(&self, cf: &str) -> DbResult<usize> {
+        Ok(self.db.iterator(cf, IteratorMode::Start)?.count())
+    }
+
+    /// Version, script length and script, so no script's prefix is a prefix of another's
+    fn script_prefix(script_public_key: &ScriptPublicKey) -> Vec<u8> {
+        let script = script_public_key.script();
+        let mut prefix = script_public_key.version().to_le_bytes().to_vec();
+        prefix.extend_from_slice(&(script.len() as u32).to_le_bytes());
+        prefix.extend_from_slice(script);
+        prefix
+    }
+
+    fn address_key(script_public_key: &ScriptPublicKey, outpoint: &TransactionOutpoint) -> Vec<u8> {
+        let mut key = Self::script_prefix(script_public_key);
+        key.extend_from_slice(&UtxoStore::outpoint_to_key(outpoint));
+        key
+    }
+}
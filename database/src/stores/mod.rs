@@ -4,6 +4,9 @@ pub mod utxo_store;
 pub mod ghostdag_store;
 pub mod reachability_store;
 pub mod metadata_store;
+pub mod index_store;
+pub mod provenance_store;
+pub mod fee_store;
 
 pub use block_store::BlockStore;
 pub use header_store::HeaderStore;
@@ -11,3 +14,6 @@ pub use utxo_store::UtxoStore;
 pub use ghostdag_store::GhostdagStore;
 pub use reachability_store::ReachabilityStore;
 pub use metadata_store::MetadataStore;
+pub use index_store::{IndexStore, ReindexStats, SecondaryIndex};
+pub use provenance_store::ProvenanceStore;
+pub use fee_store::FeeStore;
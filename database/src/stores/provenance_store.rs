@@ -0,0 +1,49 @@
+//! First-seen source of each block, see `consensus_core::provenance`
+
+use crate::db::CF_BLOCK_PROVENANCE;
+use crate::{Database, DbResult};
+use consensus_core::provenance::BlockProvenance;
+use consensus_core::Hash;
+use std::sync::Arc;
+
+pub struct ProvenanceStore {
+    db: Arc<Database>,
+}
+
+impl ProvenanceStore {
+    pub fn new(db: Arc<Database>) -> Self { Self { db } }
+
+    pub fn put(&self, hash: &Hash, provenance: &BlockProvenance) -> DbResult<()> {
+        self.db.put(CF_BLOCK_PROVENANCE, &hash.as_bytes(), &bincode::serialize(provenance)?)
+    }
+
+    pub fn get(&self, hash: &Hash) -> DbResult<Option<BlockProvenance>> {
+        match self.db.get(CF_BLOCK_PROVENANCE, &hash.as_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::provenance::BlockSource;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_provenance_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let store = ProvenanceStore::new(Arc::new(Database::open(tmp.path()).unwrap()));
+        let hash = Hash::from_u64_word(7);
+        assert_eq!(store.get(&hash).unwrap(), None);
+
+        let mut provenance = BlockProvenance::new(BlockSource::Peer("10.0.0.1:16111".parse().unwrap()), 1_000);
+        store.put(&hash, &provenance).unwrap();
+        assert_eq!(store.get(&hash).unwrap(), Some(provenance));
+
+        provenance.invalid = true;
+        store.put(&hash, &provenance).unwrap();
+        assert!(store.get(&hash).unwrap().unwrap().invalid);
+    }
+}
@@ -1,8 +1,16 @@
 use crate::{Database, DbResult};
 use crate::cache::WriteThroughCache;
+use crate::db::{CF_METADATA, CF_UTXOS};
+use consensus_core::muhash::{MuHash, MUHASH_SIZE};
 use consensus_core::tx::{TransactionOutpoint, UtxoEntry};
 use std::sync::Arc;
 
+/// Metadata key of the commitment to the stored UTXO set
+const UTXO_COMMITMENT_KEY: &[u8] = b"utxo_commitment";
+
+/// Size of the commitments stored before the UTXO set was committed to with MuHash
+const LEGACY_COMMITMENT_SIZE: usize = 32;
+
 pub struct UtxoStore {
     db: Arc<Database>,
     cache: WriteThroughCache<TransactionOutpoint, UtxoEntry>,
@@ -13,10 +21,14 @@ impl UtxoStore {
         Self { db, cache: WriteThroughCache::new(cache_size) }
     }
 
-    pub fn put_utxo(&self, outpoint: &TransactionOutpoint, entry: &UtxoEntry) -> DbResult<()> {
+    /// Store a UTXO together with the commitment of the set that includes it
+    pub fn put_utxo(&self, outpoint: &TransactionOutpoint, entry: &UtxoEntry, commitment: &MuHash) -> DbResult<()> {
         let key = Self::outpoint_to_key(outpoint);
         let serialized = bincode::serialize(entry)?;
-        self.db.put(crate::db::CF_UTXOS, &key, &serialized)?;
+        let mut batch = self.db.batch();
+        self.db.batch_put(&mut batch, CF_UTXOS, &key, &serialized)?;
+        self.db.batch_put(&mut batch, CF_METADATA, UTXO_COMMITMENT_KEY, &commitment.to_bytes())?;
+        self.db.write_batch(batch)?;
         self.cache.insert(outpoint.clone(), entry.clone());
         Ok(())
     }
@@ -31,13 +43,37 @@ impl UtxoStore {
         } else { Ok(None) }
     }
 
-    pub fn delete_utxo(&self, outpoint: &TransactionOutpoint) -> DbResult<()> {
+    /// Delete a UTXO together with storing the commitment of the set without it
+    pub fn delete_utxo(&self, outpoint: &TransactionOutpoint, commitment: &MuHash) -> DbResult<()> {
         let key = Self::outpoint_to_key(outpoint);
-        self.db.delete(crate::db::CF_UTXOS, &key)?;
+        let mut batch = self.db.batch();
+        self.db.batch_delete(&mut batch, CF_UTXOS, &key)?;
+        self.db.batch_put(&mut batch, CF_METADATA, UTXO_COMMITMENT_KEY, &commitment.to_bytes())?;
+        self.db.write_batch(batch)?;
         self.cache.remove(outpoint);
         Ok(())
     }
 
+    /// Commitment stored with the last UTXO change, if any. One in the 32-byte
+    /// format of the XOR accumulator used before MuHash counts as none.
+    pub fn get_commitment(&self) -> DbResult<Option<MuHash>> {
+        match self.db.get(CF_METADATA, UTXO_COMMITMENT_KEY)? {
+            Some(data) if data.len() == LEGACY_COMMITMENT_SIZE => Ok(None),
+            Some(data) => {
+                let bytes: [u8; MUHASH_SIZE] = data
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| crate::DbError::InvalidData(format!("UTXO commitment of {} bytes", data.len())))?;
+                Ok(Some(MuHash::from_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_commitment(&self, commitment: &MuHash) -> DbResult<()> {
+        self.db.put(CF_METADATA, UTXO_COMMITMENT_KEY, &commitment.to_bytes())
+    }
+
     pub fn has_utxo(&self, outpoint: &TransactionOutpoint) -> DbResult<bool> {
         if self.cache.get(outpoint).is_some() { return Ok(true); }
         let key = Self::outpoint_to_key(outpoint);
@@ -53,19 +89,48 @@ impl UtxoStore {
 
     /// Sum amounts of all UTXO entries in the DB (returns total as u128)
     pub fn sum_amounts(&self) -> DbResult<u128> {
+        self.sum_amounts_matching(|_| true)
+    }
+
+    /// Sum amounts of the UTXO entries accepted by `filter`
+    pub fn sum_amounts_matching(&self, filter: impl Fn(&UtxoEntry) -> bool) -> DbResult<u128> {
         let mut total: u128 = 0;
         let iter = self.db.iterator(crate::db::CF_UTXOS, rocksdb::IteratorMode::Start)?;
         for item in iter {
             let (_k, value) = item?;
             let entry: UtxoEntry = bincode::deserialize(&value)?;
-            total = total.saturating_add(entry.amount as u128);
+            if filter(&entry) {
+                total = total.saturating_add(entry.amount as u128);
+            }
         }
         Ok(total)
     }
 
-    fn outpoint_to_key(outpoint: &TransactionOutpoint) -> Vec<u8> {
+    /// UTXO entries accepted by `filter`, with their outpoints
+    pub fn entries_matching(&self, filter: impl Fn(&UtxoEntry) -> bool) -> DbResult<Vec<(TransactionOutpoint, UtxoEntry)>> {
+        let mut entries = Vec::new();
+        let iter = self.db.iterator(crate::db::CF_UTXOS, rocksdb::IteratorMode::Start)?;
+        for item in iter {
+            let (key, value) = item?;
+            let entry: UtxoEntry = bincode::deserialize(&value)?;
+            if filter(&entry) {
+                entries.push((Self::key_to_outpoint(&key)?, entry));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub(crate) fn outpoint_to_key(outpoint: &TransactionOutpoint) -> Vec<u8> {
         let mut key = outpoint.transaction_id.as_bytes().to_vec();
         key.extend_from_slice(&outpoint.index.to_le_bytes());
         key
     }
+
+    pub(crate) fn key_to_outpoint(key: &[u8]) -> DbResult<TransactionOutpoint> {
+        if key.len() != 36 {
+            return Err(crate::DbError::InvalidData(format!("UTXO key of {} bytes", key.len())));
+        }
+        let index = u32::from_le_bytes(key[32..].try_into().unwrap());
+        Ok(TransactionOutpoint::new(consensus_core::Hash::from_slice(&key[..32]), index))
+    }
 }
@@ -0,0 +1,14 @@
+-- Transaction payloads: arbitrary bytes applications embed in transactions
+
+CREATE TABLE IF NOT EXISTS transaction_payloads (
+    tx_hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    payload BLOB NOT NULL,
+    -- The payload as text, when it is valid UTF-8
+    utf8 TEXT,
+    hex_preview TEXT NOT NULL,
+    utf8_preview TEXT
+);
+
+-- Prefix searches scan a range of this index
+CREATE INDEX IF NOT EXISTS idx_tx_payloads_payload ON transaction_payloads(payload);
@@ -0,0 +1,61 @@
+-- Double-entry address ledger: one credit per output and one debit per spent
+-- output of every transaction accepted by a selected chain block. Address
+-- balances are materialized from it by triggers, so rolling a reorged chain
+-- block back is deleting its rows.
+
+CREATE TABLE IF NOT EXISTS address_ledger (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Chain block accepting the transaction
+    accepting_block_hash TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,
+    address TEXT NOT NULL,
+    -- The output credited, or spent by the debit
+    outpoint_hash TEXT NOT NULL,
+    outpoint_index INTEGER NOT NULL,
+    -- Positive for credits, negative for debits
+    amount INTEGER NOT NULL,
+    is_debit BOOLEAN NOT NULL,
+    UNIQUE(outpoint_hash, outpoint_index, is_debit)
+);
+
+CREATE INDEX IF NOT EXISTS idx_address_ledger_accepting_block ON address_ledger(accepting_block_hash);
+CREATE INDEX IF NOT EXISTS idx_address_ledger_address ON address_ledger(address);
+
+-- Indexer progress, such as the chain block the ledger is up to
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS address_ledger_insert AFTER INSERT ON address_ledger
+BEGIN
+    INSERT OR IGNORE INTO addresses (address) VALUES (NEW.address);
+    UPDATE addresses SET
+        balance = balance + NEW.amount,
+        total_received = total_received + CASE WHEN NEW.is_debit THEN 0 ELSE NEW.amount END,
+        total_sent = total_sent + CASE WHEN NEW.is_debit THEN -NEW.amount ELSE 0 END,
+        received_count = received_count + CASE WHEN NEW.is_debit THEN 0 ELSE 1 END,
+        sent_count = sent_count + CASE WHEN NEW.is_debit THEN 1 ELSE 0 END,
+        utxo_count = utxo_count + CASE WHEN NEW.is_debit THEN -1 ELSE 1 END,
+        updated_at = CURRENT_TIMESTAMP
+    WHERE address = NEW.address;
+END;
+
+CREATE TRIGGER IF NOT EXISTS address_ledger_delete AFTER DELETE ON address_ledger
+BEGIN
+    UPDATE addresses SET
+        balance = balance - OLD.amount,
+        total_received = total_received - CASE WHEN OLD.is_debit THEN 0 ELSE OLD.amount END,
+        total_sent = total_sent - CASE WHEN OLD.is_debit THEN -OLD.amount ELSE 0 END,
+        received_count = received_count - CASE WHEN OLD.is_debit THEN 0 ELSE 1 END,
+        sent_count = sent_count - CASE WHEN OLD.is_debit THEN 1 ELSE 0 END,
+        utxo_count = utxo_count - CASE WHEN OLD.is_debit THEN -1 ELSE 1 END,
+        updated_at = CURRENT_TIMESTAMP
+    WHERE address = OLD.address;
+END;
+
+-- Balances tracked before the ledger existed are rebuilt from it
+UPDATE addresses SET
+    balance = 0, total_received = 0, total_sent = 0, received_count = 0, sent_count = 0, utxo_count = 0
+WHERE NOT EXISTS (SELECT 1 FROM indexer_state WHERE key = 'ledger_version');
+INSERT OR IGNORE INTO indexer_state (key, value) VALUES ('ledger_version', '1');
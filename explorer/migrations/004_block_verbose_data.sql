@@ -0,0 +1,23 @@
+-- GHOSTDAG data of each block as the node reports it. Children are not kept:
+-- they are the blocks naming the block in block_parents.
+
+ALTER TABLE blocks ADD COLUMN selected_parent TEXT;
+-- Kept up to date from the virtual chain changes
+ALTER TABLE blocks ADD COLUMN is_chain_block BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE blocks ADD COLUMN difficulty REAL;
+-- The node has only the header, the transactions pruned or never downloaded
+ALTER TABLE blocks ADD COLUMN is_header_only BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_blocks_selected_parent ON blocks(selected_parent);
+
+-- Blocks merged by each block, colored blue or red
+CREATE TABLE IF NOT EXISTS block_mergesets (
+    block_hash TEXT NOT NULL,
+    merged_hash TEXT NOT NULL,
+    is_blue BOOLEAN NOT NULL,
+    -- Coloring order among the blues, selected parent first
+    position INTEGER NOT NULL,
+    PRIMARY KEY (block_hash, merged_hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_block_mergesets_merged ON block_mergesets(merged_hash);
@@ -0,0 +1,12 @@
+//! Extractors whose rejections are reported in the API's error envelope
+
+use axum::extract::FromRequestParts;
+use crate::error::ExplorerError;
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ExplorerError))]
+pub struct Path<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ExplorerError))]
+pub struct Query<T>(pub T);
@@ -2,6 +2,8 @@
 
 pub mod server;
 pub mod routes;
+pub mod spec;
+pub mod extract;
 
 pub use server::ApiServer;
 
@@ -1,13 +1,13 @@
 //! Address-related routes
 
 use axum::{
-    Router,
-    routing::get,
-    extract::{Path, Query, State},
+    extract::State,
     Json,
 };
 use serde::Deserialize;
 use std::sync::Arc;
+use crate::api::extract::{Path, Query};
+use crate::api::spec::{Param, Routes};
 use crate::database::Database;
 use crate::database::queries::AddressQueries;
 use crate::models::PaginatedResponse;
@@ -19,10 +19,10 @@ struct PaginationParams {
     page_size: Option<i32>,
 }
 
-pub fn routes(database: Arc<Database>) -> Router {
-    Router::new()
-        .route("/addresses/:address", get(get_address))
-        .route("/addresses/:address/transactions", get(get_address_transactions))
+pub fn routes(database: Arc<Database>) -> Routes {
+    Routes::new()
+        .get("/addresses/:address", get_address, &[])
+        .get("/addresses/:address/transactions", get_address_transactions, &[Param::query("page"), Param::query("page_size")])
         .with_state(database)
 }
 
@@ -0,0 +1,50 @@
+//! Operator routes
+
+use axum::{
+    extract::State,
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::api::extract::Query;
+use crate::api::spec::{Param, Routes};
+use crate::database::Database;
+use crate::database::queries::LedgerQueries;
+use crate::models::{LedgerCheck, LEDGER_CHECK_LIMIT};
+use crate::error::{ExplorerError, Result};
+
+/// Either one `address` or a random `sample` of addresses is checked
+#[derive(Deserialize)]
+struct LedgerCheckParams {
+    address: Option<String>,
+    sample: Option<i64>,
+}
+
+pub fn routes(database: Arc<Database>) -> Routes {
+    Routes::new()
+        .get("/admin/ledger/check", check_ledger, &[Param::query("address"), Param::query("sample")])
+        .with_state(database)
+}
+
+/// Recompute balances from the address ledger and report those the
+/// materialized balances disagree with
+#[axum::debug_handler]
+async fn check_ledger(
+    State(db): State<Arc<Database>>,
+    Query(params): Query<LedgerCheckParams>,
+) -> Result<Json<LedgerCheck>> {
+    let limit = params.sample.unwrap_or(100).min(LEDGER_CHECK_LIMIT).max(1);
+    let pool = Arc::new(db.pool().clone());
+
+    let checks = LedgerQueries::check_balances(pool, params.address.as_deref(), limit).await?;
+    if checks.is_empty() {
+        if let Some(address) = params.address {
+            return Err(ExplorerError::NotFound(format!("Address {} not found", address)));
+        }
+    }
+
+    Ok(Json(LedgerCheck {
+        checked: checks.len(),
+        mismatches: checks.into_iter().filter(|check| check.balance != check.ledger_balance).collect(),
+    }))
+}
@@ -1,17 +1,18 @@
 //! Block-related routes
 
 use axum::{
-    Router,
-    routing::get,
-    extract::{Path, Query, State},
+    extract::State,
     Json,
 };
 use serde::Deserialize;
 use std::sync::Arc;
+use crate::api::extract::{Path, Query};
+use crate::api::spec::{Param, Routes};
 use crate::database::Database;
 use crate::database::queries::BlockQueries;
 use crate::models::PaginatedResponse;
-use crate::error::Result;
+use crate::lookup::{Indexed, Lookup};
+use crate::error::{ExplorerError, Result};
 
 #[derive(Deserialize)]
 struct PaginationParams {
@@ -19,25 +20,35 @@ struct PaginationParams {
     page_size: Option<i32>,
 }
 
-pub fn routes(database: Arc<Database>) -> Router {
-    Router::new()
-        .route("/blocks", get(list_blocks))
-        .route("/blocks/:hash", get(get_block_by_hash))
-        .route("/blocks/height/:height", get(get_block_by_height))
-        .route("/blocks/recent", get(get_recent_blocks))
-        .with_state(database)
+#[derive(Clone)]
+pub struct BlocksState {
+    pub database: Arc<Database>,
+    pub lookup: Arc<Lookup>,
+}
+
+const PAGINATION: &[Param] = &[Param::query("page"), Param::query("page_size")];
+
+pub fn routes(database: Arc<Database>, lookup: Arc<Lookup>) -> Routes {
+    let state = BlocksState { database, lookup };
+    Routes::new()
+        .get("/blocks", list_blocks, PAGINATION)
+        .get("/blocks/:hash", get_block_by_hash, &[])
+        .get("/blocks/:hash/dag", get_block_dag, &[])
+        .get("/blocks/height/:height", get_block_by_height, &[])
+        .get("/blocks/recent", get_recent_blocks, &[Param::query("page_size")])
+        .with_state(state)
 }
 
 #[axum::debug_handler]
 async fn list_blocks(
-    State(db): State<Arc<Database>>,
+    State(state): State<BlocksState>,
     Query(params): Query<PaginationParams>,
 ) -> Result<Json<PaginatedResponse<crate::models::BlockSummary>>> {
     let page = params.page.unwrap_or(1).max(1);
     let page_size = params.page_size.unwrap_or(20).min(100).max(1);
     let offset = (page - 1) * page_size;
 
-    let pool = Arc::new(db.pool().clone());
+    let pool = Arc::new(state.database.pool().clone());
     let blocks = BlockQueries::list_recent(pool.clone(), page_size as i64, offset as i64).await?;
     let total = BlockQueries::count(pool).await?;
     let total_pages = (total as f64 / page_size as f64).ceil() as i32;
@@ -51,33 +62,46 @@ async fn list_blocks(
     }))
 }
 
+/// Indexed blocks, else the node's copy marked `"indexed": false`
 #[axum::debug_handler]
 async fn get_block_by_hash(
-    State(db): State<Arc<Database>>,
+    State(state): State<BlocksState>,
     Path(hash): Path<String>,
-) -> Result<Json<Option<crate::models::BlockSummary>>> {
-    let pool = Arc::new(db.pool().clone());
-    let block = BlockQueries::get_by_hash(pool, &hash).await?;
+) -> Result<Json<Indexed<crate::models::BlockSummary>>> {
+    let block = state.lookup.block(&hash).await?
+        .ok_or_else(|| ExplorerError::NotFound(format!("Block {}", hash)))?;
     Ok(Json(block))
 }
 
+/// Parents, children and mergeset of an indexed block
+#[axum::debug_handler]
+async fn get_block_dag(
+    State(state): State<BlocksState>,
+    Path(hash): Path<String>,
+) -> Result<Json<crate::models::BlockDag>> {
+    let pool = Arc::new(state.database.pool().clone());
+    let dag = BlockQueries::get_dag(pool, &hash).await?
+        .ok_or_else(|| ExplorerError::NotFound(format!("Block {}", hash)))?;
+    Ok(Json(dag))
+}
+
 #[axum::debug_handler]
 async fn get_block_by_height(
-    State(db): State<Arc<Database>>,
+    State(state): State<BlocksState>,
     Path(height): Path<i64>,
 ) -> Result<Json<Option<crate::models::BlockSummary>>> {
-    let pool = Arc::new(db.pool().clone());
+    let pool = Arc::new(state.database.pool().clone());
     let block = BlockQueries::get_by_height(pool, height).await?;
     Ok(Json(block))
 }
 
 #[axum::debug_handler]
 async fn get_recent_blocks(
-    State(db): State<Arc<Database>>,
+    State(state): State<BlocksState>,
     Query(params): Query<PaginationParams>,
 ) -> Result<Json<PaginatedResponse<crate::models::BlockSummary>>> {
     let page_size = params.page_size.unwrap_or(10).min(50);
-    let pool = Arc::new(db.pool().clone());
+    let pool = Arc::new(state.database.pool().clone());
     let blocks = BlockQueries::list_recent(pool, page_size as i64, 0).await?;
     let total = blocks.len() as i64;
 
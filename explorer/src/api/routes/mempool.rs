@@ -0,0 +1,76 @@
+//! Mempool routes
+
+use axum::{
+    extract::State,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::api::extract::{Path, Query};
+use crate::api::spec::{Param, Routes};
+use crate::database::Database;
+use crate::database::queries::TransactionQueries;
+use crate::mempool::{MempoolCache, MempoolSummary, PendingTransaction};
+use crate::models::{PaginatedResponse, TransactionSummary};
+use crate::error::{ExplorerError, Result};
+
+#[derive(Clone)]
+pub struct MempoolState {
+    pub database: Arc<Database>,
+    pub mempool: Arc<MempoolCache>,
+}
+
+#[derive(Deserialize)]
+struct PaginationParams {
+    page: Option<i32>,
+    page_size: Option<i32>,
+}
+
+/// A transaction looked up by id: still pending, or already indexed
+#[derive(Serialize)]
+#[serde(tag = "status", content = "transaction", rename_all = "snake_case")]
+pub enum TransactionLookup {
+    Pending(PendingTransaction),
+    Confirmed(TransactionSummary),
+}
+
+pub fn routes(database: Arc<Database>, mempool: Arc<MempoolCache>) -> Routes {
+    Routes::new()
+        .get("/mempool", get_mempool, &[])
+        .get("/mempool/transactions", list_mempool_transactions, &[Param::query("page"), Param::query("page_size")])
+        .get("/mempool/transactions/:id", get_mempool_transaction, &[])
+        .with_state(MempoolState { database, mempool })
+}
+
+#[axum::debug_handler]
+async fn get_mempool(
+    State(state): State<MempoolState>,
+) -> Result<Json<MempoolSummary>> {
+    Ok(Json(state.mempool.summary().await?))
+}
+
+#[axum::debug_handler]
+async fn list_mempool_transactions(
+    State(state): State<MempoolState>,
+    Query(params): Query<PaginationParams>,
+) -> Result<Json<PaginatedResponse<PendingTransaction>>> {
+    let page = params.page.unwrap_or(1).max(1);
+    let page_size = params.page_size.unwrap_or(20).min(100).max(1);
+    Ok(Json(state.mempool.page(page, page_size).await?))
+}
+
+#[axum::debug_handler]
+async fn get_mempool_transaction(
+    State(state): State<MempoolState>,
+    Path(id): Path<String>,
+) -> Result<Json<TransactionLookup>> {
+    if let Some(tx) = state.mempool.get(&id).await? {
+        return Ok(Json(TransactionLookup::Pending(tx)));
+    }
+    // Not pending (any more): serve the confirmed record if it has been indexed
+    let pool = Arc::new(state.database.pool().clone());
+    match TransactionQueries::get_by_hash(pool, &id).await? {
+        Some(tx) => Ok(Json(TransactionLookup::Confirmed(tx))),
+        None => Err(ExplorerError::NotFound(format!("Transaction {} not found", id))),
+    }
+}
@@ -5,4 +5,8 @@ pub mod transactions;
 pub mod addresses;
 pub mod stats;
 pub mod search;
+pub mod network;
+pub mod mempool;
+pub mod payloads;
 
+pub mod admin;
@@ -0,0 +1,23 @@
+//! Network routes
+
+use axum::{
+    extract::State,
+    Json,
+};
+use std::sync::Arc;
+use rpc_core::{PeerInfo, RpcApi};
+use crate::api::spec::Routes;
+use crate::error::Result;
+
+pub fn routes(rpc_client: Arc<dyn RpcApi>) -> Routes {
+    Routes::new()
+        .get("/network/peers", get_peers, &[])
+        .with_state(rpc_client)
+}
+
+#[axum::debug_handler]
+async fn get_peers(
+    State(rpc_client): State<Arc<dyn RpcApi>>,
+) -> Result<Json<Vec<PeerInfo>>> {
+    Ok(Json(rpc_client.get_peer_info().await?))
+}
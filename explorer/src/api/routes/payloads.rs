@@ -0,0 +1,69 @@
+//! Transaction payload routes
+
+use axum::{
+    extract::State,
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::api::extract::{Path, Query};
+use crate::api::spec::{Param, Routes};
+use crate::database::Database;
+use crate::database::queries::PayloadQueries;
+use crate::models::{Payload, PayloadSummary, PAYLOAD_SEARCH_LIMIT};
+use crate::error::{ExplorerError, Result};
+
+/// Exactly one of `prefix` (hex) and `utf8` selects the search mode
+#[derive(Deserialize)]
+struct PayloadSearchParams {
+    prefix: Option<String>,
+    utf8: Option<String>,
+    limit: Option<i64>,
+}
+
+pub fn routes(database: Arc<Database>) -> Routes {
+    Routes::new()
+        .get("/payloads/search", search_payloads, &[Param::query("prefix"), Param::query("utf8"), Param::query("limit")])
+        .get("/transactions/:hash/payload", get_payload, &[])
+        .with_state(database)
+}
+
+#[axum::debug_handler]
+async fn search_payloads(
+    State(db): State<Arc<Database>>,
+    Query(params): Query<PayloadSearchParams>,
+) -> Result<Json<Vec<PayloadSummary>>> {
+    let limit = params.limit.unwrap_or(20).min(PAYLOAD_SEARCH_LIMIT).max(1);
+    let pool = Arc::new(db.pool().clone());
+
+    let payloads = match (params.prefix.as_deref(), params.utf8.as_deref()) {
+        (Some(prefix), None) => {
+            let prefix = hex::decode(prefix)
+                .map_err(|e| ExplorerError::InvalidInput(format!("Invalid hex prefix: {}", e)))?;
+            if prefix.is_empty() {
+                return Err(ExplorerError::InvalidInput("Empty prefix".to_string()));
+            }
+            PayloadQueries::search_prefix(pool, &prefix, limit).await?
+        }
+        (None, Some(text)) => {
+            if text.is_empty() {
+                return Err(ExplorerError::InvalidInput("Empty text".to_string()));
+            }
+            PayloadQueries::search_utf8(pool, text, limit).await?
+        }
+        _ => return Err(ExplorerError::InvalidInput("Pass either prefix or utf8".to_string())),
+    };
+
+    Ok(Json(payloads))
+}
+
+#[axum::debug_handler]
+async fn get_payload(
+    State(db): State<Arc<Database>>,
+    Path(hash): Path<String>,
+) -> Result<Json<Payload>> {
+    let pool = Arc::new(db.pool().clone());
+    PayloadQueries::get(pool, &hash).await?
+        .map(Json)
+        .ok_or_else(|| ExplorerError::NotFound(format!("Transaction {} has no payload", hash)))
+}
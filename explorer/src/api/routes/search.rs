@@ -1,13 +1,13 @@
 //! Search routes
 
 use axum::{
-    Router,
-    routing::get,
-    extract::{Query, State},
+    extract::State,
     Json,
 };
 use serde::Deserialize;
 use std::sync::Arc;
+use crate::api::extract::Query;
+use crate::api::spec::{Param, Routes};
 use crate::database::Database;
 use crate::database::queries::{BlockQueries, TransactionQueries, AddressQueries};
 use crate::error::Result;
@@ -17,9 +17,9 @@ struct SearchParams {
     q: String,
 }
 
-pub fn routes(database: Arc<Database>) -> Router {
-    Router::new()
-        .route("/search", get(search))
+pub fn routes(database: Arc<Database>) -> Routes {
+    Routes::new()
+        .get("/search", search, &[Param::required("q")])
         .with_state(database)
 }
 
@@ -1,12 +1,11 @@
 //! Statistics routes
 
 use axum::{
-    Router,
-    routing::get,
     extract::State,
     Json,
 };
 use std::sync::Arc;
+use crate::api::spec::Routes;
 use crate::database::Database;
 
 use crate::error::Result;
@@ -18,12 +17,12 @@ pub struct StatsState {
     pub rpc_client: Arc<dyn RpcApi>,
 }
 
-pub fn routes(database: Arc<Database>, rpc_client: Arc<dyn RpcApi>) -> Router {
+pub fn routes(database: Arc<Database>, rpc_client: Arc<dyn RpcApi>) -> Routes {
     let state = StatsState { database, rpc_client };
-    Router::new()
-        .route("/stats/network", get(get_network_stats))
-        .route("/stats/mining", get(get_mining_stats))
-        .route("/stats/blockdag", get(get_blockdag_stats))
+    Routes::new()
+        .get("/stats/network", get_network_stats, &[])
+        .get("/stats/mining", get_mining_stats, &[])
+        .get("/stats/blockdag", get_blockdag_stats, &[])
         .with_state(state)
 }
 
@@ -58,6 +57,14 @@ async fn get_network_stats(
     .fetch_one(&*pool)
     .await?;
 
+    // As the node reported it for the highest indexed chain block
+    let difficulty = sqlx::query_scalar::<_, Option<f64>>(
+        "SELECT difficulty FROM blocks WHERE is_chain_block ORDER BY blue_score DESC LIMIT 1"
+    )
+    .fetch_optional(&*pool)
+    .await?
+    .flatten();
+
     let mempool_size = sqlx::query_scalar::<_, i64>(
         "SELECT COUNT(*) FROM mempool_transactions"
     )
@@ -76,7 +83,7 @@ async fn get_network_stats(
         address_count,
         total_supply,
         hashrate: None, // TODO: Calculate from recent blocks
-        difficulty: None, // TODO: Get from latest block
+        difficulty,
         avg_block_time: None, // TODO: Calculate from block timestamps
         mempool_size: mempool_size as i32,
         mempool_bytes,
@@ -1,17 +1,18 @@
 //! Transaction-related routes
 
 use axum::{
-    Router,
-    routing::get,
-    extract::{Path, Query, State},
+    extract::State,
     Json,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use crate::api::extract::{Path, Query};
+use crate::api::spec::{Param, Routes};
 use crate::database::Database;
 use crate::database::queries::TransactionQueries;
 use crate::models::PaginatedResponse;
-use crate::error::Result;
+use crate::lookup::{Indexed, Lookup};
+use crate::error::{ExplorerError, Result};
 
 #[derive(Deserialize)]
 struct PaginationParams {
@@ -19,24 +20,39 @@ struct PaginationParams {
     page_size: Option<i32>,
 }
 
-pub fn routes(database: Arc<Database>) -> Router {
-    Router::new()
-        .route("/transactions", get(list_transactions))
-        .route("/transactions/:hash", get(get_transaction_by_hash))
-        .route("/transactions/pending", get(get_pending_transactions))
-        .with_state(database)
+/// Canonical encoding of a transaction
+#[derive(Serialize)]
+struct RawTransaction {
+    hash: String,
+    hex: String,
+}
+
+#[derive(Clone)]
+pub struct TransactionsState {
+    pub database: Arc<Database>,
+    pub lookup: Arc<Lookup>,
+}
+
+pub fn routes(database: Arc<Database>, lookup: Arc<Lookup>) -> Routes {
+    let state = TransactionsState { database, lookup };
+    Routes::new()
+        .get("/transactions", list_transactions, &[Param::query("page"), Param::query("page_size")])
+        .get("/transactions/:hash", get_transaction_by_hash, &[])
+        .get("/transactions/:hash/raw", get_raw_transaction, &[])
+        .get("/transactions/pending", get_pending_transactions, &[])
+        .with_state(state)
 }
 
 #[axum::debug_handler]
 async fn list_transactions(
-    State(db): State<Arc<Database>>,
+    State(state): State<TransactionsState>,
     Query(params): Query<PaginationParams>,
 ) -> Result<Json<PaginatedResponse<crate::models::TransactionSummary>>> {
     let page = params.page.unwrap_or(1).max(1);
     let page_size = params.page_size.unwrap_or(20).min(100).max(1);
     let offset = (page - 1) * page_size;
 
-    let pool = Arc::new(db.pool().clone());
+    let pool = Arc::new(state.database.pool().clone());
     let txs = TransactionQueries::list_recent(pool.clone(), page_size as i64, offset as i64).await?;
     let total = TransactionQueries::count(pool).await?;
 
@@ -49,21 +65,33 @@ async fn list_transactions(
     }))
 }
 
+/// Indexed transactions, else the node's view marked `"indexed": false`
 #[axum::debug_handler]
 async fn get_transaction_by_hash(
-    State(db): State<Arc<Database>>,
+    State(state): State<TransactionsState>,
     Path(hash): Path<String>,
-) -> Result<Json<Option<crate::models::TransactionSummary>>> {
-    let pool = Arc::new(db.pool().clone());
-    let tx = TransactionQueries::get_by_hash(pool, &hash).await?;
+) -> Result<Json<Indexed<crate::models::TransactionSummary>>> {
+    let tx = state.lookup.transaction(&hash).await?
+        .ok_or_else(|| ExplorerError::NotFound(format!("Transaction {}", hash)))?;
     Ok(Json(tx))
 }
 
+/// Hex of the transaction's canonical encoding, as the node has it
+#[axum::debug_handler]
+async fn get_raw_transaction(
+    State(state): State<TransactionsState>,
+    Path(hash): Path<String>,
+) -> Result<Json<RawTransaction>> {
+    let hex = state.lookup.raw_transaction(&hash).await?
+        .ok_or_else(|| ExplorerError::NotFound(format!("Transaction {}", hash)))?;
+    Ok(Json(RawTransaction { hash, hex }))
+}
+
 #[axum::debug_handler]
 async fn get_pending_transactions(
-    State(db): State<Arc<Database>>,
+    State(state): State<TransactionsState>,
 ) -> Result<Json<Vec<crate::models::TransactionSummary>>> {
-    let pool = Arc::new(db.pool().clone());
+    let pool = Arc::new(state.database.pool().clone());
     let txs = TransactionQueries::list_pending(pool).await?;
     Ok(Json(txs))
 }
@@ -2,24 +2,58 @@
 
 use axum::{
     Router,
-    http::Method,
+    extract::{rejection::WebSocketUpgradeRejection, Request, State, WebSocketUpgrade},
+    http::{HeaderName, HeaderValue, Method, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::any,
 };
 use tower_http::cors::{CorsLayer, Any};
 use std::sync::Arc;
+use std::time::Duration;
 use crate::database::Database;
+use crate::indexer::PriorityQueue;
+use crate::lookup::Lookup;
+use crate::mempool::{MempoolCache, DEFAULT_MEMPOOL_TTL};
+use crate::websocket::subscriptions::SubscriptionManager;
+use crate::websocket::server::WSServer;
 use rpc_core::RpcApi;
 use crate::api::routes;
-use crate::error::Result;
+use crate::api::spec::{self, Routes, API_PREFIX, API_VERSION};
+use crate::error::{ExplorerError, Result};
+
+/// How often the mempool is polled for `new_transaction` events
+const MEMPOOL_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Sent on every response; a request carrying another version is refused
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("x-api-version");
 
 pub struct ApiServer {
     database: Arc<Database>,
     rpc_client: Arc<dyn RpcApi>,
+    subscriptions: Arc<SubscriptionManager>,
+    mempool: Arc<MempoolCache>,
+    lookup: Arc<Lookup>,
     port: u16,
 }
 
 impl ApiServer {
     pub fn new(database: Arc<Database>, rpc_client: Arc<dyn RpcApi>, port: u16) -> Self {
-        Self { database, rpc_client, port }
+        let subscriptions = Arc::new(SubscriptionManager::new());
+        let mempool = Arc::new(MempoolCache::new(rpc_client.clone(), DEFAULT_MEMPOOL_TTL, subscriptions.clone()));
+        let lookup = Arc::new(Lookup::new(database.clone(), rpc_client.clone(), Arc::new(PriorityQueue::new())));
+        Self { database, rpc_client, subscriptions, mempool, lookup, port }
+    }
+
+    /// Queue blocks served from the node for the indexer to pick up first
+    pub fn with_priority_queue(self, priority: Arc<PriorityQueue>) -> Self {
+        let lookup = Arc::new(Lookup::new(self.database.clone(), self.rpc_client.clone(), priority));
+        Self { lookup, ..self }
+    }
+
+    /// Source of the events pushed to WebSocket clients
+    pub fn subscriptions(&self) -> Arc<SubscriptionManager> {
+        self.subscriptions.clone()
     }
 
     pub fn router(&self) -> Router {
@@ -28,14 +62,28 @@ impl ApiServer {
             .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
             .allow_headers(Any);
 
+        let api = Routes::new()
+            .merge(routes::blocks::routes(self.database.clone(), self.lookup.clone()))
+            .merge(routes::transactions::routes(self.database.clone(), self.lookup.clone()))
+            .merge(routes::payloads::routes(self.database.clone()))
+            .merge(routes::addresses::routes(self.database.clone()))
+            .merge(routes::stats::routes(self.database.clone(), self.rpc_client.clone()))
+            .merge(routes::search::routes(self.database.clone()))
+            .merge(routes::network::routes(self.rpc_client.clone()))
+            .merge(routes::mempool::routes(self.database.clone(), self.mempool.clone()))
+            .merge(routes::admin::routes(self.database.clone()))
+            .merge(Routes::new().get("/ws", websocket, &[]).with_state(self.subscriptions.clone()));
+        let (api, endpoints) = spec::with_spec(api).into_parts();
+
+        // Unversioned paths predate /api/v1 and are kept for one release only
+        let legacy = endpoints.iter()
+            .fold(Router::new(), |legacy, endpoint| legacy.route(&endpoint.path, any(redirect_legacy)));
+
         Router::new()
-            .nest("/api/v1", Router::new()
-                .merge(routes::blocks::routes(self.database.clone()))
-                .merge(routes::transactions::routes(self.database.clone()))
-                .merge(routes::addresses::routes(self.database.clone()))
-                .merge(routes::stats::routes(self.database.clone(), self.rpc_client.clone()))
-                .merge(routes::search::routes(self.database.clone()))
-            )
+            .nest(API_PREFIX, api)
+            .merge(legacy)
+            .fallback(unknown_endpoint)
+            .layer(middleware::from_fn(negotiate_version))
             .layer(cors)
     }
 
@@ -47,9 +95,137 @@ impl ApiServer {
 
         tracing::info!("API server listening on {}", addr);
 
-        axum::serve(listener, app).await
-            .map_err(|e| crate::error::ExplorerError::Internal(format!("Server error: {}", e)))?;
+        let refresh = self.mempool.spawn_refresh(MEMPOOL_REFRESH_INTERVAL);
+
+        let served = axum::serve(listener, app).await
+            .map_err(|e| crate::error::ExplorerError::Internal(format!("Server error: {}", e)));
+        refresh.abort();
+        served?;
 
         Ok(())
     }
 }
+
+async fn websocket(
+    State(subscriptions): State<Arc<SubscriptionManager>>,
+    ws: std::result::Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response> {
+    Ok(WSServer::handle_connection(ws?, subscriptions).await)
+}
+
+/// Permanent redirect to the versioned path, flagged as deprecated
+async fn redirect_legacy(uri: Uri) -> Response {
+    let location = match uri.query() {
+        Some(query) => format!("{}{}?{}", API_PREFIX, uri.path(), query),
+        None => format!("{}{}", API_PREFIX, uri.path()),
+    };
+    let headers = [
+        (HeaderName::from_static("deprecation"), "true".to_string()),
+        (HeaderName::from_static("link"), format!("<{}>; rel=\"successor-version\"", location)),
+    ];
+    (headers, Redirect::permanent(&location)).into_response()
+}
+
+async fn unknown_endpoint(uri: Uri) -> ExplorerError {
+    ExplorerError::UnknownEndpoint(uri.path().to_string())
+}
+
+async fn negotiate_version(request: Request, next: Next) -> Response {
+    let requested = request.headers().get(API_VERSION_HEADER).cloned();
+    let mut response = match requested {
+        Some(version) if version != API_VERSION => {
+            ExplorerError::UnsupportedVersion(String::from_utf8_lossy(version.as_bytes()).into_owned()).into_response()
+        }
+        _ => next.run(request).await,
+    };
+    response.headers_mut().insert(API_VERSION_HEADER, HeaderValue::from_static(API_VERSION));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::StatusCode;
+    use serde_json::Value;
+    use tempfile::{tempdir, TempDir};
+    use tower::ServiceExt;
+    use rpc_client::JioRpcClient;
+
+    /// Router over a migrated database and a node that is never reachable
+    async fn router() -> (TempDir, Router) {
+        let dir = tempdir().unwrap();
+        let database = Arc::new(Database::new(&dir.path().join("explorer.db")).await.unwrap());
+        database.migrate().await.unwrap();
+        let rpc_client = Arc::new(JioRpcClient::new("ws://127.0.0.1:1").unwrap());
+        (dir, ApiServer::new(database, rpc_client, 0).router())
+    }
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, Response) {
+        let response = router.clone().oneshot(axum::http::Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        (response.status(), response)
+    }
+
+    async fn json(response: Response) -> Value {
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_get_the_error_envelope() {
+        let (_dir, router) = router().await;
+
+        let (status, response) = get(&router, "/api/v1/blocks/height/tall").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[API_VERSION_HEADER], API_VERSION);
+        let body = json(response).await;
+        assert_eq!(body["error"]["code"], "invalid_input");
+        assert!(body["error"]["message"].as_str().unwrap().starts_with("Invalid input"));
+
+        let (status, response) = get(&router, "/api/v1/search").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json(response).await["error"]["code"], "invalid_input");
+
+        let (status, response) = get(&router, "/api/v1/nowhere").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(json(response).await["error"]["code"], "unknown_endpoint");
+
+        let request = axum::http::Request::get("/api/v1/spec").header(API_VERSION_HEADER, "2").body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json(response).await["error"]["code"], "unsupported_version");
+    }
+
+    #[tokio::test]
+    async fn test_spec_lists_every_route() {
+        let (_dir, router) = router().await;
+        let (status, response) = get(&router, "/api/v1/spec").await;
+        assert_eq!(status, StatusCode::OK);
+        let spec = json(response).await;
+        assert_eq!(spec["version"], API_VERSION);
+
+        let endpoints = spec["endpoints"].as_array().unwrap();
+        let paths: Vec<&str> = endpoints.iter().map(|endpoint| endpoint["path"].as_str().unwrap()).collect();
+        for path in ["/api/v1/blocks/:hash", "/api/v1/search", "/api/v1/admin/ledger/check", "/api/v1/ws", "/api/v1/spec"] {
+            assert!(paths.contains(&path), "{} missing from the spec", path);
+        }
+        let search = endpoints.iter().find(|endpoint| endpoint["path"] == "/api/v1/search").unwrap();
+        assert_eq!(search["params"], serde_json::json!([{ "name": "q", "in": "query", "required": true }]));
+
+        // Every listed path is routed, and its unversioned form redirects to it
+        for path in paths {
+            let uri = path.split('/')
+                .map(|segment| if segment.starts_with(':') { "00" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+            let (status, response) = get(&router, &uri).await;
+            if status.is_client_error() || status.is_server_error() {
+                assert_ne!(json(response).await["error"]["code"], "unknown_endpoint", "{} is not routed", uri);
+            }
+
+            let (status, response) = get(&router, &format!("{}?page=2", &uri[API_PREFIX.len()..])).await;
+            assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+            assert_eq!(response.headers()["location"], format!("{}?page=2", uri));
+            assert_eq!(response.headers()["deprecation"], "true");
+        }
+    }
+}
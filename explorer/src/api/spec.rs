@@ -0,0 +1,125 @@
+//! Endpoint registry: the router, the legacy redirects and `GET /api/v1/spec`
+//! are all built from the same list, so the spec cannot drift from the routes
+
+use axum::{
+    Router,
+    handler::Handler,
+    routing::get,
+    extract::State,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Version served under `API_PREFIX`
+pub const API_VERSION: &str = "1";
+pub const API_PREFIX: &str = "/api/v1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamLocation {
+    Path,
+    Query,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Param {
+    pub name: &'static str,
+    #[serde(rename = "in")]
+    pub location: ParamLocation,
+    pub required: bool,
+}
+
+impl Param {
+    /// Optional query parameter
+    pub const fn query(name: &'static str) -> Self {
+        Self { name, location: ParamLocation::Query, required: false }
+    }
+
+    /// Query parameter the endpoint rejects requests without
+    pub const fn required(name: &'static str) -> Self {
+        Self { name, location: ParamLocation::Query, required: true }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Endpoint {
+    pub method: &'static str,
+    /// Relative to `API_PREFIX` while registered, absolute in the spec
+    pub path: String,
+    pub params: Vec<Param>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiSpec {
+    pub version: &'static str,
+    pub endpoints: Vec<Endpoint>,
+}
+
+/// A router along with the endpoints registered on it
+pub struct Routes<S = ()> {
+    router: Router<S>,
+    endpoints: Vec<Endpoint>,
+}
+
+impl<S: Clone + Send + Sync + 'static> Default for Routes<S> {
+    fn default() -> Self {
+        Self { router: Router::new(), endpoints: Vec::new() }
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> Routes<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a GET endpoint; path parameters are taken from `path`
+    pub fn get<H, T>(mut self, path: &'static str, handler: H, query: &[Param]) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        let params = path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .map(|name| Param { name, location: ParamLocation::Path, required: true })
+            .chain(query.iter().cloned())
+            .collect();
+        self.endpoints.push(Endpoint { method: "GET", path: path.to_string(), params });
+        Self { router: self.router.route(path, get(handler)), ..self }
+    }
+
+    pub fn with_state<S2>(self, state: S) -> Routes<S2> {
+        Routes { router: self.router.with_state(state), endpoints: self.endpoints }
+    }
+}
+
+impl Routes {
+    pub fn merge(mut self, other: Routes) -> Self {
+        self.endpoints.extend(other.endpoints);
+        Self { router: self.router.merge(other.router), ..self }
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    pub fn into_parts(self) -> (Router, Vec<Endpoint>) {
+        (self.router, self.endpoints)
+    }
+}
+
+/// Add `GET /spec`, describing `routes` and itself
+pub fn with_spec(routes: Routes) -> Routes {
+    let spec_route = Routes::<Arc<ApiSpec>>::new().get("/spec", get_spec, &[]);
+    let endpoints = routes.endpoints().iter().chain(spec_route.endpoints.iter())
+        .map(|endpoint| Endpoint { path: format!("{}{}", API_PREFIX, endpoint.path), ..endpoint.clone() })
+        .collect();
+    let spec = Arc::new(ApiSpec { version: API_VERSION, endpoints });
+    routes.merge(spec_route.with_state(spec))
+}
+
+#[axum::debug_handler]
+async fn get_spec(State(spec): State<Arc<ApiSpec>>) -> Json<ApiSpec> {
+    Json(spec.as_ref().clone())
+}
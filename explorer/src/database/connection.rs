@@ -3,7 +3,20 @@
 use sqlx::sqlite::{SqlitePoolOptions, SqliteConnectOptions};
 use std::time::Duration;
 use std::path::Path;
-use crate::error::Result;
+use crate::error::{ExplorerError, Result};
+
+/// Migrations in order, each applied once; a database at version N has
+/// applied the first N. Databases from before versioning are at version 0,
+/// which is why the first three only create what does not exist yet.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("initial_schema", include_str!("../../migrations/001_initial_schema.sql")),
+    ("transaction_payloads", include_str!("../../migrations/002_transaction_payloads.sql")),
+    ("address_ledger", include_str!("../../migrations/003_address_ledger.sql")),
+    ("block_verbose_data", include_str!("../../migrations/004_block_verbose_data.sql")),
+];
+
+/// Schema version this binary migrates databases to
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
 
 pub struct Database {
     pool: sqlx::SqlitePool,
@@ -33,11 +46,44 @@ impl Database {
         &self.pool
     }
 
-    pub async fn migrate(&self) -> Result<()> {
-        // Run migrations manually
-        sqlx::query(include_str!("../../migrations/001_initial_schema.sql"))
-            .execute(&self.pool)
+    /// Version of the schema on disk, 0 for databases never migrated
+    pub async fn schema_version(&self) -> Result<i64> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at INTEGER NOT NULL
+            )"
+        )
+        .execute(&self.pool)
+        .await?;
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&self.pool)
             .await?;
+        Ok(version.unwrap_or(0))
+    }
+
+    /// Apply the pending migrations in order, each along with its version
+    /// row in one transaction. A schema newer than [`SCHEMA_VERSION`] was
+    /// written by a newer explorer and is refused untouched.
+    pub async fn migrate(&self) -> Result<()> {
+        let current = self.schema_version().await?;
+        if current > SCHEMA_VERSION {
+            return Err(ExplorerError::UnsupportedSchema { found: current, supported: SCHEMA_VERSION });
+        }
+
+        for (version, (name, sql)) in (1..).zip(MIGRATIONS).skip(current as usize) {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(sql).execute(&mut *tx).await?;
+            sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)")
+                .bind(version)
+                .bind(*name)
+                .bind(chrono::Utc::now().timestamp())
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            tracing::info!("Applied explorer schema migration {} ({})", version, name);
+        }
         Ok(())
     }
 }
@@ -73,6 +119,73 @@ mod tests {
         temp_dir.close().unwrap();
     }
 
+    #[tokio::test]
+    async fn test_fresh_database_migrates_to_latest_version() {
+        let temp_dir = tempdir().unwrap();
+        let db = Database::new(&temp_dir.path().join("test.db")).await.unwrap();
+        assert_eq!(db.schema_version().await.unwrap(), 0);
+
+        db.migrate().await.unwrap();
+        assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+        let applied: Vec<(i64, String)> = sqlx::query_as("SELECT version, name FROM schema_version ORDER BY version")
+            .fetch_all(db.pool())
+            .await
+            .unwrap();
+        let expected: Vec<(i64, String)> = (1..).zip(MIGRATIONS).map(|(version, (name, _))| (version, name.to_string())).collect();
+        assert_eq!(applied, expected);
+
+        // Nothing is pending the second time
+        db.migrate().await.unwrap();
+        let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM schema_version").fetch_one(db.pool()).await.unwrap();
+        assert_eq!(rows, SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_pending_migrations_run_from_the_recorded_version() {
+        let temp_dir = tempdir().unwrap();
+        let db = Database::new(&temp_dir.path().join("test.db")).await.unwrap();
+        db.migrate().await.unwrap();
+
+        // Undo the last migration as if the database predated it
+        for undo in [
+            "DROP TABLE block_mergesets",
+            "DROP INDEX idx_blocks_selected_parent",
+            "ALTER TABLE blocks DROP COLUMN selected_parent",
+            "ALTER TABLE blocks DROP COLUMN is_chain_block",
+            "ALTER TABLE blocks DROP COLUMN difficulty",
+            "ALTER TABLE blocks DROP COLUMN is_header_only",
+        ] {
+            sqlx::query(undo).execute(db.pool()).await.unwrap();
+        }
+        sqlx::query("DELETE FROM schema_version WHERE version = ?").bind(SCHEMA_VERSION).execute(db.pool()).await.unwrap();
+        db.migrate().await.unwrap();
+        assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+        let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM block_mergesets").fetch_one(db.pool()).await.unwrap();
+        assert_eq!(rows, 0);
+        let (chain_blocks,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM blocks WHERE is_chain_block").fetch_one(db.pool()).await.unwrap();
+        assert_eq!(chain_blocks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_is_refused() {
+        let temp_dir = tempdir().unwrap();
+        let db = Database::new(&temp_dir.path().join("test.db")).await.unwrap();
+        db.migrate().await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'from_the_future', 0)")
+            .bind(SCHEMA_VERSION + 1)
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        let result = db.migrate().await;
+        assert!(matches!(
+            result,
+            Err(ExplorerError::UnsupportedSchema { found, supported }) if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+        assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION + 1);
+    }
+
     #[tokio::test]
     async fn test_database_directory_creation() {
         let temp_dir = tempdir().unwrap();
@@ -18,7 +18,11 @@ impl BlockQueries {
                 size,
                 coinbase_value,
                 (SELECT COUNT(*) FROM block_parents WHERE block_hash = ?) as parent_count,
-                blue_score
+                blue_score,
+                selected_parent,
+                is_chain_block,
+                difficulty,
+                is_header_only
             FROM blocks
             WHERE hash = ?
             "#
@@ -42,7 +46,11 @@ impl BlockQueries {
                 size,
                 coinbase_value,
                 (SELECT COUNT(*) FROM block_parents WHERE block_hash = blocks.hash) as parent_count,
-                blue_score
+                blue_score,
+                selected_parent,
+                is_chain_block,
+                difficulty,
+                is_header_only
             FROM blocks
             WHERE height = ?
             "#
@@ -65,7 +73,11 @@ impl BlockQueries {
                 size,
                 coinbase_value,
                 (SELECT COUNT(*) FROM block_parents WHERE block_hash = blocks.hash) as parent_count,
-                blue_score
+                blue_score,
+                selected_parent,
+                is_chain_block,
+                difficulty,
+                is_header_only
             FROM blocks
             ORDER BY height DESC
             LIMIT ? OFFSET ?
@@ -79,6 +91,45 @@ impl BlockQueries {
         Ok(blocks)
     }
 
+    /// Parents, children and mergeset of an indexed block; `None` if it is not indexed
+    pub async fn get_dag(pool: Arc<sqlx::SqlitePool>, hash: &str) -> Result<Option<BlockDag>> {
+        let indexed: Option<(String,)> = sqlx::query_as("SELECT hash FROM blocks WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&*pool)
+            .await?;
+        if indexed.is_none() {
+            return Ok(None);
+        }
+
+        let parents = sqlx::query_scalar::<_, String>(
+            "SELECT parent_hash FROM block_parents WHERE block_hash = ? AND level = 0 ORDER BY parent_hash"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let children = sqlx::query_scalar::<_, String>(
+            "SELECT block_hash FROM block_parents WHERE parent_hash = ? AND level = 0 ORDER BY block_hash"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let mergeset = sqlx::query_as::<_, (String, bool)>(
+            "SELECT merged_hash, is_blue FROM block_mergesets WHERE block_hash = ? ORDER BY position"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let (blues, reds): (Vec<_>, Vec<_>) = mergeset.into_iter().partition(|(_, is_blue)| *is_blue);
+
+        Ok(Some(BlockDag {
+            hash: hash.to_string(),
+            parents,
+            children,
+            merge_set_blues: blues.into_iter().map(|(merged, _)| merged).collect(),
+            merge_set_reds: reds.into_iter().map(|(merged, _)| merged).collect(),
+        }))
+    }
+
     pub async fn count(pool: Arc<sqlx::SqlitePool>) -> Result<i64> {
         let count = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) as count FROM blocks"
@@ -108,7 +159,10 @@ impl TransactionQueries {
                 size,
                 is_coinbase,
                 is_confirmed,
-                confirmation_count
+                confirmation_count,
+                COALESCE((SELECT size FROM transaction_payloads WHERE tx_hash = transactions.hash), 0) as payload_size,
+                (SELECT hex_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_preview,
+                (SELECT utf8_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_utf8
             FROM transactions
             WHERE hash = ?
             "#
@@ -135,7 +189,10 @@ impl TransactionQueries {
                 size,
                 is_coinbase,
                 is_confirmed,
-                confirmation_count
+                confirmation_count,
+                COALESCE((SELECT size FROM transaction_payloads WHERE tx_hash = transactions.hash), 0) as payload_size,
+                (SELECT hex_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_preview,
+                (SELECT utf8_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_utf8
             FROM transactions
             ORDER BY timestamp DESC
             LIMIT ? OFFSET ?
@@ -164,7 +221,10 @@ impl TransactionQueries {
                 size,
                 is_coinbase,
                 is_confirmed,
-                confirmation_count
+                confirmation_count,
+                COALESCE((SELECT size FROM transaction_payloads WHERE tx_hash = transactions.hash), 0) as payload_size,
+                (SELECT hex_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_preview,
+                (SELECT utf8_preview FROM transaction_payloads WHERE tx_hash = transactions.hash) as payload_utf8
             FROM transactions
             WHERE is_confirmed = FALSE
             ORDER BY timestamp DESC
@@ -176,6 +236,45 @@ impl TransactionQueries {
         Ok(txs)
     }
 
+    /// Parents, children and mergeset of an indexed block; `None` if it is not indexed
+    pub async fn get_dag(pool: Arc<sqlx::SqlitePool>, hash: &str) -> Result<Option<BlockDag>> {
+        let indexed: Option<(String,)> = sqlx::query_as("SELECT hash FROM blocks WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&*pool)
+            .await?;
+        if indexed.is_none() {
+            return Ok(None);
+        }
+
+        let parents = sqlx::query_scalar::<_, String>(
+            "SELECT parent_hash FROM block_parents WHERE block_hash = ? AND level = 0 ORDER BY parent_hash"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let children = sqlx::query_scalar::<_, String>(
+            "SELECT block_hash FROM block_parents WHERE parent_hash = ? AND level = 0 ORDER BY block_hash"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let mergeset = sqlx::query_as::<_, (String, bool)>(
+            "SELECT merged_hash, is_blue FROM block_mergesets WHERE block_hash = ? ORDER BY position"
+        )
+        .bind(hash)
+        .fetch_all(&*pool)
+        .await?;
+        let (blues, reds): (Vec<_>, Vec<_>) = mergeset.into_iter().partition(|(_, is_blue)| *is_blue);
+
+        Ok(Some(BlockDag {
+            hash: hash.to_string(),
+            parents,
+            children,
+            merge_set_blues: blues.into_iter().map(|(merged, _)| merged).collect(),
+            merge_set_reds: reds.into_iter().map(|(merged, _)| merged).collect(),
+        }))
+    }
+
     pub async fn count(pool: Arc<sqlx::SqlitePool>) -> Result<i64> {
         let count = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) as count FROM transactions"
@@ -235,7 +334,10 @@ impl AddressQueries {
                 t.size,
                 t.is_coinbase,
                 t.is_confirmed,
-                t.confirmation_count
+                t.confirmation_count,
+                COALESCE((SELECT size FROM transaction_payloads WHERE tx_hash = t.hash), 0) as payload_size,
+                (SELECT hex_preview FROM transaction_payloads WHERE tx_hash = t.hash) as payload_preview,
+                (SELECT utf8_preview FROM transaction_payloads WHERE tx_hash = t.hash) as payload_utf8
             FROM transactions t
             INNER JOIN address_transactions at ON t.hash = at.tx_hash
             WHERE at.address = ?
@@ -253,3 +355,131 @@ impl AddressQueries {
     }
 }
 
+
+pub struct PayloadQueries;
+
+impl PayloadQueries {
+    pub async fn get(pool: Arc<sqlx::SqlitePool>, tx_hash: &str) -> Result<Option<Payload>> {
+        let payload = sqlx::query_as::<_, Payload>(
+            r#"
+            SELECT
+                tx_hash,
+                size,
+                lower(hex(payload)) as hex,
+                utf8
+            FROM transaction_payloads
+            WHERE tx_hash = ?
+            "#
+        )
+        .bind(tx_hash)
+        .fetch_optional(&*pool)
+        .await?;
+
+        Ok(payload)
+    }
+
+    /// Transactions whose payload starts with `prefix`, most recent first
+    pub async fn search_prefix(pool: Arc<sqlx::SqlitePool>, prefix: &[u8], limit: i64) -> Result<Vec<PayloadSummary>> {
+        // Payloads starting with the prefix sort between it and the next prefix of its length
+        let upper = prefix_upper_bound(prefix);
+        let payloads = sqlx::query_as::<_, PayloadSummary>(
+            r#"
+            SELECT
+                p.tx_hash,
+                t.block_hash,
+                t.timestamp,
+                p.size,
+                p.hex_preview,
+                p.utf8_preview,
+                p.size > ? as truncated
+            FROM transaction_payloads p
+            INNER JOIN transactions t ON t.hash = p.tx_hash
+            WHERE p.payload >= ? AND (? IS NULL OR p.payload < ?)
+            ORDER BY t.timestamp DESC, p.tx_hash
+            LIMIT ?
+            "#
+        )
+        .bind(PAYLOAD_PREVIEW_BYTES as i64)
+        .bind(prefix)
+        .bind(&upper)
+        .bind(&upper)
+        .bind(limit)
+        .fetch_all(&*pool)
+        .await?;
+
+        Ok(payloads)
+    }
+
+    /// Transactions whose payload is UTF-8 text containing `text`, most recent first
+    pub async fn search_utf8(pool: Arc<sqlx::SqlitePool>, text: &str, limit: i64) -> Result<Vec<PayloadSummary>> {
+        let payloads = sqlx::query_as::<_, PayloadSummary>(
+            r#"
+            SELECT
+                p.tx_hash,
+                t.block_hash,
+                t.timestamp,
+                p.size,
+                p.hex_preview,
+                p.utf8_preview,
+                p.size > ? as truncated
+            FROM transaction_payloads p
+            INNER JOIN transactions t ON t.hash = p.tx_hash
+            WHERE p.utf8 IS NOT NULL AND instr(p.utf8, ?) > 0
+            ORDER BY t.timestamp DESC, p.tx_hash
+            LIMIT ?
+            "#
+        )
+        .bind(PAYLOAD_PREVIEW_BYTES as i64)
+        .bind(text)
+        .bind(limit)
+        .fetch_all(&*pool)
+        .await?;
+
+        Ok(payloads)
+    }
+}
+
+pub struct LedgerQueries;
+
+impl LedgerQueries {
+    /// Balances of `address`, or of up to `limit` addresses picked at random,
+    /// next to the sums of their ledger rows
+    pub async fn check_balances(pool: Arc<sqlx::SqlitePool>, address: Option<&str>, limit: i64) -> Result<Vec<BalanceCheck>> {
+        let checks = sqlx::query_as::<_, BalanceCheck>(
+            r#"
+            SELECT
+                a.address,
+                a.balance,
+                COALESCE(SUM(l.amount), 0) as ledger_balance
+            FROM addresses a
+            LEFT JOIN address_ledger l ON l.address = a.address
+            WHERE a.address IN (
+                SELECT address FROM addresses
+                WHERE ?1 IS NULL OR address = ?1
+                ORDER BY RANDOM()
+                LIMIT ?2
+            )
+            GROUP BY a.address, a.balance
+            "#
+        )
+        .bind(address)
+        .bind(limit)
+        .fetch_all(&*pool)
+        .await?;
+
+        Ok(checks)
+    }
+}
+
+/// Smallest byte string greater than every string starting with `prefix`,
+/// or `None` if there is none (the prefix is all 0xff bytes)
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
@@ -102,6 +102,19 @@ CREATE INDEX IF NOT EXISTS idx_tx_outputs_address ON transaction_outputs(address
 CREATE INDEX IF NOT EXISTS idx_tx_outputs_is_spent ON transaction_outputs(is_spent);
 "#;
 
+pub const CREATE_TRANSACTION_PAYLOADS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS transaction_payloads (
+    tx_hash VARCHAR(64) PRIMARY KEY,
+    size INTEGER NOT NULL,
+    payload BYTEA NOT NULL,
+    utf8 TEXT,
+    hex_preview TEXT NOT NULL,
+    utf8_preview TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_tx_payloads_payload ON transaction_payloads(payload);
+"#;
+
 pub const CREATE_ADDRESSES_TABLE: &str = r#"
 CREATE TABLE IF NOT EXISTS addresses (
     address VARCHAR(255) PRIMARY KEY,
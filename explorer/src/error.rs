@@ -2,6 +2,7 @@
 
 use thiserror::Error;
 use axum::{
+    extract::rejection::{PathRejection, QueryRejection, WebSocketUpgradeRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
@@ -31,6 +32,15 @@ pub enum ExplorerError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
+    #[error("No endpoint at {0}")]
+    UnknownEndpoint(String),
+
+    #[error("Unsupported API version {0}")]
+    UnsupportedVersion(String),
+
+    #[error("Database schema version {found} is newer than version {supported}, the latest this explorer supports")]
+    UnsupportedSchema { found: i64, supported: i64 },
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -43,22 +53,46 @@ impl From<rpc_core::RpcError> for ExplorerError {
     }
 }
 
+impl From<PathRejection> for ExplorerError {
+    fn from(rejection: PathRejection) -> Self {
+        ExplorerError::InvalidInput(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ExplorerError {
+    fn from(rejection: QueryRejection) -> Self {
+        ExplorerError::InvalidInput(rejection.body_text())
+    }
+}
+
+impl From<WebSocketUpgradeRejection> for ExplorerError {
+    fn from(rejection: WebSocketUpgradeRejection) -> Self {
+        ExplorerError::InvalidInput(rejection.body_text())
+    }
+}
+
+/// Every error is served as `{"error": {"code", "message"}}`
 impl IntoResponse for ExplorerError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            ExplorerError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
-            ExplorerError::Rpc(_) => (StatusCode::INTERNAL_SERVER_ERROR, "RPC error"),
-            ExplorerError::Cache(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Cache error"),
-            ExplorerError::Serialization(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Serialization error"),
-            ExplorerError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "IO error"),
-            ExplorerError::NotFound(_) => (StatusCode::NOT_FOUND, "Not found"),
-            ExplorerError::InvalidInput(_) => (StatusCode::BAD_REQUEST, "Invalid input"),
-            ExplorerError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
+        let (status, code) = match self {
+            ExplorerError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+            ExplorerError::Rpc(_) => (StatusCode::INTERNAL_SERVER_ERROR, "rpc_error"),
+            ExplorerError::Cache(_) => (StatusCode::INTERNAL_SERVER_ERROR, "cache_error"),
+            ExplorerError::Serialization(_) => (StatusCode::INTERNAL_SERVER_ERROR, "serialization_error"),
+            ExplorerError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
+            ExplorerError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            ExplorerError::InvalidInput(_) => (StatusCode::BAD_REQUEST, "invalid_input"),
+            ExplorerError::UnknownEndpoint(_) => (StatusCode::NOT_FOUND, "unknown_endpoint"),
+            ExplorerError::UnsupportedVersion(_) => (StatusCode::BAD_REQUEST, "unsupported_version"),
+            ExplorerError::UnsupportedSchema { .. } => (StatusCode::INTERNAL_SERVER_ERROR, "unsupported_schema"),
+            ExplorerError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
         };
 
         let body = Json(json!({
-            "error": error_message,
-            "message": self.to_string(),
+            "error": {
+                "code": code,
+                "message": self.to_string(),
+            },
         }));
 
         (status, body).into_response()
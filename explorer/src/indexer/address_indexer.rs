@@ -1,11 +1,16 @@
-//! Address indexing and balance tracking
+//! Address indexing, and balances kept by a double-entry ledger of the
+//! transactions accepted by the selected chain
 
 use std::sync::Arc;
-use consensus_core::tx::Transaction;
+use consensus_core::{tx::Transaction, Hash};
+use rpc_core::VirtualChainChanged;
 use crate::database::Database;
-use crate::error::Result;
+use crate::error::{ExplorerError, Result};
 use sqlx::Row;
 
+/// `indexer_state` key of the chain block the ledger is up to
+const LEDGER_TIP_KEY: &str = "ledger_tip";
+
 pub struct AddressIndexer {
     pool: Arc<sqlx::SqlitePool>,
 }
@@ -17,86 +22,143 @@ impl AddressIndexer {
         }
     }
     
+    /// Record the activity of the addresses `tx` pays and spends from; their
+    /// balances follow the ledger, see [`Self::apply_chain_changes`]
     pub async fn update_from_transaction(&self, tx: &Transaction) -> Result<()> {
-        let tx_hash = tx.hash().to_string();
         let timestamp = chrono::Utc::now().timestamp() as i64;
         
         // Process outputs (addresses receiving)
         for output in &tx.outputs {
             if let Some(address) = self.extract_address(&output.script_public_key) {
-                self.update_address_received(&address, output.value, timestamp).await?;
+                self.record_activity(&address, timestamp).await?;
             }
         }
         
         // Process inputs (addresses sending)
         for input in &tx.inputs {
             // Get the previous output to find the address
-            if let Some((address, value)) = self.get_previous_output_address(&input.previous_outpoint.transaction_id.to_string(), input.previous_outpoint.index).await? {
-                self.update_address_sent(&address, value, timestamp).await?;
+            if let Some((address, _)) = self.get_previous_output_address(&input.previous_outpoint.transaction_id.to_string(), input.previous_outpoint.index).await? {
+                self.record_activity(&address, timestamp).await?;
             }
         }
         
         Ok(())
     }
     
-    async fn update_address_received(&self, address: &str, value: u64, timestamp: i64) -> Result<()> {
+    async fn record_activity(&self, address: &str, timestamp: i64) -> Result<()> {
         sqlx::query(
             r#"
             INSERT INTO addresses (
-                address, balance, total_received, received_count,
-                tx_count, utxo_count, first_seen_timestamp, last_seen_timestamp
-            ) VALUES ($1, $2, $3, 1, 1, 1, $4, $4)
+                address, tx_count, first_seen_timestamp, last_seen_timestamp
+            ) VALUES ($1, 1, $2, $2)
             ON CONFLICT (address) DO UPDATE SET
-                balance = addresses.balance + $2,
-                total_received = addresses.total_received + $3,
-                received_count = addresses.received_count + 1,
                 tx_count = addresses.tx_count + 1,
-                utxo_count = addresses.utxo_count + 1,
-                last_seen_timestamp = GREATEST(addresses.last_seen_timestamp, $4),
-                updated_at = NOW()
+                first_seen_timestamp = COALESCE(addresses.first_seen_timestamp, $2),
+                last_seen_timestamp = MAX(COALESCE(addresses.last_seen_timestamp, $2), $2),
+                updated_at = CURRENT_TIMESTAMP
             "#,
         )
         .bind(address)
-        .bind(value as i64)
-        .bind(value as i64)
         .bind(timestamp)
         .execute(&*self.pool)
         .await?;
 
         Ok(())
     }
-    
-    async fn update_address_sent(&self, address: &str, value: u64, timestamp: i64) -> Result<()> {
-        sqlx::query(
-            r#"
-            UPDATE addresses
-            SET
-                balance = balance - $1,
-                total_sent = total_sent + $2,
-                sent_count = sent_count + 1,
-                tx_count = tx_count + 1,
-                utxo_count = GREATEST(0, utxo_count - 1),
-                last_seen_timestamp = GREATEST(last_seen_timestamp, $3),
-                updated_at = NOW()
-            WHERE address = $4
-            "#,
-        )
-        .bind(value as i64)
-        .bind(value as i64)
-        .bind(timestamp)
-        .bind(address)
-        .execute(&*self.pool)
-        .await?;
 
+    /// Move the ledger along a change of the selected chain: the rows of
+    /// removed chain blocks are deleted, and the transactions accepted by added
+    /// ones credit their outputs and debit the outputs they spend. Applied in
+    /// full or not at all; fails while an accepted transaction is not indexed.
+    pub async fn apply_chain_changes(&self, changes: &VirtualChainChanged) -> Result<()> {
+        let mut db_tx = self.pool.begin().await?;
+
+        for hash in &changes.removed_chain_block_hashes {
+            sqlx::query("DELETE FROM address_ledger WHERE accepting_block_hash = $1")
+                .bind(hash.to_string())
+                .execute(&mut *db_tx)
+                .await?;
+        }
+
+        for accepted in &changes.accepted_transaction_ids {
+            let accepting_block_hash = accepted.accepting_block_hash.to_string();
+            for id in &accepted.accepted_transaction_ids {
+                let tx_hash = id.to_string();
+                let indexed: Option<(String,)> = sqlx::query_as("SELECT hash FROM transactions WHERE hash = $1")
+                    .bind(&tx_hash)
+                    .fetch_optional(&mut *db_tx)
+                    .await?;
+                if indexed.is_none() {
+                    return Err(ExplorerError::NotFound(format!("Accepted transaction {} is not indexed yet", tx_hash)));
+                }
+
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO address_ledger (
+                        accepting_block_hash, tx_hash, address, outpoint_hash, outpoint_index, amount, is_debit
+                    )
+                    SELECT $1, tx_hash, address, tx_hash, "index", value, FALSE
+                    FROM transaction_outputs
+                    WHERE tx_hash = $2 AND address IS NOT NULL
+                    "#,
+                )
+                .bind(&accepting_block_hash)
+                .bind(&tx_hash)
+                .execute(&mut *db_tx)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO address_ledger (
+                        accepting_block_hash, tx_hash, address, outpoint_hash, outpoint_index, amount, is_debit
+                    )
+                    SELECT $1, i.tx_hash, o.address, o.tx_hash, o."index", -o.value, TRUE
+                    FROM transaction_inputs i
+                    JOIN transaction_outputs o
+                        ON o.tx_hash = i.previous_outpoint_hash AND o."index" = i.previous_outpoint_index
+                    WHERE i.tx_hash = $2 AND o.address IS NOT NULL
+                    "#,
+                )
+                .bind(&accepting_block_hash)
+                .bind(&tx_hash)
+                .execute(&mut *db_tx)
+                .await?;
+            }
+        }
+
+        if let Some(tip) = changes.added_chain_block_hashes.last() {
+            sqlx::query(
+                r#"
+                INSERT INTO indexer_state (key, value) VALUES ($1, $2)
+                ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value
+                "#,
+            )
+            .bind(LEDGER_TIP_KEY)
+            .bind(tip.to_string())
+            .execute(&mut *db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
         Ok(())
     }
+
+    /// Chain block the ledger is up to, if any
+    pub async fn ledger_tip(&self) -> Result<Option<Hash>> {
+        let tip: Option<(String,)> = sqlx::query_as("SELECT value FROM indexer_state WHERE key = $1")
+            .bind(LEDGER_TIP_KEY)
+            .fetch_optional(&*self.pool)
+            .await?;
+        tip.map(|(hex,)| hex.parse().map_err(|_| ExplorerError::Internal(format!("Invalid ledger tip {}", hex))))
+            .transpose()
+    }
     
     async fn get_previous_output_address(&self, tx_hash: &str, index: u32) -> Result<Option<(String, u64)>> {
         let result = sqlx::query(
             r#"
             SELECT address, value
             FROM transaction_outputs
-            WHERE tx_hash = $1 AND index = $2
+            WHERE tx_hash = $1 AND "index" = $2
             "#,
         )
         .bind(tx_hash)
@@ -112,11 +174,132 @@ impl AddressIndexer {
     }
     
     fn extract_address(&self, script_pub_key: &consensus_core::tx::ScriptPublicKey) -> Option<String> {
-        if script_pub_key.script().is_empty() {
-            None
-        } else {
-            Some(hex::encode(script_pub_key.script()))
-        }
+        script_address(script_pub_key)
     }
 }
 
+/// Address the explorer files an output under; outputs with non-standard scripts have none
+pub fn script_address(script_pub_key: &consensus_core::tx::ScriptPublicKey) -> Option<String> {
+    consensus_core::tx::script_class::extract_address(script_pub_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::queries::{AddressQueries, LedgerQueries};
+    use crate::models::AddressSummary;
+    use crate::indexer::transaction_indexer::TransactionIndexer;
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::script_class::pay_to_address_script;
+    use consensus_core::tx::{TransactionInput, TransactionOutpoint, TransactionOutput};
+    use rpc_core::RpcAcceptedTransactionIds;
+    use tempfile::{tempdir, TempDir};
+
+    const A: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const B: &str = "1A1z7agoat3FwzZsQwtfTHtVtWWbooZewH";
+    const C: &str = "1eA9ctCNq41iLLnQErtTUVdxpRyZ9hZmW";
+
+    fn transaction(n: u8, spends: Option<&Transaction>, pays: &[(&str, u64)]) -> Transaction {
+        let inputs = spends.map(|tx| TransactionInput::new(TransactionOutpoint::new(tx.id(), 0), vec![], 0, 1)).into_iter().collect();
+        let outputs = pays.iter().map(|(address, value)| TransactionOutput::new(*value, pay_to_address_script(address).unwrap())).collect();
+        Transaction::new(1, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![n])
+    }
+
+    /// Database with `txs` indexed, and its address indexer
+    async fn indexed(txs: &[&Transaction]) -> (TempDir, Arc<Database>, AddressIndexer) {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(Database::new(&temp_dir.path().join("test.db")).await.unwrap());
+        db.migrate().await.unwrap();
+        let tx_indexer = TransactionIndexer::new(db.clone());
+        for tx in txs {
+            tx_indexer.index(tx, None).await.unwrap();
+        }
+        (temp_dir, db.clone(), AddressIndexer::new(db))
+    }
+
+    fn chain_changes(removed: &[(Hash, Vec<&Transaction>)], added: &[(Hash, Vec<&Transaction>)]) -> VirtualChainChanged {
+        let accepted = |blocks: &[(Hash, Vec<&Transaction>)]| {
+            blocks
+                .iter()
+                .map(|(hash, txs)| RpcAcceptedTransactionIds {
+                    accepting_block_hash: *hash,
+                    accepted_transaction_ids: txs.iter().map(|tx| tx.id()).collect(),
+                })
+                .collect()
+        };
+        VirtualChainChanged {
+            removed_chain_block_hashes: removed.iter().map(|(hash, _)| *hash).collect(),
+            added_chain_block_hashes: added.iter().map(|(hash, _)| *hash).collect(),
+            accepted_transaction_ids: accepted(added),
+            removed_transaction_ids: accepted(removed),
+        }
+    }
+
+    async fn balances(db: &Database) -> Vec<(String, i64, i64, i64, i64, i64, i64)> {
+        sqlx::query_as(
+            "SELECT address, balance, total_received, total_sent, received_count, sent_count, utxo_count FROM addresses ORDER BY address",
+        )
+        .fetch_all(db.pool())
+        .await
+        .unwrap()
+    }
+
+    async fn summary(db: &Database, address: &str) -> AddressSummary {
+        AddressQueries::get_summary(Arc::new(db.pool().clone()), address).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reorg_balances_match_a_rebuild() {
+        let genesis = Hash::from_u64_word(1);
+        let (a1, b1, b2) = (Hash::from_u64_word(2), Hash::from_u64_word(3), Hash::from_u64_word(4));
+        let funding = transaction(0, None, &[(A, 1_000)]);
+        // Competing spends of the funding output, one on each side of the fork
+        let to_b = transaction(1, Some(&funding), &[(B, 600), (A, 400)]);
+        let to_c = transaction(2, Some(&funding), &[(C, 1_000)]);
+        let reward = transaction(3, None, &[(B, 50)]);
+        let txs = [&funding, &to_b, &to_c, &reward];
+
+        let (_dir, db, indexer) = indexed(&txs).await;
+        indexer.apply_chain_changes(&chain_changes(&[], &[(genesis, vec![&funding]), (a1, vec![&to_b])])).await.unwrap();
+        assert_eq!(summary(&db, A).await.balance, 400);
+        assert_eq!(summary(&db, B).await.balance, 600);
+
+        indexer.apply_chain_changes(&chain_changes(&[(a1, vec![&to_b])], &[(b1, vec![&to_c]), (b2, vec![&reward])])).await.unwrap();
+        assert_eq!(indexer.ledger_tip().await.unwrap(), Some(b2));
+        let a = summary(&db, A).await;
+        assert_eq!((a.balance, a.total_received, a.total_sent, a.utxo_count), (0, 1_000, 1_000, 0));
+        assert_eq!(summary(&db, B).await.balance, 50);
+        assert_eq!(summary(&db, C).await.balance, 1_000);
+
+        let (_rebuilt_dir, rebuilt, rebuilt_indexer) = indexed(&txs).await;
+        rebuilt_indexer
+            .apply_chain_changes(&chain_changes(&[], &[(genesis, vec![&funding]), (b1, vec![&to_c]), (b2, vec![&reward])]))
+            .await
+            .unwrap();
+        assert_eq!(balances(&db).await, balances(&rebuilt).await);
+
+        let checks = LedgerQueries::check_balances(Arc::new(db.pool().clone()), None, 100).await.unwrap();
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|check| check.balance == check.ledger_balance));
+    }
+
+    #[tokio::test]
+    async fn test_changes_accepting_unindexed_transactions_are_not_applied() {
+        let (genesis, block) = (Hash::from_u64_word(1), Hash::from_u64_word(2));
+        let funding = transaction(0, None, &[(A, 1_000)]);
+        let unindexed = transaction(1, Some(&funding), &[(B, 1_000)]);
+        let (_dir, db, indexer) = indexed(&[&funding]).await;
+        indexer.apply_chain_changes(&chain_changes(&[], &[(genesis, vec![&funding])])).await.unwrap();
+        let before = balances(&db).await;
+
+        let changes = chain_changes(&[], &[(block, vec![&funding, &unindexed])]);
+        assert!(indexer.apply_chain_changes(&changes).await.is_err());
+        assert_eq!(balances(&db).await, before);
+        assert_eq!(indexer.ledger_tip().await.unwrap(), Some(genesis));
+
+        // Applies once the transaction is indexed
+        TransactionIndexer::new(db.clone()).index(&unindexed, None).await.unwrap();
+        indexer.apply_chain_changes(&changes).await.unwrap();
+        assert_eq!(balances(&db).await, vec![(B.to_string(), 1_000, 1_000, 0, 1, 0, 1), (A.to_string(), 0, 1_000, 1_000, 1, 1, 0)]);
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::sync::Arc;
 use consensus_core::block::Block;
+use rpc_core::{RpcBlockVerboseData, VirtualChainChanged};
 use crate::database::Database;
 use crate::error::Result;
 
@@ -48,9 +49,9 @@ impl BlockIndexer {
         .bind(block.header.blue_score as i64)
         .bind(format!("{}", block.header.blue_work))
         .bind(block.header.pruning_point.to_string())
-        .bind(self.calculate_block_size(block) as i32)
+        .bind(block_size(block) as i32)
         .bind(block.transactions.len() as i32)
-        .bind(self.get_coinbase_value(block) as i64)
+        .bind(coinbase_value(block) as i64)
         .execute(&*self.pool)
         .await?;
         
@@ -60,6 +61,60 @@ impl BlockIndexer {
         Ok(())
     }
     
+    /// Record the GHOSTDAG data the node gave for an indexed block
+    pub async fn index_verbose(&self, data: &RpcBlockVerboseData) -> Result<()> {
+        let hash = data.hash.to_string();
+        let mut db_tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE blocks SET
+                selected_parent = $2, is_chain_block = $3, difficulty = $4, is_header_only = $5
+            WHERE hash = $1
+            "#,
+        )
+        .bind(&hash)
+        .bind(data.selected_parent.to_string())
+        .bind(data.is_chain_block)
+        .bind(data.difficulty)
+        .bind(data.is_header_only)
+        .execute(&mut *db_tx)
+        .await?;
+
+        // The mergeset of a block never changes, but may be indexed again
+        sqlx::query("DELETE FROM block_mergesets WHERE block_hash = $1").bind(&hash).execute(&mut *db_tx).await?;
+        let blues = data.merge_set_blues_hashes.iter().map(|merged| (merged, true));
+        let reds = data.merge_set_reds_hashes.iter().map(|merged| (merged, false));
+        for (position, (merged, is_blue)) in blues.chain(reds).enumerate() {
+            sqlx::query("INSERT INTO block_mergesets (block_hash, merged_hash, is_blue, position) VALUES ($1, $2, $3, $4)")
+                .bind(&hash)
+                .bind(merged.to_string())
+                .bind(is_blue)
+                .bind(position as i64)
+                .execute(&mut *db_tx)
+                .await?;
+        }
+
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    /// Move the chain flags of indexed blocks to the node's selected chain
+    pub async fn apply_chain_changes(&self, changes: &VirtualChainChanged) -> Result<()> {
+        let mut db_tx = self.pool.begin().await?;
+        let removed = changes.removed_chain_block_hashes.iter().map(|hash| (hash, false));
+        let added = changes.added_chain_block_hashes.iter().map(|hash| (hash, true));
+        for (hash, is_chain_block) in removed.chain(added) {
+            sqlx::query("UPDATE blocks SET is_chain_block = $2 WHERE hash = $1")
+                .bind(hash.to_string())
+                .bind(is_chain_block)
+                .execute(&mut *db_tx)
+                .await?;
+        }
+        db_tx.commit().await?;
+        Ok(())
+    }
+
     async fn index_parents(&self, block_hash: &str, parents_by_level: &[Vec<consensus_core::Hash>]) -> Result<()> {
         for (level, parents) in parents_by_level.iter().enumerate() {
             for parent in parents {
@@ -79,20 +134,99 @@ impl BlockIndexer {
         }
         Ok(())
     }
-    
+}
 
-    
-    fn calculate_block_size(&self, block: &Block) -> usize {
-        // Approximate block size
-        std::mem::size_of_val(block) + block.transactions.iter().map(|tx| std::mem::size_of_val(tx)).sum::<usize>()
+/// Approximate block size
+pub(crate) fn block_size(block: &Block) -> usize {
+    std::mem::size_of_val(block) + block.transactions.iter().map(|tx| std::mem::size_of_val(tx)).sum::<usize>()
+}
+
+pub(crate) fn coinbase_value(block: &Block) -> u64 {
+    if let Some(coinbase) = block.transactions.first() {
+        coinbase.outputs.iter().map(|out| out.value).sum()
+    } else {
+        0
     }
-    
-    fn get_coinbase_value(&self, block: &Block) -> u64 {
-        if let Some(coinbase) = block.transactions.first() {
-            coinbase.outputs.iter().map(|out| out.value).sum()
-        } else {
-            0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::header::Header;
+    use consensus_core::Hash;
+    use tempfile::{tempdir, TempDir};
+
+    fn hash(n: u64) -> Hash {
+        Hash::from_u64_word(n)
+    }
+
+    fn verbose_data(block: Hash, selected_parent: Hash, blues: &[Hash], reds: &[Hash]) -> RpcBlockVerboseData {
+        RpcBlockVerboseData {
+            hash: block,
+            blue_score: 2,
+            blue_work: "0a".to_string(),
+            selected_parent,
+            merge_set_blues_hashes: blues.to_vec(),
+            merge_set_reds_hashes: reds.to_vec(),
+            is_chain_block: true,
+            children_hashes: vec![],
+            difficulty: 2.5,
+            is_header_only: false,
         }
     }
-}
 
+    async fn indexer_with(blocks: &[Hash]) -> (TempDir, Arc<Database>, BlockIndexer) {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(Database::new(&temp_dir.path().join("test.db")).await.unwrap());
+        db.migrate().await.unwrap();
+        let indexer = BlockIndexer::new(db.clone());
+        for block in blocks {
+            indexer.index(&Block::new(Header::from_precomputed_hash(*block, vec![]), vec![])).await.unwrap();
+        }
+        (temp_dir, db, indexer)
+    }
+
+    async fn mergeset(db: &Database, block: Hash) -> Vec<(String, bool)> {
+        sqlx::query_as("SELECT merged_hash, is_blue FROM block_mergesets WHERE block_hash = $1 ORDER BY position")
+            .bind(block.to_string())
+            .fetch_all(db.pool())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verbose_data_is_persisted() {
+        let (_dir, db, indexer) = indexer_with(&[hash(3)]).await;
+        indexer.index_verbose(&verbose_data(hash(3), hash(1), &[hash(1), hash(2)], &[hash(4)])).await.unwrap();
+        // Indexing again replaces the mergeset rather than adding to it
+        indexer.index_verbose(&verbose_data(hash(3), hash(1), &[hash(1), hash(2)], &[hash(4)])).await.unwrap();
+
+        let (selected_parent, is_chain_block, difficulty): (String, bool, f64) =
+            sqlx::query_as("SELECT selected_parent, is_chain_block, difficulty FROM blocks WHERE hash = $1")
+                .bind(hash(3).to_string())
+                .fetch_one(db.pool())
+                .await
+                .unwrap();
+        assert_eq!((selected_parent, is_chain_block, difficulty), (hash(1).to_string(), true, 2.5));
+        assert_eq!(
+            mergeset(&db, hash(3)).await,
+            vec![(hash(1).to_string(), true), (hash(2).to_string(), true), (hash(4).to_string(), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_flags_follow_chain_changes() {
+        let (_dir, db, indexer) = indexer_with(&[hash(1), hash(2)]).await;
+        indexer.index_verbose(&verbose_data(hash(1), hash(1), &[], &[])).await.unwrap();
+
+        let changes = VirtualChainChanged {
+            removed_chain_block_hashes: vec![hash(1)],
+            added_chain_block_hashes: vec![hash(2)],
+            accepted_transaction_ids: vec![],
+            removed_transaction_ids: vec![],
+        };
+        indexer.apply_chain_changes(&changes).await.unwrap();
+        let chain: Vec<(String,)> = sqlx::query_as("SELECT hash FROM blocks WHERE is_chain_block").fetch_all(db.pool()).await.unwrap();
+        assert_eq!(chain, vec![(hash(2).to_string(),)]);
+    }
+}
@@ -4,6 +4,10 @@ pub mod service;
 pub mod block_indexer;
 pub mod transaction_indexer;
 pub mod address_indexer;
+pub mod priority;
+pub mod throttle;
 
 pub use service::IndexerService;
+pub use priority::PriorityQueue;
+pub use throttle::RequestThrottle;
 
@@ -0,0 +1,53 @@
+//! Blocks to index ahead of the polling loop
+//!
+//! The API pushes blocks here when a visitor asks for data the node has but the
+//! index has not reached yet. The indexer drains the queue before each poll.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use consensus_core::Hash;
+
+/// Most blocks waiting for priority indexing; later pushes are dropped
+pub const PRIORITY_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Default)]
+pub struct PriorityQueue {
+    hashes: Mutex<VecDeque<Hash>>,
+    notify: Notify,
+}
+
+impl PriorityQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a block and wake the indexer. Returns false if it was already queued or the queue is full.
+    pub fn push(&self, hash: Hash) -> bool {
+        let mut hashes = self.hashes.lock().unwrap();
+        if hashes.len() >= PRIORITY_QUEUE_CAPACITY || hashes.contains(&hash) {
+            return false;
+        }
+        hashes.push_back(hash);
+        drop(hashes);
+        self.notify.notify_one();
+        true
+    }
+
+    pub fn pop(&self) -> Option<Hash> {
+        self.hashes.lock().unwrap().pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Resolves after the next push
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+}
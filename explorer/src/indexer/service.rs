@@ -1,15 +1,19 @@
 //! Main indexer service
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::sync::Arc;
+use futures_util::StreamExt;
 use tokio::sync::broadcast;
 use tokio::time::{interval, Duration};
 use tracing::{info, error};
 use consensus_core::{block::Block, Hash};
 use crate::database::Database;
-use crate::indexer::{block_indexer::BlockIndexer, transaction_indexer::TransactionIndexer, address_indexer::AddressIndexer};
+use crate::indexer::{priority::PriorityQueue, throttle::RequestThrottle, block_indexer::BlockIndexer, transaction_indexer::TransactionIndexer, address_indexer::AddressIndexer};
 use crate::error::Result;
-use rpc_core::RpcApi;
+use rpc_core::{RpcApi, RpcBlockVerboseData};
+
+/// How often the node is polled for new blocks
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct IndexerService {
     database: Arc<Database>,
@@ -17,6 +21,9 @@ pub struct IndexerService {
     tx_indexer: TransactionIndexer,
     address_indexer: AddressIndexer,
     block_sender: broadcast::Sender<Block>,
+    poll_interval: Duration,
+    priority: Arc<PriorityQueue>,
+    throttle: RequestThrottle,
 }
 
 impl IndexerService {
@@ -29,67 +36,126 @@ impl IndexerService {
             tx_indexer: TransactionIndexer::new(database.clone()),
             address_indexer: AddressIndexer::new(database.clone()),
             block_sender,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            priority: Arc::new(PriorityQueue::new()),
+            throttle: RequestThrottle::default(),
         }
     }
     
     pub fn block_sender(&self) -> broadcast::Sender<Block> {
         self.block_sender.clone()
     }
+
+    /// Blocks the API wants indexed before the next poll
+    pub fn priority_queue(&self) -> Arc<PriorityQueue> {
+        self.priority.clone()
+    }
     
+    /// Poll the node for new blocks every `poll_interval`
+    pub fn with_poll_interval(self, poll_interval: Duration) -> Self {
+        Self { poll_interval, ..self }
+    }
+
+    /// Keep at most `max_in_flight` requests to the node pending, starting at
+    /// most `requests_per_second` a second (0 for no rate limit)
+    pub fn with_fetch_limits(self, max_in_flight: usize, requests_per_second: u32) -> Self {
+        Self { throttle: RequestThrottle::new(max_in_flight, requests_per_second), ..self }
+    }
+
     pub async fn start(&self, coordinator: Arc<dyn RpcApi>) -> Result<()> {
         info!("Starting indexer service");
 
         // Start indexing loop
-        let mut interval = interval(Duration::from_secs(5));
-        let mut last_processed_height: i64 = -1;
+        let mut interval = interval(self.poll_interval);
+        // Last indexed block; the node pages blocks in topological order after it
+        let mut low_hash: Option<Hash> = None;
 
         loop {
-            interval.tick().await;
-
-            // Get current block count from coordinator
-            let block_count = match coordinator.get_block_count().await {
-                Ok(count) => count as i64,
-                Err(e) => {
-                    error!("Failed to get block count: {:?}", e);
-                    continue;
-                }
-            };
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.priority.notified() => {}
+            }
 
-            // Process new blocks
-            if block_count > last_processed_height + 1 {
-                for height in (last_processed_height + 1)..block_count {
-                    if let Err(e) = self.process_block_at_height(height, &coordinator).await {
-                        error!("Failed to process block at height {}: {:?}", height, e);
+            self.index_priority_blocks(coordinator.as_ref()).await;
+
+            loop {
+                let page = match self.throttle.run(coordinator.get_blocks(low_hash, true, true, true)).await {
+                    Ok(page) => page,
+                    Err(e) => {
+                        error!("Failed to get blocks after {:?}: {:?}", low_hash, e);
                         break;
                     }
-                    last_processed_height = height;
+                };
+                if page.blocks.is_empty() {
+                    break;
                 }
+
+                let mut verbose_data: HashMap<Hash, RpcBlockVerboseData> =
+                    page.block_verbose_data.into_iter().map(|data| (data.hash, data)).collect();
+                for block in page.blocks {
+                    let hash = block.header.hash;
+                    match self.index_block(&block, verbose_data.remove(&hash).as_ref()).await {
+                        Ok(()) => {
+                            // Broadcast block event
+                            let _ = self.block_sender.send(block);
+                        }
+                        Err(e) => error!("Failed to index block {}: {:?}", hash, e),
+                    }
+                    low_hash = Some(hash);
+                }
+            }
+
+            if let Err(e) = self.update_ledger(coordinator.as_ref()).await {
+                error!("Failed to update the address ledger: {:?}", e);
             }
         }
     }
-    
-    async fn process_block_at_height(&self, height: i64, coordinator: &Arc<dyn RpcApi>) -> Result<()> {
-        // Get block by height using the RPC method
-        if let Ok(block) = coordinator.get_block_by_height(height as u64).await {
-            // Index the block
-            if let Err(e) = self.index_block(block).await {
-                tracing::warn!("Failed to index block at height {}: {:?}", height, e);
+
+    /// Index the blocks queued by the API, fetching up to the in-flight limit at once
+    async fn index_priority_blocks(&self, coordinator: &dyn RpcApi) {
+        let hashes: Vec<Hash> = std::iter::from_fn(|| self.priority.pop()).collect();
+        let mut fetched = futures_util::stream::iter(hashes)
+            .map(|hash| async move { (hash, self.throttle.run(coordinator.get_block_verbose(hash)).await) })
+            .buffer_unordered(self.throttle.max_in_flight());
+        while let Some((hash, result)) = fetched.next().await {
+            match result {
+                Ok(verbose) => {
+                    let mut block = verbose.block;
+                    let verbose_data = block.verbose_data.take();
+                    let indexed = match Block::try_from(block) {
+                        Ok(block) => self.index_block(&block, verbose_data.as_ref()).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = indexed {
+                        error!("Failed to index block {}: {:?}", hash, e);
+                    }
+                }
+                Err(e) => error!("Failed to get block {}: {:?}", hash, e),
             }
-        } else {
-            tracing::warn!("Failed to get block at height {}", height);
         }
-        Ok(())
     }
-    
-    async fn index_block(&self, block: Block) -> Result<()> {
+
+    /// Move the address ledger to the node's selected chain, from the chain
+    /// block it is up to; retried on the next poll if it fails
+    async fn update_ledger(&self, coordinator: &dyn RpcApi) -> Result<()> {
+        let tip = self.address_indexer.ledger_tip().await?;
+        let changes = self.throttle.run(coordinator.get_virtual_chain_from_block(tip, true)).await?;
+        self.address_indexer.apply_chain_changes(&changes).await?;
+        self.block_indexer.apply_chain_changes(&changes).await
+    }
+
+    async fn index_block(&self, block: &Block, verbose_data: Option<&RpcBlockVerboseData>) -> Result<()> {
         info!("Indexing block: {}", block.header.hash);
         
         // Index block
-        self.block_indexer.index(&block).await?;
+        self.block_indexer.index(block).await?;
+        if let Some(verbose_data) = verbose_data {
+            self.block_indexer.index_verbose(verbose_data).await?;
+        }
         
         // Index transactions
         for tx in &block.transactions {
-            self.tx_indexer.index(tx, Some(&block)).await?;
+            self.tx_indexer.index(tx, Some(block)).await?;
         }
         
         // Update addresses
@@ -97,10 +163,127 @@ impl IndexerService {
             self.address_indexer.update_from_transaction(tx).await?;
         }
         
-        // Broadcast block event
-        let _ = self.block_sender.send(block);
-        
         Ok(())
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
+    use consensus_core::tx::{Transaction, TransactionOutpoint, UtxoEntry};
+    use rpc_core::{model::*, RpcError, RpcTransactionVerbose};
+    use tempfile::{tempdir, TempDir};
+    use tokio::time::Instant;
+
+    /// Node taking a while to answer getBlockVerbose, counting requests in flight. Every other method fails.
+    #[derive(Default)]
+    struct MockRpc {
+        calls: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    fn unsupported<T>() -> std::result::Result<T, RpcError> {
+        Err(RpcError::Internal("not supported by mock".into()))
+    }
+
+    #[async_trait]
+    impl RpcApi for MockRpc {
+        async fn get_block_verbose(&self, _: Hash) -> std::result::Result<RpcBlockVerbose, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            unsupported()
+        }
+
+        async fn get_info(&self) -> std::result::Result<GetInfoResponse, RpcError> { unsupported() }
+        async fn set_log_level(&self, _: String, _: String) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn get_block_count(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_block(&self, _: Hash) -> std::result::Result<Block, RpcError> { unsupported() }
+        async fn get_block_status(&self, _: Hash) -> std::result::Result<BlockStatusInfo, RpcError> { unsupported() }
+        async fn get_block_dag_info(&self) -> std::result::Result<BlockDagInfo, RpcError> { unsupported() }
+        async fn get_blocks(&self, _: Option<Hash>, _: bool, _: bool, _: bool) -> std::result::Result<GetBlocksResponse, RpcError> { unsupported() }
+        async fn get_peer_info(&self) -> std::result::Result<Vec<PeerInfo>, RpcError> { unsupported() }
+        async fn get_connection_count(&self) -> std::result::Result<ConnectionCounts, RpcError> { unsupported() }
+        async fn add_peer(&self, _: String, _: bool) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn remove_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn ban_peer(&self, _: String, _: u64) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn unban_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn get_banned_peers(&self) -> std::result::Result<Vec<BannedPeer>, RpcError> { unsupported() }
+        async fn submit_block(&self, _: Block) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn send_raw_transaction(&self, _: String, _: bool) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_transaction_hex(&self, _: Hash) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn get_mempool_info(&self) -> std::result::Result<MempoolInfo, RpcError> { unsupported() }
+        async fn get_fee_estimate(&self) -> std::result::Result<FeeEstimate, RpcError> { unsupported() }
+        async fn get_mempool_entries(&self, _: bool, _: bool) -> std::result::Result<Vec<MempoolEntry>, RpcError> { unsupported() }
+        async fn flush_mempool(&self) -> std::result::Result<usize, RpcError> { unsupported() }
+        async fn get_block_template(&self, _: String, _: Option<String>) -> std::result::Result<BlockTemplate, RpcError> { unsupported() }
+        async fn submit_block_hex(&self, _: String) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn submit_block_with_coinbase(&self, _: Hash, _: u64, _: Vec<u8>, _: Option<CoinbaseOverrides>) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_mining_info(&self) -> std::result::Result<MiningInfo, RpcError> { unsupported() }
+        async fn estimate_network_hashes_per_second(&self, _: u32, _: Option<Hash>) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_balances(&self) -> std::result::Result<GetBalancesResponse, RpcError> { unsupported() }
+        async fn get_new_address(&self) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn send_to_address(&self, _: String, _: u64) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_balance_by_address(&self, _: String) -> std::result::Result<AddressBalance, RpcError> { unsupported() }
+        async fn get_utxos_by_address(&self, _: String) -> std::result::Result<Vec<RpcUtxoByAddress>, RpcError> { unsupported() }
+        async fn get_virtual_utxo_entry(&self, _: TransactionOutpoint) -> std::result::Result<Option<UtxoEntry>, RpcError> { unsupported() }
+        async fn get_virtual_selected_parent_blue_score(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_coin_supply(&self) -> std::result::Result<CoinSupply, RpcError> { unsupported() }
+        async fn get_block_by_height(&self, _: u64) -> std::result::Result<Block, RpcError> { unsupported() }
+        async fn get_transaction(&self, _: Hash) -> std::result::Result<Transaction, RpcError> { unsupported() }
+        async fn get_transaction_verbose(&self, _: Hash) -> std::result::Result<RpcTransactionVerbose, RpcError> { unsupported() }
+        async fn get_recent_blocks(&self, _: usize) -> std::result::Result<Vec<Block>, RpcError> { unsupported() }
+        async fn get_dag_tips(&self) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_block_children(&self, _: Hash) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_headers(&self, _: Hash, _: usize) -> std::result::Result<Vec<String>, RpcError> { unsupported() }
+        async fn get_virtual_chain_from_block(&self, _: Option<Hash>, _: bool) -> std::result::Result<VirtualChainChanged, RpcError> { unsupported() }
+        async fn get_block_hex(&self, _: Hash) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn decode_block(&self, _: String) -> std::result::Result<RpcBlock, RpcError> { unsupported() }
+        async fn decode_transaction(&self, _: String) -> std::result::Result<RpcTransaction, RpcError> { unsupported() }
+    }
+
+    /// Service with `blocks` blocks queued for priority indexing
+    async fn service_with_queued(blocks: u64) -> (TempDir, IndexerService) {
+        let dir = tempdir().unwrap();
+        let database = Database::new(&dir.path().join("test.db")).await.unwrap();
+        database.migrate().await.unwrap();
+        let service = IndexerService::new(Arc::new(database));
+        for n in 1..=blocks {
+            assert!(service.priority.push(Hash::from_u64_word(n)));
+        }
+        (dir, service)
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_stay_within_the_limit() {
+        let (_dir, service) = service_with_queued(12).await;
+        let service = service.with_fetch_limits(3, 0);
+        let node = MockRpc::default();
+        service.index_priority_blocks(&node).await;
+
+        assert_eq!(node.calls.load(Ordering::SeqCst), 12);
+        let max_in_flight = node.max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight <= 3, "{} requests in flight", max_in_flight);
+        assert!(max_in_flight > 1, "requests were not sent concurrently");
+        assert!(service.priority.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_paced() {
+        let (_dir, service) = service_with_queued(10).await;
+        let service = service.with_fetch_limits(8, 50);
+        let node = MockRpc::default();
+        let started = Instant::now();
+        service.index_priority_blocks(&node).await;
+
+        // Ten requests at one every 20ms
+        assert_eq!(node.calls.load(Ordering::SeqCst), 10);
+        assert!(started.elapsed() >= Duration::from_millis(180), "took {:?}", started.elapsed());
+    }
+}
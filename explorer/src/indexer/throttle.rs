@@ -0,0 +1,61 @@
+//! Limits on the indexer's requests to the node
+//!
+//! A catching-up indexer would otherwise ask for blocks as fast as the node
+//! answers, slowing the node down for everything else it serves.
+
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::Semaphore;
+use tokio::time::{Duration, Instant};
+
+/// Most indexer requests to the node in flight at once
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// Most indexer requests started per second
+pub const DEFAULT_REQUESTS_PER_SECOND: u32 = 50;
+
+pub struct RequestThrottle {
+    permits: Semaphore,
+    max_in_flight: usize,
+    /// Time between request starts, zero when the rate is not limited
+    spacing: Duration,
+    next_start: Mutex<Instant>,
+}
+
+impl RequestThrottle {
+    /// At most `max_in_flight` requests at once, started at most
+    /// `requests_per_second` a second; 0 leaves the rate unlimited
+    pub fn new(max_in_flight: usize, requests_per_second: u32) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        let spacing = match requests_per_second {
+            0 => Duration::ZERO,
+            rate => Duration::from_secs(1) / rate,
+        };
+        Self { permits: Semaphore::new(max_in_flight), max_in_flight, spacing, next_start: Mutex::new(Instant::now()) }
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// Send `request` once one of the in-flight slots is free and the rate allows
+    pub async fn run<F: Future>(&self, request: F) -> F::Output {
+        let _permit = self.permits.acquire().await.expect("request permits are never closed");
+        if !self.spacing.is_zero() {
+            let start = {
+                let mut next_start = self.next_start.lock().unwrap();
+                let start = (*next_start).max(Instant::now());
+                *next_start = start + self.spacing;
+                start
+            };
+            tokio::time::sleep_until(start).await;
+        }
+        request.await
+    }
+}
+
+impl Default for RequestThrottle {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IN_FLIGHT, DEFAULT_REQUESTS_PER_SECOND)
+    }
+}
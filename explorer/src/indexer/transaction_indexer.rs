@@ -4,6 +4,7 @@ use std::sync::Arc;
 use consensus_core::{block::Block, tx::Transaction};
 use crate::database::Database;
 use crate::error::Result;
+use crate::models::PAYLOAD_PREVIEW_BYTES;
 
 pub struct TransactionIndexer {
     pool: Arc<sqlx::SqlitePool>,
@@ -54,7 +55,7 @@ impl TransactionIndexer {
         .bind(tx.lock_time as i64)
         .bind(tx.inputs.len() as i32)
         .bind(tx.outputs.len() as i32)
-        .bind(self.calculate_tx_size(tx) as i32)
+        .bind(transaction_size(tx) as i32)
         .bind(&fee)
         .bind(value as i64)
         .bind(chrono::Utc::now().timestamp() as i64)
@@ -73,6 +74,34 @@ impl TransactionIndexer {
             self.index_output(&hash, idx, output).await?;
         }
 
+        if !tx.payload.is_empty() {
+            self.index_payload(&hash, &tx.payload).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn index_payload(&self, tx_hash: &str, payload: &[u8]) -> Result<()> {
+        let utf8 = std::str::from_utf8(payload).ok();
+        let (hex_preview, utf8_preview) = payload_preview(payload);
+
+        sqlx::query(
+            r#"
+            INSERT INTO transaction_payloads (
+                tx_hash, size, payload, utf8, hex_preview, utf8_preview
+            ) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (tx_hash) DO NOTHING
+            "#,
+        )
+        .bind(tx_hash)
+        .bind(payload.len() as i64)
+        .bind(payload)
+        .bind(utf8)
+        .bind(hex_preview)
+        .bind(utf8_preview)
+        .execute(&*self.pool)
+        .await?;
+
         Ok(())
     }
     
@@ -80,9 +109,9 @@ impl TransactionIndexer {
         sqlx::query(
             r#"
             INSERT INTO transaction_inputs (
-                tx_hash, index, previous_outpoint_hash, previous_outpoint_index, sequence
+                tx_hash, "index", previous_outpoint_hash, previous_outpoint_index, sequence
             ) VALUES ($1, $2, $3, $4, $5)
-            ON CONFLICT (tx_hash, index) DO NOTHING
+            ON CONFLICT (tx_hash, "index") DO NOTHING
             "#,
         )
         .bind(tx_hash)
@@ -98,7 +127,7 @@ impl TransactionIndexer {
             r#"
             UPDATE transaction_outputs
             SET is_spent = TRUE, spent_by_tx_hash = $1, spent_by_input_index = $2
-            WHERE tx_hash = $3 AND index = $4
+            WHERE tx_hash = $3 AND "index" = $4
             "#,
         )
         .bind(tx_hash)
@@ -118,10 +147,10 @@ impl TransactionIndexer {
         sqlx::query(
             r#"
             INSERT INTO transaction_outputs (
-                tx_hash, index, value, script_public_key_version,
+                tx_hash, "index", value, script_public_key_version,
                 script_public_key_script, address
             ) VALUES ($1, $2, $3, $4, $5, $6)
-            ON CONFLICT (tx_hash, index) DO NOTHING
+            ON CONFLICT (tx_hash, "index") DO NOTHING
             "#,
         )
         .bind(tx_hash)
@@ -153,17 +182,7 @@ impl TransactionIndexer {
     }
     
     fn extract_address(&self, script_pub_key: &consensus_core::tx::ScriptPublicKey) -> Option<String> {
-        // Simplified address extraction
-        // In production, this should properly decode the script
-        if script_pub_key.script().is_empty() {
-            None
-        } else {
-            Some(hex::encode(&script_pub_key.script()))
-        }
-    }
-    
-    fn calculate_tx_size(&self, tx: &Transaction) -> usize {
-        std::mem::size_of_val(tx)
+        super::address_indexer::script_address(script_pub_key)
     }
     
     fn get_block_height(&self, hash: &str) -> Option<i64> {
@@ -173,3 +192,89 @@ impl TransactionIndexer {
     }
 }
 
+pub(crate) fn transaction_size(tx: &Transaction) -> usize {
+    std::mem::size_of_val(tx)
+}
+
+/// Hex of the payload's first [`PAYLOAD_PREVIEW_BYTES`] bytes, and the same bytes as text when the payload is UTF-8
+pub(crate) fn payload_preview(payload: &[u8]) -> (String, Option<&str>) {
+    let preview = &payload[..payload.len().min(PAYLOAD_PREVIEW_BYTES)];
+    // Cut the text where the preview bytes end, backing off to a character boundary
+    let utf8_preview = std::str::from_utf8(payload).ok().map(|text| {
+        let end = (0..=preview.len()).rev().find(|&end| text.is_char_boundary(end)).unwrap_or(0);
+        &text[..end]
+    });
+    (hex::encode(preview), utf8_preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::queries::{PayloadQueries, TransactionQueries};
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput};
+    use consensus_core::Hash;
+    use tempfile::tempdir;
+
+    fn with_payload(n: u8, payload: &[u8]) -> Transaction {
+        Transaction::new(
+            1,
+            vec![TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([n; 32]), 0), vec![], 0, 1)],
+            vec![TransactionOutput::new(1_000, ScriptPublicKey::default())],
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            payload.to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_payloads_are_indexed_and_searchable() {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(Database::new(&temp_dir.path().join("test.db")).await.unwrap());
+        db.migrate().await.unwrap();
+        let indexer = TransactionIndexer::new(db.clone());
+        let pool = Arc::new(db.pool().clone());
+
+        let binary = with_payload(1, &[0x6a, 0x01, 0xff, 0xfe]);
+        let other_binary = with_payload(2, &[0x6a, 0x02]);
+        let long_text = "hello, jio! ".repeat(10);
+        let text = with_payload(3, long_text.as_bytes());
+        let empty = with_payload(4, &[]);
+        for tx in [&binary, &other_binary, &text, &empty] {
+            indexer.index(tx, None).await.unwrap();
+        }
+
+        // Listings carry previews
+        let summary = TransactionQueries::get_by_hash(pool.clone(), &binary.hash().to_string()).await.unwrap().unwrap();
+        assert_eq!((summary.payload_size, summary.payload_preview.as_deref(), summary.payload_utf8), (4, Some("6a01fffe"), None));
+        let summary = TransactionQueries::get_by_hash(pool.clone(), &text.hash().to_string()).await.unwrap().unwrap();
+        assert_eq!(summary.payload_size, 120);
+        assert_eq!(summary.payload_utf8.as_deref(), Some(&long_text[..PAYLOAD_PREVIEW_BYTES]));
+        let summary = TransactionQueries::get_by_hash(pool.clone(), &empty.hash().to_string()).await.unwrap().unwrap();
+        assert_eq!((summary.payload_size, summary.payload_preview), (0, None));
+
+        // Prefix search matches bytes, including a prefix ending in 0xff
+        let hashes = |found: Vec<crate::models::PayloadSummary>| found.into_iter().map(|p| p.tx_hash).collect::<Vec<_>>();
+        let mut found = hashes(PayloadQueries::search_prefix(pool.clone(), &[0x6a], 10).await.unwrap());
+        found.sort();
+        let mut expected = vec![binary.hash().to_string(), other_binary.hash().to_string()];
+        expected.sort();
+        assert_eq!(found, expected);
+        assert_eq!(hashes(PayloadQueries::search_prefix(pool.clone(), &[0x6a, 0x01, 0xff], 10).await.unwrap()), vec![binary.hash().to_string()]);
+        assert!(PayloadQueries::search_prefix(pool.clone(), &[0x6b], 10).await.unwrap().is_empty());
+        assert_eq!(PayloadQueries::search_prefix(pool.clone(), &[0x6a], 1).await.unwrap().len(), 1);
+
+        // Text search finds substrings of UTF-8 payloads only
+        let found = PayloadQueries::search_utf8(pool.clone(), "jio!", 10).await.unwrap();
+        assert_eq!(hashes(found.clone()), vec![text.hash().to_string()]);
+        assert!(found[0].truncated);
+        assert!(PayloadQueries::search_utf8(pool.clone(), "JIO!", 10).await.unwrap().is_empty());
+
+        // The full payload has its own lookup
+        let payload = PayloadQueries::get(pool.clone(), &text.hash().to_string()).await.unwrap().unwrap();
+        assert_eq!(payload.utf8.as_deref(), Some(long_text.as_str()));
+        assert_eq!(payload.hex, hex::encode(long_text.as_bytes()));
+        assert!(PayloadQueries::get(pool, &empty.hash().to_string()).await.unwrap().is_none());
+    }
+}
@@ -9,6 +9,8 @@ pub mod database;
 pub mod models;
 pub mod websocket;
 pub mod cache;
+pub mod mempool;
+pub mod lookup;
 pub mod error;
 
 pub use error::{ExplorerError, Result};
@@ -16,4 +18,3 @@ pub use error::{ExplorerError, Result};
 // Type alias for database pool
 pub type DbPool = sqlx::SqlitePool;
 
-pub mod rpc_client;
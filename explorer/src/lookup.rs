@@ -0,0 +1,440 @@
+//! Block and transaction lookups with a node fallback
+//!
+//! The index trails the node, so a hash missing from the database may simply not
+//! be indexed yet. On a miss the node is asked directly: its answer is returned
+//! marked `"indexed": false` and the block is queued for priority indexing.
+//! Node requests are capped by a small semaphore, and hashes the node does not
+//! know are remembered for a while so repeated lookups of junk stay local.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use consensus_core::{block::Block, Hash};
+use rpc_core::{RpcApi, RpcError, RpcTransactionVerbose};
+use crate::database::Database;
+use crate::database::queries::{BlockQueries, TransactionQueries};
+use crate::error::{ExplorerError, Result};
+use crate::indexer::block_indexer::{block_size, coinbase_value};
+use crate::indexer::transaction_indexer::{payload_preview, transaction_size};
+use crate::indexer::PriorityQueue;
+use crate::models::{BlockSummary, TransactionSummary};
+
+/// Most node lookups in flight at once
+pub const DEFAULT_NODE_LOOKUP_CONCURRENCY: usize = 4;
+
+/// How long a hash the node does not know is answered without asking again
+pub const DEFAULT_MISS_TTL: Duration = Duration::from_secs(30);
+
+/// Most hashes remembered as unknown
+pub const MISS_CACHE_CAPACITY: usize = 1024;
+
+/// Code the node answers with for an unknown block or transaction
+const NOT_FOUND_CODE: i32 = -5;
+
+/// What a hash was looked up as; block and transaction misses are cached apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Block,
+    Transaction,
+}
+
+/// A lookup result and whether it came from the index or straight from the node
+#[derive(Debug, Clone, Serialize)]
+pub struct Indexed<T> {
+    #[serde(flatten)]
+    pub data: T,
+    pub indexed: bool,
+}
+
+pub struct Lookup {
+    database: Arc<Database>,
+    rpc_client: Arc<dyn RpcApi>,
+    priority: Arc<PriorityQueue>,
+    permits: Semaphore,
+    miss_ttl: Duration,
+    /// Hashes the node did not know, with when they may be asked about again
+    misses: Mutex<HashMap<(Kind, Hash), Instant>>,
+}
+
+impl Lookup {
+    pub fn new(database: Arc<Database>, rpc_client: Arc<dyn RpcApi>, priority: Arc<PriorityQueue>) -> Self {
+        Self {
+            database,
+            rpc_client,
+            priority,
+            permits: Semaphore::new(DEFAULT_NODE_LOOKUP_CONCURRENCY),
+            miss_ttl: DEFAULT_MISS_TTL,
+            misses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Allow at most `concurrency` node lookups at once
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self { permits: Semaphore::new(concurrency.max(1)), ..self }
+    }
+
+    /// Remember hashes the node does not know for `miss_ttl`
+    pub fn with_miss_ttl(self, miss_ttl: Duration) -> Self {
+        Self { miss_ttl, ..self }
+    }
+
+    /// Block by hash from the index, else from the node. `None` if neither knows it.
+    pub async fn block(&self, hash: &str) -> Result<Option<Indexed<BlockSummary>>> {
+        let pool = Arc::new(self.database.pool().clone());
+        if let Some(block) = BlockQueries::get_by_hash(pool, hash).await? {
+            return Ok(Some(Indexed { data: block, indexed: true }));
+        }
+
+        let hash = parse_hash(hash)?;
+        let Some(block) = self.ask_node(Kind::Block, hash, |rpc| async move { rpc.get_block(hash).await }).await? else {
+            return Ok(None);
+        };
+        self.priority.push(hash);
+        Ok(Some(Indexed { data: block_summary(&block), indexed: false }))
+    }
+
+    /// Transaction by hash from the index, else from the node. `None` if neither knows it.
+    pub async fn transaction(&self, hash: &str) -> Result<Option<Indexed<TransactionSummary>>> {
+        let pool = Arc::new(self.database.pool().clone());
+        if let Some(tx) = TransactionQueries::get_by_hash(pool, hash).await? {
+            return Ok(Some(Indexed { data: tx, indexed: true }));
+        }
+
+        let hash = parse_hash(hash)?;
+        let Some(verbose) = self.ask_node(Kind::Transaction, hash, |rpc| async move { rpc.get_transaction_verbose(hash).await }).await? else {
+            return Ok(None);
+        };
+        // Mempool transactions are indexed with the block that includes them
+        if let Some(block_hash) = verbose.block_hash {
+            self.priority.push(block_hash);
+        }
+        Ok(Some(Indexed { data: transaction_summary(&verbose), indexed: false }))
+    }
+
+    /// Hex of the canonical encoding of a transaction, which only the node keeps.
+    /// `None` if the node doesn't know it.
+    pub async fn raw_transaction(&self, hash: &str) -> Result<Option<String>> {
+        let hash = parse_hash(hash)?;
+        self.ask_node(Kind::Transaction, hash, |rpc| async move { rpc.get_transaction_hex(hash).await }).await
+    }
+
+    /// Run a node request under the concurrency limit, caching "not found" answers
+    async fn ask_node<T, F, Fut>(&self, kind: Kind, hash: Hash, request: F) -> Result<Option<T>>
+    where
+        F: FnOnce(Arc<dyn RpcApi>) -> Fut,
+        Fut: std::future::Future<Output = std::result::Result<T, RpcError>>,
+    {
+        let key = (kind, hash);
+        if self.is_known_miss(&key, Instant::now()) {
+            return Ok(None);
+        }
+
+        let _permit = self.permits.acquire().await.map_err(|e| ExplorerError::Internal(e.to_string()))?;
+        match request(self.rpc_client.clone()).await {
+            Ok(found) => Ok(Some(found)),
+            Err(RpcError::Rpc { code: NOT_FOUND_CODE, .. }) => {
+                self.record_miss(key, Instant::now());
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn is_known_miss(&self, key: &(Kind, Hash), now: Instant) -> bool {
+        let mut misses = self.misses.lock().unwrap();
+        match misses.get(key) {
+            Some(&expires) if expires > now => true,
+            Some(_) => {
+                misses.remove(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn record_miss(&self, key: (Kind, Hash), now: Instant) {
+        let mut misses = self.misses.lock().unwrap();
+        if misses.len() >= MISS_CACHE_CAPACITY {
+            misses.retain(|_, expires| *expires > now);
+        }
+        if misses.len() >= MISS_CACHE_CAPACITY {
+            // Forget the miss closest to expiring
+            if let Some(oldest) = misses.iter().min_by_key(|(_, expires)| **expires).map(|(key, _)| *key) {
+                misses.remove(&oldest);
+            }
+        }
+        misses.insert(key, now + self.miss_ttl);
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<Hash> {
+    let bytes = hex::decode(hash).map_err(|e| ExplorerError::InvalidInput(format!("Invalid hash {}: {}", hash, e)))?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| ExplorerError::InvalidInput(format!("Invalid hash {}: expected 32 bytes", hash)))?;
+    Ok(Hash::from(bytes))
+}
+
+/// The summary the indexer would store for `block`
+fn block_summary(block: &Block) -> BlockSummary {
+    BlockSummary {
+        hash: block.header.hash.to_string(),
+        height: block.header.daa_score as i64,
+        timestamp: block.header.timestamp as i64,
+        tx_count: block.transactions.len() as i32,
+        size: block_size(block) as i32,
+        coinbase_value: coinbase_value(block) as i64,
+        parent_count: block.header.parents_by_level.iter().map(Vec::len).sum::<usize>() as i32,
+        blue_score: block.header.blue_score as i64,
+        selected_parent: None,
+        is_chain_block: false,
+        difficulty: None,
+        is_header_only: false,
+    }
+}
+
+/// The summary the indexer would store for the node's view of a transaction
+fn transaction_summary(verbose: &RpcTransactionVerbose) -> TransactionSummary {
+    let tx = &verbose.transaction;
+    let (preview, utf8) = if tx.payload.is_empty() {
+        (None, None)
+    } else {
+        let (hex, utf8) = payload_preview(&tx.payload);
+        (Some(hex), utf8.map(str::to_string))
+    };
+    TransactionSummary {
+        hash: tx.hash().to_string(),
+        block_hash: verbose.block_hash.map(|hash| hash.to_string()),
+        block_height: None,
+        timestamp: chrono::Utc::now().timestamp(),
+        input_count: tx.inputs.len() as i32,
+        output_count: tx.outputs.len() as i32,
+        value: tx.outputs.iter().map(|out| out.value).sum::<u64>() as i64,
+        fee: verbose.fee.map(|fee| fee as i64),
+        size: transaction_size(tx) as i32,
+        is_coinbase: tx.is_coinbase(),
+        is_confirmed: verbose.block_hash.is_some(),
+        confirmation_count: verbose.confirmations as i32,
+        payload_size: tx.payload.len() as i64,
+        payload_preview: preview,
+        payload_utf8: utf8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
+    use consensus_core::header::Header;
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput, UtxoEntry};
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+    use rpc_core::model::*;
+    use tempfile::{tempdir, TempDir};
+    use crate::indexer::block_indexer::BlockIndexer;
+
+    /// Node that knows the blocks and transactions set by the test. Every other method fails.
+    #[derive(Default)]
+    struct MockRpc {
+        blocks: std::sync::Mutex<HashMap<Hash, Block>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockRpc {
+        fn add_block(&self, block: Block) {
+            self.blocks.lock().unwrap().insert(block.header.hash, block);
+        }
+    }
+
+    fn unsupported<T>() -> std::result::Result<T, RpcError> {
+        Err(RpcError::Internal("not supported by mock".into()))
+    }
+
+    fn not_found<T>() -> std::result::Result<T, RpcError> {
+        Err(RpcError::Rpc { code: NOT_FOUND_CODE, message: "not found".into() })
+    }
+
+    #[async_trait]
+    impl RpcApi for MockRpc {
+        async fn get_block(&self, hash: Hash) -> std::result::Result<Block, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.blocks.lock().unwrap().get(&hash).cloned().map_or_else(not_found, Ok)
+        }
+
+        async fn get_transaction_verbose(&self, hash: Hash) -> std::result::Result<RpcTransactionVerbose, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let blocks = self.blocks.lock().unwrap();
+            let found = blocks.values().find_map(|block| {
+                block.transactions.iter().find(|tx| tx.hash() == hash).map(|tx| (block.header.hash, tx.clone()))
+            });
+            match found {
+                Some((block_hash, transaction)) => Ok(RpcTransactionVerbose {
+                    transaction,
+                    block_hash: Some(block_hash),
+                    accepting_block_hash: Some(block_hash),
+                    accepting_blue_score: Some(1),
+                    confirmations: 1,
+                    fee: Some(0),
+                }),
+                None => not_found(),
+            }
+        }
+
+        async fn get_transaction_hex(&self, hash: Hash) -> std::result::Result<String, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let blocks = self.blocks.lock().unwrap();
+            let found = blocks.values().flat_map(|block| &block.transactions).find(|tx| tx.hash() == hash);
+            found.map_or_else(not_found, |tx| Ok(hex::encode(consensus_core::encoding::encode_transaction(tx))))
+        }
+
+        async fn get_info(&self) -> std::result::Result<GetInfoResponse, RpcError> { unsupported() }
+        async fn set_log_level(&self, _: String, _: String) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn get_block_count(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_block_verbose(&self, _: Hash) -> std::result::Result<RpcBlockVerbose, RpcError> { unsupported() }
+        async fn get_block_status(&self, _: Hash) -> std::result::Result<BlockStatusInfo, RpcError> { unsupported() }
+        async fn get_block_dag_info(&self) -> std::result::Result<BlockDagInfo, RpcError> { unsupported() }
+        async fn get_blocks(&self, _: Option<Hash>, _: bool, _: bool, _: bool) -> std::result::Result<GetBlocksResponse, RpcError> { unsupported() }
+        async fn get_peer_info(&self) -> std::result::Result<Vec<PeerInfo>, RpcError> { unsupported() }
+        async fn get_connection_count(&self) -> std::result::Result<ConnectionCounts, RpcError> { unsupported() }
+        async fn add_peer(&self, _: String, _: bool) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn remove_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn ban_peer(&self, _: String, _: u64) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn unban_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn get_banned_peers(&self) -> std::result::Result<Vec<BannedPeer>, RpcError> { unsupported() }
+        async fn submit_block(&self, _: Block) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn send_raw_transaction(&self, _: String, _: bool) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_mempool_info(&self) -> std::result::Result<MempoolInfo, RpcError> { unsupported() }
+        async fn get_fee_estimate(&self) -> std::result::Result<FeeEstimate, RpcError> { unsupported() }
+        async fn get_mempool_entries(&self, _: bool, _: bool) -> std::result::Result<Vec<MempoolEntry>, RpcError> { unsupported() }
+        async fn flush_mempool(&self) -> std::result::Result<usize, RpcError> { unsupported() }
+        async fn get_block_template(&self, _: String, _: Option<String>) -> std::result::Result<BlockTemplate, RpcError> { unsupported() }
+        async fn submit_block_hex(&self, _: String) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn submit_block_with_coinbase(&self, _: Hash, _: u64, _: Vec<u8>, _: Option<CoinbaseOverrides>) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_mining_info(&self) -> std::result::Result<MiningInfo, RpcError> { unsupported() }
+        async fn estimate_network_hashes_per_second(&self, _: u32, _: Option<Hash>) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_balances(&self) -> std::result::Result<GetBalancesResponse, RpcError> { unsupported() }
+        async fn get_new_address(&self) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn send_to_address(&self, _: String, _: u64) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_balance_by_address(&self, _: String) -> std::result::Result<AddressBalance, RpcError> { unsupported() }
+        async fn get_utxos_by_address(&self, _: String) -> std::result::Result<Vec<RpcUtxoByAddress>, RpcError> { unsupported() }
+        async fn get_virtual_utxo_entry(&self, _: TransactionOutpoint) -> std::result::Result<Option<UtxoEntry>, RpcError> { unsupported() }
+        async fn get_virtual_selected_parent_blue_score(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_coin_supply(&self) -> std::result::Result<CoinSupply, RpcError> { unsupported() }
+        async fn get_block_by_height(&self, _: u64) -> std::result::Result<Block, RpcError> { unsupported() }
+        async fn get_transaction(&self, _: Hash) -> std::result::Result<Transaction, RpcError> { unsupported() }
+        async fn get_recent_blocks(&self, _: usize) -> std::result::Result<Vec<Block>, RpcError> { unsupported() }
+        async fn get_dag_tips(&self) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_block_children(&self, _: Hash) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_headers(&self, _: Hash, _: usize) -> std::result::Result<Vec<String>, RpcError> { unsupported() }
+        async fn get_virtual_chain_from_block(&self, _: Option<Hash>, _: bool) -> std::result::Result<VirtualChainChanged, RpcError> { unsupported() }
+        async fn get_block_hex(&self, _: Hash) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn decode_block(&self, _: String) -> std::result::Result<RpcBlock, RpcError> { unsupported() }
+        async fn decode_transaction(&self, _: String) -> std::result::Result<RpcTransaction, RpcError> { unsupported() }
+    }
+
+    fn block(n: u8) -> Block {
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new(TransactionOutpoint::new(Hash::from([n; 32]), 0), vec![], 0, 1)],
+            vec![TransactionOutput::new(1_000, ScriptPublicKey::default())],
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            b"hello".to_vec(),
+        );
+        let header = Header::new_finalized(
+            1, vec![vec![ZERO_HASH]], ZERO_HASH, ZERO_HASH, ZERO_HASH, n as u64, 0x1f00ffff, 0, n as u64, BlueWorkType::from(0u64), n as u64, ZERO_HASH,
+        );
+        Block::new(header, vec![tx])
+    }
+
+    async fn lookup() -> (Lookup, Arc<Database>, Arc<MockRpc>, Arc<PriorityQueue>, TempDir) {
+        let temp_dir = tempdir().unwrap();
+        let database = Arc::new(Database::new(&temp_dir.path().join("test.db")).await.unwrap());
+        database.migrate().await.unwrap();
+        let rpc = Arc::new(MockRpc::default());
+        let priority = Arc::new(PriorityQueue::new());
+        (Lookup::new(database.clone(), rpc.clone(), priority.clone()), database, rpc, priority, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_indexed_block_is_served_from_the_database() {
+        let (lookup, database, rpc, priority, _dir) = lookup().await;
+        let block = block(1);
+        BlockIndexer::new(database).index(&block).await.unwrap();
+
+        let found = lookup.block(&block.header.hash.to_string()).await.unwrap().unwrap();
+        assert!(found.indexed);
+        assert_eq!(found.data.hash, block.header.hash.to_string());
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 0);
+        assert!(priority.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unindexed_data_falls_back_to_the_node() {
+        let (lookup, _database, rpc, priority, _dir) = lookup().await;
+        let block = block(2);
+        rpc.add_block(block.clone());
+
+        let found = lookup.block(&block.header.hash.to_string()).await.unwrap().unwrap();
+        assert!(!found.indexed);
+        assert_eq!(found.data.tx_count, 1);
+        assert_eq!(found.data.coinbase_value, 1_000);
+        assert_eq!(priority.pop(), Some(block.header.hash));
+
+        let tx = &block.transactions[0];
+        let found = lookup.transaction(&tx.hash().to_string()).await.unwrap().unwrap();
+        assert!(!found.indexed);
+        assert_eq!(found.data.block_hash, Some(block.header.hash.to_string()));
+        assert_eq!(found.data.payload_utf8.as_deref(), Some("hello"));
+        // The containing block is queued once
+        assert_eq!(priority.pop(), Some(block.header.hash));
+        assert!(priority.is_empty());
+
+        let json = serde_json::to_value(&found).unwrap();
+        assert_eq!(json["indexed"], false);
+        assert_eq!(json["hash"], tx.hash().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_hash_is_not_found_and_remembered() {
+        let (lookup, _database, rpc, priority, _dir) = lookup().await;
+        let unknown = Hash::from([9; 32]).to_string();
+
+        assert!(lookup.block(&unknown).await.unwrap().is_none());
+        assert!(lookup.transaction(&unknown).await.unwrap().is_none());
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 2);
+        // Repeated lookups are answered by the miss cache
+        assert!(lookup.block(&unknown).await.unwrap().is_none());
+        assert!(lookup.transaction(&unknown).await.unwrap().is_none());
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 2);
+        assert!(priority.is_empty());
+
+        // Once a miss expires the node is asked again
+        let lookup = lookup.with_miss_ttl(Duration::ZERO);
+        let other = Hash::from([8; 32]).to_string();
+        assert!(lookup.block(&other).await.unwrap().is_none());
+        assert!(lookup.block(&other).await.unwrap().is_none());
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 4);
+
+        assert!(matches!(lookup.block("not-a-hash").await, Err(ExplorerError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_raw_transaction_comes_from_the_node() {
+        let (lookup, database, rpc, _priority, _dir) = lookup().await;
+        let block = block(3);
+        BlockIndexer::new(database).index(&block).await.unwrap();
+        rpc.add_block(block.clone());
+
+        let tx = &block.transactions[0];
+        let raw = lookup.raw_transaction(&tx.hash().to_string()).await.unwrap().unwrap();
+        let decoded = consensus_core::encoding::decode_transaction(&hex::decode(&raw).unwrap()).unwrap();
+        assert_eq!(&decoded, tx);
+        // Indexed or not, the bytes are the node's
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 1);
+
+        assert!(lookup.raw_transaction(&Hash::from([9; 32]).to_string()).await.unwrap().is_none());
+    }
+}
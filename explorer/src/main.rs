@@ -6,9 +6,10 @@ use jio_explorer::{
     database::Database,
     api::ApiServer,
     indexer::IndexerService,
+    indexer::throttle::{DEFAULT_MAX_IN_FLIGHT, DEFAULT_REQUESTS_PER_SECOND},
     error::Result,
-    rpc_client::RpcClient,
 };
+use rpc_client::JioRpcClient;
 use rpc_core::RpcApi;
 
 #[tokio::main]
@@ -48,11 +49,21 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| "ws://localhost:16110".to_string());
     info!("Connecting to JIOPad daemon at: {}", jiopad_url);
 
-    let coordinator: Arc<dyn RpcApi> = Arc::new(RpcClient::new(&jiopad_url)
+    let coordinator: Arc<dyn RpcApi> = Arc::new(JioRpcClient::new(&jiopad_url)
         .map_err(|e| jio_explorer::error::ExplorerError::Internal(format!("Failed to create RPC client: {}", e)))?);
 
-    // Start indexer service
-    let indexer = IndexerService::new(database.clone());
+    // Start indexer service, pacing its requests so catching up doesn't slow the node down
+    let max_in_flight = std::env::var("INDEXER_MAX_IN_FLIGHT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_IN_FLIGHT);
+    let requests_per_second = std::env::var("INDEXER_REQUESTS_PER_SECOND")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_REQUESTS_PER_SECOND);
+    let indexer = IndexerService::new(database.clone())
+        .with_fetch_limits(max_in_flight, requests_per_second);
+    let priority = indexer.priority_queue();
     let coordinator_clone = Arc::clone(&coordinator);
     tokio::spawn(async move {
         if let Err(e) = indexer.start(coordinator_clone).await {
@@ -61,7 +72,8 @@ async fn main() -> Result<()> {
     });
 
     // Start API server
-    let api_server = ApiServer::new(database.clone(), coordinator.clone(), 3000);
+    let api_server = ApiServer::new(database.clone(), coordinator.clone(), 3000)
+        .with_priority_queue(priority);
     info!("Starting API server on port 3000");
     api_server.start().await?;
 
@@ -0,0 +1,406 @@
+//! Pending transaction view
+//!
+//! The explorer serves the node's mempool from a snapshot fetched with
+//! `get_mempool_entries`. The snapshot is reused until it is older than a short
+//! TTL, so page loads from many visitors cost the node one request per TTL.
+//! Transactions seen for the first time in a snapshot are pushed to WebSocket
+//! subscribers as `new_transaction` events.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use consensus_core::tx::Transaction;
+use rpc_core::{MempoolEntry, RpcApi};
+use crate::error::Result;
+use crate::indexer::address_indexer::script_address;
+use crate::models::PaginatedResponse;
+use crate::websocket::subscriptions::SubscriptionManager;
+
+/// How long a mempool snapshot is served before it is refetched
+pub const DEFAULT_MEMPOOL_TTL: Duration = Duration::from_secs(2);
+
+/// Upper bounds of the feerate histogram buckets, in sompi per gram of mass.
+/// Feerates above the last bound fall into a final open ended bucket.
+pub const FEERATE_BUCKETS: [f64; 7] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// A transaction waiting in the node's mempool
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingTransaction {
+    pub id: String,
+    pub mass: u64,
+    pub bytes: u64,
+    pub fee: u64,
+    /// Fee per gram of mass
+    pub feerate: f64,
+    /// Unix time in milliseconds the explorer first saw the transaction
+    pub first_seen: i64,
+    pub age_secs: i64,
+    /// Addresses paid by the transaction's outputs
+    pub addresses: Vec<String>,
+}
+
+impl PendingTransaction {
+    fn from_entry(entry: &MempoolEntry, first_seen: i64) -> Self {
+        let tx = &entry.transaction;
+        let mass = tx.calculate_mass();
+        let mut addresses: Vec<String> =
+            tx.outputs.iter().filter_map(|output| script_address(&output.script_public_key)).collect();
+        addresses.dedup();
+        Self {
+            id: tx.id().to_string(),
+            mass,
+            bytes: transaction_bytes(tx),
+            fee: entry.fee,
+            feerate: entry.fee as f64 / mass.max(1) as f64,
+            first_seen,
+            age_secs: 0,
+            addresses,
+        }
+    }
+}
+
+/// Pending transactions with a feerate in `[min_feerate, max_feerate)`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeerateBucket {
+    pub min_feerate: f64,
+    /// None for the last, open ended bucket
+    pub max_feerate: Option<f64>,
+    pub count: usize,
+    pub mass: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MempoolSummary {
+    pub count: usize,
+    pub bytes: u64,
+    pub mass: u64,
+    pub histogram: Vec<FeerateBucket>,
+}
+
+/// Bucket `transactions` by feerate using the bounds in [`FEERATE_BUCKETS`]
+pub fn feerate_histogram(transactions: &[PendingTransaction]) -> Vec<FeerateBucket> {
+    let mut lower = 0.0;
+    let mut histogram: Vec<FeerateBucket> = FEERATE_BUCKETS
+        .iter()
+        .map(|&upper| {
+            let bucket = FeerateBucket { min_feerate: lower, max_feerate: Some(upper), count: 0, mass: 0 };
+            lower = upper;
+            bucket
+        })
+        .collect();
+    histogram.push(FeerateBucket { min_feerate: lower, max_feerate: None, count: 0, mass: 0 });
+
+    for tx in transactions {
+        let index = FEERATE_BUCKETS.iter().position(|&upper| tx.feerate < upper).unwrap_or(FEERATE_BUCKETS.len());
+        histogram[index].count += 1;
+        histogram[index].mass += tx.mass;
+    }
+    histogram
+}
+
+/// Serialized size of `tx` with fixed width integers and length prefixed scripts
+fn transaction_bytes(tx: &Transaction) -> u64 {
+    let inputs: usize = tx.inputs.iter().map(|input| 32 + 4 + 8 + input.signature_script.len() + 8).sum();
+    let outputs: usize = tx.outputs.iter().map(|output| 8 + 2 + 8 + output.script_public_key.script().len()).sum();
+    // version, input and output counts, lock time, subnetwork id, gas, payload length
+    (2 + 8 + inputs + 8 + outputs + 8 + 20 + 8 + 8 + tx.payload.len()) as u64
+}
+
+struct Snapshot {
+    fetched_at: Instant,
+    /// Highest feerate first
+    transactions: Vec<PendingTransaction>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    snapshot: Option<Snapshot>,
+    /// First sighting of every transaction in the last snapshot, by id
+    first_seen: HashMap<String, i64>,
+}
+
+/// Server side cache of the node's mempool
+pub struct MempoolCache {
+    rpc_client: Arc<dyn RpcApi>,
+    ttl: Duration,
+    subscriptions: Arc<SubscriptionManager>,
+    // Held across the fetch so concurrent requests on an expired snapshot wait
+    // for one refresh instead of each calling the node
+    state: Mutex<CacheState>,
+}
+
+impl MempoolCache {
+    pub fn new(rpc_client: Arc<dyn RpcApi>, ttl: Duration, subscriptions: Arc<SubscriptionManager>) -> Self {
+        Self { rpc_client, ttl, subscriptions, state: Mutex::new(CacheState::default()) }
+    }
+
+    /// Pending transactions, highest feerate first
+    pub async fn transactions(&self) -> Result<Vec<PendingTransaction>> {
+        self.transactions_at(Instant::now(), chrono::Utc::now().timestamp_millis()).await
+    }
+
+    /// Pending transactions as of `now`, refetching from the node if the snapshot
+    /// is older than the TTL. `now_ms` is the matching wall clock time.
+    pub async fn transactions_at(&self, now: Instant, now_ms: i64) -> Result<Vec<PendingTransaction>> {
+        let mut state = self.state.lock().await;
+        let fresh = state.snapshot.as_ref().is_some_and(|snapshot| now.saturating_duration_since(snapshot.fetched_at) < self.ttl);
+        if !fresh {
+            let entries = self.rpc_client.get_mempool_entries(false, true).await?;
+            let mut first_seen = HashMap::with_capacity(entries.len());
+            let mut transactions = Vec::with_capacity(entries.len());
+            for entry in &entries {
+                let id = entry.transaction.id().to_string();
+                let seen = state.first_seen.get(&id).copied();
+                let tx = PendingTransaction::from_entry(entry, seen.unwrap_or(now_ms));
+                if seen.is_none() {
+                    self.subscriptions.broadcast_transaction(tx.clone());
+                }
+                first_seen.insert(id, tx.first_seen);
+                transactions.push(tx);
+            }
+            transactions.sort_by(|a, b| b.feerate.total_cmp(&a.feerate));
+            // Transactions that left the mempool are forgotten, so a confirmed
+            // transaction is served from the index from now on
+            state.first_seen = first_seen;
+            state.snapshot = Some(Snapshot { fetched_at: now, transactions });
+        }
+
+        let snapshot = state.snapshot.as_ref().expect("snapshot is set above");
+        Ok(snapshot
+            .transactions
+            .iter()
+            .map(|tx| PendingTransaction { age_secs: (now_ms - tx.first_seen).max(0) / 1000, ..tx.clone() })
+            .collect())
+    }
+
+    pub async fn summary(&self) -> Result<MempoolSummary> {
+        let transactions = self.transactions().await?;
+        Ok(MempoolSummary {
+            count: transactions.len(),
+            bytes: transactions.iter().map(|tx| tx.bytes).sum(),
+            mass: transactions.iter().map(|tx| tx.mass).sum(),
+            histogram: feerate_histogram(&transactions),
+        })
+    }
+
+    /// One page of pending transactions, pages starting at 1
+    pub async fn page(&self, page: i32, page_size: i32) -> Result<PaginatedResponse<PendingTransaction>> {
+        let transactions = self.transactions().await?;
+        let total = transactions.len() as i64;
+        let data = transactions.into_iter().skip(((page - 1) * page_size) as usize).take(page_size as usize).collect();
+        Ok(PaginatedResponse {
+            data,
+            total,
+            page,
+            page_size,
+            total_pages: (total as f64 / page_size as f64).ceil() as i32,
+        })
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<PendingTransaction>> {
+        Ok(self.transactions().await?.into_iter().find(|tx| tx.id == id))
+    }
+
+    /// Refresh the snapshot every `interval` so `new_transaction` events go out
+    /// even while nobody is loading mempool pages
+    pub fn spawn_refresh(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = cache.transactions().await {
+                    tracing::warn!("Failed to refresh mempool: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
+    use consensus_core::block::Block;
+    use consensus_core::subnets::SubnetworkId;
+    use consensus_core::tx::script_class::pay_to_address_script;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput, UtxoEntry};
+    use consensus_core::Hash;
+    use rpc_core::model::*;
+
+    /// Node whose mempool is set by the test. Every other method fails.
+    #[derive(Default)]
+    struct MockRpc {
+        entries: std::sync::Mutex<Vec<MempoolEntry>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockRpc {
+        fn set_entries(&self, entries: Vec<MempoolEntry>) {
+            *self.entries.lock().unwrap() = entries;
+        }
+    }
+
+    fn unsupported<T>() -> std::result::Result<T, RpcError> {
+        Err(RpcError::Internal("not supported by mock".into()))
+    }
+
+    #[async_trait]
+    impl RpcApi for MockRpc {
+        async fn get_mempool_entries(&self, _: bool, _: bool) -> std::result::Result<Vec<MempoolEntry>, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.entries.lock().unwrap().clone())
+        }
+
+        async fn get_info(&self) -> std::result::Result<GetInfoResponse, RpcError> { unsupported() }
+        async fn set_log_level(&self, _: String, _: String) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn get_block_count(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_block(&self, _: Hash) -> std::result::Result<Block, RpcError> { unsupported() }
+        async fn get_block_verbose(&self, _: Hash) -> std::result::Result<RpcBlockVerbose, RpcError> { unsupported() }
+        async fn get_block_status(&self, _: Hash) -> std::result::Result<BlockStatusInfo, RpcError> { unsupported() }
+        async fn get_block_dag_info(&self) -> std::result::Result<BlockDagInfo, RpcError> { unsupported() }
+        async fn get_blocks(&self, _: Option<Hash>, _: bool, _: bool, _: bool) -> std::result::Result<GetBlocksResponse, RpcError> { unsupported() }
+        async fn get_peer_info(&self) -> std::result::Result<Vec<PeerInfo>, RpcError> { unsupported() }
+        async fn get_connection_count(&self) -> std::result::Result<ConnectionCounts, RpcError> { unsupported() }
+        async fn add_peer(&self, _: String, _: bool) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn remove_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn ban_peer(&self, _: String, _: u64) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn unban_peer(&self, _: String) -> std::result::Result<(), RpcError> { unsupported() }
+        async fn get_banned_peers(&self) -> std::result::Result<Vec<BannedPeer>, RpcError> { unsupported() }
+        async fn submit_block(&self, _: Block) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn send_raw_transaction(&self, _: String, _: bool) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_mempool_info(&self) -> std::result::Result<MempoolInfo, RpcError> { unsupported() }
+        async fn get_fee_estimate(&self) -> std::result::Result<FeeEstimate, RpcError> { unsupported() }
+        async fn flush_mempool(&self) -> std::result::Result<usize, RpcError> { unsupported() }
+        async fn get_block_template(&self, _: String, _: Option<String>) -> std::result::Result<BlockTemplate, RpcError> { unsupported() }
+        async fn submit_block_hex(&self, _: String) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn submit_block_with_coinbase(&self, _: Hash, _: u64, _: Vec<u8>, _: Option<CoinbaseOverrides>) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_mining_info(&self) -> std::result::Result<MiningInfo, RpcError> { unsupported() }
+        async fn estimate_network_hashes_per_second(&self, _: u32, _: Option<Hash>) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_balances(&self) -> std::result::Result<GetBalancesResponse, RpcError> { unsupported() }
+        async fn get_new_address(&self) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn send_to_address(&self, _: String, _: u64) -> std::result::Result<Hash, RpcError> { unsupported() }
+        async fn get_balance_by_address(&self, _: String) -> std::result::Result<AddressBalance, RpcError> { unsupported() }
+        async fn get_utxos_by_address(&self, _: String) -> std::result::Result<Vec<RpcUtxoByAddress>, RpcError> { unsupported() }
+        async fn get_virtual_utxo_entry(&self, _: TransactionOutpoint) -> std::result::Result<Option<UtxoEntry>, RpcError> { unsupported() }
+        async fn get_virtual_selected_parent_blue_score(&self) -> std::result::Result<u64, RpcError> { unsupported() }
+        async fn get_coin_supply(&self) -> std::result::Result<CoinSupply, RpcError> { unsupported() }
+        async fn get_block_by_height(&self, _: u64) -> std::result::Result<Block, RpcError> { unsupported() }
+        async fn get_transaction(&self, _: Hash) -> std::result::Result<Transaction, RpcError> { unsupported() }
+        async fn get_transaction_verbose(&self, _: Hash) -> std::result::Result<RpcTransactionVerbose, RpcError> { unsupported() }
+        async fn get_recent_blocks(&self, _: usize) -> std::result::Result<Vec<Block>, RpcError> { unsupported() }
+        async fn get_dag_tips(&self) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_block_children(&self, _: Hash) -> std::result::Result<Vec<Hash>, RpcError> { unsupported() }
+        async fn get_headers(&self, _: Hash, _: usize) -> std::result::Result<Vec<String>, RpcError> { unsupported() }
+        async fn get_virtual_chain_from_block(&self, _: Option<Hash>, _: bool) -> std::result::Result<VirtualChainChanged, RpcError> { unsupported() }
+        async fn get_block_hex(&self, _: Hash) -> std::result::Result<String, RpcError> { unsupported() }
+        async fn decode_block(&self, _: String) -> std::result::Result<RpcBlock, RpcError> { unsupported() }
+        async fn decode_transaction(&self, _: String) -> std::result::Result<RpcTransaction, RpcError> { unsupported() }
+        async fn get_transaction_hex(&self, _: Hash) -> std::result::Result<String, RpcError> { unsupported() }
+    }
+
+    /// Entry paying `fee` for a one input, one output transaction of mass 180
+    fn entry(n: u8, fee: u64) -> MempoolEntry {
+        let transaction = Transaction::new(
+            0,
+            vec![TransactionInput::new(TransactionOutpoint::new(Hash::from([n; 32]), 0), vec![], 0, 1)],
+            vec![TransactionOutput::new(1000, ScriptPublicKey::from_vec(0, vec![0xac, n]))],
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![],
+        );
+        MempoolEntry { fee, transaction, is_orphan: false }
+    }
+
+    fn cache(rpc: Arc<MockRpc>, ttl: Duration) -> (MempoolCache, Arc<SubscriptionManager>) {
+        let subscriptions = Arc::new(SubscriptionManager::new());
+        (MempoolCache::new(rpc, ttl, subscriptions.clone()), subscriptions)
+    }
+
+    #[tokio::test]
+    async fn test_feerate_histogram_buckets() {
+        let rpc = Arc::new(MockRpc::default());
+        // Feerates 0, 1, 1.5, 7, 100 and 1000
+        rpc.set_entries(vec![entry(1, 0), entry(2, 180), entry(3, 270), entry(4, 1260), entry(5, 18_000), entry(6, 180_000)]);
+        let (cache, _) = cache(rpc, DEFAULT_MEMPOOL_TTL);
+
+        let summary = cache.summary().await.unwrap();
+        assert_eq!(summary.count, 6);
+        assert_eq!(summary.mass, 6 * 180);
+        let counts: Vec<usize> = summary.histogram.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, vec![1, 2, 0, 1, 0, 0, 0, 2]);
+        assert_eq!(summary.histogram[1], FeerateBucket { min_feerate: 1.0, max_feerate: Some(2.0), count: 2, mass: 360 });
+        assert_eq!(summary.histogram[7].min_feerate, 100.0);
+        assert_eq!(summary.histogram[7].max_feerate, None);
+
+        let page = cache.page(1, 4).await.unwrap();
+        assert_eq!(page.total, 6);
+        assert_eq!(page.total_pages, 2);
+        assert_eq!(page.data[0].feerate, 1000.0);
+        // The test outputs' scripts are non-standard and have no address
+        assert!(page.data[0].addresses.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_entry_addresses() {
+        let rpc = Arc::new(MockRpc::default());
+        let address = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+        let mut paying = entry(1, 500);
+        paying.transaction.outputs[0].script_public_key = pay_to_address_script(address).unwrap();
+        rpc.set_entries(vec![paying]);
+        let (cache, _) = cache(rpc, DEFAULT_MEMPOOL_TTL);
+
+        let page = cache.page(1, 10).await.unwrap();
+        assert_eq!(page.data[0].addresses, vec![address.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_is_refetched_after_ttl() {
+        let rpc = Arc::new(MockRpc::default());
+        rpc.set_entries(vec![entry(1, 500)]);
+        let (cache, _) = cache(rpc.clone(), Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert_eq!(cache.transactions_at(start, 0).await.unwrap().len(), 1);
+        rpc.set_entries(vec![entry(1, 500), entry(2, 500)]);
+        let cached = cache.transactions_at(start + Duration::from_secs(9), 9_000).await.unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].age_secs, 9);
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 1);
+
+        let refreshed = cache.transactions_at(start + Duration::from_secs(10), 10_000).await.unwrap();
+        assert_eq!(refreshed.len(), 2);
+        assert_eq!(rpc.calls.load(Ordering::SeqCst), 2);
+        // Age counts from the first snapshot the transaction appeared in
+        let first = refreshed.iter().find(|tx| tx.first_seen == 0).unwrap();
+        assert_eq!(first.age_secs, 10);
+    }
+
+    #[tokio::test]
+    async fn test_new_transactions_are_announced_once() {
+        let rpc = Arc::new(MockRpc::default());
+        rpc.set_entries(vec![entry(1, 500)]);
+        let (cache, subscriptions) = cache(rpc.clone(), Duration::ZERO);
+        let mut events = subscriptions.subscribe_transactions();
+        let start = Instant::now();
+
+        cache.transactions_at(start, 0).await.unwrap();
+        rpc.set_entries(vec![entry(1, 500), entry(2, 500)]);
+        cache.transactions_at(start, 0).await.unwrap();
+
+        let first = events.try_recv().unwrap();
+        let second = events.try_recv().unwrap();
+        assert_ne!(first.id, second.id);
+        assert!(events.try_recv().is_err());
+
+        // A confirmed transaction leaves the cache and is looked up in the index
+        rpc.set_entries(vec![entry(2, 500)]);
+        assert!(cache.get(&first.id).await.unwrap().is_none());
+        assert!(cache.get(&second.id).await.unwrap().is_some());
+    }
+}
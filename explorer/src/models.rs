@@ -14,6 +14,24 @@ pub struct BlockSummary {
     pub coinbase_value: i64,
     pub parent_count: i32,
     pub blue_score: i64,
+    /// As the node reported it; null until the node's verbose data is indexed
+    pub selected_parent: Option<String>,
+    pub is_chain_block: bool,
+    pub difficulty: Option<f64>,
+    /// The node kept only the header, so the transactions are missing
+    pub is_header_only: bool,
+}
+
+/// A block's place in the DAG
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockDag {
+    pub hash: String,
+    pub parents: Vec<String>,
+    /// Indexed blocks naming the block as a parent
+    pub children: Vec<String>,
+    /// Blocks merged as blue, selected parent first
+    pub merge_set_blues: Vec<String>,
+    pub merge_set_reds: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -30,6 +48,40 @@ pub struct TransactionSummary {
     pub is_coinbase: bool,
     pub is_confirmed: bool,
     pub confirmation_count: i32,
+    /// Bytes the transaction carries in its payload
+    pub payload_size: i64,
+    /// Hex of the payload's first [`PAYLOAD_PREVIEW_BYTES`] bytes
+    pub payload_preview: Option<String>,
+    /// The same bytes as text, when the whole payload is valid UTF-8
+    pub payload_utf8: Option<String>,
+}
+
+/// Payload bytes shown in listings; the full payload has its own endpoint
+pub const PAYLOAD_PREVIEW_BYTES: usize = 64;
+
+/// Most transactions one payload search returns
+pub const PAYLOAD_SEARCH_LIMIT: i64 = 100;
+
+/// Transaction found by a payload search, with previews of its payload
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PayloadSummary {
+    pub tx_hash: String,
+    pub block_hash: Option<String>,
+    pub timestamp: i64,
+    pub size: i64,
+    pub hex_preview: String,
+    pub utf8_preview: Option<String>,
+    /// Whether the payload is longer than the previews
+    pub truncated: bool,
+}
+
+/// The whole payload of a transaction
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Payload {
+    pub tx_hash: String,
+    pub size: i64,
+    pub hex: String,
+    pub utf8: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -46,6 +98,24 @@ pub struct AddressSummary {
     pub last_seen: Option<i64>,
 }
 
+/// Most addresses one ledger check samples
+pub const LEDGER_CHECK_LIMIT: i64 = 1000;
+
+/// Materialized balance of an address next to the sum of its ledger rows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BalanceCheck {
+    pub address: String,
+    pub balance: i64,
+    pub ledger_balance: i64,
+}
+
+/// Addresses checked against the ledger and those whose balance differs from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerCheck {
+    pub checked: usize,
+    pub mismatches: Vec<BalanceCheck>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkStats {
     pub block_count: i64,
@@ -34,6 +34,7 @@ impl WSServer {
     async fn handle_socket(socket: WebSocket, manager: Arc<SubscriptionManager>) {
         let (mut sender, mut receiver) = socket.split();
         let mut block_rx = manager.subscribe_blocks();
+        let mut tx_rx = manager.subscribe_transactions();
         
         // Handle incoming messages
         let manager_clone = manager.clone();
@@ -54,17 +55,31 @@ impl WSServer {
             }
         });
         
-        // Handle outgoing messages (block broadcasts)
+        // Handle outgoing messages (block and mempool broadcasts)
         let mut send_task = tokio::spawn(async move {
-            while let Ok(block) = block_rx.recv().await {
-                let event = WSEvent {
-                    channel: "blocks:new".to_string(),
-                    data: serde_json::json!({
-                        "hash": block.header.hash.to_string(),
-                        "height": 0, // TODO: Get height
-                        "timestamp": block.header.timestamp,
-                        "txCount": block.transactions.len(),
-                    }),
+            loop {
+                let event = tokio::select! {
+                    block = block_rx.recv() => match block {
+                        Ok(block) => WSEvent {
+                            channel: "blocks:new".to_string(),
+                            data: serde_json::json!({
+                                "hash": block.header.hash.to_string(),
+                                "height": 0, // TODO: Get height
+                                "timestamp": block.header.timestamp,
+                                "txCount": block.transactions.len(),
+                            }),
+                        },
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    tx = tx_rx.recv() => match tx {
+                        Ok(tx) => WSEvent {
+                            channel: "new_transaction".to_string(),
+                            data: serde_json::to_value(&tx).unwrap_or_default(),
+                        },
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
                 };
                 
                 if let Ok(json) = serde_json::to_string(&event) {
@@ -4,17 +4,21 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use consensus_core::block::Block;
+use crate::mempool::PendingTransaction;
 
 pub struct SubscriptionManager {
     block_sender: broadcast::Sender<Block>,
+    transaction_sender: broadcast::Sender<PendingTransaction>,
     subscriptions: Arc<RwLock<HashMap<String, usize>>>,
 }
 
 impl SubscriptionManager {
     pub fn new() -> Self {
         let (block_sender, _) = broadcast::channel(100);
+        let (transaction_sender, _) = broadcast::channel(1000);
         Self {
             block_sender,
+            transaction_sender,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -23,6 +27,10 @@ impl SubscriptionManager {
         self.block_sender.subscribe()
     }
     
+    pub fn subscribe_transactions(&self) -> broadcast::Receiver<PendingTransaction> {
+        self.transaction_sender.subscribe()
+    }
+    
     pub async fn subscribe(&self, channel: &str) {
         let mut subs = self.subscriptions.write().await;
         *subs.entry(channel.to_string()).or_insert(0) += 1;
@@ -41,5 +49,10 @@ impl SubscriptionManager {
     pub fn broadcast_block(&self, block: Block) {
         let _ = self.block_sender.send(block);
     }
+    
+    /// Announce a transaction that just entered the mempool
+    pub fn broadcast_transaction(&self, tx: PendingTransaction) {
+        let _ = self.transaction_sender.send(tx);
+    }
 }
 
@@ -10,7 +10,11 @@ use std::time::Instant;
 #[derive(Parser, Debug)]
 #[command(name = "genesis_tool")]
 struct Opts {
-	/// Print bincode-serialized genesis as hex
+	/// Network whose genesis to start from: mainnet, testnet, devnet or simnet
+	#[arg(long, default_value = "mainnet")]
+	network: String,
+
+	/// Print the canonically encoded genesis as hex
 	#[arg(long)]
 	hex: bool,
 
@@ -46,7 +50,14 @@ fn parse_bits(s: &str) -> Option<u32> {
 fn main() {
 	let opts = Opts::parse();
 
-	let mut genesis = core_genesis::default_genesis();
+	let network = match opts.network.parse() {
+		Ok(network) => network,
+		Err(e) => {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+	};
+	let genesis = core_genesis::genesis(network);
 
 	// Build a mutable header from the genesis and allow overrides
 	let mut header: Header = (&genesis).into();
@@ -121,27 +132,22 @@ fn main() {
 	println!("Coinbase payload (utf8): {}", String::from_utf8_lossy(genesis.coinbase_payload));
 
 	if opts.hex || opts.out.is_some() {
-		match bincode::serialize(&block) {
-			Ok(bytes) => {
-				println!("Serialized genesis (hex): {}", hex::encode(&bytes));
-				if let Some(path) = opts.out.as_deref() {
-					if let Err(e) = fs::write(path, &bytes) {
-						eprintln!("Failed to write genesis to {}: {}", path, e);
-					} else {
-						println!("Wrote serialized genesis to {}", path);
-					}
-				} else if opts.mine {
-					// Default place to store a freshly mined genesis so it's easy to find in the repo
-					let default_path = "jiopad/genesis.bin";
-					if let Err(e) = fs::write(default_path, &bytes) {
-						eprintln!("Failed to write default-genesis to {}: {}", default_path, e);
-					} else {
-						println!("Wrote serialized genesis to {}", default_path);
-					}
-				}
+		let bytes = consensus_core::encoding::encode_block(&block);
+		println!("Serialized genesis (hex): {}", hex::encode(&bytes));
+		if let Some(path) = opts.out.as_deref() {
+			if let Err(e) = fs::write(path, &bytes) {
+				eprintln!("Failed to write genesis to {}: {}", path, e);
+			} else {
+				println!("Wrote serialized genesis to {}", path);
+			}
+		} else if opts.mine {
+			// Default place to store a freshly mined genesis so it's easy to find in the repo
+			let default_path = "jiopad/genesis.bin";
+			if let Err(e) = fs::write(default_path, &bytes) {
+				eprintln!("Failed to write default-genesis to {}: {}", default_path, e);
+			} else {
+				println!("Wrote serialized genesis to {}", default_path);
 			}
-			Err(e) => eprintln!("Failed to serialize genesis block: {}", e),
 		}
 	}
 }
-
@@ -1,10 +1,13 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name = "jiopad")]
 #[command(about = "JIO blockchain full node daemon", long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to configuration file (optional, uses defaults if not provided)
     #[arg(short, long)]
     pub config_path: Option<PathBuf>,
@@ -13,26 +16,46 @@ pub struct Args {
     #[arg(short, long)]
     pub data_dir: Option<PathBuf>,
 
-    /// Network (mainnet, testnet, devnet)
+    /// Network (mainnet, testnet, simnet, devnet)
     #[arg(short, long)]
     pub network: Option<String>,
 
-    /// Log level (trace, debug, info, warn, error)
-    #[arg(short, long, default_value = "info")]
-    pub log_level: String,
+    /// Devnet consensus params override file (TOML); overrides network.params_file
+    #[arg(long)]
+    pub params_file: Option<PathBuf>,
+
+    /// Log level (trace, debug, info, warn, error); overrides the config file
+    #[arg(short, long)]
+    pub log_level: Option<String>,
+
+    /// Also write logs to size-rotated files in this directory
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
 
     /// Enable mining
     #[arg(long)]
     pub enable_mining: bool,
 
-    /// Mining address (required if mining enabled)
+    /// Mining address (required if mining enabled without --wallet-keystore)
     #[arg(long)]
     pub mining_address: Option<String>,
 
+    /// Keystore of the node's wallet, unlocked with $JIOPAD_WALLET_PASSWORD or a prompt
+    #[arg(long)]
+    pub wallet_keystore: Option<PathBuf>,
+
     /// RPC server port
     #[arg(long)]
     pub rpc_port: Option<u16>,
 
+    /// P2P listen hosts, comma separated (IPv4, IPv6 or `*` for both)
+    #[arg(long)]
+    pub p2p_listen: Option<String>,
+
+    /// RPC listen hosts, comma separated (IPv4, IPv6 or `*` for both)
+    #[arg(long)]
+    pub rpc_listen: Option<String>,
+
     /// P2P listen port
     #[arg(long)]
     pub p2p_port: Option<u16>,
@@ -41,15 +64,87 @@ pub struct Args {
     #[arg(long)]
     pub bootstrap_peers: Option<String>,
 
+    /// Don't map the P2P port on the local gateway via UPnP/NAT-PMP
+    #[arg(long)]
+    pub no_upnp: bool,
+
+    /// Encrypt P2P connections with peers that support it
+    #[arg(long)]
+    pub p2p_encryption: bool,
+
+    /// Refuse P2P peers that don't support encryption (implies --p2p-encryption)
+    #[arg(long)]
+    pub p2p_require_encryption: bool,
+
     /// Disable RPC server
     #[arg(long)]
     pub no_rpc: bool,
 
+    /// Seconds between status reports (0 disables them)
+    #[arg(long)]
+    pub status_interval: Option<u64>,
+
+    /// Emit status reports as one JSON object per line on stdout; logs go to stderr
+    #[arg(long)]
+    pub status_json: bool,
+
+    /// Delete block bodies deeper than the pruning depth
+    #[arg(long, conflicts_with = "archive")]
+    pub prune: bool,
+
     /// Run as archive node (keep full history)
     #[arg(long)]
     pub archive: bool,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Rebuild the transaction, address, children and DAA score indexes from the stored blocks, then exit
+    Reindex,
+    /// Re-validate the headers, proof of work and optionally bodies of stored blocks, then exit
+    #[command(name = "verifychain")]
+    VerifyChain {
+        /// Check only the last N blocks by DAA score
+        #[arg(long)]
+        depth: Option<usize>,
+
+        /// Also validate block bodies
+        #[arg(long)]
+        check_bodies: bool,
+    },
+    /// Recompute the UTXO set commitment and compare it with the stored one, then exit
+    #[command(name = "verifyutxo")]
+    VerifyUtxo,
+    /// Check the block, header, chain index and UTXO stores against each other, then exit
+    #[command(name = "checkdb")]
+    CheckDb {
+        /// Roll the chain index back to the last consistent chain block and discard partial data
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Write the UTXO set and headers at the selected chain tip to a snapshot file, then exit
+    #[command(name = "exportsnapshot")]
+    ExportSnapshot {
+        /// File to write the snapshot to
+        path: PathBuf,
+    },
+    /// Bootstrap an empty database from a snapshot file, then exit; the node syncs forward from its checkpoint
+    #[command(name = "importsnapshot")]
+    ImportSnapshot {
+        /// Snapshot file to read
+        path: PathBuf,
+
+        /// Hex hash of the block the snapshot must be taken at
+        #[arg(long)]
+        checkpoint: String,
+
+        /// Hex UTXO commitment the snapshot must carry at the checkpoint, as
+        /// printed by exportsnapshot on a node you trust
+        #[arg(long)]
+        utxo_commitment: String,
+    },
+}
+
 pub fn parse_args() -> Args {
     Args::parse()
 }
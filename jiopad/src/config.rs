@@ -1,9 +1,22 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write as _};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::str::FromStr;
 use consensus_core::config::genesis as core_genesis;
+use consensus_core::Hash;
 use hex::encode as hex_encode;
+use network::p2p::RateLimits;
 
+pub use crate::metrics::MetricsConfig;
+
+/// Daemon configuration, read from a TOML file by [`Config::load`]. Run
+/// [`Config::write_default`] for a commented file listing every key.
+///
+/// Settings are layered, each layer overriding the keys it sets in the ones
+/// before it: built-in defaults, the network preset, the config file and the
+/// command line flags. See [`Config::resolve`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub network: NetworkConfig,
@@ -12,13 +25,55 @@ pub struct Config {
     pub rpc: RpcConfig,
     pub mining: MiningConfig,
     pub p2p: P2PConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub mempool: MempoolConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub wallet: WalletConfig,
+}
+
+/// Default listen ports of a network. Each network uses its own range so that
+/// nodes of different networks never connect to each other by accident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkPorts {
+    pub p2p: u16,
+    pub rpc: u16,
+    pub grpc: u16,
+    pub metrics: u16,
+}
+
+impl NetworkPorts {
+    pub const MAINNET: Self = Self { p2p: 16111, rpc: 16110, grpc: 16112, metrics: 16119 };
+    pub const TESTNET: Self = Self { p2p: 16211, rpc: 16210, grpc: 16212, metrics: 16219 };
+    pub const SIMNET: Self = Self { p2p: 16511, rpc: 16510, grpc: 16512, metrics: 16519 };
+    pub const DEVNET: Self = Self { p2p: 16611, rpc: 16610, grpc: 16612, metrics: 16619 };
+
+    pub fn for_network(network: &str) -> Option<Self> {
+        match network {
+            "mainnet" => Some(Self::MAINNET),
+            "testnet" => Some(Self::TESTNET),
+            "simnet" => Some(Self::SIMNET),
+            "devnet" => Some(Self::DEVNET),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
+    /// mainnet, testnet, simnet or devnet
     pub network_id: String,
+    /// Hex encoded hash of the expected genesis block
     pub genesis_hash: String,
     pub genesis_timestamp: u64,
+    /// TOML file overriding consensus parameters of a devnet, see [`ParamsOverride`]
+    #[serde(default)]
+    pub params_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,56 +84,585 @@ pub struct ConsensusConfig {
     pub difficulty_window_size: u64,
     pub max_block_size: u64,
     pub coinbase_maturity: u64,
+    /// Compact difficulty bits blocks declare until the selected chain below
+    /// them fills a difficulty window
+    #[serde(default = "default_block_bits")]
+    pub block_bits: u32,
+    /// Block subsidy before the first halving, in sompi
+    #[serde(default = "default_initial_subsidy")]
+    pub initial_subsidy: u64,
+    /// Blocks between halvings of the subsidy
+    #[serde(default = "default_subsidy_halving_interval")]
+    pub subsidy_halving_interval: u64,
+    /// Heaviest block accepted
+    #[serde(default = "default_max_block_mass")]
+    pub max_block_mass: u64,
+    /// Heaviest transaction accepted, by estimated size
+    #[serde(default = "default_max_tx_mass")]
+    pub max_tx_mass: u64,
+    /// Blocks the chain must merge, as "<DAA score>:<hash>"
+    #[serde(default)]
+    pub checkpoints: Vec<String>,
+    /// Accept transactions of subnetworks other than native and coinbase; devnet only
+    #[serde(default)]
+    pub experimental_subnetworks: bool,
+    /// Gas all transactions of a block may use together
+    #[serde(default)]
+    pub max_block_gas: u64,
+    /// DAA score from which transactions must commit to at least their storage mass
+    #[serde(default)]
+    pub storage_mass_activation: Option<u64>,
+}
+
+/// Largest GHOSTDAG k the node supports
+pub const MAX_GHOSTDAG_K: u32 = 255;
+
+impl ConsensusConfig {
+    /// Hash of the parameters blocks are validated with. Nodes with different
+    /// hashes don't peer, and a database refuses to open with another one.
+    pub fn params_hash(&self) -> Hash {
+        let mut bytes = b"jiopad consensus params v1".to_vec();
+        for value in [
+            self.ghostdag_k as u64,
+            self.max_block_parents as u64,
+            self.target_time_per_block,
+            self.difficulty_window_size,
+            self.max_block_size,
+            self.coinbase_maturity,
+            self.initial_subsidy,
+            self.subsidy_halving_interval,
+            self.max_block_mass,
+            self.max_tx_mass,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // Left out otherwise, so the hashes of existing networks don't change
+        if self.experimental_subnetworks {
+            bytes.extend_from_slice(b"experimental subnetworks");
+            bytes.extend_from_slice(&self.max_block_gas.to_le_bytes());
+        }
+        if let Some(activation) = self.storage_mass_activation {
+            bytes.extend_from_slice(b"storage mass activation");
+            bytes.extend_from_slice(&activation.to_le_bytes());
+        }
+        consensus_core::hashing::double_sha256(&bytes)
+    }
+
+    /// The configured checkpoints by DAA score
+    pub fn checkpoints(&self) -> Result<BTreeMap<u64, Hash>, ConfigError> {
+        let mut checkpoints = BTreeMap::new();
+        for checkpoint in &self.checkpoints {
+            let invalid = |reason: &str| ConfigError::InvalidConsensusParams {
+                key: "checkpoints",
+                reason: format!("entry \"{}\" {}", checkpoint, reason),
+            };
+            let (daa_score, hash) = checkpoint.split_once(':').ok_or_else(|| invalid("is not \"<DAA score>:<hash>\""))?;
+            let daa_score = daa_score.trim().parse().map_err(|_| invalid("has an invalid DAA score"))?;
+            let hash = Hash::from_str(hash.trim()).map_err(|_| invalid("has an invalid hash"))?;
+            if checkpoints.insert(daa_score, hash).is_some_and(|other| other != hash) {
+                return Err(invalid("conflicts with another checkpoint at the same DAA score"));
+            }
+        }
+        Ok(checkpoints)
+    }
+
+    /// Check that the parameters are in range and consistent with each other
+    pub fn validate_params(&self) -> Result<(), ConfigError> {
+        let invalid = |key, reason: String| Err(ConfigError::InvalidConsensusParams { key, reason });
+        if !(1..=MAX_GHOSTDAG_K).contains(&self.ghostdag_k) {
+            return invalid("ghostdag_k", format!("is {}, it must be between 1 and {}", self.ghostdag_k, MAX_GHOSTDAG_K));
+        }
+        if self.max_block_parents == 0 {
+            return invalid("max_block_parents", "must be at least 1".to_string());
+        }
+        if self.target_time_per_block == 0 {
+            return invalid("target_time_per_block", "must be at least 1".to_string());
+        }
+        if self.difficulty_window_size < 2 {
+            return invalid("difficulty_window_size", format!("is {}, it must be at least 2", self.difficulty_window_size));
+        }
+        if self.max_tx_mass == 0 {
+            return invalid("max_tx_mass", "must be at least 1".to_string());
+        }
+        if self.max_block_mass < self.max_tx_mass {
+            return invalid(
+                "max_block_mass",
+                format!("({}) must be at least max_tx_mass ({})", self.max_block_mass, self.max_tx_mass),
+            );
+        }
+        if self.experimental_subnetworks && self.max_block_gas == 0 {
+            return invalid("max_block_gas", "must be at least 1 with experimental_subnetworks".to_string());
+        }
+        self.checkpoints()?;
+        Ok(())
+    }
+}
+
+/// Consensus parameters a devnet overrides, read from the TOML file named by
+/// `network.params_file`. Keys left out keep the devnet's value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamsOverride {
+    pub ghostdag_k: Option<u32>,
+    pub max_block_parents: Option<usize>,
+    pub target_time_per_block: Option<u64>,
+    pub difficulty_window_size: Option<u64>,
+    pub max_block_mass: Option<u64>,
+    pub max_tx_mass: Option<u64>,
+    pub coinbase_maturity: Option<u64>,
+    pub initial_subsidy: Option<u64>,
+    pub subsidy_halving_interval: Option<u64>,
+    pub experimental_subnetworks: Option<bool>,
+    pub max_block_gas: Option<u64>,
+    pub storage_mass_activation: Option<u64>,
+}
+
+impl ParamsOverride {
+    /// Read the override file at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read params file {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| parse_error(path, &content, &e))
+    }
+
+    /// Set the overridden parameters in `consensus`
+    pub fn apply(&self, consensus: &mut ConsensusConfig) {
+        fn set<T: Copy>(value: Option<T>, target: &mut T) {
+            if let Some(value) = value {
+                *target = value;
+            }
+        }
+        set(self.ghostdag_k, &mut consensus.ghostdag_k);
+        set(self.max_block_parents, &mut consensus.max_block_parents);
+        set(self.target_time_per_block, &mut consensus.target_time_per_block);
+        set(self.difficulty_window_size, &mut consensus.difficulty_window_size);
+        set(self.max_block_mass, &mut consensus.max_block_mass);
+        set(self.max_tx_mass, &mut consensus.max_tx_mass);
+        set(self.coinbase_maturity, &mut consensus.coinbase_maturity);
+        set(self.initial_subsidy, &mut consensus.initial_subsidy);
+        set(self.subsidy_halving_interval, &mut consensus.subsidy_halving_interval);
+        set(self.experimental_subnetworks, &mut consensus.experimental_subnetworks);
+        set(self.max_block_gas, &mut consensus.max_block_gas);
+        if let Some(activation) = self.storage_mass_activation {
+            consensus.storage_mass_activation = Some(activation);
+        }
+    }
+}
+
+fn default_block_bits() -> u32 {
+    0x1f00ffff
+}
+
+fn default_initial_subsidy() -> u64 {
+    consensus::process::coinbase::INITIAL_BLOCK_REWARD_SOMPI
+}
+
+fn default_subsidy_halving_interval() -> u64 {
+    consensus_core::constants::SUBSIDY_HALVING_INTERVAL
+}
+
+fn default_max_block_mass() -> u64 {
+    consensus_core::constants::MAX_BLOCK_MASS
+}
+
+fn default_max_tx_mass() -> u64 {
+    consensus::consensus::validation::transaction_validator::MAX_TRANSACTION_SIZE
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
     pub data_dir: PathBuf,
+    /// Database cache size in bytes
     pub db_cache_size: usize,
+    /// Delete old block bodies; an archival node (false) keeps all of them
     pub enable_pruning: bool,
+    /// Blue score distance from the virtual below which bodies are pruned
     pub pruning_depth: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcConfig {
     pub enabled: bool,
+    /// Comma separated listen hosts; IPv6 allowed, `*` binds all IPv4 and IPv6 interfaces
     pub bind_address: String,
+    /// wRPC (WebSocket JSON-RPC) port
     pub port: u16,
+    #[serde(default = "default_grpc_port")]
+    pub grpc_port: u16,
+    /// WebSocket clients served at once; further ones are refused until one disconnects
     pub max_connections: usize,
+    /// Requests of one client handled at once
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// Responses waiting to be written to one client before it is dropped as too slow
+    #[serde(default = "default_max_queued_messages")]
+    pub max_queued_messages: usize,
+    /// Coinbase payload bytes reserved in block templates for pool extranonces
+    #[serde(default)]
+    pub extranonce_size: usize,
+}
+
+fn default_grpc_port() -> u16 {
+    NetworkPorts::MAINNET.grpc
+}
+
+fn default_max_concurrent_requests() -> usize {
+    rpc_wrpc::server::DEFAULT_MAX_CONCURRENT_REQUESTS
+}
+
+fn default_max_queued_messages() -> usize {
+    rpc_wrpc::server::DEFAULT_MAX_QUEUED_MESSAGES
+}
+
+/// Periodic status report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusConfig {
+    /// Seconds between reports; 0 disables the report
+    pub interval_secs: u64,
+    /// Emit one JSON object per report instead of a text line
+    pub json: bool,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self { interval_secs: 30, json: false }
+    }
+}
+
+/// Mempool relay policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolConfig {
+    /// Relay transactions with non-standard output scripts
+    #[serde(default)]
+    pub accept_non_standard: bool,
+    /// Feerate in sompi per gram that outputs must be worth spending at; also sets the dust threshold
+    #[serde(default = "default_min_relay_feerate")]
+    pub min_relay_feerate: u64,
+    /// Save pending transactions on shutdown and re-admit them on startup
+    #[serde(default = "default_persist")]
+    pub persist: bool,
+    /// Addresses that transactions paying only to them are relayed for below the minimum feerate
+    #[serde(default)]
+    pub fee_exempt_addresses: Vec<String>,
+    /// Most transactions a pooled transaction and its unconfirmed ancestors may number
+    #[serde(default = "default_max_ancestors")]
+    pub max_ancestors: usize,
+    /// Most transactions a pooled transaction and its unconfirmed descendants may number
+    #[serde(default = "default_max_descendants")]
+    pub max_descendants: usize,
+    /// Heaviest set of unconfirmed ancestors or descendants, in grams
+    #[serde(default = "default_max_package_mass")]
+    pub max_package_mass: u64,
+}
+
+fn default_max_ancestors() -> usize {
+    crate::mempool::DEFAULT_MAX_ANCESTORS
+}
+
+fn default_max_descendants() -> usize {
+    crate::mempool::DEFAULT_MAX_DESCENDANTS
+}
+
+fn default_max_package_mass() -> u64 {
+    crate::mempool::DEFAULT_MAX_PACKAGE_MASS
+}
+
+fn default_persist() -> bool {
+    true
+}
+
+fn default_min_relay_feerate() -> u64 {
+    consensus_core::constants::MIN_TRANSACTION_FEE_RATE
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            accept_non_standard: false,
+            min_relay_feerate: default_min_relay_feerate(),
+            persist: default_persist(),
+            fee_exempt_addresses: Vec::new(),
+            max_ancestors: default_max_ancestors(),
+            max_descendants: default_max_descendants(),
+            max_package_mass: default_max_package_mass(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MiningConfig {
     pub enabled: bool,
+    /// Address paid by mined coinbases; required when mining is enabled
     pub mining_address: Option<String>,
     pub num_threads: usize,
 }
 
+/// Wallet of the node itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletConfig {
+    /// Keystore unlocked at startup; mined coinbases are paid to its first address
+    /// unless mining.mining_address is set
+    pub keystore: Option<PathBuf>,
+}
+
+/// Logging
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// trace, debug, info, warn or error, or an `EnvFilter` directive such as `info,network=debug`
+    pub level: String,
+    /// Directory of `jiopad.log` and its rotated predecessors; no file is written when unset
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// Size in bytes at which the log file is rotated
+    #[serde(default = "default_max_log_file_size")]
+    pub max_file_size: u64,
+    /// Rotated log files kept besides the current one
+    #[serde(default = "default_max_log_files")]
+    pub max_files: usize,
+}
+
+fn default_max_log_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_log_files() -> usize {
+    5
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            dir: None,
+            max_file_size: default_max_log_file_size(),
+            max_files: default_max_log_files(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct P2PConfig {
+    /// Comma separated listen hosts; IPv6 allowed, `*` binds all IPv4 and IPv6 interfaces
     pub listen_address: String,
     pub port: u16,
+    /// Outbound peers the connection manager keeps connected
+    #[serde(default = "default_min_peers")]
+    pub min_peers: usize,
     pub max_peers: usize,
+    /// Permanent peers, redialed whenever they disconnect
     pub bootstrap_peers: Vec<String>,
+    /// Map the listen port on the local gateway via UPnP/NAT-PMP
+    #[serde(default = "default_true")]
     pub enable_upnp: bool,
+    /// Upgrade peer connections to an encrypted transport when the remote supports it
+    #[serde(default)]
+    pub enable_encryption: bool,
+    /// Accept plaintext connections from peers that don't support encryption
+    #[serde(default = "default_true")]
+    pub allow_plaintext: bool,
+    /// Persistent node identity key for authenticated peering (ephemeral if unset)
+    #[serde(default)]
+    pub identity_key_path: Option<PathBuf>,
+    /// Misbehavior score at which a peer is banned
+    #[serde(default = "default_ban_threshold")]
+    pub ban_threshold: u32,
+    /// How long a banned peer is refused, in seconds
+    #[serde(default = "default_ban_duration_secs")]
+    pub ban_duration_secs: u64,
+    /// Per-peer limits on received messages and bytes, by message type
+    #[serde(default)]
+    pub rate_limits: RateLimits,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_min_peers() -> usize {
+    network::connection_manager::ConnectionConfig::default().min_peers
+}
+
+fn default_ban_threshold() -> u32 {
+    network::p2p::ban::DEFAULT_BAN_THRESHOLD
+}
+
+fn default_ban_duration_secs() -> u64 {
+    network::p2p::ban::DEFAULT_BAN_DURATION.as_secs()
+}
+
+/// Why a configuration was rejected by [`Config::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `network.network_id` is not a known network
+    UnknownNetwork(String),
+    /// Two enabled listeners are configured on the same port
+    PortConflict { first: &'static str, second: &'static str, port: u16 },
+    /// The outbound peer target exceeds the peer limit, or the limit is zero
+    PeerLimits { min_peers: usize, max_peers: usize },
+    /// The data directory can't be created or written to
+    DataDirNotWritable { path: PathBuf, reason: String },
+    /// Mining is enabled without an address to pay the coinbase to
+    MissingMiningAddress,
+    /// `consensus.subsidy_halving_interval` is zero
+    ZeroHalvingInterval,
+    /// A consensus parameter is out of range or inconsistent with another
+    InvalidConsensusParams { key: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownNetwork(network) => {
+                write!(f, "unknown network '{}', expected one of mainnet, testnet, simnet, devnet", network)
+            }
+            ConfigError::PortConflict { first, second, port } => {
+                write!(f, "{} and {} are both configured on port {}", first, second, port)
+            }
+            ConfigError::PeerLimits { min_peers, max_peers } => write!(
+                f,
+                "p2p.min_peers ({}) must not exceed p2p.max_peers ({}), which must be at least 1",
+                min_peers, max_peers
+            ),
+            ConfigError::DataDirNotWritable { path, reason } => {
+                write!(f, "data directory {} is not writable: {}", path.display(), reason)
+            }
+            ConfigError::MissingMiningAddress => write!(f, "mining is enabled but neither mining.mining_address nor wallet.keystore is set"),
+            ConfigError::ZeroHalvingInterval => write!(f, "consensus.subsidy_halving_interval must be at least 1"),
+            ConfigError::InvalidConsensusParams { key, reason } => write!(f, "consensus.{} {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
-    /// Load configuration from file if it exists, otherwise use defaults
+    /// Check the configuration for values the daemon can't run with
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if NetworkPorts::for_network(&self.network.network_id).is_none() {
+            return Err(ConfigError::UnknownNetwork(self.network.network_id.clone()));
+        }
+
+        // Port 0 picks a free port, so it never conflicts
+        let mut listeners = vec![("p2p.port", self.p2p.port)];
+        if self.rpc.enabled {
+            listeners.push(("rpc.port", self.rpc.port));
+            listeners.push(("rpc.grpc_port", self.rpc.grpc_port));
+        }
+        if self.metrics.enabled {
+            listeners.push(("metrics.port", self.metrics.port));
+        }
+        for (i, (first, port)) in listeners.iter().enumerate() {
+            if let Some((second, _)) = listeners[i + 1..].iter().find(|(_, other)| *port != 0 && other == port) {
+                return Err(ConfigError::PortConflict { first: *first, second: *second, port: *port });
+            }
+        }
+
+        if self.p2p.max_peers == 0 || self.p2p.min_peers > self.p2p.max_peers {
+            return Err(ConfigError::PeerLimits { min_peers: self.p2p.min_peers, max_peers: self.p2p.max_peers });
+        }
+
+        if self.mining.enabled && self.mining.mining_address.is_none() && self.wallet.keystore.is_none() {
+            return Err(ConfigError::MissingMiningAddress);
+        }
+
+        if self.consensus.subsidy_halving_interval == 0 {
+            return Err(ConfigError::ZeroHalvingInterval);
+        }
+        self.consensus.validate_params()?;
+        if self.consensus.experimental_subnetworks && self.network.network_id != "devnet" {
+            return Err(ConfigError::InvalidConsensusParams {
+                key: "experimental_subnetworks",
+                reason: format!("is only supported on devnet, not {}", self.network.network_id),
+            });
+        }
+
+        check_writable(&self.storage.data_dir)
+    }
+
+    /// Build the configuration of a node started with `args`: the preset of
+    /// `--network` (or of the file's network, mainnet otherwise), overridden by
+    /// the keys set in `--config-path`, overridden by the other flags, with the
+    /// consensus parameters of the params file on top
+    pub fn resolve(args: &crate::cli::Args) -> Result<Self, String> {
+        let mut config = Self::layered(args.network.as_deref(), args.config_path.as_deref())?;
+        config.apply_cli_overrides(args);
+        config.apply_params_file()?;
+        Ok(config)
+    }
+
+    /// Load configuration from file if it exists, otherwise use defaults.
+    /// Keys missing from the file keep the value of its network's preset.
     pub fn load(path: &Path) -> Result<Self, String> {
-        // Try to load from file, but fall back to defaults if file doesn't exist
-        if path.exists() {
-            let content = fs::read_to_string(path)
-                .map_err(|e| format!("Failed to read config file: {}", e))?;
+        let mut config = Self::layered(None, Some(path))?;
+        config.apply_params_file()?;
+        Ok(config)
+    }
 
-            let config: Config = toml::from_str(&content)
-                .map_err(|e| format!("Failed to parse config: {}", e))?;
+    /// Override the consensus parameters with those of `network.params_file`,
+    /// if set. Only a devnet takes a params file.
+    pub fn apply_params_file(&mut self) -> Result<(), String> {
+        let Some(path) = &self.network.params_file else {
+            return Ok(());
+        };
+        if self.network.network_id != "devnet" {
+            return Err(format!(
+                "network.params_file is only supported on devnet, not {}",
+                self.network.network_id
+            ));
+        }
+        ParamsOverride::load(path)?.apply(&mut self.consensus);
+        Ok(())
+    }
 
-            Ok(config)
-        } else {
-            // Use defaults if file not found
-            Ok(Config::default())
+    /// The preset of `network`, or of the network named in the file at `path`,
+    /// with the keys set in that file on top, except for the network itself.
+    /// A missing file sets nothing.
+    pub fn layered(network: Option<&str>, path: Option<&Path>) -> Result<Self, String> {
+        let file = match path.filter(|path| path.exists()) {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read config file: {}", e))?;
+                let table: toml::Table = toml::from_str(&content)
+                    .map_err(|e| parse_error(path, &content, &e))?;
+                Some((path, content, table))
+            }
+            None => None,
+        };
+
+        let file_network = file.as_ref().and_then(|(_, _, table)| table.get("network")?.get("network_id")?.as_str());
+        let mut config = Self::for_network(network.or(file_network).unwrap_or("mainnet"))?;
+
+        if let Some((path, content, table)) = file {
+            let mut merged = toml::Table::try_from(&config).map_err(|e| format!("Failed to serialize config: {}", e))?;
+            merge_tables(&mut merged, table);
+            config = merged.try_into().map_err(|e: toml::de::Error| {
+                // Deserializing the file alone locates type errors, unless it
+                // first misses a key the preset provides
+                match toml::from_str::<Config>(&content) {
+                    Err(file_error) if file_error.span().is_some() && !file_error.message().starts_with("missing field") => {
+                        parse_error(path, &content, &file_error)
+                    }
+                    _ => format!("Failed to parse config {}: {}", path.display(), e.message().trim_end()),
+                }
+            })?;
+        }
+        // The network asked for wins over the file's
+        if let Some(network) = network {
+            config.network.network_id = network.to_string();
+        }
+        Ok(config)
+    }
+
+    /// Write the default configuration to `path` as a commented TOML file.
+    /// An existing file is left untouched.
+    pub fn write_default(path: &Path) -> Result<(), String> {
+        if path.exists() {
+            return Err(format!("Config file {} already exists", path.display()));
+        }
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
         }
+        fs::write(path, default_template()).map_err(|e| format!("Failed to write config file: {}", e))
     }
 
     /// Load default configuration for network
@@ -92,25 +676,59 @@ impl Config {
             "testnet" => {
                 config.network.network_id = "testnet".to_string();
             }
+            "simnet" => {
+                // Local test network: trivial proof of work, quickly spendable
+                // coinbases and halvings within reach of a test run
+                config.network.network_id = "simnet".to_string();
+                config.consensus.coinbase_maturity = 10;
+                config.consensus.subsidy_halving_interval = 2_100;
+                config.consensus.block_bits = consensus_pow::target::MAX_TARGET_BITS;
+            }
             "devnet" => {
                 config.network.network_id = "devnet".to_string();
             }
             _ => return Err(format!("Unknown network: {}", network)),
         }
+        let genesis = core_genesis::genesis(network.parse()?);
+        config.network.genesis_hash = hex_encode(genesis.hash.as_bytes());
+        config.network.genesis_timestamp = genesis.timestamp;
+        config.apply_network_ports(network);
 
         Ok(config)
     }
 
+    /// Use the default ports of `network` for every listener
+    pub fn apply_network_ports(&mut self, network: &str) {
+        if let Some(ports) = NetworkPorts::for_network(network) {
+            self.p2p.port = ports.p2p;
+            self.rpc.port = ports.rpc;
+            self.rpc.grpc_port = ports.grpc;
+            self.metrics.port = ports.metrics;
+        }
+    }
+
     /// Override config with CLI arguments
     pub fn apply_cli_overrides(&mut self, args: &crate::cli::Args) {
         if let Some(data_dir) = &args.data_dir {
             self.storage.data_dir = data_dir.clone();
         }
 
+        if let Some(params_file) = &args.params_file {
+            self.network.params_file = Some(params_file.clone());
+        }
+
         if let Some(rpc_port) = args.rpc_port {
             self.rpc.port = rpc_port;
         }
 
+        if let Some(rpc_listen) = &args.rpc_listen {
+            self.rpc.bind_address = rpc_listen.clone();
+        }
+
+        if let Some(p2p_listen) = &args.p2p_listen {
+            self.p2p.listen_address = p2p_listen.clone();
+        }
+
         if let Some(p2p_port) = args.p2p_port {
             self.p2p.port = p2p_port;
         }
@@ -121,7 +739,52 @@ impl Config {
 
         if args.enable_mining {
             self.mining.enabled = true;
-            self.mining.mining_address = args.mining_address.clone();
+        }
+
+        // Keep the file's address unless one is given
+        if let Some(mining_address) = &args.mining_address {
+            self.mining.mining_address = Some(mining_address.clone());
+        }
+
+        if let Some(keystore) = &args.wallet_keystore {
+            self.wallet.keystore = Some(keystore.clone());
+        }
+
+        if args.prune {
+            self.storage.enable_pruning = true;
+        }
+
+        if args.archive {
+            self.storage.enable_pruning = false;
+        }
+
+        if args.no_upnp {
+            self.p2p.enable_upnp = false;
+        }
+
+        if args.p2p_encryption {
+            self.p2p.enable_encryption = true;
+        }
+
+        if args.p2p_require_encryption {
+            self.p2p.enable_encryption = true;
+            self.p2p.allow_plaintext = false;
+        }
+
+        if let Some(interval) = args.status_interval {
+            self.status.interval_secs = interval;
+        }
+
+        if args.status_json {
+            self.status.json = true;
+        }
+
+        if let Some(level) = &args.log_level {
+            self.log.level = level.clone();
+        }
+
+        if let Some(dir) = &args.log_dir {
+            self.log.dir = Some(dir.clone());
         }
 
         if let Some(peers) = &args.bootstrap_peers {
@@ -132,6 +795,119 @@ impl Config {
     }
 }
 
+/// Comments written above the tables and keys of the default config file
+const TEMPLATE_COMMENTS: &[(&str, &str)] = &[
+    ("network", "Network the node joins; `jiopad --network` selects one with its default ports. A devnet takes params_file = \"<path>\", a TOML file overriding any of ghostdag_k, max_block_parents, target_time_per_block, difficulty_window_size, max_block_mass, max_tx_mass, coinbase_maturity, initial_subsidy, subsidy_halving_interval, experimental_subnetworks, max_block_gas and storage_mass_activation"),
+    ("network.network_id", "mainnet, testnet, simnet or devnet"),
+    ("network.genesis_hash", "Hex encoded hash of the expected genesis block"),
+    ("consensus", "Consensus parameters; all nodes of a network must agree on them"),
+    ("consensus.coinbase_maturity", "Blocks (DAA score) before a coinbase output can be spent"),
+    ("consensus.block_bits", "Compact difficulty bits blocks declare until the selected chain below them fills a difficulty window of difficulty_window_size blocks"),
+    ("consensus.initial_subsidy", "Block subsidy before the first halving, in sompi"),
+    ("consensus.subsidy_halving_interval", "Blocks between halvings of the subsidy; at least 1"),
+    ("consensus.max_block_mass", "Heaviest block accepted; at least max_tx_mass"),
+    ("consensus.max_tx_mass", "Heaviest transaction accepted, by estimated size"),
+    ("consensus.checkpoints", "Blocks the chain must merge, as \"<DAA score>:<hash>\"; chains passing these DAA scores without them are rejected"),
+    ("consensus.experimental_subnetworks", "Accept transactions of subnetworks other than native and coinbase, which may carry gas; devnet only"),
+    ("consensus.max_block_gas", "Gas all transactions of a block may use together; at least 1 with experimental_subnetworks"),
+    ("consensus.storage_mass_activation", "DAA score from which transactions must commit to at least their storage mass; never when unset"),
+    ("storage", "Database location and pruning"),
+    ("storage.data_dir", "Directory holding the database and peer lists; created if missing"),
+    ("storage.db_cache_size", "Database cache size in bytes"),
+    ("storage.enable_pruning", "Delete block bodies deeper than pruning_depth; false keeps a full archive that peers can sync history from"),
+    ("storage.pruning_depth", "Blue score below the virtual's beyond which block bodies are pruned"),
+    ("rpc", "WebSocket JSON-RPC server"),
+    ("rpc.bind_address", "Comma separated listen hosts; IPv6 allowed, `*` binds all IPv4 and IPv6 interfaces"),
+    ("rpc.max_connections", "WebSocket clients served at once; further ones are refused until one disconnects"),
+    ("rpc.max_concurrent_requests", "Requests of one client handled at once; its further requests wait"),
+    ("rpc.max_queued_messages", "Responses waiting to be written to one client before it is disconnected as too slow"),
+    ("rpc.extranonce_size", "Bytes at the end of the block template coinbase payload that pools fill with an extranonce on submitBlockWithCoinbase; 0 reserves none"),
+    ("mining", "Built-in miner. Set mining_address = \"<address>\" to enable it"),
+    ("p2p", "Peer to peer networking"),
+    ("p2p.listen_address", "Comma separated listen hosts; IPv6 allowed, `*` binds all IPv4 and IPv6 interfaces"),
+    ("p2p.min_peers", "Outbound peers the connection manager keeps connected"),
+    ("p2p.max_peers", "Upper limit of connected peers; at least min_peers"),
+    ("p2p.bootstrap_peers", "Permanent peers as \"host:port\", redialed whenever they disconnect"),
+    ("p2p.ban_threshold", "Misbehavior score at which a peer is banned"),
+    ("p2p.ban_duration_secs", "How long a banned peer is refused, in seconds"),
+    ("p2p.rate_limits", "Per-peer limits on received messages and bytes, by message type"),
+    ("metrics", "Prometheus metrics endpoint, served at /metrics"),
+    ("status", "Periodic status report; interval_secs = 0 disables it"),
+    ("mempool", "Mempool relay policy"),
+    ("mempool.min_relay_feerate", "Sompi per gram; outputs worth less than the fee to create and spend them at this rate are dust and refused"),
+    ("mempool.fee_exempt_addresses", "Transactions paying only to these addresses are relayed below min_relay_feerate"),
+    ("mempool.persist", "Keep pending transactions in mempool.dat across restarts; each is validated again on startup"),
+    ("mempool.max_ancestors", "Unconfirmed transactions a transaction may chain on, itself included; longer chains are refused"),
+    ("mempool.max_descendants", "Unconfirmed transactions that may chain on a pooled transaction, itself included"),
+    ("mempool.max_package_mass", "Heaviest chain of unconfirmed ancestors or descendants, in grams"),
+    ("wallet", "Wallet of the node. Set keystore = \"<path>\" to unlock it at startup, with the password from JIOPAD_WALLET_PASSWORD or a prompt"),
+    ("log", "Log level: trace, debug, info, warn or error, or an EnvFilter directive such as \"info,network=debug\". Set dir = \"<path>\" to also write size-rotated log files"),
+    ("log.max_file_size", "Size in bytes at which jiopad.log is rotated"),
+    ("log.max_files", "Rotated log files kept; older ones are deleted"),
+];
+
+/// The default configuration as TOML, with comments documenting its tables and keys
+pub fn default_template() -> String {
+    let body = toml::to_string_pretty(&Config::default()).expect("default config serializes");
+    let mut template = String::from("# jiopad configuration\n");
+    let mut table = String::new();
+    for line in body.lines() {
+        let key = if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = name.to_string();
+            table.clone()
+        } else if let Some((key, _)) = line.split_once(" = ") {
+            format!("{}.{}", table, key)
+        } else {
+            String::new()
+        };
+        if let Some((_, comment)) = TEMPLATE_COMMENTS.iter().find(|(name, _)| *name == key) {
+            let _ = writeln!(template, "# {}", comment);
+        }
+        let _ = writeln!(template, "{}", line);
+    }
+    template
+}
+
+/// Describe a TOML error in the config file at `path` by line and column
+/// Set the keys of `overrides` in `base`, merging tables key by key
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => merge_tables(base, overrides),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn parse_error(path: &Path, content: &str, e: &toml::de::Error) -> String {
+    match e.span().and_then(|span| content.get(..span.start)) {
+        Some(before) => {
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+            format!(
+                "Failed to parse config {} at line {}, column {}: {}",
+                path.display(),
+                line,
+                column,
+                e.message().trim_end()
+            )
+        }
+        None => format!("Failed to parse config {}: {}", path.display(), e.message().trim_end()),
+    }
+}
+
+/// Create `dir` if needed and check that files can be created in it
+fn check_writable(dir: &Path) -> Result<(), ConfigError> {
+    let not_writable = |e: std::io::Error| ConfigError::DataDirNotWritable { path: dir.to_path_buf(), reason: e.to_string() };
+    fs::create_dir_all(dir).map_err(not_writable)?;
+    let probe = dir.join(".write_test");
+    fs::write(&probe, b"").map_err(not_writable)?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Self {
         // Compute deterministic genesis hash from consensus core default genesis so config matches runtime
@@ -143,6 +919,7 @@ impl Default for Config {
                 network_id: "mainnet".to_string(),
                 genesis_hash: genesis_hash_hex,
                 genesis_timestamp: genesis.timestamp,
+                params_file: None,
             },
             consensus: ConsensusConfig {
                 ghostdag_k: 18,
@@ -151,6 +928,15 @@ impl Default for Config {
                 difficulty_window_size: 2641,
                 max_block_size: 1_000_000,
                 coinbase_maturity: 100,
+                block_bits: default_block_bits(),
+                initial_subsidy: default_initial_subsidy(),
+                subsidy_halving_interval: default_subsidy_halving_interval(),
+                max_block_mass: default_max_block_mass(),
+                max_tx_mass: default_max_tx_mass(),
+                checkpoints: Vec::new(),
+                experimental_subnetworks: false,
+                max_block_gas: 0,
+                storage_mass_activation: None,
             },
             storage: StorageConfig {
                 data_dir: PathBuf::from("./data"),
@@ -161,8 +947,12 @@ impl Default for Config {
             rpc: RpcConfig {
                 enabled: true,
                 bind_address: "127.0.0.1".to_string(),
-                port: 16110,
+                port: NetworkPorts::MAINNET.rpc,
+                grpc_port: NetworkPorts::MAINNET.grpc,
                 max_connections: 100,
+                max_concurrent_requests: default_max_concurrent_requests(),
+                max_queued_messages: default_max_queued_messages(),
+                extranonce_size: 0,
             },
             mining: MiningConfig {
                 enabled: false,
@@ -171,11 +961,23 @@ impl Default for Config {
             },
             p2p: P2PConfig {
                 listen_address: "0.0.0.0".to_string(),
-                port: 16111,
+                port: NetworkPorts::MAINNET.p2p,
+                min_peers: default_min_peers(),
                 max_peers: 50,
                 bootstrap_peers: vec![],
                 enable_upnp: true,
+                enable_encryption: false,
+                allow_plaintext: true,
+                identity_key_path: None,
+                ban_threshold: default_ban_threshold(),
+                ban_duration_secs: default_ban_duration_secs(),
+                rate_limits: RateLimits::default(),
             },
+            metrics: MetricsConfig::default(),
+            status: StatusConfig::default(),
+            mempool: MempoolConfig::default(),
+            wallet: WalletConfig::default(),
+            log: LogConfig::default(),
         }
     }
 }
@@ -4,13 +4,46 @@ use consensus::consensus::types::ConsensusConfig as CoreConsensusConfig;
 use consensus::consensus::storage::ConsensusStorage;
 use consensus::consensus::ghostdag::{GhostdagManager, GhostdagProtocol, stores::GhostdagStore};
 use consensus::consensus::difficulty::DifficultyManager;
+use consensus::consensus::validation::signature::verify_transaction_signatures;
 use consensus::consensus::validation::{BlockValidator, HeaderValidator, TransactionValidator, ContextualValidator};
+use consensus::consensus::validation::transaction_validator::MAX_MONEY;
+use consensus::process::coinbase::CoinbaseProcessor;
+use consensus::process::consistency::{ConsistencyChecker, ConsistencyReport, Finding, RepairOutcome, Severity};
+use consensus::process::pruning::{PruningConfig, PruningManager};
 use consensus::pipeline::{BlockProcessor, HeaderProcessor, BodyProcessor, VirtualProcessor, DepsManager};
 use consensus::consensus::dag::{BlockRelations, ReachabilityStore, DagTopology};
+use consensus_core::config::genesis::genesis;
+use consensus_core::config::params::Params;
+use consensus_core::header::Header;
+use consensus_core::constants::STORAGE_MASS_PARAMETER;
+use consensus_core::time::{Clock, NetworkAdjustedClock, SystemClock};
+use consensus_core::tx::Transaction;
+use consensus_core::utxo::{UtxoCollection, UtxoView};
 use consensus_core::{Hash, ZERO_HASH};
-use consensus_core::config::genesis as core_genesis;
 use std::sync::Arc;
 
+/// Hash GHOSTDAG is rooted at: the configured genesis hash, or zero if it is not valid hex
+pub fn ghostdag_genesis(network_config: &crate::config::NetworkConfig) -> Hash {
+    if network_config.genesis_hash == "0000000000000000000000000000000000000000000000000000000000000000" {
+        ZERO_HASH
+    } else {
+        Hash::try_from_slice(&hex::decode(&network_config.genesis_hash).unwrap_or(vec![0; 32])[..32]).unwrap_or(ZERO_HASH)
+    }
+}
+
+/// Genesis header of the configured network, whose bits start the blue work count
+pub fn genesis_header(network_config: &crate::config::NetworkConfig) -> Result<Header, String> {
+    Ok(Header::from(&genesis(network_config.network_id.parse()?)))
+}
+
+/// A consistency finding as one line: the block it concerns, if any, and what is wrong
+pub fn describe_finding(finding: &Finding) -> String {
+    match finding.hash {
+        Some(hash) => format!("{}: {}", hash, finding.message),
+        None => finding.message.clone(),
+    }
+}
+
 /// Consensus manager that coordinates all consensus components
 pub struct ConsensusManager {
     config: ConsensusConfig,
@@ -20,33 +53,66 @@ pub struct ConsensusManager {
     storage: Arc<ConsensusStorage>,
     dag_topology: Arc<DagTopology>,
     virtual_processor: Arc<VirtualProcessor>,
+    pruning: Arc<PruningManager>,
+    transaction_validator: Arc<TransactionValidator>,
+    contextual_validator: Arc<ContextualValidator>,
+    clock: Arc<NetworkAdjustedClock>,
 }
 
 impl ConsensusManager {
     /// Create a new consensus manager
     pub async fn new(config: &ConsensusConfig, storage: Arc<StorageManager>, network_config: &crate::config::NetworkConfig) -> Result<Self, String> {
+        Self::with_local_clock(config, storage, network_config, Arc::new(SystemClock)).await
+    }
+
+    /// Create a consensus manager reading the local time from `local_clock`, which
+    /// peers' clocks then adjust
+    pub async fn with_local_clock(
+        config: &ConsensusConfig,
+        storage: Arc<StorageManager>,
+        network_config: &crate::config::NetworkConfig,
+        local_clock: Arc<dyn Clock>,
+    ) -> Result<Self, String> {
         // Convert config to core consensus config
         let core_config = CoreConsensusConfig {
+            network: network_config.network_id.parse()?,
             ghostdag_k: config.ghostdag_k,
             max_block_parents: config.max_block_parents,
             target_time_per_block: config.target_time_per_block,
             difficulty_window_size: config.difficulty_window_size,
             max_block_size: config.max_block_size,
             coinbase_maturity: config.coinbase_maturity,
+            initial_subsidy: config.initial_subsidy,
+            subsidy_halving_interval: config.subsidy_halving_interval,
+            checkpoints: config.checkpoints().map_err(|e| e.to_string())?,
         };
 
     // Get consensus storage from the provided StorageManager (so bootstrap uses the persistent manager)
     let consensus_storage = storage.consensus_storage();
 
-        // Bootstrap genesis block into storage if empty
-        // If there are no blocks stored yet, construct the default genesis and persist it.
-        if consensus_storage.block_store().block_count() == 0 {
-            // Build default genesis from consensus core
-            let genesis_block = core_genesis::default_genesis();
-            let genesis_block: consensus_core::block::Block = (&genesis_block).into();
-            // store as the first block and apply to UTXO set with daa score 0
-            let _ = consensus_storage.apply_block(&genesis_block, genesis_block.header.daa_score);
+        // The network's genesis must be the configured one, and the database must
+        // be built on it; an empty database is bootstrapped with it. A database
+        // imported from a snapshot has headers and UTXOs but no blocks, and its
+        // UTXO set already holds the genesis coinbase.
+        let genesis_block = core_config.genesis_block();
+        if ghostdag_genesis(network_config) != genesis_block.header.hash {
+            return Err(format!(
+                "Configured genesis hash {} is not the {} genesis {}",
+                network_config.genesis_hash, core_config.network, genesis_block.header.hash
+            ));
         }
+        consensus_storage.init_genesis(&genesis_block).map_err(|e| format!("Refusing to start: {}", e))?;
+        // Reopening a data directory with other consensus parameters would
+        // mix blocks validated by different rules
+        storage.check_params_hash(config.params_hash()).map_err(|e| format!("Refusing to start: {}", e))?;
+
+        // Pruning drops old block bodies unless the node is archival; genesis always stays
+        let pruning = Arc::new(PruningManager::new(PruningConfig {
+            pruning_depth: storage.config().pruning_depth,
+            enabled: storage.config().enable_pruning,
+            ..Default::default()
+        }));
+        pruning.mark_block_protected(genesis_block.header.hash);
 
         // Initialize DAG components
         let block_relations = Arc::new(BlockRelations::new());
@@ -64,22 +130,55 @@ impl ConsensusManager {
         let ghostdag_manager = Arc::new(GhostdagManager::new(ghostdag_protocol.clone(), ghostdag_store.clone()));
 
         // Initialize genesis block
-        let genesis_hash = if network_config.genesis_hash == "0000000000000000000000000000000000000000000000000000000000000000" {
-            ZERO_HASH
-        } else {
-            Hash::try_from_slice(&hex::decode(&network_config.genesis_hash).unwrap_or(vec![0; 32])[..32]).unwrap_or(ZERO_HASH)
-        };
+        let genesis_hash = ghostdag_genesis(network_config);
         reachability_store.init_genesis(genesis_hash);
-        ghostdag_manager.init_genesis(genesis_hash);
+        ghostdag_manager.init_genesis(&genesis_block.header);
 
-        // Initialize difficulty manager
-        let difficulty_manager = Arc::new(DifficultyManager::new());
+        // GHOSTDAG data is kept in memory only; recompute it for the stored headers,
+        // parents first since blue scores grow from parent to child
+        let mut headers: Vec<_> = consensus_storage.block_store().get_all_headers().into_iter().filter(|header| !header.direct_parents().is_empty()).collect();
+        headers.sort_by_key(|header| (header.daa_score, header.hash));
+        for header in &headers {
+            if let Err(e) = ghostdag_manager.add_block(header) {
+                tracing::debug!("Failed to recompute GHOSTDAG data of {}: {}", header.hash, e);
+            }
+        }
+
+        // Initialize difficulty manager; blocks declare the configured bits until
+        // the selected chain below them fills a difficulty window
+        let difficulty_manager = Arc::new(
+            DifficultyManager::with_params(config.target_time_per_block, config.difficulty_window_size as usize)
+                .with_initial_bits(config.block_bits),
+        );
 
         // Initialize validators
-        let transaction_validator = Arc::new(TransactionValidator::new());
-        let header_validator = Arc::new(HeaderValidator::new());
-        let block_validator = Arc::new(BlockValidator::new(header_validator.clone(), transaction_validator.clone()));
-        let contextual_validator = Arc::new(ContextualValidator::new(block_validator.clone(), transaction_validator.clone()));
+        let transaction_validator = Arc::new(
+            TransactionValidator::with_params(config.max_tx_mass, MAX_MONEY, core_config.coinbase_maturity)
+                .with_experimental_subnetworks(config.experimental_subnetworks),
+        );
+        let clock = Arc::new(NetworkAdjustedClock::new(local_clock));
+        let header_validator = Arc::new(
+            HeaderValidator::new()
+                .with_max_block_parents(config.max_block_parents)
+                .with_clock(clock.clone())
+                .with_checkpoints(core_config.checkpoints.clone())
+                .with_pruning_depth(storage.config().pruning_depth),
+        );
+        let block_validator = Arc::new(
+            BlockValidator::new(header_validator.clone(), transaction_validator.clone())
+                .with_max_block_mass(config.max_block_mass)
+                .with_max_block_gas(config.max_block_gas),
+        );
+        let signature_threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let contextual_validator = Arc::new(
+            ContextualValidator::new(block_validator.clone(), transaction_validator.clone())
+                .with_params(&Params {
+                    storage_mass_parameter: STORAGE_MASS_PARAMETER,
+                    storage_mass_activation: config.storage_mass_activation,
+                    ..Params::default()
+                })
+                .with_signature_verification(signature_threads),
+        );
 
         // Initialize dependency manager
         let deps_manager = Arc::new(DepsManager::new());
@@ -95,15 +194,15 @@ impl ConsensusManager {
 
         let body_processor = Arc::new(BodyProcessor::new(
             block_validator,
-            contextual_validator,
+            contextual_validator.clone(),
             consensus_storage.block_store(),
             consensus_storage.utxo_set(),
-        ));
+        ).with_coinbase_processor(CoinbaseProcessor::new(core_config.clone())));
 
-        let virtual_processor = Arc::new(VirtualProcessor::new(
-            ghostdag_manager.clone(),
-            consensus_storage.block_store(),
-        ));
+        let virtual_processor = Arc::new(
+            VirtualProcessor::new(ghostdag_manager.clone(), consensus_storage.block_store())
+                .with_fee_store(consensus_storage.fee_store()),
+        );
 
         let block_processor = Arc::new(BlockProcessor::new(
             header_processor,
@@ -112,9 +211,9 @@ impl ConsensusManager {
             ghostdag_manager.clone(),
             consensus_storage.clone(),
             deps_manager,
-        ));
+        ).with_clock(clock.clone()));
 
-        Ok(Self {
+        let manager = Self {
             config: config.clone(),
             block_processor,
             ghostdag_manager,
@@ -122,7 +221,34 @@ impl ConsensusManager {
             storage: consensus_storage,
             dag_topology,
             virtual_processor,
-        })
+            pruning,
+            transaction_validator,
+            contextual_validator,
+            clock,
+        };
+
+        // A crash between store writes shows up here; `check-db --repair` fixes the chain index
+        let report = manager.check_consistency(true);
+        for finding in &report.findings {
+            match finding.severity {
+                Severity::Error => tracing::error!("Database consistency: {}", describe_finding(finding)),
+                Severity::Warning => tracing::warn!("Database consistency: {}", describe_finding(finding)),
+            }
+        }
+        if !report.is_ok() {
+            tracing::error!("Database is inconsistent; run `jiopad check-db --repair`");
+        }
+        Ok(manager)
+    }
+
+    /// Consensus settings the node was started with
+    pub fn config(&self) -> &ConsensusConfig {
+        &self.config
+    }
+
+    /// Network-adjusted clock block timestamps are validated against
+    pub fn clock(&self) -> Arc<NetworkAdjustedClock> {
+        self.clock.clone()
     }
 
     /// Get block processor
@@ -157,8 +283,76 @@ impl ConsensusManager {
         0
     }
 
+    /// Blue score of the virtual block, or 0 if it cannot be computed yet
+    pub fn virtual_blue_score(&self) -> u64 {
+        self.block_processor.get_virtual_block_data(self.config.max_block_parents)
+            .map(|data| data.ghostdag_data.blue_score)
+            .unwrap_or(0)
+    }
+
+    /// Selected parent of the virtual block and its blue score
+    pub fn sink(&self) -> Option<(Hash, u64)> {
+        let data = self.block_processor.get_virtual_block_data(self.config.max_block_parents).ok()?;
+        let sink = data.ghostdag_data.selected_parent;
+        Some((sink, self.ghostdag_manager.get_ghostdag_data(&sink).map_or(0, |data| data.blue_score)))
+    }
+
+    /// Selected parent of `hash` and its blue score
+    pub fn selected_parent(&self, hash: &Hash) -> Option<(Hash, u64)> {
+        let parent = self.ghostdag_manager.get_ghostdag_data(hash)?.selected_parent;
+        let blue_score = self.ghostdag_manager.get_ghostdag_data(&parent)?.blue_score;
+        Some((parent, blue_score))
+    }
+
     /// Get virtual processor
     pub fn virtual_processor(&self) -> Arc<VirtualProcessor> {
         self.virtual_processor.clone()
     }
+
+    /// Check `tx` against the consensus rules and the current UTXO set, as a
+    /// block built on the virtual would, returning its fee. `pending` are
+    /// unconfirmed transactions, such as mempool ones, applied first: `tx` may
+    /// spend their outputs but not the outputs they spend.
+    pub fn validate_transaction(&self, tx: &Transaction, pending: &[Transaction]) -> Result<u64, String> {
+        let utxo_set = self.storage.utxo_set();
+        let mut confirmed = UtxoCollection::new();
+        for input in tx.inputs.iter().chain(pending.iter().flat_map(|parent| parent.inputs.iter())) {
+            if let Some(entry) = utxo_set.get_utxo(&input.previous_outpoint) {
+                confirmed.insert(input.previous_outpoint, entry);
+            }
+        }
+        let daa_score = self.virtual_blue_score();
+        let mut view = UtxoView::new(&confirmed);
+        for parent in pending {
+            view.apply_transaction(parent, daa_score);
+        }
+        let fee = self.transaction_validator.validate_transaction_with_utxo(tx, &view, daa_score).map_err(|e| e.to_string())?;
+        self.contextual_validator.validate_storage_mass(tx, &view, daa_score).map_err(|e| e.to_string())?;
+        verify_transaction_signatures(tx, &view).map_err(|e| e.to_string())?;
+        Ok(fee)
+    }
+
+    /// Whether the node keeps every block body instead of pruning old ones
+    pub fn is_archival(&self) -> bool {
+        self.pruning.is_archival()
+    }
+
+    /// Check the stores against each other; a quick check covers the chain index near its tip
+    pub fn check_consistency(&self, quick: bool) -> ConsistencyReport {
+        ConsistencyChecker::new(self.storage.clone(), self.ghostdag_manager.clone()).check(quick)
+    }
+
+    /// Roll the chain index back to the last consistent chain block of `report` and
+    /// discard bodies without headers
+    pub fn repair_consistency(&self, report: &ConsistencyReport) -> Result<RepairOutcome, String> {
+        ConsistencyChecker::new(self.storage.clone(), self.ghostdag_manager.clone()).repair(report).map_err(|e| e.to_string())
+    }
+
+    /// Delete one batch of block bodies below the pruning depth, returning their hashes
+    pub fn prune(&self) -> Vec<Hash> {
+        let ghostdag = &self.ghostdag_manager;
+        self.pruning.prune_bodies(&self.storage.block_store(), self.virtual_blue_score(), |hash| {
+            ghostdag.get_ghostdag_data(hash).map(|data| data.blue_score)
+        })
+    }
 }
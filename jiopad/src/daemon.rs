@@ -1,11 +1,17 @@
 use crate::config::Config;
+use crate::metrics::{MetricsRegistry, MetricsServer, NodeMetrics, ReorgDetector};
+use crate::status::{StatusFormat, StatusReporter, StatusSample};
 use crate::ui;
 use tokio::signal;
 use tokio::sync::broadcast;
 use tokio::time::{interval, Duration};
-use tracing::info;
+use tracing::{info, warn};
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Instant;
+use wallet::HotWallet;
+
+/// Environment variable holding the password of the wallet keystore; prompted for when unset
+pub const WALLET_PASSWORD_ENV: &str = "JIOPAD_WALLET_PASSWORD";
 
 // Real implementations
 pub use crate::consensus_manager::ConsensusManager;
@@ -13,6 +19,7 @@ pub use crate::network_manager::NetworkManager;
 pub use crate::rpc_server::RpcServer;
 pub use crate::mining_coordinator::MiningCoordinator;
 pub use crate::mempool::Mempool;
+use crate::mempool::{ChainLimits, MEMPOOL_FILE, MEMPOOL_LOAD_TIME_LIMIT};
 pub use crate::sync_manager::SyncManager;
 pub use crate::storage_manager::StorageManager;
 
@@ -27,11 +34,15 @@ pub struct Daemon {
     mining: Option<Arc<MiningCoordinator>>,
     mempool: Arc<Mempool>,
     sync: Arc<SyncManager>,
+    metrics_registry: Arc<MetricsRegistry>,
+    metrics: NodeMetrics,
 }
 
 impl Daemon {
     /// Create new daemon instance
     pub async fn new(config: Config) -> Result<Self, String> {
+        config.validate().map_err(|e| format!("Invalid configuration: {}", e))?;
+
         ui::print_section("Initializing Components");
         
         // Create shutdown channel
@@ -53,19 +64,53 @@ impl Daemon {
         );
         ui::print_component_status("Consensus Engine", ui::ComponentStatus::Running);
 
+        // Metrics are registered up front so every component can update them
+        let metrics_registry = Arc::new(MetricsRegistry::new());
+        let metrics = NodeMetrics::new(&metrics_registry);
+
         // Initialize mempool
         ui::print_component_status("Mempool", ui::ComponentStatus::Starting);
         info!("Initializing mempool");
-        let mempool = Arc::new(
-            Mempool::new()
-        );
+        let fee_exempt_scripts = config
+            .mempool
+            .fee_exempt_addresses
+            .iter()
+            .map(|address| {
+                wallet::Address::to_script_pub_key(address).map_err(|e| format!("Invalid fee exempt address {}: {}", address, e))
+            })
+            .collect::<Result<_, String>>()?;
+        let validator = consensus.clone();
+        let mut mempool = Mempool::new()
+            .with_metrics(metrics.clone())
+            .with_accept_non_standard(config.mempool.accept_non_standard)
+            .with_min_relay_feerate(config.mempool.min_relay_feerate)
+            .with_fee_exempt_scripts(fee_exempt_scripts)
+            .with_chain_limits(ChainLimits {
+                max_ancestors: config.mempool.max_ancestors,
+                max_descendants: config.mempool.max_descendants,
+                max_package_mass: config.mempool.max_package_mass,
+            })
+            .with_validator(Arc::new(move |tx, pending| validator.validate_transaction(tx, pending)));
+        if config.mempool.persist {
+            mempool = mempool.with_persistence(config.storage.data_dir.join(MEMPOOL_FILE));
+            // Blocks mined while the node was down may have spent saved transactions' inputs
+            match mempool.restore(|tx, pending| consensus.validate_transaction(tx, pending), MEMPOOL_LOAD_TIME_LIMIT) {
+                Ok(outcome) => info!(
+                    "Restored {} mempool transactions ({} no longer valid, {} dropped at the load time limit)",
+                    outcome.restored, outcome.discarded, outcome.expired
+                ),
+                Err(e) => warn!("Failed to restore the mempool: {}", e),
+            }
+        }
+        let mempool = Arc::new(mempool);
         ui::print_component_status("Mempool", ui::ComponentStatus::Running);
 
         // Initialize network layer
         ui::print_component_status("P2P Network", ui::ComponentStatus::Starting);
         info!("Initializing P2P network");
         let network = Arc::new(
-            NetworkManager::new(&config.p2p, consensus.clone()).await?
+            NetworkManager::new(&config.p2p, &config.storage.data_dir, consensus.clone()).await?
+                .with_mempool(mempool.clone())
         );
         ui::print_component_status("P2P Network", ui::ComponentStatus::Running);
 
@@ -77,12 +122,24 @@ impl Daemon {
         );
         ui::print_component_status("Sync Manager", ui::ComponentStatus::Running);
 
+        // Unlock the node's wallet (optional)
+        let wallet = match &config.wallet.keystore {
+            Some(keystore) => {
+                ui::print_component_status("Wallet", ui::ComponentStatus::Starting);
+                let wallet = Arc::new(open_wallet(keystore)?);
+                info!("Wallet {} unlocked, mining address {}", keystore.display(), wallet.mining_address());
+                ui::print_component_status("Wallet", ui::ComponentStatus::Running);
+                Some(wallet)
+            }
+            None => None,
+        };
+
         // Initialize RPC server (optional)
         let rpc_server = if config.rpc.enabled {
             ui::print_component_status("RPC Server", ui::ComponentStatus::Starting);
             info!("Initializing RPC server on {}:{}", config.rpc.bind_address, config.rpc.port);
             let server = Arc::new(
-                RpcServer::new(&config.rpc, consensus.clone(), network.clone(), mempool.clone()).await?
+                RpcServer::new(&config.rpc, &config.network.network_id, consensus.clone(), network.clone(), sync.clone(), mempool.clone(), wallet.clone()).await?
             );
             ui::print_component_status("RPC Server", ui::ComponentStatus::Running);
             Some(server)
@@ -94,13 +151,15 @@ impl Daemon {
         let mining = if config.mining.enabled {
             ui::print_component_status("Mining Coordinator", ui::ComponentStatus::Starting);
             info!("Initializing mining coordinator");
-            let addr = config.mining.mining_address.as_ref()
+            // Pay to the configured address, or else to the node's wallet
+            let addr = config.mining.mining_address.clone()
+                .or_else(|| wallet.as_ref().map(|wallet| wallet.mining_address()))
                 .ok_or("Mining enabled but no mining address provided")?;
 
             let mc_config = crate::mining_coordinator::MiningCoordinatorConfig {
                 enabled: true,
                 num_workers: config.mining.num_threads,
-                mining_address: addr.clone(),
+                mining_address: addr,
             };
 
             let coordinator = Arc::new(
@@ -122,64 +181,112 @@ impl Daemon {
             mining,
             mempool,
             sync,
+            metrics_registry,
+            metrics,
         })
     }
 
+    /// RPC server, when enabled
+    pub fn rpc_server(&self) -> Option<Arc<RpcServer>> {
+        self.rpc_server.clone()
+    }
+
+    /// Sender that stops a running daemon
+    pub fn shutdown_handle(&self) -> broadcast::Sender<()> {
+        self.shutdown_tx.clone()
+    }
+
     /// Run the daemon
     pub async fn run(self) -> Result<(), String> {
         ui::print_section("Starting Services");
         info!("Starting JIOPad daemon");
 
         let shutdown_rx = self.shutdown_tx.subscribe();
-        let start_time = Instant::now();
 
         // Start all components
         self.start_components().await?;
 
         ui::print_status("✓", "JIOPad daemon is now running", ui::StatusType::Success);
         ui::print_status("ℹ", "Press Ctrl+C to stop the daemon", ui::StatusType::Info);
-        println!();
 
-        // Start status update task
-        let status_handle = {
+        // Serve metrics and keep the sampled ones current
+        let metrics_handles = if self.config.metrics.enabled {
+            let server = MetricsServer::bind(
+                &self.config.metrics.bind_address,
+                self.config.metrics.port,
+                self.metrics_registry.clone(),
+            ).await?;
+            info!("Serving metrics on http://{}/metrics", server.local_addr()?);
+            Some((server.spawn(), self.spawn_metrics_sampler()))
+        } else {
+            None
+        };
+
+        // Start periodic status report
+        let status_handle = (self.config.status.interval_secs > 0).then(|| {
             let consensus = self.consensus.clone();
-            let _network = self.network.clone();
+            let network = self.network.clone();
             let mempool = self.mempool.clone();
             let mining = self.mining.clone();
-            let start_time = start_time;
-            
+            let sync = self.sync.clone();
+            let period = Duration::from_secs(self.config.status.interval_secs);
+            let format = if self.config.status.json { StatusFormat::Json } else { StatusFormat::Text };
+
             tokio::spawn(async move {
-                let mut interval = interval(Duration::from_secs(30));
+                let mut reporter = StatusReporter::new(format);
+                let mut interval = interval(period);
+                // The first tick completes immediately
+                interval.tick().await;
                 loop {
                     interval.tick().await;
-                    
-                    // Collect status information
-                    let block_count = consensus.storage().block_store().block_count() as u64;
-                    let peer_count = 0; // TODO: Get from network manager
-                    let mempool_size = mempool.size();
-                    let is_mining = mining.is_some();
-                    let mining_hashrate = 0.0; // TODO: Get from mining coordinator
-                    
-                    let status = ui::NodeStatus {
-                        uptime: start_time.elapsed(),
-                        block_count,
-                        peer_count,
-                        is_mining,
-                        mining_hashrate,
-                        mempool_size,
-                        sync_percentage: 100.0, // TODO: Calculate actual sync percentage
+
+                    let (peers_inbound, peers_outbound) = network.peer_counts();
+                    let sync_progress = sync.sync_progress();
+                    let sample = StatusSample {
+                        blue_score: consensus.virtual_blue_score(),
+                        tips: consensus.virtual_processor().get_tips().len(),
+                        peers_inbound,
+                        peers_outbound,
+                        mempool_size: mempool.size(),
+                        sync_progress: sync.get_sync_progress(),
+                        sync_bytes_per_sec: sync_progress.bytes_per_second,
+                        sync_eta: sync_progress.eta,
+                        block_count: consensus.storage().block_store().block_count() as u64,
+                        hashrate: mining.as_ref()
+                            .filter(|mining| mining.is_mining())
+                            .and_then(|mining| mining.get_mining_stats().ok())
+                            .map(|stats| stats.overall_hash_rate),
                     };
-                    
-                    print!("{}", status);
+                    if let Err(e) = reporter.emit(sample) {
+                        warn!("Failed to write status report: {}", e);
+                    }
                 }
             })
+        });
+
+        // Prune old block bodies unless running as an archive
+        let pruning_handle = if self.consensus.is_archival() {
+            info!("Archival mode: keeping all block bodies");
+            None
+        } else {
+            info!("Pruning block bodies deeper than {} blue score", self.config.storage.pruning_depth);
+            Some(self.spawn_pruning())
         };
 
         // Wait for shutdown signal
         self.wait_for_shutdown(shutdown_rx).await;
         
         // Cancel status updates
-        status_handle.abort();
+        if let Some(handle) = status_handle {
+            handle.abort();
+        }
+        if let Some(handle) = pruning_handle {
+            handle.abort();
+        }
+        if let Some((server, sampler)) = metrics_handles {
+            server.abort();
+            sampler.abort();
+        }
 
         // Stop all components
         self.stop_components().await?;
@@ -187,7 +294,69 @@ impl Daemon {
         Ok(())
     }
 
-    async fn start_components(&self) -> Result<(), String> {
+    /// Sample node state into the metrics every second
+    fn spawn_metrics_sampler(&self) -> tokio::task::JoinHandle<()> {
+        let consensus = self.consensus.clone();
+        let network = self.network.clone();
+        let mempool = self.mempool.clone();
+        let mining = self.mining.clone();
+        let sync = self.sync.clone();
+        let metrics = self.metrics.clone();
+        let rpc = self.rpc_server.as_ref().map(|rpc| rpc.coordinator());
+
+        tokio::spawn(async move {
+            let mut reorgs = ReorgDetector::default();
+            let mut interval = interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+
+                let (peers_inbound, peers_outbound) = network.peer_counts();
+                metrics.blocks.observe(consensus.storage().block_store().block_count() as u64);
+                metrics.sample_block_timings(&consensus.block_processor().timings());
+                metrics.blue_score.set(consensus.virtual_blue_score() as f64);
+                metrics.tips.set(consensus.virtual_processor().get_tips().len() as f64);
+                metrics.mempool_size.set(mempool.size() as f64);
+                metrics.peers_inbound.set(peers_inbound as f64);
+                metrics.peers_outbound.set(peers_outbound as f64);
+                metrics.sync_progress.set(sync.get_sync_progress());
+                if let Some(rpc) = &rpc {
+                    metrics.rpc_connections.set(rpc.active_connections() as f64);
+                    metrics.rpc_clients_dropped.observe(rpc.dropped_clients());
+                }
+                metrics.hashrate.set(
+                    mining.as_ref()
+                        .filter(|mining| mining.is_mining())
+                        .and_then(|mining| mining.get_mining_stats().ok())
+                        .map_or(0.0, |stats| stats.overall_hash_rate),
+                );
+                if let Some((sink, blue_score)) = consensus.sink() {
+                    if reorgs.observe(sink, blue_score, |hash| consensus.selected_parent(hash)) {
+                        metrics.reorgs.inc();
+                    }
+                }
+            }
+        })
+    }
+
+    /// Delete a batch of prunable block bodies every ten seconds
+    fn spawn_pruning(&self) -> tokio::task::JoinHandle<()> {
+        let consensus = self.consensus.clone();
+
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(10));
+            loop {
+                interval.tick().await;
+                // Reading every stored block is slow, keep it off the async workers
+                let batch = consensus.clone();
+                let pruned = tokio::task::spawn_blocking(move || batch.prune()).await.unwrap_or_default();
+                if !pruned.is_empty() {
+                    info!("Pruned {} block bodies", pruned.len());
+                }
+            }
+        })
+    }
+
+        async fn start_components(&self) -> Result<(), String> {
         // Start network layer
         ui::print_component_status("Network Layer", ui::ComponentStatus::Starting);
         info!("Starting network layer");
@@ -244,6 +413,14 @@ impl Daemon {
         info!("Stopping network layer");
         self.network.stop().await?;
 
+        // Nothing can add transactions any more
+        if self.config.mempool.persist {
+            match self.mempool.save() {
+                Ok(saved) => info!("Saved {} mempool transactions", saved),
+                Err(e) => warn!("Failed to save the mempool: {}", e),
+            }
+        }
+
         info!("All components stopped");
         Ok(())
     }
@@ -264,3 +441,13 @@ impl Daemon {
         let _ = self.shutdown_tx.send(());
     }
 }
+
+/// Unlock `keystore` with the password from [`WALLET_PASSWORD_ENV`], prompting for it if unset
+fn open_wallet(keystore: &Path) -> Result<HotWallet, String> {
+    let password = match std::env::var(WALLET_PASSWORD_ENV) {
+        Ok(password) => password,
+        Err(_) => rpassword::prompt_password(format!("Password for wallet {}: ", keystore.display()))
+            .map_err(|e| format!("Failed to read wallet password: {}", e))?,
+    };
+    HotWallet::open(keystore, &password).map_err(|e| format!("Failed to open wallet {}: {}", keystore.display(), e))
+}
@@ -16,6 +16,9 @@ pub mod storage_manager;
 pub mod sync_manager;
 pub mod mining_coordinator;
 pub mod mempool;
+pub mod logging;
+pub mod metrics;
 pub mod network_manager;
+pub mod status;
 pub mod ui;
 
@@ -0,0 +1,178 @@
+//! Logging of the node
+//!
+//! Logs go to stdout (stderr in JSON status mode) and, when a log directory is
+//! configured, to [`LOG_FILE`] in it through a non-blocking writer. The file is
+//! rotated to `jiopad.log.1`, `jiopad.log.2`, … once it reaches the size limit,
+//! dropping the oldest beyond the configured count. The level filter can be
+//! changed at runtime through the [`LogHandle`], e.g. to turn on
+//! `network=debug` for a while without restarting.
+
+use crate::config::LogConfig;
+use rpc_core::LogControl;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::{Directive, EnvFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+/// Name of the current log file in the log directory
+pub const LOG_FILE: &str = "jiopad.log";
+
+static GLOBAL_HANDLE: OnceLock<Arc<LogHandle>> = OnceLock::new();
+
+/// Log file that moves itself aside once it grows past a size limit
+pub struct RotatingFile {
+    dir: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Append to [`LOG_FILE`] in `dir`, creating the directory if missing.
+    /// `max_files` rotated files are kept besides the current one.
+    pub fn open(dir: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(LOG_FILE))?;
+        let size = file.metadata()?.len();
+        Ok(Self { dir: dir.to_path_buf(), max_size, max_files, file, size })
+    }
+
+    /// Path of the `n`th most recent rotated file; 0 is the current one
+    pub fn path(dir: &Path, n: usize) -> PathBuf {
+        match n {
+            0 => dir.join(LOG_FILE),
+            n => dir.join(format!("{}.{}", LOG_FILE, n)),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(Self::path(&self.dir, 0))?;
+        } else {
+            let oldest = Self::path(&self.dir, self.max_files);
+            if oldest.exists() {
+                fs::remove_file(oldest)?;
+            }
+            for n in (0..self.max_files).rev() {
+                let from = Self::path(&self.dir, n);
+                if from.exists() {
+                    fs::rename(from, Self::path(&self.dir, n + 1))?;
+                }
+            }
+            self.file = OpenOptions::new().create(true).append(true).open(Self::path(&self.dir, 0))?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Lines longer than the limit still go to a file of their own
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Changes the level filter of a running subscriber
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    directives: Mutex<Vec<String>>,
+}
+
+impl LogHandle {
+    /// The filter in effect, as `EnvFilter` directives
+    pub fn filter(&self) -> String {
+        self.directives.lock().unwrap().join(",")
+    }
+}
+
+impl LogControl for LogHandle {
+    fn set_log_level(&self, target: &str, level: &str) -> Result<String, String> {
+        let level = level.to_ascii_lowercase();
+        level.parse::<LevelFilter>().map_err(|_| format!("Invalid log level: {}", level))?;
+
+        let mut directives = self.directives.lock().unwrap();
+        let mut updated: Vec<String> = directives.iter().filter(|directive| directive_target(directive) != target).cloned().collect();
+        if target.is_empty() {
+            updated.insert(0, level);
+        } else {
+            updated.push(format!("{}={}", target, level));
+        }
+
+        let filter = updated.join(",");
+        let env_filter = EnvFilter::try_new(&filter).map_err(|e| format!("Invalid log target {}: {}", target, e))?;
+        self.filter.reload(env_filter).map_err(|e| e.to_string())?;
+        *directives = updated;
+        Ok(filter)
+    }
+}
+
+/// Target a directive applies to; empty for the default level
+fn directive_target(directive: &str) -> &str {
+    directive.split_once('=').map_or("", |(target, _)| target)
+}
+
+/// Subscriber logging as `config` says, with `RUST_LOG` taking precedence
+/// over its level. Logs for the file are written by a background thread until
+/// the returned guard is dropped.
+pub fn build(
+    config: &LogConfig,
+    stderr: bool,
+) -> Result<(impl Subscriber + Send + Sync, Arc<LogHandle>, Option<WorkerGuard>), String> {
+    let level = std::env::var("RUST_LOG").ok().filter(|level| !level.trim().is_empty()).unwrap_or_else(|| config.level.clone());
+    // Invalid directives are ignored, as `EnvFilter::new` does
+    let directives: Vec<String> =
+        level.split(',').map(str::trim).filter(|directive| directive.parse::<Directive>().is_ok()).map(String::from).collect();
+    let (filter, reload_handle) = reload::Layer::new(EnvFilter::new(directives.join(",")));
+
+    // Keep stdout free for JSON status reports
+    let console_writer = if stderr { BoxMakeWriter::new(io::stderr) } else { BoxMakeWriter::new(io::stdout) };
+    let console = fmt::layer().with_target(true).with_thread_ids(true).with_writer(console_writer);
+
+    let (file, guard) = match &config.dir {
+        Some(dir) => {
+            let writer = RotatingFile::open(dir, config.max_file_size, config.max_files)
+                .map_err(|e| format!("Failed to open log file in {}: {}", dir.display(), e))?;
+            let (writer, guard) = tracing_appender::non_blocking(writer);
+            let layer = fmt::layer().with_target(true).with_thread_ids(true).with_ansi(false).with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let subscriber = Registry::default().with(filter).with(console).with(file);
+    let handle = Arc::new(LogHandle { filter: reload_handle, directives: Mutex::new(directives) });
+    Ok((subscriber, handle, guard))
+}
+
+/// Install the subscriber of [`build`] for the whole process. Keep the
+/// returned guard until exit so buffered file logs get written.
+pub fn init(config: &LogConfig, stderr: bool) -> Result<Option<WorkerGuard>, String> {
+    let (subscriber, handle, guard) = build(config, stderr)?;
+    tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())?;
+    let _ = GLOBAL_HANDLE.set(handle);
+    Ok(guard)
+}
+
+/// Handle of the subscriber installed by [`init`]
+pub fn global_handle() -> Option<Arc<LogHandle>> {
+    GLOBAL_HANDLE.get().cloned()
+}
@@ -1,5 +1,13 @@
-use jiopad::{Daemon, Config, cli, ui};
+use jiopad::{Daemon, Config, cli, logging, ui};
+use jiopad::consensus_manager::{describe_finding, genesis_header, ConsensusManager};
+use jiopad::storage_manager::StorageManager;
+use consensus::process::consistency::Severity;
+use consensus::process::snapshot::TrustedCheckpoint;
+use consensus_core::Hash;
+use std::path::Path;
 use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
 use tracing::{info, error};
 
 #[tokio::main]
@@ -7,24 +15,88 @@ async fn main() {
     // Parse command line arguments
     let args = cli::parse_args();
 
-    // Initialize logging
-    init_logging(&args);
+    // In JSON status mode stdout carries only status reports
+    ui::set_quiet(args.status_json);
+
+    // Defaults, then the network preset, the config file and the CLI flags
+    let config = Config::resolve(&args).unwrap_or_else(|e| {
+        ui::print_status("✗", &format!("Failed to load configuration: {}", e), ui::StatusType::Error);
+        error!("Failed to load configuration: {}", e);
+        process::exit(1);
+    });
 
     // Print startup banner
-    let network = args.network.as_deref().unwrap_or("mainnet");
-    ui::print_banner(env!("CARGO_PKG_VERSION"), network);
-
-    // Load configuration (use defaults unless config file is provided)
-    let mut config = if let Some(network) = &args.network {
-        Config::for_network(network).unwrap_or_else(|_| Config::default())
-    } else if let Some(config_path) = &args.config_path {
-        Config::load(config_path).unwrap_or_else(|_| Config::default())
-    } else {
-        Config::default()
-    };
+    ui::print_banner(env!("CARGO_PKG_VERSION"), &config.network.network_id);
 
-    // Apply CLI overrides
-    config.apply_cli_overrides(&args);
+    // Initialize logging; the guard flushes the log file when main returns
+    let _log_guard = logging::init(&config.log, args.status_json).unwrap_or_else(|e| {
+        ui::print_status("✗", &format!("Failed to initialize logging: {}", e), ui::StatusType::Error);
+        process::exit(1);
+    });
+    info!("JIOPad {} starting on {}", env!("CARGO_PKG_VERSION"), config.network.network_id);
+    info!("Data directory: {}", config.storage.data_dir.display());
+    info!("Configuration: {}", serde_json::to_string(&config).unwrap_or_default());
+
+    match args.command {
+        Some(cli::Command::Reindex) => {
+            if let Err(e) = reindex(&config).await {
+                ui::print_status("✗", &format!("Reindex failed: {}", e), ui::StatusType::Error);
+                error!("Reindex failed: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Some(cli::Command::VerifyChain { depth, check_bodies }) => {
+            match verify_chain(&config, depth, check_bodies).await {
+                Ok(true) => return,
+                Ok(false) => process::exit(2),
+                Err(e) => {
+                    ui::print_status("✗", &format!("Chain verification failed: {}", e), ui::StatusType::Error);
+                    error!("Chain verification failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(cli::Command::VerifyUtxo) => {
+            match verify_utxo(&config).await {
+                Ok(true) => return,
+                Ok(false) => process::exit(2),
+                Err(e) => {
+                    ui::print_status("✗", &format!("UTXO verification failed: {}", e), ui::StatusType::Error);
+                    error!("UTXO verification failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(cli::Command::CheckDb { repair }) => {
+            match check_db(&config, repair).await {
+                Ok(true) => return,
+                Ok(false) => process::exit(2),
+                Err(e) => {
+                    ui::print_status("✗", &format!("Database check failed: {}", e), ui::StatusType::Error);
+                    error!("Database check failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(cli::Command::ExportSnapshot { path }) => {
+            if let Err(e) = export_snapshot(&config, &path).await {
+                ui::print_status("✗", &format!("Snapshot export failed: {}", e), ui::StatusType::Error);
+                error!("Snapshot export failed: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Some(cli::Command::ImportSnapshot { path, checkpoint, utxo_commitment }) => {
+            if let Err(e) = import_snapshot(&config, &path, &checkpoint, &utxo_commitment).await {
+                ui::print_status("✗", &format!("Snapshot import failed: {}", e), ui::StatusType::Error);
+                error!("Snapshot import failed: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
 
     // Print configuration summary
     ui::print_config_summary(&config);
@@ -50,15 +122,145 @@ async fn main() {
     info!("JIOPad daemon stopped gracefully");
 }
 
-fn init_logging(args: &cli::Args) {
-    use tracing_subscriber::{EnvFilter, fmt};
+/// Rebuild the secondary indexes of the node's database, reporting every 10%
+async fn reindex(config: &Config) -> Result<(), String> {
+    ui::print_section("Reindexing");
+    let storage = StorageManager::new(&config.storage).await?;
+    let mut reported = None;
+    let stats = storage.reindex(|done, total| {
+        let percent = done * 100 / total.max(1);
+        if reported.map_or(true, |last| percent >= last + 10 || done == total) {
+            reported = Some(percent);
+            ui::print_status("ℹ", &format!("Read {}/{} blocks ({}%)", done, total, percent), ui::StatusType::Info);
+        }
+    })?;
+    ui::print_status(
+        "✓",
+        &format!(
+            "Indexed {} transactions and {} unspent outputs from {} blocks and {} headers",
+            stats.transactions, stats.unspent_outputs, stats.blocks, stats.headers
+        ),
+        ui::StatusType::Success,
+    );
+    Ok(())
+}
 
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new(&args.log_level));
+/// Re-validate stored blocks, listing each one that fails. Returns whether all passed.
+async fn verify_chain(config: &Config, depth: Option<usize>, check_bodies: bool) -> Result<bool, String> {
+    ui::print_section("Verifying chain");
+    let storage = StorageManager::new(&config.storage).await?;
+    let mut reported = None;
+    let report = storage.verify_chain(depth, check_bodies, |done, total| {
+        let percent = done * 100 / total.max(1);
+        if reported.map_or(true, |last| percent >= last + 10 || done == total) {
+            reported = Some(percent);
+            ui::print_status("ℹ", &format!("Checked {}/{} blocks ({}%)", done, total, percent), ui::StatusType::Info);
+        }
+    });
+    for failure in &report.failures {
+        ui::print_status(
+            "✗",
+            &format!("Block {} (DAA score {}): {}", failure.hash, failure.daa_score, failure.error),
+            ui::StatusType::Error,
+        );
+    }
+    let summary = format!("Checked {} headers and {} bodies, {} failed", report.checked, report.bodies_checked, report.failures.len());
+    if report.is_ok() {
+        ui::print_status("✓", &summary, ui::StatusType::Success);
+    } else {
+        ui::print_status("✗", &summary, ui::StatusType::Error);
+    }
+    Ok(report.is_ok())
+}
 
-    fmt()
-        .with_env_filter(filter)
-        .with_target(true)
-        .with_thread_ids(true)
-        .init();
+/// Compare the stored UTXO commitment with one recomputed from the set. Returns whether they match.
+async fn verify_utxo(config: &Config) -> Result<bool, String> {
+    ui::print_section("Verifying UTXO set");
+    let storage = StorageManager::new(&config.storage).await?;
+    let check = storage.verify_utxo_commitment()?;
+    ui::print_status("ℹ", &format!("Stored commitment:     {}", check.stored), ui::StatusType::Info);
+    ui::print_status("ℹ", &format!("Recomputed commitment: {}", check.computed), ui::StatusType::Info);
+    if check.is_ok() {
+        ui::print_status("✓", &format!("Commitment matches {} UTXOs", check.utxos), ui::StatusType::Success);
+    } else {
+        ui::print_status("✗", &format!("Commitment does not match {} UTXOs", check.utxos), ui::StatusType::Error);
+    }
+    Ok(check.is_ok())
+}
+
+/// Check the node's stores against each other, listing each finding, and with
+/// `repair` roll back to the last consistent chain block. Returns whether the
+/// database is consistent, after any repair.
+async fn check_db(config: &Config, repair: bool) -> Result<bool, String> {
+    ui::print_section("Checking database");
+    let storage = Arc::new(StorageManager::new(&config.storage).await?);
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await?;
+    let mut report = consensus.check_consistency(false);
+    for finding in &report.findings {
+        let (symbol, status) = match finding.severity {
+            Severity::Error => ("✗", ui::StatusType::Error),
+            Severity::Warning => ("⚠", ui::StatusType::Warning),
+        };
+        ui::print_status(symbol, &format!("{}: {}", finding.severity, describe_finding(finding)), status);
+    }
+    ui::print_status(
+        "ℹ",
+        &format!("Checked {} chain blocks and {} headers, {} findings", report.chain_blocks, report.headers, report.findings.len()),
+        ui::StatusType::Info,
+    );
+
+    if repair && report.is_repairable() {
+        let outcome = consensus.repair_consistency(&report)?;
+        let tip = outcome.chain_tip.map_or("none".to_string(), |(blue_score, hash)| format!("{} (blue score {})", hash, blue_score));
+        ui::print_status(
+            "ℹ",
+            &format!("Rolled back {} chain blocks to {}, removed {} bodies without headers", outcome.rolled_back.len(), tip, outcome.bodies_removed),
+            ui::StatusType::Info,
+        );
+        report = consensus.check_consistency(false);
+    }
+
+    if report.is_ok() {
+        ui::print_status("✓", "Database is consistent", ui::StatusType::Success);
+    } else if repair {
+        ui::print_status("✗", "Database is still inconsistent; resync or import a snapshot", ui::StatusType::Error);
+    } else {
+        ui::print_status("✗", "Database is inconsistent; rerun with --repair", ui::StatusType::Error);
+    }
+    Ok(report.is_ok())
+}
+
+/// Write a snapshot of the node's UTXO set and headers to `path`
+async fn export_snapshot(config: &Config, path: &Path) -> Result<(), String> {
+    ui::print_section("Exporting snapshot");
+    let storage = StorageManager::new(&config.storage).await?;
+    let snapshot = storage.export_snapshot(path)?;
+    ui::print_status("ℹ", &format!("Checkpoint: {}", snapshot.checkpoint), ui::StatusType::Info);
+    ui::print_status("ℹ", &format!("Commitment: {}", snapshot.commitment), ui::StatusType::Info);
+    ui::print_status(
+        "✓",
+        &format!("Wrote {} UTXOs and {} headers to {}", snapshot.utxos.len(), snapshot.headers.len(), path.display()),
+        ui::StatusType::Success,
+    );
+    Ok(())
+}
+
+/// Bootstrap the node's empty database from the snapshot at `path`, which must
+/// be at `checkpoint` with the UTXO set committing to `utxo_commitment`
+async fn import_snapshot(config: &Config, path: &Path, checkpoint: &str, utxo_commitment: &str) -> Result<(), String> {
+    ui::print_section("Importing snapshot");
+    let trusted = TrustedCheckpoint {
+        hash: Hash::from_str(checkpoint).map_err(|e| format!("Invalid checkpoint {}: {}", checkpoint, e))?,
+        utxo_commitment: Hash::from_str(utxo_commitment).map_err(|e| format!("Invalid UTXO commitment {}: {}", utxo_commitment, e))?,
+    };
+    let storage = StorageManager::new(&config.storage).await?;
+    let genesis = genesis_header(&config.network)?;
+    let imported = storage.import_snapshot(path, &genesis, config.consensus.ghostdag_k, &trusted)?;
+    ui::print_status("ℹ", &format!("Checkpoint: {} (blue score {})", imported.checkpoint, imported.blue_score), ui::StatusType::Info);
+    ui::print_status(
+        "✓",
+        &format!("Imported {} UTXOs and {} headers; the node syncs forward from the checkpoint", imported.utxos, imported.headers),
+        ui::StatusType::Success,
+    );
+    Ok(())
 }
@@ -1,37 +1,430 @@
-use consensus_core::tx::Transaction;
+//! Memory pool of the node
+//!
+//! Every pooled transaction tracks its in-pool ancestors and descendants with
+//! their combined fee and mass. A transaction is refused if it would make a
+//! chain of unconfirmed transactions exceed the [`ChainLimits`], and a full pool
+//! evicts by the descendant sets, so a parent always leaves with its children.
+//!
+//! With persistence enabled the pool is written to [`MEMPOOL_FILE`] in the data
+//! directory on shutdown and read back on startup, so a restart doesn't drop
+//! pending transactions. The file is the magic bytes `JIOMEMP`, the version
+//! (u32) and an entry count, followed by each entry's received time (unix
+//! milliseconds), fee and canonically encoded transaction (length + bytes).
+//! Integers are little-endian and counts and lengths are u64, as in
+//! [`consensus_core::encoding`].
+
+use crate::metrics::NodeMetrics;
+use consensus_core::constants::{MIN_TRANSACTION_FEE_RATE, STORAGE_MASS_PARAMETER};
+use consensus_core::encoding::{decode_transaction, encode_transaction};
+use consensus_core::mass::{dust_threshold, utxo_plurality};
+use consensus_core::tx::script_class::{classify, ScriptClass};
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutpoint};
 use consensus_core::Hash;
 use rpc_core::{MempoolInterface, model::MempoolEntry};
-use std::collections::HashMap;
+use rpc_core::package::PackageFeerate;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Name of the mempool file in the data directory
+pub const MEMPOOL_FILE: &str = "mempool.dat";
+
+/// Magic bytes every mempool file starts with
+pub const MEMPOOL_MAGIC: &[u8; 7] = b"JIOMEMP";
+
+/// Format version written after the magic bytes
+pub const MEMPOOL_VERSION: u32 = 1;
+
+/// How long restoring the saved pool may take; entries left then are dropped
+pub const MEMPOOL_LOAD_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+/// Default of [`ChainLimits::max_ancestors`]
+pub const DEFAULT_MAX_ANCESTORS: usize = 25;
+
+/// Default of [`ChainLimits::max_descendants`]
+pub const DEFAULT_MAX_DESCENDANTS: usize = 25;
+
+/// Default of [`ChainLimits::max_package_mass`]
+pub const DEFAULT_MAX_PACKAGE_MASS: u64 = 100_000;
+
+/// Checks a transaction against the UTXO set with the effects of the pooled
+/// transactions given applied, returning its fee
+pub type TransactionValidator = Arc<dyn Fn(&Transaction, &[Transaction]) -> Result<u64, String> + Send + Sync>;
+
+/// A pooled transaction with what the pool knows about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolTransaction {
+    pub transaction: Transaction,
+    /// When the transaction entered the pool, in unix milliseconds
+    pub received_time: u64,
+    /// Fee in sompi, 0 where it wasn't computed on admission
+    pub fee: u64,
+}
+
+impl MempoolTransaction {
+    fn to_entry(&self) -> MempoolEntry {
+        MempoolEntry { fee: self.fee, transaction: self.transaction.clone(), is_orphan: false }
+    }
+}
+
+/// Limits on chains of unconfirmed transactions. Counts and masses include the
+/// transaction itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainLimits {
+    /// Most transactions a pooled transaction and its in-pool ancestors may number
+    pub max_ancestors: usize,
+    /// Most transactions a pooled transaction and its in-pool descendants may number
+    pub max_descendants: usize,
+    /// Heaviest ancestor or descendant set, in grams
+    pub max_package_mass: u64,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self {
+            max_ancestors: DEFAULT_MAX_ANCESTORS,
+            max_descendants: DEFAULT_MAX_DESCENDANTS,
+            max_package_mass: DEFAULT_MAX_PACKAGE_MASS,
+        }
+    }
+}
+
+/// In-pool relatives of a pooled transaction; counts and packages include the
+/// transaction itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainStats {
+    pub ancestors: usize,
+    /// Combined fee and mass of the transaction with its ancestors
+    pub ancestor_package: PackageFeerate,
+    pub descendants: usize,
+    /// Combined fee and mass of the transaction with its descendants
+    pub descendant_package: PackageFeerate,
+}
+
+/// Outcome of [`Mempool::restore`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MempoolRestore {
+    /// Entries admitted back into the pool
+    pub restored: usize,
+    /// Entries no longer valid, such as those spending outputs mined while offline
+    pub discarded: usize,
+    /// Entries not checked before the load time limit ran out
+    pub expired: usize,
+}
+
+/// What the pool knows of a pooled transaction's relatives
+#[derive(Debug, Clone)]
+struct Relatives {
+    own: PackageFeerate,
+    ancestors: HashSet<Hash>,
+    descendants: HashSet<Hash>,
+    ancestor_package: PackageFeerate,
+    descendant_package: PackageFeerate,
+}
+
+/// Pooled transactions with their relatives, updated as transactions enter and
+/// leave. Transactions leave with their descendants when evicted and with their
+/// ancestors when mined, so removing one never disconnects its relatives.
+#[derive(Default)]
+struct Pool {
+    transactions: HashMap<Hash, MempoolTransaction>,
+    relatives: HashMap<Hash, Relatives>,
+    /// Pooled transactions by the id of a transaction they spend from, pooled or not
+    spenders: HashMap<Hash, HashSet<Hash>>,
+    /// The pooled transaction spending each outpoint spent in the pool
+    spent: HashMap<TransactionOutpoint, Hash>,
+}
+
+impl Pool {
+    /// The first input of `tx` spending an outpoint a pooled transaction spends, with that transaction
+    fn conflict(&self, tx: &Transaction) -> Option<(usize, Hash)> {
+        tx.inputs.iter().enumerate().find_map(|(index, input)| self.spent.get(&input.previous_outpoint).map(|spender| (index, *spender)))
+    }
+
+    /// In-pool ancestors and descendants `tx` would have
+    fn relatives_of(&self, tx: &Transaction) -> (HashSet<Hash>, HashSet<Hash>) {
+        let mut ancestors = HashSet::new();
+        for input in &tx.inputs {
+            let parent = input.previous_outpoint.transaction_id;
+            if let Some(relatives) = self.relatives.get(&parent) {
+                ancestors.insert(parent);
+                ancestors.extend(&relatives.ancestors);
+            }
+        }
+        // Children can precede their parent only where nothing validates them
+        let mut descendants = HashSet::new();
+        for child in self.spenders.get(&tx.hash()).into_iter().flatten() {
+            descendants.insert(*child);
+            descendants.extend(&self.relatives[child].descendants);
+        }
+        (ancestors, descendants)
+    }
+
+    fn package<'a>(&self, members: impl IntoIterator<Item = &'a Hash>) -> PackageFeerate {
+        members.into_iter().fold(PackageFeerate::default(), |package, member| package + self.relatives[member].own)
+    }
+
+    /// Count and package of the set `relatives` of a transaction worth
+    /// `package` with them, once `added` join the set
+    fn grown(&self, relatives: &HashSet<Hash>, package: PackageFeerate, added: &HashSet<Hash>) -> (usize, PackageFeerate) {
+        let new: Vec<&Hash> = added.iter().filter(|member| !relatives.contains(member)).collect();
+        (relatives.len() + new.len() + 1, package + self.package(new))
+    }
+
+    /// Refuse a transaction worth `own` with in-pool `ancestors` and `descendants`
+    /// if it or one of its relatives would exceed `limits`
+    fn check_limits(
+        &self,
+        own: PackageFeerate,
+        ancestors: &HashSet<Hash>,
+        descendants: &HashSet<Hash>,
+        limits: &ChainLimits,
+    ) -> Result<(), String> {
+        let check = |hash: Option<&Hash>, kind: &str, (count, package): (usize, PackageFeerate), max_count: usize| {
+            let subject = hash.map_or("The transaction".to_string(), |hash| format!("Pooled transaction {}", hash));
+            if count > max_count {
+                return Err(format!("{} would have {} {} counting itself; the limit is {}", subject, count, kind, max_count));
+            }
+            if package.mass > limits.max_package_mass {
+                return Err(format!(
+                    "{} would have {} weighing {} grams; the limit is {}",
+                    subject, kind, package.mass, limits.max_package_mass
+                ));
+            }
+            Ok(())
+        };
+        check(None, "ancestors", (ancestors.len() + 1, own + self.package(ancestors)), limits.max_ancestors)?;
+        check(None, "descendants", (descendants.len() + 1, own + self.package(descendants)), limits.max_descendants)?;
+        for ancestor in ancestors {
+            let relatives = &self.relatives[ancestor];
+            let (count, package) = self.grown(&relatives.descendants, relatives.descendant_package, descendants);
+            check(Some(ancestor), "descendants", (count + 1, package + own), limits.max_descendants)?;
+        }
+        for descendant in descendants {
+            let relatives = &self.relatives[descendant];
+            let (count, package) = self.grown(&relatives.ancestors, relatives.ancestor_package, ancestors);
+            check(Some(descendant), "ancestors", (count + 1, package + own), limits.max_ancestors)?;
+        }
+        Ok(())
+    }
+
+    /// Pool `entry`, whose in-pool relatives are `ancestors` and `descendants`,
+    /// adding it and its relatives to the sets of theirs
+    fn insert(&mut self, entry: MempoolTransaction, ancestors: HashSet<Hash>, descendants: HashSet<Hash>) {
+        let hash = entry.transaction.hash();
+        let own = PackageFeerate::new(entry.fee, entry.transaction.calculate_mass());
+        self.relatives.insert(
+            hash,
+            Relatives {
+                own,
+                ancestor_package: own + self.package(&ancestors),
+                descendant_package: own + self.package(&descendants),
+                ancestors: ancestors.clone(),
+                descendants: descendants.clone(),
+            },
+        );
+        let joining_descendants: HashSet<Hash> = descendants.iter().copied().chain([hash]).collect();
+        let joining_ancestors: HashSet<Hash> = ancestors.iter().copied().chain([hash]).collect();
+        for ancestor in &ancestors {
+            let relatives = &self.relatives[ancestor];
+            let new: Vec<Hash> = joining_descendants.difference(&relatives.descendants).copied().collect();
+            let package = self.package(&new);
+            let relatives = self.relatives.get_mut(ancestor).unwrap();
+            relatives.descendants.extend(new);
+            relatives.descendant_package = relatives.descendant_package + package;
+        }
+        for descendant in &descendants {
+            let relatives = &self.relatives[descendant];
+            let new: Vec<Hash> = joining_ancestors.difference(&relatives.ancestors).copied().collect();
+            let package = self.package(&new);
+            let relatives = self.relatives.get_mut(descendant).unwrap();
+            relatives.ancestors.extend(new);
+            relatives.ancestor_package = relatives.ancestor_package + package;
+        }
+        for input in &entry.transaction.inputs {
+            self.spenders.entry(input.previous_outpoint.transaction_id).or_default().insert(hash);
+            self.spent.insert(input.previous_outpoint, hash);
+        }
+        self.transactions.insert(hash, entry);
+    }
+
+    fn remove(&mut self, hash: &Hash) -> Option<MempoolTransaction> {
+        let entry = self.transactions.remove(hash)?;
+        let removed = self.relatives.remove(hash).expect("pooled transactions have relatives");
+        for ancestor in &removed.ancestors {
+            if let Some(relatives) = self.relatives.get_mut(ancestor) {
+                relatives.descendants.remove(hash);
+                relatives.descendant_package = relatives.descendant_package - removed.own;
+            }
+        }
+        for descendant in &removed.descendants {
+            if let Some(relatives) = self.relatives.get_mut(descendant) {
+                relatives.ancestors.remove(hash);
+                relatives.ancestor_package = relatives.ancestor_package - removed.own;
+            }
+        }
+        for input in &entry.transaction.inputs {
+            if self.spent.get(&input.previous_outpoint) == Some(hash) {
+                self.spent.remove(&input.previous_outpoint);
+            }
+            let parent = input.previous_outpoint.transaction_id;
+            if let Some(spenders) = self.spenders.get_mut(&parent) {
+                spenders.remove(hash);
+                if spenders.is_empty() {
+                    self.spenders.remove(&parent);
+                }
+            }
+        }
+        Some(entry)
+    }
+
+    /// The pooled transaction outside `keep` whose descendant set has the lowest
+    /// feerate, ties going to the lower hash
+    fn eviction_candidate(&self, keep: &HashSet<Hash>) -> Option<(&Hash, &Relatives)> {
+        self.relatives
+            .iter()
+            .filter(|(hash, _)| !keep.contains(hash))
+            .min_by(|a, b| a.1.descendant_package.cmp_feerate(&b.1.descendant_package).then(a.0.cmp(b.0)))
+    }
+}
 
 /// Memory pool for pending transactions
 pub struct Mempool {
-    transactions: Arc<RwLock<HashMap<Hash, Transaction>>>,
+    pool: RwLock<Pool>,
     max_size: usize,
+    chain_limits: ChainLimits,
+    metrics: Option<NodeMetrics>,
+    accept_non_standard: bool,
+    min_relay_feerate: u64,
+    validator: Option<TransactionValidator>,
+    /// Scripts that transactions paying only to them may relay below the minimum feerate
+    fee_exempt_scripts: HashSet<ScriptPublicKey>,
+    path: Option<PathBuf>,
 }
 
 impl Mempool {
     /// Create a new mempool
     pub fn new() -> Self {
         Self {
-            transactions: Arc::new(RwLock::new(HashMap::new())),
+            pool: RwLock::new(Pool::default()),
             max_size: 50000, // Default max size
+            chain_limits: ChainLimits::default(),
+            metrics: None,
+            accept_non_standard: false,
+            min_relay_feerate: MIN_TRANSACTION_FEE_RATE,
+            validator: None,
+            fee_exempt_scripts: HashSet::new(),
+            path: None,
         }
     }
 
+    /// Hold at most `max_size` transactions; a full pool evicts the package worth least
+    pub fn with_max_size(self, max_size: usize) -> Self {
+        Self { max_size, ..self }
+    }
+
+    /// Refuse transactions that would make a chain of unconfirmed transactions exceed `chain_limits`
+    pub fn with_chain_limits(self, chain_limits: ChainLimits) -> Self {
+        Self { chain_limits, ..self }
+    }
+
+    /// Save the pool to `path` with [`Self::save`] and restore it from there
+    pub fn with_persistence(self, path: PathBuf) -> Self {
+        Self { path: Some(path), ..self }
+    }
+
+    /// Count accepted and refused transactions in `metrics`
+    pub fn with_metrics(self, metrics: NodeMetrics) -> Self {
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    /// Accept transactions with non-standard output scripts
+    pub fn with_accept_non_standard(self, accept_non_standard: bool) -> Self {
+        Self { accept_non_standard, ..self }
+    }
+
+    /// Minimum feerate in sompi per gram that outputs must be worth spending at
+    pub fn with_min_relay_feerate(self, min_relay_feerate: u64) -> Self {
+        Self { min_relay_feerate, ..self }
+    }
+
+    /// Validate added transactions with `validator`, which also gives the fee
+    /// their feerate is checked with. Without one fees aren't known on
+    /// admission and the minimum feerate isn't enforced.
+    pub fn with_validator(self, validator: TransactionValidator) -> Self {
+        Self { validator: Some(validator), ..self }
+    }
+
+    /// Relay transactions paying only to `scripts` whatever their feerate, such
+    /// as the operator consolidating their own outputs
+    pub fn with_fee_exempt_scripts(self, scripts: HashSet<ScriptPublicKey>) -> Self {
+        Self { fee_exempt_scripts: scripts, ..self }
+    }
+
+    pub fn min_relay_feerate(&self) -> u64 {
+        self.min_relay_feerate
+    }
+
     /// Add a transaction to the mempool
     pub fn add_transaction(&self, tx: Transaction) -> Result<(), String> {
+        let result = self.admit(tx);
+        self.record_admission(&result);
+        result
+    }
+
+    fn admit(&self, tx: Transaction) -> Result<(), String> {
+        let fee = match &self.validator {
+            Some(validate) => {
+                let fee = validate(&tx, &self.pending_effects(&tx))?;
+                self.check_feerate(&tx, fee)?;
+                fee
+            }
+            None => 0,
+        };
+        self.insert_transaction(MempoolTransaction { transaction: tx, received_time: unix_now_millis(), fee })
+    }
+
+    /// Refuse `tx` paying `fee` below the minimum relay feerate unless exempt
+    fn check_feerate(&self, tx: &Transaction, fee: u64) -> Result<(), String> {
+        let exempt = !tx.outputs.is_empty()
+            && tx.outputs.iter().all(|output| self.fee_exempt_scripts.contains(&output.script_public_key));
+        let mass = tx.calculate_mass();
+        if exempt || tx.is_coinbase() || fee as u128 >= self.min_relay_feerate as u128 * mass as u128 {
+            return Ok(());
+        }
+        Err(format!(
+            "Feerate of {:.2} sompi/gram is below the minimum relay feerate of {} sompi/gram",
+            fee as f64 / mass.max(1) as f64,
+            self.min_relay_feerate
+        ))
+    }
+
+    fn record_admission(&self, result: &Result<(), String>) {
+        if let Some(metrics) = &self.metrics {
+            match &result {
+                Ok(()) => metrics.transactions_accepted.inc(),
+                Err(_) => metrics.transactions_rejected.inc(),
+            }
+            metrics.mempool_size.set(self.size() as f64);
+        }
+    }
+
+    fn insert_transaction(&self, entry: MempoolTransaction) -> Result<(), String> {
+        let tx = &entry.transaction;
         let hash = tx.hash();
-        let mut transactions = self.transactions.write().unwrap();
+        let mut pool = self.pool.write().unwrap();
 
-        // Check if already exists
-        if transactions.contains_key(&hash) {
+        // Keyed by transaction id, which excludes signature scripts, so a re-signed
+        // variant of a pooled transaction is treated as a duplicate
+        if pool.transactions.contains_key(&hash) {
             return Err("Transaction already in mempool".to_string());
         }
 
-        // Check size limit
-        if transactions.len() >= self.max_size {
-            return Err("Mempool is full".to_string());
+        // The first spend of an output pooled wins
+        if let Some((index, spender)) = pool.conflict(tx) {
+            return Err(format!("Input {} spends an output pooled transaction {} already spends", index, spender));
         }
 
         // Basic validation (placeholder - would do full validation)
@@ -39,95 +432,287 @@ impl Mempool {
             return Err("Transaction has no inputs".to_string());
         }
 
-        transactions.insert(hash, tx);
+        if !self.accept_non_standard {
+            let non_standard = tx.outputs.iter().position(|output| classify(&output.script_public_key) == ScriptClass::NonStandard);
+            if let Some(index) = non_standard {
+                return Err(format!("Output {} has a non-standard script", index));
+            }
+        }
+
+        // Dust is a relay policy, blocks may still contain it
+        if !tx.is_coinbase() {
+            for (index, output) in tx.outputs.iter().enumerate() {
+                let threshold = dust_threshold(utxo_plurality(&output.script_public_key), STORAGE_MASS_PARAMETER, self.min_relay_feerate);
+                if output.value < threshold {
+                    return Err(format!("Output {} of {} sompi is dust; the threshold is {} sompi", index, output.value, threshold));
+                }
+            }
+        }
+
+        let own = PackageFeerate::new(entry.fee, tx.calculate_mass());
+        let (ancestors, descendants) = pool.relatives_of(tx);
+        pool.check_limits(own, &ancestors, &descendants, &self.chain_limits)?;
+
+        if pool.transactions.len() >= self.max_size {
+            let incoming = own + pool.package(&ancestors);
+            Self::make_room(&mut pool, &entry, incoming, ancestors.union(&descendants).copied().collect())?;
+        }
+
+        // Eviction may have taken descendants that preceded their parent
+        let (ancestors, descendants) = pool.relatives_of(&entry.transaction);
+        pool.insert(entry, ancestors, descendants);
         Ok(())
     }
 
+    /// Evict the pooled transaction whose descendant set has the lowest
+    /// feerate, with that set, if `entry` worth `incoming` with its pooled
+    /// ancestors pays a higher feerate. A cheap parent is kept while its
+    /// children pay for it; the relatives of `entry` in `keep` are never evicted.
+    fn make_room(pool: &mut Pool, entry: &MempoolTransaction, incoming: PackageFeerate, keep: HashSet<Hash>) -> Result<(), String> {
+        let candidate = pool.eviction_candidate(&keep).map(|(hash, relatives)| {
+            let evicted: Vec<Hash> = relatives.descendants.iter().copied().chain([*hash]).collect();
+            (evicted, relatives.descendant_package)
+        });
+        match candidate {
+            Some((evicted, worth)) if incoming.cmp_feerate(&worth).is_gt() => {
+                for victim in &evicted {
+                    pool.remove(victim);
+                }
+                tracing::debug!(
+                    "Evicted {} transaction(s) at {:.2} sompi/gram for {} at {:.2} sompi/gram",
+                    evicted.len(),
+                    worth.feerate(),
+                    entry.transaction.id(),
+                    incoming.feerate()
+                );
+                Ok(())
+            }
+            _ => Err("Mempool is full".to_string()),
+        }
+    }
+
+    /// Pooled transactions with their metadata, oldest first
+    pub fn get_all_entries(&self) -> Vec<MempoolTransaction> {
+        let mut entries: Vec<_> = self.pool.read().unwrap().transactions.values().cloned().collect();
+        entries.sort_by_key(|entry| (entry.received_time, entry.transaction.hash()));
+        entries
+    }
+
+    /// Write the pool to its file, returning the number of transactions saved
+    pub fn save(&self) -> Result<usize, String> {
+        let path = self.path.as_ref().ok_or("Mempool persistence is disabled")?;
+        let entries = self.get_all_entries();
+        // Written aside and renamed, so a crash never leaves a truncated file
+        let partial = path.with_extension("dat.tmp");
+        fs::write(&partial, encode_entries(&entries)).map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        fs::rename(&partial, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(entries.len())
+    }
+
+    /// Pooled transactions to validate `tx` on top of: those whose outputs it
+    /// spends, then those spending the same outputs as it, which hide them
+    pub fn pending_effects(&self, tx: &Transaction) -> Vec<Transaction> {
+        let pool = self.pool.read().unwrap();
+        let parents = tx.inputs.iter().filter_map(|input| pool.transactions.get(&input.previous_outpoint.transaction_id));
+        let conflicts = tx.inputs.iter().filter_map(|input| pool.spent.get(&input.previous_outpoint)).map(|spender| &pool.transactions[spender]);
+        let mut pending: Vec<Transaction> = Vec::new();
+        for entry in parents.chain(conflicts) {
+            if !pending.iter().any(|tx| tx.hash() == entry.transaction.hash()) {
+                pending.push(entry.transaction.clone());
+            }
+        }
+        pending
+    }
+
+    /// Admit the transactions saved in the pool's file again, oldest first.
+    /// Each one must pass `validate`, given the [pending effects](Self::pending_effects)
+    /// of the pool and returning its fee, and the pool's own policy, which
+    /// refuses spending an output an earlier one spends. Entries still unchecked after
+    /// `time_limit` are dropped. A missing file restores nothing.
+    pub fn restore<F>(&self, validate: F, time_limit: Duration) -> Result<MempoolRestore, String>
+    where
+        F: Fn(&Transaction, &[Transaction]) -> Result<u64, String>,
+    {
+        let path = self.path.as_ref().ok_or("Mempool persistence is disabled")?;
+        if !path.exists() {
+            return Ok(MempoolRestore::default());
+        }
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut entries = decode_entries(&bytes).map_err(|e| format!("Invalid mempool file {}: {}", path.display(), e))?;
+        entries.sort_by_key(|entry| (entry.received_time, entry.transaction.hash()));
+
+        let started = Instant::now();
+        let mut outcome = MempoolRestore::default();
+        for (i, mut entry) in entries.iter().cloned().enumerate() {
+            if started.elapsed() > time_limit {
+                outcome.expired = entries.len() - i;
+                break;
+            }
+            let admitted = validate(&entry.transaction, &self.pending_effects(&entry.transaction)).and_then(|fee| {
+                entry.fee = fee;
+                self.insert_transaction(entry.clone())
+            });
+            match admitted {
+                Ok(()) => outcome.restored += 1,
+                Err(e) => {
+                    tracing::debug!("Dropping saved mempool transaction {}: {}", entry.transaction.id(), e);
+                    outcome.discarded += 1;
+                }
+            }
+            self.record_admission(&admitted);
+        }
+        Ok(outcome)
+    }
+
     /// Remove a transaction from the mempool
     pub fn remove_transaction(&self, hash: &Hash) -> Option<Transaction> {
-        let mut transactions = self.transactions.write().unwrap();
-        transactions.remove(hash)
+        let removed = self.pool.write().unwrap().remove(hash).map(|entry| entry.transaction);
+        if let Some(metrics) = &self.metrics {
+            metrics.mempool_size.set(self.size() as f64);
+        }
+        removed
     }
 
     /// Get a transaction by hash
     pub fn get_transaction(&self, hash: &Hash) -> Option<Transaction> {
-        let transactions = self.transactions.read().unwrap();
-        transactions.get(hash).cloned()
+        let pool = self.pool.read().unwrap();
+        pool.transactions.get(hash).map(|entry| entry.transaction.clone())
+    }
+
+    /// In-pool ancestors and descendants of a pooled transaction
+    pub fn chain_stats(&self, hash: &Hash) -> Option<ChainStats> {
+        let pool = self.pool.read().unwrap();
+        pool.relatives.get(hash).map(|relatives| ChainStats {
+            ancestors: relatives.ancestors.len() + 1,
+            ancestor_package: relatives.ancestor_package,
+            descendants: relatives.descendants.len() + 1,
+            descendant_package: relatives.descendant_package,
+        })
     }
 
     /// Get all transactions
     pub fn get_all_transactions(&self) -> Vec<Transaction> {
-        let transactions = self.transactions.read().unwrap();
-        transactions.values().cloned().collect()
+        let pool = self.pool.read().unwrap();
+        pool.transactions.values().map(|entry| entry.transaction.clone()).collect()
     }
 
     /// Get mempool size
     pub fn size(&self) -> usize {
-        let transactions = self.transactions.read().unwrap();
-        transactions.len()
+        let pool = self.pool.read().unwrap();
+        pool.transactions.len()
     }
 
     /// Clear the mempool
     pub fn clear(&self) {
-        let mut transactions = self.transactions.write().unwrap();
-        transactions.clear();
+        let mut pool = self.pool.write().unwrap();
+        *pool = Pool::default();
     }
 
     /// Check if transaction exists in mempool
     pub fn contains(&self, hash: &Hash) -> bool {
-        let transactions = self.transactions.read().unwrap();
-        transactions.contains_key(hash)
+        let pool = self.pool.read().unwrap();
+        pool.transactions.contains_key(hash)
     }
 }
 
 /// Implement the MempoolInterface trait for Mempool
 impl MempoolInterface for Mempool {
     fn add_transaction(&self, tx: Transaction) -> Result<(), String> {
-        let hash = tx.hash();
-        let mut transactions = self.transactions.write().unwrap();
-
-        // Check if already exists
-        if transactions.contains_key(&hash) {
-            return Err("Transaction already in mempool".to_string());
-        }
-
-        // Check size limit
-        if transactions.len() >= self.max_size {
-            return Err("Mempool is full".to_string());
-        }
-
-        // Basic validation (placeholder - would do full validation)
-        if tx.inputs.is_empty() && !tx.is_coinbase() {
-            return Err("Transaction has no inputs".to_string());
-        }
-
-        transactions.insert(hash, tx);
-        Ok(())
+        Mempool::add_transaction(self, tx)
     }
 
     fn remove_transaction(&self, tx_id: &str) -> Result<(), String> {
-        // Parse tx_id as hash (placeholder implementation)
-        Err("Not implemented".to_string())
+        let hash: Hash = tx_id.parse().map_err(|e| format!("Invalid transaction id: {:?}", e))?;
+        Mempool::remove_transaction(self, &hash)
+            .map(|_| ())
+            .ok_or_else(|| "Transaction not in mempool".to_string())
     }
 
     fn size(&self) -> usize {
-        let transactions = self.transactions.read().unwrap();
-        transactions.len()
+        Mempool::size(self)
     }
 
     fn get_all_transactions(&self) -> Vec<Transaction> {
-        let transactions = self.transactions.read().unwrap();
-        transactions.values().cloned().collect()
+        Mempool::get_all_transactions(self)
+    }
+
+    fn min_relay_feerate(&self) -> u64 {
+        self.min_relay_feerate
     }
 
     fn get_entries(&self) -> Vec<MempoolEntry> {
-        let transactions = self.transactions.read().unwrap();
-        transactions.values().map(|tx| {
-            MempoolEntry {
-                fee: 0, // TODO: Calculate actual fee
-                transaction: tx.clone(),
-                is_orphan: false,
-            }
-        }).collect()
+        let pool = self.pool.read().unwrap();
+        pool.transactions.values().map(MempoolTransaction::to_entry).collect()
+    }
+
+    fn flush(&self) -> Result<usize, String> {
+        self.save()
+    }
+}
+
+fn unix_now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Contents of a mempool file holding `entries`
+pub fn encode_entries(entries: &[MempoolTransaction]) -> Vec<u8> {
+    let mut bytes = MEMPOOL_MAGIC.to_vec();
+    bytes.extend_from_slice(&MEMPOOL_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for entry in entries {
+        bytes.extend_from_slice(&entry.received_time.to_le_bytes());
+        bytes.extend_from_slice(&entry.fee.to_le_bytes());
+        let tx = encode_transaction(&entry.transaction);
+        bytes.extend_from_slice(&(tx.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&tx);
+    }
+    bytes
+}
+
+/// Entries of the mempool file `bytes`
+pub fn decode_entries(bytes: &[u8]) -> Result<Vec<MempoolTransaction>, String> {
+    let mut reader = Reader(bytes);
+    if reader.bytes(MEMPOOL_MAGIC.len(), "magic")? != MEMPOOL_MAGIC {
+        return Err("Not a mempool file".to_string());
+    }
+    let version = u32::from_le_bytes(reader.array("version")?);
+    if version != MEMPOOL_VERSION {
+        return Err(format!("Unsupported mempool file version {}", version));
+    }
+    let count = reader.count("entry count")?;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let received_time = u64::from_le_bytes(reader.array("received time")?);
+        let fee = u64::from_le_bytes(reader.array("fee")?);
+        let len = reader.count("transaction")?;
+        let transaction = decode_transaction(reader.bytes(len, "transaction")?).map_err(|e| format!("Invalid transaction: {}", e))?;
+        entries.push(MempoolTransaction { transaction, received_time, fee });
+    }
+    if !reader.0.is_empty() {
+        return Err(format!("{} trailing bytes after the entries", reader.0.len()));
+    }
+    Ok(entries)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.0.len() < len {
+            return Err(format!("File ends while reading its {}", what));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, what: &str) -> Result<[u8; N], String> {
+        Ok(self.bytes(N, what)?.try_into().expect("slice has length N"))
+    }
+
+    /// A count or length; each counted item takes at least one byte
+    fn count(&mut self, what: &str) -> Result<usize, String> {
+        let count = u64::from_le_bytes(self.array(what)?);
+        usize::try_from(count).ok().filter(|count| *count <= self.0.len()).ok_or_else(|| format!("File ends while reading its {}", what))
     }
 }
@@ -0,0 +1,389 @@
+//! Prometheus metrics
+//!
+//! Subsystems register counters and gauges in a [`MetricsRegistry`] and update
+//! them as blocks and transactions flow. Values that are cheaper to read than
+//! to track, like the block count or the peer counts, are sampled into their
+//! metrics by the daemon every second, as are the per-stage block processing
+//! time histograms kept by the block processor. [`MetricsServer`] serves the
+//! registry on `/metrics` in the Prometheus text exposition format.
+
+use crate::config::NetworkPorts;
+use consensus::pipeline::{ProcessingStage, ProcessingTimings};
+use consensus_core::Hash;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Largest request head the metrics server reads
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Where the metrics endpoint listens, if enabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    /// Defaults to the network's metrics port, see [`NetworkPorts`]
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { enabled: false, bind_address: "127.0.0.1".to_string(), port: NetworkPorts::MAINNET.metrics }
+    }
+}
+
+/// Monotonically increasing value
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Raise the counter to `total`, for counters sampled from a running total
+    pub fn observe(&self, total: u64) {
+        self.0.fetch_max(total, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that goes up and down
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Default)]
+struct HistogramData {
+    buckets: Vec<(f64, u64)>,
+    sum: f64,
+    count: u64,
+}
+
+/// Distribution of observations, sampled from a cumulative histogram kept elsewhere
+#[derive(Debug, Clone, Default)]
+pub struct Histogram(Arc<RwLock<HistogramData>>);
+
+impl Histogram {
+    /// Replace the distribution. `buckets` are upper bounds with the cumulative
+    /// number of observations at or below each, in increasing order.
+    pub fn set(&self, buckets: Vec<(f64, u64)>, sum: f64, count: u64) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = HistogramData { buckets, sum, count };
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).count
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+#[derive(Debug)]
+struct Family {
+    name: String,
+    help: String,
+    /// Series of the family by label set, in registration order
+    series: Vec<(Vec<(String, String)>, Value)>,
+}
+
+/// Set of named metrics
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    families: RwLock<Vec<Family>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counter `name`, registered on first use
+    pub fn counter(&self, name: &str, help: &str) -> Counter {
+        match self.register(name, help, &[], Value::Counter(Counter::default())) {
+            Value::Counter(counter) => counter,
+            _ => panic!("metric {} is registered as another type", name),
+        }
+    }
+
+    /// Gauge `name`, registered on first use
+    pub fn gauge(&self, name: &str, help: &str) -> Gauge {
+        self.gauge_with_labels(name, help, &[])
+    }
+
+    /// Gauge `name` with the label set `labels`, registered on first use
+    pub fn gauge_with_labels(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.register(name, help, labels, Value::Gauge(Gauge::default())) {
+            Value::Gauge(gauge) => gauge,
+            _ => panic!("metric {} is registered as another type", name),
+        }
+    }
+
+    /// Histogram `name` with the label set `labels`, registered on first use
+    pub fn histogram_with_labels(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Histogram {
+        match self.register(name, help, labels, Value::Histogram(Histogram::default())) {
+            Value::Histogram(histogram) => histogram,
+            _ => panic!("metric {} is registered as another type", name),
+        }
+    }
+
+    fn register(&self, name: &str, help: &str, labels: &[(&str, &str)], value: Value) -> Value {
+        let labels: Vec<(String, String)> = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut families = self.families.write().unwrap_or_else(|e| e.into_inner());
+        let index = match families.iter().position(|family| family.name == name) {
+            Some(index) => index,
+            None => {
+                families.push(Family { name: name.to_string(), help: help.to_string(), series: Vec::new() });
+                families.len() - 1
+            }
+        };
+        let family = &mut families[index];
+        if let Some((_, existing)) = family.series.iter().find(|(existing, _)| *existing == labels) {
+            return existing.clone();
+        }
+        family.series.push((labels, value.clone()));
+        value
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let families = self.families.read().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for family in families.iter() {
+            let kind = match family.series.first() {
+                Some((_, Value::Counter(_))) => "counter",
+                Some((_, Value::Histogram(_))) => "histogram",
+                _ => "gauge",
+            };
+            let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", family.name, kind);
+            for (labels, value) in &family.series {
+                let name = &family.name;
+                let _ = match value {
+                    Value::Counter(counter) => writeln!(out, "{}{} {}", name, render_labels(labels, None), counter.get()),
+                    Value::Gauge(gauge) => writeln!(out, "{}{} {}", name, render_labels(labels, None), gauge.get()),
+                    Value::Histogram(histogram) => {
+                        let data = histogram.0.read().unwrap_or_else(|e| e.into_inner());
+                        for (bound, count) in &data.buckets {
+                            let le = bound.to_string();
+                            let _ = writeln!(out, "{}_bucket{} {}", name, render_labels(labels, Some(&le)), count);
+                        }
+                        let _ = writeln!(out, "{}_bucket{} {}", name, render_labels(labels, Some("+Inf")), data.count);
+                        let _ = writeln!(out, "{}_sum{} {}", name, render_labels(labels, None), data.sum);
+                        writeln!(out, "{}_count{} {}", name, render_labels(labels, None), data.count)
+                    }
+                };
+            }
+        }
+        out
+    }
+}
+
+/// `{k="v",...}` for a label set, with the bucket bound `le` last if given
+fn render_labels(labels: &[(String, String)], le: Option<&str>) -> String {
+    let mut rendered: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v))).collect();
+    if let Some(le) = le {
+        rendered.push(format!("le=\"{}\"", le));
+    }
+    if rendered.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", rendered.join(","))
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Metrics of a running node
+#[derive(Debug, Clone)]
+pub struct NodeMetrics {
+    pub blocks: Counter,
+    pub reorgs: Counter,
+    pub transactions_accepted: Counter,
+    pub transactions_rejected: Counter,
+    pub blue_score: Gauge,
+    pub tips: Gauge,
+    pub mempool_size: Gauge,
+    pub peers_inbound: Gauge,
+    pub peers_outbound: Gauge,
+    pub sync_progress: Gauge,
+    pub hashrate: Gauge,
+    pub rpc_connections: Gauge,
+    pub rpc_clients_dropped: Counter,
+    /// Processing time of accepted blocks by pipeline stage
+    pub block_stage_seconds: Vec<(ProcessingStage, Histogram)>,
+}
+
+impl NodeMetrics {
+    pub fn new(registry: &MetricsRegistry) -> Self {
+        Self {
+            blocks: registry.counter("jiopad_blocks_total", "Blocks stored by the node"),
+            reorgs: registry.counter("jiopad_reorgs_total", "Times the selected tip moved off the previous selected chain"),
+            transactions_accepted: registry
+                .counter("jiopad_mempool_transactions_accepted_total", "Transactions accepted into the mempool"),
+            transactions_rejected: registry
+                .counter("jiopad_mempool_transactions_rejected_total", "Transactions refused by the mempool"),
+            blue_score: registry.gauge("jiopad_virtual_blue_score", "Blue score of the virtual block"),
+            tips: registry.gauge("jiopad_dag_tips", "Current DAG tips"),
+            mempool_size: registry.gauge("jiopad_mempool_size", "Transactions in the mempool"),
+            peers_inbound: registry.gauge_with_labels("jiopad_peers", "Connected peers", &[("direction", "inbound")]),
+            peers_outbound: registry.gauge_with_labels("jiopad_peers", "Connected peers", &[("direction", "outbound")]),
+            sync_progress: registry.gauge("jiopad_sync_progress", "Sync progress from 0 to 1"),
+            hashrate: registry.gauge("jiopad_mining_hashrate", "Hashes per second of the local miner"),
+            rpc_connections: registry.gauge("jiopad_rpc_connections", "Connected RPC clients"),
+            rpc_clients_dropped: registry
+                .counter("jiopad_rpc_clients_dropped_total", "RPC clients disconnected for not keeping up with their responses"),
+            block_stage_seconds: ProcessingStage::ALL
+                .iter()
+                .map(|stage| {
+                    let histogram = registry.histogram_with_labels(
+                        "jiopad_block_stage_seconds",
+                        "Time accepted blocks spent in each processing stage",
+                        &[("stage", stage.name())],
+                    );
+                    (*stage, histogram)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl NodeMetrics {
+    /// Copy the stage histograms accumulated by the block processor
+    pub fn sample_block_timings(&self, timings: &ProcessingTimings) {
+        for (stage, histogram) in &self.block_stage_seconds {
+            let snapshot = timings.snapshot(*stage);
+            histogram.set(snapshot.buckets, snapshot.sum, snapshot.count);
+        }
+    }
+}
+
+/// Counts reorgs by following the selected tip
+#[derive(Debug, Default)]
+pub struct ReorgDetector {
+    last: Option<(Hash, u64)>,
+}
+
+impl ReorgDetector {
+    /// Record `sink` with blue score `blue_score` as the selected tip. Returns
+    /// true if the previous one is not on its selected chain. `selected_parent`
+    /// gives the selected parent and its blue score of a block.
+    pub fn observe(&mut self, sink: Hash, blue_score: u64, selected_parent: impl Fn(&Hash) -> Option<(Hash, u64)>) -> bool {
+        let Some((last, last_score)) = self.last.replace((sink, blue_score)) else {
+            return false;
+        };
+        let (mut current, mut score) = (sink, blue_score);
+        while current != last {
+            if score <= last_score {
+                return true;
+            }
+            match selected_parent(&current) {
+                Some((parent, parent_score)) if parent != current => (current, score) = (parent, parent_score),
+                // Chain unknown past this point; don't count what can't be shown
+                _ => return false,
+            }
+        }
+        false
+    }
+}
+
+/// HTTP server exposing a registry on `/metrics`
+pub struct MetricsServer {
+    listener: TcpListener,
+    registry: Arc<MetricsRegistry>,
+}
+
+impl MetricsServer {
+    pub async fn bind(host: &str, port: u16, registry: Arc<MetricsRegistry>) -> Result<Self, String> {
+        let listener = TcpListener::bind((host, port))
+            .await
+            .map_err(|e| format!("Failed to bind metrics server to {}:{}: {}", host, port, e))?;
+        Ok(Self { listener, registry })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    /// Serve scrapes until the task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match self.listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Metrics server accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let registry = self.registry.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &registry).await {
+                        tracing::debug!("Metrics request from {} failed: {}", addr, e);
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// Answer one request on `stream` and close it
+async fn serve(mut stream: TcpStream, registry: &MetricsRegistry) -> Result<(), String> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err("request too large".into());
+        }
+        let n = stream.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&request);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+
+    let (status, body) = match (method, path.split('?').next().unwrap_or_default()) {
+        ("GET", "/metrics") => ("200 OK", registry.render()),
+        ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())
+}
+
@@ -1,50 +1,405 @@
 use crate::config::P2PConfig;
 use crate::consensus_manager::ConsensusManager;
+use crate::mempool::Mempool;
+use crate::sync_manager::headers_after;
+use consensus::consensus::types::BlockStatus;
 use consensus_core::block::Block;
+use consensus_core::errors::ConsensusError;
+use consensus_core::provenance::BlockSource;
 use consensus_core::tx::Transaction;
 use consensus_core::Hash;
+use network::connection_manager::{ConnectionConfig, Dialer, PEER_OUTBOX_CAPACITY};
+use network::handler::MessageHandler;
+use network::hub::Hub;
+use network::inventory::{InventoryItem, INV_FLUSH_INTERVAL};
+use network::listen::{bind_listeners, parse_peer_address, resolve_listen_addresses};
+use network::nat::{NatManager, NatStatus};
+use network::p2p::{negotiate, BanPolicy, EncryptionConfig, HandshakeError, Misbehavior, NodeIdentity, Peer, PeerDirection, RateLimits, Role};
+use network::protowire::{frame_len, ChainTip, Message, VersionMessage};
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
-use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// User agent advertised in the version handshake
+const USER_AGENT: &str = concat!("jiopad/", env!("CARGO_PKG_VERSION"));
+
+/// How often our selected tip is checked and announced to peers if it changed
+const TIP_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// File in the data directory holding peers added as permanent over RPC
+const PERMANENT_PEERS_FILE: &str = "permanent_peers";
+
+/// Blocks served for a single `RequestBlocks`
+const MAX_BLOCKS_PER_REQUEST: usize = 100;
 
 /// Network manager for P2P communication
 pub struct NetworkManager {
     config: P2PConfig,
-    peers: Arc<std::sync::RwLock<HashMap<String, PeerConnection>>>,
+    encryption: EncryptionConfig,
+    hub: Arc<Hub>,
+    consensus: Arc<ConsensusManager>,
+    mempool: Option<Arc<Mempool>>,
+    nat: Option<Arc<NatManager>>,
+    nat_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    connection_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    shutdown: broadcast::Sender<()>,
+}
+
+/// Opens outbound connections for the hub's connection manager
+struct NetworkDialer {
+    encryption: EncryptionConfig,
+    hub: Arc<Hub>,
+    consensus: Arc<ConsensusManager>,
+    rate_limits: RateLimits,
+}
+
+#[async_trait::async_trait]
+impl Dialer for NetworkDialer {
+    async fn dial(&self, address: SocketAddr) -> Result<Arc<Peer>, String> {
+        let stream = TcpStream::connect(address).await
+            .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+
+        let version = NetworkManager::version_message(&self.encryption, &self.hub, &self.consensus);
+        let (transport, remote) = negotiate(stream, Role::Initiator, &self.encryption, version).await
+            .map_err(|e| format!("Handshake with {} failed: {}", address, e))?;
+        tracing::info!("Connected to {} ({}, encrypted: {})", address, remote.user_agent, transport.is_encrypted());
+
+        let (tx, outbox) = mpsc::channel(PEER_OUTBOX_CAPACITY);
+        let peer = Peer::from_handshake(address.to_string(), address, PeerDirection::Outbound, remote, tx)
+            .with_rate_limits(self.rate_limits);
+        let peer = Arc::new(peer);
+        self.hub.spawn_connection(&peer, transport, outbox);
+        Ok(peer)
+    }
+}
+
+/// Acts on what peers send: blocks go to consensus with their provenance,
+/// transactions to the mempool, announcements of unknown items are answered
+/// with requests and block and header requests are served from storage
+struct PeerMessageHandler {
+    consensus: Arc<ConsensusManager>,
+    mempool: Option<Arc<Mempool>>,
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for PeerMessageHandler {
+    async fn handle(&self, hub: &Arc<Hub>, peer: &Arc<Peer>, msg: Message) {
+        // Block requests are served from storage rather than the relay cache
+        let unknown = match msg {
+            Message::RequestBlocks { .. } => Vec::new(),
+            _ => hub.handle_inventory(&peer.id, &msg),
+        };
+        match msg {
+            Message::Block(block) => self.handle_block(hub, peer, block).await,
+            Message::Transaction(tx) => self.handle_transaction(hub, peer, tx).await,
+            Message::InvBlock { .. } | Message::InvTransaction { .. } => self.request_unknown(peer, unknown),
+            Message::RequestBlocks { hashes } => self.serve_blocks(peer, &hashes),
+            Message::RequestHeaders { locator, limit } => {
+                let headers = headers_after(&self.consensus.storage(), &locator, limit as usize);
+                if let Err(e) = peer.try_send_message(Message::Headers { headers }) {
+                    tracing::debug!("Dropping headers for {}: {}", peer.address, e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl PeerMessageHandler {
+    /// Process a block and relay it once valid; the parents an orphan lacks
+    /// are requested from the peer that sent it
+    async fn handle_block(&self, hub: &Arc<Hub>, peer: &Peer, block: Block) {
+        let hash = block.header.hash;
+        let relay = block.clone();
+        match process_peer_block(hub, &self.consensus, peer.address, block).await {
+            Ok(BlockStatus::Valid) => {
+                if let Some(mempool) = &self.mempool {
+                    for tx in relay.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                        mempool.remove_transaction(&tx.hash());
+                    }
+                }
+                hub.broadcast(Message::Block(relay)).await;
+            }
+            Ok(BlockStatus::Orphan) => {
+                let storage = self.consensus.storage();
+                let hashes: Vec<Hash> = relay.header.direct_parents().iter().copied().filter(|parent| !storage.has_block(parent)).collect();
+                if let Err(e) = peer.try_send_message(Message::RequestBlocks { hashes }) {
+                    tracing::debug!("Requesting parents of {} from {} failed: {}", hash, peer.address, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::debug!("Block {} from {} rejected: {}", hash, peer.address, e),
+        }
+    }
+
+    /// Admit a transaction to the mempool and relay it if it was accepted
+    async fn handle_transaction(&self, hub: &Arc<Hub>, peer: &Peer, tx: Transaction) {
+        let Some(mempool) = &self.mempool else { return };
+        match mempool.add_transaction(tx.clone()) {
+            Ok(()) => hub.broadcast(Message::Transaction(tx)).await,
+            Err(e) => tracing::debug!("Transaction {} from {} rejected: {}", tx.hash(), peer.address, e),
+        }
+    }
+
+    /// Request the announced items we have neither stored nor pooled
+    fn request_unknown(&self, peer: &Peer, items: Vec<InventoryItem>) {
+        let storage = self.consensus.storage();
+        let (mut blocks, mut transactions) = (Vec::new(), Vec::new());
+        for item in items {
+            match item {
+                InventoryItem::Block(hash) if !storage.has_block(&hash) => blocks.push(hash),
+                InventoryItem::Transaction(hash) if self.mempool.as_ref().map_or(false, |mempool| !mempool.contains(&hash)) => {
+                    transactions.push(hash)
+                }
+                _ => {}
+            }
+        }
+        let requests = [Message::RequestBlocks { hashes: blocks }, Message::RequestTransactions { hashes: transactions }];
+        for msg in requests.into_iter().filter(|msg| !InventoryItem::listed_in(msg).is_empty()) {
+            if let Err(e) = peer.try_send_message(msg) {
+                tracing::debug!("Requesting inventory from {} failed: {}", peer.address, e);
+            }
+        }
+    }
+
+    /// Send the stored blocks among `hashes`, up to `MAX_BLOCKS_PER_REQUEST`
+    /// and as long as the peer's serving budget lasts
+    fn serve_blocks(&self, peer: &Peer, hashes: &[Hash]) {
+        let storage = self.consensus.storage();
+        for hash in hashes.iter().take(MAX_BLOCKS_PER_REQUEST) {
+            let Some(block) = storage.get_block(hash) else { continue };
+            let msg = Message::Block(block);
+            if !peer.try_serve(frame_len(&msg).unwrap_or(u64::MAX)) {
+                tracing::debug!("Serving budget of {} exhausted", peer.address);
+                return;
+            }
+            peer.mark_known(InventoryItem::Block(*hash));
+            if let Err(e) = peer.try_send_message(msg) {
+                tracing::debug!("Dropping requested block {} for {}: {}", hash, peer.address, e);
+                return;
+            }
+        }
+    }
 }
 
-struct PeerConnection {
-    address: String,
-    stream: Option<TcpStream>,
-    last_seen: std::time::Instant,
+/// Process a block received from the peer at `address`. Its ban score goes
+/// up when the block turns out invalid, the more so for a block failing its
+/// proof of work; an orphan the block completes that turns out invalid counts
+/// against the peer that first delivered it.
+async fn process_peer_block(hub: &Hub, consensus: &ConsensusManager, address: SocketAddr, block: Block) -> Result<BlockStatus, String> {
+    let processor = consensus.block_processor();
+    let (result, provenance) = processor.process_block_from(block, BlockSource::Peer(address));
+    let mut offenders = Vec::new();
+    if provenance.map_or(false, |provenance| provenance.invalid) {
+        let misbehavior = match &result {
+            Err(ConsensusError::InvalidProofOfWork) => Misbehavior::InvalidPow,
+            _ => Misbehavior::InvalidBlock,
+        };
+        offenders.push((address, misbehavior));
+    } else if matches!(result, Ok(ref result) if result.status == BlockStatus::Valid) {
+        // Orphans completed by this block were delivered by whoever sent them first
+        let storage = processor.storage();
+        for orphan in processor.process_orphans().into_iter().filter(|result| result.status == BlockStatus::Invalid) {
+            offenders.extend(storage.get_provenance(&orphan.hash).and_then(|p| p.source.peer()).map(|peer| (peer, Misbehavior::InvalidBlock)));
+        }
+    }
+    for (offender, misbehavior) in offenders {
+        hub.report_misbehavior(offender, misbehavior).await;
+    }
+    result.map(|result| result.status).map_err(|e| format!("Block processing failed: {}", e))
 }
 
 impl NetworkManager {
     /// Create a new network manager
-    pub async fn new(config: &P2PConfig, consensus: Arc<ConsensusManager>) -> Result<Self, String> {
+    pub async fn new(config: &P2PConfig, data_dir: &Path, consensus: Arc<ConsensusManager>) -> Result<Self, String> {
+        let identity = match &config.identity_key_path {
+            Some(path) => Some(NodeIdentity::load_or_generate(path)?),
+            None => None,
+        };
+
+        let hub = Arc::new(Hub::with_ban_policy(BanPolicy {
+            threshold: config.ban_threshold,
+            duration: std::time::Duration::from_secs(config.ban_duration_secs),
+        }).with_clock(consensus.clock()));
+        let permanent = hub.load_peers_file(&data_dir.join(PERMANENT_PEERS_FILE))?;
+        if permanent > 0 {
+            tracing::info!("Loaded {} permanent peers", permanent);
+        }
+
         Ok(Self {
             config: config.clone(),
-            peers: Arc::new(std::sync::RwLock::new(HashMap::new())),
+            encryption: EncryptionConfig {
+                enabled: config.enable_encryption,
+                allow_plaintext: config.allow_plaintext,
+                identity,
+            },
+            hub,
+            consensus,
+            mempool: None,
+            nat: config.enable_upnp.then(|| Arc::new(NatManager::with_default_mappers(config.port))),
+            nat_task: std::sync::Mutex::new(None),
+            connection_task: std::sync::Mutex::new(None),
+            shutdown: broadcast::channel(1).0,
         })
     }
 
+    /// Admit transactions peers relay to `mempool`
+    pub fn with_mempool(self, mempool: Arc<Mempool>) -> Self {
+        Self { mempool: Some(mempool), ..self }
+    }
+
+    /// Hub shared with the RPC layer
+    pub fn hub(&self) -> Arc<Hub> {
+        self.hub.clone()
+    }
+
+    /// Current NAT traversal state
+    pub fn nat_status(&self) -> NatStatus {
+        self.hub.nat_status()
+    }
+
+    /// Our version message, advertising the external address if one was mapped
+    /// and the consensus parameters peers must share
+    fn version_message(encryption: &EncryptionConfig, hub: &Hub, consensus: &ConsensusManager) -> VersionMessage {
+        VersionMessage::new(USER_AGENT, encryption.enabled)
+            .with_address(hub.nat_status().external_address)
+            .with_tip(Self::chain_tip(consensus))
+            .with_nonce(hub.nonce())
+            .with_archival(consensus.is_archival())
+            .with_timestamp(hub.clock().local_millis())
+            .with_params_hash(consensus.config().params_hash())
+    }
+
+    /// Our selected tip as advertised to peers
+    fn chain_tip(consensus: &ConsensusManager) -> ChainTip {
+        let block_store = consensus.storage().block_store();
+        let Some((blue_score, hash)) = block_store.get_chain_tip() else {
+            return ChainTip::default();
+        };
+        let blue_work = block_store.get_header(&hash).map(|header| header.blue_work).unwrap_or_default();
+        ChainTip { hash, blue_score, blue_work }
+    }
+
+    /// Connected peer with the heaviest advertised tip, to sync from
+    pub fn best_peer(&self) -> Option<Arc<Peer>> {
+        self.hub.best_peer()
+    }
+
     /// Start the network manager
     pub async fn start(&self) -> Result<(), String> {
-        tracing::info!("Starting P2P network on {}:{}", self.config.listen_address, self.config.port);
+        tracing::info!("Starting P2P network on {} port {}", self.config.listen_address, self.config.port);
+
+        // Bind every listen address before anything else so a taken port aborts startup
+        let addresses = resolve_listen_addresses(&self.config.listen_address, self.config.port)?;
+        let listeners = bind_listeners(&addresses)?;
+        self.hub.set_local_addresses(addresses);
+
+        // Map the listen port on the gateway; on failure we keep running outbound-only
+        if let Some(nat) = &self.nat {
+            let status = nat.start().await;
+            if let Some(address) = status.external_address {
+                self.hub.broadcast(Message::Addr { addresses: vec![address] }).await;
+            }
+            self.hub.set_nat_status(status);
+            let handle = nat.clone().spawn_refresh(self.hub.clone(), self.shutdown.subscribe());
+            *self.nat_task.lock().unwrap() = Some(handle);
+        }
+
+        // What peers send is routed to consensus and the mempool
+        self.hub.set_message_handler(Arc::new(PeerMessageHandler {
+            consensus: self.consensus.clone(),
+            mempool: self.mempool.clone(),
+        }));
+
+        // Spawn a connection handler per listener
+        for listener in listeners {
+            self.spawn_accept_loop(listener);
+        }
+
+        // Bootstrap peers are kept connected by the hub's connection manager
+        for peer_addr in &self.config.bootstrap_peers {
+            match self.resolve_peer_address(peer_addr).await {
+                Ok(address) => self.hub.add_address(address, true),
+                Err(e) => tracing::warn!("Skipping bootstrap peer {}: {}", peer_addr, e),
+            }
+        }
+        let dialer = Arc::new(NetworkDialer {
+            encryption: self.encryption.clone(),
+            hub: self.hub.clone(),
+            consensus: self.consensus.clone(),
+            rate_limits: self.config.rate_limits,
+        });
+        let connections = ConnectionConfig {
+            min_peers: self.config.min_peers,
+            max_peers: self.config.max_peers,
+            ..ConnectionConfig::default()
+        };
+        let handle = self.hub.spawn_connection_manager(connections, dialer, self.shutdown.subscribe());
+        *self.connection_task.lock().unwrap() = Some(handle);
+
+        // Batched block and transaction announcements; the task ends on shutdown
+        self.hub.spawn_inventory_flusher(INV_FLUSH_INTERVAL, self.shutdown.subscribe());
 
-        // Start listening for connections
-        let listener = TcpListener::bind(format!("{}:{}", self.config.listen_address, self.config.port))
-            .await
-            .map_err(|e| format!("Failed to bind to address: {}", e))?;
+        // Peers pick whom to sync from by the tips they last heard of
+        let consensus = self.consensus.clone();
+        self.hub.spawn_tip_announcer(TIP_ANNOUNCE_INTERVAL, move || Self::chain_tip(&consensus), self.shutdown.subscribe());
 
-        // Spawn connection handler
-        let peers = self.peers.clone();
+        Ok(())
+    }
+
+    fn spawn_accept_loop(&self, listener: TcpListener) {
+        if let Ok(addr) = listener.local_addr() {
+            tracing::info!("P2P listening on {}", addr);
+        }
+        let encryption = self.encryption.clone();
+        let hub = self.hub.clone();
+        let consensus = self.consensus.clone();
+        let rate_limits = self.config.rate_limits;
         tokio::spawn(async move {
             loop {
                 match listener.accept().await {
                     Ok((stream, addr)) => {
+                        if hub.is_banned(addr.ip()) {
+                            tracing::debug!("Refusing connection from banned {}", addr);
+                            continue;
+                        }
                         tracing::info!("Accepted connection from {}", addr);
-                        // Handle connection (placeholder)
+                        let encryption = encryption.clone();
+                        let version = Self::version_message(&encryption, &hub, &consensus);
+                        let hub = hub.clone();
+                        tokio::spawn(async move {
+                            match negotiate(stream, Role::Responder, &encryption, version).await {
+                                Ok((transport, remote)) => {
+                                    tracing::info!(
+                                        "Peer {} connected ({}, encrypted: {})",
+                                        addr, remote.user_agent, transport.is_encrypted()
+                                    );
+                                    let (tx, outbox) = mpsc::channel(PEER_OUTBOX_CAPACITY);
+                                    let peer = Arc::new(Peer::from_handshake(
+                                        addr.to_string(), addr, PeerDirection::Inbound, remote, tx,
+                                    ).with_rate_limits(rate_limits));
+                                    match hub.add_peer(peer.clone()).await {
+                                        Ok(()) => {
+                                            hub.spawn_connection(&peer, transport, outbox);
+                                        }
+                                        Err(e) => tracing::warn!("Dropping peer {}: {}", addr, e),
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::warn!("Handshake with {} failed: {}", addr, e);
+                                    if let Some(misbehavior) = match &e {
+                                        HandshakeError::Frame(frame) => Misbehavior::from_frame_error(frame),
+                                        HandshakeError::Failed(_) => None,
+                                    } {
+                                        hub.report_misbehavior(addr, misbehavior).await;
+                                    }
+                                }
+                            }
+                        });
                     }
                     Err(e) => {
                         tracing::error!("Failed to accept connection: {}", e);
@@ -52,53 +407,61 @@ impl NetworkManager {
                 }
             }
         });
-
-        // Connect to bootstrap peers
-        for peer_addr in &self.config.bootstrap_peers {
-            if let Err(e) = self.connect_to_peer(peer_addr.clone()).await {
-                tracing::warn!("Failed to connect to bootstrap peer {}: {}", peer_addr, e);
-            }
-        }
-
-        Ok(())
     }
 
     /// Stop the network manager
     pub async fn stop(&self) -> Result<(), String> {
         tracing::info!("Stopping P2P network");
+
+        // Stop dialing and remove the gateway port mapping
+        let _ = self.shutdown.send(());
+        let connection_task = self.connection_task.lock().unwrap().take();
+        if let Some(handle) = connection_task {
+            let _ = handle.await;
+        }
+        let nat_task = self.nat_task.lock().unwrap().take();
+        if let Some(handle) = nat_task {
+            let _ = handle.await;
+            self.hub.set_nat_status(NatStatus::default());
+        }
+
         // Close all connections
         Ok(())
     }
 
-    /// Connect to a peer
-    async fn connect_to_peer(&self, address: String) -> Result<(), String> {
-        let stream = TcpStream::connect(&address).await
-            .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
-
-        let mut peers = self.peers.write().unwrap();
-        peers.insert(address.clone(), PeerConnection {
-            address,
-            stream: Some(stream),
-            last_seen: std::time::Instant::now(),
-        });
-
-        Ok(())
+    /// Resolve a configured peer address. Accepts IPv4, bracketed IPv6 and bare
+    /// IPs using our own port; anything else is looked up as a host name.
+    async fn resolve_peer_address(&self, address: &str) -> Result<SocketAddr, String> {
+        match parse_peer_address(address, self.config.port) {
+            Ok(address) => Ok(address),
+            Err(_) => tokio::net::lookup_host(address).await
+                .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+                .next()
+                .ok_or_else(|| format!("No addresses found for {}", address)),
+        }
     }
 
-    /// Broadcast a block to all peers
+    /// Announce a block to all peers; they fetch it if they lack it
     pub async fn broadcast_block(&self, block: &Block) -> Result<(), String> {
-        // Placeholder - would serialize and send block to all peers
         tracing::debug!("Broadcasting block {} to peers", block.header.hash);
+        self.hub.broadcast(Message::Block(block.clone())).await;
         Ok(())
     }
 
-    /// Broadcast a transaction to all peers
+    /// Announce a transaction to all peers; they fetch it if they lack it
     pub async fn broadcast_transaction(&self, tx: &Transaction) -> Result<(), String> {
-        // Placeholder - would serialize and send transaction to all peers
         tracing::debug!("Broadcasting transaction {} to peers", tx.hash());
+        self.hub.broadcast(Message::Transaction(tx.clone())).await;
         Ok(())
     }
 
+    /// Process a block received from the peer at `address`. Its ban score goes
+    /// up when the block turns out invalid; an orphan the block completes that
+    /// turns out invalid counts against the peer that first delivered it.
+    pub async fn process_peer_block(&self, address: SocketAddr, block: Block) -> Result<BlockStatus, String> {
+        process_peer_block(&self.hub, &self.consensus, address, block).await
+    }
+
     /// Request blocks from peers
     pub async fn request_blocks(&self, hashes: Vec<Hash>) -> Result<(), String> {
         // Placeholder - would send block requests to peers
@@ -108,7 +471,11 @@ impl NetworkManager {
 
     /// Get connected peer count
     pub fn peer_count(&self) -> usize {
-        let peers = self.peers.read().unwrap();
-        peers.len()
+        self.hub.peer_count()
+    }
+
+    /// Get connected inbound and outbound peer counts
+    pub fn peer_counts(&self) -> (usize, usize) {
+        self.hub.peer_counts()
     }
 }
@@ -1,49 +1,82 @@
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use crate::consensus_manager::ConsensusManager;
 use crate::network_manager::NetworkManager;
+use crate::sync_manager::SyncManager;
 use crate::mempool::Mempool;
 use crate::mining_coordinator::MiningCoordinator;
 use crate::config::RpcConfig;
-use rpc_wrpc::WrpcServer;
+use crate::logging;
+use rpc_wrpc::{ConnectionLimits, WrpcServer};
 use rpc_core::RpcCoordinator;
-use network::hub::Hub;
 use tokio::task::JoinHandle;
 use tracing::info;
+use wallet::HotWallet;
 
 /// RPC server that manages WebSocket and HTTP RPC endpoints
 pub struct RpcServer {
     config: RpcConfig,
     server_handle: Mutex<Option<JoinHandle<Result<(), String>>>>,
+    local_addrs: Mutex<Vec<SocketAddr>>,
     coordinator: Arc<RpcCoordinator>,
 }
 
 impl RpcServer {
-    /// Create a new RPC server instance
-    pub async fn new(cfg: &RpcConfig, consensus: Arc<ConsensusManager>, _network: Arc<NetworkManager>, mempool: Arc<Mempool>) -> Result<Self, String> {
-        // Build minimal Hub for RPC coordinator (will not be fully integrated with NetworkManager yet)
-        let hub = Arc::new(Hub::new());
+    /// Create a new RPC server instance for a node on `network_id`
+    pub async fn new(
+        cfg: &RpcConfig,
+        network_id: &str,
+        consensus: Arc<ConsensusManager>,
+        network: Arc<NetworkManager>,
+        sync: Arc<SyncManager>,
+        mempool: Arc<Mempool>,
+        wallet: Option<Arc<HotWallet>>,
+    ) -> Result<Self, String> {
+        // Share the network manager's hub so RPC sees its state
+        let hub = network.hub();
 
         // Create RpcCoordinator using components from ConsensusManager and provided mempool
-        let coordinator = Arc::new(RpcCoordinator::new(
+        let coordinator = RpcCoordinator::new(
             consensus.block_processor(),
             consensus.storage(),
             hub,
             mempool.clone() as Arc<dyn rpc_core::mempool::MempoolInterface>,
-            None,
-        ));
+            wallet,
+        )
+        .with_template_bits(consensus.config().block_bits)
+        .with_max_block_mass(consensus.config().max_block_mass)
+        .with_max_block_parents(consensus.config().max_block_parents)
+        .with_extranonce_size(cfg.extranonce_size)
+        .with_max_connections(cfg.max_connections)
+        .with_coinbase_maturity(consensus.config().coinbase_maturity)
+        .with_subsidy_schedule(consensus.config().initial_subsidy, consensus.config().subsidy_halving_interval)
+        .with_clock(consensus.clock())
+        .with_network_id(network_id.to_string())
+        .with_sync_status(sync);
+        // The log level can only be changed when the daemon installed the subscriber
+        let coordinator = Arc::new(match logging::global_handle() {
+            Some(handle) => coordinator.with_log_control(handle),
+            None => coordinator,
+        });
 
         Ok(Self {
             config: cfg.clone(),
             server_handle: Mutex::new(None),
+            local_addrs: Mutex::new(Vec::new()),
             coordinator,
         })
     }
 
     /// Start the RPC server
     pub async fn start(&self) -> Result<(), String> {
-        info!("RPC server configured for {}:{}", self.config.bind_address, self.config.port);
-        // Start the wRPC server in a background task
-        let wrpc = WrpcServer::new(self.coordinator.clone(), self.config.port);
+        info!("RPC server configured for {} port {}", self.config.bind_address, self.config.port);
+        // Bind before spawning so a taken port fails startup instead of the background task
+        let addresses = network::listen::resolve_listen_addresses(&self.config.bind_address, self.config.port)?;
+        let wrpc = WrpcServer::bind(self.coordinator.clone(), &addresses)?.with_limits(ConnectionLimits {
+            max_concurrent_requests: self.config.max_concurrent_requests,
+            max_queued_messages: self.config.max_queued_messages,
+        });
+        *self.local_addrs.lock().unwrap() = wrpc.local_addrs();
         let handle = tokio::spawn(async move { wrpc.start().await });
 
         let mut guard = self.server_handle.lock().unwrap();
@@ -52,6 +85,16 @@ impl RpcServer {
         Ok(())
     }
 
+    /// Coordinator serving the RPC methods
+    pub fn coordinator(&self) -> Arc<RpcCoordinator> {
+        self.coordinator.clone()
+    }
+
+    /// Addresses the server is listening on, empty until started
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.local_addrs.lock().unwrap().clone()
+    }
+
     /// Stop the RPC server
     pub async fn stop(&self) -> Result<(), String> {
         let mut handle = self.server_handle.lock().unwrap();
@@ -0,0 +1,153 @@
+//! Periodic node status report
+//!
+//! Every interval the daemon samples consensus, network, mempool, sync and mining
+//! state and emits one report. Text reports are logged through `tracing` so they
+//! line up with the rest of the log; JSON reports are written as one object per
+//! line to a dedicated writer (stdout by default) for machine consumption.
+
+use crate::ui;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Output format of the status report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFormat {
+    Text,
+    Json,
+}
+
+/// Raw node state sampled at the end of an interval
+#[derive(Debug, Clone, Default)]
+pub struct StatusSample {
+    pub blue_score: u64,
+    pub tips: usize,
+    pub peers_inbound: usize,
+    pub peers_outbound: usize,
+    pub mempool_size: usize,
+    /// Sync progress from 0.0 to 1.0
+    pub sync_progress: f64,
+    /// Block body download speed in bytes per second
+    pub sync_bytes_per_sec: f64,
+    /// Time until the block bodies being synced are downloaded
+    pub sync_eta: Option<Duration>,
+    /// Total number of stored blocks
+    pub block_count: u64,
+    /// Hashes per second, when mining
+    pub hashrate: Option<f64>,
+}
+
+/// One status report
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusReport {
+    pub uptime_secs: u64,
+    pub blue_score: u64,
+    pub tips: usize,
+    pub peers_inbound: usize,
+    pub peers_outbound: usize,
+    pub mempool_size: usize,
+    /// Sync progress in percent
+    pub sync_percent: f64,
+    pub sync_bytes_per_sec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_eta_secs: Option<u64>,
+    pub block_count: u64,
+    /// Blocks added per second over the last interval
+    pub blocks_per_sec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hashrate: Option<f64>,
+}
+
+impl fmt::Display for StatusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "uptime {} | blue score {} | tips {} | peers {} in / {} out | mempool {} | sync {:.1}% | {:.2} blocks/s",
+            ui::format_duration(Duration::from_secs(self.uptime_secs)),
+            self.blue_score,
+            self.tips,
+            self.peers_inbound,
+            self.peers_outbound,
+            self.mempool_size,
+            self.sync_percent,
+            self.blocks_per_sec,
+        )?;
+        if let Some(eta) = self.sync_eta_secs {
+            write!(
+                f,
+                " | download {}/s, ETA {}",
+                ui::format_bytes(self.sync_bytes_per_sec as u64),
+                ui::format_duration(Duration::from_secs(eta))
+            )?;
+        }
+        if let Some(hashrate) = self.hashrate {
+            write!(f, " | mining {}", ui::format_hashrate(hashrate))?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns samples into reports and emits them
+pub struct StatusReporter {
+    format: StatusFormat,
+    /// Dedicated writer for JSON reports; text reports go through `tracing`
+    writer: Box<dyn Write + Send>,
+    started: Instant,
+    last: Option<(u64, Instant)>,
+}
+
+impl StatusReporter {
+    /// Reporter writing JSON reports to stdout
+    pub fn new(format: StatusFormat) -> Self {
+        Self::with_writer(format, Box::new(io::stdout()))
+    }
+
+    pub fn with_writer(format: StatusFormat, writer: Box<dyn Write + Send>) -> Self {
+        Self { format, writer, started: Instant::now(), last: None }
+    }
+
+    /// Build the report for `sample` taken at `now`. Blocks per second are
+    /// measured against the previous sample, or against startup for the first one.
+    pub fn report_at(&mut self, sample: StatusSample, now: Instant) -> StatusReport {
+        let (last_count, last_time) = self.last.unwrap_or((sample.block_count, self.started));
+        let elapsed = now.saturating_duration_since(last_time).as_secs_f64();
+        let blocks_per_sec = if elapsed > 0.0 {
+            sample.block_count.saturating_sub(last_count) as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.last = Some((sample.block_count, now));
+
+        StatusReport {
+            uptime_secs: now.saturating_duration_since(self.started).as_secs(),
+            blue_score: sample.blue_score,
+            tips: sample.tips,
+            peers_inbound: sample.peers_inbound,
+            peers_outbound: sample.peers_outbound,
+            mempool_size: sample.mempool_size,
+            sync_percent: (sample.sync_progress * 100.0).clamp(0.0, 100.0),
+            sync_bytes_per_sec: sample.sync_bytes_per_sec,
+            sync_eta_secs: sample.sync_eta.map(|eta| eta.as_secs()),
+            block_count: sample.block_count,
+            blocks_per_sec,
+            hashrate: sample.hashrate,
+        }
+    }
+
+    /// Build and emit the report for `sample`
+    pub fn emit(&mut self, sample: StatusSample) -> io::Result<StatusReport> {
+        let report = self.report_at(sample, Instant::now());
+        match self.format {
+            StatusFormat::Text => tracing::info!(target: "status", "{}", report),
+            StatusFormat::Json => {
+                let mut line = serde_json::to_string(&report).map_err(io::Error::from)?;
+                line.push('\n');
+                // A single write per report keeps lines whole
+                self.writer.write_all(line.as_bytes())?;
+                self.writer.flush()?;
+            }
+        }
+        Ok(report)
+    }
+}
@@ -1,15 +1,30 @@
 use crate::config::StorageConfig;
-use consensus::consensus::storage::{ConsensusStorage, BlockStore as ConsensusBlockStore, UtxoSet};
+use consensus::consensus::storage::{ConsensusStorage, BlockFeeStore, BlockStore as ConsensusBlockStore, UtxoCommitmentCheck, UtxoSet};
+use consensus::consensus::storage::fee_store::DEFAULT_FEE_WINDOW;
+use consensus::process::snapshot::{SnapshotImport, TrustedCheckpoint, UtxoSnapshot};
+use consensus::process::verify::{ChainVerifier, VerifyReport};
+use consensus_core::header::Header;
+use consensus_core::Hash;
 use std::sync::Arc;
 use std::path::Path;
 use database::Database;
 use database::stores::BlockStore as DbBlockStore;
+use database::stores::{FeeStore, IndexStore, MetadataStore, ProvenanceStore, ReindexStats};
 use std::sync::Arc as StdArc;
 
+/// Metadata key recording the version of the secondary indexes in the database
+const INDEX_VERSION_KEY: &str = "secondary_index_version";
+const INDEX_VERSION: &[u8] = &[2];
+
+/// Metadata key recording the hash of the consensus parameters the database was built with
+const PARAMS_HASH_KEY: &str = "consensus_params_hash";
+
 /// Storage manager that coordinates all storage components
 pub struct StorageManager {
     config: StorageConfig,
     consensus_storage: Arc<ConsensusStorage>,
+    index_store: StdArc<IndexStore>,
+    metadata: MetadataStore,
 }
 
 impl StorageManager {
@@ -33,18 +48,113 @@ impl StorageManager {
 
     // Create DB-backed block/header/UTXO stores
     let db_block_store = StdArc::new(DbBlockStore::new(db.clone(), cache_entries));
-    let db_header_store = StdArc::new(database::stores::HeaderStore::new(db.clone(), cache_entries));
+    let db_header_store = db_block_store.headers();
+    // Blocks written before headers and bodies were stored apart
+    let migrated = db_block_store.migrate_legacy_blocks().map_err(|e| format!("Failed to split stored blocks: {}", e))?;
+    if migrated > 0 {
+        tracing::info!("Split {} stored blocks into headers and bodies", migrated);
+    }
     let db_utxo_store = StdArc::new(database::stores::UtxoStore::new(db.clone(), cache_entries));
 
-    let consensus_block_store = Arc::new(ConsensusBlockStore::new_with_db(db_block_store, Some(db_header_store)));
-    let consensus_utxo = Arc::new(UtxoSet::new_with_db(db_utxo_store));
+    let index_store = StdArc::new(IndexStore::new(db.clone()));
+
+    let consensus_block_store = Arc::new(ConsensusBlockStore::new_with_db(db_block_store, Some(db_header_store)).with_index(index_store.clone()));
+    let consensus_utxo = Arc::new(UtxoSet::new_with_db(db_utxo_store).with_address_index(index_store.clone()));
 
-    let consensus_storage = Arc::new(ConsensusStorage::with_stores(consensus_block_store, consensus_utxo));
+    let provenance_store = Arc::new(ProvenanceStore::new(db.clone()));
+    let fee_store = Arc::new(BlockFeeStore::new_with_db(DEFAULT_FEE_WINDOW, StdArc::new(FeeStore::new(db.clone()))));
+    let consensus_storage = Arc::new(
+        ConsensusStorage::with_stores(consensus_block_store, consensus_utxo)
+            .with_provenance_store(provenance_store)
+            .with_fee_store(fee_store),
+    );
 
-        Ok(Self {
+        let storage = Self {
             config: config.clone(),
             consensus_storage,
-        })
+            index_store,
+            metadata: MetadataStore::new(db),
+        };
+
+        // Databases written before the secondary indexes existed get them built once
+        let version = storage.metadata.get(INDEX_VERSION_KEY).map_err(|e| format!("Failed to read index version: {}", e))?;
+        if version.as_deref() != Some(INDEX_VERSION) {
+            if storage.block_store().block_count() > 0 {
+                tracing::info!("Building secondary indexes");
+                storage.reindex(|_, _| {})?;
+            } else {
+                storage.mark_indexed()?;
+            }
+        }
+
+        Ok(storage)
+    }
+
+    /// Rebuild the transaction, address, children and DAA score indexes from the stored
+    /// blocks and UTXOs. `progress` gets the blocks read so far and their total.
+    pub fn reindex(&self, progress: impl FnMut(usize, usize)) -> Result<ReindexStats, String> {
+        let stats = self.index_store.rebuild(progress).map_err(|e| format!("Failed to rebuild indexes: {}", e))?;
+        self.mark_indexed()?;
+        Ok(stats)
+    }
+
+    /// Re-validate the last `depth` stored blocks, or all of them. `progress`
+    /// gets the blocks checked so far and their total.
+    pub fn verify_chain(&self, depth: Option<usize>, check_bodies: bool, progress: impl FnMut(usize, usize)) -> VerifyReport {
+        ChainVerifier::new(self.block_store()).with_bodies(check_bodies).verify(depth, progress)
+    }
+
+    /// Recompute the commitment over the whole UTXO set and compare it with the stored one
+    pub fn verify_utxo_commitment(&self) -> Result<UtxoCommitmentCheck, String> {
+        self.utxo_set().verify_commitment().map_err(|e| format!("Failed to read the UTXO set: {}", e))
+    }
+
+    /// Write the UTXO set and headers at the selected chain tip to `path`
+    pub fn export_snapshot(&self, path: &Path) -> Result<UtxoSnapshot, String> {
+        let snapshot = UtxoSnapshot::export(&self.consensus_storage)?;
+        snapshot.save(path)?;
+        Ok(snapshot)
+    }
+
+    /// Bootstrap an empty database from the snapshot at `path`, recomputing
+    /// GHOSTDAG data on top of `genesis`. Only a snapshot taken at `trusted`,
+    /// with the UTXO set committed to there, is accepted.
+    pub fn import_snapshot(
+        &self,
+        path: &Path,
+        genesis: &Header,
+        ghostdag_k: u32,
+        trusted: &TrustedCheckpoint,
+    ) -> Result<SnapshotImport, String> {
+        UtxoSnapshot::load(path)?.import(&self.consensus_storage, genesis, ghostdag_k, trusted)
+    }
+
+    /// Record `params_hash` in a new database, or check that the database was
+    /// built with the same consensus parameters
+    pub fn check_params_hash(&self, params_hash: Hash) -> Result<(), String> {
+        let stored = self.metadata.get(PARAMS_HASH_KEY).map_err(|e| format!("Failed to read params hash: {}", e))?;
+        match stored {
+            Some(stored) if stored != params_hash.as_bytes() => Err(format!(
+                "Data directory {} was built with consensus params {}, the configuration gives {}",
+                self.config.data_dir.display(),
+                hex::encode(&stored),
+                params_hash
+            )),
+            Some(_) => Ok(()),
+            None => self
+                .metadata
+                .put(PARAMS_HASH_KEY, &params_hash.as_bytes())
+                .map_err(|e| format!("Failed to write params hash: {}", e)),
+        }
+    }
+
+    fn mark_indexed(&self) -> Result<(), String> {
+        self.metadata.put(INDEX_VERSION_KEY, INDEX_VERSION).map_err(|e| format!("Failed to write index version: {}", e))
+    }
+
+    /// Secondary indexes kept next to the block and UTXO stores
+    pub fn index_store(&self) -> StdArc<IndexStore> {
+        self.index_store.clone()
     }
 
     /// Get consensus storage
@@ -62,6 +172,11 @@ impl StorageManager {
         self.consensus_storage.utxo_set()
     }
 
+    /// Settings the storage was opened with
+    pub fn config(&self) -> &StorageConfig {
+        &self.config
+    }
+
     /// Get data directory
     pub fn data_dir(&self) -> &Path {
         &self.config.data_dir
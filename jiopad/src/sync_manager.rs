@@ -1,14 +1,123 @@
 use crate::consensus_manager::ConsensusManager;
 use crate::network_manager::NetworkManager;
+use consensus::consensus::storage::ConsensusStorage;
+use consensus::consensus::validation::HeaderValidator;
 use consensus::process::sync::SyncProcess;
+use consensus::process::{DownloadConfig, SyncProgress};
 use consensus::pipeline::BlockProcessor;
 use consensus_core::block::Block;
+use consensus_core::encoding::block_size;
+use consensus_core::hashing::header::calculate_header_hash;
+use consensus_core::header::Header;
 use consensus_core::Hash;
-use std::sync::Arc;
+use network::protowire::ChainTip;
+use rpc_core::SyncStatusProvider;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Headers asked of a peer at once
+pub const HEADERS_BATCH_SIZE: usize = 2000;
+
+/// How long a body download task waits for work other peers may hand back
+const BODY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Body requests unanswered for this many request timeouts drop their peer.
+/// After one timeout their bodies are already requested from other peers, and
+/// a late answer still counts.
+const BODY_REQUEST_DROP_TIMEOUTS: u32 = 2;
+
+/// A peer blocks are synced from
+#[async_trait::async_trait]
+pub trait SyncPeer: Send + Sync {
+    fn address(&self) -> SocketAddr;
+
+    /// Selected tip the peer advertised last, in its handshake or since
+    fn tip(&self) -> ChainTip;
+
+    /// Up to `limit` headers following the first `locator` hash the peer
+    /// knows, parents first; empty once the peer's tip is reached
+    async fn request_headers(&self, locator: Vec<Hash>, limit: usize) -> Result<Vec<Header>, String>;
+
+    /// The blocks of `hashes`
+    async fn request_blocks(&self, hashes: Vec<Hash>) -> Result<Vec<Block>, String>;
+}
+
+/// Stage of a sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPhase {
+    #[default]
+    Idle,
+    /// Downloading and validating the header chain
+    Headers,
+    /// Downloading bodies and processing them in header order
+    Bodies,
+    Synced,
+}
+
+/// Progress of the current or last sync
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncStatus {
+    pub phase: SyncPhase,
+    /// Peer the headers are downloaded from
+    pub peer: Option<SocketAddr>,
+    /// Highest blue score the peers advertised
+    pub target_blue_score: u64,
+    /// Blue score of the highest downloaded header
+    pub headers_blue_score: u64,
+    /// Blue score of the local tip
+    pub bodies_blue_score: u64,
+    /// Headers downloaded and validated
+    pub headers: u64,
+    /// Bodies processed, out of the bodies of the downloaded headers
+    pub bodies_processed: u64,
+    pub bodies_total: u64,
+    /// Body download speed and ETA
+    pub download: SyncProgress,
+}
+
+impl SyncStatus {
+    /// Share of the target blue score the downloaded headers reach, from 0.0 to 1.0
+    pub fn headers_progress(&self) -> f64 {
+        Self::fraction(self.headers_blue_score, self.target_blue_score)
+    }
+
+    /// Share of the target blue score the local tip reaches, from 0.0 to 1.0
+    pub fn bodies_progress(&self) -> f64 {
+        Self::fraction(self.bodies_blue_score, self.target_blue_score)
+    }
+
+    fn fraction(score: u64, target: u64) -> f64 {
+        if score >= target {
+            1.0
+        } else {
+            score as f64 / target as f64
+        }
+    }
+}
+
+/// The peer of `peers` with the heaviest advertised tip, ties going to the first
+pub fn best_peer(peers: &[Arc<dyn SyncPeer>]) -> Option<&Arc<dyn SyncPeer>> {
+    peers.iter().rev().max_by_key(|peer| peer.tip().blue_work)
+}
+
+/// Headers a peer serves for `RequestHeaders`: up to `limit` stored headers in
+/// DAA order following the first `locator` hash known, or from genesis if none is
+pub fn headers_after(storage: &ConsensusStorage, locator: &[Hash], limit: usize) -> Vec<Header> {
+    let block_store = storage.block_store();
+    let start = locator.iter().find_map(|hash| block_store.get_header(hash)).map(|header| (header.daa_score, header.hash));
+    block_store.get_headers_after_daa(start, limit.min(HEADERS_BATCH_SIZE))
+}
 
 /// Sync manager that handles block synchronization
 pub struct SyncManager {
+    consensus: Arc<ConsensusManager>,
     sync_process: Arc<SyncProcess>,
+    header_validator: HeaderValidator,
+    headers_batch_size: usize,
+    status: Mutex<SyncStatus>,
 }
 
 impl SyncManager {
@@ -19,7 +128,29 @@ impl SyncManager {
             consensus.storage().block_store(),
         ));
 
-        Self { sync_process }
+        Self {
+            header_validator: HeaderValidator::new()
+                .with_max_block_parents(consensus.config().max_block_parents)
+                .with_clock(consensus.clock()),
+            consensus,
+            sync_process,
+            headers_batch_size: HEADERS_BATCH_SIZE,
+            status: Mutex::new(SyncStatus::default()),
+        }
+    }
+
+    /// Ask peers for up to `headers_batch_size` headers at once
+    pub fn with_headers_batch_size(self, headers_batch_size: usize) -> Self {
+        Self { headers_batch_size, ..self }
+    }
+
+    /// Limit the body requests outstanding with each peer and the time they have to answer
+    pub fn with_download_config(self, config: DownloadConfig) -> Self {
+        let sync_process = Arc::new(
+            SyncProcess::new(self.consensus.block_processor(), self.consensus.storage().block_store())
+                .with_download_config(config),
+        );
+        Self { sync_process, ..self }
     }
 
     /// Start synchronization
@@ -35,6 +166,249 @@ impl SyncManager {
         Ok(())
     }
 
+    /// Sync headers first from `peers`: download and validate the header chain
+    /// from the peer with the heaviest advertised tip, then download the
+    /// bodies from all peers in parallel and process them in header order. A
+    /// peer that fails or disconnects is dropped and the others take over.
+    pub async fn sync_from(&self, peers: Vec<Arc<dyn SyncPeer>>) -> Result<SyncStatus, String> {
+        let local_blue_score = self.local_blue_score();
+        *self.status.lock().unwrap() = SyncStatus {
+            phase: SyncPhase::Headers,
+            target_blue_score: peers.iter().map(|peer| peer.tip().blue_score).max().unwrap_or(0).max(local_blue_score),
+            headers_blue_score: local_blue_score,
+            bodies_blue_score: local_blue_score,
+            ..Default::default()
+        };
+        let mut peers = peers;
+        let headers = self.sync_headers(&mut peers).await?;
+        self.update_status(|status| {
+            status.phase = SyncPhase::Bodies;
+            status.bodies_total = headers.len() as u64;
+        });
+        self.sync_bodies(&headers, peers).await?;
+        self.update_status(|status| status.phase = SyncPhase::Synced);
+        Ok(self.sync_status())
+    }
+
+    /// Download headers until a peer has no more. Each batch comes from the
+    /// peer advertising the heaviest tip at the time; a peer that fails or
+    /// serves an invalid header is dropped.
+    async fn sync_headers(&self, peers: &mut Vec<Arc<dyn SyncPeer>>) -> Result<Vec<Header>, String> {
+        let block_store = self.consensus.storage().block_store();
+        let mut headers = Vec::new();
+        let mut downloaded = HashSet::new();
+        let mut last_received = None;
+        'peers: while let Some(peer) = best_peer(peers).cloned() {
+            let drop_peer = |peers: &mut Vec<Arc<dyn SyncPeer>>| peers.retain(|other| other.address() != peer.address());
+            self.update_status(|status| {
+                status.peer = Some(peer.address());
+                status.target_blue_score = status.target_blue_score.max(peer.tip().blue_score);
+            });
+            let locator = last_received.into_iter().chain(self.locator()).collect();
+            let batch = match peer.request_headers(locator, self.headers_batch_size).await {
+                Ok(batch) if batch.len() <= self.headers_batch_size => batch,
+                Ok(batch) => {
+                    tracing::warn!("Dropping sync peer {}: sent {} headers for {}", peer.address(), batch.len(), self.headers_batch_size);
+                    drop_peer(peers);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("Dropping sync peer {}: {}", peer.address(), e);
+                    drop_peer(peers);
+                    continue;
+                }
+            };
+            if batch.is_empty() {
+                return Ok(headers);
+            }
+
+            for header in batch {
+                last_received = Some(header.hash);
+                if downloaded.contains(&header.hash) || block_store.has_block(&header.hash) {
+                    continue;
+                }
+                if let Err(e) = self.validate_header(&header, &downloaded) {
+                    tracing::warn!("Dropping sync peer {}: header {} {}", peer.address(), header.hash, e);
+                    drop_peer(peers);
+                    last_received = headers.last().map(|header: &Header| header.hash);
+                    continue 'peers;
+                }
+                downloaded.insert(header.hash);
+                headers.push(header);
+            }
+            let headers_blue_score = headers.iter().map(|header| header.blue_score).max().unwrap_or(0);
+            self.update_status(|status| {
+                status.headers = headers.len() as u64;
+                // Peers advertise their tips as of the handshake and may have grown since
+                status.headers_blue_score = status.headers_blue_score.max(headers_blue_score);
+                status.target_blue_score = status.target_blue_score.max(headers_blue_score);
+            });
+        }
+        Err("No peer left to download headers from".to_string())
+    }
+
+    /// Our selected chain from the tip back to genesis, at doubling distances
+    fn locator(&self) -> Vec<Hash> {
+        let block_store = self.consensus.storage().block_store();
+        let Some((tip_score, tip)) = block_store.get_chain_tip() else {
+            return Vec::new();
+        };
+        let mut locator = vec![tip];
+        let mut step = 1;
+        let mut score = tip_score;
+        while score > 0 {
+            score = score.saturating_sub(step);
+            step *= 2;
+            locator.extend(block_store.get_chain_block_by_blue_score(score));
+        }
+        locator
+    }
+
+    /// Context-free checks of `header`, whose parents must be stored or among
+    /// the `downloaded` headers
+    fn validate_header(&self, header: &Header, downloaded: &HashSet<Hash>) -> Result<(), String> {
+        if calculate_header_hash(header) != header.hash {
+            return Err("does not match its hash".to_string());
+        }
+        self.header_validator.validate_header(header).map_err(|e| format!("is invalid: {}", e))?;
+        let block_store = self.consensus.storage().block_store();
+        match header.direct_parents().iter().find(|parent| !downloaded.contains(parent) && !block_store.has_header(parent)) {
+            Some(parent) => Err(format!("has unknown parent {}", parent)),
+            None => Ok(()),
+        }
+    }
+
+    /// Download the bodies of `headers` from `peers` in parallel, processing
+    /// them in the order of the headers as they arrive
+    async fn sync_bodies(&self, headers: &[Header], peers: Vec<Arc<dyn SyncPeer>>) -> Result<(), String> {
+        // Headers don't declare body sizes; the window reserves an estimate for each
+        self.sync_process.start_ibd(headers.iter().map(|header| (header.hash, None)).collect())?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut tasks: Vec<_> = peers.into_iter().map(|peer| self.spawn_body_download(peer, tx.clone())).collect();
+        drop(tx);
+        tasks.push(self.spawn_request_expiry());
+
+        let result = self.process_bodies(headers, &mut rx).await;
+        for task in tasks {
+            task.abort();
+        }
+        result
+    }
+
+    async fn process_bodies(
+        &self,
+        headers: &[Header],
+        rx: &mut mpsc::UnboundedReceiver<(SocketAddr, Result<Vec<Block>, String>)>,
+    ) -> Result<(), String> {
+        let processor = self.consensus.block_processor();
+        let mut received = HashMap::new();
+        let mut next = 0;
+        while next < headers.len() {
+            let Some((address, result)) = rx.recv().await else {
+                return Err("No peer left to download bodies from".to_string());
+            };
+            match result {
+                Ok(blocks) => received.extend(blocks.into_iter().map(|block| (block.header.hash, block))),
+                Err(e) => tracing::warn!("Dropping sync peer {}: {}", address, e),
+            }
+            while let Some(block) = headers.get(next).and_then(|header| received.remove(&header.hash)) {
+                Self::process_body(&processor, block)?;
+                next += 1;
+                let bodies_blue_score = self.local_blue_score();
+                self.update_status(|status| {
+                    status.bodies_processed = next as u64;
+                    status.bodies_blue_score = bodies_blue_score;
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn process_body(processor: &BlockProcessor, block: Block) -> Result<(), String> {
+        let hash = block.header.hash;
+        let result = processor.process_block(block).map_err(|e| format!("Block {} failed to process: {}", hash, e))?;
+        match result.status {
+            consensus::consensus::types::BlockStatus::Valid => Ok(()),
+            status => Err(format!("Block {} is {:?}", hash, status)),
+        }
+    }
+
+    /// Give up the body requests past their timeout as they expire, so the
+    /// other peers' download tasks request those bodies next
+    fn spawn_request_expiry(&self) -> tokio::task::JoinHandle<()> {
+        let sync_process = self.sync_process.clone();
+        let period = (sync_process.download_config().request_timeout / 4).max(BODY_POLL_INTERVAL);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            while !sync_process.is_sync_complete() {
+                interval.tick().await;
+                for (peer, hashes) in sync_process.expire_requests() {
+                    tracing::warn!("Sync peer {} did not deliver {} bodies in time, requesting them from other peers", peer, hashes.len());
+                }
+            }
+        })
+    }
+
+    /// Request bodies from `peer` as its download window allows until every
+    /// body arrived. When the peer fails or stops answering, its requests go
+    /// back to the queue.
+    fn spawn_body_download(
+        &self,
+        peer: Arc<dyn SyncPeer>,
+        tx: mpsc::UnboundedSender<(SocketAddr, Result<Vec<Block>, String>)>,
+    ) -> tokio::task::JoinHandle<()> {
+        let sync_process = self.sync_process.clone();
+        let drop_after = sync_process.download_config().request_timeout * BODY_REQUEST_DROP_TIMEOUTS;
+        tokio::spawn(async move {
+            let address = peer.address();
+            while !sync_process.is_sync_complete() && !tx.is_closed() {
+                let hashes = sync_process.next_request(address);
+                if hashes.is_empty() {
+                    tokio::time::sleep(BODY_POLL_INTERVAL).await;
+                    continue;
+                }
+                let answer = match tokio::time::timeout(drop_after, peer.request_blocks(hashes.clone())).await {
+                    Ok(answer) => answer,
+                    Err(_) => Err(format!("did not answer a request for {} blocks in {:?}", hashes.len(), drop_after)),
+                };
+                let result = answer.and_then(|blocks| {
+                    let delivered: HashSet<Hash> = blocks.iter().map(|block| calculate_header_hash(&block.header)).collect();
+                    if delivered.len() != blocks.len() || hashes.iter().any(|hash| !delivered.contains(hash)) {
+                        return Err(format!("answered a request for {} blocks with other blocks", hashes.len()));
+                    }
+                    Ok(blocks)
+                });
+                match result {
+                    Ok(blocks) => {
+                        for block in &blocks {
+                            sync_process.body_received(&block.header.hash, block_size(block));
+                        }
+                        let _ = tx.send((address, Ok(blocks)));
+                    }
+                    Err(e) => {
+                        sync_process.peer_disconnected(address);
+                        let _ = tx.send((address, Err(e)));
+                        break;
+                    }
+                }
+            }
+        })
+    }
+
+    fn local_blue_score(&self) -> u64 {
+        self.consensus.storage().block_store().get_chain_tip().map_or(0, |(blue_score, _)| blue_score)
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut SyncStatus)) {
+        update(&mut self.status.lock().unwrap());
+    }
+
+    /// Phase and progress of the current or last sync
+    pub fn sync_status(&self) -> SyncStatus {
+        SyncStatus { download: self.sync_process.sync_progress(), ..self.status.lock().unwrap().clone() }
+    }
+
     /// Process a block received during sync
     pub async fn process_sync_block(&self, block: Block) -> Result<(), String> {
         let status = self.sync_process.process_sync_block(block)
@@ -53,8 +427,24 @@ impl SyncManager {
         self.sync_process.is_sync_complete()
     }
 
-    /// Get sync progress (0.0 to 1.0)
+    /// Get sync progress (0.0 to 1.0), estimated from the blue score of the
+    /// local tip against the one the peers advertised
     pub fn get_sync_progress(&self) -> f64 {
-        self.sync_process.get_sync_progress()
+        self.sync_status().bodies_progress()
+    }
+
+    /// Body download progress with speed and ETA
+    pub fn sync_progress(&self) -> SyncProgress {
+        self.sync_process.sync_progress()
+    }
+}
+
+impl SyncStatusProvider for SyncManager {
+    fn is_synced(&self) -> bool {
+        !matches!(self.sync_status().phase, SyncPhase::Headers | SyncPhase::Bodies)
+    }
+
+    fn progress(&self) -> f64 {
+        self.get_sync_progress()
     }
 }
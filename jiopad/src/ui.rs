@@ -1,8 +1,20 @@
 //! User interface utilities for better console output
 
-use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+/// Set when stdout is reserved for machine readable output
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress the banner, sections and status lines printed to stdout
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
 /// ANSI color codes for terminal output
 pub mod colors {
     pub const RESET: &str = "\x1b[0m";
@@ -32,6 +44,9 @@ pub mod colors {
 
 /// Print startup banner
 pub fn print_banner(version: &str, network: &str) {
+    if is_quiet() {
+        return;
+    }
     println!();
     println!("{}╔══════════════════════════════════════════════════════════════╗{}", colors::BRIGHT_CYAN, colors::RESET);
     println!("{}║{}                                                              {}║{}", colors::BRIGHT_CYAN, colors::RESET, colors::BRIGHT_CYAN, colors::RESET);
@@ -47,6 +62,9 @@ pub fn print_banner(version: &str, network: &str) {
 
 /// Print status line with icon and color
 pub fn print_status(icon: &str, message: &str, status: StatusType) {
+    if is_quiet() {
+        return;
+    }
     let color = match status {
         StatusType::Success => colors::BRIGHT_GREEN,
         StatusType::Info => colors::BRIGHT_CYAN,
@@ -70,6 +88,9 @@ pub enum StatusType {
 
 /// Print a section header
 pub fn print_section(title: &str) {
+    if is_quiet() {
+        return;
+    }
     println!();
     println!("{}━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━{}", colors::DIM, colors::RESET);
     println!("{}  {}{}", colors::BRIGHT_CYAN, colors::BOLD, title);
@@ -79,11 +100,22 @@ pub fn print_section(title: &str) {
 
 /// Print key-value pair in a formatted way
 pub fn print_kv(key: &str, value: &str) {
+    if is_quiet() {
+        return;
+    }
     println!("  {}{}:{}{} {}{}{}", 
         colors::BRIGHT_WHITE, key, colors::RESET, colors::DIM, 
         colors::BRIGHT_CYAN, value, colors::RESET);
 }
 
+/// Listen hosts and port as socket addresses, e.g. `0.0.0.0:16111, [::]:16111`
+fn format_listen_addresses(hosts: &str, port: u16) -> String {
+    match network::listen::resolve_listen_addresses(hosts, port) {
+        Ok(addresses) => addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+        Err(_) => format!("{} (invalid) port {}", hosts, port),
+    }
+}
+
 /// Print configuration summary
 pub fn print_config_summary(config: &crate::config::Config) {
     print_section("Configuration");
@@ -91,12 +123,18 @@ pub fn print_config_summary(config: &crate::config::Config) {
     print_kv("Network", &config.network.network_id);
     print_kv("Data Directory", config.storage.data_dir.to_str().unwrap_or("N/A"));
     let rpc_status = if config.rpc.enabled {
-        format!("{}:{}", config.rpc.bind_address, config.rpc.port)
+        format_listen_addresses(&config.rpc.bind_address, config.rpc.port)
     } else {
         "Disabled".to_string()
     };
     print_kv("RPC Server", &rpc_status);
-    print_kv("P2P Port", &config.p2p.port.to_string());
+    print_kv("P2P Listen", &format_listen_addresses(&config.p2p.listen_address, config.p2p.port));
+    print_kv("P2P Encryption", match (config.p2p.enable_encryption, config.p2p.allow_plaintext) {
+        (false, _) => "Disabled",
+        (true, true) => "Preferred",
+        (true, false) => "Required",
+    });
+    print_kv("UPnP/NAT-PMP", if config.p2p.enable_upnp { "Enabled" } else { "Disabled" });
     print_kv("Mining", if config.mining.enabled {
         "Enabled"
     } else {
@@ -113,6 +151,9 @@ pub fn print_config_summary(config: &crate::config::Config) {
 
 /// Print component status
 pub fn print_component_status(component: &str, status: ComponentStatus) {
+    if is_quiet() {
+        return;
+    }
     let (icon, color, text) = match status {
         ComponentStatus::Starting => ("⏳", colors::BRIGHT_YELLOW, "Starting"),
         ComponentStatus::Running => ("✓", colors::BRIGHT_GREEN, "Running"),
@@ -179,43 +220,6 @@ pub fn format_hashrate(hashrate: f64) -> String {
     }
 }
 
-/// Node status summary
-pub struct NodeStatus {
-    pub uptime: Duration,
-    pub block_count: u64,
-    pub peer_count: usize,
-    pub is_mining: bool,
-    pub mining_hashrate: f64,
-    pub mempool_size: usize,
-    pub sync_percentage: f64,
-}
-
-impl fmt::Display for NodeStatus {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{}━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━{}", colors::DIM, colors::RESET)?;
-        writeln!(f, "{}  Node Status Summary{}", colors::BRIGHT_CYAN, colors::RESET)?;
-        writeln!(f, "{}━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━{}", colors::DIM, colors::RESET)?;
-        writeln!(f)?;
-        writeln!(f, "  {}Uptime:{}           {}", colors::BRIGHT_WHITE, colors::RESET, format_duration(self.uptime))?;
-        writeln!(f, "  {}Blocks:{}           {}", colors::BRIGHT_WHITE, colors::RESET, self.block_count)?;
-        writeln!(f, "  {}Peers:{}            {}", colors::BRIGHT_WHITE, colors::RESET, self.peer_count)?;
-        writeln!(f, "  {}Mempool:{}          {} transactions", colors::BRIGHT_WHITE, colors::RESET, self.mempool_size)?;
-        writeln!(f, "  {}Sync:{}             {:.1}%", colors::BRIGHT_WHITE, colors::RESET, self.sync_percentage)?;
-        
-        if self.is_mining {
-            writeln!(f, "  {}Mining:{}           {} {}", 
-                colors::BRIGHT_WHITE, colors::RESET, 
-                colors::BRIGHT_GREEN, format_hashrate(self.mining_hashrate))?;
-        } else {
-            writeln!(f, "  {}Mining:{}           {}Disabled{}", 
-                colors::BRIGHT_WHITE, colors::RESET, 
-                colors::DIM, colors::RESET)?;
-        }
-        
-        writeln!(f)
-    }
-}
-
 /// Progress bar for long operations
 pub struct ProgressBar {
     width: usize,
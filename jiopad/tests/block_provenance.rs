@@ -0,0 +1,76 @@
+//! Blocks remember who first delivered them, and peers delivering invalid
+//! blocks are banned on that record
+
+use consensus_core::block::Block;
+use consensus_core::hashing::header::validate_pow;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::network_manager::NetworkManager;
+use jiopad::storage_manager::StorageManager;
+use network::p2p::Misbehavior;
+use rpc_core::{BlockTemplate, CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const PAY_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+/// First block on `template` whose proof of work is `valid` or not
+fn block_on(template: &BlockTemplate, valid: bool, skip: usize) -> Block {
+    (0u64..)
+        .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+        .filter(|block| validate_pow(&block.header) == valid)
+        .nth(skip)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_block_source_is_recorded_per_delivery_path() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    config.p2p.ban_threshold = 100;
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = Arc::new(ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap());
+    let network = NetworkManager::new(&config.p2p, dir.path(), consensus.clone()).await.unwrap();
+    let hub = network.hub();
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        hub.clone(),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    );
+    let peer: SocketAddr = "10.0.0.9:16111".parse().unwrap();
+
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let from_rpc = coordinator.submit_block(block_on(&template, true, 0)).await.unwrap();
+    let status = coordinator.get_block_status(from_rpc).await.unwrap();
+    assert_eq!((status.status.as_str(), status.source.as_deref()), ("valid", Some("rpc")));
+    assert!(status.first_seen.is_some());
+
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let block = block_on(&template, true, 0);
+    let from_peer = block.header.hash;
+    network.process_peer_block(peer, block.clone()).await.unwrap();
+    let verbose = coordinator.get_block_verbose(from_peer).await.unwrap();
+    assert_eq!(verbose.source.as_deref(), Some("10.0.0.9:16111"));
+    assert_eq!(verbose.first_seen, coordinator.get_block_status(from_peer).await.unwrap().first_seen);
+
+    // Resubmitting over RPC keeps the peer as first source
+    coordinator.submit_block(block).await.unwrap();
+    assert_eq!(coordinator.get_block_status(from_peer).await.unwrap().source.as_deref(), Some("10.0.0.9:16111"));
+
+    // Each block failing its proof of work adds to the peer's ban score
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let invalid = block_on(&template, false, 0);
+    let _ = network.process_peer_block(peer, invalid.clone()).await;
+    let status = coordinator.get_block_status(invalid.header.hash).await.unwrap();
+    assert_eq!((status.status.as_str(), status.source.as_deref()), ("invalid", Some("10.0.0.9:16111")));
+    assert!(!hub.is_banned(peer.ip()));
+    assert_eq!(hub.peer_score(peer.ip()), Misbehavior::InvalidPow.penalty());
+
+    let _ = network.process_peer_block(peer, block_on(&template, false, 1)).await;
+    assert!(hub.is_banned(peer.ip()));
+}
@@ -0,0 +1,95 @@
+//! Blocks mined on one node reach a connected node over P2P: the announcement
+//! is answered with a request, the block is served from storage and accepted
+//! with the serving peer as its source, missing parents being fetched first
+
+mod common;
+
+use common::wait_for;
+use consensus_core::block::Block;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::network_manager::NetworkManager;
+use jiopad::storage_manager::StorageManager;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const PAY_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+struct Node {
+    port: u16,
+    consensus: Arc<ConsensusManager>,
+    network: NetworkManager,
+    coordinator: RpcCoordinator,
+}
+
+async fn start_node(dir: &Path, bootstrap_peers: Vec<String>) -> Node {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.to_path_buf();
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    config.p2p.enable_upnp = false;
+    config.p2p.enable_encryption = false;
+    config.p2p.allow_plaintext = true;
+    config.p2p.bootstrap_peers = bootstrap_peers;
+
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = Arc::new(ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap());
+    let mempool = Arc::new(Mempool::new());
+    let network = NetworkManager::new(&config.p2p, dir, consensus.clone()).await.unwrap().with_mempool(mempool.clone());
+    network.start().await.unwrap();
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        network.hub(),
+        mempool as Arc<dyn MempoolInterface>,
+        None,
+    );
+    Node { port: config.p2p.port, consensus, network, coordinator }
+}
+
+async fn mine(node: &Node) -> Block {
+    let template = node.coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let block = (0u64..)
+        .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+        .find(|block| validate_pow(&block.header))
+        .unwrap();
+    node.coordinator.submit_block(block.clone()).await.unwrap();
+    block
+}
+
+async fn wait_for_valid(node: &Node, hash: Hash) {
+    wait_for(&format!("block {} to become valid", hash), 5, || async {
+        node.coordinator.get_block_status(hash).await.map_or(false, |status| status.status == "valid").then_some(())
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_mined_blocks_are_relayed_to_peers() {
+    let (dir_a, dir_b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let a = start_node(dir_a.path(), Vec::new()).await;
+    let b = start_node(dir_b.path(), vec![format!("127.0.0.1:{}", a.port)]).await;
+    wait_for("the nodes to connect", 5, || async { (a.network.peer_count() == 1 && b.network.peer_count() == 1).then_some(()) }).await;
+
+    // Only the second block is announced; B fetches the first as its missing parent
+    let parent = mine(&a).await;
+    let child = mine(&a).await;
+    a.network.broadcast_block(&child).await.unwrap();
+    wait_for_valid(&b, child.header.hash).await;
+    assert!(b.consensus.storage().has_block(&parent.header.hash));
+
+    let source = format!("127.0.0.1:{}", a.port);
+    for hash in [parent.header.hash, child.header.hash] {
+        let status = b.coordinator.get_block_status(hash).await.unwrap();
+        assert_eq!((status.status.as_str(), status.source.as_deref()), ("valid", Some(source.as_str())));
+    }
+
+    a.network.stop().await.unwrap();
+    b.network.stop().await.unwrap();
+}
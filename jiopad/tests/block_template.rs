@@ -0,0 +1,137 @@
+//! Extra data requested with a block template ends up in its coinbase payload,
+//! pools can submit templates mined with an extranonce, and transactions are
+//! picked by the feerate of their package with their pooled ancestors
+
+use consensus::process::coinbase::MAX_COINBASE_EXTRA_DATA_LEN;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::script_class::pay_to_address_script;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator, RpcError};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const PAY_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+async fn coordinator(dir: &TempDir) -> RpcCoordinator {
+    coordinator_with_mempool(dir, Mempool::new()).await
+}
+
+async fn coordinator_with_mempool(dir: &TempDir, mempool: Mempool) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(mempool) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+#[tokio::test]
+async fn test_template_coinbase_carries_extra_data() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = coordinator(&dir).await;
+
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), Some("pool/42".to_string())).await.unwrap();
+    let coinbase = &template.transactions[0];
+    assert!(coinbase.is_coinbase());
+    assert!(coinbase.payload.ends_with(b"pool/42"));
+    assert_eq!(coinbase.id(), coinbase.hash());
+
+    let plain = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    assert!(!plain.transactions[0].payload.ends_with(b"pool/42"));
+
+    let too_long = "x".repeat(MAX_COINBASE_EXTRA_DATA_LEN + 1);
+    let err = coordinator.get_block_template(PAY_ADDRESS.to_string(), Some(too_long)).await.unwrap_err();
+    assert!(matches!(err, RpcError::Rpc { code: -8, .. }));
+}
+
+#[tokio::test]
+async fn test_block_mined_with_extranonce_is_accepted() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = coordinator(&dir).await.with_extranonce_size(8);
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), Some("pool/42".to_string())).await.unwrap();
+    assert_eq!(template.extranonce_size, 8);
+    assert!(template.transactions[0].payload.ends_with(b"pool/42\0\0\0\0\0\0\0\0"));
+
+    // Split the reward between two miners
+    let script = |byte: u8| ScriptPublicKey::from_vec(0, vec![byte; 20]);
+    let overrides = CoinbaseOverrides {
+        outputs: Some(vec![
+            TransactionOutput::new(template.coinbase_value / 2, script(1)),
+            TransactionOutput::new(template.coinbase_value - template.coinbase_value / 2, script(2)),
+        ]),
+    };
+
+    // A solution for a non-zero extranonce that the same nonce doesn't give without it
+    let zero = [0u8; 8];
+    let (extranonce, nonce, block) = (1u64..)
+        .flat_map(|extranonce| (0..64u64).map(move |nonce| (extranonce.to_le_bytes(), nonce)))
+        .find_map(|(extranonce, nonce)| {
+            let block = template.to_block(nonce, &extranonce, &overrides).unwrap();
+            let without = template.to_block(nonce, &zero, &overrides).unwrap();
+            (validate_pow(&block.header) && !validate_pow(&without.header)).then_some((extranonce, nonce, block))
+        })
+        .unwrap();
+
+    let err = coordinator
+        .submit_block_with_coinbase(template.template_id, nonce, zero.to_vec(), Some(overrides.clone()))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::Rpc { code: -25, .. }));
+    let err = coordinator.submit_block_with_coinbase(template.template_id, nonce, vec![1; 4], None).await.unwrap_err();
+    assert!(matches!(err, RpcError::Rpc { code: -8, .. }));
+    let err = coordinator.submit_block_with_coinbase(Hash::default(), nonce, extranonce.to_vec(), None).await.unwrap_err();
+    assert!(matches!(err, RpcError::Rpc { code: -8, .. }));
+
+    let hash = coordinator
+        .submit_block_with_coinbase(template.template_id, nonce, extranonce.to_vec(), Some(overrides))
+        .await
+        .unwrap();
+    assert_eq!(hash, block.header.hash);
+    let stored = coordinator.get_block(hash).await.unwrap();
+    assert!(stored.transactions[0].payload.ends_with(&extranonce));
+    assert_eq!(stored.transactions[0].outputs.len(), 2);
+}
+
+#[tokio::test]
+async fn test_template_takes_packages_by_ancestor_feerate() {
+    let script = pay_to_address_script(PAY_ADDRESS).unwrap();
+    let tx = |outpoint: TransactionOutpoint| {
+        Transaction::new(
+            0,
+            vec![TransactionInput::new(outpoint, vec![], 0, 1)],
+            vec![TransactionOutput::new(1000, script.clone())],
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            vec![],
+        )
+    };
+    let parent = tx(TransactionOutpoint::new(Hash::from_bytes([1; 32]), 0));
+    let child = tx(TransactionOutpoint::new(parent.hash(), 0));
+    let loner = tx(TransactionOutpoint::new(Hash::from_bytes([2; 32]), 0));
+    // The parent pays 1 sompi/gram, its package with the child 10 and the loner 5
+    let fees: HashMap<Hash, u64> = [(&parent, 180), (&child, 19 * 180), (&loner, 5 * 180)].map(|(tx, fee)| (tx.hash(), fee)).into();
+    let mempool = Mempool::new().with_validator(Arc::new(move |tx: &Transaction, _: &[Transaction]| Ok(fees[&tx.hash()])));
+    for tx in [&loner, &parent, &child] {
+        mempool.add_transaction(tx.clone()).unwrap();
+    }
+
+    let dir = TempDir::new().unwrap();
+    let coordinator = coordinator_with_mempool(&dir, mempool).await;
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let ids: Vec<Hash> = template.transactions[1..].iter().map(Transaction::hash).collect();
+    assert_eq!(ids, vec![parent.hash(), child.hash(), loner.hash()]);
+}
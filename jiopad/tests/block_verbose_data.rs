@@ -0,0 +1,83 @@
+//! Verbose block data on a small DAG: two blocks on genesis merged by a third,
+//! and a header-only block on top. The mergesets and children the node serves
+//! match the topology, over the coordinator and the wire alike.
+
+mod common;
+
+use common::{block_on, node, ADDRESSES};
+use consensus_core::Hash;
+use rpc_client::JioRpcClient;
+use rpc_core::RpcApi;
+use rpc_wrpc::WrpcServer;
+use std::collections::HashSet;
+use tempfile::TempDir;
+
+fn set(hashes: &[Hash]) -> HashSet<Hash> {
+    hashes.iter().copied().collect()
+}
+
+#[tokio::test]
+async fn test_verbose_data_matches_the_dag() {
+    let dir = TempDir::new().unwrap();
+    let (consensus, coordinator) = node(&dir).await;
+    let genesis = coordinator.get_dag_tips().await.unwrap()[0];
+
+    // genesis <- a, b <- c <- d, d known by its header only
+    let a = block_on(&consensus, &coordinator.get_block_template(ADDRESSES[0].to_string(), None).await.unwrap(), None);
+    let b = block_on(&consensus, &coordinator.get_block_template(ADDRESSES[1].to_string(), None).await.unwrap(), None);
+    coordinator.submit_block(a.clone()).await.unwrap();
+    coordinator.submit_block(b.clone()).await.unwrap();
+    let template = coordinator.get_block_template(ADDRESSES[2].to_string(), None).await.unwrap();
+    let c = block_on(&consensus, &template, Some(vec![a.header.hash, b.header.hash]));
+    coordinator.submit_block(c.clone()).await.unwrap();
+    let template = coordinator.get_block_template(ADDRESSES[0].to_string(), None).await.unwrap();
+    let d = block_on(&consensus, &template, Some(vec![c.header.hash]));
+    consensus.block_processor().process_header_only(d.header.clone()).unwrap();
+
+    let genesis_data = coordinator.get_block_verbose(genesis).await.unwrap().block.verbose_data.unwrap();
+    assert_eq!(set(&genesis_data.children_hashes), set(&[a.header.hash, b.header.hash]));
+    assert!(genesis_data.is_chain_block);
+
+    // c merges both of its parents as blue, its selected parent first
+    let c_data = coordinator.get_block_verbose(c.header.hash).await.unwrap().block.verbose_data.unwrap();
+    let selected_parent = c_data.selected_parent;
+    let other = if selected_parent == a.header.hash { b.header.hash } else { a.header.hash };
+    assert!(selected_parent == a.header.hash || selected_parent == b.header.hash);
+    assert_eq!(c_data.merge_set_blues_hashes, vec![selected_parent, other]);
+    assert!(c_data.merge_set_reds_hashes.is_empty());
+    assert_eq!(c_data.blue_score, c.header.blue_score);
+    assert!(c_data.is_chain_block);
+    assert!(!c_data.is_header_only);
+    assert!(c_data.difficulty > 0.0);
+
+    // Only the selected parent of c is on the chain; both have c as their only child
+    for parent in [a.header.hash, b.header.hash] {
+        let data = coordinator.get_block_verbose(parent).await.unwrap().block.verbose_data.unwrap();
+        assert_eq!(data.selected_parent, genesis);
+        assert_eq!(data.merge_set_blues_hashes, vec![genesis]);
+        assert_eq!(data.children_hashes, vec![c.header.hash]);
+        assert_eq!(data.is_chain_block, parent == selected_parent);
+    }
+
+    // The header-only block is served with no transactions and flagged
+    let d_verbose = coordinator.get_block_verbose(d.header.hash).await.unwrap();
+    assert!(d_verbose.block.transactions.is_empty());
+    let d_data = d_verbose.block.verbose_data.unwrap();
+    assert!(d_data.is_header_only);
+    assert_eq!(d_data.selected_parent, c.header.hash);
+    assert_eq!(d_data.merge_set_blues_hashes, vec![c.header.hash]);
+
+    // getBlocks gives the same data over the wire, one entry per block in order
+    let server = WrpcServer::bind(coordinator.clone(), &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let client = JioRpcClient::new(&server.local_addrs()[0].to_string()).unwrap();
+    let server = tokio::spawn(server.start());
+    let page = client.get_blocks(None, true, false, true).await.unwrap();
+    let hashes: Vec<Hash> = page.blocks.iter().map(|block| block.header.hash).collect();
+    let verbose_hashes: Vec<Hash> = page.block_verbose_data.iter().map(|data| data.hash).collect();
+    assert_eq!(verbose_hashes, hashes);
+    let served_c = page.block_verbose_data.iter().find(|data| data.hash == c.header.hash).unwrap();
+    assert_eq!(served_c, &c_data);
+    assert!(client.get_blocks(None, true, false, false).await.unwrap().block_verbose_data.is_empty());
+
+    server.abort();
+}
@@ -0,0 +1,100 @@
+//! `jiopad checkdb`: stores that disagree after a crash are found, and repair
+//! rolls the chain index back to the last consistent chain block
+
+use clap::Parser;
+use consensus::process::consistency::Severity;
+use consensus_core::hashing::header::validate_pow;
+use jiopad::cli::{Args, Command};
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+async fn open(config: &Config) -> (Arc<StorageManager>, ConsensusManager) {
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage.clone(), &config.network).await.unwrap();
+    (storage, consensus)
+}
+
+async fn mine(consensus: &ConsensusManager, blocks: usize) {
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    );
+    for _ in 0..blocks {
+        let template = coordinator.get_block_template(ADDRESS.to_string(), None).await.unwrap();
+        let block = (0u64..)
+            .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+            .find(|block| validate_pow(&block.header))
+            .unwrap();
+        coordinator.submit_block(block).await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_missing_ghostdag_data_is_found_and_repaired() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let (storage, consensus) = open(&config).await;
+    mine(&consensus, 5).await;
+
+    let block_store = storage.block_store();
+    let (tip_score, _) = block_store.get_chain_tip().unwrap();
+    let report = consensus.check_consistency(false);
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+    assert!(report.chain_blocks >= 5);
+
+    // Lose the GHOSTDAG data of a chain block in the middle
+    let broken_score = tip_score - 2;
+    let broken = block_store.get_chain_block_by_blue_score(broken_score).unwrap();
+    let below = block_store.get_chain_block_by_blue_score(broken_score - 1).unwrap();
+    consensus.ghostdag_manager().store().remove(&broken);
+
+    for quick in [true, false] {
+        let report = consensus.check_consistency(quick);
+        assert!(!report.is_ok());
+        assert!(report.is_repairable());
+        assert_eq!(report.rollback_from, Some(broken_score));
+        assert_eq!(report.findings.len(), 1, "{:?}", report.findings);
+        assert_eq!(report.findings[0].severity, Severity::Error);
+        assert_eq!(report.findings[0].hash, Some(broken));
+    }
+
+    // Repair leaves the chain block below it as the tip
+    let report = consensus.check_consistency(false);
+    let outcome = consensus.repair_consistency(&report).unwrap();
+    assert_eq!(outcome.rolled_back.len(), 3);
+    assert_eq!(outcome.rolled_back[0], (broken_score, broken));
+    assert_eq!(outcome.chain_tip, Some((broken_score - 1, below)));
+    assert_eq!(block_store.get_chain_tip(), Some((broken_score - 1, below)));
+    let report = consensus.check_consistency(false);
+    assert!(report.is_ok(), "{:?}", report.findings);
+    assert!(!report.is_repairable());
+
+    // After a restart the chain index grows back over the rolled back blocks
+    drop((block_store, consensus, storage));
+    let (storage, consensus) = open(&config).await;
+    mine(&consensus, 1).await;
+    assert_eq!(storage.block_store().get_chain_tip().map(|(blue_score, _)| blue_score), Some(tip_score + 1));
+    assert_eq!(storage.block_store().get_chain_block_by_blue_score(broken_score), Some(broken));
+    let report = consensus.check_consistency(false);
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+}
+
+#[test]
+fn test_checkdb_arguments() {
+    let args = Args::parse_from(["jiopad", "checkdb"]);
+    assert!(matches!(args.command, Some(Command::CheckDb { repair: false })));
+    let args = Args::parse_from(["jiopad", "checkdb", "--repair"]);
+    assert!(matches!(args.command, Some(Command::CheckDb { repair: true })));
+}
@@ -0,0 +1,69 @@
+//! Chains passing a checkpoint's DAA score are accepted only if they merge
+//! the checkpoint block
+
+use consensus_core::block::Block;
+use consensus_core::hashing::header::validate_pow;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+async fn coordinator(dir: &TempDir, checkpoints: Vec<String>) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    config.consensus.checkpoints = checkpoints;
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+/// A block on the tip of `coordinator`, its coinbase paying `extra_data`
+async fn mine(coordinator: &RpcCoordinator, extra_data: &str) -> Block {
+    let template = coordinator.get_block_template(ADDRESS.to_string(), Some(extra_data.to_string())).await.unwrap();
+    (0u64..)
+        .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+        .find(|block| validate_pow(&block.header))
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_blocks_must_match_checkpoints() {
+    let (miner_dir, matching_dir, mismatching_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+    let miner = coordinator(&miner_dir, Vec::new()).await;
+    let block = mine(&miner, "checkpoint").await;
+    let rival = mine(&miner, "rival").await;
+    assert_ne!(block.header.hash, rival.header.hash);
+    assert_eq!(block.header.daa_score, rival.header.daa_score);
+    // A chain through the rival alone
+    miner.submit_block(rival.clone()).await.unwrap();
+    let past_rival = mine(&miner, "past rival").await;
+    let checkpoint = format!("{}:{}", block.header.daa_score, block.header.hash);
+
+    let matching = coordinator(&matching_dir, vec![checkpoint]).await;
+    assert_eq!(matching.submit_block(block.clone()).await.unwrap(), block.header.hash);
+    // Blocks beside the checkpoint are accepted, chains passing it without merging it are not
+    assert_eq!(matching.submit_block(rival.clone()).await.unwrap(), rival.header.hash);
+    let err = matching.submit_block(past_rival.clone()).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("CheckpointMismatch"), "{:?}", err);
+    let merging = mine(&matching, "merging").await;
+    assert!(merging.header.direct_parents().contains(&block.header.hash));
+    assert!(matching.submit_block(merging).await.is_ok());
+
+    // The chain through the rival is accepted by a node checkpointing it
+    let rival_checkpoint = format!("{}:{}", rival.header.daa_score, rival.header.hash);
+    let mismatching = coordinator(&mismatching_dir, vec![rival_checkpoint]).await;
+    assert!(mismatching.submit_block(rival).await.is_ok());
+    assert!(mismatching.submit_block(past_rival).await.is_ok());
+}
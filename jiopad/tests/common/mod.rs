@@ -0,0 +1,73 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use consensus_core::block::Block;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{BlockTemplate, CoinbaseOverrides, MempoolInterface, RpcCoordinator};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+pub const ADDRESSES: [&str; 3] = ["1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", "1A1z7agoat3FwzZsQwtfTHtVtWWbooZewH", "1eA9ctCNq41iLLnQErtTUVdxpRyZ9hZmW"];
+
+/// A devnet node storing its data in `dir`, and a coordinator serving it
+pub async fn node(dir: &TempDir) -> (ConsensusManager, Arc<RpcCoordinator>) {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    let coordinator = Arc::new(RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    ));
+    (consensus, coordinator)
+}
+
+/// First block on `template` with a valid proof of work, its parents replaced
+/// by `parents` if given
+pub fn block_on(consensus: &ConsensusManager, template: &BlockTemplate, parents: Option<Vec<Hash>>) -> Block {
+    let mut block = template.to_block(0, &[], &CoinbaseOverrides::default()).unwrap();
+    if let Some(parents) = parents {
+        block.header.parents_by_level = vec![parents];
+        let data = consensus.block_processor().ghostdag_manager().calculate_ghostdag(&block.header).unwrap();
+        block.header.blue_score = data.blue_score;
+        block.header.daa_score = data.blue_score;
+        block.header.blue_work = data.blue_work;
+    }
+    block.header.finalize();
+    while !validate_pow(&block.header) {
+        block.header.nonce += 1;
+        block.header.finalize();
+    }
+    block
+}
+
+/// Poll `check` until it yields a value, failing the test after `secs` seconds
+pub async fn wait_for<T, F, Fut>(what: &str, secs: u64, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let poll = async {
+        loop {
+            if let Some(value) = check().await {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(secs), poll)
+        .await
+        .unwrap_or_else(|_| panic!("timed out waiting for {}", what))
+}
@@ -0,0 +1,68 @@
+use jiopad::config::default_template;
+use jiopad::Config;
+use std::fs;
+use tempfile::TempDir;
+
+fn to_toml(config: &Config) -> String {
+    toml::to_string(config).unwrap()
+}
+
+#[test]
+fn test_default_template_round_trips() {
+    let template = default_template();
+    assert!(template.contains("# Outbound peers the connection manager keeps connected\nmin_peers = "));
+    assert!(template.contains("# Log level"));
+    let parsed: Config = toml::from_str(&template).unwrap();
+    assert_eq!(to_toml(&parsed), to_toml(&Config::default()));
+
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("conf").join("jiopad.toml");
+    Config::write_default(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), template);
+    assert_eq!(to_toml(&Config::load(&path).unwrap()), to_toml(&Config::default()));
+    // An existing config is never overwritten
+    assert!(Config::write_default(&path).unwrap_err().contains("already exists"));
+}
+
+#[test]
+fn test_load_valid_file() {
+    let mut config = Config::for_network("testnet").unwrap();
+    config.storage.data_dir = "/var/lib/jiopad".into();
+    config.rpc.bind_address = "127.0.0.1,::1".to_string();
+    config.p2p.min_peers = 4;
+    config.p2p.max_peers = 16;
+    config.mining.mining_address = Some("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_string());
+    config.log.level = "debug".to_string();
+
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("jiopad.toml");
+    fs::write(&path, to_toml(&config)).unwrap();
+    let loaded = Config::load(&path).unwrap();
+    assert_eq!(loaded.network.network_id, "testnet");
+    assert_eq!(loaded.storage.data_dir, std::path::PathBuf::from("/var/lib/jiopad"));
+    assert_eq!(loaded.rpc.bind_address, "127.0.0.1,::1");
+    assert_eq!((loaded.p2p.min_peers, loaded.p2p.max_peers), (4, 16));
+    assert_eq!(loaded.mining.mining_address.as_deref(), Some("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"));
+    assert_eq!(loaded.log.level, "debug");
+
+    // Tables added later are optional in older files
+    let without_log = to_toml(&config).replace("[log]\nlevel = \"debug\"\n", "");
+    fs::write(&path, without_log).unwrap();
+    assert_eq!(Config::load(&path).unwrap().log.level, "info");
+}
+
+#[test]
+fn test_malformed_file_reports_position() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("jiopad.toml");
+
+    fs::write(&path, default_template().replace("max_peers = 50", "max_peers = fifty")).unwrap();
+    let line = default_template().lines().position(|line| line == "max_peers = 50").unwrap() + 1;
+    let err = Config::load(&path).unwrap_err();
+    assert!(err.contains(&format!("at line {}, column 13", line)), "{}", err);
+
+    fs::write(&path, "[network]\nnetwork_id = \"mainnet\"\ngenesis_timestamp = \"soon\"\n").unwrap();
+    let err = Config::load(&path).unwrap_err();
+    assert!(err.starts_with("Failed to parse config"), "{}", err);
+    assert!(err.contains("at line 3, column 21"), "{}", err);
+}
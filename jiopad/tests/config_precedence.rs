@@ -0,0 +1,131 @@
+//! Configuration layers: built-in defaults < network preset < config file < CLI flags
+
+use clap::Parser;
+use jiopad::cli::Args;
+use jiopad::config::NetworkPorts;
+use jiopad::Config;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+fn resolve(args: &[&str]) -> Config {
+    Config::resolve(&Args::parse_from([&["jiopad"][..], args].concat())).unwrap()
+}
+
+fn to_toml(config: &Config) -> String {
+    toml::to_string(config).unwrap()
+}
+
+/// Write a partial config file and return its path
+fn config_file(tmp: &TempDir, content: &str) -> String {
+    let path = tmp.path().join("jiopad.toml");
+    fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_defaults_without_flags() {
+    assert_eq!(to_toml(&resolve(&[])), to_toml(&Config::default()));
+}
+
+#[test]
+fn test_network_preset_overrides_defaults() {
+    let config = resolve(&["--network", "simnet"]);
+    assert_eq!(to_toml(&config), to_toml(&Config::for_network("simnet").unwrap()));
+    assert_eq!(config.consensus.coinbase_maturity, 10);
+    assert_eq!(config.p2p.port, NetworkPorts::SIMNET.p2p);
+    assert_eq!(config.rpc.port, NetworkPorts::SIMNET.rpc);
+}
+
+#[test]
+fn test_file_overrides_its_network_preset() {
+    let tmp = TempDir::new().unwrap();
+    let path = config_file(&tmp, "[network]\nnetwork_id = \"simnet\"\n\n[consensus]\ncoinbase_maturity = 5\n\n[p2p]\nport = 1234\n");
+    let config = resolve(&["--config-path", &path]);
+    assert_eq!(config.network.network_id, "simnet");
+    assert_eq!(config.consensus.coinbase_maturity, 5);
+    assert_eq!(config.p2p.port, 1234);
+    // Keys the file doesn't set keep the preset's values
+    assert_eq!(config.rpc.port, NetworkPorts::SIMNET.rpc);
+    assert_eq!(config.consensus.block_bits, Config::for_network("simnet").unwrap().consensus.block_bits);
+    assert_eq!(config.p2p.max_peers, Config::default().p2p.max_peers);
+}
+
+#[test]
+fn test_network_and_config_together() {
+    let tmp = TempDir::new().unwrap();
+    let path = config_file(&tmp, "[network]\nnetwork_id = \"testnet\"\n\n[p2p]\nport = 1234\n");
+    let config = resolve(&["--network", "simnet", "--config-path", &path]);
+    assert_eq!(config.network.network_id, "simnet");
+    assert_eq!(config.consensus.coinbase_maturity, 10);
+    assert_eq!(config.p2p.port, 1234);
+    assert_eq!(config.rpc.port, NetworkPorts::SIMNET.rpc);
+
+    // Without a network in the file the flag picks the preset too
+    let path = config_file(&tmp, "[log]\nlevel = \"debug\"\n");
+    let config = resolve(&["--network", "testnet", "--config-path", &path]);
+    assert_eq!(config.network.network_id, "testnet");
+    assert_eq!(config.rpc.port, NetworkPorts::TESTNET.rpc);
+    assert_eq!(config.log.level, "debug");
+}
+
+#[test]
+fn test_cli_flags_override_file() {
+    let tmp = TempDir::new().unwrap();
+    let path = config_file(
+        &tmp,
+        &format!(
+            "[storage]\ndata_dir = \"/var/lib/jiopad\"\n\n[rpc]\nport = 2345\n\n[p2p]\nport = 1234\n\n[mining]\nmining_address = \"{}\"\n\n[log]\nlevel = \"debug\"\n",
+            ADDRESS
+        ),
+    );
+    let config = resolve(&[
+        "--config-path", &path,
+        "--data-dir", "/tmp/jiopad",
+        "--p2p-port", "4321",
+        "--log-level", "warn",
+        "--enable-mining",
+    ]);
+    assert_eq!(config.storage.data_dir, PathBuf::from("/tmp/jiopad"));
+    assert_eq!(config.p2p.port, 4321);
+    assert_eq!(config.log.level, "warn");
+    // Flags that aren't given leave the file's values alone
+    assert_eq!(config.rpc.port, 2345);
+    assert!(config.mining.enabled);
+    assert_eq!(config.mining.mining_address.as_deref(), Some(ADDRESS));
+
+    // And flags win over the preset as well
+    let config = resolve(&["--network", "devnet", "--rpc-port", "3456", "--no-rpc"]);
+    assert_eq!(config.rpc.port, 3456);
+    assert!(!config.rpc.enabled);
+    assert_eq!(config.p2p.port, NetworkPorts::DEVNET.p2p);
+}
+
+#[test]
+fn test_unknown_network_is_an_error() {
+    let args = Args::parse_from(["jiopad", "--network", "mainet"]);
+    assert_eq!(Config::resolve(&args).unwrap_err(), "Unknown network: mainet");
+
+    let tmp = TempDir::new().unwrap();
+    let path = config_file(&tmp, "[network]\nnetwork_id = \"mainet\"\n");
+    let args = Args::parse_from(["jiopad", "--config-path", &path]);
+    assert_eq!(Config::resolve(&args).unwrap_err(), "Unknown network: mainet");
+}
+
+#[test]
+fn test_prune_and_archive_flags() {
+    let tmp = TempDir::new().unwrap();
+    assert!(!resolve(&[]).storage.enable_pruning);
+    assert!(resolve(&["--prune"]).storage.enable_pruning);
+
+    // --archive wins over a file that enables pruning
+    let path = config_file(&tmp, "[storage]\nenable_pruning = true\npruning_depth = 500\n");
+    let config = resolve(&["--config-path", &path]);
+    assert!(config.storage.enable_pruning);
+    assert_eq!(config.storage.pruning_depth, 500);
+    assert!(!resolve(&["--config-path", &path, "--archive"]).storage.enable_pruning);
+
+    assert!(Args::try_parse_from(["jiopad", "--prune", "--archive"]).is_err());
+}
@@ -0,0 +1,179 @@
+use jiopad::config::ConfigError;
+use jiopad::Config;
+use tempfile::TempDir;
+
+fn valid_config(data_dir: &TempDir) -> Config {
+    let mut config = Config::default();
+    config.storage.data_dir = data_dir.path().join("data");
+    config
+}
+
+#[test]
+fn test_default_configs_are_valid() {
+    let tmp = TempDir::new().unwrap();
+    for network in ["mainnet", "testnet", "simnet", "devnet"] {
+        let mut config = Config::for_network(network).unwrap();
+        config.storage.data_dir = tmp.path().join(network);
+        assert_eq!(config.validate(), Ok(()), "{}", network);
+        assert!(config.storage.data_dir.is_dir());
+    }
+}
+
+#[test]
+fn test_unknown_network() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = valid_config(&tmp);
+    config.network.network_id = "mainet".to_string();
+    let err = config.validate().unwrap_err();
+    assert_eq!(err, ConfigError::UnknownNetwork("mainet".to_string()));
+    assert!(err.to_string().contains("'mainet'"));
+}
+
+#[test]
+fn test_port_conflicts() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = valid_config(&tmp);
+    config.rpc.port = config.p2p.port;
+    assert_eq!(
+        config.validate(),
+        Err(ConfigError::PortConflict { first: "p2p.port", second: "rpc.port", port: config.p2p.port })
+    );
+
+    // Disabled listeners don't hold their port
+    config.rpc.enabled = false;
+    assert_eq!(config.validate(), Ok(()));
+
+    let mut config = valid_config(&tmp);
+    config.metrics.enabled = true;
+    config.metrics.port = config.rpc.grpc_port;
+    assert_eq!(
+        config.validate(),
+        Err(ConfigError::PortConflict { first: "rpc.grpc_port", second: "metrics.port", port: config.rpc.grpc_port })
+    );
+
+    // Ephemeral ports may repeat
+    let mut config = valid_config(&tmp);
+    config.p2p.port = 0;
+    config.rpc.port = 0;
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn test_peer_limits() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = valid_config(&tmp);
+    config.p2p.min_peers = 10;
+    config.p2p.max_peers = 8;
+    assert_eq!(config.validate(), Err(ConfigError::PeerLimits { min_peers: 10, max_peers: 8 }));
+
+    config.p2p.min_peers = 0;
+    config.p2p.max_peers = 0;
+    assert_eq!(config.validate(), Err(ConfigError::PeerLimits { min_peers: 0, max_peers: 0 }));
+}
+
+#[test]
+fn test_data_dir_not_writable() {
+    let tmp = TempDir::new().unwrap();
+    let file = tmp.path().join("file");
+    std::fs::write(&file, b"").unwrap();
+
+    let mut config = valid_config(&tmp);
+    config.storage.data_dir = file.join("data");
+    match config.validate() {
+        Err(ConfigError::DataDirNotWritable { path, .. }) => assert_eq!(path, file.join("data")),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_mining_without_address() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = valid_config(&tmp);
+    config.mining.enabled = true;
+    assert_eq!(config.validate(), Err(ConfigError::MissingMiningAddress));
+
+    // The node's wallet provides one
+    config.wallet.keystore = Some(tmp.path().join("wallet.json"));
+    assert_eq!(config.validate(), Ok(()));
+    config.wallet.keystore = None;
+
+    config.mining.mining_address = Some("1A1z7agoat3FwzZsQwtfTHtVtWWbooZewH".to_string());
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn test_zero_halving_interval() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = valid_config(&tmp);
+    config.consensus.subsidy_halving_interval = 0;
+    let err = config.validate().unwrap_err();
+    assert_eq!(err, ConfigError::ZeroHalvingInterval);
+    assert!(err.to_string().contains("subsidy_halving_interval"));
+
+    // Networks keep their own schedules
+    assert_eq!(Config::for_network("mainnet").unwrap().consensus.subsidy_halving_interval, 210_000);
+    assert_eq!(Config::for_network("simnet").unwrap().consensus.subsidy_halving_interval, 2_100);
+}
+
+#[test]
+fn test_broken_config_file_is_an_error() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("jiopad.toml");
+    std::fs::write(&path, "[network\nnetwork_id = ").unwrap();
+    assert!(Config::load(&path).unwrap_err().starts_with("Failed to parse config"));
+    assert!(Config::for_network("mainet").is_err());
+}
+
+#[test]
+fn test_consensus_params_out_of_range() {
+    let tmp = TempDir::new().unwrap();
+    let invalid_key = |configure: fn(&mut Config)| {
+        let mut config = valid_config(&tmp);
+        configure(&mut config);
+        match config.validate() {
+            Err(ConfigError::InvalidConsensusParams { key, .. }) => key,
+            other => panic!("unexpected {:?}", other),
+        }
+    };
+    assert_eq!(invalid_key(|config| config.consensus.ghostdag_k = 0), "ghostdag_k");
+    assert_eq!(invalid_key(|config| config.consensus.ghostdag_k = 256), "ghostdag_k");
+    assert_eq!(invalid_key(|config| config.consensus.difficulty_window_size = 1), "difficulty_window_size");
+    assert_eq!(
+        invalid_key(|config| config.consensus.max_block_mass = config.consensus.max_tx_mass - 1),
+        "max_block_mass"
+    );
+    // Experimental subnetworks need a gas limit, and only a devnet enables them
+    assert_eq!(invalid_key(|config| config.consensus.experimental_subnetworks = true), "max_block_gas");
+    assert_eq!(
+        invalid_key(|config| {
+            config.consensus.experimental_subnetworks = true;
+            config.consensus.max_block_gas = 1_000_000;
+        }),
+        "experimental_subnetworks"
+    );
+
+    let mut config = valid_config(&tmp);
+    config.consensus.ghostdag_k = 0;
+    assert!(config.validate().unwrap_err().to_string().starts_with("consensus.ghostdag_k"));
+}
+
+#[test]
+fn test_checkpoints() {
+    let tmp = TempDir::new().unwrap();
+    let hash = "11".repeat(32);
+    let mut config = valid_config(&tmp);
+    config.consensus.checkpoints = vec![format!("100:{}", hash), format!(" 200 : {}", "22".repeat(32))];
+    assert_eq!(config.validate(), Ok(()));
+    let checkpoints = config.consensus.checkpoints().unwrap();
+    assert_eq!(checkpoints.keys().copied().collect::<Vec<_>>(), vec![100, 200]);
+    assert_eq!(checkpoints[&100].to_string(), hash);
+
+    for checkpoint in [hash.clone(), format!("x:{}", hash), "100:abc".to_string(), format!("200:{}", hash)] {
+        let mut config = valid_config(&tmp);
+        config.consensus.checkpoints = vec![format!("200:{}", "22".repeat(32)), checkpoint.clone()];
+        match config.validate() {
+            Err(ConfigError::InvalidConsensusParams { key: "checkpoints", reason }) => assert!(reason.contains(&checkpoint), "{}", reason),
+            other => panic!("unexpected {:?} for {}", other, checkpoint),
+        }
+    }
+}
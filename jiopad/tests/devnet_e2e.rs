@@ -0,0 +1,182 @@
+//! End to end run on a local simnet node: mine, send a transaction, wait for
+//! its confirmation and check that the node and the explorer agree on the
+//! resulting balances.
+
+mod common;
+
+use common::wait_for;
+use consensus_core::tx::{ScriptPublicKey, TransactionOutpoint};
+use jio_explorer::database::queries::{AddressQueries, BlockQueries, TransactionQueries};
+use jio_explorer::database::Database;
+use jio_explorer::indexer::address_indexer::{script_address, AddressIndexer};
+use jio_explorer::indexer::IndexerService;
+use jiopad::{Config, Daemon};
+use mining::rpc_miner::{self, RpcMiner, RpcMinerConfig};
+use rpc_client::JioRpcClient;
+use rpc_core::RpcApi;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::{Address, Keys, Signer, TxBuilder};
+
+const AMOUNT: u64 = 50_000;
+const FEE: u64 = 1_000;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_mine_send_confirm_and_index() {
+    // Node
+    let node_dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = node_dir.path().to_path_buf();
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = 0;
+    config.p2p.bootstrap_peers.clear();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+    let client = Arc::new(JioRpcClient::new(&rpc_addr.to_string()).unwrap());
+
+    // Wallets
+    let miner_keys = Keys::new();
+    let (miner_secret, miner_public) = miner_keys.generate_address().unwrap();
+    let miner_address = Address::from_public_key(&miner_public);
+    let (_, recipient_public) = Keys::new().generate_address().unwrap();
+    let recipient_address = Address::from_public_key(&recipient_public);
+
+    // Mine until the miner owns a mature coinbase output
+    let mut miner = RpcMiner::new(RpcMinerConfig {
+        num_workers: 1,
+        mining_address: miner_address.clone(),
+        template_refresh_interval_ms: 50,
+        max_iterations: 1_000_000,
+    });
+    let template_addr = rpc_addr.to_string();
+    let pay_address = miner_address.clone();
+    let submit_addr = rpc_addr.to_string();
+    miner.start_mining(
+        move || rpc_miner::fetch_template(&template_addr, &pay_address),
+        move |block| rpc_miner::submit_block(&submit_addr, &block),
+    );
+    wait_for("a mature coinbase", 60, || {
+        let client = client.clone();
+        let address = miner_address.clone();
+        async move {
+            let balance = client.get_balance_by_address(address).await.ok()?;
+            (balance.balance > 0).then_some(())
+        }
+    })
+    .await;
+
+    // Spend the oldest coinbase output, which is the first to mature
+    let (outpoint, entry) = client
+        .get_utxos_by_address(miner_address.clone())
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|utxo| utxo.utxo_entry.is_coinbase)
+        .min_by_key(|utxo| utxo.utxo_entry.block_daa_score)
+        .map(|utxo| (utxo.outpoint, utxo.utxo_entry))
+        .unwrap();
+    let recipient_script = Address::to_script_pub_key(&recipient_address).unwrap();
+    let change_script = Address::to_script_pub_key(&miner_address).unwrap();
+    let utxos: HashMap<TransactionOutpoint, _> = [(outpoint.clone(), entry.clone())].into();
+    let tx = TxBuilder::new()
+        .add_input(outpoint, vec![])
+        .add_output(AMOUNT, recipient_script.clone())
+        .add_output(entry.amount - AMOUNT - FEE, change_script)
+        .build(&utxos)
+        .unwrap();
+    let tx = Signer::new(miner_keys).sign_transaction(tx, &[miner_secret], &[entry]).unwrap();
+    let tx_hash = client
+        .send_raw_transaction(hex::encode(consensus_core::encoding::encode_transaction(&tx)), false)
+        .await
+        .unwrap();
+    assert_eq!(tx_hash, tx.hash());
+
+    let block_hash = wait_for("the transaction to confirm", 60, || {
+        let client = client.clone();
+        async move {
+            let verbose = client.get_transaction_verbose(tx_hash).await.ok()?;
+            (verbose.confirmations >= 1).then_some(verbose.block_hash?)
+        }
+    })
+    .await;
+    tokio::task::block_in_place(|| miner.shutdown());
+
+    // Explorer
+    let explorer_dir = TempDir::new().unwrap();
+    let database = Arc::new(Database::new(&explorer_dir.path().join("explorer.db")).await.unwrap());
+    database.migrate().await.unwrap();
+    let pool = Arc::new(database.pool().clone());
+    let ledger = AddressIndexer::new(database.clone());
+    let indexer = IndexerService::new(database).with_poll_interval(Duration::from_millis(100));
+    let indexer_client = client.clone();
+    let indexer_handle = tokio::spawn(async move { indexer.start(indexer_client).await });
+
+    let indexed = wait_for("the explorer to index the transaction", 30, || {
+        let pool = pool.clone();
+        let hash = tx_hash.to_string();
+        async move { TransactionQueries::get_by_hash(pool, &hash).await.ok()?.filter(|tx| tx.is_confirmed) }
+    })
+    .await;
+    assert_eq!(indexed.block_hash, Some(block_hash.to_string()));
+
+    // No more blocks are mined, so the explorer catches up once it has the tips
+    let tips = client.get_dag_tips().await.unwrap();
+    wait_for("the explorer to index the tips", 30, || {
+        let pool = pool.clone();
+        let tips = tips.clone();
+        async move {
+            for tip in tips {
+                BlockQueries::get_by_hash(pool.clone(), &tip.to_string()).await.ok()??;
+            }
+            Some(())
+        }
+    })
+    .await;
+
+    // Balances follow the ledger, which follows the selected chain
+    let chain = client.get_virtual_chain_from_block(None, false).await.unwrap();
+    let chain_tip = chain.added_chain_block_hashes.last().copied();
+    let ledger = &ledger;
+    wait_for("the explorer ledger to reach the chain tip", 30, || async move {
+        (ledger.ledger_tip().await.ok()? == chain_tip).then_some(())
+    })
+    .await;
+
+    // Both sides agree on the balances
+    let explorer_balance = |script: ScriptPublicKey| {
+        let pool = pool.clone();
+        async move {
+            AddressQueries::get_summary(pool, &script_address(&script).unwrap())
+                .await
+                .unwrap()
+                .map_or(0, |summary| summary.balance as u64)
+        }
+    };
+    let recipient = client.get_balance_by_address(recipient_address.clone()).await.unwrap();
+    assert_eq!(recipient.balance, AMOUNT);
+    assert_eq!(recipient.pending_balance, 0);
+    assert_eq!(explorer_balance(recipient_script).await, AMOUNT);
+
+    // The explorer doesn't know about coinbase maturity
+    let miner_balance = client.get_balance_by_address(miner_address.clone()).await.unwrap();
+    let miner_script = Address::to_script_pub_key(&miner_address).unwrap();
+    assert_eq!(explorer_balance(miner_script).await, miner_balance.balance + miner_balance.pending_balance);
+
+    indexer_handle.abort();
+    shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,141 @@
+//! Devnets take their consensus parameters from a params file; nodes and data
+//! directories with other parameters are refused
+
+mod common;
+
+use common::wait_for;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::storage_manager::StorageManager;
+use jiopad::{Config, Daemon};
+use serde_json::json;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::node_client::NodeClient;
+
+const PARAMS: &str = "ghostdag_k = 10\nmax_block_mass = 250000\nmax_tx_mass = 50000\n";
+
+fn devnet_config(data_dir: &Path, params_file: Option<&Path>) -> Config {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = data_dir.to_path_buf();
+    config.network.params_file = params_file.map(Path::to_path_buf);
+    config.apply_params_file().unwrap();
+    config
+}
+
+#[test]
+fn test_params_file_overrides_devnet_params() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("params.toml");
+    std::fs::write(&path, PARAMS).unwrap();
+    let config = devnet_config(&tmp.path().join("data"), Some(&path));
+    let defaults = Config::for_network("devnet").unwrap().consensus;
+    assert_eq!(config.consensus.ghostdag_k, 10);
+    assert_eq!(config.consensus.max_block_mass, 250_000);
+    assert_eq!(config.consensus.max_tx_mass, 50_000);
+    assert_eq!(config.consensus.coinbase_maturity, defaults.coinbase_maturity);
+    assert_ne!(config.consensus.params_hash(), defaults.params_hash());
+    assert_eq!(config.validate(), Ok(()));
+
+    // Keys are checked, and only a devnet takes a params file
+    std::fs::write(&path, "ghostdag_kk = 10\n").unwrap();
+    assert!(Config::for_network("devnet").map(|mut config| {
+        config.network.params_file = Some(path.clone());
+        config.apply_params_file()
+    }).unwrap().is_err());
+    std::fs::write(&path, PARAMS).unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.network.params_file = Some(path);
+    assert!(config.apply_params_file().unwrap_err().contains("devnet"));
+}
+
+#[tokio::test]
+async fn test_data_dir_refuses_other_params() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("params.toml");
+    std::fs::write(&path, PARAMS).unwrap();
+    let data_dir = tmp.path().join("data");
+
+    let config = devnet_config(&data_dir, Some(&path));
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage.clone(), &config.network).await.unwrap();
+    drop((consensus, storage));
+
+    // The same parameters reopen it
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage.clone(), &config.network).await.unwrap();
+    drop((consensus, storage));
+
+    let config = devnet_config(&data_dir, None);
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let err = ConsensusManager::new(&config.consensus, storage, &config.network).await.err().unwrap();
+    assert!(err.contains("consensus params"), "{}", err);
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Start a devnet node listening for peers on `p2p_port` and dialing `peers`
+async fn start_node(
+    data_dir: &Path,
+    params_file: Option<&Path>,
+    p2p_port: u16,
+    peers: &[u16],
+) -> (NodeClient, tokio::sync::broadcast::Sender<()>, tokio::task::JoinHandle<Result<(), String>>) {
+    let mut config = devnet_config(data_dir, params_file);
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = p2p_port;
+    config.p2p.bootstrap_peers = peers.iter().map(|port| format!("127.0.0.1:{}", port)).collect();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+    (NodeClient::new(&rpc_addr.to_string()), shutdown, node)
+}
+
+async fn peer_count(client: &NodeClient) -> u64 {
+    let counts = client.call("getConnectionCount", json!([])).await.unwrap();
+    counts["inbound_peers"].as_u64().unwrap() + counts["outbound_peers"].as_u64().unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_only_nodes_with_the_same_params_peer() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("params.toml");
+    std::fs::write(&path, PARAMS).unwrap();
+    let first_port = free_port();
+
+    let (first, first_shutdown, first_node) = start_node(&tmp.path().join("first"), Some(&path), first_port, &[]).await;
+    let (second, second_shutdown, second_node) =
+        start_node(&tmp.path().join("second"), Some(&path), free_port(), &[first_port]).await;
+    wait_for("the nodes sharing params to peer", 30, || {
+        let (first, second) = (&first, &second);
+        async move { (peer_count(first).await > 0 && peer_count(second).await > 0).then_some(()) }
+    })
+    .await;
+    let peers = peer_count(&first).await;
+
+    // A node on the default devnet params is turned away
+    let (other, other_shutdown, other_node) = start_node(&tmp.path().join("other"), None, free_port(), &[first_port]).await;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(peer_count(&other).await, 0);
+    assert_eq!(peer_count(&first).await, peers);
+
+    for (shutdown, node) in [(first_shutdown, first_node), (second_shutdown, second_node), (other_shutdown, other_node)] {
+        shutdown.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+//! Each network has its own genesis, the one its preset configures, and a node
+//! refuses a database built on another genesis
+
+use consensus::ConsensusConfig as CoreConsensusConfig;
+use consensus_core::merkle::MerkleTree;
+use consensus_core::network::NetworkType;
+use jiopad::config::Config;
+use jiopad::consensus_manager::{ghostdag_genesis, ConsensusManager};
+use jiopad::storage_manager::StorageManager;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[test]
+fn test_genesis_matches_configured_hash() {
+    let mut hashes = HashSet::new();
+    for network in NetworkType::iter() {
+        let config = Config::for_network(&network.to_string()).unwrap();
+        let genesis = CoreConsensusConfig { network, ..Default::default() }.genesis_block();
+        assert_eq!(config.network.genesis_hash, hex::encode(genesis.header.hash.as_bytes()), "{}", network);
+        assert_eq!(ghostdag_genesis(&config.network), genesis.header.hash);
+        assert_eq!(config.network.genesis_timestamp, genesis.header.timestamp);
+
+        assert!(genesis.header.direct_parents().is_empty());
+        assert_eq!(genesis.header.daa_score, 0);
+        assert_eq!(genesis.header.blue_score, 0);
+        assert_eq!(genesis.transactions.len(), 1);
+        assert!(genesis.transactions[0].is_coinbase());
+        assert_eq!(genesis.header.hash_merkle_root, MerkleTree::from_hashes(vec![genesis.transactions[0].id()]).root());
+        hashes.insert(genesis.header.hash);
+    }
+    assert_eq!(hashes.len(), NetworkType::iter().count(), "networks must not share a genesis");
+
+    // The built-in defaults are mainnet's
+    assert_eq!(Config::default().network.genesis_hash, Config::for_network("mainnet").unwrap().network.genesis_hash);
+}
+
+#[tokio::test]
+async fn test_node_refuses_database_of_another_genesis() {
+    let dir = TempDir::new().unwrap();
+    let mut testnet = Config::for_network("testnet").unwrap();
+    testnet.storage.data_dir = dir.path().to_path_buf();
+    {
+        let storage = Arc::new(StorageManager::new(&testnet.storage).await.unwrap());
+        let consensus = ConsensusManager::new(&testnet.consensus, storage.clone(), &testnet.network).await.unwrap();
+        let genesis = ghostdag_genesis(&testnet.network);
+        assert!(consensus.storage().has_block(&genesis));
+    }
+
+    let mut mainnet = Config::for_network("mainnet").unwrap();
+    mainnet.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&mainnet.storage).await.unwrap());
+    let error = ConsensusManager::new(&mainnet.consensus, storage.clone(), &mainnet.network).await.err().unwrap();
+    assert!(error.contains("genesis"), "{}", error);
+    assert!(!storage.consensus_storage().has_block(&ghostdag_genesis(&mainnet.network)));
+    drop(storage);
+
+    // The same network starts again
+    let storage = Arc::new(StorageManager::new(&testnet.storage).await.unwrap());
+    assert!(ConsensusManager::new(&testnet.consensus, storage, &testnet.network).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_configured_genesis_must_be_the_networks() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    config.network.genesis_hash = Config::for_network("devnet").unwrap().network.genesis_hash;
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let error = ConsensusManager::new(&config.consensus, storage.clone(), &config.network).await.err().unwrap();
+    assert!(error.contains("is not the simnet genesis"), "{}", error);
+    assert_eq!(storage.consensus_storage().block_store().block_count(), 0);
+}
@@ -0,0 +1,360 @@
+//! Headers-first sync from mock peers serving a chain mined on another node,
+//! switching peers when one disconnects or serves invalid headers, following
+//! the peer with the heaviest tip, handing the bodies an unresponsive peer
+//! holds to the others, and the progress reported along the way
+
+use consensus::process::DownloadConfig;
+use consensus_core::block::Block;
+use consensus_core::encoding::block_size;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::header::Header;
+use consensus_core::{BlueWorkType, Hash};
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::network_manager::NetworkManager;
+use jiopad::storage_manager::StorageManager;
+use jiopad::sync_manager::{best_peer, headers_after, SyncManager, SyncPeer, SyncPhase};
+use network::protowire::ChainTip;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+const CHAIN_LENGTH: usize = 12;
+const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+async fn consensus(dir: &TempDir) -> Arc<ConsensusManager> {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    Arc::new(ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap())
+}
+
+/// A node with a chain of `CHAIN_LENGTH` blocks on genesis
+async fn mined_node(dir: &TempDir) -> Arc<ConsensusManager> {
+    let consensus = consensus(dir).await;
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    );
+    for _ in 0..CHAIN_LENGTH {
+        let template = coordinator.get_block_template(ADDRESS.to_string(), None).await.unwrap();
+        let block = (0u64..)
+            .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+            .find(|block| validate_pow(&block.header))
+            .unwrap();
+        coordinator.submit_block(block).await.unwrap();
+    }
+    consensus
+}
+
+async fn sync_manager(dir: &TempDir, consensus: Arc<ConsensusManager>) -> SyncManager {
+    let config = Config::for_network("devnet").unwrap();
+    let network = Arc::new(NetworkManager::new(&config.p2p, dir.path(), consensus.clone()).await.unwrap());
+    SyncManager::new(network, consensus).with_headers_batch_size(5)
+}
+
+fn chain_tip(consensus: &ConsensusManager) -> Option<(u64, Hash)> {
+    consensus.storage().block_store().get_chain_tip()
+}
+
+/// Serves the chain of `node` up to `served_blue_score`, disconnecting once it
+/// served `bodies_before_disconnect` bodies and corrupting the headers it serves
+/// if `corrupt_headers` is set. Bodies take `latency` to arrive, or never do if
+/// `unresponsive` is set. It advertises the tip of `node` unless `advertised`
+/// overrides it.
+struct MockPeer {
+    address: SocketAddr,
+    node: Arc<ConsensusManager>,
+    served_blue_score: u64,
+    bodies_before_disconnect: usize,
+    bodies_served: AtomicUsize,
+    largest_body_request: AtomicUsize,
+    header_requests: AtomicUsize,
+    corrupt_headers: bool,
+    latency: Duration,
+    unresponsive: bool,
+    advertised: Mutex<Option<ChainTip>>,
+}
+
+impl MockPeer {
+    fn new(port: u16, node: Arc<ConsensusManager>) -> Self {
+        Self {
+            address: SocketAddr::from(([10, 0, 0, 1], port)),
+            node,
+            served_blue_score: u64::MAX,
+            bodies_before_disconnect: usize::MAX,
+            bodies_served: AtomicUsize::new(0),
+            largest_body_request: AtomicUsize::new(0),
+            header_requests: AtomicUsize::new(0),
+            corrupt_headers: false,
+            latency: Duration::ZERO,
+            unresponsive: false,
+            advertised: Mutex::new(None),
+        }
+    }
+
+    fn advertise(&self, blue_score: u64, blue_work: u64) {
+        let tip = ChainTip { hash: Hash::from_bytes([blue_score as u8; 32]), blue_score, blue_work: BlueWorkType::from(blue_work) };
+        *self.advertised.lock().unwrap() = Some(tip);
+    }
+}
+
+#[async_trait::async_trait]
+impl SyncPeer for MockPeer {
+    fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    fn tip(&self) -> ChainTip {
+        if let Some(tip) = *self.advertised.lock().unwrap() {
+            return tip;
+        }
+        let Some((blue_score, hash)) = chain_tip(&self.node) else {
+            return ChainTip::default();
+        };
+        let blue_work = self.node.storage().block_store().get_header(&hash).map(|header| header.blue_work).unwrap_or_default();
+        ChainTip { hash, blue_score, blue_work }
+    }
+
+    async fn request_headers(&self, locator: Vec<Hash>, limit: usize) -> Result<Vec<Header>, String> {
+        self.header_requests.fetch_add(1, Ordering::SeqCst);
+        let mut headers = headers_after(&self.node.storage(), &locator, limit);
+        headers.retain(|header| header.blue_score <= self.served_blue_score);
+        if self.corrupt_headers {
+            for header in &mut headers {
+                header.nonce += 1;
+            }
+        }
+        Ok(headers)
+    }
+
+    async fn request_blocks(&self, hashes: Vec<Hash>) -> Result<Vec<Block>, String> {
+        self.largest_body_request.fetch_max(hashes.len(), Ordering::SeqCst);
+        if self.bodies_served.fetch_add(hashes.len(), Ordering::SeqCst) >= self.bodies_before_disconnect {
+            return Err("connection reset".to_string());
+        }
+        if self.unresponsive {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(self.latency).await;
+        let block_store = self.node.storage().block_store();
+        Ok(hashes.iter().filter_map(|hash| block_store.get_block(hash)).collect())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_sync_switches_peers_on_disconnect() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+    assert_eq!(sync.sync_status().phase, SyncPhase::Idle);
+
+    // The first peer serves a few bodies, then drops the connection
+    let mut flaky = MockPeer::new(1, server.clone());
+    flaky.bodies_before_disconnect = 3;
+    let peers: Vec<Arc<dyn SyncPeer>> = vec![Arc::new(flaky), Arc::new(MockPeer::new(2, server.clone()))];
+    let status = sync.sync_from(peers).await.unwrap();
+
+    assert_eq!(status.phase, SyncPhase::Synced);
+    assert_eq!(status.headers, CHAIN_LENGTH as u64);
+    assert_eq!(status.bodies_total, CHAIN_LENGTH as u64);
+    assert_eq!(status.bodies_processed, CHAIN_LENGTH as u64);
+    assert_eq!(status.download.remaining_blocks, 0);
+    assert_eq!(chain_tip(&client), chain_tip(&server));
+    assert!(sync.is_sync_complete());
+
+    // Synced again, there is nothing left to download
+    let status = sync.sync_from(vec![Arc::new(MockPeer::new(2, server.clone()))]).await.unwrap();
+    assert_eq!(status.headers, 0);
+    assert_eq!(status.phase, SyncPhase::Synced);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_bodies_held_by_an_unresponsive_peer_come_from_the_others() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let config = DownloadConfig { max_blocks_in_flight: 2, request_timeout: Duration::from_millis(200), ..DownloadConfig::default() };
+    let sync = sync_manager(&client_dir, client.clone()).await.with_download_config(config);
+
+    // The first peer serves headers but never answers a body request; the
+    // second answers each after a delay, so both get requests
+    let mut silent = MockPeer::new(1, server.clone());
+    silent.unresponsive = true;
+    let mut slow = MockPeer::new(2, server.clone());
+    slow.latency = Duration::from_millis(20);
+    let (silent, slow) = (Arc::new(silent), Arc::new(slow));
+    let peers: Vec<Arc<dyn SyncPeer>> = vec![silent.clone(), slow.clone()];
+    let status = tokio::time::timeout(Duration::from_secs(30), sync.sync_from(peers)).await.expect("sync stalled").unwrap();
+
+    assert!(silent.bodies_served.load(Ordering::SeqCst) > 0);
+    assert_eq!(slow.bodies_served.load(Ordering::SeqCst), CHAIN_LENGTH);
+    assert_eq!(status.phase, SyncPhase::Synced);
+    assert_eq!(status.bodies_processed, CHAIN_LENGTH as u64);
+    assert_eq!(status.download.remaining_blocks, 0);
+    assert_eq!(chain_tip(&client), chain_tip(&server));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_download_window_counts_the_bytes_of_received_bodies() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+
+    let mut slow = MockPeer::new(1, server.clone());
+    slow.latency = Duration::from_millis(50);
+    let slow = Arc::new(slow);
+    let status = sync.sync_from(vec![slow.clone()]).await.unwrap();
+
+    // Small bodies fill the window by count, not by the largest allowed size
+    assert_eq!(slow.largest_body_request.load(Ordering::SeqCst), CHAIN_LENGTH);
+    let block_store = server.storage().block_store();
+    let (_, tip) = chain_tip(&server).unwrap();
+    let mut bytes = 0;
+    let mut hash = tip;
+    while let Some(block) = block_store.get_block(&hash).filter(|block| !block.header.direct_parents().is_empty()) {
+        bytes += block_size(&block);
+        hash = block.header.direct_parents()[0];
+    }
+    assert_eq!(status.download.downloaded_blocks, CHAIN_LENGTH as u64);
+    assert_eq!(status.download.downloaded_bytes, bytes);
+    assert_eq!((status.download.remaining_blocks, status.download.remaining_bytes), (0, 0));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_peer_serving_invalid_headers_is_dropped() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+
+    let mut corrupt = MockPeer::new(1, server.clone());
+    corrupt.corrupt_headers = true;
+    let err = sync.sync_from(vec![Arc::new(corrupt)]).await.unwrap_err();
+    assert!(err.contains("No peer left"), "{}", err);
+    assert_eq!(sync.sync_status().headers, 0);
+
+    let mut corrupt = MockPeer::new(1, server.clone());
+    corrupt.corrupt_headers = true;
+    let peers: Vec<Arc<dyn SyncPeer>> = vec![Arc::new(corrupt), Arc::new(MockPeer::new(2, server.clone()))];
+    let status = sync.sync_from(peers).await.unwrap();
+    assert_eq!(status.bodies_processed, CHAIN_LENGTH as u64);
+    assert_eq!(status.peer, Some(SocketAddr::from(([10, 0, 0, 1], 2))));
+    assert_eq!(chain_tip(&client), chain_tip(&server));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_progress_increases_toward_the_advertised_tip() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+    let target = chain_tip(&server).unwrap().0;
+    assert_eq!(target, CHAIN_LENGTH as u64);
+
+    // Headers arrive, but the peer drops before serving any body
+    let mut headers_only = MockPeer::new(1, server.clone());
+    headers_only.served_blue_score = 6;
+    headers_only.bodies_before_disconnect = 0;
+    assert!(sync.sync_from(vec![Arc::new(headers_only)]).await.is_err());
+    let status = sync.sync_status();
+    assert_eq!(status.target_blue_score, target);
+    assert_eq!(status.headers_progress(), 0.5);
+    assert_eq!(status.bodies_progress(), 0.0);
+
+    // Each round the peer serves three more blocks of the twelve it advertises
+    let mut progress = vec![sync.get_sync_progress()];
+    for served in [3, 6, 9, 12] {
+        let mut peer = MockPeer::new(2, server.clone());
+        peer.served_blue_score = served;
+        let status = sync.sync_from(vec![Arc::new(peer)]).await.unwrap();
+        assert_eq!(status.bodies_blue_score, served);
+        assert_eq!(status.headers_progress(), status.bodies_progress());
+        progress.push(sync.get_sync_progress());
+    }
+    assert_eq!(progress, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_headers_come_from_the_peer_with_the_heaviest_tip() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+
+    let (light, heavy, tied) = (MockPeer::new(1, server.clone()), MockPeer::new(2, server.clone()), MockPeer::new(3, server.clone()));
+    light.advertise(12, 100);
+    heavy.advertise(12, 300);
+    tied.advertise(12, 300);
+    let (light, heavy, tied) = (Arc::new(light), Arc::new(heavy), Arc::new(tied));
+    let peers: Vec<Arc<dyn SyncPeer>> = vec![light.clone(), heavy.clone(), tied.clone()];
+    // Ties go to the peer listed first
+    assert_eq!(best_peer(&peers).unwrap().address(), heavy.address);
+
+    let status = sync.sync_from(peers).await.unwrap();
+    assert_eq!(status.peer, Some(heavy.address));
+    assert_eq!(chain_tip(&client), chain_tip(&server));
+    assert_eq!(light.header_requests.load(Ordering::SeqCst), 0);
+    assert_eq!(tied.header_requests.load(Ordering::SeqCst), 0);
+    assert!(heavy.header_requests.load(Ordering::SeqCst) > 0);
+    assert!(best_peer(&[]).is_none());
+}
+
+/// Announces a heavier tip for its rival after serving its first batch
+struct OvertakenPeer {
+    inner: MockPeer,
+    rival: Arc<MockPeer>,
+}
+
+#[async_trait::async_trait]
+impl SyncPeer for OvertakenPeer {
+    fn address(&self) -> SocketAddr {
+        self.inner.address
+    }
+
+    fn tip(&self) -> ChainTip {
+        self.inner.tip()
+    }
+
+    async fn request_headers(&self, locator: Vec<Hash>, limit: usize) -> Result<Vec<Header>, String> {
+        let headers = self.inner.request_headers(locator, limit).await?;
+        self.rival.advertise(12, 500);
+        Ok(headers)
+    }
+
+    async fn request_blocks(&self, hashes: Vec<Hash>) -> Result<Vec<Block>, String> {
+        self.inner.request_blocks(hashes).await
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_a_heavier_tip_announced_mid_sync_takes_over() {
+    let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let server = mined_node(&server_dir).await;
+    let client = consensus(&client_dir).await;
+    let sync = sync_manager(&client_dir, client.clone()).await;
+
+    let first = MockPeer::new(1, server.clone());
+    first.advertise(12, 200);
+    let rival = Arc::new(MockPeer::new(2, server.clone()));
+    rival.advertise(12, 100);
+    let first = Arc::new(OvertakenPeer { inner: first, rival: rival.clone() });
+    let peers: Vec<Arc<dyn SyncPeer>> = vec![first.clone(), rival.clone()];
+
+    let status = sync.sync_from(peers).await.unwrap();
+    assert_eq!(first.inner.header_requests.load(Ordering::SeqCst), 1);
+    assert!(rival.header_requests.load(Ordering::SeqCst) > 0);
+    assert_eq!(status.peer, Some(rival.address));
+    assert_eq!(status.headers, CHAIN_LENGTH as u64);
+    assert_eq!(chain_tip(&client), chain_tip(&server));
+}
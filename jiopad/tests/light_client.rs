@@ -0,0 +1,67 @@
+//! getHeaders serves the selected chain as canonical-encoded headers, which a
+//! light client checks for linkage and proof of work
+
+use consensus_core::encoding::decode_header;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::header::Header;
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator, RpcError};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const PAY_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+async fn coordinator(dir: &TempDir) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+/// Mine and submit a block on the current template, returning its parent and hash
+async fn mine(coordinator: &RpcCoordinator) -> (Hash, Hash) {
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let block = (0u64..)
+        .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+        .find(|block| validate_pow(&block.header))
+        .unwrap();
+    let hash = coordinator.submit_block(block).await.unwrap();
+    (template.parent_hashes[0], hash)
+}
+
+fn decode(headers: &[String]) -> Vec<Header> {
+    headers.iter().map(|encoded| decode_header(&hex::decode(encoded).unwrap()).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn test_get_headers_follows_the_selected_chain() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = coordinator(&dir).await;
+    let (genesis, first) = mine(&coordinator).await;
+    let (parent, second) = mine(&coordinator).await;
+    assert_eq!(parent, first);
+
+    let headers = decode(&coordinator.get_headers(genesis, 10).await.unwrap());
+    assert_eq!(headers.iter().map(|header| header.hash).collect::<Vec<_>>(), vec![first, second]);
+    assert_eq!(headers[1].direct_parents(), &[first]);
+    assert!(headers.iter().all(validate_pow));
+
+    // The limit caps the page and the tip has nothing after it
+    assert_eq!(decode(&coordinator.get_headers(genesis, 1).await.unwrap())[0].hash, first);
+    assert!(coordinator.get_headers(second, 10).await.unwrap().is_empty());
+
+    let err = coordinator.get_headers(Hash::from_bytes([7; 32]), 10).await.unwrap_err();
+    assert!(matches!(err, RpcError::Rpc { code: -5, .. }));
+}
@@ -0,0 +1,90 @@
+//! File logging rotates by size, and the level filter changes at runtime
+
+use jiopad::config::LogConfig;
+use jiopad::logging::{self, RotatingFile, LOG_FILE};
+use rpc_core::LogControl;
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+use tracing::{debug, info};
+
+#[test]
+fn test_log_file_rotates_past_the_size_limit() {
+    let dir = TempDir::new().unwrap();
+    let line = [b'x'; 29].iter().chain(b"\n").copied().collect::<Vec<u8>>();
+    let mut file = RotatingFile::open(dir.path(), 100, 2).unwrap();
+    // Three lines fit in a file, the fourth starts the next one
+    for _ in 0..10 {
+        file.write_all(&line).unwrap();
+    }
+    file.flush().unwrap();
+
+    let sizes: Vec<u64> = (0..=2).map(|n| fs::metadata(RotatingFile::path(dir.path(), n)).unwrap().len()).collect();
+    assert_eq!(sizes, vec![30, 90, 90]);
+    // The oldest file was dropped
+    assert!(!RotatingFile::path(dir.path(), 3).exists());
+
+    // Reopening continues the current file
+    let mut file = RotatingFile::open(dir.path(), 100, 2).unwrap();
+    file.write_all(&line).unwrap();
+    assert_eq!(fs::metadata(dir.path().join(LOG_FILE)).unwrap().len(), 60);
+
+    // Without rotated files to keep the current one starts over
+    let mut file = RotatingFile::open(dir.path(), 100, 0).unwrap();
+    file.write_all(&line).unwrap();
+    file.write_all(&line).unwrap();
+    assert_eq!(fs::metadata(dir.path().join(LOG_FILE)).unwrap().len(), 30);
+    assert!(!RotatingFile::path(dir.path(), 3).exists());
+}
+
+#[test]
+fn test_subscriber_writes_rotated_files() {
+    let dir = TempDir::new().unwrap();
+    let config = LogConfig { level: "info".to_string(), dir: Some(dir.path().to_path_buf()), max_file_size: 1024, max_files: 3 };
+    let (subscriber, _, guard) = logging::build(&config, true).unwrap();
+    tracing::subscriber::with_default(subscriber, || {
+        for n in 0..100 {
+            info!("filling the log file with line {}", n);
+        }
+    });
+    // Dropping the guard writes out what's buffered
+    drop(guard);
+
+    for n in 0..=3 {
+        let size = fs::metadata(RotatingFile::path(dir.path(), n)).unwrap().len();
+        assert!(size > 0 && size <= 1024, "file {} has {} bytes", n, size);
+    }
+    assert!(!RotatingFile::path(dir.path(), 4).exists());
+    let current = fs::read_to_string(dir.path().join(LOG_FILE)).unwrap();
+    assert!(current.contains("filling the log file with line 99"));
+}
+
+#[test]
+fn test_log_level_changes_at_runtime() {
+    let dir = TempDir::new().unwrap();
+    let config = LogConfig { level: "info".to_string(), dir: Some(dir.path().to_path_buf()), ..LogConfig::default() };
+    let (subscriber, handle, guard) = logging::build(&config, true).unwrap();
+    tracing::subscriber::with_default(subscriber, || {
+        debug!(target: "network", "peer chatter before");
+        assert_eq!(handle.set_log_level("network", "debug"), Ok("info,network=debug".to_string()));
+        debug!(target: "network", "peer chatter after");
+        debug!(target: "consensus", "consensus chatter");
+
+        // Setting a target again replaces its directive
+        assert_eq!(handle.set_log_level("network", "WARN"), Ok("info,network=warn".to_string()));
+        info!(target: "network", "network info hidden");
+        assert_eq!(handle.set_log_level("", "debug"), Ok("debug,network=warn".to_string()));
+        debug!(target: "consensus", "consensus debug shown");
+
+        assert!(handle.set_log_level("network", "loud").is_err());
+        assert_eq!(handle.filter(), "debug,network=warn");
+    });
+    drop(guard);
+
+    let log = fs::read_to_string(dir.path().join(LOG_FILE)).unwrap();
+    assert!(!log.contains("peer chatter before"));
+    assert!(log.contains("peer chatter after"));
+    assert!(!log.contains("consensus chatter"));
+    assert!(!log.contains("network info hidden"));
+    assert!(log.contains("consensus debug shown"));
+}
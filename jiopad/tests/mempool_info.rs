@@ -0,0 +1,87 @@
+//! Mempool info reports fee and mass totals and how many blocks the pool fills
+
+use consensus_core::constants::MAX_BLOCK_MASS;
+use consensus_core::subnets::SubnetworkId;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{MempoolEntry, MempoolInterface, RpcApi, RpcCoordinator};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Pool of prepared entries
+struct SyntheticMempool(Vec<MempoolEntry>);
+
+impl MempoolInterface for SyntheticMempool {
+    fn add_transaction(&self, _tx: Transaction) -> Result<(), String> {
+        Err("Synthetic mempool is read-only".to_string())
+    }
+
+    fn remove_transaction(&self, _tx_id: &str) -> Result<(), String> {
+        Err("Synthetic mempool is read-only".to_string())
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get_all_transactions(&self) -> Vec<Transaction> {
+        self.0.iter().map(|entry| entry.transaction.clone()).collect()
+    }
+
+    fn get_entries(&self) -> Vec<MempoolEntry> {
+        self.0.clone()
+    }
+}
+
+/// Transaction of 200 mass: one input, one output and a 64 byte payload
+fn entry(n: u64, fee: u64) -> MempoolEntry {
+    let transaction = Transaction::new(
+        0,
+        vec![TransactionInput::new(TransactionOutpoint::new(Hash::from_le_u64([n, 0, 0, 0]), 0), vec![], 0, 1)],
+        vec![TransactionOutput::new(1_000, ScriptPublicKey::from_vec(0, vec![0xac]))],
+        0,
+        SubnetworkId::default(),
+        0,
+        vec![0; 64],
+    );
+    assert_eq!(transaction.calculate_mass(), 200);
+    MempoolEntry { fee, transaction, is_orphan: false }
+}
+
+async fn coordinator(dir: &TempDir, entries: Vec<MempoolEntry>) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(SyntheticMempool(entries)) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+#[tokio::test]
+async fn test_mempool_info_estimates_block_count() {
+    let per_block = MAX_BLOCK_MASS / 200;
+    // Two full blocks and a partial third
+    let count = per_block * 2 + per_block / 2;
+    let entries: Vec<MempoolEntry> = (0..count).map(|n| entry(n, 1_000 + n % 7)).collect();
+    let total_fees: u64 = entries.iter().map(|entry| entry.fee).sum();
+
+    let dir = TempDir::new().unwrap();
+    let info = coordinator(&dir, entries).await.get_mempool_info().await.unwrap();
+    assert_eq!(info.size, count as usize);
+    assert_eq!(info.total_fees, total_fees);
+    assert_eq!(info.total_mass, count * 200);
+    assert_eq!(info.estimated_block_count, 3);
+
+    let dir = TempDir::new().unwrap();
+    let info = coordinator(&dir, Vec::new()).await.get_mempool_info().await.unwrap();
+    assert_eq!((info.size, info.total_fees, info.total_mass, info.estimated_block_count), (0, 0, 0, 0));
+}
@@ -0,0 +1,148 @@
+//! The mempool survives a restart through `mempool.dat`, and entries spent by
+//! blocks mined while the node was down are dropped on the way back in
+
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::script_class::pay_to_address_script;
+use consensus_core::tx::{Transaction, TransactionInput, TransactionOutpoint, TransactionOutput, UtxoEntry};
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::{encode_entries, Mempool, MempoolRestore, MempoolTransaction, MEMPOOL_FILE, MEMPOOL_LOAD_TIME_LIMIT};
+use jiopad::storage_manager::StorageManager;
+use rpc_core::MempoolInterface;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::{Address, Keys, Signer};
+
+fn outpoint(n: u8) -> TransactionOutpoint {
+    TransactionOutpoint::new(Hash::from_bytes([n; 32]), 0)
+}
+
+/// The wallet every test output is paid to
+fn keys() -> Keys {
+    Keys::from_seed([7; 64])
+}
+
+fn address() -> String {
+    Address::from_public_key(&keys().generate_address().unwrap().1)
+}
+
+/// A transaction spending `outpoints` of `spent_amount` each, paid to and signed by [`keys`]
+fn spend(outpoints: &[TransactionOutpoint], spent_amount: u64, value: u64) -> Transaction {
+    let secret = keys().generate_address().unwrap().0;
+    let tx = Transaction::new(
+        1,
+        outpoints.iter().map(|outpoint| TransactionInput::new(*outpoint, vec![], 0, 1)).collect(),
+        vec![TransactionOutput::new(value, pay_to_address_script(&address()).unwrap())],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    );
+    let entry = UtxoEntry::new(spent_amount, pay_to_address_script(&address()).unwrap(), 0, false);
+    let mut tx = Signer::new(keys()).sign_transaction(tx, &vec![secret; outpoints.len()], &vec![entry; outpoints.len()]).unwrap();
+    tx.finalize();
+    tx
+}
+
+#[tokio::test]
+async fn test_restart_keeps_only_still_valid_transactions() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    let utxo_set = consensus.storage().utxo_set();
+    let script = pay_to_address_script(&address()).unwrap();
+    for n in 1..=3 {
+        utxo_set.add_utxo(outpoint(n), UtxoEntry::new(10_000, script.clone(), 0, false)).unwrap();
+    }
+
+    let path = dir.path().join(MEMPOOL_FILE);
+    let first = spend(&[outpoint(1)], 10_000, 9_000);
+    let second = spend(&[outpoint(2)], 10_000, 9_500);
+    let mined = spend(&[outpoint(3)], 10_000, 9_900);
+    // Spends the change of `first`, which only the pool knows about
+    let chained = spend(&[TransactionOutpoint::new(first.id(), 0)], 9_000, 8_800);
+    let mempool = Mempool::new().with_persistence(path.clone());
+    for tx in [&first, &second, &mined, &chained] {
+        mempool.add_transaction(tx.clone()).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    assert_eq!(mempool.flush(), Ok(4));
+    drop(mempool);
+
+    // While the node is down a block spends the input of `mined`
+    utxo_set.remove_utxo(&outpoint(3)).unwrap();
+
+    let mempool = Mempool::new().with_persistence(path.clone());
+    let outcome = mempool.restore(|tx, pending| consensus.validate_transaction(tx, pending), MEMPOOL_LOAD_TIME_LIMIT).unwrap();
+    assert_eq!(outcome, MempoolRestore { restored: 3, discarded: 1, expired: 0 });
+    assert_eq!(mempool.get_transaction(&first.hash()), Some(first.clone()));
+    assert_eq!(mempool.get_transaction(&chained.hash()), Some(chained.clone()));
+    assert_eq!(mempool.get_transaction(&second.hash()), Some(second.clone()));
+    assert_eq!(mempool.get_transaction(&mined.hash()), None);
+
+    // A pooled spend of an output hides it from a second one
+    let double_spend = spend(&[outpoint(1)], 10_000, 8_000);
+    let err = consensus.validate_transaction(&double_spend, &mempool.pending_effects(&double_spend)).unwrap_err();
+    assert_eq!(err, "Invalid UTXO reference");
+    assert!(consensus.validate_transaction(&double_spend, &[]).is_ok());
+    assert!(mempool.add_transaction(double_spend).unwrap_err().contains(&first.hash().to_string()));
+
+    // Spending a key's output takes its signature
+    let mut unsigned = spend(&[outpoint(2)], 10_000, 9_000);
+    unsigned.inputs[0].signature_script.clear();
+    let err = consensus.validate_transaction(&unsigned, &[]).unwrap_err();
+    assert!(err.contains("invalid signature"), "{}", err);
+
+    // Restored entries carry the fee validation computed
+    let mut fees: Vec<u64> = mempool.get_entries().iter().map(|entry| entry.fee).collect();
+    fees.sort();
+    assert_eq!(fees, vec![200, 500, 1_000]);
+}
+
+#[test]
+fn test_restore_drops_conflicts_and_honours_the_time_limit() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(MEMPOOL_FILE);
+
+    // A missing file restores nothing
+    let mempool = Mempool::new().with_persistence(path.clone());
+    assert_eq!(mempool.restore(|_, _| Ok(0), MEMPOOL_LOAD_TIME_LIMIT), Ok(MempoolRestore::default()));
+
+    let original = spend(&[outpoint(1)], 10_000, 9_000);
+    let double_spend = spend(&[outpoint(1), outpoint(2)], 10_000, 9_000);
+    mempool.add_transaction(original.clone()).unwrap();
+    assert!(mempool.add_transaction(double_spend.clone()).is_err());
+    // So a file holding both is written by hand
+    let entries = [
+        MempoolTransaction { transaction: original.clone(), received_time: 1, fee: 0 },
+        MempoolTransaction { transaction: double_spend.clone(), received_time: 2, fee: 0 },
+    ];
+    std::fs::write(&path, encode_entries(&entries)).unwrap();
+
+    // The earlier of two transactions spending the same output wins
+    let restored = Mempool::new().with_persistence(path.clone());
+    let outcome = restored.restore(|_, _| Ok(100), MEMPOOL_LOAD_TIME_LIMIT).unwrap();
+    assert_eq!(outcome, MempoolRestore { restored: 1, discarded: 1, expired: 0 });
+    assert!(restored.get_transaction(&original.hash()).is_some());
+
+    let expired = Mempool::new().with_persistence(path.clone());
+    let outcome = expired
+        .restore(
+            |_, _| {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(0)
+            },
+            Duration::from_millis(5),
+        )
+        .unwrap();
+    assert_eq!(outcome, MempoolRestore { restored: 1, discarded: 0, expired: 1 });
+
+    // Without persistence there is nothing to flush
+    assert!(Mempool::new().flush().is_err());
+    std::fs::write(&path, b"garbage").unwrap();
+    assert!(Mempool::new().with_persistence(path).restore(|_, _| Ok(0), MEMPOOL_LOAD_TIME_LIMIT).is_err());
+}
@@ -0,0 +1,249 @@
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::script_class::pay_to_address_script;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::mempool::{ChainLimits, Mempool};
+use rpc_core::package::PackageFeerate;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+fn tx(n: u8, scripts: Vec<ScriptPublicKey>) -> Transaction {
+    tx_with_values(n, scripts.into_iter().map(|script| (1000, script)).collect())
+}
+
+fn tx_with_values(n: u8, outputs: Vec<(u64, ScriptPublicKey)>) -> Transaction {
+    Transaction::new(
+        0,
+        vec![TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([n; 32]), 0), vec![], 0, 1)],
+        outputs.into_iter().map(|(value, script)| TransactionOutput::new(value, script)).collect(),
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    )
+}
+
+#[test]
+fn test_non_standard_outputs_are_not_relayed() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    let non_standard = ScriptPublicKey::from_vec(0, vec![0xac]);
+
+    let mempool = Mempool::new();
+    mempool.add_transaction(tx(1, vec![standard.clone()])).unwrap();
+    let err = mempool.add_transaction(tx(2, vec![standard.clone(), non_standard.clone()])).unwrap_err();
+    assert_eq!(err, "Output 1 has a non-standard script");
+    assert_eq!(mempool.size(), 1);
+
+    let permissive = Mempool::new().with_accept_non_standard(true);
+    permissive.add_transaction(tx(2, vec![standard, non_standard])).unwrap();
+}
+
+#[test]
+fn test_dust_outputs_are_not_relayed() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+
+    let mempool = Mempool::new();
+    let err = mempool.add_transaction(tx_with_values(1, vec![(1000, standard.clone()), (160, standard.clone())])).unwrap_err();
+    assert_eq!(err, "Output 1 of 160 sompi is dust; the threshold is 161 sompi");
+    mempool.add_transaction(tx_with_values(1, vec![(1000, standard.clone()), (161, standard.clone())])).unwrap();
+
+    // The threshold scales with the relay feerate
+    let strict = Mempool::new().with_min_relay_feerate(10);
+    let err = strict.add_transaction(tx_with_values(2, vec![(1000, standard.clone())])).unwrap_err();
+    assert_eq!(err, "Output 0 of 1000 sompi is dust; the threshold is 1601 sompi");
+    strict.add_transaction(tx_with_values(2, vec![(1601, standard)])).unwrap();
+}
+
+#[test]
+fn test_transactions_below_the_min_relay_feerate_are_refused() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    // One input and one output weigh 180 grams
+    let txs: Vec<Transaction> = (1..=4).map(|n| tx(n, vec![standard.clone()])).collect();
+    assert_eq!(txs[0].calculate_mass(), 180);
+    let fees: Arc<HashMap<Hash, u64>> = Arc::new(txs.iter().map(|tx| tx.hash()).zip([359, 360, 1_000, 0]).collect());
+    let mempool = || {
+        let fees = fees.clone();
+        Mempool::new().with_min_relay_feerate(2).with_validator(Arc::new(move |tx: &Transaction, _: &[Transaction]| Ok(fees[&tx.hash()])))
+    };
+
+    let strict = mempool();
+    let err = strict.add_transaction(txs[0].clone()).unwrap_err();
+    assert_eq!(err, "Feerate of 1.99 sompi/gram is below the minimum relay feerate of 2 sompi/gram");
+    assert_eq!(strict.size(), 0);
+    strict.add_transaction(txs[1].clone()).unwrap();
+    assert_eq!(strict.get_all_entries()[0].fee, 360);
+
+    // Validation failures are reported as they are
+    let failing = Mempool::new().with_validator(Arc::new(|_: &Transaction, _: &[Transaction]| Err("Missing input".to_string())));
+    assert_eq!(failing.add_transaction(txs[2].clone()).unwrap_err(), "Missing input");
+
+    // Paying only to exempt scripts relays for free
+    let exempt = mempool().with_fee_exempt_scripts(HashSet::from([standard.clone()]));
+    exempt.add_transaction(txs[3].clone()).unwrap();
+    assert!(mempool().add_transaction(txs[3].clone()).is_err());
+}
+
+/// Transaction spending output 0 of `parent`
+fn spending(parent: &Transaction, script: ScriptPublicKey) -> Transaction {
+    Transaction::new(
+        0,
+        vec![TransactionInput::new(TransactionOutpoint::new(parent.hash(), 0), vec![], 0, 1)],
+        vec![TransactionOutput::new(1000, script)],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    )
+}
+
+#[test]
+fn test_full_mempool_evicts_by_package_feerate() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    let parent = tx(1, vec![standard.clone()]);
+    let child = spending(&parent, standard.clone());
+    let loner = tx(2, vec![standard.clone()]);
+    let newcomers: Vec<Transaction> = (3..=6).map(|n| tx(n, vec![standard.clone()])).collect();
+    // Feerates per 180 gram transaction: the parent pays 1 but its package with the child pays 10
+    let feerates = [(&parent, 1), (&child, 19), (&loner, 5), (&newcomers[0], 6), (&newcomers[1], 2), (&newcomers[2], 11), (&newcomers[3], 12)];
+    let fees: HashMap<Hash, u64> = feerates.iter().map(|(tx, feerate)| (tx.hash(), feerate * 180)).collect();
+    let mempool = Mempool::new().with_max_size(3).with_validator(Arc::new(move |tx: &Transaction, _: &[Transaction]| Ok(fees[&tx.hash()])));
+    for tx in [&parent, &child, &loner] {
+        mempool.add_transaction(tx.clone()).unwrap();
+    }
+    // The child pays for its parent
+    let package = PackageFeerate::new(20 * 180, 2 * 180);
+    assert_eq!(mempool.chain_stats(&parent.hash()).unwrap().descendant_package, package);
+    assert_eq!(mempool.chain_stats(&child.hash()).unwrap().ancestor_package, package);
+
+    // The loner at 5 goes rather than the parent at 1
+    mempool.add_transaction(newcomers[0].clone()).unwrap();
+    assert!(!mempool.contains(&loner.hash()));
+    assert!(mempool.contains(&parent.hash()) && mempool.contains(&child.hash()));
+
+    // Nothing pays less than 2
+    assert_eq!(mempool.add_transaction(newcomers[1].clone()).unwrap_err(), "Mempool is full");
+
+    // 11 evicts the transaction at 6, then 12 evicts the package at 10 as a whole
+    mempool.add_transaction(newcomers[2].clone()).unwrap();
+    assert!(!mempool.contains(&newcomers[0].hash()));
+    mempool.add_transaction(newcomers[3].clone()).unwrap();
+    assert!(!mempool.contains(&parent.hash()) && !mempool.contains(&child.hash()));
+    assert_eq!(mempool.size(), 2);
+    assert_eq!(mempool.chain_stats(&child.hash()), None);
+}
+
+#[test]
+fn test_chains_of_unconfirmed_transactions_are_limited() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    let mut chain = vec![tx(1, vec![standard.clone()])];
+    while chain.len() < 30 {
+        chain.push(spending(chain.last().unwrap(), standard.clone()));
+    }
+
+    // The 26th link would have 25 unconfirmed ancestors
+    let mempool = Mempool::new();
+    let refused = chain.iter().position(|tx| mempool.add_transaction(tx.clone()).is_err()).unwrap();
+    assert_eq!(refused, 25);
+    assert_eq!(
+        mempool.add_transaction(chain[25].clone()).unwrap_err(),
+        "The transaction would have 26 ancestors counting itself; the limit is 25"
+    );
+    let root = mempool.chain_stats(&chain[0].hash()).unwrap();
+    assert_eq!((root.ancestors, root.descendants), (1, 25));
+    assert_eq!(root.descendant_package.mass, 25 * 180);
+    let tip = mempool.chain_stats(&chain[24].hash()).unwrap();
+    assert_eq!((tip.ancestors, tip.descendants), (25, 1));
+
+    // Mining the root shortens the chain by one link
+    mempool.remove_transaction(&chain[0].hash()).unwrap();
+    assert_eq!(mempool.chain_stats(&chain[24].hash()).unwrap().ancestors, 24);
+    mempool.add_transaction(chain[25].clone()).unwrap();
+    assert_eq!(mempool.chain_stats(&chain[1].hash()).unwrap().descendants, 25);
+    assert!(mempool.add_transaction(chain[26].clone()).is_err());
+
+    // A parent and 24 children make 25 descendants
+    let parent = tx(2, vec![standard.clone(); 30]);
+    let children: Vec<Transaction> = (0..30)
+        .map(|index| {
+            Transaction::new(
+                0,
+                vec![TransactionInput::new(TransactionOutpoint::new(parent.hash(), index), vec![], 0, 1)],
+                vec![TransactionOutput::new(1000, standard.clone())],
+                0,
+                SUBNETWORK_ID_NATIVE,
+                0,
+                vec![],
+            )
+        })
+        .collect();
+    let mempool = Mempool::new();
+    mempool.add_transaction(parent.clone()).unwrap();
+    for child in &children[..24] {
+        mempool.add_transaction(child.clone()).unwrap();
+    }
+    assert_eq!(
+        mempool.add_transaction(children[24].clone()).unwrap_err(),
+        format!("Pooled transaction {} would have 26 descendants counting itself; the limit is 25", parent.hash())
+    );
+
+    // The mass of a package is limited too
+    let light = Mempool::new().with_chain_limits(ChainLimits { max_package_mass: 3 * 180, ..Default::default() });
+    for tx in &chain[..3] {
+        light.add_transaction(tx.clone()).unwrap();
+    }
+    assert_eq!(
+        light.add_transaction(chain[3].clone()).unwrap_err(),
+        "The transaction would have ancestors weighing 720 grams; the limit is 540"
+    );
+}
+
+#[test]
+fn test_children_pooled_before_their_parent_are_linked() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    let parent = tx(1, vec![standard.clone()]);
+    let child = spending(&parent, standard.clone());
+    let grandchild = spending(&child, standard.clone());
+
+    let mempool = Mempool::new().with_chain_limits(ChainLimits { max_ancestors: 3, ..Default::default() });
+    mempool.add_transaction(grandchild.clone()).unwrap();
+    mempool.add_transaction(parent.clone()).unwrap();
+    assert_eq!(mempool.chain_stats(&parent.hash()).unwrap().descendants, 1);
+
+    // The child joins the two
+    mempool.add_transaction(child.clone()).unwrap();
+    let stats = mempool.chain_stats(&grandchild.hash()).unwrap();
+    assert_eq!(stats.ancestors, 3);
+    assert_eq!(stats.ancestor_package.mass, 3 * 180);
+    assert_eq!(mempool.chain_stats(&parent.hash()).unwrap().descendants, 3);
+
+    // Which would be too long with another link below the grandchild
+    let mempool = Mempool::new().with_chain_limits(ChainLimits { max_ancestors: 3, ..Default::default() });
+    mempool.add_transaction(spending(&grandchild, standard.clone())).unwrap();
+    mempool.add_transaction(grandchild.clone()).unwrap();
+    mempool.add_transaction(parent.clone()).unwrap();
+    let great_grandchild = spending(&grandchild, standard).hash();
+    assert_eq!(
+        mempool.add_transaction(child).unwrap_err(),
+        format!("Pooled transaction {} would have 4 ancestors counting itself; the limit is 3", great_grandchild)
+    );
+}
+
+#[test]
+fn test_second_spends_of_a_pooled_output_are_refused() {
+    let standard = pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    let first = tx(1, vec![standard.clone()]);
+    let second = tx_with_values(1, vec![(2000, standard.clone())]);
+
+    let mempool = Mempool::new();
+    mempool.add_transaction(first.clone()).unwrap();
+    assert_eq!(
+        mempool.add_transaction(second.clone()).unwrap_err(),
+        format!("Input 0 spends an output pooled transaction {} already spends", first.hash())
+    );
+    assert_eq!(mempool.pending_effects(&second), vec![first.clone()]);
+
+    // Once the first leaves the output is free again
+    mempool.remove_transaction(&first.hash()).unwrap();
+    mempool.add_transaction(second.clone()).unwrap();
+    assert_eq!(mempool.get_all_transactions(), vec![second]);
+}
@@ -0,0 +1,150 @@
+use consensus_core::subnets::SubnetworkId;
+use consensus_core::tx::script_class::pay_to_address_script;
+use consensus_core::tx::{Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::mempool::Mempool;
+use consensus::pipeline::{BlockTimings, ProcessingStage, ProcessingTimings};
+use jiopad::metrics::{MetricsRegistry, MetricsServer, NodeMetrics, ReorgDetector};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn hash(n: u8) -> Hash {
+    Hash::from_bytes([n; 32])
+}
+
+fn tx(n: u8) -> Transaction {
+    Transaction::new(
+        0,
+        vec![TransactionInput::new(TransactionOutpoint::new(hash(n), 0), vec![], 0, 1)],
+        vec![TransactionOutput::new(1000, pay_to_address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap())],
+        0,
+        SubnetworkId::default(),
+        0,
+        vec![],
+    )
+}
+
+async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+/// Sample lines of a scrape by series, e.g. `jiopad_peers{direction="inbound"}`
+fn samples(body: &str) -> HashMap<String, f64> {
+    body.lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .map(|line| {
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            (series.to_string(), value.parse().unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_scrape_reports_node_metrics() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let metrics = NodeMetrics::new(&registry);
+    let mempool = Mempool::new().with_metrics(metrics.clone());
+    mempool.add_transaction(tx(1)).unwrap();
+    mempool.add_transaction(tx(2)).unwrap();
+    assert!(mempool.add_transaction(tx(1)).is_err());
+    metrics.blocks.observe(5);
+    metrics.blue_score.set(4.0);
+    metrics.peers_inbound.set(1.0);
+    metrics.peers_outbound.set(3.0);
+
+    let server = MetricsServer::bind("127.0.0.1", 0, registry.clone()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = server.spawn();
+
+    let response = get(addr, "/metrics").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(body.contains("# TYPE jiopad_blocks_total counter"));
+    assert!(body.contains("# TYPE jiopad_mempool_size gauge"));
+
+    let values = samples(body);
+    assert_eq!(values["jiopad_blocks_total"], 5.0);
+    assert_eq!(values["jiopad_virtual_blue_score"], 4.0);
+    assert_eq!(values["jiopad_mempool_size"], 2.0);
+    assert_eq!(values["jiopad_mempool_transactions_accepted_total"], 2.0);
+    assert_eq!(values["jiopad_mempool_transactions_rejected_total"], 1.0);
+    assert_eq!(values["jiopad_peers{direction=\"inbound\"}"], 1.0);
+    assert_eq!(values["jiopad_peers{direction=\"outbound\"}"], 3.0);
+    assert_eq!(values["jiopad_reorgs_total"], 0.0);
+    assert_eq!(values["jiopad_mining_hashrate"], 0.0);
+
+    // Counters follow new transactions between scrapes
+    mempool.add_transaction(tx(3)).unwrap();
+    let values = samples(get(addr, "/metrics").await.split_once("\r\n\r\n").unwrap().1);
+    assert_eq!(values["jiopad_mempool_transactions_accepted_total"], 3.0);
+    assert_eq!(values["jiopad_mempool_size"], 3.0);
+
+    assert!(get(addr, "/other").await.starts_with("HTTP/1.1 404"));
+    handle.abort();
+}
+
+#[test]
+fn test_sampled_counter_never_decreases() {
+    let registry = MetricsRegistry::new();
+    let metrics = NodeMetrics::new(&registry);
+    metrics.blocks.observe(12);
+    metrics.blocks.observe(10);
+    assert_eq!(metrics.blocks.get(), 12);
+    // Registering a name again hands out the same series
+    registry.counter("jiopad_blocks_total", "Blocks stored by the node").inc();
+    assert!(registry.render().contains("\njiopad_blocks_total 13\n"));
+    assert_eq!(registry.render().matches("# HELP jiopad_peers ").count(), 1);
+}
+
+#[test]
+fn test_block_stage_histograms() {
+    let registry = MetricsRegistry::new();
+    let metrics = NodeMetrics::new(&registry);
+    let timings = ProcessingTimings::new();
+    timings.observe(&BlockTimings {
+        header_validation: Duration::from_micros(50),
+        ghostdag: Duration::from_millis(2),
+        body_validation: Duration::from_millis(30),
+        virtual_update: Duration::from_secs(2),
+    });
+    timings.observe(&BlockTimings { ghostdag: Duration::from_millis(3), ..Default::default() });
+    metrics.sample_block_timings(&timings);
+    assert_eq!(metrics.block_stage_seconds.len(), ProcessingStage::ALL.len());
+
+    let body = registry.render();
+    assert_eq!(body.matches("# TYPE jiopad_block_stage_seconds histogram").count(), 1);
+    let values = samples(&body);
+    assert_eq!(values["jiopad_block_stage_seconds_count{stage=\"ghostdag\"}"], 2.0);
+    assert_eq!(values["jiopad_block_stage_seconds_bucket{stage=\"ghostdag\",le=\"0.0025\"}"], 1.0);
+    assert_eq!(values["jiopad_block_stage_seconds_bucket{stage=\"ghostdag\",le=\"0.005\"}"], 2.0);
+    assert_eq!(values["jiopad_block_stage_seconds_sum{stage=\"ghostdag\"}"], 0.005);
+    // Observations above the largest bound only show in +Inf
+    assert_eq!(values["jiopad_block_stage_seconds_bucket{stage=\"virtual_update\",le=\"1\"}"], 1.0);
+    assert_eq!(values["jiopad_block_stage_seconds_bucket{stage=\"virtual_update\",le=\"+Inf\"}"], 2.0);
+}
+
+#[test]
+fn test_reorg_detection() {
+    // 1 <- 2 <- 3 is the selected chain, 4 a sibling of 2
+    let parents = |h: &Hash| match h.as_bytes()[0] {
+        2 => Some((hash(1), 1)),
+        3 => Some((hash(2), 2)),
+        4 => Some((hash(1), 1)),
+        _ => None,
+    };
+    let mut detector = ReorgDetector::default();
+    assert!(!detector.observe(hash(1), 1, parents));
+    assert!(!detector.observe(hash(3), 3, parents));
+    assert!(!detector.observe(hash(3), 3, parents));
+    assert!(detector.observe(hash(4), 2, parents));
+    assert!(!detector.observe(hash(4), 2, parents));
+}
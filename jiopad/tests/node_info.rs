@@ -0,0 +1,65 @@
+//! getInfo summarizes the node: version, network, sync state, counts and NAT state
+
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::network_manager::NetworkManager;
+use jiopad::storage_manager::StorageManager;
+use jiopad::sync_manager::SyncManager;
+use network::nat::NatStatus;
+use rpc_core::{MempoolInterface, RpcApi, RpcCoordinator, RpcNatStatus, SyncStatusProvider};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_get_info_is_populated() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = Arc::new(ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap());
+    let network = Arc::new(NetworkManager::new(&config.p2p, dir.path(), consensus.clone()).await.unwrap());
+    let sync = Arc::new(SyncManager::new(network.clone(), consensus.clone()));
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        network.hub(),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    )
+    .with_network_id(config.network.network_id.clone())
+    .with_sync_status(sync.clone() as Arc<dyn SyncStatusProvider>);
+
+    let info = coordinator.get_info().await.unwrap();
+    assert_eq!(info.server_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.network, "devnet");
+    // No sync has run, so the node is as far as its peers
+    assert!(info.is_synced);
+    assert_eq!(info.sync_progress, 1.0);
+    assert_eq!(info.peer_count, 0);
+    // Genesis only
+    assert_eq!(info.block_count, 1);
+    assert_eq!(info.header_count, 1);
+    assert_eq!(info.mempool_size, 0);
+    assert_eq!(coordinator.get_block_dag_info().await.unwrap().network, "devnet");
+    // NAT traversal hasn't run
+    assert_eq!(info.nat, RpcNatStatus::default());
+
+    // The status the hub records is what getInfo reports
+    network.hub().set_nat_status(NatStatus {
+        protocol: Some("upnp"),
+        external_address: Some("203.0.113.7:16111".parse().unwrap()),
+        inbound_reachable: true,
+        last_error: None,
+    });
+    let nat = coordinator.get_info().await.unwrap().nat;
+    assert_eq!(nat.protocol.as_deref(), Some("upnp"));
+    assert_eq!(nat.external_address.as_deref(), Some("203.0.113.7:16111"));
+    assert!(nat.inbound_reachable);
+
+    // Clients see every field
+    let json = serde_json::to_value(&info).unwrap();
+    for field in ["server_version", "network", "is_synced", "sync_progress", "peer_count", "block_count", "header_count", "mempool_size", "nat"] {
+        assert!(json.get(field).is_some(), "{} missing from {}", field, json);
+    }
+}
@@ -0,0 +1,104 @@
+//! A simnet node started with `--wallet-keystore`: templates requested without
+//! a pay address fund the node's wallet, which then pays through sendToAddress.
+
+mod common;
+
+use common::wait_for;
+use jiopad::daemon::WALLET_PASSWORD_ENV;
+use jiopad::{Config, Daemon};
+use mining::rpc_miner::{self, RpcMiner, RpcMinerConfig};
+use serde_json::json;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::keystore::Keystore;
+use wallet::node_client::NodeClient;
+use wallet::service::RECEIVE_PATH;
+use wallet::{Address, Keys};
+
+const SEED: [u8; 64] = [5u8; 64];
+const AMOUNT: u64 = 50_000;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_node_wallet_mines_and_pays() {
+    let wallet_dir = TempDir::new().unwrap();
+    let keystore_path = wallet_dir.path().join("wallet.json");
+    let mut keystore = Keystore::new();
+    keystore.encrypt("password", &Keystore::create_wallet_data(SEED)).unwrap();
+    keystore.save(&keystore_path).unwrap();
+    std::env::set_var(WALLET_PASSWORD_ENV, "password");
+
+    let node_dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = node_dir.path().to_path_buf();
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = 0;
+    config.p2p.bootstrap_peers.clear();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+    config.wallet.keystore = Some(keystore_path.clone());
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+    let client = NodeClient::new(&rpc_addr.to_string());
+
+    // Templates without a pay address go to the wallet's first receive address
+    let keys = Keys::from_seed(SEED);
+    let path = [&RECEIVE_PATH[..], &[0]].concat();
+    let mining_address = Address::from_public_key(&keys.public_key(&keys.derive_key(&path).unwrap()));
+    let template = client.call("getBlockTemplate", json!([])).await.unwrap();
+    assert_eq!(template["pay_address"], mining_address.as_str());
+
+    let new_address = client.call("getNewAddress", json!([])).await.unwrap();
+    assert_ne!(new_address.as_str().unwrap(), mining_address);
+
+    let mut miner = RpcMiner::new(RpcMinerConfig {
+        num_workers: 1,
+        mining_address: String::new(),
+        template_refresh_interval_ms: 50,
+        max_iterations: 1_000_000,
+    });
+    let template_addr = rpc_addr.to_string();
+    let submit_addr = rpc_addr.to_string();
+    miner.start_mining(
+        move || rpc_miner::fetch_template(&template_addr, ""),
+        move |block| rpc_miner::submit_block(&submit_addr, &block),
+    );
+    wait_for("a mature coinbase in the node's wallet", 60, || {
+        let client = &client;
+        async move {
+            let balances = client.call("getBalances", json!([])).await.ok()?;
+            (balances["available_balance"].as_u64()? > AMOUNT).then_some(())
+        }
+    })
+    .await;
+
+    let (_, recipient_public) = Keys::new().generate_address().unwrap();
+    let recipient = Address::from_public_key(&recipient_public);
+    client.call("sendToAddress", json!([recipient, AMOUNT])).await.unwrap();
+    let balances = client.call("getBalances", json!([])).await.unwrap();
+    assert_eq!(balances["pending_incoming_balance"], 0);
+    assert!(balances["pending_outgoing_change_balance"].is_u64());
+
+    wait_for("the payment to confirm", 60, || {
+        let (client, recipient) = (&client, &recipient);
+        async move { (client.get_balance_by_address(recipient).await.ok()?.balance == AMOUNT).then_some(()) }
+    })
+    .await;
+    tokio::task::block_in_place(|| miner.shutdown());
+
+    // Invalid addresses and unaffordable amounts are refused
+    assert!(client.call("sendToAddress", json!(["not an address", AMOUNT])).await.is_err());
+    assert!(client.call("sendToAddress", json!([recipient, u64::MAX])).await.is_err());
+
+    shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,91 @@
+//! Operators ban peers over RPC: a banned address is disconnected and refused
+//! until it is unbanned
+
+mod common;
+
+use common::wait_for;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::network_manager::NetworkManager;
+use jiopad::storage_manager::StorageManager;
+use network::p2p::{negotiate, EncryptionConfig, Role, Transport};
+use network::protowire::VersionMessage;
+use rpc_core::{MempoolInterface, RpcApi, RpcCoordinator, RpcError};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use tokio::net::TcpStream;
+
+/// Connect to `address` as a plaintext peer running `consensus`'s params
+async fn connect(address: SocketAddr, consensus: &ConsensusManager) -> Result<Transport<TcpStream>, String> {
+    let stream = TcpStream::connect(address).await.map_err(|e| e.to_string())?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let version = VersionMessage::new("peer-bans-test", false)
+        .with_nonce(7)
+        .with_timestamp(now)
+        .with_params_hash(consensus.config().params_hash());
+    let plaintext = EncryptionConfig { enabled: false, allow_plaintext: true, identity: None };
+    let (transport, _) = tokio::time::timeout(Duration::from_secs(5), negotiate(stream, Role::Initiator, &plaintext, version))
+        .await
+        .map_err(|_| "handshake timed out".to_string())??;
+    Ok(transport)
+}
+
+async fn wait_for_peers(coordinator: &RpcCoordinator, expected: usize) {
+    wait_for(&format!("{} peers", expected), 5, || async { (coordinator.get_peer_info().await.unwrap().len() == expected).then_some(()) }).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_banned_peer_is_disconnected_until_unbanned() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    config.p2p.enable_upnp = false;
+    config.p2p.enable_encryption = false;
+    config.p2p.allow_plaintext = true;
+    let address: SocketAddr = format!("127.0.0.1:{}", config.p2p.port).parse().unwrap();
+
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = Arc::new(ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap());
+    let network = NetworkManager::new(&config.p2p, dir.path(), consensus.clone()).await.unwrap();
+    network.start().await.unwrap();
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        network.hub(),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    );
+
+    let mut transport = connect(address, &consensus).await.unwrap();
+    wait_for_peers(&coordinator, 1).await;
+
+    // Banning drops the connection
+    coordinator.ban_peer("127.0.0.1".to_string(), 600).await.unwrap();
+    wait_for_peers(&coordinator, 0).await;
+    let closed = tokio::time::timeout(Duration::from_secs(5), async {
+        while transport.read_frame().await.is_ok() {}
+    });
+    assert!(closed.await.is_ok(), "connection still open");
+    let banned = coordinator.get_banned_peers().await.unwrap();
+    assert_eq!(banned.len(), 1);
+    assert_eq!(banned[0].address, "127.0.0.1");
+    assert!(banned[0].remaining_secs > 590 && banned[0].remaining_secs <= 600);
+
+    // Reconnecting, from any port, is refused
+    assert!(connect(address, &consensus).await.is_err());
+    wait_for_peers(&coordinator, 0).await;
+
+    // Until the ban is lifted; a peer address unbans its IP
+    coordinator.unban_peer("127.0.0.1:1234".to_string()).await.unwrap();
+    assert!(coordinator.get_banned_peers().await.unwrap().is_empty());
+    assert!(matches!(coordinator.unban_peer("127.0.0.1".to_string()).await, Err(RpcError::Rpc { code: -30, .. })));
+    let _transport = connect(address, &consensus).await.unwrap();
+    wait_for_peers(&coordinator, 1).await;
+
+    network.stop().await.unwrap();
+}
@@ -0,0 +1,112 @@
+//! Raw hex of blocks and transactions over RPC: fetching the canonical
+//! encoding, decoding it without submitting, and re-encoding to the same bytes
+
+use consensus_core::block::Block;
+use consensus_core::encoding;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+/// A coordinator over a node with `blocks` blocks mined on genesis
+async fn mined_coordinator(dir: &TempDir, blocks: usize) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    let coordinator = RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    );
+    for _ in 0..blocks {
+        let template = coordinator.get_block_template(ADDRESS.to_string(), None).await.unwrap();
+        let block = (0u64..)
+            .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+            .find(|block| validate_pow(&block.header))
+            .unwrap();
+        coordinator.submit_block(block).await.unwrap();
+    }
+    coordinator
+}
+
+/// Spends `inputs` outputs with long signature scripts into one output, carrying `payload_size` bytes
+fn large_transaction(inputs: u32, payload_size: usize) -> Transaction {
+    let inputs = (0..inputs)
+        .map(|i| TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([i as u8; 32]), i), vec![i as u8; 107], u64::MAX - i as u64, 1))
+        .collect();
+    let output = TransactionOutput::new(1_000, ScriptPublicKey::from_vec(0, vec![0x20; 34]));
+    let payload = (0..payload_size).map(|i| i as u8).collect();
+    Transaction::new(0, inputs, vec![output], 7, SUBNETWORK_ID_NATIVE, 0, payload).with_mass(123_456)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_block_hex_roundtrips() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = mined_coordinator(&dir, 3).await;
+
+    for block in coordinator.get_recent_blocks(3).await.unwrap() {
+        let block_hex = coordinator.get_block_hex(block.header.hash).await.unwrap();
+        assert_eq!(hex::decode(&block_hex).unwrap(), encoding::encode_block(&block));
+
+        let decoded = coordinator.decode_block(block_hex.clone()).await.unwrap();
+        assert_eq!(decoded.header.hash, block.header.hash.to_string());
+        let reencoded = encoding::encode_block(&Block::try_from(decoded).unwrap());
+        assert_eq!(hex::encode(reencoded), block_hex);
+
+        // The coinbase is served by its id
+        let coinbase = &block.transactions[0];
+        let tx_hex = coordinator.get_transaction_hex(coinbase.id()).await.unwrap();
+        let decoded = coordinator.decode_transaction(tx_hex.clone()).await.unwrap();
+        assert_eq!(decoded.transaction_id, coinbase.id().to_string());
+        assert_eq!(hex::encode(encoding::encode_transaction(&Transaction::try_from(decoded).unwrap())), tx_hex);
+    }
+
+    assert!(coordinator.get_block_hex(Hash::from_bytes([9; 32])).await.is_err());
+    assert!(coordinator.get_transaction_hex(Hash::from_bytes([9; 32])).await.is_err());
+}
+
+#[tokio::test]
+async fn test_large_transaction_hex_roundtrips() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = mined_coordinator(&dir, 0).await;
+    let tx = large_transaction(250, 100_000);
+    let tx_hex = hex::encode(encoding::encode_transaction(&tx));
+
+    // Decoding doesn't submit
+    let decoded = coordinator.decode_transaction(tx_hex.clone()).await.unwrap();
+    assert_eq!(decoded.inputs.len(), 250);
+    assert_eq!(decoded.payload.len(), 200_000);
+    assert_eq!(decoded.mass, 123_456);
+    assert!(coordinator.get_mempool_entries(false, false).await.unwrap().is_empty());
+
+    // Through JSON, as clients receive it
+    let json = serde_json::to_string(&decoded).unwrap();
+    let decoded: rpc_core::RpcTransaction = serde_json::from_str(&json).unwrap();
+    let reencoded = encoding::encode_transaction(&Transaction::try_from(decoded).unwrap());
+    assert_eq!(hex::encode(reencoded), tx_hex);
+}
+
+#[tokio::test]
+async fn test_malformed_hex_is_rejected() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = mined_coordinator(&dir, 0).await;
+    let tx_hex = hex::encode(encoding::encode_transaction(&large_transaction(2, 10)));
+
+    assert!(coordinator.decode_transaction("zz".to_string()).await.is_err());
+    assert!(coordinator.decode_transaction(tx_hex[..tx_hex.len() - 2].to_string()).await.is_err());
+    assert!(coordinator.decode_transaction(format!("{}00", tx_hex)).await.is_err());
+    assert!(coordinator.decode_block(tx_hex).await.is_err());
+}
@@ -0,0 +1,93 @@
+//! `jiopad reindex`: secondary indexes are rebuilt from the stored blocks and UTXOs
+
+use consensus_core::block::Block;
+use consensus_core::header::Header;
+use consensus_core::subnets::{SUBNETWORK_ID_COINBASE, SUBNETWORK_ID_NATIVE};
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::{BlueWorkType, Hash, ZERO_HASH};
+use database::stores::SecondaryIndex;
+use jiopad::storage_manager::StorageManager;
+use jiopad::Config;
+use tempfile::TempDir;
+
+fn script(n: u8) -> ScriptPublicKey {
+    ScriptPublicKey::from_vec(0, vec![n; 34])
+}
+
+fn block(parents: Vec<Hash>, timestamp: u64, transactions: Vec<Transaction>) -> Block {
+    let header = Header::new_finalized(
+        1,
+        vec![parents],
+        ZERO_HASH,
+        ZERO_HASH,
+        ZERO_HASH,
+        timestamp,
+        0x1f00ffff,
+        0,
+        timestamp,
+        BlueWorkType::from(0u64),
+        timestamp,
+        ZERO_HASH,
+    );
+    Block::new(header, transactions)
+}
+
+#[tokio::test]
+async fn test_reindex_restores_lookups() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = Config::default().storage;
+    config.data_dir = tmp.path().to_path_buf();
+    let storage = StorageManager::new(&config).await.unwrap();
+    let consensus_storage = storage.consensus_storage();
+
+    // A coinbase paying script 1, then a child block spending it to script 2
+    let coinbase = Transaction::new(0, vec![], vec![TransactionOutput::new(5_000, script(1))], 0, SUBNETWORK_ID_COINBASE, 0, vec![]);
+    let parent = block(vec![], 1, vec![coinbase.clone()]);
+    let payment = Transaction::new(
+        0,
+        vec![TransactionInput::new(TransactionOutpoint::new(coinbase.id(), 0), vec![], 0, 1)],
+        vec![TransactionOutput::new(4_000, script(2))],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    );
+    let child = block(vec![parent.header.hash], 2, vec![payment.clone()]);
+    consensus_storage.apply_block(&parent, 1).unwrap();
+    consensus_storage.apply_block(&child, 2).unwrap();
+
+    let block_store = storage.block_store();
+    let utxo_set = storage.utxo_set();
+    let assert_lookups = || {
+        assert_eq!(block_store.blocks_containing_transaction(&payment.id()), vec![child.header.hash]);
+        assert_eq!(block_store.blocks_containing_transaction(&coinbase.id()), vec![parent.header.hash]);
+        assert_eq!(block_store.get_children(&parent.header.hash), vec![child.header.hash]);
+        let unspent = utxo_set.get_utxos_by_script(&script(2));
+        assert_eq!(unspent.len(), 1);
+        assert_eq!(unspent[0].0, TransactionOutpoint::new(payment.id(), 0));
+        assert!(utxo_set.get_utxos_by_script(&script(1)).is_empty());
+    };
+    assert_lookups();
+
+    // Lose every index; lookups come back empty
+    let index_store = storage.index_store();
+    for index in SecondaryIndex::ALL {
+        index_store.clear(index).unwrap();
+    }
+    assert!(block_store.blocks_containing_transaction(&payment.id()).is_empty());
+    assert!(block_store.get_children(&parent.header.hash).is_empty());
+    assert!(utxo_set.get_utxos_by_script(&script(2)).is_empty());
+
+    let mut progress = Vec::new();
+    let stats = storage.reindex(|done, total| progress.push((done, total))).unwrap();
+    assert_eq!(stats.blocks, 2);
+    assert_eq!(stats.transactions, 2);
+    assert_eq!(stats.unspent_outputs, 1);
+    assert_eq!(progress.last(), Some(&(2, 2)));
+    assert_lookups();
+
+    // The rebuilt indexes are persisted
+    drop((block_store, utxo_set, consensus_storage, index_store, storage));
+    let storage = StorageManager::new(&config).await.unwrap();
+    assert_eq!(storage.block_store().get_children(&parent.header.hash), vec![child.header.hash]);
+}
@@ -0,0 +1,123 @@
+//! The shared RPC client against an in-process wRPC server: requests give what
+//! the coordinator gives, share one connection, and subscriptions stream the
+//! node's notifications over connections of their own
+
+use consensus_core::block::Block;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::Hash;
+use futures_util::StreamExt;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_client::JioRpcClient;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator, RpcError};
+use rpc_wrpc::WrpcServer;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const PAY_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+/// Coordinator of a fresh devnet node, served on a local port
+async fn serve(dir: &TempDir) -> (Arc<RpcCoordinator>, String, tokio::task::JoinHandle<Result<(), String>>) {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    let coordinator = Arc::new(RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    ));
+    let server = WrpcServer::bind(coordinator.clone(), &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let addr = server.local_addrs()[0].to_string();
+    (coordinator, addr, tokio::spawn(server.start()))
+}
+
+async fn mine(coordinator: &RpcCoordinator) -> Block {
+    let template = coordinator.get_block_template(PAY_ADDRESS.to_string(), None).await.unwrap();
+    let block = (0u64..)
+        .map(|nonce| template.to_block(nonce, &[], &CoinbaseOverrides::default()).unwrap())
+        .find(|block| validate_pow(&block.header))
+        .unwrap();
+    coordinator.submit_block(block.clone()).await.unwrap();
+    block
+}
+
+#[tokio::test]
+async fn test_requests_match_the_coordinator() {
+    let dir = TempDir::new().unwrap();
+    let (coordinator, addr, server) = serve(&dir).await;
+    let block = mine(&coordinator).await;
+    let client = JioRpcClient::connect(&addr).await.unwrap();
+
+    assert_eq!(client.get_block_count().await.unwrap(), coordinator.get_block_count().await.unwrap());
+    assert_eq!(client.get_dag_tips().await.unwrap(), vec![block.header.hash]);
+    assert_eq!(client.get_block(block.header.hash).await.unwrap().header.hash, block.header.hash);
+    assert_eq!(client.get_block_by_height(block.header.daa_score).await.unwrap().header.hash, block.header.hash);
+    let info = client.get_block_dag_info().await.unwrap();
+    assert_eq!(info.tip_hashes, coordinator.get_block_dag_info().await.unwrap().tip_hashes);
+
+    // Refusals come back as the node's errors, leaving the connection usable
+    let unknown = client.get_block(Hash::from_u64_word(7)).await;
+    assert!(matches!(unknown, Err(RpcError::Rpc { .. })), "{:?}", unknown.map(|block| block.header.hash));
+    assert_eq!(client.get_block_count().await.unwrap(), coordinator.get_block_count().await.unwrap());
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_use_one_connection() {
+    let dir = TempDir::new().unwrap();
+    let (_coordinator, addr, server) = serve(&dir).await;
+    let client = Arc::new(JioRpcClient::new(&addr).unwrap());
+
+    let requests: Vec<_> = (0..16)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move { client.get_connection_count().await })
+        })
+        .collect();
+    for request in requests {
+        assert_eq!(request.await.unwrap().unwrap().rpc_connections, 1);
+    }
+
+    // A subscription has a connection of its own, closed when it is dropped
+    let subscription = client.subscribe_new_block_template().await.unwrap();
+    assert_eq!(client.get_connection_count().await.unwrap().rpc_connections, 2);
+    drop(subscription);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while client.get_connection_count().await.unwrap().rpc_connections != 1 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the subscription's connection to close");
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_subscriptions_stream_notifications() {
+    let dir = TempDir::new().unwrap();
+    let (coordinator, addr, server) = serve(&dir).await;
+    let client = JioRpcClient::new(&addr).unwrap();
+
+    let (tip, mut chain) = client.subscribe_virtual_chain_changed(None, true).await.unwrap();
+    assert_eq!(tip.blue_score, 0);
+    let mut templates = client.subscribe_new_block_template().await.unwrap();
+
+    let block = mine(&coordinator).await;
+    let changed = tokio::time::timeout(Duration::from_secs(5), chain.next()).await.expect("a chain notification").unwrap().unwrap();
+    assert_eq!(changed.subscription_id, chain.id());
+    assert_eq!(changed.changes.added_chain_block_hashes.last(), Some(&block.header.hash));
+    assert_eq!(changed.changes.accepted_transaction_ids.last().unwrap().accepting_block_hash, block.header.hash);
+    let template = tokio::time::timeout(Duration::from_secs(5), templates.next()).await.expect("a template notification").unwrap().unwrap();
+    assert_eq!(template.subscription_id, templates.id());
+
+    server.abort();
+}
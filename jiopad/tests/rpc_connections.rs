@@ -0,0 +1,93 @@
+//! The wRPC server counts its clients, reports them with getConnectionCount and
+//! refuses clients beyond the configured maximum until one disconnects or fails
+//! its handshake
+
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{ConnectionCounts, MempoolInterface, RpcCoordinator};
+use rpc_wrpc::WrpcServer;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::node_client::NodeClient;
+
+async fn coordinator(dir: &TempDir) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+async fn counts(client: &NodeClient) -> ConnectionCounts {
+    serde_json::from_value(client.call("getConnectionCount", json!([])).await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_connection_count_follows_clients() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = Arc::new(coordinator(&dir).await.with_max_connections(2));
+    let server = WrpcServer::bind(coordinator.clone(), &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let addr = server.local_addrs()[0].to_string();
+    let server = tokio::spawn(server.start());
+
+    let first = NodeClient::new(&addr);
+    assert_eq!(counts(&first).await, ConnectionCounts { rpc_connections: 1, max_rpc_connections: 2, inbound_peers: 0, outbound_peers: 0 });
+    let second = NodeClient::new(&addr);
+    assert_eq!(counts(&second).await.rpc_connections, 2);
+
+    // A third client is refused while both are connected
+    let third = NodeClient::new(&addr);
+    let refusal = third.call("getConnectionCount", json!([])).await.unwrap_err();
+    assert!(refusal.contains("Connection limit reached"), "{}", refusal);
+    assert_eq!(counts(&first).await.rpc_connections, 2);
+
+    drop(second);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while counts(&first).await.rpc_connections != 1 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the disconnect to be counted");
+    assert_eq!(counts(&third).await.rpc_connections, 2);
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_failed_handshake_releases_its_slot() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = Arc::new(coordinator(&dir).await.with_max_connections(1));
+    let server = WrpcServer::bind(coordinator, &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let addr = server.local_addrs()[0];
+    let server = tokio::spawn(server.start());
+
+    // A client that is not speaking WebSocket fails the handshake
+    let mut raw = tokio::net::TcpStream::connect(addr).await.unwrap();
+    tokio::io::AsyncWriteExt::write_all(&mut raw, b"not a websocket handshake\r\n\r\n").await.unwrap();
+    drop(raw);
+
+    // With the only slot held forever, this client would keep being refused
+    let client = NodeClient::new(&addr.to_string());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while client.call("getConnectionCount", json!([])).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the failed client to be released");
+    assert_eq!(counts(&client).await.rpc_connections, 1);
+
+    server.abort();
+}
@@ -0,0 +1,150 @@
+//! `jiopad exportsnapshot` / `importsnapshot`: a node bootstrapped from another
+//! node's snapshot carries on processing blocks from the checkpoint
+
+use clap::Parser;
+use consensus::consensus::types::ConsensusConfig;
+use consensus::process::coinbase::CoinbaseProcessor;
+use consensus::process::snapshot::TrustedCheckpoint;
+use consensus_core::block::Block;
+use consensus_core::constants::BLOCK_VERSION;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::header::Header;
+use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+use consensus_core::{Hash, ZERO_HASH};
+use jiopad::cli::{Args, Command};
+use jiopad::config::Config;
+use jiopad::consensus_manager::{genesis_header, ghostdag_genesis, ConsensusManager};
+use jiopad::storage_manager::StorageManager;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+
+fn config(dir: &TempDir) -> Config {
+    let mut config = Config::default();
+    config.storage.data_dir = dir.path().to_path_buf();
+    config.consensus.block_bits = consensus_pow::target::MAX_TARGET_BITS;
+    config
+}
+
+/// Block on `parent` committing to the GHOSTDAG data `consensus` computes for it
+fn mine_block(consensus: &ConsensusManager, parent: Hash, payload: u8) -> Block {
+    let ghostdag = consensus.ghostdag_manager().get_virtual_ghostdag_data(vec![parent]).unwrap();
+    let schedule = ConsensusConfig {
+        initial_subsidy: consensus.config().initial_subsidy,
+        subsidy_halving_interval: consensus.config().subsidy_halving_interval,
+        ..ConsensusConfig::default()
+    };
+    let coinbase_payload = CoinbaseProcessor::new(schedule).coinbase_payload(ghostdag.blue_score, vec![payload]);
+    let coinbase = Transaction::new(
+        0,
+        Vec::new(),
+        vec![TransactionOutput::new(5_000_000_000, ScriptPublicKey::from_vec(0, vec![payload]))],
+        0,
+        SUBNETWORK_ID_COINBASE,
+        0,
+        coinbase_payload.to_bytes(),
+    );
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let mut header = Header::new_finalized(
+        BLOCK_VERSION,
+        vec![vec![parent]],
+        ZERO_HASH,
+        ZERO_HASH,
+        ZERO_HASH,
+        timestamp,
+        consensus.config().block_bits,
+        0,
+        ghostdag.blue_score,
+        ghostdag.blue_work,
+        ghostdag.blue_score,
+        ZERO_HASH,
+    );
+    while !validate_pow(&header) {
+        header.nonce += 1;
+        header.finalize();
+    }
+    Block::new(header, vec![coinbase])
+}
+
+#[tokio::test]
+async fn test_imported_snapshot_continues_processing() {
+    let source_dir = TempDir::new().unwrap();
+    let source_config = config(&source_dir);
+    let source = Arc::new(StorageManager::new(&source_config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&source_config.consensus, source.clone(), &source_config.network).await.unwrap();
+    let mut tip = ghostdag_genesis(&source_config.network);
+    for payload in 1..=3 {
+        let result = consensus.block_processor().process_block(mine_block(&consensus, tip, payload)).unwrap();
+        assert!(result.is_valid(), "block rejected: {:?}", result.error);
+        tip = result.hash;
+    }
+
+    let file = source_dir.path().join("utxo.snapshot");
+    let snapshot = source.export_snapshot(&file).unwrap();
+    assert_eq!(snapshot.checkpoint, tip);
+    assert_eq!(snapshot.headers.len(), 3);
+    assert_eq!(snapshot.utxos.len(), source.utxo_set().len());
+    let trusted = TrustedCheckpoint { hash: tip, utxo_commitment: source.verify_utxo_commitment().unwrap().stored.finalize() };
+    assert_eq!(snapshot.commitment, trusted.utxo_commitment);
+    // Only empty databases take a snapshot
+    assert!(source.import_snapshot(&file, &genesis_header(&source_config.network).unwrap(), 18, &trusted).is_err());
+
+    let target_dir = TempDir::new().unwrap();
+    let target_config = config(&target_dir);
+    let target = Arc::new(StorageManager::new(&target_config.storage).await.unwrap());
+    let genesis = genesis_header(&target_config.network).unwrap();
+    let imported = target.import_snapshot(&file, &genesis, target_config.consensus.ghostdag_k, &trusted).unwrap();
+    assert_eq!(imported.checkpoint, tip);
+    assert_eq!(imported.blue_score, 3);
+    assert_eq!(target.block_store().get_chain_tip(), Some((3, tip)));
+    assert_eq!(target.verify_utxo_commitment().unwrap().stored.finalize(), snapshot.commitment);
+
+    // The imported node builds on the checkpoint
+    let consensus = ConsensusManager::new(&target_config.consensus, target.clone(), &target_config.network).await.unwrap();
+    assert_eq!(target.block_store().block_count(), 0);
+    let block = mine_block(&consensus, tip, 4);
+    assert_eq!(block.header.blue_score, 4);
+    let result = consensus.block_processor().process_block(block).unwrap();
+    assert!(result.is_valid(), "block rejected: {:?}", result.error);
+    assert_eq!(target.block_store().get_chain_tip(), Some((4, result.hash)));
+    assert_eq!(target.utxo_set().len(), snapshot.utxos.len() + 1);
+    assert!(target.verify_utxo_commitment().unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_snapshot_at_another_checkpoint_is_refused() {
+    let source_dir = TempDir::new().unwrap();
+    let source_config = config(&source_dir);
+    let source = Arc::new(StorageManager::new(&source_config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&source_config.consensus, source.clone(), &source_config.network).await.unwrap();
+    let block = mine_block(&consensus, ghostdag_genesis(&source_config.network), 1);
+    assert!(consensus.block_processor().process_block(block).unwrap().is_valid());
+    let file = source_dir.path().join("utxo.snapshot");
+    let snapshot = source.export_snapshot(&file).unwrap();
+
+    let target_dir = TempDir::new().unwrap();
+    let target_config = config(&target_dir);
+    let target = StorageManager::new(&target_config.storage).await.unwrap();
+    let genesis = genesis_header(&target_config.network).unwrap();
+    let elsewhere = TrustedCheckpoint { hash: Hash::from_bytes([9; 32]), utxo_commitment: snapshot.commitment };
+    let error = target.import_snapshot(&file, &genesis, 18, &elsewhere).unwrap_err();
+    assert!(error.contains("trusted checkpoint"), "{}", error);
+    // Nor at the checkpoint with another UTXO set
+    let other_set = TrustedCheckpoint { hash: snapshot.checkpoint, utxo_commitment: Hash::from_bytes([9; 32]) };
+    let error = target.import_snapshot(&file, &genesis, 18, &other_set).unwrap_err();
+    assert!(error.contains("trusted commitment"), "{}", error);
+    assert_eq!(target.block_store().header_count(), 0);
+    assert!(target.utxo_set().is_empty());
+}
+
+#[test]
+fn test_snapshot_arguments() {
+    let args = Args::parse_from(["jiopad", "exportsnapshot", "/tmp/utxo.snapshot"]);
+    assert!(matches!(args.command, Some(Command::ExportSnapshot { path }) if path.to_str() == Some("/tmp/utxo.snapshot")));
+
+    let args = Args::parse_from(["jiopad", "importsnapshot", "/tmp/utxo.snapshot", "--checkpoint", "ab", "--utxo-commitment", "cd"]);
+    assert!(matches!(args.command, Some(Command::ImportSnapshot { checkpoint, utxo_commitment, .. }) if checkpoint == "ab" && utxo_commitment == "cd"));
+    // Both are required
+    assert!(Args::try_parse_from(["jiopad", "importsnapshot", "/tmp/utxo.snapshot", "--checkpoint", "ab"]).is_err());
+}
@@ -0,0 +1,83 @@
+//! The standalone miner mines on a simnet node over wRPC, moving to the next
+//! template when the node notifies it rather than on its refresh interval
+
+mod common;
+
+use common::wait_for;
+use jiopad::{Config, Daemon};
+use mining::rpc_miner::{self, Payout, RpcMiner, RpcMinerConfig};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::node_client::NodeClient;
+use wallet::{Address, Keys};
+
+fn new_address() -> String {
+    Address::from_public_key(&Keys::new().generate_address().unwrap().1)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_miner_follows_template_notifications() {
+    let node_dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = node_dir.path().to_path_buf();
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = 0;
+    config.p2p.bootstrap_peers.clear();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+    let client = NodeClient::new(&rpc_addr.to_string());
+    let initial_count = client.call("getBlockCount", json!([])).await.unwrap().as_u64().unwrap();
+
+    // Half of the templates pay the dev fee address
+    let (address, devfee_address) = (new_address(), new_address());
+    let payout = Arc::new(Payout::new(address.clone(), Some(devfee_address.clone()), 50).unwrap());
+
+    // Templates are only refreshed on the interval long after the test is over
+    let mut miner = RpcMiner::new(RpcMinerConfig {
+        num_workers: 2,
+        mining_address: address.clone(),
+        template_refresh_interval_ms: 600_000,
+        max_iterations: 1_000_000,
+    });
+    miner.listen_for_templates(&rpc_addr.to_string());
+    let template_addr = rpc_addr.to_string();
+    let submit_addr = rpc_addr.to_string();
+    miner.start_mining(
+        move || rpc_miner::fetch_template(&template_addr, payout.next_address()),
+        move |block| rpc_miner::submit_block(&submit_addr, &block),
+    );
+    wait_for("blocks mined on notified templates", 60, || {
+        let blocks_mined = miner.get_stats().blocks_mined;
+        async move { (blocks_mined >= 2).then_some(()) }
+    })
+    .await;
+    let count = client.call("getBlockCount", json!([])).await.unwrap().as_u64().unwrap();
+    assert!(count >= initial_count + 2);
+
+    // Both the miner and the dev fee address get paid
+    for address in [&address, &devfee_address] {
+        wait_for("a block paying each address", 60, || {
+            let client = &client;
+            async move { (!client.get_utxos_by_address(address).await.ok()?.is_empty()).then_some(()) }
+        })
+        .await;
+    }
+    tokio::task::block_in_place(|| miner.shutdown());
+
+    shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+}
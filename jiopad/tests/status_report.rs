@@ -0,0 +1,102 @@
+use jiopad::status::{StatusFormat, StatusReporter, StatusSample};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Writer that captures everything written into a shared buffer
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn sample(block_count: u64, hashrate: Option<f64>) -> StatusSample {
+    StatusSample {
+        blue_score: block_count - 1,
+        tips: 2,
+        peers_inbound: 3,
+        peers_outbound: 5,
+        mempool_size: 7,
+        sync_progress: 0.5,
+        sync_bytes_per_sec: 0.0,
+        sync_eta: None,
+        block_count,
+        hashrate,
+    }
+}
+
+#[test]
+fn test_status_json_smoke() {
+    let capture = Capture::default();
+    let mut reporter = StatusReporter::with_writer(StatusFormat::Json, Box::new(capture.clone()));
+    reporter.emit(sample(10, None)).unwrap();
+    reporter.emit(sample(12, Some(1500.0))).unwrap();
+
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "expected one JSON object per report: {:?}", output);
+
+    let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(first["blue_score"], 9);
+    assert_eq!(first["tips"], 2);
+    assert_eq!(first["peers_inbound"], 3);
+    assert_eq!(first["peers_outbound"], 5);
+    assert_eq!(first["mempool_size"], 7);
+    assert_eq!(first["sync_percent"], 50.0);
+    assert!(first["blocks_per_sec"].is_number());
+    assert!(first.get("hashrate").is_none());
+    assert!(first.get("sync_eta_secs").is_none());
+
+    let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(second["block_count"], 12);
+    assert_eq!(second["hashrate"], 1500.0);
+}
+
+#[test]
+fn test_blocks_per_sec_over_interval() {
+    let mut reporter = StatusReporter::with_writer(StatusFormat::Json, Box::new(io::sink()));
+    let start = Instant::now();
+    reporter.report_at(sample(100, None), start);
+    let report = reporter.report_at(sample(130, None), start + Duration::from_secs(10));
+    assert_eq!(report.blocks_per_sec, 3.0);
+
+    // Same block count on the next interval means no progress
+    let report = reporter.report_at(sample(130, None), start + Duration::from_secs(20));
+    assert_eq!(report.blocks_per_sec, 0.0);
+}
+
+#[test]
+fn test_status_text_line() {
+    let mut reporter = StatusReporter::with_writer(StatusFormat::Text, Box::new(io::sink()));
+    let line = reporter.report_at(sample(10, Some(2_000_000.0)), Instant::now()).to_string();
+    assert!(!line.contains('\n'));
+    assert!(line.contains("blue score 9"));
+    assert!(line.contains("peers 3 in / 5 out"));
+    assert!(line.contains("sync 50.0%"));
+    assert!(line.contains("mining 2.00 MH/s"));
+}
+
+#[test]
+fn test_status_reports_download_speed_and_eta() {
+    let mut reporter = StatusReporter::with_writer(StatusFormat::Json, Box::new(io::sink()));
+    let sample = StatusSample {
+        sync_bytes_per_sec: 2048.0,
+        sync_eta: Some(Duration::from_secs(90)),
+        ..sample(10, None)
+    };
+    let report = reporter.report_at(sample, Instant::now());
+    assert_eq!(report.sync_eta_secs, Some(90));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["sync_bytes_per_sec"], 2048.0);
+    assert_eq!(json["sync_eta_secs"], 90);
+    assert!(report.to_string().contains("download 2.00 KB/s, ETA 1m 30s"));
+}
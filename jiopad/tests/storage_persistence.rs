@@ -2,9 +2,9 @@ use tempfile::TempDir;
 use std::sync::Arc;
 
 use database::Database;
-use database::stores::{BlockStore as DbBlockStore, HeaderStore as DbHeaderStore, UtxoStore as DbUtxoStore};
-use consensus::consensus::storage::{ConsensusStorage, BlockStore as ConsensusBlockStore, UtxoSet};
-use consensus_core::{ZERO_HASH, BlueWorkType};
+use database::stores::{BlockStore as DbBlockStore, FeeStore as DbFeeStore, HeaderStore as DbHeaderStore, UtxoStore as DbUtxoStore};
+use consensus::consensus::storage::{ConsensusStorage, BlockFeeRecord, BlockFeeStore, BlockStore as ConsensusBlockStore, UtxoSet};
+use consensus_core::{Hash, ZERO_HASH, BlueWorkType};
 use consensus_core::header::Header;
 use consensus_core::block::Block;
 
@@ -72,3 +72,39 @@ fn test_persistence_block_and_utxo() {
     // Block should be persisted
     assert!(cs2.has_block(&hash));
 }
+
+fn fee_record(n: u64) -> BlockFeeRecord {
+    BlockFeeRecord { hash: Hash::from_le_u64([n, 0, 0, 0]), daa_score: n, total_fees: n * 100, mass: 100 }
+}
+
+fn daa_scores(store: &BlockFeeStore) -> Vec<u64> {
+    store.recent(10).iter().map(|record| record.daa_score).collect()
+}
+
+#[test]
+fn test_persistence_block_fees() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path();
+
+    let db = Arc::new(Database::open(path).expect("open db"));
+    let store = BlockFeeStore::new_with_db(3, Arc::new(DbFeeStore::new(db.clone())));
+    for n in 1..=5 {
+        store.insert(fee_record(n)).expect("insert fee record");
+    }
+    drop(store);
+    drop(db);
+
+    // The window is reloaded, and evicted records were deleted along the way
+    let db2 = Arc::new(Database::open(path).expect("open db2"));
+    let db_fees = Arc::new(DbFeeStore::new(db2.clone()));
+    assert_eq!(db_fees.get_all().unwrap().len(), 3);
+    let store2 = BlockFeeStore::new_with_db(3, db_fees.clone());
+    assert_eq!(daa_scores(&store2), vec![5, 4, 3]);
+    store2.insert(fee_record(6)).expect("insert fee record");
+    assert_eq!(daa_scores(&store2), vec![6, 5, 4]);
+
+    // A narrower window drops the oldest stored records
+    let narrowed = BlockFeeStore::new_with_db(2, db_fees.clone());
+    assert_eq!(daa_scores(&narrowed), vec![6, 5]);
+    assert_eq!(db_fees.get_all().unwrap().len(), 2);
+}
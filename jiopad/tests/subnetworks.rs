@@ -0,0 +1,60 @@
+//! Only native and coinbase transactions are valid, and they carry no gas,
+//! unless a devnet enables experimental subnetworks with a block gas limit
+
+use consensus_core::subnets::{SubnetworkId, SUBNETWORK_ID_NATIVE};
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::storage_manager::StorageManager;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn transaction(subnetwork_id: SubnetworkId, gas: u64) -> Transaction {
+    let outpoint = TransactionOutpoint::new(Hash::from_le_u64([7, 0, 0, 0]), 0);
+    let output = TransactionOutput::new(1000, ScriptPublicKey::from_vec(0, vec![1; 20]));
+    Transaction::new(1, vec![TransactionInput::new(outpoint, vec![], 0, 1)], vec![output], 0, subnetwork_id, gas, Vec::new())
+}
+
+async fn open(config: &Config) -> ConsensusManager {
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_non_native_subnetworks_and_gas_are_rejected() {
+    let dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let consensus = open(&config).await;
+
+    let err = consensus.validate_transaction(&transaction(SUBNETWORK_ID_NATIVE, 1), &[]).unwrap_err();
+    assert!(err.contains("carries gas"), "{}", err);
+    let err = consensus.validate_transaction(&transaction(SubnetworkId::from(2u64), 0), &[]).unwrap_err();
+    assert!(err.contains("is not enabled"), "{}", err);
+    // Without gas a native transaction only fails on its unknown input
+    let err = consensus.validate_transaction(&transaction(SUBNETWORK_ID_NATIVE, 0), &[]).unwrap_err();
+    assert_eq!(err, "Invalid UTXO reference");
+}
+
+#[tokio::test]
+async fn test_devnet_params_file_enables_experimental_subnetworks() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("params.toml");
+    std::fs::write(&path, "experimental_subnetworks = true\nmax_block_gas = 100000\n").unwrap();
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().join("data");
+    config.network.params_file = Some(path);
+    config.apply_params_file().unwrap();
+    assert!(config.consensus.experimental_subnetworks);
+    assert_eq!(config.consensus.max_block_gas, 100_000);
+    assert_eq!(config.validate(), Ok(()));
+    assert_ne!(config.consensus.params_hash(), Config::for_network("devnet").unwrap().consensus.params_hash());
+
+    // Experimental transactions now pass the subnetwork rules, gas included
+    let consensus = open(&config).await;
+    let err = consensus.validate_transaction(&transaction(SubnetworkId::from(2u64), 700), &[]).unwrap_err();
+    assert_eq!(err, "Invalid UTXO reference");
+    let err = consensus.validate_transaction(&transaction(SUBNETWORK_ID_NATIVE, 1), &[]).unwrap_err();
+    assert!(err.contains("carries gas"), "{}", err);
+}
@@ -0,0 +1,88 @@
+//! utxosChanged subscriptions only deliver the outputs of the addresses they
+//! watch, and follow the address set as it is modified
+
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::tx::TransactionOutput;
+use jiopad::config::Config;
+use jiopad::consensus_manager::ConsensusManager;
+use jiopad::mempool::Mempool;
+use jiopad::storage_manager::StorageManager;
+use network::Hub;
+use rpc_core::{CoinbaseOverrides, MempoolInterface, RpcApi, RpcCoordinator};
+use rpc_wrpc::WrpcServer;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::node_client::{NodeClient, NodeUtxosChanged, UtxoSubscription};
+use wallet::{Address, Keys};
+
+async fn coordinator(dir: &TempDir) -> RpcCoordinator {
+    let mut config = Config::for_network("devnet").unwrap();
+    config.storage.data_dir = dir.path().to_path_buf();
+    let storage = Arc::new(StorageManager::new(&config.storage).await.unwrap());
+    let consensus = ConsensusManager::new(&config.consensus, storage, &config.network).await.unwrap();
+    RpcCoordinator::new(
+        consensus.block_processor(),
+        consensus.storage(),
+        Arc::new(Hub::new()),
+        Arc::new(Mempool::new()) as Arc<dyn MempoolInterface>,
+        None,
+    )
+}
+
+fn new_address() -> String {
+    Address::from_public_key(&Keys::new().generate_address().unwrap().1)
+}
+
+/// Mine a block whose coinbase is split evenly between `addresses`
+async fn mine_paying(coordinator: &RpcCoordinator, addresses: &[&str]) -> consensus_core::Hash {
+    let template = coordinator.get_block_template(addresses[0].to_string(), None).await.unwrap();
+    let share = template.coinbase_value / addresses.len() as u64;
+    let outputs = addresses.iter().map(|address| TransactionOutput::new(share, Address::to_script_pub_key(address).unwrap())).collect();
+    let overrides = CoinbaseOverrides { outputs: Some(outputs) };
+    let block = (0u64..).map(|nonce| template.to_block(nonce, &[], &overrides).unwrap()).find(|block| validate_pow(&block.header)).unwrap();
+    coordinator.submit_block(block).await.unwrap()
+}
+
+async fn next(subscription: &mut UtxoSubscription) -> NodeUtxosChanged {
+    tokio::time::timeout(Duration::from_secs(5), subscription.next()).await.expect("a notification").unwrap()
+}
+
+#[tokio::test]
+async fn test_only_subscribed_addresses_are_notified() {
+    let dir = TempDir::new().unwrap();
+    let coordinator = Arc::new(coordinator(&dir).await);
+    let server = WrpcServer::bind(coordinator.clone(), &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let client = NodeClient::new(&server.local_addrs()[0].to_string());
+    let server = tokio::spawn(server.start());
+    let (watched, other) = (new_address(), new_address());
+
+    let mut subscription = client.subscribe_utxos_changed(&[watched.clone()]).await.unwrap();
+    let hash = mine_paying(&coordinator, &[&watched, &other]).await;
+    let changed = next(&mut subscription).await;
+    assert_eq!(changed.block_hash, hash);
+    assert_eq!(changed.added.iter().map(|utxo| utxo.address.as_str()).collect::<Vec<_>>(), vec![watched.as_str()]);
+    assert!(changed.removed.is_empty());
+
+    subscription.add_addresses(&[other.clone()]).await.unwrap();
+    let hash = mine_paying(&coordinator, &[&watched, &other]).await;
+    let changed = next(&mut subscription).await;
+    assert_eq!(changed.block_hash, hash);
+    let mut addresses: Vec<&str> = changed.added.iter().map(|utxo| utxo.address.as_str()).collect();
+    addresses.sort();
+    let mut expected = vec![watched.as_str(), other.as_str()];
+    expected.sort();
+    assert_eq!(addresses, expected);
+
+    // The subscription ends with its connection
+    drop(subscription);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !coordinator.utxo_subscriptions().is_empty() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the subscription to be dropped");
+
+    server.abort();
+}
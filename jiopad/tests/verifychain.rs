@@ -0,0 +1,99 @@
+//! `jiopad verifychain`: stored blocks are re-validated and corrupted ones reported
+
+use clap::Parser;
+use consensus_core::block::Block;
+use consensus_core::constants::BLOCK_VERSION;
+use consensus_core::hashing::header::validate_pow;
+use consensus_core::header::Header;
+use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutput};
+use consensus_core::{BlueWorkType, Hash, ZERO_HASH};
+use jiopad::cli::{Args, Command};
+use jiopad::storage_manager::StorageManager;
+use jiopad::Config;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+
+/// Block on top of `parents` at DAA score `daa_score` with a nonce satisfying the easiest target
+fn mine_block(parents: Vec<Hash>, daa_score: u64) -> Block {
+    let coinbase = Transaction::new(
+        0,
+        vec![],
+        vec![TransactionOutput::new(5_000, ScriptPublicKey::from_vec(0, vec![1; 34]))],
+        0,
+        SUBNETWORK_ID_COINBASE,
+        0,
+        daa_score.to_le_bytes().to_vec(),
+    );
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let mut header = Header::new_finalized(
+        BLOCK_VERSION,
+        vec![parents],
+        ZERO_HASH,
+        ZERO_HASH,
+        ZERO_HASH,
+        timestamp,
+        0x207fffff,
+        0,
+        daa_score,
+        BlueWorkType::from(0u64),
+        daa_score,
+        ZERO_HASH,
+    );
+    while !validate_pow(&header) {
+        header.nonce += 1;
+        header.finalize();
+    }
+    Block::new(header, vec![coinbase])
+}
+
+#[tokio::test]
+async fn test_verifychain_flags_corrupted_header() {
+    let tmp = TempDir::new().unwrap();
+    let mut config = Config::default().storage;
+    config.data_dir = tmp.path().to_path_buf();
+    let storage = StorageManager::new(&config).await.unwrap();
+    let block_store = storage.block_store();
+
+    let mut blocks = vec![mine_block(vec![], 1)];
+    for daa_score in 2..=5 {
+        let parent = blocks.last().unwrap().header.hash;
+        blocks.push(mine_block(vec![parent], daa_score));
+    }
+    for block in &blocks {
+        block_store.store_block(block.clone()).unwrap();
+    }
+    // The second block's body was pruned, leaving its header
+    block_store.store_header(blocks[1].header.clone()).unwrap();
+    block_store.remove_block(&blocks[1].header.hash);
+
+    let report = storage.verify_chain(None, true, |_, _| {});
+    assert!(report.is_ok(), "{:?}", report.failures);
+    assert_eq!((report.checked, report.bodies_checked), (5, 4));
+
+    // Flip the nonce of the stored header, and of a block stored with its body
+    let mut header = blocks[1].header.clone();
+    header.nonce = header.nonce.wrapping_add(1);
+    block_store.store_header(header).unwrap();
+    let mut block = blocks[3].clone();
+    block.header.nonce = block.header.nonce.wrapping_add(1);
+    block_store.store_block(block).unwrap();
+
+    let report = storage.verify_chain(None, false, |_, _| {});
+    let failed: Vec<Hash> = report.failures.iter().map(|failure| failure.hash).collect();
+    assert_eq!(failed, vec![blocks[1].header.hash, blocks[3].header.hash]);
+    assert_eq!(report.failures[0].daa_score, 2);
+
+    // Only the last two blocks by DAA score
+    let report = storage.verify_chain(Some(2), false, |_, _| {});
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.failures.iter().map(|failure| failure.hash).collect::<Vec<_>>(), vec![blocks[3].header.hash]);
+}
+
+#[test]
+fn test_verifychain_arguments() {
+    let args = Args::parse_from(["jiopad", "verifychain", "--depth", "100", "--check-bodies"]);
+    assert!(matches!(args.command, Some(Command::VerifyChain { depth: Some(100), check_bodies: true })));
+    let args = Args::parse_from(["jiopad", "verifychain"]);
+    assert!(matches!(args.command, Some(Command::VerifyChain { depth: None, check_bodies: false })));
+}
@@ -0,0 +1,72 @@
+//! `jiopad verifyutxo`: the stored UTXO commitment is checked against the set on disk
+
+use clap::Parser;
+use consensus::consensus::storage::UtxoSet;
+use consensus_core::block::Block;
+use consensus_core::header::Header;
+use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutpoint, TransactionOutput};
+use consensus_core::{BlueWorkType, ZERO_HASH};
+use database::stores::UtxoStore;
+use database::Database;
+use jiopad::cli::{Args, Command};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn coinbase_block(timestamp: u64, outputs: u64) -> Block {
+    let outputs = (1..=outputs).map(|n| TransactionOutput::new(n * 1_000, ScriptPublicKey::from_vec(0, vec![n as u8; 34]))).collect();
+    let coinbase = Transaction::new(0, vec![], outputs, 0, SUBNETWORK_ID_COINBASE, 0, timestamp.to_le_bytes().to_vec());
+    let header = Header::new_finalized(
+        1,
+        vec![],
+        ZERO_HASH,
+        ZERO_HASH,
+        ZERO_HASH,
+        timestamp,
+        0x1f00ffff,
+        0,
+        timestamp,
+        BlueWorkType::from(0u64),
+        timestamp,
+        ZERO_HASH,
+    );
+    Block::new(header, vec![coinbase])
+}
+
+#[test]
+fn test_verifyutxo_detects_mutated_entry() {
+    let tmp = TempDir::new().unwrap();
+    let db = Arc::new(Database::open(tmp.path()).unwrap());
+    let store = Arc::new(UtxoStore::new(db.clone(), 16));
+    let utxo_set = UtxoSet::new_with_db(store.clone());
+    let block = coinbase_block(1, 3);
+    utxo_set.apply_block(&block, 1).unwrap();
+    utxo_set.apply_block(&coinbase_block(2, 2), 2).unwrap();
+
+    let check = utxo_set.verify_commitment().unwrap();
+    assert!(check.is_ok());
+    assert_eq!(check.utxos, 5);
+
+    // The commitment survives a restart
+    drop(utxo_set);
+    let utxo_set = UtxoSet::new_with_db(store.clone());
+    assert!(utxo_set.verify_commitment().unwrap().is_ok());
+
+    // Silently corrupt one entry, leaving the stored commitment as it was
+    let outpoint = TransactionOutpoint::new(block.transactions[0].id(), 1);
+    let mut entry = store.get_utxo(&outpoint).unwrap().unwrap();
+    entry.amount += 1;
+    let stored = store.get_commitment().unwrap().unwrap();
+    store.put_utxo(&outpoint, &entry, &stored).unwrap();
+
+    let check = UtxoSet::new_with_db(store).verify_commitment().unwrap();
+    assert!(!check.is_ok());
+    assert_eq!(check.stored, stored);
+    assert_eq!(check.utxos, 5);
+}
+
+#[test]
+fn test_verifyutxo_arguments() {
+    let args = Args::parse_from(["jiopad", "verifyutxo"]);
+    assert!(matches!(args.command, Some(Command::VerifyUtxo)));
+}
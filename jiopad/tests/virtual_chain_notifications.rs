@@ -0,0 +1,75 @@
+//! notifyVirtualChainChanged subscribers see the selected chain move, reorgs
+//! included, and catch up on what they missed when they resubscribe
+
+mod common;
+
+use common::{block_on, node, ADDRESSES};
+use consensus_core::block::Block;
+use consensus_core::Hash;
+use rpc_core::RpcApi;
+use rpc_wrpc::WrpcServer;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::node_client::{NodeClient, NodeVirtualChainChanged, VirtualChainSubscription};
+
+async fn next(subscription: &mut VirtualChainSubscription) -> NodeVirtualChainChanged {
+    tokio::time::timeout(Duration::from_secs(5), subscription.next()).await.expect("a notification").unwrap()
+}
+
+/// Ids of the transactions of `block`
+fn ids(block: &Block) -> Vec<Hash> {
+    block.transactions.iter().map(|tx| tx.id()).collect()
+}
+
+#[tokio::test]
+async fn test_reorg_is_notified_as_removed_and_added_blocks() {
+    let dir = TempDir::new().unwrap();
+    let (consensus, coordinator) = node(&dir).await;
+    let server = WrpcServer::bind(coordinator.clone(), &["127.0.0.1:0".parse().unwrap()]).unwrap();
+    let client = NodeClient::new(&server.local_addrs()[0].to_string());
+    let server = tokio::spawn(server.start());
+
+    let mut subscription = client.subscribe_virtual_chain_changed(None, true).await.unwrap();
+    assert_eq!(subscription.tip().blue_score, 0);
+
+    // Two competing blocks on genesis; the first one extends the chain
+    let a1 = block_on(&consensus, &coordinator.get_block_template(ADDRESSES[0].to_string(), None).await.unwrap(), None);
+    let b1 = block_on(&consensus, &coordinator.get_block_template(ADDRESSES[1].to_string(), None).await.unwrap(), None);
+    coordinator.submit_block(a1.clone()).await.unwrap();
+    let changed = next(&mut subscription).await;
+    assert!(changed.removed_chain_block_hashes.is_empty());
+    assert_eq!(changed.added_chain_block_hashes.last(), Some(&a1.header.hash));
+    assert_eq!(changed.accepted_transaction_ids.last().unwrap().accepted_transaction_ids, ids(&a1));
+    coordinator.submit_block(b1.clone()).await.unwrap();
+
+    // A child of the second one takes the chain over
+    let template = coordinator.get_block_template(ADDRESSES[2].to_string(), None).await.unwrap();
+    let b2 = block_on(&consensus, &template, Some(vec![b1.header.hash]));
+    coordinator.submit_block(b2.clone()).await.unwrap();
+    let changed = next(&mut subscription).await;
+    assert_eq!(changed.removed_chain_block_hashes, vec![a1.header.hash]);
+    assert_eq!(changed.added_chain_block_hashes, vec![b1.header.hash, b2.header.hash]);
+    assert_eq!(changed.removed_transaction_ids[0].accepted_transaction_ids, ids(&a1));
+    let accepted: Vec<_> = changed.accepted_transaction_ids.iter().map(|accepted| accepted.accepted_transaction_ids.clone()).collect();
+    assert_eq!(accepted, vec![ids(&b1), ids(&b2)]);
+
+    // Resubscribing from the tip last known before the reorg catches up on it
+    let mut resubscribed = client.subscribe_virtual_chain_changed(Some(&a1.header.hash), false).await.unwrap();
+    assert_eq!(resubscribed.tip().hash, b2.header.hash);
+    let caught_up = next(&mut resubscribed).await;
+    assert_eq!(caught_up.removed_chain_block_hashes, vec![a1.header.hash]);
+    assert_eq!(caught_up.added_chain_block_hashes, vec![b1.header.hash, b2.header.hash]);
+    assert!(caught_up.accepted_transaction_ids.is_empty());
+
+    // The same changes can be asked for without subscribing
+    let from_a1 = coordinator.get_virtual_chain_from_block(Some(a1.header.hash), true).await.unwrap();
+    assert_eq!(from_a1.removed_chain_block_hashes, caught_up.removed_chain_block_hashes);
+    assert_eq!(from_a1.added_chain_block_hashes, caught_up.added_chain_block_hashes);
+    assert_eq!(from_a1.removed_transaction_ids[0].accepted_transaction_ids, ids(&a1));
+    let whole = coordinator.get_virtual_chain_from_block(None, false).await.unwrap();
+    assert_eq!(whole.added_chain_block_hashes[1..], [b1.header.hash, b2.header.hash]);
+    assert!(whole.removed_chain_block_hashes.is_empty());
+
+    assert!(client.subscribe_virtual_chain_changed(Some(&Hash::from_u64_word(7)), false).await.is_err());
+    server.abort();
+}
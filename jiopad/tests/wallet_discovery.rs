@@ -0,0 +1,92 @@
+//! Address discovery of an imported seed against a local simnet node: coinbases
+//! paid to receive addresses 5 and 37 are both found.
+
+mod common;
+
+use common::wait_for;
+use jiopad::{Config, Daemon};
+use mining::rpc_miner::{self, RpcMiner, RpcMinerConfig};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tempfile::TempDir;
+use wallet::discovery::{self, DEFAULT_GAP_LIMIT};
+use wallet::keystore::Keystore;
+use wallet::node_client::NodeClient;
+use wallet::service::RECEIVE_PATH;
+use wallet::{Address, Keys};
+
+const SEED: [u8; 64] = [37u8; 64];
+
+fn receive_address(index: u32) -> String {
+    let keys = Keys::from_seed(SEED);
+    let secret_key = keys.derive_key(&[&RECEIVE_PATH[..], &[index]].concat()).unwrap();
+    Address::from_public_key(&keys.public_key(&secret_key))
+}
+
+/// Mine to `address` until the node reports an output for it
+async fn fund(rpc_addr: SocketAddr, client: &NodeClient, address: &str) {
+    let mut miner = RpcMiner::new(RpcMinerConfig {
+        num_workers: 1,
+        mining_address: address.to_string(),
+        template_refresh_interval_ms: 50,
+        max_iterations: 1_000_000,
+    });
+    let (template_addr, pay_address, submit_addr) = (rpc_addr.to_string(), address.to_string(), rpc_addr.to_string());
+    miner.start_mining(
+        move || rpc_miner::fetch_template(&template_addr, &pay_address),
+        move |block| rpc_miner::submit_block(&submit_addr, &block),
+    );
+    wait_for("a coinbase output", 60, || async move {
+        (!client.get_utxos_by_address(address).await.ok()?.is_empty()).then_some(())
+    })
+    .await;
+    tokio::task::block_in_place(|| miner.shutdown());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_discovery_finds_funded_indexes() {
+    let node_dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = node_dir.path().to_path_buf();
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = 0;
+    config.p2p.bootstrap_peers.clear();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+
+    let client = NodeClient::new(&format!("ws://{}", rpc_addr));
+    fund(rpc_addr, &client, &receive_address(5)).await;
+    fund(rpc_addr, &client, &receive_address(37)).await;
+
+    // 31 unused addresses lie between the two, more than the default gap
+    let keys = Keys::from_seed(SEED);
+    let found = discovery::discover(&keys, &client, DEFAULT_GAP_LIMIT).await.unwrap();
+    assert_eq!(found.receive.used, vec![5]);
+
+    let found = discovery::discover(&keys, &client, 32).await.unwrap();
+    assert_eq!(found.receive.used, vec![5, 37]);
+    assert_eq!(found.receive.count, 38);
+    assert_eq!(found.change.count, 0);
+    assert!(found.balance() > 0);
+
+    // The keystore continues after the discovered addresses
+    let mut data = Keystore::create_wallet_data(SEED);
+    found.apply(&mut data);
+    assert!(data.addresses.contains_key(&receive_address(37)));
+    assert_eq!(data.next_index(RECEIVE_PATH[3]), 38);
+
+    shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+}
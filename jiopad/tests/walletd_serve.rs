@@ -0,0 +1,162 @@
+//! `walletd serve` against a local simnet node: mine to the wallet, pay through
+//! its HTTP API and watch the payment confirm.
+
+mod common;
+
+use common::wait_for;
+use jiopad::{Config, Daemon};
+use mining::rpc_miner::{self, RpcMiner, RpcMinerConfig};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use wallet::keystore::Keystore;
+use wallet::service::{ServiceConfig, WalletServer, WalletService};
+use wallet::{Address, Keys};
+
+const AMOUNT: u64 = 50_000;
+
+/// Address of the wallet server and the token it takes
+#[derive(Clone, Copy)]
+struct Api<'a> {
+    addr: SocketAddr,
+    token: &'a str,
+}
+
+/// Call `method` on the wallet server, returning its result or error object
+async fn call(wallet: Api<'_>, method: &str, params: Value) -> Result<Value, Value> {
+    let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+    let mut stream = TcpStream::connect(wallet.addr).await.unwrap();
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        wallet.addr,
+        wallet.token,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let mut response: Value = serde_json::from_str(body).unwrap();
+    match response.get("error") {
+        Some(error) => Err(error.clone()),
+        None => Ok(response["result"].take()),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_walletd_serve_pays_through_http() {
+    // Node
+    let node_dir = TempDir::new().unwrap();
+    let mut config = Config::for_network("simnet").unwrap();
+    config.storage.data_dir = node_dir.path().to_path_buf();
+    config.rpc.bind_address = "127.0.0.1".to_string();
+    config.rpc.port = 0;
+    config.p2p.listen_address = "127.0.0.1".to_string();
+    config.p2p.port = 0;
+    config.p2p.bootstrap_peers.clear();
+    config.metrics.enabled = false;
+    config.status.interval_secs = 0;
+
+    let daemon = Daemon::new(config).await.unwrap();
+    let rpc_server = daemon.rpc_server().unwrap();
+    let shutdown = daemon.shutdown_handle();
+    let node = tokio::spawn(daemon.run());
+    let rpc_addr = wait_for("the RPC server", 10, || {
+        let addrs = rpc_server.local_addrs();
+        async move { addrs.first().copied() }
+    })
+    .await;
+
+    // Wallet service on a fresh keystore
+    let wallet_dir = TempDir::new().unwrap();
+    let keystore_path = wallet_dir.path().join("wallet.json");
+    let mut keystore = Keystore::new();
+    keystore.encrypt("password", &Keystore::create_wallet_data([9u8; 64])).unwrap();
+    keystore.save(&keystore_path).unwrap();
+
+    let service_config = ServiceConfig::new(keystore_path.clone(), rpc_addr.to_string())
+        .with_sync_interval(Duration::from_millis(100));
+    let service = WalletService::new(service_config).unwrap();
+    let server = WalletServer::bind("127.0.0.1:0", service.clone()).await.unwrap();
+    let token = server.auth_token().to_string();
+    let wallet = Api { addr: server.local_addr().unwrap(), token: &token };
+    let server_handle = server.spawn();
+    let sync_handle = service.spawn_sync();
+
+    // Locked until unlocked over the API
+    let error = call(wallet, "get_new_address", json!({})).await.unwrap_err();
+    assert_eq!(error["message"], "Wallet is locked");
+    assert!(call(wallet, "unlock", json!({ "password": "wrong" })).await.is_err());
+    call(wallet, "unlock", json!({ "password": "password" })).await.unwrap();
+    let addresses = call(wallet, "list_addresses", json!({})).await.unwrap();
+    let wallet_address = addresses[0].as_str().unwrap().to_string();
+    let second_address = call(wallet, "get_new_address", json!({})).await.unwrap();
+    assert_ne!(second_address.as_str().unwrap(), wallet_address);
+
+    // Mine to the wallet until it has a mature coinbase
+    let mut miner = RpcMiner::new(RpcMinerConfig {
+        num_workers: 1,
+        mining_address: wallet_address.clone(),
+        template_refresh_interval_ms: 50,
+        max_iterations: 1_000_000,
+    });
+    let template_addr = rpc_addr.to_string();
+    let pay_address = wallet_address.clone();
+    let submit_addr = rpc_addr.to_string();
+    miner.start_mining(
+        move || rpc_miner::fetch_template(&template_addr, &pay_address),
+        move |block| rpc_miner::submit_block(&submit_addr, &block),
+    );
+    wait_for("a mature coinbase in the wallet", 60, || async move {
+        let balance = call(wallet, "get_balance", json!({})).await.ok()?;
+        (balance["available"].as_u64()? > AMOUNT).then_some(())
+    })
+    .await;
+    let utxos = call(wallet, "list_utxos", json!({})).await.unwrap();
+    assert!(utxos.as_array().unwrap().iter().any(|utxo| utxo["spendable"] == true));
+
+    // Pay an outside address
+    let (_, recipient_public) = Keys::new().generate_address().unwrap();
+    let recipient = Address::from_public_key(&recipient_public);
+    let error = call(wallet, "send", json!({ "address": recipient, "amount": 100 })).await.unwrap_err();
+    assert!(error["message"].as_str().unwrap().contains("dust threshold"), "{}", error);
+    let transaction_id = call(wallet, "send", json!({ "address": recipient, "amount": AMOUNT, "feerate": 1 }))
+        .await
+        .unwrap();
+    // Nobody else pays the wallet; the change of the send is tracked on its own
+    let balance = call(wallet, "get_balance", json!({})).await.unwrap();
+    assert_eq!(balance["pending_incoming"], 0);
+    assert!(balance["pending_outgoing_change"].is_u64());
+
+    let entry = wait_for("the payment to confirm", 60, || {
+        let transaction_id = transaction_id.clone();
+        async move {
+            let history = call(wallet, "list_history", json!({})).await.ok()?;
+            history.as_array()?.iter().find(|entry| entry["transaction_id"] == transaction_id && entry["confirmed"] == true).cloned()
+        }
+    })
+    .await;
+    tokio::task::block_in_place(|| miner.shutdown());
+    assert_eq!(entry["direction"], "send");
+    assert_eq!(entry["amount"], AMOUNT);
+    assert_eq!(entry["address"], recipient.as_str());
+
+    // The node agrees that the recipient was paid
+    let client = wallet::node_client::NodeClient::new(&rpc_addr.to_string());
+    assert_eq!(client.get_balance_by_address(&recipient).await.unwrap().balance, AMOUNT);
+
+    // Locking drops the keys but keeps serving the synced state
+    call(wallet, "lock", json!({})).await.unwrap();
+    assert!(call(wallet, "send", json!({ "address": recipient, "amount": AMOUNT })).await.is_err());
+    call(wallet, "get_balance", json!({})).await.unwrap();
+
+    server_handle.abort();
+    sync_handle.abort();
+    shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), node).await.unwrap().unwrap().unwrap();
+}
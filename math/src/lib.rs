@@ -5,7 +5,7 @@ use std::fmt;
 
 /// Simple 192-bit unsigned integer implemented as 3 little-endian u64 limbs.
 /// Provides the small API used by the consensus core (From<u64>, AddAssign, Add, to_bytes).
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct Uint192([u64; 3]);
 
 /// Empty MuHash constant representing zero in MuHash context
@@ -26,6 +26,25 @@ impl Uint192 {
         out[16..24].copy_from_slice(&self.0[2].to_le_bytes());
         out
     }
+
+    /// Builds a value from little-endian bytes, the inverse of `to_bytes`
+    pub fn from_le_bytes(bytes: [u8; 24]) -> Self {
+        let limb = |i: usize| u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+        Self([limb(0), limb(1), limb(2)])
+    }
+}
+
+impl Ord for Uint192 {
+    /// Numeric order: the most significant limb decides first
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for Uint192 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl AddAssign for Uint192 {
@@ -72,6 +91,14 @@ mod tests {
         assert_eq!(a.to_bytes()[0..8], 3u64.to_le_bytes());
     }
 
+    #[test]
+    fn ordering_is_numeric_across_limbs() {
+        let mut above_u64 = Uint192::from(u64::MAX);
+        above_u64 += Uint192::from(1u64);
+        assert!(above_u64 > Uint192::from(u64::MAX));
+        assert!(Uint192::from(2u64) > Uint192::from(1u64));
+    }
+
     #[test]
     fn to_bytes_length() {
         let a = Uint192::from(0x11223344u64);
@@ -79,4 +106,11 @@ mod tests {
         assert_eq!(bytes.len(), 24);
         assert_eq!(&bytes[0..8], &0x11223344u64.to_le_bytes());
     }
+
+    #[test]
+    fn from_le_bytes_roundtrip() {
+        let mut a = Uint192::from(u64::MAX);
+        a += Uint192::from(5u64);
+        assert_eq!(Uint192::from_le_bytes(a.to_bytes()), a);
+    }
 }
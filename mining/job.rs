@@ -7,6 +7,7 @@ use crate::pow::Target;
 use rpc_core::model::BlockTemplate;
 use consensus_core::tx::Transaction;
 use consensus_core::Hash;
+use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Represents a mining job that workers process
@@ -126,7 +127,7 @@ impl MiningJob {
 }
 
 /// Mined block result that workers send back
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MinedBlock {
     /// The job that produced this block
     pub job_id: u64,
@@ -172,7 +173,7 @@ impl MinedBlock {
 }
 
 /// Gets the current timestamp in milliseconds since UNIX_EPOCH
-fn current_timestamp() -> u64 {
+pub(crate) fn current_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
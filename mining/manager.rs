@@ -1,17 +1,21 @@
 //! Mining manager that coordinates workers and job distribution
 //!
 //! This module manages the lifecycle of mining workers, distributes mining jobs,
-//! collects results, and coordinates difficulty adjustments.
+//! collects results, and coordinates difficulty adjustments. With a submitter
+//! configured, found blocks go through a [`SubmissionQueue`] that retries them
+//! until the node accepts them.
 
 use crate::difficulty::DifficultyManager;
-use crate::job::{MinedBlock, MiningJob};
+use crate::job::{current_timestamp, MinedBlock, MiningJob};
 use crate::pow::Target;
+use crate::submission::{BlockSubmitter, SubmissionQueue, SubmissionStatus};
 use crate::worker::{MinerWorker, WorkerStats};
 use consensus_core::block::Block;
 use consensus_core::Hash;
 use log;
 use rpc_core::model::BlockTemplate;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -36,6 +40,9 @@ impl Default for MiningConfig {
     }
 }
 
+/// Jobs kept so late solutions can still be matched to their template
+const RECENT_JOBS: usize = 8;
+
 /// Result of mining a block
 #[derive(Clone, Debug)]
 pub struct MiningResult {
@@ -45,6 +52,44 @@ pub struct MiningResult {
     pub worker_id: usize,
     pub time_ms: u64,
     pub hash_rate: f64,
+    /// Where the block's submission stands
+    pub submission: SubmissionStatus,
+}
+
+impl MiningResult {
+    fn new(mined_block: &MinedBlock, submission: SubmissionStatus) -> Self {
+        Self {
+            job_id: mined_block.job_id,
+            nonce: mined_block.nonce,
+            block_hash: mined_block.block_hash,
+            worker_id: mined_block.worker_id,
+            time_ms: mined_block.time_ms,
+            hash_rate: mined_block.hash_rate(),
+            submission,
+        }
+    }
+}
+
+/// Final outcomes of submitted blocks
+#[derive(Debug, Default)]
+struct SubmissionCounts {
+    accepted: AtomicU64,
+    stale: AtomicU64,
+    rejected: AtomicU64,
+    duplicate: AtomicU64,
+}
+
+impl SubmissionCounts {
+    fn record(&self, status: &SubmissionStatus) {
+        let counter = match status {
+            SubmissionStatus::Accepted => &self.accepted,
+            SubmissionStatus::Stale => &self.stale,
+            SubmissionStatus::Rejected(_) => &self.rejected,
+            SubmissionStatus::Duplicate => &self.duplicate,
+            SubmissionStatus::NotSubmitted | SubmissionStatus::Queued { .. } => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 /// Manages mining operations
@@ -52,6 +97,8 @@ pub struct MiningManager {
     config: MiningConfig,
     /// Currently active mining job
     current_job: Arc<Mutex<Option<MiningJob>>>,
+    /// Latest jobs, newest last
+    recent_jobs: Mutex<VecDeque<MiningJob>>,
     /// Channels to send jobs to workers
     job_senders: Vec<Sender<MiningJob>>,
     /// Channel to receive mined blocks (mutex-wrapped so the manager is Sync)
@@ -67,6 +114,11 @@ pub struct MiningManager {
     difficulty_manager: DifficultyManager,
     /// Start time of mining session
     session_start: Instant,
+    /// Hands found blocks to the node, if configured
+    submitter: Option<Arc<dyn BlockSubmitter>>,
+    /// Found blocks waiting to be accepted
+    submissions: Mutex<SubmissionQueue>,
+    submission_counts: SubmissionCounts,
 }
 
 impl MiningManager {
@@ -102,6 +154,7 @@ impl MiningManager {
         Self {
             config,
             current_job: Arc::new(Mutex::new(None)),
+            recent_jobs: Mutex::new(VecDeque::new()),
             job_senders,
             result_rx: Mutex::new(result_rx),
             result_tx,
@@ -110,6 +163,30 @@ impl MiningManager {
             worker_stats: Arc::new(Mutex::new(initial_stats)),
             difficulty_manager: DifficultyManager::new(),
             session_start: Instant::now(),
+            submitter: None,
+            submissions: Mutex::new(SubmissionQueue::new()),
+            submission_counts: SubmissionCounts::default(),
+        }
+    }
+
+    /// Submit found blocks through `submitter`, queuing them in `queue` until accepted.
+    /// Solutions left in a persisted queue by an earlier session are retried as well.
+    pub fn with_submitter(mut self, submitter: Arc<dyn BlockSubmitter>, queue: SubmissionQueue) -> Self {
+        self.submitter = Some(submitter);
+        self.submissions = Mutex::new(queue);
+        self
+    }
+
+    /// Target a template has to be mined to: its expanded `target`, else its
+    /// `bits`, else the local difficulty manager's current target
+    fn template_target(&self, template: &BlockTemplate) -> Target {
+        match Target::from_hex(&template.target) {
+            Ok(target) => target,
+            Err(_) if template.bits != 0 => Target::from_bits(template.bits),
+            Err(_) => self
+                .difficulty_manager
+                .get_current_target()
+                .unwrap_or_else(|| Target::from_bits(0x207fffff)),
         }
     }
 
@@ -123,10 +200,7 @@ impl MiningManager {
 
     /// Updates the mining job for all workers
     pub fn update_job(&self, template: BlockTemplate) {
-        let target = self
-            .difficulty_manager
-            .get_current_target()
-            .unwrap_or_else(|| Target::from_bits(0x207fffff));
+        let target = self.template_target(&template);
 
         let job = MiningJob::new(template, target);
         let job_id = job.job_id;
@@ -135,6 +209,13 @@ impl MiningManager {
 
         // Store as current job
         *self.current_job.lock().unwrap() = Some(job.clone());
+        {
+            let mut recent_jobs = self.recent_jobs.lock().unwrap();
+            recent_jobs.push_back(job.clone());
+            if recent_jobs.len() > RECENT_JOBS {
+                recent_jobs.pop_front();
+            }
+        }
 
         // Distribute job to all workers
         for (i, sender) in self.job_senders.iter().enumerate() {
@@ -151,33 +232,55 @@ impl MiningManager {
     }
 
     /// Collects mining results (non-blocking)
+    ///
+    /// With a submitter configured, newly found blocks are queued and every due
+    /// submission is attempted. A block is reported when it is found and again
+    /// whenever a later retry changes its submission status.
     pub fn collect_results(&self) -> Vec<MiningResult> {
+        let mut found = Vec::new();
+        while let Ok(mined_block) = self.result_rx.lock().unwrap().try_recv() {
+            // Update worker stats
+            if let Ok(mut stats) = self.worker_stats.lock() {
+                if mined_block.worker_id < stats.len() {
+                    stats[mined_block.worker_id].update(&mined_block);
+                }
+            }
+            found.push(mined_block);
+        }
+
+        let Some(submitter) = &self.submitter else {
+            return found.iter().map(|mined_block| MiningResult::new(mined_block, SubmissionStatus::NotSubmitted)).collect();
+        };
+
+        let now_ms = current_timestamp();
+        let mut queue = self.submissions.lock().unwrap_or_else(|e| e.into_inner());
         let mut results = Vec::new();
-        loop {
-            match self.result_rx.lock().unwrap().try_recv() {
-                Ok(mined_block) => {
-                    let result = MiningResult {
-                        job_id: mined_block.job_id,
-                        nonce: mined_block.nonce,
-                        block_hash: mined_block.block_hash,
-                        worker_id: mined_block.worker_id,
-                        time_ms: mined_block.time_ms,
-                        hash_rate: mined_block.hash_rate(),
-                    };
-
-                    // Update worker stats
-                    if let Ok(mut stats) = self.worker_stats.lock() {
-                        if mined_block.worker_id < stats.len() {
-                            stats[mined_block.worker_id].update(&mined_block);
-                        }
+        for mined_block in found {
+            let template = self
+                .recent_jobs
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .find(|job| job.job_id == mined_block.job_id)
+                .map(|job| job.template.clone());
+            let status = match template {
+                // Superseded by many jobs since, so its parents are long gone
+                None => SubmissionStatus::Stale,
+                Some(template) => {
+                    if queue.push(mined_block.clone(), template, now_ms) {
+                        continue;
                     }
-
-                    results.push(result);
+                    SubmissionStatus::Duplicate
                 }
-                Err(_) => break,
-            }
+            };
+            self.submission_counts.record(&status);
+            results.push(MiningResult::new(&mined_block, status));
         }
 
+        for (mined_block, status) in queue.process(submitter.as_ref(), now_ms) {
+            self.submission_counts.record(&status);
+            results.push(MiningResult::new(&mined_block, status));
+        }
         results
     }
 
@@ -210,6 +313,11 @@ impl MiningManager {
             total_iterations,
             total_time_ms,
             overall_hash_rate,
+            accepted_blocks: self.submission_counts.accepted.load(Ordering::Relaxed),
+            stale_blocks: self.submission_counts.stale.load(Ordering::Relaxed),
+            rejected_blocks: self.submission_counts.rejected.load(Ordering::Relaxed),
+            duplicate_blocks: self.submission_counts.duplicate.load(Ordering::Relaxed),
+            pending_blocks: self.submissions.lock().map_or(0, |queue| queue.len() as u64),
             worker_count: self.config.num_workers,
             worker_stats: stats,
         }
@@ -258,6 +366,16 @@ pub struct SessionStats {
     pub total_iterations: u64,
     pub total_time_ms: u64,
     pub overall_hash_rate: f64,
+    /// Found blocks the node accepted
+    pub accepted_blocks: u64,
+    /// Found blocks dropped because their template's parents were no longer tips
+    pub stale_blocks: u64,
+    /// Found blocks the node refused
+    pub rejected_blocks: u64,
+    /// Solutions found again after being handled once
+    pub duplicate_blocks: u64,
+    /// Found blocks waiting for a retry
+    pub pending_blocks: u64,
     pub worker_count: usize,
     pub worker_stats: Vec<WorkerStats>,
 }
@@ -266,10 +384,14 @@ impl SessionStats {
     /// Formats session stats as a readable string
     pub fn format_summary(&self) -> String {
         format!(
-            "Mining Session Stats:\n  Duration: {}ms\n  Total Blocks: {}\n  Total Iterations: {}\n  \
-             Workers: {}\n  Overall Hash Rate: {:.2} MH/s",
+            "Mining Session Stats:\n  Duration: {}ms\n  Total Blocks: {}\n  Accepted: {}\n  Stale: {}\n  \
+             Rejected: {}\n  Pending: {}\n  Total Iterations: {}\n  Workers: {}\n  Overall Hash Rate: {:.2} MH/s",
             self.session_duration_ms,
             self.total_blocks,
+            self.accepted_blocks,
+            self.stale_blocks,
+            self.rejected_blocks,
+            self.pending_blocks,
             self.total_iterations,
             self.worker_count,
             self.overall_hash_rate / 1_000_000.0
@@ -306,10 +428,16 @@ mod tests {
             total_iterations: 10_000_000,
             total_time_ms: 5000,
             overall_hash_rate: 2_000_000.0,
+            accepted_blocks: 8,
+            stale_blocks: 1,
+            rejected_blocks: 0,
+            duplicate_blocks: 0,
+            pending_blocks: 1,
             worker_count: 4,
             worker_stats: vec![],
         };
         let summary = stats.format_summary();
         assert!(summary.contains("Mining Session Stats"));
+        assert!(summary.contains("Stale: 1"));
     }
 }
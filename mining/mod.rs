@@ -11,12 +11,14 @@
 //! - [`job`]: Mining job definitions and mined block results
 //! - [`worker`]: Multithreaded worker implementation for mining operations
 //! - [`manager`]: Coordinates multiple workers and manages mining sessions
+//! - [`submission`]: Persistent queue retrying found blocks until the node accepts them
 //! - [`difficulty`]: Difficulty adjustment algorithm (DAA) similar to Kaspa
 
 pub mod pow;
 pub mod job;
 pub mod worker;
 pub mod manager;
+pub mod submission;
 pub mod difficulty;
 pub mod rpc_miner;
 
@@ -28,8 +30,9 @@ pub use pow::{ProofOfWork, Target};
 pub use job::{MiningJob, MinedBlock};
 pub use worker::{MinerWorker, WorkerStats};
 pub use manager::{MiningManager, MiningConfig, MiningResult, SessionStats};
+pub use submission::{BlockSubmitter, SubmissionQueue, SubmissionStatus, SubmitError};
 pub use difficulty::{DifficultyManager, DifficultyConfig};
-pub use rpc_miner::{RpcMiner, RpcMinerConfig, MiningStats};
+pub use rpc_miner::{RpcMiner, RpcMinerConfig, MiningStats, Payout};
 
 /// Prelude module for convenient imports
 pub mod prelude {
@@ -37,6 +40,7 @@ pub mod prelude {
     pub use crate::job::{MiningJob, MinedBlock};
     pub use crate::worker::{MinerWorker, WorkerStats};
     pub use crate::manager::{MiningManager, MiningConfig, MiningResult, SessionStats};
+    pub use crate::submission::{BlockSubmitter, SubmissionQueue, SubmissionStatus, SubmitError};
     pub use crate::difficulty::{DifficultyManager, DifficultyConfig};
-    pub use crate::rpc_miner::{RpcMiner, RpcMinerConfig, MiningStats};
+    pub use crate::rpc_miner::{RpcMiner, RpcMinerConfig, MiningStats, Payout};
 }
@@ -5,6 +5,7 @@
 //! Kaspa's PoW system.
 
 use consensus_core::Hash;
+use consensus_pow::target;
 use crypto_hashes::double_sha256;
 use primitive_types::U256;
 use std::cmp::Ordering;
@@ -19,43 +20,39 @@ impl Target {
         Target(value)
     }
 
-    /// Creates a Target from compact bits representation (Kaspa format)
-    /// Format: [3 bytes mantissa][1 byte exponent]
+    /// Creates a Target from compact bits: `[1 byte exponent][3 bytes mantissa]`
     pub fn from_bits(bits: u32) -> Self {
-        let size = (bits >> 24) as usize;
-        let word = bits & 0x007fffff;
-
-        let value = if size <= 3 {
-            U256::from(word >> (8 * (3 - size)))
-        } else {
-            U256::from(word) << (8 * (size - 3))
-        };
-
-        Target(value)
+        Target(target::target_from_bits(bits))
     }
 
     /// Converts Target to compact bits representation
     pub fn to_bits(&self) -> u32 {
-        let mut bytes = [0u8; 32];
-        self.0.to_big_endian(&mut bytes);
-
-        // Find first non-zero byte
-        let mut size = 32;
-        for (i, &byte) in bytes.iter().enumerate() {
-            if byte != 0 {
-                size = 32 - i;
-                break;
-            }
-        }
+        target::target_to_bits(self.0)
+    }
 
-        if size <= 3 {
-            let word = u32::from_be_bytes([bytes[29], bytes[30], bytes[31], 0]) >> (8 * (3 - size));
-            (3 << 24) | word
-        } else {
-            let offset = 32 - size;
-            let word = u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], 0]);
-            ((size as u32) << 24) | word
-        }
+    /// Parses a target given as 64 big-endian hex characters, as in `BlockTemplate::target`
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        target::target_from_hex(hex).map(Target)
+    }
+
+    /// The target as 64 big-endian hex characters
+    pub fn to_hex(&self) -> String {
+        target::target_to_hex(self.0)
+    }
+
+    /// Creates the Target with the given difficulty, relative to the easiest target
+    pub fn from_difficulty(difficulty: f64) -> Self {
+        Target(target::difficulty_to_target(difficulty))
+    }
+
+    /// Difficulty of this target, relative to the easiest target
+    pub fn difficulty(&self) -> f64 {
+        target::target_to_difficulty(self.0)
+    }
+
+    /// Whether `hash`, read as a big-endian 256-bit number, is at most this target
+    pub fn meets(&self, hash: &Hash) -> bool {
+        U256::from_big_endian(&hash.as_bytes()) <= self.0
     }
 
     /// Returns the inner U256 value
@@ -107,9 +104,7 @@ impl ProofOfWork {
     /// # Returns
     /// true if the hash is less than or equal to the target
     pub fn is_valid_pow(header_bytes: &[u8], target: &Target) -> bool {
-        let hash = Self::compute_hash(header_bytes);
-        let hash_u256 = U256::from_big_endian(hash.as_bytes());
-        hash_u256 <= target.0
+        target.meets(&Self::compute_hash(header_bytes))
     }
 
     /// Calculates the hash rate (hashes per second)
@@ -129,7 +124,7 @@ impl ProofOfWork {
 
     /// Converts a hash to U256 for comparison
     fn hash_to_u256(hash: &Hash) -> U256 {
-        U256::from_big_endian(hash.as_bytes())
+        U256::from_big_endian(&hash.as_bytes())
     }
 
     /// Compares hash against target and returns ordering
@@ -149,8 +144,8 @@ mod tests {
         let bits = 0x207fffff;
         let target = Target::from_bits(bits);
         let recovered = target.to_bits();
-        // Note: exact recovery may not be perfect due to precision loss
-        assert!(recovered > 0);
+        assert_eq!(recovered, bits);
+        assert_eq!(target.as_u256(), U256::from(0x7fffff) << 232);
     }
 
     #[test]
@@ -184,4 +179,45 @@ mod tests {
         let target2 = Target::from_bits(0x1fffffff);
         assert!(target2 < target1); // Lower bits value = harder target
     }
+
+    fn hash_of(value: U256) -> Hash {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        Hash::from_bytes(bytes)
+    }
+
+    #[test]
+    fn test_hash_equal_to_target_meets_it() {
+        let target = Target::from_bits(0x1f00ffff);
+        assert!(target.meets(&hash_of(target.as_u256())));
+        assert_eq!(ProofOfWork::compare_hash_to_target(&hash_of(target.as_u256()), &target), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_hash_one_above_target_fails() {
+        let target = Target::from_bits(0x1f00ffff);
+        let hash = hash_of(target.as_u256() + U256::one());
+        assert!(!target.meets(&hash));
+        assert_eq!(ProofOfWork::compare_hash_to_target(&hash, &target), Ordering::Greater);
+        assert!(target.meets(&hash_of(target.as_u256() - U256::one())));
+    }
+
+    #[test]
+    fn test_low_bytes_are_compared() {
+        // Hashes differing only in their last byte must not compare equal
+        let target = Target::new(U256::from(0x1234));
+        assert!(target.meets(&hash_of(U256::from(0x1234))));
+        assert!(!target.meets(&hash_of(U256::from(0x1235))));
+    }
+
+    #[test]
+    fn test_target_hex_and_difficulty() {
+        let target = Target::from_bits(0x1f00ffff);
+        assert_eq!(Target::from_hex(&target.to_hex()).unwrap(), target);
+        assert!(Target::from_hex("1f00ffff").is_err());
+        assert_eq!(Target::from_bits(0x207fffff).difficulty(), 1.0);
+        let harder = Target::from_difficulty(256.0);
+        assert_eq!(harder.as_u256(), Target::from_bits(0x207fffff).as_u256() >> 8);
+        assert_eq!(harder.difficulty(), 256.0);
+    }
 }
@@ -1,4 +1,5 @@
-use std::sync::{Arc, Mutex};
+use std::net::TcpStream;
+use std::sync::{mpsc, Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -6,9 +7,15 @@ use log::{info, warn};
 
 use consensus_core::{
     block::Block,
+    header::Header,
+    merkle::MerkleTree,
     Hash,
 };
 use consensus::process::mining::BlockTemplate;
+use crate::pow::Target;
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{connect, Message, WebSocket};
 
 // Use consensus header PoW validation so miner and node agree on PoW algorithm
 use consensus_core::hashing::header as header_hashing;
@@ -26,28 +33,259 @@ pub struct RpcMinerConfig {
     pub max_iterations: u64,
 }
 
+/// Convert a node's block template into the header and transactions to mine
+pub fn template_from_rpc(rpc_tmpl: rpc_core::model::BlockTemplate) -> Result<BlockTemplate, String> {
+    // The header only commits to `bits`; refuse a template whose expanded target disagrees
+    let target = Target::from_hex(&rpc_tmpl.target)?;
+    if target != Target::from_bits(rpc_tmpl.bits) {
+        return Err(format!("Template target {} does not match bits {:08x}", rpc_tmpl.target, rpc_tmpl.bits));
+    }
+
+    // Compute merkle root using consensus merkle routine from transaction hashes
+    let tx_hashes: Vec<Hash> = rpc_tmpl.transactions.iter().map(|tx| tx.hash()).collect();
+    let merkle_root = if tx_hashes.is_empty() {
+        Default::default()
+    } else {
+        MerkleTree::from_hashes(tx_hashes).root()
+    };
+
+    let header = Header::new_finalized(
+        rpc_tmpl.version as u16,
+        vec![rpc_tmpl.parent_hashes],
+        merkle_root,
+        Default::default(),
+        Default::default(),
+        rpc_tmpl.timestamp,
+        rpc_tmpl.bits,
+        0,
+        rpc_tmpl.daa_score,
+        rpc_tmpl.blue_work,
+        rpc_tmpl.blue_score,
+        Default::default(),
+    );
+
+    Ok(BlockTemplate {
+        header,
+        transactions: rpc_tmpl.transactions,
+        coinbase_reward: rpc_tmpl.coinbase_value,
+    })
+}
+
+/// Hashes a worker tries between checks for a replaced template
+const TEMPLATE_CHECK_INTERVAL: u64 = 1024;
+
+/// Wait before reconnecting to a node that went away
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a notification read blocks before the shutdown flag is checked
+const NOTIFICATION_READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Open a WebSocket connection to `rpc_addr` (host:port)
+fn connect_rpc(rpc_addr: &str) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
+    let url = url::Url::parse(&format!("ws://{}", rpc_addr)).map_err(|e| e.to_string())?;
+    let (socket, _response) = connect(url).map_err(|e| format!("WS connect error: {}", e))?;
+    Ok(socket)
+}
+
+/// Send one JSON-RPC request over `socket` and wait for its result
+fn request(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>, method: &str, params: Value) -> Result<Value, String> {
+    let req = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    socket.write_message(Message::Text(req.to_string())).map_err(|e| e.to_string())?;
+
+    loop {
+        let msg = socket.read_message().map_err(|e| e.to_string())?;
+        if let Message::Text(txt) = msg {
+            let v: Value = serde_json::from_str(&txt).map_err(|e| e.to_string())?;
+            if let Some(error) = v.get("error") {
+                return Err(format!("{} failed: {}", method, error));
+            }
+            if let Some(result) = v.get("result") {
+                return Ok(result.clone());
+            }
+        }
+    }
+}
+
+/// Send one JSON-RPC request over a fresh WebSocket connection to `rpc_addr` (host:port)
+fn call_rpc(rpc_addr: &str, method: &str, params: Value) -> Result<Value, String> {
+    request(&mut connect_rpc(rpc_addr)?, method, params)
+}
+
+/// Subscribe to the newBlockTemplate notifications of the node at `rpc_addr`
+/// and signal `templates` for each of them, and once after every connection
+/// since templates may have changed while disconnected. Reconnects until
+/// `shutdown` is set; gives up if the node has no such subscription.
+fn listen_for_templates(rpc_addr: &str, templates: mpsc::Sender<()>, shutdown: Arc<AtomicBool>) {
+    while !shutdown.load(Ordering::Relaxed) {
+        let mut socket = match connect_rpc(rpc_addr) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Failed to connect for template notifications: {}", e);
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        };
+        match request(&mut socket, "notifyNewBlockTemplate", json!({})) {
+            Ok(_) => info!("Subscribed to new block templates at {}", rpc_addr),
+            Err(e) if e.starts_with("notifyNewBlockTemplate failed") => {
+                warn!("Node does not notify new block templates ({}); polling for them instead", e);
+                return;
+            }
+            Err(e) => {
+                warn!("Lost connection to {}: {}", rpc_addr, e);
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        }
+        if templates.send(()).is_err() {
+            return;
+        }
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            let _ = stream.set_read_timeout(Some(NOTIFICATION_READ_TIMEOUT));
+        }
+
+        while !shutdown.load(Ordering::Relaxed) {
+            match socket.read_message() {
+                Ok(Message::Text(text)) => {
+                    let is_template = serde_json::from_str::<Value>(&text)
+                        .map_or(false, |v| v.get("method").and_then(Value::as_str) == Some("newBlockTemplate"));
+                    if is_template && templates.send(()).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    warn!("Lost template notifications from {}: {}; reconnecting", rpc_addr, e);
+                    thread::sleep(RECONNECT_DELAY);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Fetch a block template paying `pay_address` from the node at `rpc_addr`
+pub fn fetch_template(rpc_addr: &str, pay_address: &str) -> Result<BlockTemplate, String> {
+    let result = call_rpc(rpc_addr, "getBlockTemplate", json!({ "payAddress": pay_address }))?;
+    let rpc_tmpl: rpc_core::model::BlockTemplate = serde_json::from_value(result).map_err(|e| e.to_string())?;
+    template_from_rpc(rpc_tmpl)
+}
+
+/// Picks the address each template pays: `devfee_percent` percent of the
+/// templates, spread evenly, pay the dev fee address and the rest the miner's
+pub struct Payout {
+    address: String,
+    devfee_address: Option<String>,
+    devfee_percent: u64,
+    templates: AtomicU64,
+}
+
+impl Payout {
+    pub fn new(address: String, devfee_address: Option<String>, devfee_percent: u64) -> Result<Self, String> {
+        if devfee_percent > 100 {
+            return Err(format!("Dev fee is {} percent, at most 100 is allowed", devfee_percent));
+        }
+        if devfee_percent > 0 && devfee_address.is_none() {
+            return Err("A dev fee needs an address to pay it to".to_string());
+        }
+        Ok(Self { address, devfee_address, devfee_percent, templates: AtomicU64::new(0) })
+    }
+
+    /// Address the next template pays
+    pub fn next_address(&self) -> &str {
+        let n = self.templates.fetch_add(1, Ordering::Relaxed);
+        let devfee_due = (n + 1) * self.devfee_percent / 100 > n * self.devfee_percent / 100;
+        match &self.devfee_address {
+            Some(devfee_address) if devfee_due => devfee_address,
+            _ => &self.address,
+        }
+    }
+}
+
+/// Submit a solved block to the node at `rpc_addr`, returning its hash
+pub fn submit_block(rpc_addr: &str, block: &Block) -> Result<String, String> {
+    // Send the canonical encoding as hex via submitBlockHex
+    let bytes = consensus_core::encoding::encode_block(block);
+    let result = call_rpc(rpc_addr, "submitBlockHex", json!({ "blockHex": hex::encode(bytes) }))?;
+    result.as_str().map(str::to_string).ok_or_else(|| "Invalid hash result".to_string())
+}
+
 /// Mining statistics
 #[derive(Clone, Debug)]
 pub struct MiningStats {
+    /// Blocks the node accepted
     pub blocks_mined: u64,
+    /// Solutions found on a template that had been replaced, and not submitted
+    pub blocks_stale: u64,
+    /// Solutions the node refused or that could not be submitted
+    pub blocks_rejected: u64,
     pub total_hashes: u64,
     pub hash_rate: f64, // H/s
     pub avg_time_per_block_ms: u64,
     pub uptime_ms: u64,
 }
 
+/// Counters shared by the workers
+#[derive(Default)]
+struct Counters {
+    accepted: AtomicU64,
+    stale: AtomicU64,
+    rejected: AtomicU64,
+    hashes: AtomicU64,
+}
+
+/// Template being mined, and a number bumped whenever it is replaced by one
+/// on other parents or with other transactions
+#[derive(Default)]
+struct CurrentTemplate {
+    template: Mutex<Option<BlockTemplate>>,
+    generation: AtomicU64,
+}
+
+impl CurrentTemplate {
+    /// Mine `template` from now on
+    fn replace(&self, template: BlockTemplate) {
+        let Ok(mut current) = self.template.lock() else {
+            return;
+        };
+        let switched = current.as_ref().map_or(true, |current| {
+            current.header.parents_by_level != template.header.parents_by_level
+                || current.header.hash_merkle_root != template.header.hash_merkle_root
+        });
+        *current = Some(template);
+        if switched {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// The template and its generation
+    fn get(&self) -> Option<(BlockTemplate, u64)> {
+        let template = self.template.lock().ok()?.clone()?;
+        Some((template, self.generation.load(Ordering::SeqCst)))
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
+}
+
 /// RPC-based miner that fetches templates and submits solved blocks
 pub struct RpcMiner {
     config: RpcMinerConfig,
-    current_template: Arc<Mutex<Option<BlockTemplate>>>,
-    stats_blocks_mined: Arc<AtomicU64>,
-    stats_total_hashes: Arc<AtomicU64>,
+    current_template: Arc<CurrentTemplate>,
+    counters: Arc<Counters>,
     stats_uptime_start: Instant,
     shutdown_flag: Arc<AtomicBool>,
     worker_threads: Vec<thread::JoinHandle<()>>,
     template_thread: Option<thread::JoinHandle<()>>,
+    listener_thread: Option<thread::JoinHandle<()>>,
+    /// Signals that a new template should be fetched right away
+    template_notifications: Option<mpsc::Receiver<()>>,
     start_time: Option<Instant>,
-    last_block_hash: Arc<Mutex<Option<String>>>,
+    /// Parents of the last solved template, which is not mined again
+    solved_parents: Arc<Mutex<Option<Vec<Vec<Hash>>>>>,
 }
 
 impl RpcMiner {
@@ -55,18 +293,30 @@ impl RpcMiner {
     pub fn new(config: RpcMinerConfig) -> Self {
         Self {
             config,
-            current_template: Arc::new(Mutex::new(None)),
-            stats_blocks_mined: Arc::new(AtomicU64::new(0)),
-            stats_total_hashes: Arc::new(AtomicU64::new(0)),
+            current_template: Arc::new(CurrentTemplate::default()),
+            counters: Arc::new(Counters::default()),
             stats_uptime_start: Instant::now(),
             shutdown_flag: Arc::new(AtomicBool::new(false)),
             worker_threads: Vec::new(),
             template_thread: None,
+            listener_thread: None,
+            template_notifications: None,
             start_time: None,
-            last_block_hash: Arc::new(Mutex::new(None)),
+            solved_parents: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Fetch a template whenever the node at `rpc_addr` notifies a new one,
+    /// besides every `template_refresh_interval_ms`; the interval alone
+    /// paces the miner while the node can't notify. Call before mining.
+    pub fn listen_for_templates(&mut self, rpc_addr: &str) {
+        let (sender, receiver) = mpsc::channel();
+        self.template_notifications = Some(receiver);
+        let rpc_addr = rpc_addr.to_string();
+        let shutdown = self.shutdown_flag.clone();
+        self.listener_thread = Some(thread::spawn(move || listen_for_templates(&rpc_addr, sender, shutdown)));
+    }
+
     /// Start mining with provided RPC closure functions
     pub fn start_mining<F, S>(&mut self, get_template: F, submit_block: S)
     where
@@ -75,14 +325,13 @@ impl RpcMiner {
     {
         self.start_time = Some(Instant::now());
         
-        let get_template = Arc::new(get_template);
         let submit_block = Arc::new(submit_block);
 
         // Start template fetcher thread
         let template = self.current_template.clone();
-        let get_tmpl = get_template.clone();
-        let interval = self.config.template_refresh_interval_ms;
+        let interval = Duration::from_millis(self.config.template_refresh_interval_ms);
         let shutdown = self.shutdown_flag.clone();
+        let mut notifications = self.template_notifications.take();
 
         let template_handle = thread::spawn(move || {
             loop {
@@ -90,43 +339,43 @@ impl RpcMiner {
                     break;
                 }
 
-                match get_tmpl() {
-                    Ok(tmpl) => {
-                        if let Ok(mut t) = template.lock() {
-                            *t = Some(tmpl);
-                        }
-                    }
+                match get_template() {
+                    Ok(tmpl) => template.replace(tmpl),
                     Err(e) => {
                         warn!("Failed to fetch template: {}", e);
                     }
                 }
 
-                thread::sleep(Duration::from_millis(interval));
+                // Wait for a notification, or poll once the interval is over
+                match notifications.as_ref().map(|notifications| notifications.recv_timeout(interval)) {
+                    Some(Ok(())) => while notifications.as_ref().is_some_and(|n| n.try_recv().is_ok()) {},
+                    Some(Err(mpsc::RecvTimeoutError::Timeout)) => {}
+                    Some(Err(mpsc::RecvTimeoutError::Disconnected)) => notifications = None,
+                    None => thread::sleep(interval),
+                }
             }
         });
 
         self.template_thread = Some(template_handle);
 
         // Start worker threads
-        let last_hash = self.last_block_hash.clone();
         for worker_id in 0..self.config.num_workers {
             let template = self.current_template.clone();
             let submit = submit_block.clone();
             let max_iter = self.config.max_iterations;
-            let stats_blocks = self.stats_blocks_mined.clone();
-            let stats_hashes = self.stats_total_hashes.clone();
+            let counters = self.counters.clone();
             let shutdown = self.shutdown_flag.clone();
+            let solved = self.solved_parents.clone();
 
-            let _last_hash = last_hash.clone();
             let worker = thread::spawn(move || {
                 Self::worker_loop(
                     worker_id,
                     template,
                     submit,
                     max_iter,
-                    stats_blocks,
-                    stats_hashes,
+                    counters,
                     shutdown,
+                    solved,
                 )
             });
 
@@ -139,32 +388,33 @@ impl RpcMiner {
     /// Worker thread mining loop
     fn worker_loop(
         worker_id: usize,
-        current_template: Arc<Mutex<Option<BlockTemplate>>>,
+        current_template: Arc<CurrentTemplate>,
         submit_block: Arc<impl Fn(Block) -> Result<String, String>>,
         max_iterations: u64,
-        blocks_mined: Arc<AtomicU64>,
-        total_hashes: Arc<AtomicU64>,
+        counters: Arc<Counters>,
         shutdown: Arc<AtomicBool>,
+        solved_parents: Arc<Mutex<Option<Vec<Vec<Hash>>>>>,
     ) {
         let mut nonce = worker_id as u64;
         let mut local_hash_count = 0u64;
 
         while !shutdown.load(Ordering::Relaxed) {
-            // Get current template
-            let template_opt = match current_template.lock() {
-                Ok(guard) => guard.clone(),
-                Err(_) => {
-                    thread::sleep(Duration::from_millis(100));
-                    continue;
-                }
-            };
+            // A block was already found on these parents; wait for the next template
+            let template_opt = current_template.get().filter(|(template, _)| {
+                solved_parents.lock().map_or(true, |solved| solved.as_ref() != Some(&template.header.parents_by_level))
+            });
+
+            if let Some((template, generation)) = template_opt {
+                let target = Target::from_bits(template.header.bits);
 
-            if let Some(template) = template_opt {
-                // Mine on this template
-                for _ in 0..max_iterations {
+                // Mine on this template until it is replaced
+                for iteration in 0..max_iterations {
                     if shutdown.load(Ordering::Relaxed) {
                         break;
                     }
+                    if iteration % TEMPLATE_CHECK_INTERVAL == 0 && !current_template.is_current(generation) {
+                        break;
+                    }
 
                     // Create header with nonce
                     let mut header = template.header.clone();
@@ -174,8 +424,16 @@ impl RpcMiner {
                     nonce = nonce.wrapping_add(1);
                     local_hash_count += 1;
 
-                    // Check PoW using consensus header hashing to ensure miner/validator parity
-                    if header_hashing::validate_pow(&header) {
+                    // Hash with consensus header hashing to ensure miner/validator parity,
+                    // then compare all 256 bits against the template target
+                    if target.meets(&header_hashing::calculate_pow_hash(&header)) {
+                        // A solution for a replaced template only builds on outdated tips
+                        if !current_template.is_current(generation) {
+                            info!("Worker {} found block {} on a replaced template; not submitting it", worker_id, header.hash);
+                            counters.stale.fetch_add(1, Ordering::Relaxed);
+                            break;
+                        }
+
                         // Found valid block!
                         let block = Block::new(header.clone(), template.transactions.clone());
                         
@@ -190,17 +448,22 @@ impl RpcMiner {
                         match submit_block(block) {
                             Ok(_hash_str) => {
                                 info!("Worker {} mined block and submitted", worker_id);
-                                blocks_mined.fetch_add(1, Ordering::Relaxed);
+                                counters.accepted.fetch_add(1, Ordering::Relaxed);
+                                if let Ok(mut solved) = solved_parents.lock() {
+                                    *solved = Some(header.parents_by_level.clone());
+                                }
+                                break;
                             }
                             Err(e) => {
                                 warn!("Worker {} failed to submit block: {}", worker_id, e);
+                                counters.rejected.fetch_add(1, Ordering::Relaxed);
                             }
                         }
                     }
 
                     // Update stats periodically
                     if local_hash_count % 10000 == 0 {
-                        total_hashes.fetch_add(local_hash_count, Ordering::Relaxed);
+                        counters.hashes.fetch_add(local_hash_count, Ordering::Relaxed);
                         local_hash_count = 0;
                     }
                 }
@@ -215,15 +478,15 @@ impl RpcMiner {
 
         // Final update of stats
         if local_hash_count > 0 {
-            total_hashes.fetch_add(local_hash_count, Ordering::Relaxed);
+            counters.hashes.fetch_add(local_hash_count, Ordering::Relaxed);
         }
     }
 
     /// Get current mining statistics
     pub fn get_stats(&self) -> MiningStats {
         let uptime_ms = self.stats_uptime_start.elapsed().as_millis() as u64;
-        let blocks_mined = self.stats_blocks_mined.load(Ordering::Relaxed);
-        let total_hashes = self.stats_total_hashes.load(Ordering::Relaxed);
+        let blocks_mined = self.counters.accepted.load(Ordering::Relaxed);
+        let total_hashes = self.counters.hashes.load(Ordering::Relaxed);
 
         let hash_rate = if uptime_ms > 0 {
             (total_hashes as f64) / (uptime_ms as f64 / 1000.0)
@@ -239,6 +502,8 @@ impl RpcMiner {
 
         MiningStats {
             blocks_mined,
+            blocks_stale: self.counters.stale.load(Ordering::Relaxed),
+            blocks_rejected: self.counters.rejected.load(Ordering::Relaxed),
             total_hashes,
             hash_rate,
             avg_time_per_block_ms,
@@ -251,10 +516,13 @@ impl RpcMiner {
         info!("Shutting down RPC miner...");
         self.shutdown_flag.store(true, Ordering::Relaxed);
 
-        // Wait for template thread
+        // Wait for the template and notification threads
         if let Some(handle) = self.template_thread.take() {
             let _ = handle.join();
         }
+        if let Some(handle) = self.listener_thread.take() {
+            let _ = handle.join();
+        }
 
         // Wait for all worker threads
         for handle in self.worker_threads.drain(..) {
@@ -262,8 +530,10 @@ impl RpcMiner {
         }
 
         let final_stats = self.get_stats();
-        info!("Miner shutdown - Blocks: {}, Total hashes: {}, Hash rate: {:.2} H/s, Uptime: {}ms",
+        info!("Miner shutdown - Blocks: {}, Stale: {}, Rejected: {}, Total hashes: {}, Hash rate: {:.2} H/s, Uptime: {}ms",
             final_stats.blocks_mined,
+            final_stats.blocks_stale,
+            final_stats.blocks_rejected,
             final_stats.total_hashes,
             final_stats.hash_rate,
             final_stats.uptime_ms
@@ -278,3 +548,23 @@ impl Drop for RpcMiner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_devfee_share_of_templates() {
+        let payout = Payout::new("miner".to_string(), Some("dev".to_string()), 25).unwrap();
+        let addresses: Vec<&str> = (0..8).map(|_| payout.next_address()).collect();
+        assert_eq!(addresses, ["miner", "miner", "miner", "dev", "miner", "miner", "miner", "dev"]);
+
+        let payout = Payout::new("miner".to_string(), None, 0).unwrap();
+        assert!((0..100).all(|_| payout.next_address() == "miner"));
+        let payout = Payout::new("miner".to_string(), Some("dev".to_string()), 100).unwrap();
+        assert!((0..10).all(|_| payout.next_address() == "dev"));
+
+        assert!(Payout::new("miner".to_string(), None, 1).is_err());
+        assert!(Payout::new("miner".to_string(), Some("dev".to_string()), 101).is_err());
+    }
+}
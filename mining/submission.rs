@@ -0,0 +1,368 @@
+//! Submission of found blocks
+//!
+//! A solution found by a worker is queued before it is submitted and stays
+//! queued until the node accepts it or its template goes stale, that is when
+//! one of the template's parents is no longer a DAG tip and the block could
+//! only be orphaned. Transient failures are retried with exponential backoff.
+//! The queue is written to disk after every change so solutions survive a
+//! restart, and the hashes of handled solutions are remembered so the same
+//! nonce on the same template is never submitted twice.
+
+use crate::job::MinedBlock;
+use consensus_core::Hash;
+use rpc_core::model::BlockTemplate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// Delay before the first retry, doubled after every failed attempt
+pub const RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// Longest delay between two attempts
+pub const RETRY_MAX_DELAY_MS: u64 = 30_000;
+
+/// Handled solutions remembered for deduplication
+pub const MAX_SEEN_SOLUTIONS: usize = 1_000;
+
+/// Why the node did not take a block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmitError {
+    /// The node was unreachable or busy; worth retrying
+    Transient(String),
+    /// The node refused the block; retrying will not help
+    Rejected(String),
+}
+
+/// Hands found blocks to the node
+pub trait BlockSubmitter: Send + Sync {
+    /// Submit the block built from `template` with the solution's nonce
+    fn submit_block(&self, template: &BlockTemplate, solution: &MinedBlock) -> Result<(), SubmitError>;
+
+    /// Current DAG tips
+    fn tips(&self) -> Result<Vec<Hash>, String>;
+}
+
+/// What became of a found block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmissionStatus {
+    /// No submitter is configured
+    NotSubmitted,
+    Accepted,
+    /// Waiting for a retry after `attempts` failed submissions
+    Queued { attempts: u32 },
+    /// The template's parents are no longer tips, so the block would be orphaned
+    Stale,
+    Rejected(String),
+    /// The same solution was already handled
+    Duplicate,
+}
+
+/// A found block waiting to be submitted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingSolution {
+    pub solution: MinedBlock,
+    pub template: BlockTemplate,
+    /// Failed submissions so far
+    pub attempts: u32,
+    /// Unix time in milliseconds of the next attempt
+    pub next_attempt_ms: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct QueueFile {
+    pending: Vec<PendingSolution>,
+    seen: Vec<Hash>,
+}
+
+/// Delay before the attempt following `attempts` failures
+pub fn retry_delay_ms(base_ms: u64, max_ms: u64, attempts: u32) -> u64 {
+    base_ms.saturating_mul(1u64 << attempts.saturating_sub(1).min(32)).min(max_ms)
+}
+
+/// Found blocks not yet accepted, optionally backed by a file
+pub struct SubmissionQueue {
+    path: Option<PathBuf>,
+    pending: Vec<PendingSolution>,
+    seen: HashSet<Hash>,
+    seen_order: VecDeque<Hash>,
+    retry_base_ms: u64,
+    retry_max_ms: u64,
+}
+
+impl SubmissionQueue {
+    /// Queue kept in memory only
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            pending: Vec::new(),
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            retry_base_ms: RETRY_BASE_DELAY_MS,
+            retry_max_ms: RETRY_MAX_DELAY_MS,
+        }
+    }
+
+    /// Queue persisted at `path`, starting from what it holds. A missing file is an empty queue.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = match std::fs::read(path) {
+            Ok(bytes) => bincode::deserialize::<QueueFile>(&bytes)
+                .map_err(|e| format!("Failed to read mining queue {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => QueueFile::default(),
+            Err(e) => return Err(format!("Failed to read mining queue {}: {}", path.display(), e)),
+        };
+        let mut queue = Self { path: Some(path.to_path_buf()), pending: file.pending, ..Self::new() };
+        for hash in file.seen {
+            queue.remember(hash);
+        }
+        Ok(queue)
+    }
+
+    /// Retry after `base_ms`, doubling up to `max_ms`, instead of the default delays
+    pub fn with_retry_delays(self, base_ms: u64, max_ms: u64) -> Self {
+        Self { retry_base_ms: base_ms, retry_max_ms: max_ms, ..self }
+    }
+
+    pub fn pending(&self) -> &[PendingSolution] {
+        &self.pending
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn remember(&mut self, hash: Hash) -> bool {
+        if !self.seen.insert(hash) {
+            return false;
+        }
+        self.seen_order.push_back(hash);
+        if self.seen_order.len() > MAX_SEEN_SOLUTIONS {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Queue `solution` found on `template` for submission at `now_ms`.
+    /// Returns false, queuing nothing, if the solution was seen before.
+    pub fn push(&mut self, solution: MinedBlock, template: BlockTemplate, now_ms: u64) -> bool {
+        if !self.remember(solution.block_hash) {
+            return false;
+        }
+        self.pending.push(PendingSolution { solution, template, attempts: 0, next_attempt_ms: now_ms });
+        self.save();
+        true
+    }
+
+    /// Submit every solution due at `now_ms`, returning the status of each one tried
+    pub fn process(&mut self, submitter: &dyn BlockSubmitter, now_ms: u64) -> Vec<(MinedBlock, SubmissionStatus)> {
+        if !self.pending.iter().any(|pending| pending.next_attempt_ms <= now_ms) {
+            return Vec::new();
+        }
+        // Without tips every solution is tried; a failing node fails the submission too
+        let tips: Option<HashSet<Hash>> = submitter.tips().ok().map(|tips| tips.into_iter().collect());
+
+        let mut outcomes = Vec::new();
+        let mut still_pending = Vec::with_capacity(self.pending.len());
+        for mut pending in std::mem::take(&mut self.pending) {
+            if pending.next_attempt_ms > now_ms {
+                still_pending.push(pending);
+                continue;
+            }
+            let stale = tips
+                .as_ref()
+                .is_some_and(|tips| !pending.template.parent_hashes.iter().all(|parent| tips.contains(parent)));
+            let status = if stale {
+                SubmissionStatus::Stale
+            } else {
+                match submitter.submit_block(&pending.template, &pending.solution) {
+                    Ok(()) => SubmissionStatus::Accepted,
+                    Err(SubmitError::Rejected(reason)) => SubmissionStatus::Rejected(reason),
+                    Err(SubmitError::Transient(reason)) => {
+                        pending.attempts += 1;
+                        let delay = retry_delay_ms(self.retry_base_ms, self.retry_max_ms, pending.attempts);
+                        pending.next_attempt_ms = now_ms + delay;
+                        log::warn!(
+                            "Submitting block {} failed (attempt {}), retrying in {}ms: {}",
+                            pending.solution.block_hash,
+                            pending.attempts,
+                            delay,
+                            reason
+                        );
+                        SubmissionStatus::Queued { attempts: pending.attempts }
+                    }
+                }
+            };
+            outcomes.push((pending.solution.clone(), status));
+            if matches!(outcomes.last(), Some((_, SubmissionStatus::Queued { .. }))) {
+                still_pending.push(pending);
+            }
+        }
+        self.pending = still_pending;
+        self.save();
+        outcomes
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else { return };
+        let file = QueueFile { pending: self.pending.clone(), seen: self.seen_order.iter().copied().collect() };
+        let result = bincode::serialize(&file).map_err(|e| e.to_string()).and_then(|bytes| {
+            // Write a sibling file and rename it so a crash never leaves a torn queue
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, path)).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to save mining queue {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl Default for SubmissionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Node that fails the first `failures` submissions
+    struct FlakyNode {
+        failures: u32,
+        tips: Vec<Hash>,
+        submitted: Mutex<Vec<Hash>>,
+        attempts: Mutex<u32>,
+    }
+
+    impl FlakyNode {
+        fn new(failures: u32, tips: Vec<Hash>) -> Self {
+            Self { failures, tips, submitted: Mutex::new(Vec::new()), attempts: Mutex::new(0) }
+        }
+    }
+
+    impl BlockSubmitter for FlakyNode {
+        fn submit_block(&self, _template: &BlockTemplate, solution: &MinedBlock) -> Result<(), SubmitError> {
+            let mut attempts = self.attempts.lock().unwrap();
+            *attempts += 1;
+            if *attempts <= self.failures {
+                return Err(SubmitError::Transient("processor busy".into()));
+            }
+            self.submitted.lock().unwrap().push(solution.block_hash);
+            Ok(())
+        }
+
+        fn tips(&self) -> Result<Vec<Hash>, String> {
+            Ok(self.tips.clone())
+        }
+    }
+
+    fn hash(n: u8) -> Hash {
+        Hash::from_bytes([n; 32])
+    }
+
+    fn template(parent: Hash) -> BlockTemplate {
+        BlockTemplate {
+            version: 1,
+            parent_hashes: vec![parent],
+            transactions: Vec::new(),
+            coinbase_value: 5_000_000_000,
+            bits: 0x207fffff,
+            timestamp: 1000,
+            pay_address: "test".to_string(),
+            target: "0".to_string(),
+        }
+    }
+
+    fn solution(n: u8) -> MinedBlock {
+        MinedBlock::new(1, 0, n as u64, hash(n), 1000, 10)
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_and_caps() {
+        assert_eq!(retry_delay_ms(500, 30_000, 1), 500);
+        assert_eq!(retry_delay_ms(500, 30_000, 2), 1000);
+        assert_eq!(retry_delay_ms(500, 30_000, 3), 2000);
+        assert_eq!(retry_delay_ms(500, 30_000, 10), 30_000);
+        assert_eq!(retry_delay_ms(500, 30_000, u32::MAX), 30_000);
+    }
+
+    #[test]
+    fn test_failing_submission_succeeds_on_third_retry() {
+        let node = FlakyNode::new(3, vec![hash(100)]);
+        let mut queue = SubmissionQueue::new();
+        assert!(queue.push(solution(1), template(hash(100)), 0));
+
+        let mut now = 0;
+        let mut statuses = Vec::new();
+        while !queue.is_empty() {
+            let outcomes = queue.process(&node, now);
+            assert_eq!(outcomes.len(), 1);
+            statuses.push(outcomes[0].1.clone());
+            // Nothing is due again before the backoff passes
+            assert!(queue.process(&node, now + 1).is_empty());
+            now += RETRY_MAX_DELAY_MS;
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                SubmissionStatus::Queued { attempts: 1 },
+                SubmissionStatus::Queued { attempts: 2 },
+                SubmissionStatus::Queued { attempts: 3 },
+                SubmissionStatus::Accepted,
+            ]
+        );
+        assert!(queue.is_empty());
+        assert_eq!(*node.submitted.lock().unwrap(), vec![hash(1)]);
+    }
+
+    #[test]
+    fn test_solution_is_never_submitted_twice() {
+        let node = FlakyNode::new(0, vec![hash(100)]);
+        let mut queue = SubmissionQueue::new();
+        assert!(queue.push(solution(1), template(hash(100)), 0));
+        assert!(!queue.push(solution(1), template(hash(100)), 0));
+        queue.process(&node, 0);
+        assert!(!queue.push(solution(1), template(hash(100)), 0));
+        assert!(queue.process(&node, 0).is_empty());
+        assert_eq!(node.submitted.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stale_template_is_dropped() {
+        let node = FlakyNode::new(3, vec![hash(100)]);
+        let mut queue = SubmissionQueue::new();
+        queue.push(solution(1), template(hash(100)), 0);
+        assert_eq!(queue.process(&node, 0)[0].1, SubmissionStatus::Queued { attempts: 1 });
+
+        // Another block built on the parent meanwhile
+        let node = FlakyNode { tips: vec![hash(101)], ..node };
+        assert_eq!(queue.process(&node, RETRY_MAX_DELAY_MS)[0].1, SubmissionStatus::Stale);
+        assert!(queue.is_empty());
+        assert!(node.submitted.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_queue_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mining_queue");
+        let node = FlakyNode::new(1, vec![hash(100)]);
+        {
+            let mut queue = SubmissionQueue::load(&path).unwrap();
+            queue.push(solution(1), template(hash(100)), 0);
+            queue.process(&node, 0);
+        }
+
+        let mut queue = SubmissionQueue::load(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pending()[0].attempts, 1);
+        assert!(!queue.push(solution(1), template(hash(100)), 0));
+        assert_eq!(queue.process(&node, RETRY_MAX_DELAY_MS)[0].1, SubmissionStatus::Accepted);
+        assert!(SubmissionQueue::load(&path).unwrap().is_empty());
+    }
+}
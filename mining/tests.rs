@@ -178,6 +178,66 @@ mod tests {
         assert_eq!(results.len(), 0);
     }
 
+    #[test]
+    fn test_manager_retries_failed_submission_until_accepted() {
+        use crate::job::MinedBlock;
+        use crate::submission::{BlockSubmitter, SubmissionQueue, SubmissionStatus, SubmitError};
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        /// Node that is busy for the first three submissions
+        struct BusyNode {
+            attempts: AtomicU32,
+        }
+
+        impl BlockSubmitter for BusyNode {
+            fn submit_block(&self, _template: &BlockTemplate, _solution: &MinedBlock) -> Result<(), SubmitError> {
+                if self.attempts.fetch_add(1, Ordering::SeqCst) < 3 {
+                    return Err(SubmitError::Transient("processor busy".into()));
+                }
+                Ok(())
+            }
+
+            fn tips(&self) -> Result<Vec<Hash>, String> {
+                Ok(vec![Hash::default()])
+            }
+        }
+
+        let config = MiningConfig {
+            num_workers: 1,
+            job_max_age_ms: 30_000,
+        };
+        let node = Arc::new(BusyNode { attempts: AtomicU32::new(0) });
+        let queue = SubmissionQueue::new().with_retry_delays(1, 1);
+        let manager = MiningManager::new(config).with_submitter(node.clone(), queue);
+        manager.update_job(create_test_template());
+
+        let mut statuses = Vec::new();
+        for _ in 0..500 {
+            statuses.extend(manager.collect_results().into_iter().map(|result| result.submission));
+            if statuses.contains(&SubmissionStatus::Accepted) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(
+            statuses,
+            vec![
+                SubmissionStatus::Queued { attempts: 1 },
+                SubmissionStatus::Queued { attempts: 2 },
+                SubmissionStatus::Queued { attempts: 3 },
+                SubmissionStatus::Accepted,
+            ]
+        );
+        assert_eq!(node.attempts.load(Ordering::SeqCst), 4);
+        let stats = manager.get_session_stats();
+        assert_eq!(stats.total_blocks, 1);
+        assert_eq!(stats.accepted_blocks, 1);
+        assert_eq!(stats.stale_blocks, 0);
+        assert_eq!(stats.pending_blocks, 0);
+    }
+
     // ==================== Difficulty Tests ====================
 
     #[test]
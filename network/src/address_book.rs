@@ -0,0 +1,207 @@
+//! Known peer addresses and dial backoff
+//!
+//! The address book remembers addresses learned from seeds, configuration and
+//! gossip. Every failed dial doubles the wait before the address is tried again,
+//! up to `MAX_BACKOFF`. Permanent addresses are never forgotten; other addresses
+//! are dropped after `MAX_FAILURES` consecutive failures.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Wait after the first failed dial
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between dials of one address
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Consecutive failures after which a non-permanent address is forgotten
+pub const MAX_FAILURES: u32 = 10;
+
+#[derive(Debug, Clone)]
+struct AddressEntry {
+    permanent: bool,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+/// Addresses we can dial
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: HashMap<SocketAddr, AddressEntry>,
+    /// Insertion order, so dials are spread in a stable order
+    order: Vec<SocketAddr>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `address`; re-adding an address can only upgrade it to permanent
+    pub fn add(&mut self, address: SocketAddr, permanent: bool) {
+        match self.entries.get_mut(&address) {
+            Some(entry) => entry.permanent |= permanent,
+            None => {
+                self.entries.insert(address, AddressEntry { permanent, failures: 0, retry_at: None });
+                self.order.push(address);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, address: &SocketAddr) {
+        if self.entries.remove(address).is_some() {
+            self.order.retain(|a| a != address);
+        }
+    }
+
+    pub fn contains(&self, address: &SocketAddr) -> bool {
+        self.entries.contains_key(address)
+    }
+
+    pub fn is_permanent(&self, address: &SocketAddr) -> bool {
+        self.entries.get(address).is_some_and(|entry| entry.permanent)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Permanent addresses, in insertion order
+    pub fn permanent(&self) -> Vec<SocketAddr> {
+        self.order.iter().copied().filter(|a| self.is_permanent(a)).collect()
+    }
+
+    /// Addresses not in `exclude` whose backoff has elapsed at `now`, permanent ones first
+    pub fn candidates(&self, now: Instant, exclude: &HashSet<SocketAddr>) -> Vec<SocketAddr> {
+        let mut due: Vec<SocketAddr> = self
+            .order
+            .iter()
+            .copied()
+            .filter(|a| !exclude.contains(a))
+            .filter(|a| !matches!(self.entries[a].retry_at, Some(at) if at > now))
+            .collect();
+        due.sort_by_key(|a| !self.entries[a].permanent);
+        due
+    }
+
+    /// Reset the backoff of `address` after a successful dial
+    pub fn mark_success(&mut self, address: &SocketAddr) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            entry.failures = 0;
+            entry.retry_at = None;
+        }
+    }
+
+    /// Back off `address` after a failed dial at `now`
+    pub fn mark_failure(&mut self, address: &SocketAddr, now: Instant) {
+        let Some(entry) = self.entries.get_mut(address) else { return };
+        entry.failures += 1;
+        if !entry.permanent && entry.failures >= MAX_FAILURES {
+            self.remove(address);
+            return;
+        }
+        entry.retry_at = Some(now + backoff(entry.failures));
+    }
+}
+
+/// Read permanent peer addresses saved by `save_permanent`; a missing file is empty
+pub fn load_permanent(path: &Path) -> Result<Vec<SocketAddr>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read peers file: {}", e))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|e| format!("Invalid peer address {:?}: {}", line, e)))
+        .collect()
+}
+
+/// Write `addresses`, one per line
+pub fn save_permanent(path: &Path, addresses: &[SocketAddr]) -> Result<(), String> {
+    let content: String = addresses.iter().map(|address| format!("{}\n", address)).collect();
+    fs::write(path, content).map_err(|e| format!("Failed to write peers file: {}", e))
+}
+
+/// Wait before the next dial after `failures` consecutive failures
+pub fn backoff(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    (INITIAL_BACKOFF * 2u32.pow(doublings)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+        assert_eq!(backoff(30), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_failed_address_waits_for_backoff() {
+        let mut book = AddressBook::new();
+        book.add(addr(1), false);
+        book.add(addr(2), false);
+        let now = Instant::now();
+        book.mark_failure(&addr(1), now);
+
+        assert_eq!(book.candidates(now, &HashSet::new()), vec![addr(2)]);
+        assert_eq!(book.candidates(now + Duration::from_secs(1), &HashSet::new()), vec![addr(1), addr(2)]);
+
+        book.mark_success(&addr(1));
+        assert_eq!(book.candidates(now, &HashSet::new()), vec![addr(1), addr(2)]);
+    }
+
+    #[test]
+    fn test_permanent_addresses_come_first_and_are_kept() {
+        let mut book = AddressBook::new();
+        book.add(addr(1), false);
+        book.add(addr(2), true);
+        assert_eq!(book.candidates(Instant::now(), &HashSet::new()), vec![addr(2), addr(1)]);
+
+        let now = Instant::now();
+        for _ in 0..MAX_FAILURES {
+            book.mark_failure(&addr(1), now);
+            book.mark_failure(&addr(2), now);
+        }
+        assert!(!book.contains(&addr(1)));
+        assert!(book.contains(&addr(2)));
+        assert_eq!(book.permanent(), vec![addr(2)]);
+    }
+
+    #[test]
+    fn test_permanent_addresses_round_trip() {
+        let path = std::env::temp_dir().join(format!("jio-peers-{}", std::process::id()));
+        assert!(load_permanent(&path).unwrap().is_empty());
+
+        let addresses = vec![addr(1), "[::1]:16111".parse().unwrap()];
+        save_permanent(&path, &addresses).unwrap();
+        assert_eq!(load_permanent(&path).unwrap(), addresses);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_excluded_addresses_are_skipped() {
+        let mut book = AddressBook::new();
+        book.add(addr(1), false);
+        book.add(addr(2), false);
+        let connected: HashSet<SocketAddr> = [addr(1)].into_iter().collect();
+        assert_eq!(book.candidates(Instant::now(), &connected), vec![addr(2)]);
+    }
+}
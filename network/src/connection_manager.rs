@@ -0,0 +1,419 @@
+//! Outbound connection management
+//!
+//! The hub's connection manager periodically dials addresses from its address
+//! book until `min_peers` outbound peers are connected, never exceeding
+//! `max_peers` connections overall. Permanent addresses are redialed whenever
+//! they are not connected. Dialing itself is behind the `Dialer` trait so the
+//! daemon can register the connections it opens and tests can count dials.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use crate::p2p::{negotiate, EncryptionConfig, Peer, PeerDirection, Role};
+use crate::protowire::VersionMessage;
+
+/// Messages queued for a peer before sends start failing
+pub const PEER_OUTBOX_CAPACITY: usize = 256;
+
+/// Peer count targets of the connection manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    /// Outbound peers to maintain
+    pub min_peers: usize,
+    /// Connections allowed in total, inbound included
+    pub max_peers: usize,
+    /// Time between connection maintenance rounds
+    pub interval: Duration,
+    /// Time allowed for one dial including the handshake
+    pub dial_timeout: Duration,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self { min_peers: 3, max_peers: 8, interval: Duration::from_secs(5), dial_timeout: Duration::from_secs(10) }
+    }
+}
+
+/// Why a peer could not be added or removed on request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerRequestError {
+    /// The address is not `host:port` or the host did not resolve
+    Unresolvable(String),
+    /// The address is one of our own listen addresses
+    SelfConnection(SocketAddr),
+    /// A peer at the address is already connected
+    AlreadyConnected(SocketAddr),
+    /// The address is neither known nor connected
+    NotFound(SocketAddr),
+    /// Dialing the address failed
+    Dial(String),
+    /// The permanent peer list could not be saved
+    Storage(String),
+}
+
+impl fmt::Display for PeerRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerRequestError::Unresolvable(e) => write!(f, "unresolvable address: {}", e),
+            PeerRequestError::SelfConnection(address) => write!(f, "{} is our own address", address),
+            PeerRequestError::AlreadyConnected(address) => write!(f, "already connected to {}", address),
+            PeerRequestError::NotFound(address) => write!(f, "{} is not a known peer", address),
+            PeerRequestError::Dial(e) => write!(f, "dial failed: {}", e),
+            PeerRequestError::Storage(e) => write!(f, "failed to save peers: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PeerRequestError {}
+
+/// Resolve a `host:port` peer address, looking the host up in DNS if needed
+pub async fn resolve_peer_address(address: &str) -> Result<SocketAddr, PeerRequestError> {
+    if let Ok(address) = address.parse() {
+        return Ok(address);
+    }
+    tokio::net::lookup_host(address)
+        .await
+        .map_err(|e| PeerRequestError::Unresolvable(format!("{}: {}", address, e)))?
+        .next()
+        .ok_or_else(|| PeerRequestError::Unresolvable(format!("{}: no addresses found", address)))
+}
+
+/// Opens outbound connections for the connection manager
+#[async_trait]
+pub trait Dialer: Send + Sync {
+    /// Connect and handshake with `address`, returning the outbound peer
+    async fn dial(&self, address: SocketAddr) -> Result<Arc<Peer>, String>;
+}
+
+/// Dials over TCP and forwards the peer's queued messages to the connection
+pub struct TcpDialer {
+    encryption: EncryptionConfig,
+    version: VersionMessage,
+}
+
+impl TcpDialer {
+    pub fn new(encryption: EncryptionConfig, version: VersionMessage) -> Self {
+        Self { encryption, version }
+    }
+}
+
+#[async_trait]
+impl Dialer for TcpDialer {
+    async fn dial(&self, address: SocketAddr) -> Result<Arc<Peer>, String> {
+        let stream = TcpStream::connect(address).await
+            .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        let (transport, remote) = negotiate(stream, Role::Initiator, &self.encryption, self.version.clone()).await
+            .map_err(|e| format!("Handshake with {} failed: {}", address, e))?;
+        let (tx, outbox) = mpsc::channel(PEER_OUTBOX_CAPACITY);
+        let peer = Arc::new(Peer::from_handshake(address.to_string(), address, PeerDirection::Outbound, remote, tx));
+        peer.spawn_writer(transport, outbox);
+        Ok(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hub::Hub;
+    use tokio::net::TcpListener;
+
+    fn plaintext() -> EncryptionConfig {
+        EncryptionConfig { enabled: false, allow_plaintext: true, identity: None }
+    }
+
+    /// Listener that completes the handshake and keeps every connection open
+    async fn spawn_listener() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let version = VersionMessage::new("listener", false);
+                    if let Ok((mut transport, _)) = negotiate(stream, Role::Responder, &plaintext(), version).await {
+                        while transport.read_frame().await.is_ok() {}
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    /// Address nothing listens on
+    async fn dead_address() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    /// TCP dialer that counts dials per address
+    struct CountingDialer {
+        inner: TcpDialer,
+        dials: parking_lot::Mutex<Vec<SocketAddr>>,
+    }
+
+    impl CountingDialer {
+        fn new() -> Self {
+            Self { inner: TcpDialer::new(plaintext(), VersionMessage::new("dialer", false)), dials: Default::default() }
+        }
+
+        fn dials_to(&self, address: SocketAddr) -> usize {
+            self.dials.lock().iter().filter(|a| **a == address).count()
+        }
+    }
+
+    #[async_trait]
+    impl Dialer for CountingDialer {
+        async fn dial(&self, address: SocketAddr) -> Result<Arc<Peer>, String> {
+            self.dials.lock().push(address);
+            self.inner.dial(address).await
+        }
+    }
+
+    fn config(min_peers: usize, max_peers: usize) -> ConnectionConfig {
+        ConnectionConfig { min_peers, max_peers, interval: Duration::from_millis(20), dial_timeout: Duration::from_secs(5) }
+    }
+
+    async fn outbound_count(hub: &Hub) -> usize {
+        hub.peers().await.iter().filter(|p| p.direction == PeerDirection::Outbound).count()
+    }
+
+    #[tokio::test]
+    async fn test_converges_to_min_peers() {
+        let hub = Hub::new();
+        for _ in 0..5 {
+            hub.add_address(spawn_listener().await, false);
+        }
+        let dialer = CountingDialer::new();
+
+        hub.maintain_connections(&config(3, 8), &dialer).await;
+        assert_eq!(outbound_count(&hub).await, 3);
+
+        // Already at the target, so nothing more is dialed
+        hub.maintain_connections(&config(3, 8), &dialer).await;
+        assert_eq!(outbound_count(&hub).await, 3);
+        assert_eq!(dialer.dials.lock().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_address_is_backed_off() {
+        let hub = Hub::new();
+        let dead = dead_address().await;
+        hub.add_address(dead, false);
+        for _ in 0..3 {
+            hub.add_address(spawn_listener().await, false);
+        }
+        let dialer = CountingDialer::new();
+
+        hub.maintain_connections(&config(4, 8), &dialer).await;
+        assert_eq!(outbound_count(&hub).await, 3);
+        assert_eq!(dialer.dials_to(dead), 1);
+
+        // Still short of the target, but the dead address waits out its backoff
+        hub.maintain_connections(&config(4, 8), &dialer).await;
+        assert_eq!(dialer.dials_to(dead), 1);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_peer_is_reconnected() {
+        let hub = Hub::new();
+        let permanent = spawn_listener().await;
+        hub.add_address(permanent, true);
+        let dialer = CountingDialer::new();
+
+        // Permanent peers are dialed even when no outbound peers are wanted
+        hub.maintain_connections(&config(0, 8), &dialer).await;
+        let peers = hub.peers().await;
+        assert_eq!(peers.len(), 1);
+
+        peers[0].mark_disconnected();
+        hub.maintain_connections(&config(0, 8), &dialer).await;
+        let peers = hub.peers().await;
+        assert_eq!(peers.len(), 1);
+        assert!(peers[0].is_connected());
+        assert_eq!(dialer.dials_to(permanent), 2);
+    }
+
+    #[tokio::test]
+    async fn test_background_manager_converges() {
+        let hub = Arc::new(Hub::new());
+        for _ in 0..4 {
+            hub.add_address(spawn_listener().await, false);
+        }
+        let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let handle = hub.spawn_connection_manager(config(2, 8), Arc::new(CountingDialer::new()), shutdown_rx);
+
+        let mut converged = false;
+        for _ in 0..100 {
+            if outbound_count(&hub).await == 2 {
+                converged = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(converged);
+
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requested_permanent_peer_survives_drop_and_restart() {
+        let hub = Arc::new(Hub::new());
+        let path = std::env::temp_dir().join(format!("jio-requested-peers-{}", std::process::id()));
+        hub.load_peers_file(&path).unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let dialer = Arc::new(CountingDialer::new());
+        let handle = hub.spawn_connection_manager(config(0, 8), dialer.clone(), shutdown_rx);
+
+        // Dialed immediately, not on the next maintenance round
+        let address = spawn_listener().await;
+        assert_eq!(hub.connect_peer(&address.to_string(), true).await, Ok(address));
+        let peers = hub.peers().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].address, address);
+
+        peers[0].mark_disconnected();
+        let mut reconnected = false;
+        for _ in 0..100 {
+            if dialer.dials_to(address) == 2 && hub.peers().await.iter().any(|p| p.is_connected()) {
+                reconnected = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(reconnected);
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap();
+
+        // A restarted node loads the saved peer, and removing it forgets it for good
+        let restarted = Hub::new();
+        assert_eq!(restarted.load_peers_file(&path).unwrap(), 1);
+        assert!(restarted.is_permanent(&address));
+        assert_eq!(restarted.remove_peer_address(&address.to_string()).await, Ok(address));
+        assert_eq!(crate::address_book::load_permanent(&path).unwrap(), Vec::<SocketAddr>::new());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_peer_request_errors() {
+        let hub = Arc::new(Hub::new());
+        hub.set_local_addresses(vec![SocketAddr::from(([0, 0, 0, 0], 16111))]);
+        let (_shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        hub.spawn_connection_manager(config(0, 8), Arc::new(CountingDialer::new()), shutdown_rx);
+
+        assert!(matches!(hub.connect_peer("missing-port", false).await, Err(PeerRequestError::Unresolvable(_))));
+        assert!(matches!(hub.connect_peer("127.0.0.1:16111", false).await, Err(PeerRequestError::SelfConnection(_))));
+
+        let address = spawn_listener().await;
+        hub.connect_peer(&address.to_string(), false).await.unwrap();
+        assert_eq!(hub.connect_peer(&address.to_string(), true).await, Err(PeerRequestError::AlreadyConnected(address)));
+        assert!(matches!(hub.connect_peer(&dead_address().await.to_string(), false).await, Err(PeerRequestError::Dial(_))));
+
+        assert_eq!(hub.remove_peer_address(&address.to_string()).await, Ok(address));
+        assert!(hub.peers().await.is_empty());
+        assert_eq!(hub.remove_peer_address(&address.to_string()).await, Err(PeerRequestError::NotFound(address)));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_peer_is_not_evicted_by_ban() {
+        let hub = Arc::new(Hub::with_ban_policy(crate::p2p::BanPolicy { threshold: 20, duration: Duration::from_secs(60) }));
+        let (_shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        hub.spawn_connection_manager(config(0, 8), Arc::new(CountingDialer::new()), shutdown_rx);
+        let address = spawn_listener().await;
+        hub.connect_peer(&address.to_string(), true).await.unwrap();
+
+        assert!(hub.report_misbehavior(address, crate::p2p::Misbehavior::BadChecksum).await);
+        assert!(hub.is_banned(address.ip()));
+        assert_eq!(hub.peers().await.len(), 1);
+    }
+
+    /// Node that accepts connections into its hub and dials with its own nonce
+    struct TestNode {
+        hub: Arc<Hub>,
+        address: SocketAddr,
+        _shutdown: tokio::sync::broadcast::Sender<()>,
+    }
+
+    async fn spawn_node() -> TestNode {
+        let hub = Arc::new(Hub::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let version = VersionMessage::new("node", false).with_address(Some(address)).with_nonce(hub.nonce());
+
+        let accepting = hub.clone();
+        let local = version.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, from)) = listener.accept().await {
+                let (hub, local) = (accepting.clone(), local.clone());
+                tokio::spawn(async move {
+                    let Ok((transport, remote)) = negotiate(stream, Role::Responder, &plaintext(), local).await else { return };
+                    let (tx, outbox) = mpsc::channel(PEER_OUTBOX_CAPACITY);
+                    let peer = Arc::new(Peer::from_handshake(from.to_string(), from, PeerDirection::Inbound, remote, tx));
+                    if hub.add_peer(peer.clone()).await.is_ok() {
+                        peer.spawn_writer(transport, outbox);
+                    }
+                });
+            }
+        });
+
+        let (shutdown, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        hub.spawn_connection_manager(config(0, 8), Arc::new(TcpDialer::new(plaintext(), version)), shutdown_rx);
+        TestNode { hub, address, _shutdown: shutdown }
+    }
+
+    #[tokio::test]
+    async fn test_self_dial_is_refused() {
+        let node = spawn_node().await;
+        assert_eq!(
+            node.hub.connect_peer(&node.address.to_string(), true).await,
+            Err(PeerRequestError::SelfConnection(node.address))
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(node.hub.peer_count(), 0);
+        assert_eq!(node.hub.known_addresses(), 0);
+        assert!(node.hub.is_local_address(&node.address));
+    }
+
+    #[tokio::test]
+    async fn test_cross_connect_collapses_to_one_connection() {
+        let (a, b) = (spawn_node().await, spawn_node().await);
+        let (to_b, to_a) = (b.address.to_string(), a.address.to_string());
+        let _ = tokio::join!(a.hub.connect_peer(&to_b, false), b.hub.connect_peer(&to_a, false));
+
+        // The connection opened by the node with the lower nonce survives on both sides
+        let expected = if a.hub.nonce() < b.hub.nonce() { PeerDirection::Outbound } else { PeerDirection::Inbound };
+        let mut collapsed = false;
+        for _ in 0..100 {
+            let (on_a, on_b) = (a.hub.peers().await, b.hub.peers().await);
+            if on_a.len() == 1 && on_b.len() == 1 && on_a[0].direction == expected && on_b[0].direction != expected {
+                assert_eq!(on_a[0].nonce(), Some(b.hub.nonce()));
+                assert_eq!(on_b[0].nonce(), Some(a.hub.nonce()));
+                collapsed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(collapsed);
+    }
+
+    #[tokio::test]
+    async fn test_excess_inbound_peers_are_trimmed() {
+        let hub = Hub::new();
+        let mut receivers = Vec::new();
+        for port in 1..=4 {
+            let (tx, rx) = mpsc::channel(1);
+            receivers.push(rx);
+            let address = SocketAddr::from(([127, 0, 0, 1], port));
+            let remote = VersionMessage::new("inbound", false);
+            let peer = Peer::from_handshake(port.to_string(), address, PeerDirection::Inbound, remote, tx);
+            hub.add_peer(Arc::new(peer)).await.unwrap();
+        }
+        assert_eq!(hub.peers().await.len(), 4);
+
+        hub.maintain_connections(&config(0, 2), &CountingDialer::new()).await;
+        assert_eq!(hub.peers().await.len(), 2);
+    }
+}
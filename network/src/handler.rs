@@ -0,0 +1,18 @@
+//! Dispatch of peer messages
+//!
+//! The hub reads every connected peer's frames and passes those it doesn't
+//! handle itself (pings) to the [`MessageHandler`] installed with
+//! [`Hub::set_message_handler`]. The handler answers on the peer's outbound
+//! queue.
+
+use crate::hub::Hub;
+use crate::p2p::Peer;
+use crate::protowire::Message;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    /// Act on `msg` read from `peer`
+    async fn handle(&self, hub: &Arc<Hub>, peer: &Arc<Peer>, msg: Message);
+}
@@ -1,28 +1,922 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::sync::mpsc;
-use crate::protowire::Message;
-use crate::p2p::Peer;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use crate::address_book::{self, AddressBook};
+use crate::connection_manager::{resolve_peer_address, ConnectionConfig, Dialer, PeerRequestError};
+use crate::inventory::{inventory_messages, InventoryItem, RelayCache};
+use crate::protowire::{ChainTip, Message};
+use crate::p2p::{BanPolicy, Misbehavior, Peer, PeerDirection, PeerInfo, PeerScores, Transport};
+use crate::nat::NatStatus;
+use crate::handler::MessageHandler;
+use consensus_core::time::{Clock, NetworkAdjustedClock, SystemClock};
 
 pub struct Hub {
-    peers: Arc<RwLock<HashMap<String, Arc<Peer>>>>,
+    /// Random identity of this node instance, sent in our version messages
+    nonce: u64,
+    peers: parking_lot::RwLock<HashMap<String, Arc<Peer>>>,
+    nat_status: parking_lot::RwLock<NatStatus>,
+    scores: parking_lot::Mutex<PeerScores>,
+    address_book: parking_lot::Mutex<AddressBook>,
+    /// Addresses we listen on, to refuse connecting to ourselves
+    local_addresses: parking_lot::RwLock<Vec<SocketAddr>>,
+    /// Where permanent peers are saved, if anywhere
+    peers_file: parking_lot::RwLock<Option<PathBuf>>,
+    /// Set once the connection manager runs, so requested peers are dialed at once
+    connector: parking_lot::RwLock<Option<(ConnectionConfig, Arc<dyn Dialer>)>>,
+    /// Announced blocks and transactions, sent to peers that request them
+    relay: parking_lot::Mutex<RelayCache>,
+    /// Local time adjusted by the clocks of connected peers
+    clock: Arc<NetworkAdjustedClock>,
+    /// Receives the messages peers send
+    message_handler: parking_lot::RwLock<Option<Arc<dyn MessageHandler>>>,
 }
 
 impl Hub {
     pub fn new() -> Self {
-        Self { peers: Arc::new(RwLock::new(HashMap::new())) }
+        Self::with_ban_policy(BanPolicy::default())
     }
 
-    pub async fn add_peer(&self, peer: Arc<Peer>) {
-        self.peers.write().await.insert(peer.id.clone(), peer);
+    pub fn with_ban_policy(policy: BanPolicy) -> Self {
+        Self {
+            nonce: rand::random::<u64>().max(1),
+            peers: parking_lot::RwLock::new(HashMap::new()),
+            nat_status: Default::default(),
+            scores: parking_lot::Mutex::new(PeerScores::new(policy)),
+            address_book: parking_lot::Mutex::new(AddressBook::new()),
+            local_addresses: Default::default(),
+            peers_file: Default::default(),
+            connector: Default::default(),
+            relay: Default::default(),
+            clock: Arc::new(NetworkAdjustedClock::new(Arc::new(SystemClock))),
+            message_handler: Default::default(),
+        }
+    }
+
+    /// Adjust `clock` with the times peers report in their version messages
+    pub fn with_clock(self, clock: Arc<NetworkAdjustedClock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Network-adjusted time, to send in our version messages and validate blocks against
+    pub fn clock(&self) -> Arc<NetworkAdjustedClock> {
+        self.clock.clone()
+    }
+
+    /// Record the result of NAT traversal for reporting over RPC
+    pub fn set_nat_status(&self, status: NatStatus) {
+        *self.nat_status.write() = status;
+    }
+
+    pub fn nat_status(&self) -> NatStatus {
+        self.nat_status.read().clone()
+    }
+
+    /// Nonce to send in our version messages
+    pub fn nonce(&self) -> u64 {
+        self.nonce
     }
 
+    /// Record the addresses we listen on
+    pub fn set_local_addresses(&self, addresses: Vec<SocketAddr>) {
+        *self.local_addresses.write() = addresses;
+    }
+
+    /// Whether `address` reaches one of our own listeners
+    pub fn is_local_address(&self, address: &SocketAddr) -> bool {
+        let external = self.nat_status.read().external_address;
+        external.as_ref() == Some(address)
+            || self.local_addresses.read().iter().any(|local| {
+                local.port() == address.port()
+                    && (local.ip() == address.ip()
+                        || (local.ip().is_unspecified() && (address.ip().is_loopback() || address.ip().is_unspecified())))
+            })
+    }
+
+    /// Add a peer, refusing addresses that are banned unless they are permanent.
+    /// A peer presenting our own nonce is refused and its address remembered as
+    /// ours. Of two connections to the same node, both sides keep the same one:
+    /// the older if they have the same direction, otherwise the one opened by
+    /// the node with the lower nonce.
+    pub async fn add_peer(&self, peer: Arc<Peer>) -> Result<(), String> {
+        if self.is_banned(peer.address.ip()) && !self.is_permanent(&peer.address) {
+            return Err(format!("peer {} is banned", peer.address.ip()));
+        }
+        let Some(nonce) = peer.nonce() else {
+            self.sample_clock(&peer);
+            self.peers.write().insert(peer.id.clone(), peer);
+            return Ok(());
+        };
+        if nonce == self.nonce {
+            self.mark_self(&peer);
+            return Err(format!("{} is ourselves", peer.address));
+        }
+
+        let mut peers = self.peers.write();
+        let existing = peers.values().find(|p| p.nonce() == Some(nonce)).cloned();
+        if let Some(existing) = existing {
+            let preferred = if self.nonce < nonce { PeerDirection::Outbound } else { PeerDirection::Inbound };
+            let replace = !existing.is_connected() || (existing.direction != peer.direction && peer.direction == preferred);
+            if !replace {
+                return Err(format!("already connected to {} via {}", peer.address, existing.address));
+            }
+            tracing::debug!("Replacing connection to {} with {}", existing.address, peer.address);
+            peers.remove(&existing.id);
+        }
+        self.sample_clock(&peer);
+        peers.insert(peer.id.clone(), peer);
+        Ok(())
+    }
+
+    /// Take the time the peer reported in its handshake as a clock sample
+    fn sample_clock(&self, peer: &Peer) {
+        let Some(timestamp) = peer.timestamp() else { return };
+        if let Some(median) = self.clock.add_sample(peer.address.ip(), timestamp) {
+            tracing::warn!(
+                "Peers' clocks are {}s off from ours on median, more than can be corrected; check that the local time is right (now {})",
+                median / 1000,
+                self.clock.now_millis()
+            );
+        }
+    }
+
+    /// Remember the address a connection to ourselves came through and stop dialing it
+    fn mark_self(&self, peer: &Peer) {
+        let address = match peer.direction {
+            PeerDirection::Outbound => Some(peer.address),
+            PeerDirection::Inbound => peer.advertised_address(),
+        };
+        let Some(address) = address else { return };
+        tracing::debug!("{} is our own address", address);
+        let mut local = self.local_addresses.write();
+        if !local.contains(&address) {
+            local.push(address);
+        }
+        self.address_book.lock().remove(&address);
+    }
+
+    pub async fn remove_peer(&self, id: &str) -> Option<Arc<Peer>> {
+        self.peers.write().remove(id)
+    }
+
+    pub async fn peers(&self) -> Vec<Arc<Peer>> {
+        self.peers.read().values().cloned().collect()
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.peers.read().len()
+    }
+
+    /// Connected inbound and outbound peer counts
+    pub fn peer_counts(&self) -> (usize, usize) {
+        let peers = self.peers.read();
+        let inbound = peers.values().filter(|peer| peer.direction == PeerDirection::Inbound).count();
+        (inbound, peers.len() - inbound)
+    }
+
+    /// Connection details of every peer, ordered by id
+    pub async fn peer_infos(&self) -> Vec<PeerInfo> {
+        let peers = self.peers.read();
+        let scores = self.scores.lock();
+        let mut infos: Vec<PeerInfo> = peers.values().map(|peer| peer.info(scores.score(&peer.address.ip()))).collect();
+        infos.sort_by(|a, b| a.id.cmp(&b.id));
+        infos
+    }
+
+    /// Whether connections from `ip` must be refused
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.scores.lock().is_banned(&ip, Instant::now())
+    }
+
+    /// Misbehavior score of `ip`
+    pub fn peer_score(&self, ip: IpAddr) -> u32 {
+        self.scores.lock().score(&ip)
+    }
+
+    /// Penalize `address` for a protocol violation. Once its score passes the ban
+    /// threshold, every peer from that IP is disconnected. Returns whether the
+    /// address got banned.
+    pub async fn report_misbehavior(&self, address: SocketAddr, misbehavior: Misbehavior) -> bool {
+        let banned = self.scores.lock().record(address.ip(), misbehavior, Instant::now());
+        if banned {
+            tracing::warn!("Banning {} after {:?}", address.ip(), misbehavior);
+            self.disconnect_ip(address.ip());
+        } else {
+            tracing::debug!("Peer {} misbehaved: {:?}", address, misbehavior);
+        }
+        banned
+    }
+
+    /// Ban `ip` for `duration`, the ban policy's duration if zero, and disconnect
+    /// its peers as a misbehavior ban does
+    pub fn ban(&self, ip: IpAddr, duration: Duration) {
+        let mut scores = self.scores.lock();
+        let duration = if duration.is_zero() { scores.policy().duration } else { duration };
+        scores.ban(ip, Instant::now() + duration);
+        drop(scores);
+        tracing::warn!("Banning {} for {:?}", ip, duration);
+        self.disconnect_ip(ip);
+    }
+
+    /// Lift the ban on `ip`
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.scores.lock().unban(&ip)
+    }
+
+    /// Banned addresses with the time left on each ban
+    pub fn banned(&self) -> Vec<(IpAddr, Duration)> {
+        self.scores.lock().bans(Instant::now())
+    }
+
+    /// Drop the peers connected from `ip`. Dropping a peer closes its message
+    /// channel, which ends the connection. Permanent peers stay connected; their
+    /// messages are still validated.
+    fn disconnect_ip(&self, ip: IpAddr) {
+        let book = self.address_book.lock();
+        self.peers.write().retain(|_, peer| peer.address.ip() != ip || book.is_permanent(&peer.address));
+    }
+
+    /// Remember `address` for outbound connections. Permanent addresses are
+    /// reconnected whenever they drop.
+    pub fn add_address(&self, address: SocketAddr, permanent: bool) {
+        self.address_book.lock().add(address, permanent);
+    }
+
+    /// Number of addresses in the address book
+    pub fn known_addresses(&self) -> usize {
+        self.address_book.lock().len()
+    }
+
+    pub fn is_permanent(&self, address: &SocketAddr) -> bool {
+        self.address_book.lock().is_permanent(address)
+    }
+
+    /// Load permanent peers saved in `path` and save later changes there
+    pub fn load_peers_file(&self, path: &Path) -> Result<usize, String> {
+        let addresses = address_book::load_permanent(path)?;
+        for address in &addresses {
+            self.add_address(*address, true);
+        }
+        *self.peers_file.write() = Some(path.to_path_buf());
+        Ok(addresses.len())
+    }
+
+    fn save_peers_file(&self) -> Result<(), PeerRequestError> {
+        let Some(path) = self.peers_file.read().clone() else { return Ok(()) };
+        let permanent = self.address_book.lock().permanent();
+        address_book::save_permanent(&path, &permanent).map_err(PeerRequestError::Storage)
+    }
+
+    /// Connect to `address` on request. A permanent address is saved and
+    /// redialed with backoff for as long as it is in the address book, even if
+    /// this dial fails. Without a running connection manager the address is
+    /// only queued.
+    pub async fn connect_peer(&self, address: &str, permanent: bool) -> Result<SocketAddr, PeerRequestError> {
+        let address = resolve_peer_address(address).await?;
+        if self.is_local_address(&address) {
+            return Err(PeerRequestError::SelfConnection(address));
+        }
+        if self.peers.read().values().any(|peer| peer.address == address) {
+            return Err(PeerRequestError::AlreadyConnected(address));
+        }
+
+        self.add_address(address, permanent);
+        if permanent {
+            self.save_peers_file()?;
+        }
+
+        let Some((config, dialer)) = self.connector.read().clone() else { return Ok(address) };
+        let dialed = match tokio::time::timeout(config.dial_timeout, dialer.dial(address)).await {
+            Ok(dialed) => dialed,
+            Err(_) => Err(format!("Dial to {} timed out", address)),
+        };
+        match dialed {
+            Ok(peer) => self.add_peer(peer).await.map_err(|e| {
+                if self.is_local_address(&address) {
+                    PeerRequestError::SelfConnection(address)
+                } else {
+                    PeerRequestError::Dial(e)
+                }
+            })?,
+            Err(e) => {
+                self.address_book.lock().mark_failure(&address, Instant::now());
+                return Err(PeerRequestError::Dial(e));
+            }
+        }
+        self.address_book.lock().mark_success(&address);
+        Ok(address)
+    }
+
+    /// Forget `address` and disconnect any peer connected at it; the inverse of `connect_peer`
+    pub async fn remove_peer_address(&self, address: &str) -> Result<SocketAddr, PeerRequestError> {
+        let address = resolve_peer_address(address).await?;
+        let (known, permanent) = {
+            let mut book = self.address_book.lock();
+            let known = (book.contains(&address), book.is_permanent(&address));
+            book.remove(&address);
+            known
+        };
+        let connected = {
+            let mut peers = self.peers.write();
+            let before = peers.len();
+            peers.retain(|_, peer| peer.address != address);
+            peers.len() != before
+        };
+        if !known && !connected {
+            return Err(PeerRequestError::NotFound(address));
+        }
+        if permanent {
+            self.save_peers_file()?;
+        }
+        Ok(address)
+    }
+
+    /// One connection maintenance round: drop failed peers, dial permanent
+    /// addresses that are not connected and other known addresses until
+    /// `min_peers` outbound peers are connected, then disconnect the newest
+    /// non-permanent peers, inbound first, above `max_peers`.
+    pub async fn maintain_connections(&self, config: &ConnectionConfig, dialer: &dyn Dialer) {
+        self.peers.write().retain(|_, peer| peer.is_connected());
+
+        let peers = self.peers().await;
+        let connected: HashSet<SocketAddr> = peers.iter().map(|peer| peer.address).collect();
+        let mut total = peers.len();
+        let mut outbound = peers.iter().filter(|peer| peer.direction == PeerDirection::Outbound).count();
+        let (permanent, candidates) = {
+            let book = self.address_book.lock();
+            let permanent: HashSet<SocketAddr> = book.permanent().into_iter().collect();
+            (permanent, book.candidates(Instant::now(), &connected))
+        };
+
+        for address in candidates {
+            if total >= config.max_peers {
+                break;
+            }
+            // Candidates list permanent addresses first
+            if outbound >= config.min_peers && !permanent.contains(&address) {
+                break;
+            }
+            if self.is_banned(address.ip()) && !permanent.contains(&address) {
+                continue;
+            }
+            let dialed = match tokio::time::timeout(config.dial_timeout, dialer.dial(address)).await {
+                Ok(dialed) => dialed,
+                Err(_) => Err(format!("Dial to {} timed out", address)),
+            };
+            let added = match dialed {
+                Ok(peer) => self.add_peer(peer).await,
+                Err(e) => Err(e),
+            };
+            match added {
+                Ok(()) => {
+                    self.address_book.lock().mark_success(&address);
+                    total += 1;
+                    outbound += 1;
+                }
+                Err(e) => {
+                    tracing::debug!("Failed to connect to {}: {}", address, e);
+                    self.address_book.lock().mark_failure(&address, Instant::now());
+                }
+            }
+        }
+
+        if total > config.max_peers {
+            let mut peers = self.peers.write();
+            let mut removable: Vec<Arc<Peer>> =
+                peers.values().filter(|peer| !permanent.contains(&peer.address)).cloned().collect();
+            removable.sort_by_key(|peer| (peer.direction == PeerDirection::Outbound, peer.connected_duration()));
+            for peer in removable.iter().take(peers.len().saturating_sub(config.max_peers)) {
+                tracing::debug!("Disconnecting {} above the peer limit", peer.address);
+                peers.remove(&peer.id);
+            }
+        }
+    }
+
+    /// Run `maintain_connections` every `config.interval` until shutdown
+    pub fn spawn_connection_manager(
+        self: &Arc<Self>,
+        config: ConnectionConfig,
+        dialer: Arc<dyn Dialer>,
+        mut shutdown: broadcast::Receiver<()>,
+    ) -> JoinHandle<()> {
+        *self.connector.write() = Some((config, dialer.clone()));
+        let hub = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => hub.maintain_connections(&config, dialer.as_ref()).await,
+                    _ = shutdown.recv() => break,
+                }
+            }
+        })
+    }
+
+    /// Relay `msg` to every peer. Blocks and transactions are announced by hash
+    /// in the next inventory batch and sent in full only to peers that request
+    /// them; peers known to have them are skipped. Other messages are queued as
+    /// they are. A peer whose queue is full misses the message rather than
+    /// stalling the broadcast.
     pub async fn broadcast(&self, msg: Message) {
-        let peers = self.peers.read().await;
+        if let Some(item) = self.relay.lock().insert(&msg) {
+            let full: Vec<Arc<Peer>> =
+                self.peers.read().values().filter(|peer| peer.queue_inventory(item)).cloned().collect();
+            for peer in full {
+                Self::send_inventory(&peer);
+            }
+            return;
+        }
+
+        let peers = self.peers.read();
         for p in peers.values() {
-            let _ = p.send_message(msg.clone()).await;
+            if let Err(e) = p.try_send_message(msg.clone()) {
+                tracing::debug!("Dropping broadcast to {}: {}", p.id, e);
+            }
+        }
+    }
+
+    fn send_inventory(peer: &Peer) {
+        for msg in inventory_messages(&peer.take_inventory()) {
+            if let Err(e) = peer.try_send_message(msg) {
+                tracing::debug!("Dropping inventory for {}: {}", peer.id, e);
+            }
+        }
+    }
+
+    /// Pass the messages peers send to `handler`
+    pub fn set_message_handler(&self, handler: Arc<dyn MessageHandler>) {
+        *self.message_handler.write() = Some(handler);
+    }
+
+    /// Run `peer`'s connection over `transport`: messages queued through the
+    /// peer are written as by `Peer::spawn_writer`, and frames read are passed
+    /// to the message handler, pings being answered on the queue. Reading stops
+    /// when writing does, so dropping the peer still ends the connection; a
+    /// failed read drops the peer, after scoring it if the frame was its fault.
+    pub fn spawn_connection<S>(self: &Arc<Self>, peer: &Arc<Peer>, transport: Transport<S>, outbox: mpsc::Receiver<Message>) -> JoinHandle<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut reader, writer) = transport.split();
+        let mut writer = peer.spawn_writer(writer, outbox);
+        let read_timeout = peer.keepalive().read_timeout;
+        let (hub, weak) = (self.clone(), Arc::downgrade(peer));
+        tokio::spawn(async move {
+            loop {
+                let read = tokio::select! {
+                    read = tokio::time::timeout(read_timeout, reader.read_frame_counted()) => read,
+                    _ = &mut writer => break,
+                };
+                let Some(peer) = weak.upgrade() else { break };
+                let msg = match read {
+                    Ok(Ok((msg, read))) => peer.record_received(&msg, read).map(|()| msg),
+                    Ok(Err(e)) => {
+                        if let Some(misbehavior) = Misbehavior::from_frame_error(&e) {
+                            hub.report_misbehavior(peer.address, misbehavior).await;
+                        }
+                        Err(e.to_string())
+                    }
+                    Err(_) => Err(format!("read timed out after {:?}", read_timeout)),
+                };
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        tracing::debug!("Dropping {}: {}", peer.address, e);
+                        peer.mark_disconnected();
+                        hub.peers.write().retain(|_, other| !Arc::ptr_eq(other, &peer));
+                        break;
+                    }
+                };
+                if let Message::Ping { nonce } = msg {
+                    if let Err(e) = peer.try_send_message(Message::Pong { nonce }) {
+                        tracing::debug!("Pong to {} failed: {}", peer.address, e);
+                    }
+                    continue;
+                }
+                let handler = hub.message_handler.read().clone();
+                if let Some(handler) = handler {
+                    handler.handle(&hub, &peer, msg).await;
+                }
+            }
+        })
+    }
+
+    /// Send every peer's queued inventory announcements
+    pub fn flush_inventory(&self) {
+        for peer in self.peers.read().values() {
+            Self::send_inventory(peer);
+        }
+    }
+
+    /// Run `flush_inventory` every `interval` until shutdown
+    pub fn spawn_inventory_flusher(self: &Arc<Self>, interval: Duration, mut shutdown: broadcast::Receiver<()>) -> JoinHandle<()> {
+        let hub = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => hub.flush_inventory(),
+                    _ = shutdown.recv() => break,
+                }
+            }
+        })
+    }
+
+    /// Connected peer that advertised the heaviest tip, ties going to the lower id
+    pub fn best_peer(&self) -> Option<Arc<Peer>> {
+        self.peers
+            .read()
+            .values()
+            .filter(|peer| peer.is_connected())
+            .max_by(|a, b| a.tip().blue_work.cmp(&b.tip().blue_work).then_with(|| b.id.cmp(&a.id)))
+            .cloned()
+    }
+
+    /// Announce the selected tip `tip` gives to every peer each `interval`
+    /// when it changed since the last announcement, until shutdown. Peers
+    /// connecting in between learn it from our version message.
+    pub fn spawn_tip_announcer<F>(self: &Arc<Self>, interval: Duration, tip: F, mut shutdown: broadcast::Receiver<()>) -> JoinHandle<()>
+    where
+        F: Fn() -> ChainTip + Send + 'static,
+    {
+        let hub = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            let mut announced = None;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let current = tip();
+                        if announced != Some(current) {
+                            hub.broadcast(Message::Tip(current)).await;
+                            announced = Some(current);
+                        }
+                    }
+                    _ = shutdown.recv() => break,
+                }
+            }
+        })
+    }
+
+    /// Update relay state for `msg` received from peer `id`. Items the peer
+    /// announces or sends are remembered as known to it, and requests are
+    /// answered from the relay cache. Returns the announced items that are not
+    /// in the relay cache, for the caller to request if it lacks them.
+    pub fn handle_inventory(&self, id: &str, msg: &Message) -> Vec<InventoryItem> {
+        let Some(peer) = self.peers.read().get(id).cloned() else { return Vec::new() };
+        match msg {
+            Message::InvBlock { .. } | Message::InvTransaction { .. } => {
+                let items = InventoryItem::listed_in(msg);
+                let relay = self.relay.lock();
+                items
+                    .into_iter()
+                    .filter(|item| {
+                        peer.mark_known(*item);
+                        relay.get(item).is_none()
+                    })
+                    .collect()
+            }
+            Message::RequestBlocks { .. } | Message::RequestTransactions { .. } => {
+                for item in InventoryItem::listed_in(msg) {
+                    let Some(full) = self.relay.lock().get(&item) else { continue };
+                    peer.mark_known(item);
+                    if let Err(e) = peer.try_send_message(full) {
+                        tracing::debug!("Dropping requested {:?} for {}: {}", item, peer.id, e);
+                    }
+                }
+                Vec::new()
+            }
+            Message::Block(_) | Message::Transaction(_) => {
+                if let Some(item) = InventoryItem::of(msg) {
+                    peer.mark_known(item);
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use crate::p2p::{negotiate, EncryptionConfig, PeerDirection, Role};
+    use crate::protowire::VersionMessage;
+
+    fn peer(id: &str, address: &str) -> (Arc<Peer>, mpsc::Receiver<Message>) {
+        let (tx, rx) = mpsc::channel(4);
+        (Arc::new(Peer::new(id.to_string(), address.parse().unwrap(), tx)), rx)
+    }
+
+    fn tx(n: u8) -> consensus_core::tx::Transaction {
+        use consensus_core::subnets::SubnetworkId;
+        use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionInput, TransactionOutpoint, TransactionOutput};
+        Transaction::new(
+            0,
+            vec![TransactionInput::new(TransactionOutpoint::new(consensus_core::Hash::from([n; 32]), 0), vec![], 0, 1)],
+            vec![TransactionOutput::new(1000, ScriptPublicKey::from_vec(0, vec![0xac]))],
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_peer_that_has_item_gets_no_payload() {
+        let hub = Hub::new();
+        let (has_it, mut has_it_rx) = peer("has_it", "10.0.0.1:16111");
+        let (lacks_it, mut lacks_it_rx) = peer("lacks_it", "10.0.0.2:16111");
+        hub.add_peer(has_it).await.unwrap();
+        hub.add_peer(lacks_it).await.unwrap();
+
+        let tx = tx(1);
+        let item = InventoryItem::Transaction(tx.hash());
+        assert_eq!(hub.handle_inventory("has_it", &Message::InvTransaction { hashes: vec![tx.hash()] }), vec![item]);
+
+        hub.broadcast(Message::Transaction(tx.clone())).await;
+        hub.flush_inventory();
+        assert!(has_it_rx.try_recv().is_err());
+        // The other peer only gets the announcement, and the payload on request
+        assert!(matches!(lacks_it_rx.try_recv().unwrap(), Message::InvTransaction { hashes } if hashes == vec![tx.hash()]));
+        assert!(lacks_it_rx.try_recv().is_err());
+        hub.handle_inventory("lacks_it", &Message::RequestTransactions { hashes: vec![tx.hash()] });
+        assert!(matches!(lacks_it_rx.try_recv().unwrap(), Message::Transaction(sent) if sent.hash() == tx.hash()));
+
+        // Relaying it again sends nothing to anyone
+        hub.broadcast(Message::Transaction(tx)).await;
+        hub.flush_inventory();
+        assert!(has_it_rx.try_recv().is_err());
+        assert!(lacks_it_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_announcements_are_batched() {
+        let hub = Hub::new();
+        let (p, mut rx) = peer("p", "10.0.0.1:16111");
+        hub.add_peer(p).await.unwrap();
+
+        let txs: Vec<_> = (1..=3).map(tx).collect();
+        for tx in &txs {
+            hub.broadcast(Message::Transaction(tx.clone())).await;
+        }
+        assert!(rx.try_recv().is_err());
+
+        hub.flush_inventory();
+        let expected: Vec<_> = txs.iter().map(|tx| tx.hash()).collect();
+        assert!(matches!(rx.try_recv().unwrap(), Message::InvTransaction { hashes } if hashes == expected));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_peer_past_threshold_is_disconnected_and_banned() {
+        let hub = Hub::with_ban_policy(BanPolicy { threshold: 100, duration: Duration::from_secs(60) });
+        let (bad, mut bad_rx) = peer("bad", "10.0.0.1:16111");
+        let (good, _good_rx) = peer("good", "10.0.0.2:16111");
+        hub.add_peer(bad.clone()).await.unwrap();
+        hub.add_peer(good).await.unwrap();
+        drop(bad);
+
+        assert!(!hub.report_misbehavior("10.0.0.1:16111".parse().unwrap(), Misbehavior::InvalidPow).await);
+        assert_eq!(hub.peer_score("10.0.0.1".parse().unwrap()), 50);
+        assert_eq!(hub.peers().await.len(), 2);
+
+        assert!(hub.report_misbehavior("10.0.0.1:16111".parse().unwrap(), Misbehavior::InvalidPow).await);
+        let remaining: Vec<String> = hub.peers().await.iter().map(|p| p.id.clone()).collect();
+        assert_eq!(remaining, vec!["good".to_string()]);
+        // The banned peer's channel is closed once the hub drops it
+        assert!(bad_rx.recv().await.is_none());
+        assert!(hub.is_banned("10.0.0.1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_banned_peer_cannot_reconnect() {
+        let hub = Hub::with_ban_policy(BanPolicy { threshold: 20, duration: Duration::from_secs(60) });
+        hub.report_misbehavior("10.0.0.1:16111".parse().unwrap(), Misbehavior::BadChecksum).await;
+
+        // A new connection from another source port is refused as well
+        let (again, _rx) = peer("again", "10.0.0.1:40000");
+        assert!(hub.add_peer(again).await.is_err());
+        assert!(hub.peers().await.is_empty());
+
+        assert!(hub.unban("10.0.0.1".parse().unwrap()));
+        let (again, _rx) = peer("again", "10.0.0.1:40000");
+        assert!(hub.add_peer(again).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_manual_ban_disconnects_until_unbanned() {
+        let hub = Hub::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let (banned, mut banned_rx) = peer("banned", "10.0.0.1:16111");
+        let (other, _other_rx) = peer("other", "10.0.0.2:16111");
+        hub.add_peer(banned.clone()).await.unwrap();
+        hub.add_peer(other).await.unwrap();
+        drop(banned);
+
+        hub.ban(ip, Duration::from_secs(600));
+        assert!(banned_rx.recv().await.is_none());
+        assert_eq!(hub.peers().await.len(), 1);
+        let bans = hub.banned();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].0, ip);
+        assert!(bans[0].1 > Duration::from_secs(590) && bans[0].1 <= Duration::from_secs(600));
+
+        let (again, _rx) = peer("again", "10.0.0.1:40000");
+        assert!(hub.add_peer(again).await.is_err());
+        assert!(hub.unban(ip));
+        assert!(hub.banned().is_empty());
+        let (again, _rx) = peer("again", "10.0.0.1:40000");
+        assert!(hub.add_peer(again).await.is_ok());
+
+        // Zero bans for the policy's duration
+        hub.ban(ip, Duration::ZERO);
+        assert!(hub.banned()[0].1 > Duration::from_secs(60));
+    }
+
+    /// Records the messages it is given and answers block requests with an announcement
+    #[derive(Default)]
+    struct Recorder {
+        received: parking_lot::Mutex<Vec<Message>>,
+    }
+
+    #[async_trait::async_trait]
+    impl MessageHandler for Recorder {
+        async fn handle(&self, _hub: &Arc<Hub>, peer: &Arc<Peer>, msg: Message) {
+            if let Message::RequestBlocks { hashes } = &msg {
+                peer.try_send_message(Message::InvBlock { hashes: hashes.clone() }).unwrap();
+            }
+            self.received.lock().push(msg);
+        }
+    }
+
+    /// Peer `id` added to `hub` whose connection runs over an in-memory stream, and the remote end
+    async fn connected_peer(hub: &Arc<Hub>, id: &str) -> (JoinHandle<()>, crate::p2p::Transport<tokio::io::DuplexStream>) {
+        let (local, remote) = tokio::io::duplex(1 << 16);
+        let (tx, outbox) = mpsc::channel(4);
+        let peer = Arc::new(Peer::new(id.to_string(), "10.0.0.1:16111".parse().unwrap(), tx));
+        hub.add_peer(peer.clone()).await.unwrap();
+        let connection = hub.spawn_connection(&peer, crate::p2p::Transport::Plain(local), outbox);
+        (connection, crate::p2p::Transport::Plain(remote))
+    }
+
+    #[tokio::test]
+    async fn test_connection_passes_messages_to_the_handler() {
+        let hub = Arc::new(Hub::new());
+        let handler = Arc::new(Recorder::default());
+        hub.set_message_handler(handler.clone());
+        let (connection, mut remote) = connected_peer(&hub, "remote").await;
+
+        // Pings are answered by the hub, requests by the handler on the same connection
+        remote.write_frame(&Message::Ping { nonce: 5 }).await.unwrap();
+        assert!(matches!(remote.read_frame().await.unwrap(), Message::Pong { nonce: 5 }));
+        let hashes = vec![consensus_core::Hash::from([1; 32])];
+        remote.write_frame(&Message::RequestBlocks { hashes: hashes.clone() }).await.unwrap();
+        assert!(matches!(remote.read_frame().await.unwrap(), Message::InvBlock { hashes: answered } if answered == hashes));
+        assert_eq!(handler.received.lock().len(), 1);
+        assert!(hub.peer_infos().await[0].bytes_received > 0);
+
+        // Dropping the peer from the hub closes the connection
+        assert!(hub.remove_peer("remote").await.is_some());
+        assert!(remote.read_frame().await.is_err());
+        connection.await.unwrap();
+
+        // A peer whose connection fails is dropped
+        let (connection, remote) = connected_peer(&hub, "closing").await;
+        drop(remote);
+        connection.await.unwrap();
+        assert!(hub.peers().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_frames_are_scored() {
+        use tokio::io::AsyncWriteExt;
+        let hub = Arc::new(Hub::with_ban_policy(BanPolicy { threshold: 10, duration: Duration::from_secs(60) }));
+        let (local, mut remote) = tokio::io::duplex(1 << 16);
+        let (tx, outbox) = mpsc::channel(4);
+        let peer = Arc::new(Peer::new("corrupt".to_string(), "10.0.0.1:16111".parse().unwrap(), tx));
+        hub.add_peer(peer.clone()).await.unwrap();
+        let connection = hub.spawn_connection(&peer, crate::p2p::Transport::Plain(local), outbox);
+
+        // A frame whose payload is not a message
+        remote.write_all(&4u32.to_le_bytes()).await.unwrap();
+        remote.write_all(&[0xff; 4]).await.unwrap();
+        connection.await.unwrap();
+        assert!(hub.is_banned(peer.address.ip()));
+        assert!(hub.peers().await.is_empty());
+    }
+
+    fn chain_tip(n: u8, blue_work: u64) -> ChainTip {
+        ChainTip { hash: consensus_core::Hash::from([n; 32]), blue_score: n as u64, blue_work: blue_work.into() }
+    }
+
+    #[tokio::test]
+    async fn test_best_peer_follows_tip_announcements() {
+        let hub = Hub::new();
+        let mut transports = Vec::new();
+        for (n, blue_work) in [(1, 100), (2, 300), (3, 200)] {
+            let (tx, _rx) = mpsc::channel(4);
+            let version = VersionMessage::new("peer", false).with_tip(chain_tip(n, blue_work));
+            let address = format!("10.0.0.{}:16111", n).parse().unwrap();
+            let peer = Arc::new(Peer::from_handshake(format!("peer{}", n), address, PeerDirection::Inbound, version, tx));
+            hub.add_peer(peer.clone()).await.unwrap();
+            let (local, remote) = tokio::io::duplex(1024);
+            transports.push((peer, crate::p2p::Transport::Plain(local), crate::p2p::Transport::Plain(remote)));
         }
+        assert_eq!(hub.best_peer().unwrap().id, "peer2");
+
+        // The third peer's chain outgrows the second's
+        let (peer, local, remote) = &mut transports[2];
+        remote.write_frame(&Message::Tip(chain_tip(4, 400))).await.unwrap();
+        peer.read_frame(local).await.unwrap();
+        assert_eq!(hub.best_peer().unwrap().id, "peer3");
+        transports[2].0.mark_disconnected();
+        assert_eq!(hub.best_peer().unwrap().id, "peer2");
+    }
+
+    #[tokio::test]
+    async fn test_tip_is_announced_when_it_changes() {
+        let hub = Arc::new(Hub::new());
+        let (listener, mut rx) = peer("listener", "10.0.0.1:16111");
+        hub.add_peer(listener).await.unwrap();
+        let tip = Arc::new(parking_lot::Mutex::new(chain_tip(1, 100)));
+        let (shutdown, _) = broadcast::channel(1);
+        let source = tip.clone();
+        let announcer = hub.spawn_tip_announcer(Duration::from_millis(10), move || *source.lock(), shutdown.subscribe());
+
+        assert!(matches!(rx.recv().await, Some(Message::Tip(announced)) if announced == chain_tip(1, 100)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+        *tip.lock() = chain_tip(2, 200);
+        assert!(matches!(rx.recv().await, Some(Message::Tip(announced)) if announced == chain_tip(2, 200)));
+
+        shutdown.send(()).unwrap();
+        announcer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connected_peers_report_consistent_counters() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let config = EncryptionConfig { enabled: false, allow_plaintext: true, identity: None };
+        let (accepted, connected) = tokio::join!(listener.accept(), TcpStream::connect(listen_addr));
+        let (inbound_stream, remote_addr) = accepted.unwrap();
+        let outbound_stream = connected.unwrap();
+
+        let (server, client) = tokio::join!(
+            negotiate(inbound_stream, Role::Responder, &config, VersionMessage::new("server", false).with_blue_score(7).with_archival(true)),
+            negotiate(outbound_stream, Role::Initiator, &config, VersionMessage::new("client", false).with_blue_score(3))
+        );
+        let (mut server_transport, client_version) = server.unwrap();
+        let (mut client_transport, server_version) = client.unwrap();
+
+        // Each side registers the other in its own hub
+        let (server_hub, client_hub) = (Hub::new(), Hub::new());
+        let (tx, _server_rx) = mpsc::channel(4);
+        let on_server = Arc::new(Peer::from_handshake("client".into(), remote_addr, PeerDirection::Inbound, client_version, tx));
+        let (tx, _client_rx) = mpsc::channel(4);
+        let on_client = Arc::new(Peer::from_handshake("server".into(), listen_addr, PeerDirection::Outbound, server_version, tx));
+        server_hub.add_peer(on_server.clone()).await.unwrap();
+        client_hub.add_peer(on_client.clone()).await.unwrap();
+
+        // Client pings, server answers, then the server sends a block inventory
+        on_client.ping(&mut client_transport, 42).await.unwrap();
+        assert!(matches!(on_server.read_frame(&mut server_transport).await.unwrap(), Message::Ping { nonce: 42 }));
+        assert!(matches!(on_client.read_frame(&mut client_transport).await.unwrap(), Message::Pong { nonce: 42 }));
+        let inv = Message::InvBlock { hashes: vec![consensus_core::ZERO_HASH; 3] };
+        on_server.write_frame(&mut server_transport, &inv).await.unwrap();
+        on_client.read_frame(&mut client_transport).await.unwrap();
+
+        let server_view = server_hub.peer_infos().await.remove(0);
+        let client_view = client_hub.peer_infos().await.remove(0);
+        assert_eq!(server_view.direction, PeerDirection::Inbound);
+        assert_eq!(client_view.direction, PeerDirection::Outbound);
+        assert_eq!(server_view.user_agent, "client");
+        assert_eq!(client_view.user_agent, "server");
+        assert_eq!(server_view.advertised_blue_score, 3);
+        assert_eq!(client_view.advertised_blue_score, 7);
+        assert!(client_view.archival);
+        assert!(!server_view.archival);
+        assert_eq!(client_view.protocol_version, crate::protowire::PROTOCOL_VERSION);
+
+        assert!(client_view.bytes_sent > 0 && server_view.bytes_sent > client_view.bytes_sent);
+        assert_eq!(client_view.bytes_sent, server_view.bytes_received);
+        assert_eq!(server_view.bytes_sent, client_view.bytes_received);
+        assert!(client_view.last_ping_ms.is_some());
+        assert_eq!(server_view.last_ping_ms, None);
+        assert_eq!(server_view.ban_score, 0);
+        assert!(client_view.last_seen <= client_view.connected_duration);
     }
 }
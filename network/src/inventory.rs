@@ -0,0 +1,218 @@
+//! Inventory relay
+//!
+//! Blocks and transactions are relayed in two steps: the hub announces the hash
+//! of a new object to every peer (`InvBlock` / `InvTransaction`) and sends the
+//! object itself only to peers that ask for it. Each peer remembers the
+//! inventory it is known to have, whether we announced it or the peer announced
+//! or sent it to us, so nothing is announced to a peer twice. Announcements are
+//! queued per peer and sent in batches.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use consensus_core::block::Block;
+use consensus_core::tx::Transaction;
+use consensus_core::Hash;
+use crate::protowire::Message;
+
+/// Inventory items remembered per peer
+pub const MAX_KNOWN_INVENTORY: usize = 10_000;
+
+/// Recently announced objects kept to answer requests
+pub const MAX_RELAY_CACHE: usize = 1_000;
+
+/// Queued announcements that make a peer's batch go out immediately
+pub const INV_BATCH_SIZE: usize = 500;
+
+/// Longest time an announcement waits in a peer's batch
+pub const INV_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A relayable object, identified by its hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InventoryItem {
+    Block(Hash),
+    Transaction(Hash),
+}
+
+impl InventoryItem {
+    /// Item carried by `msg`, if it is a block or transaction
+    pub fn of(msg: &Message) -> Option<Self> {
+        match msg {
+            Message::Block(block) => Some(InventoryItem::Block(block.header.hash)),
+            Message::Transaction(tx) => Some(InventoryItem::Transaction(tx.hash())),
+            _ => None,
+        }
+    }
+
+    /// Items announced or requested by `msg`
+    pub fn listed_in(msg: &Message) -> Vec<Self> {
+        match msg {
+            Message::InvBlock { hashes } | Message::RequestBlocks { hashes } => {
+                hashes.iter().copied().map(InventoryItem::Block).collect()
+            }
+            Message::InvTransaction { hashes } | Message::RequestTransactions { hashes } => {
+                hashes.iter().copied().map(InventoryItem::Transaction).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Announcements for `items`: at most one `InvBlock` and one `InvTransaction`
+pub fn inventory_messages(items: &[InventoryItem]) -> Vec<Message> {
+    let (mut blocks, mut transactions) = (Vec::new(), Vec::new());
+    for item in items {
+        match item {
+            InventoryItem::Block(hash) => blocks.push(*hash),
+            InventoryItem::Transaction(hash) => transactions.push(*hash),
+        }
+    }
+    let mut messages = Vec::new();
+    if !blocks.is_empty() {
+        messages.push(Message::InvBlock { hashes: blocks });
+    }
+    if !transactions.is_empty() {
+        messages.push(Message::InvTransaction { hashes: transactions });
+    }
+    messages
+}
+
+/// Set of items that forgets the oldest once full
+#[derive(Debug)]
+pub struct KnownInventory {
+    items: HashSet<InventoryItem>,
+    order: VecDeque<InventoryItem>,
+    capacity: usize,
+}
+
+impl KnownInventory {
+    pub fn new(capacity: usize) -> Self {
+        Self { items: HashSet::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// Remember `item`. Returns false if it was already known.
+    pub fn insert(&mut self, item: InventoryItem) -> bool {
+        if !self.items.insert(item) {
+            return false;
+        }
+        self.order.push_back(item);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, item: &InventoryItem) -> bool {
+        self.items.contains(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl Default for KnownInventory {
+    fn default() -> Self {
+        Self::new(MAX_KNOWN_INVENTORY)
+    }
+}
+
+/// Recently announced blocks and transactions, served to peers that request them
+#[derive(Debug)]
+pub struct RelayCache {
+    blocks: HashMap<Hash, Block>,
+    transactions: HashMap<Hash, Transaction>,
+    order: VecDeque<InventoryItem>,
+    capacity: usize,
+}
+
+impl RelayCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { blocks: HashMap::new(), transactions: HashMap::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// Keep the object carried by `msg`, returning its item
+    pub fn insert(&mut self, msg: &Message) -> Option<InventoryItem> {
+        let item = InventoryItem::of(msg)?;
+        let added = match msg {
+            Message::Block(block) => self.blocks.insert(block.header.hash, block.clone()).is_none(),
+            Message::Transaction(tx) => self.transactions.insert(tx.hash(), tx.clone()).is_none(),
+            _ => false,
+        };
+        if added {
+            self.order.push_back(item);
+            if self.order.len() > self.capacity {
+                match self.order.pop_front() {
+                    Some(InventoryItem::Block(hash)) => {
+                        self.blocks.remove(&hash);
+                    }
+                    Some(InventoryItem::Transaction(hash)) => {
+                        self.transactions.remove(&hash);
+                    }
+                    None => {}
+                }
+            }
+        }
+        Some(item)
+    }
+
+    /// Full message for `item`, if it is still cached
+    pub fn get(&self, item: &InventoryItem) -> Option<Message> {
+        match item {
+            InventoryItem::Block(hash) => self.blocks.get(hash).cloned().map(Message::Block),
+            InventoryItem::Transaction(hash) => self.transactions.get(hash).cloned().map(Message::Transaction),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Default for RelayCache {
+    fn default() -> Self {
+        Self::new(MAX_RELAY_CACHE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> Hash {
+        Hash::from([n; 32])
+    }
+
+    #[test]
+    fn test_known_inventory_forgets_oldest() {
+        let mut known = KnownInventory::new(2);
+        assert!(known.insert(InventoryItem::Block(hash(1))));
+        assert!(!known.insert(InventoryItem::Block(hash(1))));
+        assert!(known.insert(InventoryItem::Transaction(hash(1))));
+        assert!(known.insert(InventoryItem::Transaction(hash(2))));
+
+        assert_eq!(known.len(), 2);
+        assert!(!known.contains(&InventoryItem::Block(hash(1))));
+        assert!(known.contains(&InventoryItem::Transaction(hash(1))));
+    }
+
+    #[test]
+    fn test_announcements_are_grouped_by_kind() {
+        let items = [InventoryItem::Transaction(hash(1)), InventoryItem::Block(hash(2)), InventoryItem::Transaction(hash(3))];
+        let messages = inventory_messages(&items);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(&messages[0], Message::InvBlock { hashes } if *hashes == vec![hash(2)]));
+        assert!(matches!(&messages[1], Message::InvTransaction { hashes } if *hashes == vec![hash(1), hash(3)]));
+        assert_eq!(InventoryItem::listed_in(&messages[1]), vec![items[0], items[2]]);
+        assert!(inventory_messages(&[]).is_empty());
+    }
+}
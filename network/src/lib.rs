@@ -3,6 +3,12 @@
 pub mod p2p;
 pub mod protowire;
 pub mod hub;
+pub mod handler;
+pub mod address_book;
+pub mod connection_manager;
+pub mod inventory;
+pub mod listen;
+pub mod nat;
 
 pub use p2p::Peer;
 pub use hub::Hub;
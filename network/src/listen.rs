@@ -0,0 +1,148 @@
+//! Listen address resolution and binding shared by the P2P and RPC servers.
+//!
+//! A listen host may be an IPv4 or IPv6 literal (optionally in brackets), or `*`
+//! for dual-stack, which binds both `0.0.0.0` and `[::]`. IPv6 sockets are bound
+//! v6-only so the two dual-stack listeners don't collide on the same port.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::TcpListener;
+
+/// Host value meaning "all IPv4 and IPv6 interfaces"
+pub const DUAL_STACK: &str = "*";
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Parse an IP literal, accepting bracketed IPv6 (`[::1]`)
+pub fn parse_ip(host: &str) -> Result<IpAddr, String> {
+    let host = host.trim();
+    let unbracketed = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    unbracketed.parse().map_err(|_| format!("Invalid listen address '{}'", host))
+}
+
+/// Resolve a comma separated list of listen hosts into socket addresses on `port`
+pub fn resolve_listen_addresses(hosts: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let mut addresses = Vec::new();
+    for host in hosts.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+        if host == DUAL_STACK {
+            addresses.push(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port));
+            addresses.push(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port));
+        } else {
+            addresses.push(SocketAddr::new(parse_ip(host)?, port));
+        }
+    }
+    if addresses.is_empty() {
+        return Err("No listen address configured".to_string());
+    }
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// Parse a peer address, accepting `ip:port`, `[ipv6]:port`, or a bare IP which
+/// gets `default_port`
+pub fn parse_peer_address(address: &str, default_port: u16) -> Result<SocketAddr, String> {
+    let address = address.trim();
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    parse_ip(address)
+        .map(|ip| SocketAddr::new(ip, default_port))
+        .map_err(|_| format!("Invalid peer address '{}'", address))
+}
+
+/// Bind a single TCP listener
+pub fn bind_listener(address: SocketAddr) -> Result<TcpListener, String> {
+    let bind = || -> std::io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+        if address.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&address.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+        TcpListener::from_std(socket.into())
+    };
+    bind().map_err(|e| match e.kind() {
+        std::io::ErrorKind::AddrInUse => format!("Cannot listen on {}: port {} is already in use", address, address.port()),
+        _ => format!("Cannot listen on {}: {}", address, e),
+    })
+}
+
+/// Bind every address, failing as a whole if any of them can't be bound
+pub fn bind_listeners(addresses: &[SocketAddr]) -> Result<Vec<TcpListener>, String> {
+    addresses.iter().map(|address| bind_listener(*address)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    fn ipv6_available() -> bool {
+        std::net::TcpListener::bind("[::1]:0").is_ok()
+    }
+
+    #[test]
+    fn test_resolve_listen_addresses() {
+        assert_eq!(resolve_listen_addresses("127.0.0.1", 16111).unwrap(), vec!["127.0.0.1:16111".parse().unwrap()]);
+        assert_eq!(resolve_listen_addresses("[::1]", 16111).unwrap(), vec!["[::1]:16111".parse().unwrap()]);
+        assert_eq!(
+            resolve_listen_addresses("*", 16111).unwrap(),
+            vec!["0.0.0.0:16111".parse().unwrap(), "[::]:16111".parse().unwrap()]
+        );
+        assert_eq!(resolve_listen_addresses("0.0.0.0, ::", 1).unwrap().len(), 2);
+        assert!(resolve_listen_addresses("localhost", 1).is_err());
+        assert!(resolve_listen_addresses("", 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_peer_address() {
+        assert_eq!(parse_peer_address("10.0.0.1:16111", 1).unwrap(), "10.0.0.1:16111".parse().unwrap());
+        assert_eq!(parse_peer_address("[2001:db8::1]:16211", 1).unwrap(), "[2001:db8::1]:16211".parse().unwrap());
+        assert_eq!(parse_peer_address("2001:db8::1", 16111).unwrap(), "[2001:db8::1]:16111".parse().unwrap());
+        assert_eq!(parse_peer_address("10.0.0.1", 16111).unwrap().to_string(), "10.0.0.1:16111");
+        assert!(parse_peer_address("not-an-address", 16111).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_v4_and_v6_loopback() {
+        let mut addresses = vec!["127.0.0.1:0".parse().unwrap()];
+        if ipv6_available() {
+            addresses.push("[::1]:0".parse().unwrap());
+        }
+
+        for listener in bind_listeners(&addresses).unwrap() {
+            let local = listener.local_addr().unwrap();
+            let accept = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                stream.write_all(b"ok").await.unwrap();
+            });
+            let mut client = TcpStream::connect(local).await.unwrap();
+            let mut buf = [0u8; 2];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ok");
+            accept.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_same_port() {
+        let v4 = bind_listener("0.0.0.0:0".parse().unwrap()).unwrap();
+        if !ipv6_available() {
+            return;
+        }
+        let port = v4.local_addr().unwrap().port();
+        let v6 = bind_listener(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port)).unwrap();
+        assert_eq!(v6.local_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn test_port_in_use_is_reported() {
+        let taken = bind_listener("127.0.0.1:0".parse().unwrap()).unwrap();
+        let err = bind_listener(taken.local_addr().unwrap()).unwrap_err();
+        assert!(err.contains("already in use"), "{}", err);
+    }
+}
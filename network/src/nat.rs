@@ -0,0 +1,494 @@
+//! NAT traversal for the P2P listen port.
+//!
+//! At startup the node asks the local gateway to forward its listen port, first via
+//! UPnP IGD and then via NAT-PMP. The lease is refreshed periodically and removed on
+//! shutdown. When no gateway cooperates the node keeps running outbound-only.
+
+use crate::hub::Hub;
+use crate::protowire::Message;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket as StdUdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// Default lease requested from the gateway
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(3600);
+
+/// Port NAT-PMP gateways listen on
+pub const NAT_PMP_PORT: u16 = 5351;
+
+const MAPPING_DESCRIPTION: &str = "jiopad p2p";
+
+/// A gateway protocol able to forward a port to this host
+#[async_trait]
+pub trait PortMapper: Send + Sync {
+    /// Protocol name, for logs and status reporting
+    fn protocol(&self) -> &'static str;
+
+    /// Public address of the gateway
+    async fn external_ip(&self) -> Result<IpAddr, String>;
+
+    /// Map `external_port` on the gateway to `local_port` on this host.
+    /// Returns the external port actually granted.
+    async fn add_mapping(&self, local_port: u16, external_port: u16, lease: Duration) -> Result<u16, String>;
+
+    /// Remove a mapping previously added with `add_mapping`
+    async fn remove_mapping(&self, local_port: u16, external_port: u16) -> Result<(), String>;
+}
+
+/// Current NAT traversal state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NatStatus {
+    /// Protocol that produced the active mapping
+    pub protocol: Option<&'static str>,
+    /// Address peers can reach us on, advertised in version messages
+    pub external_address: Option<SocketAddr>,
+    /// Whether inbound connections are expected to work
+    pub inbound_reachable: bool,
+    /// Last failure, if traversal did not succeed
+    pub last_error: Option<String>,
+}
+
+/// Keeps a port mapping for the P2P listen port alive
+pub struct NatManager {
+    mappers: Vec<Box<dyn PortMapper>>,
+    local_port: u16,
+    lease: Duration,
+    status: RwLock<NatStatus>,
+    active: RwLock<Option<(usize, u16)>>,
+}
+
+impl NatManager {
+    pub fn new(local_port: u16, mappers: Vec<Box<dyn PortMapper>>) -> Self {
+        Self::with_lease(local_port, mappers, DEFAULT_LEASE)
+    }
+
+    pub fn with_lease(local_port: u16, mappers: Vec<Box<dyn PortMapper>>, lease: Duration) -> Self {
+        Self { mappers, local_port, lease, status: RwLock::new(NatStatus::default()), active: RwLock::new(None) }
+    }
+
+    /// Manager trying UPnP first and NAT-PMP against the default gateway second
+    pub fn with_default_mappers(local_port: u16) -> Self {
+        let mut mappers: Vec<Box<dyn PortMapper>> = vec![Box::new(UpnpMapper::default())];
+        if let Some(gateway) = default_gateway_v4() {
+            mappers.push(Box::new(NatPmpMapper::new(SocketAddr::new(IpAddr::V4(gateway), NAT_PMP_PORT))));
+        }
+        Self::new(local_port, mappers)
+    }
+
+    pub fn status(&self) -> NatStatus {
+        self.status.read().clone()
+    }
+
+    /// Try each mapper in order until one grants a mapping. Failure is not fatal;
+    /// the returned status tells whether the node is reachable from outside.
+    pub async fn start(&self) -> NatStatus {
+        let mut errors = Vec::new();
+        for (index, mapper) in self.mappers.iter().enumerate() {
+            match self.try_map(mapper.as_ref()).await {
+                Ok(address) => {
+                    tracing::info!("{} mapped external address {} to local port {}", mapper.protocol(), address, self.local_port);
+                    *self.active.write() = Some((index, address.port()));
+                    let status = NatStatus {
+                        protocol: Some(mapper.protocol()),
+                        external_address: Some(address),
+                        inbound_reachable: true,
+                        last_error: None,
+                    };
+                    *self.status.write() = status.clone();
+                    return status;
+                }
+                Err(e) => errors.push(format!("{}: {}", mapper.protocol(), e)),
+            }
+        }
+
+        let error = if errors.is_empty() { "no port mapping protocol available".to_string() } else { errors.join("; ") };
+        tracing::warn!("NAT traversal failed ({}), running outbound-only", error);
+        let status = NatStatus { last_error: Some(error), ..Default::default() };
+        *self.status.write() = status.clone();
+        status
+    }
+
+    async fn try_map(&self, mapper: &dyn PortMapper) -> Result<SocketAddr, String> {
+        let ip = mapper.external_ip().await?;
+        let port = mapper.add_mapping(self.local_port, self.local_port, self.lease).await?;
+        Ok(SocketAddr::new(ip, port))
+    }
+
+    /// Renew the active mapping, falling back to a full restart if the gateway forgot it
+    pub async fn refresh(&self) -> NatStatus {
+        let active = *self.active.read();
+        match active {
+            Some((index, external_port)) => {
+                let mapper = &self.mappers[index];
+                match mapper.add_mapping(self.local_port, external_port, self.lease).await {
+                    Ok(_) => self.status(),
+                    Err(e) => {
+                        tracing::warn!("Failed to refresh {} mapping: {}", mapper.protocol(), e);
+                        *self.active.write() = None;
+                        self.start().await
+                    }
+                }
+            }
+            None => self.start().await,
+        }
+    }
+
+    /// Remove the active mapping from the gateway
+    pub async fn stop(&self) {
+        let active = self.active.write().take();
+        if let Some((index, external_port)) = active {
+            let mapper = &self.mappers[index];
+            if let Err(e) = mapper.remove_mapping(self.local_port, external_port).await {
+                tracing::warn!("Failed to remove {} mapping: {}", mapper.protocol(), e);
+            }
+        }
+        *self.status.write() = NatStatus::default();
+    }
+
+    /// Refresh the mapping at half the lease interval until shutdown, then remove it.
+    /// Each refresh updates the status `hub` reports and advertises the external
+    /// address to its peers again, since a restarted mapping may have moved it.
+    pub fn spawn_refresh(self: Arc<Self>, hub: Arc<Hub>, mut shutdown: broadcast::Receiver<()>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.lease / 2);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let status = self.refresh().await;
+                        if let Some(address) = status.external_address {
+                            hub.broadcast(Message::Addr { addresses: vec![address] }).await;
+                        }
+                        hub.set_nat_status(status);
+                    }
+                    _ = shutdown.recv() => {
+                        self.stop().await;
+                        break;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// UPnP IGD port mapper
+pub struct UpnpMapper {
+    search_timeout: Duration,
+}
+
+impl Default for UpnpMapper {
+    fn default() -> Self {
+        Self { search_timeout: Duration::from_secs(3) }
+    }
+}
+
+impl UpnpMapper {
+    async fn gateway(&self) -> Result<igd_next::aio::Gateway<igd_next::aio::tokio::Tokio>, String> {
+        let options = igd_next::SearchOptions { timeout: Some(self.search_timeout), ..Default::default() };
+        igd_next::aio::tokio::search_gateway(options).await.map_err(|e| format!("gateway search failed: {}", e))
+    }
+}
+
+#[async_trait]
+impl PortMapper for UpnpMapper {
+    fn protocol(&self) -> &'static str {
+        "upnp"
+    }
+
+    async fn external_ip(&self) -> Result<IpAddr, String> {
+        self.gateway().await?.get_external_ip().await.map_err(|e| e.to_string())
+    }
+
+    async fn add_mapping(&self, local_port: u16, external_port: u16, lease: Duration) -> Result<u16, String> {
+        let gateway = self.gateway().await?;
+        let local_ip = local_ip_towards(gateway.addr.ip()).ok_or("could not determine local address")?;
+        gateway
+            .add_port(
+                igd_next::PortMappingProtocol::TCP,
+                external_port,
+                SocketAddr::new(local_ip, local_port),
+                lease.as_secs() as u32,
+                MAPPING_DESCRIPTION,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(external_port)
+    }
+
+    async fn remove_mapping(&self, _local_port: u16, external_port: u16) -> Result<(), String> {
+        let gateway = self.gateway().await?;
+        gateway.remove_port(igd_next::PortMappingProtocol::TCP, external_port).await.map_err(|e| e.to_string())
+    }
+}
+
+/// NAT-PMP (RFC 6886) port mapper
+pub struct NatPmpMapper {
+    gateway: SocketAddr,
+    timeout: Duration,
+}
+
+impl NatPmpMapper {
+    pub fn new(gateway: SocketAddr) -> Self {
+        Self { gateway, timeout: Duration::from_millis(250) }
+    }
+
+    /// Send a request and wait for the response, retrying with a doubling timeout
+    async fn request(&self, request: &[u8]) -> Result<Vec<u8>, String> {
+        let bind: SocketAddr = if self.gateway.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+        let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+        socket.connect(self.gateway).await.map_err(|e| e.to_string())?;
+
+        let mut timeout = self.timeout;
+        let mut buf = [0u8; 16];
+        for _ in 0..4 {
+            socket.send(request).await.map_err(|e| e.to_string())?;
+            if let Ok(received) = tokio::time::timeout(timeout, socket.recv(&mut buf)).await {
+                let n = received.map_err(|e| e.to_string())?;
+                return Ok(buf[..n].to_vec());
+            }
+            timeout *= 2;
+        }
+        Err("gateway did not respond".to_string())
+    }
+
+    async fn map(&self, local_port: u16, external_port: u16, lifetime: u32) -> Result<u16, String> {
+        let mut request = vec![0u8, 2, 0, 0];
+        request.extend_from_slice(&local_port.to_be_bytes());
+        request.extend_from_slice(&external_port.to_be_bytes());
+        request.extend_from_slice(&lifetime.to_be_bytes());
+        let response = self.request(&request).await?;
+        parse_nat_pmp_mapping(&response, local_port)
+    }
+}
+
+#[async_trait]
+impl PortMapper for NatPmpMapper {
+    fn protocol(&self) -> &'static str {
+        "nat-pmp"
+    }
+
+    async fn external_ip(&self) -> Result<IpAddr, String> {
+        let response = self.request(&[0, 0]).await?;
+        parse_nat_pmp_external_address(&response).map(IpAddr::V4)
+    }
+
+    async fn add_mapping(&self, local_port: u16, external_port: u16, lease: Duration) -> Result<u16, String> {
+        self.map(local_port, external_port, lease.as_secs() as u32).await
+    }
+
+    async fn remove_mapping(&self, local_port: u16, _external_port: u16) -> Result<(), String> {
+        // A zero lifetime and zero suggested port deletes the mapping
+        self.map(local_port, 0, 0).await.map(|_| ())
+    }
+}
+
+fn nat_pmp_result(response: &[u8], opcode: u8, len: usize) -> Result<(), String> {
+    if response.len() < len {
+        return Err("short NAT-PMP response".to_string());
+    }
+    if response[0] != 0 || response[1] != opcode {
+        return Err("unexpected NAT-PMP response".to_string());
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => Err(format!("NAT-PMP result code {}", code)),
+    }
+}
+
+/// Parse an external address response (opcode 128)
+pub fn parse_nat_pmp_external_address(response: &[u8]) -> Result<Ipv4Addr, String> {
+    nat_pmp_result(response, 128, 12)?;
+    Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+}
+
+/// Parse a TCP mapping response (opcode 130) and return the granted external port
+pub fn parse_nat_pmp_mapping(response: &[u8], local_port: u16) -> Result<u16, String> {
+    nat_pmp_result(response, 130, 16)?;
+    let internal = u16::from_be_bytes([response[8], response[9]]);
+    if internal != local_port {
+        return Err(format!("mapping is for port {}, expected {}", internal, local_port));
+    }
+    Ok(u16::from_be_bytes([response[10], response[11]]))
+}
+
+/// Local address used to reach `remote`, i.e. the LAN address the gateway should forward to
+pub fn local_ip_towards(remote: IpAddr) -> Option<IpAddr> {
+    let bind: SocketAddr = if remote.is_ipv4() { "0.0.0.0:0".parse().ok()? } else { "[::]:0".parse().ok()? };
+    let socket = StdUdpSocket::bind(bind).ok()?;
+    socket.connect(SocketAddr::new(remote, 9)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// IPv4 default gateway from the kernel routing table
+#[cfg(target_os = "linux")]
+pub fn default_gateway_v4() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway_v4() -> Option<Ipv4Addr> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Port mapper answering from canned values
+    struct MockMapper {
+        ip: Option<IpAddr>,
+        granted_port: Option<u16>,
+        adds: Arc<AtomicUsize>,
+        removes: Arc<AtomicUsize>,
+    }
+
+    impl MockMapper {
+        fn working(granted_port: u16) -> Self {
+            Self {
+                ip: Some("203.0.113.7".parse().unwrap()),
+                granted_port: Some(granted_port),
+                adds: Default::default(),
+                removes: Default::default(),
+            }
+        }
+
+        fn failing() -> Self {
+            Self { ip: None, granted_port: None, adds: Default::default(), removes: Default::default() }
+        }
+    }
+
+    #[async_trait]
+    impl PortMapper for MockMapper {
+        fn protocol(&self) -> &'static str {
+            "mock"
+        }
+
+        async fn external_ip(&self) -> Result<IpAddr, String> {
+            self.ip.ok_or_else(|| "no gateway".to_string())
+        }
+
+        async fn add_mapping(&self, _local_port: u16, _external_port: u16, _lease: Duration) -> Result<u16, String> {
+            self.adds.fetch_add(1, Ordering::SeqCst);
+            self.granted_port.ok_or_else(|| "refused".to_string())
+        }
+
+        async fn remove_mapping(&self, _local_port: u16, _external_port: u16) -> Result<(), String> {
+            self.removes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mapping_success_and_removal() {
+        let mapper = MockMapper::working(40000);
+        let (adds, removes) = (mapper.adds.clone(), mapper.removes.clone());
+        let manager = NatManager::new(16111, vec![Box::new(MockMapper::failing()), Box::new(mapper)]);
+
+        let status = manager.start().await;
+        assert!(status.inbound_reachable);
+        assert_eq!(status.external_address, Some("203.0.113.7:40000".parse().unwrap()));
+
+        manager.refresh().await;
+        assert_eq!(adds.load(Ordering::SeqCst), 2);
+
+        manager.stop().await;
+        assert_eq!(removes.load(Ordering::SeqCst), 1);
+        assert_eq!(manager.status(), NatStatus::default());
+    }
+
+    #[tokio::test]
+    async fn test_failure_degrades_to_outbound_only() {
+        let manager = NatManager::new(16111, vec![Box::new(MockMapper::failing())]);
+        let status = manager.start().await;
+        assert!(!status.inbound_reachable);
+        assert!(status.external_address.is_none());
+        assert!(status.last_error.unwrap().contains("mock"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_task_removes_mapping_on_shutdown() {
+        let mapper = MockMapper::working(16111);
+        let removes = mapper.removes.clone();
+        let manager = Arc::new(NatManager::with_lease(16111, vec![Box::new(mapper)], Duration::from_millis(20)));
+        manager.start().await;
+
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let handle = manager.clone().spawn_refresh(Arc::new(Hub::new()), shutdown_rx);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap();
+        assert_eq!(removes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_task_reports_the_refreshed_status() {
+        let manager = Arc::new(NatManager::with_lease(16111, vec![Box::new(MockMapper::working(40000))], Duration::from_millis(20)));
+        let hub = Arc::new(Hub::new());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+        // The first refresh finds no mapping and starts one; the hub reports it
+        let handle = manager.clone().spawn_refresh(hub.clone(), shutdown_rx);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let status = hub.nat_status();
+        assert!(status.inbound_reachable);
+        assert_eq!(status.external_address, Some("203.0.113.7:40000".parse().unwrap()));
+        assert_eq!(status, manager.status());
+
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_nat_pmp_against_mock_gateway() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 16];
+            loop {
+                let (n, from) = gateway.recv_from(&mut buf).await.unwrap();
+                let response: Vec<u8> = match buf[1] {
+                    0 => vec![0, 128, 0, 0, 0, 0, 0, 1, 198, 51, 100, 9],
+                    2 => {
+                        assert_eq!(n, 12);
+                        let mut response = vec![0, 130, 0, 0, 0, 0, 0, 1];
+                        response.extend_from_slice(&buf[4..6]);
+                        // Grant a different external port than suggested
+                        response.extend_from_slice(&41000u16.to_be_bytes());
+                        response.extend_from_slice(&buf[8..12]);
+                        response
+                    }
+                    _ => vec![0, 128 + buf[1], 0, 5],
+                };
+                gateway.send_to(&response, from).await.unwrap();
+            }
+        });
+
+        let manager = NatManager::new(16111, vec![Box::new(NatPmpMapper::new(gateway_addr))]);
+        let status = manager.start().await;
+        assert_eq!(status.protocol, Some("nat-pmp"));
+        assert_eq!(status.external_address, Some("198.51.100.9:41000".parse().unwrap()));
+        manager.stop().await;
+    }
+
+    #[test]
+    fn test_nat_pmp_error_codes() {
+        assert!(parse_nat_pmp_external_address(&[0, 128, 0, 3, 0, 0, 0, 0, 1, 2, 3, 4]).is_err());
+        assert!(parse_nat_pmp_external_address(&[0, 128, 0, 0]).is_err());
+        assert!(parse_nat_pmp_mapping(&[0, 130, 0, 0, 0, 0, 0, 0, 0x3e, 0xef, 0x3e, 0xef, 0, 0, 0, 0], 16111).is_ok());
+        assert!(parse_nat_pmp_mapping(&[0, 130, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0], 16111).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Peer misbehavior scoring and bans
+//!
+//! Each protocol violation adds a penalty to the offending address's score. Once
+//! the score reaches the policy threshold the address is banned for the policy
+//! duration and its score is reset. Scores and bans are kept per IP address so
+//! a banned peer cannot come back from a different source port.
+
+use crate::protowire::FrameError;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Default score at which a peer is banned
+pub const DEFAULT_BAN_THRESHOLD: u32 = 100;
+
+/// Default ban duration
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Protocol violation committed by a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Frame failed authentication or integrity check
+    BadChecksum,
+    /// Block header does not satisfy its proof of work
+    InvalidPow,
+    /// Block failed validation
+    InvalidBlock,
+    /// Frame larger than the protocol allows, or one we never asked for
+    OversizedFrame,
+    /// Frame payload could not be decoded
+    MalformedMessage,
+}
+
+impl Misbehavior {
+    /// Score added for one occurrence
+    pub fn penalty(&self) -> u32 {
+        match self {
+            Misbehavior::BadChecksum => 20,
+            Misbehavior::InvalidPow => 50,
+            Misbehavior::InvalidBlock => 50,
+            Misbehavior::OversizedFrame => 25,
+            Misbehavior::MalformedMessage => 10,
+        }
+    }
+
+    /// Classify an error returned while reading a frame, if it is the peer's fault
+    pub fn from_frame_error(error: &FrameError) -> Option<Self> {
+        match error {
+            FrameError::Io(_) => None,
+            FrameError::TooLarge(_) => Some(Misbehavior::OversizedFrame),
+            FrameError::Decrypt(_) => Some(Misbehavior::BadChecksum),
+            FrameError::Malformed(_) | FrameError::Deserialize(_) => Some(Misbehavior::MalformedMessage),
+        }
+    }
+}
+
+/// When and for how long misbehaving peers are banned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BanPolicy {
+    pub threshold: u32,
+    pub duration: Duration,
+}
+
+impl Default for BanPolicy {
+    fn default() -> Self {
+        Self { threshold: DEFAULT_BAN_THRESHOLD, duration: DEFAULT_BAN_DURATION }
+    }
+}
+
+/// Misbehavior scores and active bans
+#[derive(Debug, Default)]
+pub struct PeerScores {
+    policy: BanPolicy,
+    scores: HashMap<IpAddr, u32>,
+    bans: HashMap<IpAddr, Instant>,
+}
+
+impl PeerScores {
+    pub fn new(policy: BanPolicy) -> Self {
+        Self { policy, scores: HashMap::new(), bans: HashMap::new() }
+    }
+
+    pub fn policy(&self) -> BanPolicy {
+        self.policy
+    }
+
+    /// Add the penalty for `misbehavior` to `ip`. Returns true if this bans the address.
+    pub fn record(&mut self, ip: IpAddr, misbehavior: Misbehavior, now: Instant) -> bool {
+        let score = self.scores.entry(ip).or_insert(0);
+        *score = score.saturating_add(misbehavior.penalty());
+        if *score < self.policy.threshold {
+            return false;
+        }
+        self.scores.remove(&ip);
+        self.bans.insert(ip, now + self.policy.duration);
+        true
+    }
+
+    /// Current score of `ip`
+    pub fn score(&self, ip: &IpAddr) -> u32 {
+        self.scores.get(ip).copied().unwrap_or(0)
+    }
+
+    /// Whether `ip` is banned at `now`; expired bans are dropped
+    pub fn is_banned(&mut self, ip: &IpAddr, now: Instant) -> bool {
+        match self.bans.get(ip) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                self.bans.remove(ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Lift the ban on `ip`. Returns whether it was banned.
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        self.bans.remove(ip).is_some()
+    }
+
+    /// Ban `ip` until `until`, whatever its score
+    pub fn ban(&mut self, ip: IpAddr, until: Instant) {
+        self.scores.remove(&ip);
+        self.bans.insert(ip, until);
+    }
+
+    /// Active bans with the time left on each, by address; expired bans are dropped
+    pub fn bans(&mut self, now: Instant) -> Vec<(IpAddr, Duration)> {
+        self.bans.retain(|_, until| *until > now);
+        let mut bans: Vec<_> = self.bans.iter().map(|(ip, until)| (*ip, *until - now)).collect();
+        bans.sort();
+        bans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    #[test]
+    fn test_ban_after_threshold() {
+        let mut scores = PeerScores::new(BanPolicy { threshold: 100, duration: Duration::from_secs(60) });
+        let now = Instant::now();
+        assert!(!scores.record(ip(1), Misbehavior::InvalidPow, now));
+        assert_eq!(scores.score(&ip(1)), 50);
+        assert!(!scores.is_banned(&ip(1), now));
+
+        assert!(scores.record(ip(1), Misbehavior::InvalidPow, now));
+        assert!(scores.is_banned(&ip(1), now));
+        assert_eq!(scores.score(&ip(1)), 0);
+        assert!(!scores.is_banned(&ip(2), now));
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let mut scores = PeerScores::new(BanPolicy { threshold: 10, duration: Duration::from_secs(60) });
+        let now = Instant::now();
+        assert!(scores.record(ip(1), Misbehavior::MalformedMessage, now));
+        assert!(scores.is_banned(&ip(1), now + Duration::from_secs(59)));
+        assert!(!scores.is_banned(&ip(1), now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_unban() {
+        let mut scores = PeerScores::new(BanPolicy { threshold: 10, duration: Duration::from_secs(60) });
+        let now = Instant::now();
+        scores.record(ip(1), Misbehavior::BadChecksum, now);
+        assert!(scores.unban(&ip(1)));
+        assert!(!scores.is_banned(&ip(1), now));
+        assert!(!scores.unban(&ip(1)));
+    }
+
+    #[test]
+    fn test_manual_ban() {
+        let mut scores = PeerScores::new(BanPolicy { threshold: 100, duration: Duration::from_secs(60) });
+        let now = Instant::now();
+        scores.ban(ip(2), now + Duration::from_secs(10));
+        scores.ban(ip(1), now + Duration::from_secs(300));
+        assert!(scores.is_banned(&ip(1), now + Duration::from_secs(200)));
+        assert_eq!(scores.bans(now), vec![(ip(1), Duration::from_secs(300)), (ip(2), Duration::from_secs(10))]);
+        assert_eq!(scores.bans(now + Duration::from_secs(10)), vec![(ip(1), Duration::from_secs(290))]);
+    }
+
+    #[test]
+    fn test_frame_error_classification() {
+        let classify = |error: FrameError| Misbehavior::from_frame_error(&error);
+        assert_eq!(classify(FrameError::TooLarge(1 << 30)), Some(Misbehavior::OversizedFrame));
+        assert_eq!(classify(FrameError::Decrypt("bad tag".into())), Some(Misbehavior::BadChecksum));
+        assert_eq!(classify(FrameError::Deserialize("eof".into())), Some(Misbehavior::MalformedMessage));
+        assert_eq!(classify(FrameError::Malformed("record too short")), Some(Misbehavior::MalformedMessage));
+        // Whatever the message, a failing stream is not the peer's doing
+        assert_eq!(classify(FrameError::Io("frame too large".into())), None);
+    }
+}
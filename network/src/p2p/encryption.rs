@@ -0,0 +1,581 @@
+//! Encrypted peer transport.
+//!
+//! Right after TCP connect both sides exchange a plaintext `Version` message that
+//! advertises encryption support. When both sides support it, a Noise_XX handshake
+//! (X25519, ChaCha20-Poly1305, BLAKE2s) derives fresh session keys and every
+//! following protowire frame is carried inside authenticated records. A record
+//! that fails authentication is a fatal error and the connection must be dropped.
+
+use crate::protowire::{self, FrameError, Message, VersionMessage, MAX_FRAME_SIZE};
+use snow::{Builder, HandshakeState, TransportState};
+use parking_lot::Mutex;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+/// Noise protocol pattern used for peer connections
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Maximum size of a single Noise message (handshake or transport record)
+const MAX_NOISE_MESSAGE: usize = 65535;
+/// Size of the ChaCha20-Poly1305 authentication tag
+const TAG_LEN: usize = 16;
+/// Maximum plaintext carried by a single encrypted record
+const MAX_RECORD_PLAINTEXT: usize = MAX_NOISE_MESSAGE - TAG_LEN;
+
+/// Encryption settings for peer connections
+#[derive(Debug, Clone)]
+pub struct EncryptionConfig {
+    /// Advertise and use encrypted transport when the remote supports it
+    pub enabled: bool,
+    /// Fall back to plaintext when the remote does not support encryption
+    pub allow_plaintext: bool,
+    /// Persistent identity used as the Noise static key. A fresh key is generated
+    /// per connection when unset.
+    pub identity: Option<NodeIdentity>,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self { enabled: false, allow_plaintext: true, identity: None }
+    }
+}
+
+/// X25519 static key pair identifying this node to authenticated peers
+#[derive(Clone)]
+pub struct NodeIdentity {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl NodeIdentity {
+    /// Generate a new random identity
+    pub fn generate() -> Result<Self, String> {
+        let keypair = Builder::new(noise_params()?).generate_keypair().map_err(|e| format!("keygen: {}", e))?;
+        Ok(Self { private: keypair.private, public: keypair.public })
+    }
+
+    /// Load the identity stored at `path`, creating and persisting a new one if missing
+    pub fn load_or_generate(path: &Path) -> Result<Self, String> {
+        if path.exists() {
+            let content = fs::read_to_string(path).map_err(|e| format!("Failed to read identity key: {}", e))?;
+            let bytes = hex::decode(content.trim()).map_err(|e| format!("Invalid identity key: {}", e))?;
+            if bytes.len() != 64 {
+                return Err("Invalid identity key length".to_string());
+            }
+            return Ok(Self { private: bytes[..32].to_vec(), public: bytes[32..].to_vec() });
+        }
+
+        let identity = Self::generate()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create identity directory: {}", e))?;
+        }
+        let mut bytes = identity.private.clone();
+        bytes.extend_from_slice(&identity.public);
+        // Only the owner may read the private key
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).map_err(|e| format!("Failed to create identity key: {}", e))?;
+        std::io::Write::write_all(&mut file, hex::encode(bytes).as_bytes()).map_err(|e| format!("Failed to write identity key: {}", e))?;
+        Ok(identity)
+    }
+
+    /// Public half of the identity, as seen by remote peers
+    pub fn public_key(&self) -> &[u8] {
+        &self.public
+    }
+}
+
+impl std::fmt::Debug for NodeIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeIdentity").field("public", &hex::encode(&self.public)).finish()
+    }
+}
+
+/// Side of the connection, which decides the handshake message order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+fn noise_params() -> Result<snow::params::NoiseParams, String> {
+    NOISE_PARAMS.parse().map_err(|e| format!("noise params: {:?}", e))
+}
+
+/// Stream carrying protowire frames inside Noise transport records. The Noise
+/// state is shared so the stream can be split into a reading and a writing half;
+/// Noise keeps separate keys and nonces per direction.
+pub struct SecureStream<S> {
+    inner: S,
+    noise: Arc<Mutex<TransportState>>,
+    remote_static: Option<Vec<u8>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SecureStream<S> {
+    /// Run the Noise_XX handshake over `inner`
+    pub async fn handshake(mut inner: S, role: Role, identity: Option<&NodeIdentity>) -> Result<Self, String> {
+        // Without a persistent identity the static key is as ephemeral as the connection
+        let ephemeral;
+        let identity = match identity {
+            Some(identity) => identity,
+            None => {
+                ephemeral = NodeIdentity::generate()?;
+                &ephemeral
+            }
+        };
+
+        let builder = Builder::new(noise_params()?).local_private_key(&identity.private);
+        let mut state = match role {
+            Role::Initiator => builder.build_initiator(),
+            Role::Responder => builder.build_responder(),
+        }
+        .map_err(|e| format!("noise init: {}", e))?;
+
+        // XX: -> e, <- e ee s es, -> s se
+        let mut writes_next = role == Role::Initiator;
+        while !state.is_handshake_finished() {
+            if writes_next {
+                write_handshake_message(&mut inner, &mut state).await?;
+            } else {
+                read_handshake_message(&mut inner, &mut state).await?;
+            }
+            writes_next = !writes_next;
+        }
+
+        let noise = state.into_transport_mode().map_err(|e| format!("noise transport: {}", e))?;
+        let remote_static = noise.get_remote_static().map(<[u8]>::to_vec);
+        Ok(Self { inner, noise: Arc::new(Mutex::new(noise)), remote_static })
+    }
+
+    /// Split into a half that reads frames and one that writes them
+    pub fn split(self) -> (SecureStream<ReadHalf<S>>, SecureStream<WriteHalf<S>>) {
+        let (reader, writer) = tokio::io::split(self.inner);
+        let reader = SecureStream { inner: reader, noise: self.noise.clone(), remote_static: self.remote_static.clone() };
+        (reader, SecureStream { inner: writer, noise: self.noise, remote_static: self.remote_static })
+    }
+}
+
+impl<S> SecureStream<S> {
+    /// Static public key presented by the remote peer during the handshake
+    pub fn remote_static(&self) -> Option<&[u8]> {
+        self.remote_static.as_deref()
+    }
+}
+
+impl<S: AsyncWrite + Unpin> SecureStream<S> {
+    /// Encrypt and send a protowire frame, returning the number of bytes
+    /// written: record headers and ciphertext
+    pub async fn write_frame(&mut self, msg: &Message) -> Result<u64, String> {
+        let payload = protowire::encode_message(msg)?;
+        let mut plaintext = Vec::with_capacity(4 + payload.len());
+        plaintext.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        plaintext.extend_from_slice(&payload);
+
+        let mut record = vec![0u8; 2 + MAX_NOISE_MESSAGE];
+        let mut written = 0;
+        for chunk in plaintext.chunks(MAX_RECORD_PLAINTEXT) {
+            let len = self.noise.lock().write_message(chunk, &mut record[2..]).map_err(|e| format!("encrypt: {}", e))?;
+            record[..2].copy_from_slice(&(len as u16).to_be_bytes());
+            // Header and ciphertext go out in a single write so records are never split by us
+            self.inner.write_all(&record[..2 + len]).await.map_err(|e| e.to_string())?;
+            written += 2 + len as u64;
+        }
+        self.inner.flush().await.map_err(|e| e.to_string())?;
+        Ok(written)
+    }
+}
+
+impl<S: AsyncRead + Unpin> SecureStream<S> {
+    /// Receive and decrypt a protowire frame. Any authentication failure is returned
+    /// as an error and the stream must not be used afterwards.
+    pub async fn read_frame(&mut self) -> Result<Message, FrameError> {
+        self.read_frame_counted().await.map(|(msg, _)| msg)
+    }
+
+    /// `read_frame`, also returning the number of bytes read: record headers
+    /// and ciphertext
+    pub async fn read_frame_counted(&mut self) -> Result<(Message, u64), FrameError> {
+        let mut read = 0;
+        let first = self.read_record(&mut read).await?;
+        if first.len() < 4 {
+            return Err(FrameError::Malformed("truncated frame header"));
+        }
+        let len = u32::from_le_bytes([first[0], first[1], first[2], first[3]]) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Err(FrameError::TooLarge(len));
+        }
+
+        let mut payload = Vec::with_capacity(len);
+        payload.extend_from_slice(&first[4..]);
+        while payload.len() < len {
+            let record = self.read_record(&mut read).await?;
+            payload.extend_from_slice(&record);
+        }
+        if payload.len() != len {
+            return Err(FrameError::Malformed("frame length mismatch"));
+        }
+        Ok((protowire::decode_message(&payload)?, read))
+    }
+
+    /// Read and decrypt one record, adding its size on the wire to `read`
+    async fn read_record(&mut self, read: &mut u64) -> Result<Vec<u8>, FrameError> {
+        let len = self.inner.read_u16().await? as usize;
+        if len < TAG_LEN {
+            return Err(FrameError::Malformed("record too short"));
+        }
+        let mut ciphertext = vec![0u8; len];
+        self.inner.read_exact(&mut ciphertext).await?;
+        *read += 2 + len as u64;
+        let mut plaintext = vec![0u8; len];
+        let n = self.noise.lock().read_message(&ciphertext, &mut plaintext).map_err(|e| FrameError::Decrypt(e.to_string()))?;
+        plaintext.truncate(n);
+        Ok(plaintext)
+    }
+}
+
+async fn write_handshake_message<S: AsyncWrite + Unpin>(stream: &mut S, state: &mut HandshakeState) -> Result<(), String> {
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+    let len = state.write_message(&[], &mut buf).map_err(|e| format!("handshake: {}", e))?;
+    stream.write_u16(len as u16).await.map_err(|e| e.to_string())?;
+    stream.write_all(&buf[..len]).await.map_err(|e| e.to_string())?;
+    stream.flush().await.map_err(|e| e.to_string())
+}
+
+async fn read_handshake_message<S: AsyncRead + Unpin>(stream: &mut S, state: &mut HandshakeState) -> Result<(), String> {
+    let len = stream.read_u16().await.map_err(|e| e.to_string())? as usize;
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message).await.map_err(|e| e.to_string())?;
+    let mut payload = vec![0u8; MAX_NOISE_MESSAGE];
+    state.read_message(&message, &mut payload).map_err(|e| format!("handshake: {}", e))?;
+    Ok(())
+}
+
+/// Negotiated peer transport, either plaintext or encrypted
+pub enum Transport<S> {
+    Plain(S),
+    Encrypted(SecureStream<S>),
+}
+
+impl<S> Transport<S> {
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Transport::Encrypted(_))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Transport<S> {
+    /// Split into a half that reads frames and one that writes them, so a
+    /// connection can read and write from separate tasks
+    pub fn split(self) -> (Transport<ReadHalf<S>>, Transport<WriteHalf<S>>) {
+        match self {
+            Transport::Plain(stream) => {
+                let (reader, writer) = tokio::io::split(stream);
+                (Transport::Plain(reader), Transport::Plain(writer))
+            }
+            Transport::Encrypted(stream) => {
+                let (reader, writer) = stream.split();
+                (Transport::Encrypted(reader), Transport::Encrypted(writer))
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Transport<S> {
+    /// Write `msg`, returning the number of bytes put on the wire
+    pub async fn write_frame(&mut self, msg: &Message) -> Result<u64, String> {
+        match self {
+            Transport::Plain(stream) => protowire::write_frame(stream, msg).await,
+            Transport::Encrypted(stream) => stream.write_frame(msg).await,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> Transport<S> {
+    pub async fn read_frame(&mut self) -> Result<Message, FrameError> {
+        self.read_frame_counted().await.map(|(msg, _)| msg)
+    }
+
+    /// Read a message, also returning the number of bytes taken off the wire
+    pub async fn read_frame_counted(&mut self) -> Result<(Message, u64), FrameError> {
+        match self {
+            Transport::Plain(stream) => protowire::read_frame_counted(stream).await,
+            Transport::Encrypted(stream) => stream.read_frame_counted().await,
+        }
+    }
+}
+
+/// Why `negotiate` failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// The remote's version frame could not be read
+    Frame(FrameError),
+    /// Writing our version failed, the remote's was unacceptable or the
+    /// encryption handshake failed
+    Failed(String),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Frame(e) => write!(f, "{}", e),
+            HandshakeError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+impl From<FrameError> for HandshakeError {
+    fn from(e: FrameError) -> Self {
+        HandshakeError::Frame(e)
+    }
+}
+
+impl From<String> for HandshakeError {
+    fn from(e: String) -> Self {
+        HandshakeError::Failed(e)
+    }
+}
+
+impl From<HandshakeError> for String {
+    fn from(e: HandshakeError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Exchange version messages over a freshly connected stream and upgrade it to an
+/// encrypted transport when both sides support it. Returns the negotiated transport
+/// and the remote's version message.
+pub async fn negotiate<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    role: Role,
+    config: &EncryptionConfig,
+    mut local: VersionMessage,
+) -> Result<(Transport<S>, VersionMessage), HandshakeError> {
+    local.supports_encryption = config.enabled;
+    let params_hash = local.params_hash;
+    protowire::write_frame(&mut stream, &Message::Version(local)).await?;
+    let remote = match protowire::read_frame(&mut stream).await? {
+        Message::Version(version) => version,
+        other => return Err(format!("expected version message, got {:?}", other).into()),
+    };
+    if remote.params_hash != params_hash {
+        return Err(format!("peer runs consensus params {}, ours are {}", remote.params_hash, params_hash).into());
+    }
+
+    if config.enabled && remote.supports_encryption {
+        let secure = SecureStream::handshake(stream, role, config.identity.as_ref()).await?;
+        return Ok((Transport::Encrypted(secure), remote));
+    }
+
+    if config.enabled && !config.allow_plaintext {
+        return Err("peer does not support encryption and plaintext is not allowed".to_string().into());
+    }
+
+    Ok((Transport::Plain(stream), remote))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{duplex, AsyncRead, AsyncWrite};
+
+    fn config(enabled: bool, allow_plaintext: bool) -> EncryptionConfig {
+        EncryptionConfig { enabled, allow_plaintext, identity: None }
+    }
+
+    fn version() -> VersionMessage {
+        VersionMessage::new("test", false)
+    }
+
+    /// Forward bytes from `from` to `to`, flipping one bit of the first chunk read after `flip` is set
+    async fn forward<R, W>(mut from: R, mut to: W, flip: Arc<AtomicBool>)
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let n = match from.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            if flip.swap(false, Ordering::SeqCst) {
+                buf[n - 1] ^= 0x01;
+            }
+            if to.write_all(&buf[..n]).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_exchange() {
+        let (a, b) = duplex(1 << 20);
+        let enabled = config(true, false);
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &enabled, version()),
+            negotiate(b, Role::Responder, &enabled, version())
+        );
+        let (mut left, remote) = left.unwrap();
+        let (mut right, _) = right.unwrap();
+        assert!(remote.supports_encryption);
+        assert!(left.is_encrypted() && right.is_encrypted());
+
+        left.write_frame(&Message::Ping { nonce: 7 }).await.unwrap();
+        match right.read_frame().await.unwrap() {
+            Message::Ping { nonce } => assert_eq!(nonce, 7),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // Frames larger than a single record are split and reassembled
+        let hashes = vec![consensus_core::ZERO_HASH; 5000];
+        right.write_frame(&Message::InvBlock { hashes: hashes.clone() }).await.unwrap();
+        match left.read_frame().await.unwrap() {
+            Message::InvBlock { hashes: received } => assert_eq!(received.len(), hashes.len()),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_halves_read_and_write_concurrently() {
+        let (a, b) = duplex(1 << 20);
+        let enabled = config(true, false);
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &enabled, version()),
+            negotiate(b, Role::Responder, &enabled, version())
+        );
+        let (mut left_reader, mut left_writer) = left.unwrap().0.split();
+        let (mut right_reader, mut right_writer) = right.unwrap().0.split();
+
+        // Both directions at once, each message sharing the one Noise state per side
+        let left_to_right = async {
+            for nonce in 0..50 {
+                left_writer.write_frame(&Message::Ping { nonce }).await.unwrap();
+            }
+        };
+        let right_to_left = async {
+            for nonce in 0..50 {
+                right_writer.write_frame(&Message::Pong { nonce }).await.unwrap();
+            }
+        };
+        let read_right = async {
+            for expected in 0..50 {
+                assert!(matches!(right_reader.read_frame().await.unwrap(), Message::Ping { nonce } if nonce == expected));
+            }
+        };
+        let read_left = async {
+            for expected in 0..50 {
+                assert!(matches!(left_reader.read_frame().await.unwrap(), Message::Pong { nonce } if nonce == expected));
+            }
+        };
+        tokio::join!(left_to_right, right_to_left, read_right, read_left);
+        assert!(left_reader.is_encrypted() && right_writer.is_encrypted());
+    }
+
+    #[tokio::test]
+    async fn test_persistent_identity_is_authenticated() {
+        let identity = NodeIdentity::generate().unwrap();
+        let (a, b) = duplex(1 << 16);
+        let (left, right) = tokio::join!(
+            SecureStream::handshake(a, Role::Initiator, Some(&identity)),
+            SecureStream::handshake(b, Role::Responder, None)
+        );
+        let (left, right) = (left.unwrap(), right.unwrap());
+        assert_eq!(right.remote_static(), Some(identity.public_key()));
+        assert_ne!(left.remote_static(), Some(identity.public_key()));
+    }
+
+    #[test]
+    fn test_identity_is_persisted() {
+        let path = std::env::temp_dir().join(format!("jio-identity-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let identity = NodeIdentity::load_or_generate(&path).unwrap();
+        assert_eq!(NodeIdentity::load_or_generate(&path).unwrap().public_key(), identity.public_key());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_fallback() {
+        let (a, b) = duplex(1 << 16);
+        let (optional, disabled) = (config(true, true), config(false, true));
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &optional, version()),
+            negotiate(b, Role::Responder, &disabled, version())
+        );
+        let (mut left, _) = left.unwrap();
+        let (mut right, _) = right.unwrap();
+        assert!(!left.is_encrypted() && !right.is_encrypted());
+
+        left.write_frame(&Message::Pong { nonce: 1 }).await.unwrap();
+        assert!(matches!(right.read_frame().await.unwrap(), Message::Pong { nonce: 1 }));
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_refused_when_required() {
+        let (a, b) = duplex(1 << 16);
+        let (required, disabled) = (config(true, false), config(false, true));
+        let (left, _right) = tokio::join!(
+            negotiate(a, Role::Initiator, &required, version()),
+            negotiate(b, Role::Responder, &disabled, version())
+        );
+        assert!(left.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_params_refused() {
+        let (a, b) = duplex(1 << 16);
+        let plain = config(false, true);
+        let ours = consensus_core::Hash::from_u64_word(1);
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &plain, version().with_params_hash(ours)),
+            negotiate(b, Role::Responder, &plain, version().with_params_hash(consensus_core::Hash::from_u64_word(2)))
+        );
+        assert!(left.err().unwrap().to_string().contains("consensus params"));
+        assert!(right.is_err());
+
+        let (a, b) = duplex(1 << 16);
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &plain, version().with_params_hash(ours)),
+            negotiate(b, Role::Responder, &plain, version().with_params_hash(ours))
+        );
+        assert_eq!(left.ok().unwrap().1.params_hash, ours);
+        assert!(right.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bit_flip_disconnects() {
+        let (a, a_mid) = duplex(1 << 16);
+        let (b_mid, b) = duplex(1 << 16);
+        let (a_mid_read, a_mid_write) = tokio::io::split(a_mid);
+        let (b_mid_read, b_mid_write) = tokio::io::split(b_mid);
+        let flip = Arc::new(AtomicBool::new(false));
+        tokio::spawn(forward(a_mid_read, b_mid_write, flip.clone()));
+        tokio::spawn(forward(b_mid_read, a_mid_write, Arc::new(AtomicBool::new(false))));
+
+        let enabled = config(true, false);
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &enabled, version()),
+            negotiate(b, Role::Responder, &enabled, version())
+        );
+        let (mut left, _) = left.unwrap();
+        let (mut right, _) = right.unwrap();
+
+        flip.store(true, Ordering::SeqCst);
+        left.write_frame(&Message::Ping { nonce: 42 }).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), right.read_frame()).await.expect("read timed out");
+        assert!(matches!(result, Err(FrameError::Decrypt(_))));
+    }
+}
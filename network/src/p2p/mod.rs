@@ -1,3 +1,9 @@
+pub mod ban;
+pub mod encryption;
 pub mod peer;
+pub mod rate_limit;
 
-pub use peer::Peer;
+pub use ban::{BanPolicy, Misbehavior, PeerScores};
+pub use encryption::{negotiate, EncryptionConfig, HandshakeError, NodeIdentity, Role, SecureStream, Transport};
+pub use peer::{KeepaliveConfig, Peer, PeerDirection, PeerInfo};
+pub use rate_limit::{MessageClass, RateLimit, RateLimits};
@@ -1,7 +1,16 @@
+use std::fmt;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use crate::protowire::Message;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::inventory::{InventoryItem, KnownInventory, INV_BATCH_SIZE};
+use crate::protowire::{self, ChainTip, Message, VersionMessage};
+use crate::p2p::rate_limit::{MessageClass, RateLimiter, RateLimits, TokenBucket};
+use crate::p2p::Transport;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 #[derive(Debug)]
 pub enum PeerState {
@@ -11,19 +20,494 @@ pub enum PeerState {
     Disconnected,
 }
 
-#[derive(Clone)]
+/// Which side opened the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerDirection {
+    /// The peer connected to us
+    Inbound,
+    /// We connected to the peer
+    Outbound,
+}
+
+impl fmt::Display for PeerDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerDirection::Inbound => write!(f, "inbound"),
+            PeerDirection::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+/// Snapshot of a connected peer
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    pub id: String,
+    pub address: SocketAddr,
+    pub direction: PeerDirection,
+    pub connected_duration: Duration,
+    /// Time since the peer last sent a frame, or since it connected
+    pub last_seen: Duration,
+    /// Round trip time of the last answered ping
+    pub last_ping_ms: Option<u64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub protocol_version: u32,
+    pub user_agent: String,
+    pub advertised_blue_score: u64,
+    /// Publicly reachable address the peer advertised, if it mapped one through its NAT
+    pub advertised_address: Option<SocketAddr>,
+    /// Whether the peer advertised that it keeps all block bodies
+    pub archival: bool,
+    pub ban_score: u32,
+}
+
+fn serve_budget(limits: RateLimits) -> TokenBucket {
+    TokenBucket::new(limits.blocks.bytes_per_sec as f64, Instant::now())
+}
+
+/// Sentinel for "no ping answered yet"
+const NO_PING: u64 = u64::MAX;
+
+/// Timeouts that drop silent and unresponsive peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Longest wait for the next frame, or the rest of a started one
+    pub read_timeout: Duration,
+    /// Time between keepalive pings
+    pub ping_interval: Duration,
+    /// Time a peer has to answer a ping
+    pub pong_timeout: Duration,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            read_timeout: Duration::from_secs(120),
+            ping_interval: Duration::from_secs(60),
+            pong_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
 pub struct Peer {
     pub id: String,
     pub address: SocketAddr,
     pub tx: mpsc::Sender<Message>,
+    pub direction: PeerDirection,
+    connected_at: Instant,
+    /// When the peer's last frame was read
+    last_received: parking_lot::Mutex<Instant>,
+    /// Version message the peer sent during the handshake
+    version: Option<VersionMessage>,
+    /// Selected tip the peer last advertised
+    tip: parking_lot::Mutex<ChainTip>,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    last_ping_ms: AtomicU64,
+    pending_ping: parking_lot::Mutex<Option<(u64, Instant)>>,
+    connected: AtomicBool,
+    /// Inventory the peer has, so it is never announced to it again
+    known_inventory: parking_lot::Mutex<KnownInventory>,
+    /// Announcements waiting for the next batch
+    pending_inventory: parking_lot::Mutex<Vec<InventoryItem>>,
+    /// Limits on what the peer may send us
+    rate_limiter: parking_lot::Mutex<RateLimiter>,
+    /// Bytes of blocks we may still serve the peer
+    serve_budget: parking_lot::Mutex<TokenBucket>,
+    keepalive: KeepaliveConfig,
 }
 
 impl Peer {
     pub fn new(id: String, address: SocketAddr, tx: mpsc::Sender<Message>) -> Self {
-        Self { id, address, tx }
+        Self {
+            id,
+            address,
+            tx,
+            direction: PeerDirection::Outbound,
+            connected_at: Instant::now(),
+            last_received: parking_lot::Mutex::new(Instant::now()),
+            version: None,
+            tip: Default::default(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            last_ping_ms: AtomicU64::new(NO_PING),
+            pending_ping: parking_lot::Mutex::new(None),
+            connected: AtomicBool::new(true),
+            known_inventory: Default::default(),
+            pending_inventory: Default::default(),
+            rate_limiter: parking_lot::Mutex::new(RateLimiter::new(RateLimits::default(), Instant::now())),
+            serve_budget: parking_lot::Mutex::new(serve_budget(RateLimits::default())),
+            keepalive: KeepaliveConfig::default(),
+        }
+    }
+
+    /// Peer that completed the version handshake, sending `remote`
+    pub fn from_handshake(
+        id: String,
+        address: SocketAddr,
+        direction: PeerDirection,
+        remote: VersionMessage,
+        tx: mpsc::Sender<Message>,
+    ) -> Self {
+        Self { direction, tip: parking_lot::Mutex::new(remote.tip()), version: Some(remote), ..Self::new(id, address, tx) }
+    }
+
+    /// Limit the peer's traffic to `limits` instead of the defaults
+    pub fn with_rate_limits(self, limits: RateLimits) -> Self {
+        Self {
+            rate_limiter: parking_lot::Mutex::new(RateLimiter::new(limits, Instant::now())),
+            serve_budget: parking_lot::Mutex::new(serve_budget(limits)),
+            ..self
+        }
+    }
+
+    /// Use `keepalive` timeouts instead of the defaults
+    pub fn with_keepalive(self, keepalive: KeepaliveConfig) -> Self {
+        Self { keepalive, ..self }
+    }
+
+    pub fn keepalive(&self) -> KeepaliveConfig {
+        self.keepalive
     }
 
     pub async fn send_message(&self, msg: Message) -> Result<(), String> {
         self.tx.send(msg).await.map_err(|e| format!("send failed: {}", e))
     }
+
+    /// Queue `msg` without waiting; fails if the peer's queue is full
+    pub fn try_send_message(&self, msg: Message) -> Result<(), String> {
+        self.tx.try_send(msg).map_err(|e| format!("send failed: {}", e))
+    }
+
+    /// Node instance nonce from the peer's version message, if it sent one
+    pub fn nonce(&self) -> Option<u64> {
+        self.version.as_ref().map(|v| v.nonce).filter(|nonce| *nonce != 0)
+    }
+
+    /// Time the peer reported in its version message, if it did
+    pub fn timestamp(&self) -> Option<u64> {
+        self.version.as_ref().map(|v| v.timestamp).filter(|timestamp| *timestamp != 0)
+    }
+
+    /// Selected tip the peer advertised in its version message or since
+    pub fn tip(&self) -> ChainTip {
+        *self.tip.lock()
+    }
+
+    /// Listen address the peer advertised in its version message
+    pub fn advertised_address(&self) -> Option<SocketAddr> {
+        self.version.as_ref().and_then(|v| v.address)
+    }
+
+    pub fn connected_duration(&self) -> Duration {
+        self.connected_at.elapsed()
+    }
+
+    /// False once the connection failed; the hub drops such peers
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_disconnected(&self) {
+        self.connected.store(false, Ordering::Relaxed);
+    }
+
+    /// Remember that the peer has `item`. Returns false if that was already known.
+    pub fn mark_known(&self, item: InventoryItem) -> bool {
+        self.known_inventory.lock().insert(item)
+    }
+
+    pub fn knows(&self, item: &InventoryItem) -> bool {
+        self.known_inventory.lock().contains(item)
+    }
+
+    /// Queue an announcement of `item` unless the peer already has it.
+    /// Returns true once the batch is full and should be sent.
+    pub fn queue_inventory(&self, item: InventoryItem) -> bool {
+        if !self.mark_known(item) {
+            return false;
+        }
+        let mut pending = self.pending_inventory.lock();
+        pending.push(item);
+        pending.len() >= INV_BATCH_SIZE
+    }
+
+    /// Take `bytes` from the budget for blocks served to the peer, which refills
+    /// at the rate the peer may send us blocks. Returns false if it is spent.
+    pub fn try_serve(&self, bytes: u64) -> bool {
+        self.serve_budget.lock().try_take(bytes as f64, Instant::now())
+    }
+
+    /// Take the queued announcements
+    pub fn take_inventory(&self) -> Vec<InventoryItem> {
+        std::mem::take(&mut *self.pending_inventory.lock())
+    }
+
+    /// Forward messages queued through `tx` to `transport` until the peer is dropped
+    /// or a write fails. The task only keeps a weak reference, so dropping the last
+    /// `Arc<Peer>` closes the queue and with it the connection.
+    pub fn spawn_writer<S>(self: &Arc<Self>, mut transport: Transport<S>, mut outbox: mpsc::Receiver<Message>) -> JoinHandle<()>
+    where
+        S: AsyncWrite + Unpin + Send + 'static,
+    {
+        let peer = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while let Some(msg) = outbox.recv().await {
+                let Some(peer) = peer.upgrade() else { break };
+                if let Err(e) = peer.write_frame(&mut transport, &msg).await {
+                    tracing::debug!("Write to {} failed: {}", peer.address, e);
+                    peer.mark_disconnected();
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Write `msg` to the peer's transport, counting the bytes put on the wire
+    /// as sent
+    pub async fn write_frame<S: AsyncWrite + Unpin>(
+        &self,
+        transport: &mut Transport<S>,
+        msg: &Message,
+    ) -> Result<(), String> {
+        let written = transport.write_frame(msg).await?;
+        self.bytes_sent.fetch_add(written, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Read the next frame from the peer's transport, counting it as received.
+    /// A peer that stays silent for the read timeout or exceeds its rate limits
+    /// is marked disconnected and an error is returned. Pings are answered,
+    /// pongs to our last ping update the round trip time and tip announcements
+    /// update the peer's tip.
+    pub async fn read_frame<S: AsyncRead + AsyncWrite + Unpin>(&self, transport: &mut Transport<S>) -> Result<Message, String> {
+        let (msg, read) = match tokio::time::timeout(self.keepalive.read_timeout, transport.read_frame_counted()).await {
+            Ok(frame) => frame?,
+            Err(_) => {
+                self.mark_disconnected();
+                return Err(format!("read timed out after {:?}", self.keepalive.read_timeout));
+            }
+        };
+        self.record_received(&msg, read)?;
+        if let Message::Ping { nonce } = &msg {
+            self.write_frame(transport, &Message::Pong { nonce: *nonce }).await?;
+        }
+        Ok(msg)
+    }
+
+    /// Account for `msg`, read from the peer in `read` bytes, as `read_frame`
+    /// does, except that pings are left for the caller to answer. Rate limits
+    /// apply to the frame length, whatever the transport.
+    pub fn record_received(&self, msg: &Message, read: u64) -> Result<(), String> {
+        self.bytes_received.fetch_add(read, Ordering::Relaxed);
+        let len = protowire::frame_len(msg)?;
+        *self.last_received.lock() = Instant::now();
+        if let Err(e) = self.rate_limiter.lock().check(MessageClass::of(msg), len, Instant::now()) {
+            self.mark_disconnected();
+            return Err(e);
+        }
+        match msg {
+            Message::Pong { nonce } => {
+                let mut pending = self.pending_ping.lock();
+                if let Some((sent_nonce, sent_at)) = *pending {
+                    if sent_nonce == *nonce {
+                        self.last_ping_ms.store(sent_at.elapsed().as_millis() as u64, Ordering::Relaxed);
+                        *pending = None;
+                    }
+                }
+            }
+            Message::Tip(tip) => *self.tip.lock() = *tip,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Send a keepalive ping; the round trip is measured when the pong is read
+    pub async fn ping<S: AsyncRead + AsyncWrite + Unpin>(&self, transport: &mut Transport<S>, nonce: u64) -> Result<(), String> {
+        *self.pending_ping.lock() = Some((nonce, Instant::now()));
+        self.write_frame(transport, &Message::Ping { nonce }).await
+    }
+
+    /// Nonce of the ping still waiting for its pong
+    fn pending_ping_nonce(&self) -> Option<u64> {
+        self.pending_ping.lock().map(|(nonce, _)| nonce)
+    }
+
+    /// Ping the peer through its queue every ping interval and mark it
+    /// disconnected if a ping goes unanswered for the pong timeout. Pongs are
+    /// matched by whoever reads the peer's frames with `read_frame`. Like the
+    /// writer, the task only keeps a weak reference and ends with the peer.
+    pub fn spawn_keepalive(self: &Arc<Self>) -> JoinHandle<()> {
+        let peer = Arc::downgrade(self);
+        let KeepaliveConfig { ping_interval, pong_timeout, .. } = self.keepalive;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(ping_interval.saturating_sub(pong_timeout)).await;
+                let nonce = rand::random::<u64>();
+                {
+                    let Some(peer) = peer.upgrade() else { break };
+                    if !peer.is_connected() {
+                        break;
+                    }
+                    *peer.pending_ping.lock() = Some((nonce, Instant::now()));
+                    if let Err(e) = peer.try_send_message(Message::Ping { nonce }) {
+                        tracing::debug!("Ping to {} failed: {}", peer.address, e);
+                        peer.mark_disconnected();
+                        break;
+                    }
+                }
+
+                tokio::time::sleep(pong_timeout).await;
+                let Some(peer) = peer.upgrade() else { break };
+                if peer.pending_ping_nonce() == Some(nonce) {
+                    tracing::debug!("{} did not answer ping within {:?}", peer.address, pong_timeout);
+                    peer.mark_disconnected();
+                    break;
+                }
+            }
+        })
+    }
+
+    pub fn info(&self, ban_score: u32) -> PeerInfo {
+        let last_ping_ms = self.last_ping_ms.load(Ordering::Relaxed);
+        PeerInfo {
+            id: self.id.clone(),
+            address: self.address,
+            direction: self.direction,
+            connected_duration: self.connected_duration(),
+            last_seen: self.last_received.lock().elapsed(),
+            last_ping_ms: (last_ping_ms != NO_PING).then_some(last_ping_ms),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            protocol_version: self.version.as_ref().map_or(0, |v| v.protocol_version),
+            user_agent: self.version.as_ref().map(|v| v.user_agent.clone()).unwrap_or_default(),
+            advertised_blue_score: self.tip().blue_score,
+            advertised_address: self.version.as_ref().and_then(|v| v.address),
+            archival: self.version.as_ref().is_some_and(|v| v.archival),
+            ban_score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::encryption::{negotiate, EncryptionConfig, Role};
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    fn keepalive() -> KeepaliveConfig {
+        KeepaliveConfig {
+            read_timeout: Duration::from_millis(200),
+            ping_interval: Duration::from_millis(100),
+            pong_timeout: Duration::from_millis(50),
+        }
+    }
+
+    fn peer() -> (Arc<Peer>, mpsc::Receiver<Message>) {
+        let (tx, outbox) = mpsc::channel(8);
+        let address = SocketAddr::from(([127, 0, 0, 1], 16111));
+        (Arc::new(Peer::new("remote".into(), address, tx).with_keepalive(keepalive())), outbox)
+    }
+
+    #[tokio::test]
+    async fn test_counters_count_encrypted_bytes_on_the_wire() {
+        let (a, b) = duplex(1 << 16);
+        let config = EncryptionConfig { enabled: true, allow_plaintext: false, identity: None };
+        let (left, right) = tokio::join!(
+            negotiate(a, Role::Initiator, &config, VersionMessage::new("left", false)),
+            negotiate(b, Role::Responder, &config, VersionMessage::new("right", false))
+        );
+        let (mut left, _) = left.unwrap();
+        let (mut right, _) = right.unwrap();
+        let (sender, _outbox) = peer();
+        let (receiver, _inbox) = peer();
+
+        let msg = Message::InvBlock { hashes: vec![consensus_core::ZERO_HASH; 3] };
+        sender.write_frame(&mut left, &msg).await.unwrap();
+        receiver.read_frame(&mut right).await.unwrap();
+
+        // A single record: its length header, then the frame and its tag encrypted
+        let sent = sender.info(0).bytes_sent;
+        assert_eq!(sent, 2 + protowire::frame_len(&msg).unwrap() + 16);
+        assert_eq!(receiver.info(0).bytes_received, sent);
+    }
+
+    #[tokio::test]
+    async fn test_idle_peer_times_out() {
+        let (local, mut remote) = duplex(1024);
+        let mut local = Transport::Plain(local);
+        let (peer, _outbox) = peer();
+
+        // A length prefix with no frame behind it must not block the reader forever
+        remote.write_all(&[16, 0, 0, 0]).await.unwrap();
+        let err = peer.read_frame(&mut local).await.unwrap_err();
+        assert!(err.starts_with("read timed out"));
+        assert!(!peer.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_last_seen_follows_received_frames() {
+        let (local, remote) = duplex(1024);
+        let (mut local, mut remote) = (Transport::Plain(local), Transport::Plain(remote));
+        let (peer, _outbox) = peer();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(peer.info(0).last_seen >= Duration::from_millis(50));
+        remote.write_frame(&Message::Pong { nonce: 1 }).await.unwrap();
+        peer.read_frame(&mut local).await.unwrap();
+        let info = peer.info(0);
+        assert!(info.connected_duration - info.last_seen >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_ping_disconnects() {
+        let (peer, mut outbox) = peer();
+        let keepalive = peer.spawn_keepalive();
+
+        assert!(matches!(outbox.recv().await, Some(Message::Ping { .. })));
+        keepalive.await.unwrap();
+        assert!(!peer.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_responsive_peer_stays_connected() {
+        let (local, remote) = duplex(1024);
+        let (mut local, mut remote) = (Transport::Plain(local), Transport::Plain(remote));
+        let (peer, mut outbox) = peer();
+        let keepalive = peer.spawn_keepalive();
+
+        for _ in 0..5 {
+            let Some(Message::Ping { nonce }) = outbox.recv().await else { panic!("expected a ping") };
+            // A pong with a stale nonce does not count as an answer
+            remote.write_frame(&Message::Pong { nonce: nonce.wrapping_add(1) }).await.unwrap();
+            peer.read_frame(&mut local).await.unwrap();
+            assert_eq!(peer.pending_ping_nonce(), Some(nonce));
+
+            remote.write_frame(&Message::Pong { nonce }).await.unwrap();
+            peer.read_frame(&mut local).await.unwrap();
+            assert_eq!(peer.pending_ping_nonce(), None);
+        }
+        assert!(peer.is_connected());
+        assert!(peer.info(0).last_ping_ms.is_some());
+        assert!(!keepalive.is_finished());
+        keepalive.abort();
+    }
+
+    #[tokio::test]
+    async fn test_tip_follows_announcements() {
+        let (local, remote) = duplex(1024);
+        let (mut local, mut remote) = (Transport::Plain(local), Transport::Plain(remote));
+        let (tx, _outbox) = mpsc::channel(8);
+        let handshake = ChainTip { hash: consensus_core::Hash::from_bytes([1; 32]), blue_score: 10, blue_work: 100u64.into() };
+        let version = VersionMessage::new("remote", false).with_tip(handshake);
+        let peer = Peer::from_handshake("remote".into(), SocketAddr::from(([127, 0, 0, 1], 16111)), PeerDirection::Inbound, version, tx);
+        assert_eq!(peer.tip(), handshake);
+
+        let announced = ChainTip { hash: consensus_core::Hash::from_bytes([2; 32]), blue_score: 11, blue_work: 110u64.into() };
+        remote.write_frame(&Message::Tip(announced)).await.unwrap();
+        peer.read_frame(&mut local).await.unwrap();
+        assert_eq!(peer.tip(), announced);
+        assert_eq!(peer.info(0).advertised_blue_score, 11);
+    }
 }
@@ -0,0 +1,270 @@
+//! Per-peer message rate limiting
+//!
+//! Every message read from a peer takes one token from a messages/second bucket
+//! and its frame size from a bytes/second bucket. Messages are split into
+//! classes with separate buckets, so a node downloading blocks during initial
+//! sync is not cut off by the much tighter limits on control traffic. A bucket
+//! holds one second worth of its rate, which is the largest burst allowed.
+
+use std::fmt;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::protowire::Message;
+
+/// Kind of traffic a message is limited as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageClass {
+    /// Handshake, keepalive and address gossip
+    Control,
+    /// Announcements and requests of blocks and transactions
+    Inventory,
+    Block,
+    Transaction,
+}
+
+impl MessageClass {
+    pub fn of(msg: &Message) -> Self {
+        match msg {
+            Message::Version(_) | Message::Ping { .. } | Message::Pong { .. } | Message::Addr { .. } => MessageClass::Control,
+            Message::InvBlock { .. }
+            | Message::RequestBlocks { .. }
+            | Message::InvTransaction { .. }
+            | Message::RequestTransactions { .. }
+            | Message::RequestHeaders { .. }
+            | Message::Tip(_) => MessageClass::Inventory,
+            Message::Block(_) | Message::Headers { .. } => MessageClass::Block,
+            Message::Transaction(_) => MessageClass::Transaction,
+        }
+    }
+}
+
+impl fmt::Display for MessageClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageClass::Control => write!(f, "control"),
+            MessageClass::Inventory => write!(f, "inventory"),
+            MessageClass::Block => write!(f, "block"),
+            MessageClass::Transaction => write!(f, "transaction"),
+        }
+    }
+}
+
+/// Sustained rate allowed for one message class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub messages_per_sec: u32,
+    pub bytes_per_sec: u64,
+}
+
+/// Rate limits per message class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    pub control: RateLimit,
+    pub inventory: RateLimit,
+    /// Generous enough for initial block download
+    pub blocks: RateLimit,
+    pub transactions: RateLimit,
+}
+
+impl RateLimits {
+    pub fn get(&self, class: MessageClass) -> RateLimit {
+        match class {
+            MessageClass::Control => self.control,
+            MessageClass::Inventory => self.inventory,
+            MessageClass::Block => self.blocks,
+            MessageClass::Transaction => self.transactions,
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            control: RateLimit { messages_per_sec: 20, bytes_per_sec: 64 * 1024 },
+            inventory: RateLimit { messages_per_sec: 200, bytes_per_sec: 2 * 1024 * 1024 },
+            blocks: RateLimit { messages_per_sec: 500, bytes_per_sec: 64 * 1024 * 1024 },
+            transactions: RateLimit { messages_per_sec: 1000, bytes_per_sec: 8 * 1024 * 1024 },
+        }
+    }
+}
+
+/// Token bucket refilled continuously at `rate` tokens per second up to `rate`
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Bucket starting full at `now`
+    pub fn new(rate: f64, now: Instant) -> Self {
+        Self { rate, tokens: rate, last: now }
+    }
+
+    /// Take `amount` tokens at `now`. Returns false, taking nothing, if there are not enough.
+    pub fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens < amount {
+            return false;
+        }
+        self.tokens -= amount;
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ClassBuckets {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+}
+
+impl ClassBuckets {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            messages: TokenBucket::new(limit.messages_per_sec as f64, now),
+            bytes: TokenBucket::new(limit.bytes_per_sec as f64, now),
+        }
+    }
+}
+
+/// Rate limit state of one peer
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    control: ClassBuckets,
+    inventory: ClassBuckets,
+    blocks: ClassBuckets,
+    transactions: ClassBuckets,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, now: Instant) -> Self {
+        Self {
+            control: ClassBuckets::new(limits.control, now),
+            inventory: ClassBuckets::new(limits.inventory, now),
+            blocks: ClassBuckets::new(limits.blocks, now),
+            transactions: ClassBuckets::new(limits.transactions, now),
+        }
+    }
+
+    /// Account for a received message of `class` whose frame is `bytes` long
+    pub fn check(&mut self, class: MessageClass, bytes: u64, now: Instant) -> Result<(), String> {
+        let buckets = match class {
+            MessageClass::Control => &mut self.control,
+            MessageClass::Inventory => &mut self.inventory,
+            MessageClass::Block => &mut self.blocks,
+            MessageClass::Transaction => &mut self.transactions,
+        };
+        if !buckets.messages.try_take(1.0, now) {
+            return Err(format!("rate limit exceeded: too many {} messages", class));
+        }
+        if !buckets.bytes.try_take(bytes as f64, now) {
+            return Err(format!("rate limit exceeded: too many {} bytes", class));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::duplex;
+    use tokio::sync::mpsc;
+    use crate::p2p::{Peer, Transport};
+
+    #[test]
+    fn test_bucket_refills_up_to_its_rate() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, now);
+        assert!(bucket.try_take(10.0, now));
+        assert!(!bucket.try_take(1.0, now));
+
+        assert!(bucket.try_take(5.0, now + Duration::from_millis(500)));
+        assert!(!bucket.try_take(1.0, now + Duration::from_millis(500)));
+
+        // A long pause refills only one second worth
+        assert!(!bucket.try_take(11.0, now + Duration::from_secs(60)));
+        assert!(bucket.try_take(10.0, now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_classes_are_limited_separately() {
+        let limits = RateLimits {
+            control: RateLimit { messages_per_sec: 2, bytes_per_sec: 1000 },
+            blocks: RateLimit { messages_per_sec: 100, bytes_per_sec: 1_000_000 },
+            ..RateLimits::default()
+        };
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(limits, now);
+        assert!(limiter.check(MessageClass::Control, 10, now).is_ok());
+        assert!(limiter.check(MessageClass::Control, 10, now).is_ok());
+        assert!(limiter.check(MessageClass::Control, 10, now).is_err());
+
+        // A block download burst is unaffected by the exhausted control bucket
+        for _ in 0..100 {
+            assert!(limiter.check(MessageClass::Block, 5000, now).is_ok());
+        }
+        assert!(limiter.check(MessageClass::Block, 5000, now).is_err());
+        assert!(limiter.check(MessageClass::Transaction, 2000, now).is_ok());
+        assert!(limiter.check(MessageClass::Inventory, 2_000_000_000, now).is_err());
+    }
+
+    fn peer_with(limits: RateLimits) -> Arc<Peer> {
+        let (tx, _rx) = mpsc::channel(1);
+        let address = SocketAddr::from(([127, 0, 0, 1], 16111));
+        Arc::new(Peer::new("remote".into(), address, tx).with_rate_limits(limits))
+    }
+
+    fn control_limits() -> RateLimits {
+        RateLimits { control: RateLimit { messages_per_sec: 10, bytes_per_sec: 64 * 1024 }, ..RateLimits::default() }
+    }
+
+    #[tokio::test]
+    async fn test_flooding_peer_is_disconnected() {
+        let (local, remote) = duplex(64 * 1024);
+        let (mut local, mut remote) = (Transport::Plain(local), Transport::Plain(remote));
+        let peer = peer_with(control_limits());
+
+        for nonce in 0..50 {
+            remote.write_frame(&Message::Pong { nonce }).await.unwrap();
+        }
+        let mut result = Ok(());
+        for _ in 0..50 {
+            result = peer.read_frame(&mut local).await.map(|_| ());
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(result.unwrap_err().starts_with("rate limit exceeded"));
+        assert!(!peer.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_normal_peer_stays_connected() {
+        let (local, remote) = duplex(64 * 1024);
+        let (mut local, mut remote) = (Transport::Plain(local), Transport::Plain(remote));
+        let peer = peer_with(control_limits());
+
+        // Within the limit, with a burst of block inventory on top
+        for nonce in 0..8 {
+            remote.write_frame(&Message::Pong { nonce }).await.unwrap();
+            peer.read_frame(&mut local).await.unwrap();
+        }
+        for _ in 0..50 {
+            remote.write_frame(&Message::InvBlock { hashes: vec![] }).await.unwrap();
+            peer.read_frame(&mut local).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for nonce in 0..2 {
+            remote.write_frame(&Message::Pong { nonce }).await.unwrap();
+            peer.read_frame(&mut local).await.unwrap();
+        }
+        assert!(peer.is_connected());
+    }
+}
@@ -1,42 +1,273 @@
 use bincode;
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::fmt;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use consensus_core::block::Block;
+use consensus_core::encoding::serde_canonical;
+use consensus_core::header::Header;
 use consensus_core::tx::Transaction;
-use consensus_core::Hash;
+use consensus_core::{BlueWorkType, Hash};
 
 pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
 
+/// Current P2P protocol version advertised in the version handshake. Version 2
+/// carries blocks and transactions in the canonical encoding of `consensus_core::encoding`;
+/// version 3 adds the sender's clock to the version message; version 4 adds the
+/// hash of the sender's consensus parameters; version 5 adds header requests for
+/// headers-first sync; version 6 adds the sender's tip to the version message
+/// and tip announcements.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Selected tip of a node's chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainTip {
+    pub hash: Hash,
+    pub blue_score: u64,
+    pub blue_work: BlueWorkType,
+}
+
+/// Version handshake payload exchanged in plaintext right after TCP connect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMessage {
+    pub protocol_version: u32,
+    pub user_agent: String,
+    /// Whether the sender is willing to upgrade the connection to an encrypted transport
+    pub supports_encryption: bool,
+    /// Publicly reachable listen address, if the sender knows it (e.g. from a NAT mapping)
+    pub address: Option<SocketAddr>,
+    /// Blue score of the sender's virtual block
+    pub blue_score: u64,
+    /// Random value identifying the sending node instance, 0 if unset. Used to
+    /// detect connections to ourselves and parallel connections to one node.
+    pub nonce: u64,
+    /// Whether the sender keeps every block body and can serve history below its pruning depth
+    pub archival: bool,
+    /// Sender's time in unix milliseconds when sending, 0 if unset
+    pub timestamp: u64,
+    /// Hash of the consensus parameters the sender validates with; peers
+    /// with different parameters don't connect
+    pub params_hash: Hash,
+    /// Sender's selected tip, default if unset
+    pub best_block: Hash,
+    /// Blue work of the sender's selected tip
+    pub blue_work: BlueWorkType,
+}
+
+impl VersionMessage {
+    pub fn new(user_agent: impl Into<String>, supports_encryption: bool) -> Self {
+        Self { protocol_version: PROTOCOL_VERSION, user_agent: user_agent.into(), supports_encryption, address: None, blue_score: 0, nonce: 0, archival: false, timestamp: 0, params_hash: Hash::default(), best_block: Hash::default(), blue_work: BlueWorkType::default() }
+    }
+
+    pub fn with_address(mut self, address: Option<SocketAddr>) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_blue_score(mut self, blue_score: u64) -> Self {
+        self.blue_score = blue_score;
+        self
+    }
+
+    /// Advertise `tip` as the sender's selected tip
+    pub fn with_tip(mut self, tip: ChainTip) -> Self {
+        self.best_block = tip.hash;
+        self.blue_score = tip.blue_score;
+        self.blue_work = tip.blue_work;
+        self
+    }
+
+    pub fn tip(&self) -> ChainTip {
+        ChainTip { hash: self.best_block, blue_score: self.blue_score, blue_work: self.blue_work }
+    }
+
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn with_archival(mut self, archival: bool) -> Self {
+        self.archival = archival;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_params_hash(mut self, params_hash: Hash) -> Self {
+        self.params_hash = params_hash;
+        self
+    }
+}
+
 /// Protowire message used by the network crate. Uses consensus_core's Block/Transaction/Hash.
+/// Blocks and transactions are embedded in their canonical encoding; the rest is bincode.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
+    Version(VersionMessage),
     Ping { nonce: u64 },
     Pong { nonce: u64 },
-    Transaction(Transaction),
-    Block(Block),
+    Transaction(#[serde(with = "serde_canonical::transaction")] Transaction),
+    Block(#[serde(with = "serde_canonical::block")] Block),
     InvBlock { hashes: Vec<Hash> },
     RequestBlocks { hashes: Vec<Hash> },
+    /// Gossip of publicly reachable peer addresses
+    Addr { addresses: Vec<SocketAddr> },
+    /// Announcement of transactions the sender can provide
+    InvTransaction { hashes: Vec<Hash> },
+    RequestTransactions { hashes: Vec<Hash> },
+    /// Request for up to `limit` headers following the first `locator` hash the
+    /// receiver knows, the locator listing the sender's chain newest first
+    RequestHeaders { locator: Vec<Hash>, limit: u32 },
+    /// Headers answering `RequestHeaders`, parents before children; empty once
+    /// the sender's tip is reached
+    Headers {
+        #[serde(with = "serde_canonical::headers")]
+        headers: Vec<Header>,
+    },
+    /// Announcement of the sender's new selected tip
+    Tip(ChainTip),
 }
 
-pub async fn write_frame(stream: &mut TcpStream, msg: &Message) -> Result<(), String> {
+/// Serialize a message into a frame payload (without the length prefix)
+pub fn encode_message(msg: &Message) -> Result<Vec<u8>, String> {
     let payload = bincode::serialize(msg).map_err(|e| format!("serialize: {}", e))?;
     if payload.len() > MAX_FRAME_SIZE {
         return Err("frame too large".into());
     }
+    Ok(payload)
+}
+
+/// Size of the frame carrying `msg`, including the length prefix
+pub fn frame_len(msg: &Message) -> Result<u64, String> {
+    let payload = bincode::serialized_size(msg).map_err(|e| format!("serialize: {}", e))?;
+    Ok(4 + payload)
+}
+
+/// Why a frame could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The stream failed or closed
+    Io(String),
+    /// The declared length exceeds `MAX_FRAME_SIZE`
+    TooLarge(usize),
+    /// An encrypted record failed authentication
+    Decrypt(String),
+    /// Records don't add up to a frame: too short, truncated or overrunning
+    /// the declared length
+    Malformed(&'static str),
+    /// The payload is not a message
+    Deserialize(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "{}", e),
+            FrameError::TooLarge(len) => write!(f, "frame too large: {} bytes", len),
+            FrameError::Decrypt(e) => write!(f, "decrypt: {}", e),
+            FrameError::Malformed(what) => write!(f, "{}", what),
+            FrameError::Deserialize(e) => write!(f, "deserialize: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        FrameError::Io(e.to_string())
+    }
+}
+
+impl From<FrameError> for String {
+    fn from(e: FrameError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Deserialize a frame payload back into a message
+pub fn decode_message(payload: &[u8]) -> Result<Message, FrameError> {
+    bincode::deserialize(payload).map_err(|e| FrameError::Deserialize(e.to_string()))
+}
+
+/// Write `msg` as a frame, returning the number of bytes written
+pub async fn write_frame<W: AsyncWrite + Unpin>(stream: &mut W, msg: &Message) -> Result<u64, String> {
+    let payload = encode_message(msg)?;
     let len = payload.len() as u32;
     stream.write_u32_le(len).await.map_err(|e| e.to_string())?;
     stream.write_all(&payload).await.map_err(|e| e.to_string())?;
-    Ok(())
+    Ok(4 + payload.len() as u64)
 }
 
-pub async fn read_frame(stream: &mut TcpStream) -> Result<Message, String> {
-    let len = stream.read_u32_le().await.map_err(|e| e.to_string())? as usize;
+pub async fn read_frame<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Message, FrameError> {
+    read_frame_counted(stream).await.map(|(msg, _)| msg)
+}
+
+/// Read a frame, returning the message and the number of bytes read
+pub async fn read_frame_counted<R: AsyncRead + Unpin>(stream: &mut R) -> Result<(Message, u64), FrameError> {
+    let len = stream.read_u32_le().await? as usize;
     if len > MAX_FRAME_SIZE {
-        return Err("frame too large".into());
+        return Err(FrameError::TooLarge(len));
     }
     let mut buf = vec![0u8; len];
-    stream.read_exact(&mut buf).await.map_err(|e| e.to_string())?;
-    let msg: Message = bincode::deserialize(&buf).map_err(|e| format!("deserialize: {}", e))?;
-    Ok(msg)
+    stream.read_exact(&mut buf).await?;
+    Ok((decode_message(&buf)?, 4 + len as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::encoding;
+    use consensus_core::subnets::SUBNETWORK_ID_COINBASE;
+    use consensus_core::tx::{ScriptPublicKey, TransactionOutput};
+    use consensus_core::{BlueWorkType, ZERO_HASH};
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn test_frames_embed_canonical_encoding() {
+        let output = TransactionOutput::new(1_000, ScriptPublicKey::from_vec(0, vec![0x20; 34]));
+        let tx = Transaction::new(0, vec![], vec![output], 0, SUBNETWORK_ID_COINBASE, 0, vec![1, 2, 3]);
+        let header = Header::new_finalized(1, vec![vec![ZERO_HASH]], ZERO_HASH, ZERO_HASH, ZERO_HASH, 1, 0x1f00ffff, 7, 1, BlueWorkType::from(1u64), 1, ZERO_HASH);
+        let block = Block::new(header, vec![tx.clone()]);
+
+        let payload = encode_message(&Message::Transaction(tx.clone())).unwrap();
+        assert!(contains(&payload, &encoding::encode_transaction(&tx)));
+        assert_eq!(frame_len(&Message::Transaction(tx.clone())).unwrap(), 4 + payload.len() as u64);
+        match decode_message(&payload).unwrap() {
+            Message::Transaction(decoded) => assert_eq!(decoded, tx),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let payload = encode_message(&Message::Block(block.clone())).unwrap();
+        assert!(contains(&payload, &encoding::encode_block(&block)));
+        match decode_message(&payload).unwrap() {
+            Message::Block(decoded) => {
+                assert_eq!(decoded.header.hash, block.header.hash);
+                assert_eq!(decoded.transactions, block.transactions);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let headers = Message::Headers { headers: vec![block.header.clone(), block.header.clone()] };
+        let payload = encode_message(&headers).unwrap();
+        assert!(contains(&payload, &encoding::encode_header(&block.header)));
+        match decode_message(&payload).unwrap() {
+            Message::Headers { headers } => {
+                assert_eq!(headers.len(), 2);
+                assert_eq!(headers[1].hash, block.header.hash);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // A truncated frame fails to decode
+        let mut truncated = payload.clone();
+        truncated.pop();
+        assert!(decode_message(&truncated).is_err());
+    }
 }
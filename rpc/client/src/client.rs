@@ -0,0 +1,496 @@
+//! [`RpcApi`] over a node's WebSocket RPC server
+
+use crate::connection::{Connection, Socket};
+use crate::subscription::Subscription;
+use async_trait::async_trait;
+use consensus_core::{block::Block, tx::{Transaction, TransactionOutpoint, UtxoEntry}, Hash};
+use rpc_core::{model::*, ChainTip, RpcApi};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::{header::AUTHORIZATION, HeaderValue};
+
+/// Time a request, connecting included, may take unless configured otherwise
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Notifications a subscription holds before the node is kept waiting
+const NOTIFICATION_QUEUE: usize = 256;
+
+/// Client of the node at a `ws://` or `wss://` URL, or `host:port`. Requests
+/// share one connection, opened on first use and reopened by the next request
+/// once it fails; each subscription has a connection of its own.
+pub struct JioRpcClient {
+    url: String,
+    auth_token: Option<String>,
+    timeout: Duration,
+    connection: Mutex<Option<Arc<Connection>>>,
+}
+
+impl JioRpcClient {
+    /// Client of the node at `url`, connecting on the first request
+    pub fn new(url: &str) -> Result<Self, RpcError> {
+        let url = if url.contains("://") { url.to_string() } else { format!("ws://{}", url) };
+        url.as_str().into_client_request().map_err(|e| RpcError::Network(format!("Invalid RPC URL {}: {}", url, e)))?;
+        Ok(Self { url, auth_token: None, timeout: DEFAULT_REQUEST_TIMEOUT, connection: Mutex::new(None) })
+    }
+
+    /// Client of the node at `url`, failing if it can't be reached
+    pub async fn connect(url: &str) -> Result<Self, RpcError> {
+        let client = Self::new(url)?;
+        client.connection().await?;
+        Ok(client)
+    }
+
+    /// Time each request may take, connecting included
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Token sent as `Authorization: Bearer <token>` when connecting, for nodes
+    /// behind an authenticating proxy
+    pub fn with_auth_token(self, token: impl Into<String>) -> Self {
+        Self { auth_token: Some(token.into()), ..self }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Send one request and wait for its result
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        self.connection().await?.request(method, params, self.timeout).await
+    }
+
+    async fn call_as<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, RpcError> {
+        decode(self.call(method, params).await?)
+    }
+
+    /// Watch the UTXOs of `addresses`
+    pub async fn subscribe_utxos_changed(&self, addresses: &[String]) -> Result<Subscription<UtxosChangedNotification>, RpcError> {
+        let params = json!({ "topic": "utxosChanged", "addresses": addresses });
+        Ok(self.subscribe("subscribe", params).await?.0)
+    }
+
+    /// Follow the selected chain from the tip returned along with the
+    /// subscription. Given the last tip known from an earlier subscription, the
+    /// first notification catches up from it.
+    pub async fn subscribe_virtual_chain_changed(
+        &self,
+        start_hash: Option<Hash>,
+        include_accepted_transaction_ids: bool,
+    ) -> Result<(ChainTip, Subscription<VirtualChainChangedNotification>), RpcError> {
+        let params = json!({
+            "includeAcceptedTransactionIds": include_accepted_transaction_ids,
+            "startHash": start_hash.map(|hash| hash.to_string()),
+        });
+        let (subscription, result) = self.subscribe("notifyVirtualChainChanged", params).await?;
+        Ok((decode(result.get("tip").cloned().unwrap_or(Value::Null))?, subscription))
+    }
+
+    /// Be told whenever there is a new block template to mine on
+    pub async fn subscribe_new_block_template(&self) -> Result<Subscription<NewBlockTemplateNotification>, RpcError> {
+        Ok(self.subscribe("notifyNewBlockTemplate", json!({})).await?.0)
+    }
+
+    /// Open a connection for one subscription, returning it along with the node's response
+    async fn subscribe<T>(&self, method: &str, params: Value) -> Result<(Subscription<T>, Value), RpcError> {
+        let (sender, receiver) = mpsc::channel(NOTIFICATION_QUEUE);
+        let connection = Connection::new(self.open().await?, Some(sender));
+        let result = connection.request(method, params, self.timeout).await?;
+        let id = result
+            .get("subscriptionId")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| RpcError::Internal(format!("Invalid {} response", method)))?;
+        Ok((Subscription::new(connection, id, receiver, self.timeout), result))
+    }
+
+    /// The shared connection, reopened if it failed
+    async fn connection(&self) -> Result<Arc<Connection>, RpcError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(open) = connection.as_ref().filter(|open| !open.is_closed()) {
+            return Ok(open.clone());
+        }
+        let opened = Arc::new(Connection::new(self.open().await?, None));
+        *connection = Some(opened.clone());
+        Ok(opened)
+    }
+
+    async fn open(&self) -> Result<Socket, RpcError> {
+        let (socket, _) = tokio::time::timeout(self.timeout, connect_async(self.request()?))
+            .await
+            .map_err(|_| RpcError::Network(format!("Connecting to {} timed out", self.url)))?
+            .map_err(|e| RpcError::Network(format!("WebSocket connection failed: {}", e)))?;
+        Ok(socket)
+    }
+
+    /// Handshake request, carrying the auth token if there is one
+    fn request(&self) -> Result<Request, RpcError> {
+        let mut request =
+            self.url.as_str().into_client_request().map_err(|e| RpcError::Network(format!("Invalid RPC URL {}: {}", self.url, e)))?;
+        if let Some(token) = &self.auth_token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| RpcError::Internal("Auth token is not a valid header value".to_string()))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        Ok(request)
+    }
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(value: Value) -> Result<T, RpcError> {
+    serde_json::from_value(value).map_err(|e| RpcError::Internal(format!("Deserialization error: {}", e)))
+}
+
+/// Hash sent as a bare hex string
+fn parse_hash(hex_hash: &str) -> Result<Hash, RpcError> {
+    let bytes = hex::decode(hex_hash).map_err(|e| RpcError::Internal(format!("Hex decode error: {}", e)))?;
+    let array: [u8; 32] = bytes.try_into().map_err(|_| RpcError::Internal("Invalid hash length".to_string()))?;
+    Ok(Hash::from(array))
+}
+
+fn parse_hashes(hex_hashes: Vec<String>) -> Result<Vec<Hash>, RpcError> {
+    hex_hashes.iter().map(|hash| parse_hash(hash)).collect()
+}
+
+#[async_trait]
+impl RpcApi for JioRpcClient {
+    async fn get_info(&self) -> Result<GetInfoResponse, RpcError> {
+        self.call_as("getInfo", json!([])).await
+    }
+
+    async fn set_log_level(&self, target: String, level: String) -> Result<String, RpcError> {
+        self.call_as("setLogLevel", json!([target, level])).await
+    }
+
+    async fn get_block_count(&self) -> Result<u64, RpcError> {
+        self.call_as("getBlockCount", json!([])).await
+    }
+
+    async fn get_block(&self, hash: Hash) -> Result<Block, RpcError> {
+        let block: RpcBlock = self.call_as("getBlock", json!([hash.to_string()])).await?;
+        Block::try_from(block)
+    }
+
+    async fn get_headers(&self, start_hash: Hash, limit: usize) -> Result<Vec<String>, RpcError> {
+        self.call_as("getHeaders", json!([start_hash.to_string(), limit])).await
+    }
+
+    async fn get_virtual_chain_from_block(&self, start_hash: Option<Hash>, include_accepted_transaction_ids: bool) -> Result<VirtualChainChanged, RpcError> {
+        let params = json!({
+            "startHash": start_hash.map(|hash| hash.to_string()),
+            "includeAcceptedTransactionIds": include_accepted_transaction_ids,
+        });
+        self.call_as("getVirtualChainFromBlock", params).await
+    }
+
+    async fn get_block_hex(&self, hash: Hash) -> Result<String, RpcError> {
+        self.call_as("getBlockHex", json!([hash.to_string()])).await
+    }
+
+    async fn decode_block(&self, block_hex: String) -> Result<RpcBlock, RpcError> {
+        self.call_as("decodeBlock", json!([block_hex])).await
+    }
+
+    async fn get_block_verbose(&self, hash: Hash) -> Result<RpcBlockVerbose, RpcError> {
+        self.call_as("getBlock", json!([hash.to_string(), true])).await
+    }
+
+    async fn get_block_status(&self, hash: Hash) -> Result<BlockStatusInfo, RpcError> {
+        self.call_as("getBlockStatus", json!([hash.to_string()])).await
+    }
+
+    async fn get_block_dag_info(&self) -> Result<BlockDagInfo, RpcError> {
+        self.call_as("getBlockDagInfo", json!([])).await
+    }
+
+    async fn get_blocks(
+        &self,
+        low_hash: Option<Hash>,
+        include_blocks: bool,
+        include_transactions: bool,
+        include_verbose_data: bool,
+    ) -> Result<GetBlocksResponse, RpcError> {
+        let params = json!({
+            "lowHash": low_hash.map(|h| h.to_string()),
+            "includeBlocks": include_blocks,
+            "includeTransactions": include_transactions,
+            "includeVerboseData": include_verbose_data
+        });
+        self.call_as("getBlocks", params).await
+    }
+
+    async fn get_peer_info(&self) -> Result<Vec<PeerInfo>, RpcError> {
+        self.call_as("getPeerInfo", json!([])).await
+    }
+
+    async fn get_connection_count(&self) -> Result<ConnectionCounts, RpcError> {
+        self.call_as("getConnectionCount", json!([])).await
+    }
+
+    async fn add_peer(&self, address: String, is_permanent: bool) -> Result<(), RpcError> {
+        self.call("addPeer", json!([address, is_permanent])).await?;
+        Ok(())
+    }
+
+    async fn remove_peer(&self, address: String) -> Result<(), RpcError> {
+        self.call("removePeer", json!([address])).await?;
+        Ok(())
+    }
+
+    async fn ban_peer(&self, address: String, duration_secs: u64) -> Result<(), RpcError> {
+        self.call("banPeer", json!([address, duration_secs])).await?;
+        Ok(())
+    }
+
+    async fn unban_peer(&self, address: String) -> Result<(), RpcError> {
+        self.call("unbanPeer", json!([address])).await?;
+        Ok(())
+    }
+
+    async fn get_banned_peers(&self) -> Result<Vec<BannedPeer>, RpcError> {
+        self.call_as("getBannedPeers", json!([])).await
+    }
+
+    async fn submit_block(&self, block: Block) -> Result<Hash, RpcError> {
+        self.call_as("submitBlock", json!([block])).await
+    }
+
+    async fn send_raw_transaction(&self, tx_hex: String, allow_high_fees: bool) -> Result<Hash, RpcError> {
+        self.call_as("sendRawTransaction", json!([tx_hex, allow_high_fees])).await
+    }
+
+    async fn get_transaction_hex(&self, id: Hash) -> Result<String, RpcError> {
+        self.call_as("getTransactionHex", json!([id.to_string()])).await
+    }
+
+    async fn decode_transaction(&self, tx_hex: String) -> Result<RpcTransaction, RpcError> {
+        self.call_as("decodeTransaction", json!([tx_hex])).await
+    }
+
+    async fn get_mempool_info(&self) -> Result<MempoolInfo, RpcError> {
+        self.call_as("getMempoolInfo", json!([])).await
+    }
+
+    async fn get_fee_estimate(&self) -> Result<FeeEstimate, RpcError> {
+        self.call_as("getFeeEstimate", json!([])).await
+    }
+
+    async fn get_mempool_entries(&self, include_orphan_pool: bool, filter_transaction_pool: bool) -> Result<Vec<MempoolEntry>, RpcError> {
+        self.call_as("getMempoolEntries", json!([include_orphan_pool, filter_transaction_pool])).await
+    }
+
+    async fn flush_mempool(&self) -> Result<usize, RpcError> {
+        self.call_as("flushMempool", json!([])).await
+    }
+
+    async fn get_block_template(&self, pay_address: String, extra_data: Option<String>) -> Result<BlockTemplate, RpcError> {
+        self.call_as("getBlockTemplate", json!([pay_address, extra_data])).await
+    }
+
+    async fn submit_block_hex(&self, block_hex: String) -> Result<Hash, RpcError> {
+        let hash: String = self.call_as("submitBlockHex", json!([block_hex])).await?;
+        parse_hash(&hash)
+    }
+
+    async fn submit_block_with_coinbase(
+        &self,
+        template_id: Hash,
+        nonce: u64,
+        extranonce: Vec<u8>,
+        coinbase_overrides: Option<CoinbaseOverrides>,
+    ) -> Result<Hash, RpcError> {
+        let params = json!({
+            "templateId": template_id.to_string(),
+            "nonce": nonce,
+            "extranonce": hex::encode(extranonce),
+            "coinbaseOverrides": coinbase_overrides,
+        });
+        let hash: String = self.call_as("submitBlockWithCoinbase", params).await?;
+        parse_hash(&hash)
+    }
+
+    async fn get_mining_info(&self) -> Result<MiningInfo, RpcError> {
+        self.call_as("getMiningInfo", json!([])).await
+    }
+
+    async fn estimate_network_hashes_per_second(&self, window_size: u32, start_hash: Option<Hash>) -> Result<u64, RpcError> {
+        self.call_as("estimateNetworkHashesPerSecond", json!([window_size, start_hash.map(|h| h.to_string())])).await
+    }
+
+    async fn get_balances(&self) -> Result<GetBalancesResponse, RpcError> {
+        self.call_as("getBalances", json!([])).await
+    }
+
+    async fn get_new_address(&self) -> Result<String, RpcError> {
+        self.call_as("getNewAddress", json!([])).await
+    }
+
+    async fn send_to_address(&self, address: String, amount: u64) -> Result<Hash, RpcError> {
+        self.call_as("sendToAddress", json!([address, amount])).await
+    }
+
+    async fn get_balance_by_address(&self, address: String) -> Result<AddressBalance, RpcError> {
+        self.call_as("getBalanceByAddress", json!([address])).await
+    }
+
+    async fn get_utxos_by_address(&self, address: String) -> Result<Vec<RpcUtxoByAddress>, RpcError> {
+        self.call_as("getUtxosByAddress", json!([address])).await
+    }
+
+    async fn get_virtual_utxo_entry(&self, outpoint: TransactionOutpoint) -> Result<Option<UtxoEntry>, RpcError> {
+        self.call_as("getVirtualUtxoEntry", json!([outpoint.transaction_id.to_string(), outpoint.index])).await
+    }
+
+    async fn get_virtual_selected_parent_blue_score(&self) -> Result<u64, RpcError> {
+        self.call_as("getVirtualSelectedParentBlueScore", json!([])).await
+    }
+
+    async fn get_coin_supply(&self) -> Result<CoinSupply, RpcError> {
+        self.call_as("getCoinSupply", json!([])).await
+    }
+
+    async fn get_block_by_height(&self, height: u64) -> Result<Block, RpcError> {
+        let block: RpcBlock = self.call_as("getBlockByHeight", json!([height])).await?;
+        Block::try_from(block)
+    }
+
+    async fn get_transaction(&self, hash: Hash) -> Result<Transaction, RpcError> {
+        self.call_as("getTransaction", json!([hash.to_string()])).await
+    }
+
+    async fn get_transaction_verbose(&self, hash: Hash) -> Result<RpcTransactionVerbose, RpcError> {
+        self.call_as("getTransaction", json!([hash.to_string(), true])).await
+    }
+
+    async fn get_recent_blocks(&self, count: usize) -> Result<Vec<Block>, RpcError> {
+        let blocks: Vec<RpcBlock> = self.call_as("getRecentBlocks", json!([count])).await?;
+        blocks.into_iter().map(Block::try_from).collect()
+    }
+
+    async fn get_dag_tips(&self) -> Result<Vec<Hash>, RpcError> {
+        parse_hashes(self.call_as("getDagTips", json!([])).await?)
+    }
+
+    async fn get_block_children(&self, hash: Hash) -> Result<Vec<Hash>, RpcError> {
+        parse_hashes(self.call_as("getBlockChildren", json!([hash.to_string()])).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request as ServerRequest, Response};
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Node stand-in answering each request with its method, except for
+    /// "hang" (never answered), "fail" (an error) and "close" (closes the
+    /// connection). Counts connections and keeps the last Authorization header.
+    struct FakeNode {
+        url: String,
+        connections: Arc<AtomicUsize>,
+        authorization: Arc<std::sync::Mutex<Option<String>>>,
+    }
+
+    async fn fake_node() -> FakeNode {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let authorization = Arc::new(std::sync::Mutex::new(None));
+        let (counter, header) = (connections.clone(), authorization.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let header = header.clone();
+                tokio::spawn(async move {
+                    let callback = |request: &ServerRequest, response: Response| {
+                        *header.lock().unwrap() =
+                            request.headers().get("authorization").map(|value| value.to_str().unwrap().to_string());
+                        Ok(response)
+                    };
+                    let mut socket = accept_hdr_async(stream, callback).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = socket.next().await {
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        let response = match request["method"].as_str().unwrap() {
+                            "hang" => continue,
+                            "close" => return,
+                            "fail" => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -5, "message": "not found" } }),
+                            method => json!({ "jsonrpc": "2.0", "id": request["id"], "result": method }),
+                        };
+                        socket.send(Message::Text(response.to_string())).await.unwrap();
+                    }
+                });
+            }
+        });
+        FakeNode { url, connections, authorization }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_one_connection() {
+        let node = fake_node().await;
+        let client = Arc::new(JioRpcClient::connect(&node.url).await.unwrap());
+        let calls: Vec<_> = (0..8)
+            .map(|n| {
+                let client = client.clone();
+                tokio::spawn(async move { client.call(&format!("method{}", n), json!([])).await })
+            })
+            .collect();
+        for (n, call) in calls.into_iter().enumerate() {
+            assert_eq!(call.await.unwrap().unwrap(), json!(format!("method{}", n)));
+        }
+        assert_eq!(node.connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_times_out_without_dropping_the_connection() {
+        let node = fake_node().await;
+        let client = JioRpcClient::new(&node.url).unwrap().with_timeout(Duration::from_millis(200));
+        let result = client.call("hang", json!([])).await;
+        assert!(matches!(result, Err(RpcError::Network(ref message)) if message.contains("timed out")), "{:?}", result);
+        assert_eq!(client.call("getInfo", json!([])).await.unwrap(), json!("getInfo"));
+        assert_eq!(node.connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_next_request_reconnects_after_the_connection_ends() {
+        let node = fake_node().await;
+        let client = JioRpcClient::new(&node.url).unwrap();
+        assert!(matches!(client.call("close", json!([])).await, Err(RpcError::Network(_))));
+        assert_eq!(client.call("getInfo", json!([])).await.unwrap(), json!("getInfo"));
+        assert_eq!(node.connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_node_errors_keep_their_code() {
+        let node = fake_node().await;
+        let client = JioRpcClient::new(&node.url).unwrap();
+        let result = client.call("fail", json!([])).await;
+        assert!(matches!(result, Err(RpcError::Rpc { code: -5, ref message }) if message == "not found"), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_is_sent_when_connecting() {
+        let node = fake_node().await;
+        let address = node.url.trim_start_matches("ws://");
+        let client = JioRpcClient::new(address).unwrap().with_auth_token("secret");
+        assert_eq!(client.url(), node.url);
+        client.call("getInfo", json!([])).await.unwrap();
+        assert_eq!(node.authorization.lock().unwrap().as_deref(), Some("Bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_node() {
+        // Lazily created clients only fail once used
+        let client = JioRpcClient::new("ws://127.0.0.1:1").unwrap();
+        assert!(matches!(client.get_block_count().await, Err(RpcError::Network(_))));
+        assert!(JioRpcClient::connect("ws://127.0.0.1:1").await.is_err());
+        assert!(JioRpcClient::new("not a url").is_err());
+    }
+}
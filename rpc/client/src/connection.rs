@@ -0,0 +1,153 @@
+//! One WebSocket connection, its responses matched to requests by id
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use rpc_core::RpcError;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+pub(crate) type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcResponse {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcError {
+    code: i32,
+    message: String,
+}
+
+/// State shared with the task reading the connection
+#[derive(Default)]
+struct Shared {
+    /// Requests waiting for their response, by id
+    pending: std::sync::Mutex<HashMap<u64, oneshot::Sender<Result<Value, RpcError>>>>,
+    closed: AtomicBool,
+}
+
+/// Requests may be sent from several tasks at once; each waits for its own
+/// response. Dropping the connection closes it.
+pub(crate) struct Connection {
+    sink: Mutex<SplitSink<Socket, Message>>,
+    shared: Arc<Shared>,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+}
+
+impl Connection {
+    /// Start reading `socket`; notifications are passed to `notifications`, or dropped without one
+    pub(crate) fn new(socket: Socket, notifications: Option<mpsc::Sender<Value>>) -> Self {
+        let (sink, stream) = socket.split();
+        let shared = Arc::new(Shared::default());
+        let reader = tokio::spawn(read(stream, shared.clone(), notifications));
+        Self { sink: Mutex::new(sink), shared, next_id: AtomicU64::new(1), reader }
+    }
+
+    /// Whether the connection failed or the node closed it
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::SeqCst)
+    }
+
+    /// Send one request and wait up to `timeout` for its result
+    pub(crate) async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        // The reader marks the connection closed before failing the requests it has
+        if self.is_closed() {
+            self.shared.pending.lock().unwrap().remove(&id);
+            return Err(RpcError::Network("Connection closed".to_string()));
+        }
+
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let exchange = async {
+            if let Err(e) = self.sink.lock().await.send(Message::Text(request.to_string())).await {
+                self.shared.closed.store(true, Ordering::SeqCst);
+                return Err(RpcError::Network(format!("Send failed: {}", e)));
+            }
+            receiver.await.unwrap_or_else(|_| Err(RpcError::Network("Connection closed without response".to_string())))
+        };
+        let result = tokio::time::timeout(timeout, exchange)
+            .await
+            .unwrap_or_else(|_| Err(RpcError::Network(format!("{} timed out after {:?}", method, timeout))));
+        // Still there if the response never came
+        self.shared.pending.lock().unwrap().remove(&id);
+        result
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Hand responses to the requests waiting for them until the connection ends,
+/// then fail the requests still waiting
+async fn read(mut stream: SplitStream<Socket>, shared: Arc<Shared>, notifications: Option<mpsc::Sender<Value>>) {
+    let mut failure = "Connection closed without response".to_string();
+    while let Some(message) = stream.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(e) => {
+                failure = format!("WebSocket error: {}", e);
+                break;
+            }
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        if message.get("method").is_some() {
+            let Some(notifications) = &notifications else {
+                continue;
+            };
+            // A closed channel means the subscription was dropped
+            if notifications.send(message.get("params").cloned().unwrap_or(Value::Null)).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        let Ok(response) = serde_json::from_value::<JsonRpcResponse>(message) else {
+            continue;
+        };
+        match (response.id, response.error) {
+            (Some(id), error) => {
+                let result = match error {
+                    Some(error) => Err(RpcError::Rpc { code: error.code, message: error.message }),
+                    None => Ok(response.result),
+                };
+                if let Some(sender) = shared.pending.lock().unwrap().remove(&id) {
+                    let _ = sender.send(result);
+                }
+            }
+            // Without an id the error is about the connection, e.g. a refusal over the node's limit
+            (None, Some(error)) => {
+                failure = error.message;
+                break;
+            }
+            (None, None) => {}
+        }
+    }
+
+    shared.closed.store(true, Ordering::SeqCst);
+    for (_, sender) in shared.pending.lock().unwrap().drain() {
+        let _ = sender.send(Err(RpcError::Network(failure.clone())));
+    }
+}
@@ -0,0 +1,11 @@
+//! JSON-RPC client of a node's WebSocket RPC server
+//!
+//! [`JioRpcClient`] implements [`rpc_core::RpcApi`], so code written against
+//! the node's coordinator runs the same against a remote node.
+
+mod client;
+mod connection;
+mod subscription;
+
+pub use client::{JioRpcClient, DEFAULT_REQUEST_TIMEOUT};
+pub use subscription::Subscription;
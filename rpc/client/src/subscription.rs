@@ -0,0 +1,51 @@
+//! Notifications of one subscription, received over a connection of its own
+
+use crate::client::decode;
+use crate::connection::Connection;
+use futures_util::Stream;
+use rpc_core::{RpcError, UtxosChangedNotification};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Stream of the notifications of a subscription. It ends when the connection
+/// does, after which the subscription has to be made again; dropping it
+/// closes the connection, ending the subscription on the node.
+pub struct Subscription<T> {
+    connection: Connection,
+    id: u64,
+    notifications: mpsc::Receiver<Value>,
+    timeout: Duration,
+    notification: PhantomData<fn() -> T>,
+}
+
+impl<T> Subscription<T> {
+    pub(crate) fn new(connection: Connection, id: u64, notifications: mpsc::Receiver<Value>, timeout: Duration) -> Self {
+        Self { connection, id, notifications, timeout, notification: PhantomData }
+    }
+
+    /// Id the node gave the subscription
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Subscription<UtxosChangedNotification> {
+    /// Start watching `add` and stop watching `remove`
+    pub async fn modify_addresses(&self, add: &[String], remove: &[String]) -> Result<(), RpcError> {
+        let params = json!({ "subscriptionId": self.id, "addAddresses": add, "removeAddresses": remove });
+        self.connection.request("modifySubscription", params, self.timeout).await.map(|_| ())
+    }
+}
+
+impl<T: DeserializeOwned> Stream for Subscription<T> {
+    type Item = Result<T, RpcError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().notifications.poll_recv(cx).map(|params| params.map(decode))
+    }
+}
@@ -1,41 +1,100 @@
 //! RPC API trait definitions
 
 use async_trait::async_trait;
-use consensus_core::{block::Block, tx::Transaction, Hash};
+use consensus_core::{block::Block, tx::{self, Transaction}, Hash};
 use crate::model::*;
 
 /// Core RPC API trait defining all available RPC methods
 #[async_trait]
 pub trait RpcApi: Send + Sync {
+    // Node methods
+    async fn get_info(&self) -> Result<GetInfoResponse, RpcError>;
+    /// Change the log level of `target` (all targets when empty) until the next
+    /// restart, returning the filter now in effect
+    async fn set_log_level(&self, target: String, level: String) -> Result<String, RpcError>;
+
     // Blockchain methods
     async fn get_block_count(&self) -> Result<u64, RpcError>;
     async fn get_block(&self, hash: Hash) -> Result<Block, RpcError>;
+    async fn get_block_verbose(&self, hash: Hash) -> Result<RpcBlockVerbose, RpcError>;
+    /// Validation status of a block and where it was first received from
+    async fn get_block_status(&self, hash: Hash) -> Result<BlockStatusInfo, RpcError>;
     async fn get_block_dag_info(&self) -> Result<BlockDagInfo, RpcError>;
-    async fn get_blocks(&self, low_hash: Option<Hash>, include_blocks: bool, include_transactions: bool) -> Result<GetBlocksResponse, RpcError>;
+    /// A page of blocks following `low_hash`, with the GHOSTDAG verbose data of
+    /// each when `include_verbose_data` is set
+    async fn get_blocks(
+        &self,
+        low_hash: Option<Hash>,
+        include_blocks: bool,
+        include_transactions: bool,
+        include_verbose_data: bool,
+    ) -> Result<GetBlocksResponse, RpcError>;
+    /// Hex of the canonical encodings of up to `limit` headers following
+    /// `start_hash` along the selected chain, for header-only clients
+    async fn get_headers(&self, start_hash: Hash, limit: usize) -> Result<Vec<String>, RpcError>;
+    /// Chain blocks removed and added since `start_hash` up to the current
+    /// selected chain tip; the whole selected chain without a start
+    async fn get_virtual_chain_from_block(&self, start_hash: Option<Hash>, include_accepted_transaction_ids: bool) -> Result<VirtualChainChanged, RpcError>;
+    /// Hex of the canonical encoding of a block, for archival and re-verification
+    async fn get_block_hex(&self, hash: Hash) -> Result<String, RpcError>;
+    /// Parse the hex of a canonically encoded block without submitting it
+    async fn decode_block(&self, block_hex: String) -> Result<RpcBlock, RpcError>;
 
     // Network methods
     async fn get_peer_info(&self) -> Result<Vec<PeerInfo>, RpcError>;
+    async fn get_connection_count(&self) -> Result<ConnectionCounts, RpcError>;
     async fn add_peer(&self, address: String, is_permanent: bool) -> Result<(), RpcError>;
+    async fn remove_peer(&self, address: String) -> Result<(), RpcError>;
+    /// Refuse peers from the IP of `address` for `duration_secs`, the default ban
+    /// duration if zero, disconnecting those connected
+    async fn ban_peer(&self, address: String, duration_secs: u64) -> Result<(), RpcError>;
+    async fn unban_peer(&self, address: String) -> Result<(), RpcError>;
+    async fn get_banned_peers(&self) -> Result<Vec<BannedPeer>, RpcError>;
     async fn submit_block(&self, block: Block) -> Result<Hash, RpcError>;
 
     // Transaction methods
     async fn send_raw_transaction(&self, tx_hex: String, allow_high_fees: bool) -> Result<Hash, RpcError>;
+    /// Hex of the canonical encoding of a block or mempool transaction
+    async fn get_transaction_hex(&self, id: Hash) -> Result<String, RpcError>;
+    /// Parse the hex of a canonically encoded transaction without submitting it
+    async fn decode_transaction(&self, tx_hex: String) -> Result<RpcTransaction, RpcError>;
     async fn get_mempool_info(&self) -> Result<MempoolInfo, RpcError>;
+    async fn get_fee_estimate(&self) -> Result<FeeEstimate, RpcError>;
     async fn get_mempool_entries(&self, include_orphan_pool: bool, filter_transaction_pool: bool) -> Result<Vec<MempoolEntry>, RpcError>;
+    /// Write the mempool to disk now, returning the number of transactions saved
+    async fn flush_mempool(&self) -> Result<usize, RpcError>;
 
     // Mining methods
     async fn get_block_template(&self, pay_address: String, extra_data: Option<String>) -> Result<BlockTemplate, RpcError>;
     async fn submit_block_hex(&self, block_hex: String) -> Result<Hash, RpcError>;
+    /// Rebuild a block from a cached template with the miner's nonce and
+    /// extranonce, check its proof of work and submit it
+    async fn submit_block_with_coinbase(
+        &self,
+        template_id: Hash,
+        nonce: u64,
+        extranonce: Vec<u8>,
+        coinbase_overrides: Option<CoinbaseOverrides>,
+    ) -> Result<Hash, RpcError>;
     async fn get_mining_info(&self) -> Result<MiningInfo, RpcError>;
 
     // Wallet methods (integration with wallet crate)
     async fn estimate_network_hashes_per_second(&self, window_size: u32, start_hash: Option<Hash>) -> Result<u64, RpcError>;
     async fn get_balances(&self) -> Result<GetBalancesResponse, RpcError>;
+    async fn get_new_address(&self) -> Result<String, RpcError>;
+    async fn send_to_address(&self, address: String, amount: u64) -> Result<Hash, RpcError>;
+    async fn get_balance_by_address(&self, address: String) -> Result<AddressBalance, RpcError>;
+    async fn get_utxos_by_address(&self, address: String) -> Result<Vec<RpcUtxoByAddress>, RpcError>;
+    /// Entry of `outpoint` at the virtual state: unspent in the confirmed set or
+    /// created by a mempool transaction, and not spent by one. `None` if it isn't spendable.
+    async fn get_virtual_utxo_entry(&self, outpoint: tx::TransactionOutpoint) -> Result<Option<tx::UtxoEntry>, RpcError>;
     async fn get_virtual_selected_parent_blue_score(&self) -> Result<u64, RpcError>;
+    async fn get_coin_supply(&self) -> Result<CoinSupply, RpcError>;
     
     // Additional methods for explorer
     async fn get_block_by_height(&self, height: u64) -> Result<Block, RpcError>;
     async fn get_transaction(&self, hash: Hash) -> Result<Transaction, RpcError>;
+    async fn get_transaction_verbose(&self, hash: Hash) -> Result<RpcTransactionVerbose, RpcError>;
     async fn get_recent_blocks(&self, count: usize) -> Result<Vec<Block>, RpcError>;
     async fn get_dag_tips(&self) -> Result<Vec<Hash>, RpcError>;
     async fn get_block_children(&self, hash: Hash) -> Result<Vec<Hash>, RpcError>;
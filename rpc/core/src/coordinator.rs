@@ -1,14 +1,89 @@
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use tokio::sync::RwLock;
-use consensus::{BlockProcessor, ConsensusStorage};
-use consensus_core::{block::Block, tx::Transaction, Hash, BlockHashSet, HashMapCustomHasher};
+use consensus::{BlockAcceptance, BlockProcessor, ConsensusStorage, GhostdagManager};
+use consensus::consensus::ghostdag::acceptance::confirmations;
+use consensus::consensus::storage::UtxoChanges;
+use consensus::pipeline::ChainChanges;
+use consensus_core::{block::Block, header::Header, tx::Transaction, Hash, BlockHashSet, HashMapCustomHasher};
+use consensus_core::constants::{COINBASE_MATURITY, MAX_BLOCK_MASS, STORAGE_MASS_PARAMETER};
+use consensus_core::encoding;
+use consensus_core::mass::dust_threshold;
+use consensus_core::provenance::BlockSource;
+use consensus_core::time::{Clock, SystemClock};
+use consensus_core::tx::{TransactionOutpoint, UtxoEntry};
+use consensus_core::utxo::{UtxoCollection, UtxoView};
 use crate::api::RpcApi;
 use crate::model::*;
 use crate::mempool::MempoolInterface;
+use crate::fee_estimator::{FeeEstimator, MempoolFeeSample};
+use crate::package::MempoolGraph;
+use crate::logging::LogControl;
+use crate::sync_status::SyncStatusProvider;
+use crate::subscriptions::{ChainSubscriptions, ChainTip, TemplateSubscriptions, UtxoSubscriptions};
 use network::Hub;
-use wallet::Keys;
+use network::connection_manager::PeerRequestError;
+use wallet::balance::{Balance, ConfirmedOutput};
+use wallet::discovery::{discover_with, DEFAULT_GAP_LIMIT};
+use wallet::HotWallet;
+use consensus_core::errors::ConsensusError;
 
+/// Maximum number of blocks returned by one get_blocks call
+pub const GET_BLOCKS_PAGE_SIZE: usize = 100;
+
+/// Maximum number of headers returned by one get_headers call
+pub const GET_HEADERS_MAX: usize = 2000;
+
+/// Number of recently accepted blocks sampled by get_fee_estimate
+pub const FEE_ESTIMATE_BLOCK_WINDOW: usize = 100;
+
+/// Block template bits when the parents' difficulty window is unavailable
+pub const DEFAULT_TEMPLATE_BITS: u32 = 0x1f00ffff;
+
+/// Parents of block templates unless configured otherwise
+pub const DEFAULT_TEMPLATE_PARENTS: usize = 4;
+
+/// Recent block templates kept for submit_block_with_coinbase
+pub const MAX_CACHED_TEMPLATES: usize = 32;
+
+/// Network reported until one is configured
+pub const DEFAULT_NETWORK_ID: &str = "testnet";
+
+/// RPC clients served at once unless configured otherwise
+pub const DEFAULT_MAX_RPC_CONNECTIONS: usize = 100;
+
+/// Map a failed add_peer/remove_peer request to its RPC error code
+fn peer_request_error(e: PeerRequestError) -> RpcError {
+    let code = match e {
+        PeerRequestError::Unresolvable(_) => -8,
+        PeerRequestError::SelfConnection(_) => -9,
+        PeerRequestError::AlreadyConnected(_) => -23,
+        PeerRequestError::NotFound(_) => -24,
+        PeerRequestError::Dial(_) => -10,
+        PeerRequestError::Storage(_) => return RpcError::Internal(e.to_string()),
+    };
+    RpcError::Rpc { code, message: e.to_string() }
+}
+
+/// IP of a peer address given as an IP, `ip:port` or `host:port`
+async fn ban_address(address: &str) -> Result<std::net::IpAddr, RpcError> {
+    match address.parse() {
+        Ok(ip) => Ok(ip),
+        Err(_) => network::connection_manager::resolve_peer_address(address).await.map(|address| address.ip()).map_err(peer_request_error),
+    }
+}
+
+/// An RPC client counted by [`RpcCoordinator::open_connection`]
+pub struct RpcConnection {
+    active_connections: Arc<AtomicUsize>,
+}
+
+impl Drop for RpcConnection {
+    fn drop(&mut self) {
+        self.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
 /// RPC Coordinator implementing the RpcApi trait
 pub struct RpcCoordinator {
@@ -16,10 +91,30 @@ pub struct RpcCoordinator {
     storage: Arc<ConsensusStorage>,
     network: Arc<Hub>,
     mempool: Arc<dyn MempoolInterface>,
-    wallet: Option<Arc<Keys>>,
-    active_connections: Arc<RwLock<usize>>,
-    peers: Arc<RwLock<HashMap<String, String>>>,
+    wallet: Option<Arc<HotWallet>>,
+    active_connections: Arc<AtomicUsize>,
+    max_connections: usize,
+    /// Clients disconnected for not keeping up with their responses
+    dropped_clients: AtomicU64,
+    utxo_subscriptions: Arc<UtxoSubscriptions>,
+    chain_subscriptions: Arc<ChainSubscriptions>,
+    template_subscriptions: Arc<TemplateSubscriptions>,
     recent_block_hashes: Arc<RwLock<BlockHashSet>>,
+    template_bits: u32,
+    /// Mass budget of block templates, coinbase included
+    max_block_mass: u64,
+    /// Most parents a block template references
+    max_block_parents: usize,
+    extranonce_size: usize,
+    /// Recently issued templates, newest last
+    templates: std::sync::Mutex<VecDeque<BlockTemplate>>,
+    coinbase_maturity: u64,
+    /// Subsidy schedule of template coinbases and the coin supply
+    coinbase_config: consensus::ConsensusConfig,
+    clock: Arc<dyn Clock>,
+    log_control: Option<Arc<dyn LogControl>>,
+    network_id: String,
+    sync_status: Option<Arc<dyn SyncStatusProvider>>,
 }
 
 impl RpcCoordinator {
@@ -28,24 +123,263 @@ impl RpcCoordinator {
         storage: Arc<ConsensusStorage>,
         network: Arc<Hub>,
         mempool: Arc<dyn MempoolInterface>,
-        wallet: Option<Arc<Keys>>,
+        wallet: Option<Arc<HotWallet>>,
     ) -> Self {
+        let utxo_subscriptions = Arc::new(UtxoSubscriptions::new());
+        let subscriptions = Arc::downgrade(&utxo_subscriptions);
+        storage.utxo_set().subscribe_changes(Arc::new(move |changes: &UtxoChanges| {
+            if let Some(subscriptions) = subscriptions.upgrade() {
+                subscriptions.notify(changes);
+            }
+        }));
+        // Every block applied to the UTXO set is a new tip to build templates on
+        let template_subscriptions = Arc::new(TemplateSubscriptions::new());
+        let subscriptions = Arc::downgrade(&template_subscriptions);
+        storage.utxo_set().subscribe_changes(Arc::new(move |_: &UtxoChanges| {
+            if let Some(subscriptions) = subscriptions.upgrade() {
+                subscriptions.notify();
+            }
+        }));
+        let chain_subscriptions = Arc::new(ChainSubscriptions::new());
+        let subscriptions = Arc::downgrade(&chain_subscriptions);
+        let (ghostdag, chain_storage) = (processor.ghostdag_manager(), storage.clone());
+        processor.subscribe_chain_changes(Arc::new(move |changes: &ChainChanges| {
+            if let Some(subscriptions) = subscriptions.upgrade() {
+                subscriptions.notify(changes.blue_score, |include| {
+                    virtual_chain_changed(&ghostdag, &chain_storage, &changes.removed, &changes.added, include)
+                });
+            }
+        }));
         Self {
             processor,
             storage,
             network,
             mempool,
             wallet,
-            active_connections: Arc::new(RwLock::new(0)),
-            peers: Arc::new(RwLock::new(HashMap::new())),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            max_connections: DEFAULT_MAX_RPC_CONNECTIONS,
+            dropped_clients: AtomicU64::new(0),
+            utxo_subscriptions,
+            chain_subscriptions,
+            template_subscriptions,
             recent_block_hashes: Arc::new(RwLock::new(BlockHashSet::new())),
+            template_bits: DEFAULT_TEMPLATE_BITS,
+            max_block_mass: MAX_BLOCK_MASS,
+            max_block_parents: DEFAULT_TEMPLATE_PARENTS,
+            extranonce_size: 0,
+            templates: std::sync::Mutex::new(VecDeque::new()),
+            coinbase_maturity: COINBASE_MATURITY,
+            coinbase_config: consensus::ConsensusConfig::default(),
+            clock: Arc::new(SystemClock),
+            log_control: None,
+            network_id: DEFAULT_NETWORK_ID.to_string(),
+            sync_status: None,
         }
     }
 
-    // Helper methods for hex encoding/decoding
+    /// Time source for block template timestamps
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Log filter that set_log_level adjusts
+    pub fn with_log_control(self, log_control: Arc<dyn LogControl>) -> Self {
+        Self { log_control: Some(log_control), ..self }
+    }
+
+    /// Network the node runs on, as reported by getInfo and getBlockDagInfo
+    pub fn with_network_id(self, network_id: String) -> Self {
+        Self { network_id, ..self }
+    }
+
+    /// Sync state reported by getInfo; without it the node counts as synced
+    pub fn with_sync_status(self, sync_status: Arc<dyn SyncStatusProvider>) -> Self {
+        Self { sync_status: Some(sync_status), ..self }
+    }
+
+    /// Compact difficulty bits put in block templates whose parents' difficulty window is unavailable
+    pub fn with_template_bits(self, template_bits: u32) -> Self {
+        Self { template_bits, ..self }
+    }
+
+    /// Fill block templates up to `max_block_mass`
+    pub fn with_max_block_mass(self, max_block_mass: u64) -> Self {
+        Self { max_block_mass, ..self }
+    }
+
+    /// Build block templates on at most `max_block_parents` tips
+    pub fn with_max_block_parents(self, max_block_parents: usize) -> Self {
+        Self { max_block_parents, ..self }
+    }
+
+    /// Coinbase payload bytes reserved in block templates for pool extranonces
+    pub fn with_extranonce_size(self, extranonce_size: usize) -> Self {
+        Self { extranonce_size, ..self }
+    }
+
+    /// DAA score distance after which coinbase outputs count as spendable balance
+    pub fn with_coinbase_maturity(self, coinbase_maturity: u64) -> Self {
+        Self { coinbase_maturity, ..self }
+    }
+
+    /// Pay template coinbases `initial_subsidy` sompi halving every
+    /// `halving_interval` blocks, and report the coin supply by that schedule
+    pub fn with_subsidy_schedule(self, initial_subsidy: u64, halving_interval: u64) -> Self {
+        let coinbase_config = consensus::ConsensusConfig {
+            initial_subsidy,
+            subsidy_halving_interval: halving_interval,
+            ..self.coinbase_config.clone()
+        };
+        Self { coinbase_config, ..self }
+    }
+
+    /// RPC clients served at once
+    pub fn with_max_connections(self, max_connections: usize) -> Self {
+        Self { max_connections, ..self }
+    }
+
+    /// Count an RPC client as connected until the returned guard is dropped.
+    /// `None` if `max_connections` clients are connected already.
+    pub fn open_connection(&self) -> Option<RpcConnection> {
+        self.active_connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| (active < self.max_connections).then_some(active + 1))
+            .ok()
+            .map(|_| RpcConnection { active_connections: self.active_connections.clone() })
+    }
+
+    /// RPC clients connected now
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// Count a client disconnected for not keeping up with its responses
+    pub fn record_dropped_client(&self) {
+        self.dropped_clients.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Clients disconnected for not keeping up with their responses since startup
+    pub fn dropped_clients(&self) -> u64 {
+        self.dropped_clients.load(Ordering::SeqCst)
+    }
+
+    /// Address-scoped utxosChanged subscriptions, notified as blocks are applied
+    pub fn utxo_subscriptions(&self) -> Arc<UtxoSubscriptions> {
+        self.utxo_subscriptions.clone()
+    }
+
+    /// virtualChainChanged subscriptions, notified as the selected chain changes
+    pub fn chain_subscriptions(&self) -> Arc<ChainSubscriptions> {
+        self.chain_subscriptions.clone()
+    }
+
+    /// newBlockTemplate subscriptions, notified as blocks are added
+    pub fn template_subscriptions(&self) -> Arc<TemplateSubscriptions> {
+        self.template_subscriptions.clone()
+    }
+
+    /// Subscribe to virtualChainChanged from the current tip or, given the tip
+    /// a reconnecting subscriber last knew, from there with a catch-up change
+    pub fn subscribe_virtual_chain(
+        &self,
+        include_accepted_transaction_ids: bool,
+        start_hash: Option<Hash>,
+    ) -> Result<(u64, ChainTip, tokio::sync::mpsc::Receiver<VirtualChainChangedNotification>), RpcError> {
+        self.chain_subscriptions.subscribe(include_accepted_transaction_ids, || {
+            let tip = self.chain_tip()?;
+            let Some(start) = start_hash else {
+                return Ok((tip, None));
+            };
+            let changes = self.chain_changes_from(Some(start), tip.hash, include_accepted_transaction_ids)?;
+            Ok((tip, Some(changes)))
+        })
+    }
+
+    /// Highest block of the selected chain
+    fn chain_tip(&self) -> Result<ChainTip, RpcError> {
+        let (blue_score, hash) = match self.storage.block_store().get_chain_tip() {
+            Some(tip) => tip,
+            // Nothing indexed before the first block after genesis
+            None => {
+                let virtual_data = self.processor.get_virtual_block_data(4)
+                    .map_err(|e| RpcError::Rpc { code: -5, message: format!("No selected chain yet: {}", e) })?;
+                let hash = virtual_data.ghostdag_data.selected_parent;
+                (self.processor.ghostdag_manager().get_blue_score(&hash).unwrap_or(0), hash)
+            }
+        };
+        Ok(ChainTip { hash, blue_score })
+    }
+
+    /// Chain blocks to remove and add to move from a chain ending at `start`
+    /// to the selected chain ending at `tip`; the whole chain up to `tip` when
+    /// there is no start
+    fn chain_changes_from(&self, start: Option<Hash>, tip: Hash, include_accepted_transaction_ids: bool) -> Result<VirtualChainChanged, RpcError> {
+        let ghostdag = self.processor.ghostdag_manager();
+        let block_store = self.storage.block_store();
+
+        // Down from the start to where it meets the current chain...
+        let mut removed = Vec::new();
+        let mut fork = None;
+        if let Some(start) = start {
+            let not_found = || RpcError::Rpc { code: -5, message: format!("Block {} not found", start) };
+            let mut current = start;
+            loop {
+                let data = ghostdag.get_ghostdag_data(&current).ok_or_else(not_found)?;
+                if block_store.get_chain_block_by_blue_score(data.blue_score) == Some(current) || data.selected_parent == current {
+                    break;
+                }
+                removed.push(current);
+                current = data.selected_parent;
+            }
+            fork = Some(current);
+        }
+        // ...and up from there to the tip
+        let mut added = Vec::new();
+        let mut block = tip;
+        while Some(block) != fork {
+            added.push(block);
+            match ghostdag.get_selected_parent(&block) {
+                Some(parent) if parent != block => block = parent,
+                _ => break,
+            }
+        }
+        added.reverse();
+        Ok(virtual_chain_changed(&ghostdag, &self.storage, &removed, &added, include_accepted_transaction_ids))
+    }
+
+    /// Address of the node's wallet that templates requested without one pay to
+    pub fn mining_address(&self) -> Option<String> {
+        self.wallet.as_ref().map(|wallet| wallet.mining_address())
+    }
+
+    fn wallet(&self) -> Result<&Arc<HotWallet>, RpcError> {
+        self.wallet.as_ref().ok_or_else(|| RpcError::Rpc { code: -18, message: "Wallet not available".to_string() })
+    }
+
+    /// Add `tx` to the mempool and announce it to peers
+    async fn submit_transaction(&self, tx: Transaction) -> Result<Hash, RpcError> {
+        self.mempool.add_transaction(tx.clone()).map_err(|e| RpcError::Rpc {
+            code: -25,
+            message: format!("Transaction rejected: {}", e),
+        })?;
+
+        // Announce to peers (best-effort); they request the transaction if they lack it
+        let message = network::protowire::Message::Transaction(tx.clone());
+        self.network.broadcast(message).await;
+
+        Ok(tx.hash())
+    }
+
+    /// Unspent outputs locked by the script of `address`
+    fn address_utxos(&self, address: &str) -> Result<Vec<(TransactionOutpoint, UtxoEntry)>, RpcError> {
+        let script_public_key = wallet::Address::to_script_pub_key(address)
+            .map_err(|e| RpcError::Rpc { code: -5, message: format!("Invalid address {}: {}", address, e) })?;
+        Ok(self.storage.utxo_set().get_utxos_by_script(&script_public_key))
+    }
+
+    // Helper methods for hex encoding/decoding, in the canonical encoding
     fn decode_hex_to_block(&self, hex: &str) -> Result<Block, RpcError> {
         match hex::decode(hex) {
-            Ok(bytes) => match bincode::deserialize::<Block>(&bytes) {
+            Ok(bytes) => match encoding::decode_block(&bytes) {
                 Ok(block) => Ok(block),
                 Err(e) => Err(RpcError::Rpc { code: -22, message: format!("Failed to deserialize block: {}", e) }),
             },
@@ -55,7 +389,7 @@ impl RpcCoordinator {
 
     fn decode_hex_to_transaction(&self, hex: &str) -> Result<Transaction, RpcError> {
         match hex::decode(hex) {
-            Ok(bytes) => match bincode::deserialize::<Transaction>(&bytes) {
+            Ok(bytes) => match encoding::decode_transaction(&bytes) {
                 Ok(tx) => Ok(tx),
                 Err(e) => Err(RpcError::Rpc { code: -22, message: format!("Failed to deserialize transaction: {}", e) }),
             },
@@ -64,10 +398,7 @@ impl RpcCoordinator {
     }
 
     fn encode_block_to_hex(&self, block: &Block) -> String {
-        match bincode::serialize(block) {
-            Ok(bytes) => hex::encode(&bytes),
-            Err(_) => "".to_string(),
-        }
+        hex::encode(encoding::encode_block(block))
     }
 
     fn get_virtual_daa_score(&self) -> u64 {
@@ -97,11 +428,19 @@ impl RpcCoordinator {
 
     fn get_past_median_time(&self) -> u64 {
         // Past median time is calculated from selected parent blocks' timestamps
-        // For now, use current Unix timestamp as a reasonable default
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs()
+        // For now, use the current time in seconds as a reasonable default
+        self.clock.now_millis() / 1000
+    }
+
+    /// Virtual blue score and the selected chain ending at the virtual selected parent
+    fn get_virtual_chain(&self) -> (u64, Vec<Hash>) {
+        match self.processor.get_virtual_block_data(4) {
+            Ok(vbd) => {
+                let chain = self.processor.ghostdag_manager().get_selected_chain(vbd.ghostdag_data.selected_parent);
+                (vbd.ghostdag_data.blue_score, chain)
+            }
+            Err(_) => (0, vec![]),
+        }
     }
 
     fn get_current_difficulty(&self) -> f64 {
@@ -115,6 +454,32 @@ impl RpcCoordinator {
 
 #[async_trait::async_trait]
 impl RpcApi for RpcCoordinator {
+    async fn get_info(&self) -> Result<GetInfoResponse, RpcError> {
+        let min_relay_feerate = self.mempool.min_relay_feerate();
+        let block_store = self.storage.block_store();
+        Ok(GetInfoResponse {
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            network: self.network_id.clone(),
+            is_synced: self.sync_status.as_ref().map_or(true, |sync| sync.is_synced()),
+            sync_progress: self.sync_status.as_ref().map_or(1.0, |sync| sync.progress()),
+            peer_count: self.network.peer_count(),
+            block_count: block_store.block_count() as u64,
+            header_count: block_store.header_count() as u64,
+            mempool_size: self.mempool.size(),
+            min_relay_feerate,
+            dust_threshold: dust_threshold(1, STORAGE_MASS_PARAMETER, min_relay_feerate),
+            virtual_daa_score: self.get_virtual_daa_score(),
+            coinbase_maturity: self.coinbase_maturity,
+            nat: self.network.nat_status().into(),
+        })
+    }
+
+    async fn set_log_level(&self, target: String, level: String) -> Result<String, RpcError> {
+        let log_control =
+            self.log_control.as_ref().ok_or_else(|| RpcError::Rpc { code: -1, message: "Log control not available".to_string() })?;
+        log_control.set_log_level(&target, &level).map_err(|message| RpcError::Rpc { code: -8, message })
+    }
+
     async fn get_block_count(&self) -> Result<u64, RpcError> {
         let count = self.storage.block_store().block_count();
         Ok(count as u64)
@@ -128,6 +493,50 @@ impl RpcApi for RpcCoordinator {
             })
     }
 
+    async fn get_block_verbose(&self, hash: Hash) -> Result<RpcBlockVerbose, RpcError> {
+        // Blocks whose transactions were pruned or never downloaded still have their header
+        let (block, is_header_only) = match self.storage.get_block(&hash) {
+            Some(block) => (block, false),
+            None => match self.storage.get_header(&hash) {
+                Some(header) => (Block::new(header, vec![]), true),
+                None => return Err(RpcError::Rpc { code: -5, message: "Block not found".to_string() }),
+            },
+        };
+        let (virtual_blue_score, chain) = self.get_virtual_chain();
+        let children = self.storage.block_store().get_children(&hash);
+        let mut verbose = block_verbose(&block, &self.processor.ghostdag_manager(), &chain, virtual_blue_score, children)?;
+        let chain_blocks: HashSet<Hash> = chain.into_iter().collect();
+        verbose.block.verbose_data = Some(block_verbose_data(
+            &block.header,
+            &self.processor.ghostdag_manager(),
+            &chain_blocks,
+            verbose.children_hashes.clone(),
+            is_header_only,
+        )?);
+        if let Some(provenance) = self.storage.get_provenance(&hash) {
+            verbose.source = Some(provenance.source.to_string());
+            verbose.first_seen = Some(provenance.first_seen);
+        }
+        Ok(verbose)
+    }
+
+    async fn get_block_status(&self, hash: Hash) -> Result<BlockStatusInfo, RpcError> {
+        let provenance = self.storage.get_provenance(&hash);
+        let status = match provenance {
+            Some(provenance) if provenance.invalid => "invalid",
+            _ if self.storage.has_block(&hash) => "valid",
+            _ if self.storage.has_header(&hash) => "headerOnly",
+            Some(_) => "orphan",
+            None => return Err(RpcError::Rpc { code: -5, message: "Block not found".to_string() }),
+        };
+        Ok(BlockStatusInfo {
+            hash,
+            status: status.to_string(),
+            source: provenance.map(|provenance| provenance.source.to_string()),
+            first_seen: provenance.map(|provenance| provenance.first_seen),
+        })
+    }
+
     async fn get_block_dag_info(&self) -> Result<BlockDagInfo, RpcError> {
         let tip_hashes = vec![]; // Tip tracking not implemented yet
         let virtual_parent_hashes = self.get_virtual_parent_hashes();
@@ -137,36 +546,142 @@ impl RpcApi for RpcCoordinator {
             block_count: self.get_block_count().await?,
             tip_hashes,
             difficulty: self.get_current_difficulty(),
-            network: "testnet".to_string(), // default to testnet for this workspace
+            network: self.network_id.clone(),
             virtual_parent_hashes,
             pruning_point_hash,
         })
     }
 
-    async fn get_blocks(&self, _low_hash: Option<Hash>, _include_blocks: bool, _include_transactions: bool) -> Result<GetBlocksResponse, RpcError> {
-        // Minimal implementation: return the requested block when low_hash is provided
-        if let Some(low_hash) = _low_hash {
-            if let Some(b) = self.storage.get_block(&low_hash) {
-                return Ok(GetBlocksResponse { blocks: vec![b], next_block_hashes: vec![] });
-            }
-        }
+    async fn get_blocks(
+        &self,
+        low_hash: Option<Hash>,
+        include_blocks: bool,
+        include_transactions: bool,
+        include_verbose_data: bool,
+    ) -> Result<GetBlocksResponse, RpcError> {
+        let page = self.storage.get_blocks_after(low_hash.as_ref(), GET_BLOCKS_PAGE_SIZE).map_err(|e| match e {
+            ConsensusError::BlockNotFound(hash) => RpcError::Rpc { code: -5, message: format!("Block {} not found", hash) },
+            ConsensusError::BlockPruned(hash) => RpcError::Rpc { code: -6, message: format!("Block {} has been pruned", hash) },
+            e => RpcError::Internal(e.to_string()),
+        })?;
+
+        let next_block_hashes = page.iter().map(|b| b.header.hash).collect();
+        let block_verbose_data = if include_blocks && include_verbose_data {
+            let ghostdag = self.processor.ghostdag_manager();
+            let chain_blocks: HashSet<Hash> = self.get_virtual_chain().1.into_iter().collect();
+            page.iter()
+                .map(|b| {
+                    let children = self.storage.block_store().get_children(&b.header.hash);
+                    block_verbose_data(&b.header, &ghostdag, &chain_blocks, children, false)
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            vec![]
+        };
+        let blocks = if include_blocks {
+            page.into_iter()
+                .map(|mut b| {
+                    // Headers only unless transactions were asked for
+                    if !include_transactions {
+                        b.transactions.clear();
+                    }
+                    b
+                })
+                .collect()
+        } else {
+            vec![]
+        };
 
-        Ok(GetBlocksResponse { blocks: vec![], next_block_hashes: vec![] })
+        Ok(GetBlocksResponse { blocks, next_block_hashes, block_verbose_data })
+    }
+
+    async fn get_headers(&self, start_hash: Hash, limit: usize) -> Result<Vec<String>, RpcError> {
+        let (_, chain) = self.get_virtual_chain();
+        let position = chain.iter().position(|hash| *hash == start_hash).ok_or_else(|| RpcError::Rpc {
+            code: -5,
+            message: format!("Block {} is not on the selected chain", start_hash),
+        })?;
+        chain[position + 1..]
+            .iter()
+            .take(limit.min(GET_HEADERS_MAX))
+            .map(|hash| {
+                self.storage
+                    .get_header(hash)
+                    .map(|header| hex::encode(encoding::encode_header(&header)))
+                    .ok_or_else(|| RpcError::Internal(format!("Header {} missing from storage", hash)))
+            })
+            .collect()
+    }
+
+    async fn get_virtual_chain_from_block(&self, start_hash: Option<Hash>, include_accepted_transaction_ids: bool) -> Result<VirtualChainChanged, RpcError> {
+        let tip = self.chain_tip()?;
+        self.chain_changes_from(start_hash, tip.hash, include_accepted_transaction_ids)
+    }
+
+    async fn get_block_hex(&self, hash: Hash) -> Result<String, RpcError> {
+        let block = self.get_block(hash).await?;
+        Ok(self.encode_block_to_hex(&block))
+    }
+
+    async fn decode_block(&self, block_hex: String) -> Result<RpcBlock, RpcError> {
+        Ok(RpcBlock::from(&self.decode_hex_to_block(&block_hex)?))
     }
 
     async fn get_peer_info(&self) -> Result<Vec<PeerInfo>, RpcError> {
-        // Network hub integration not implemented yet
-        Ok(vec![])
+        Ok(self.network.peer_infos().await.into_iter().map(PeerInfo::from).collect())
+    }
+
+    async fn get_connection_count(&self) -> Result<ConnectionCounts, RpcError> {
+        let (inbound_peers, outbound_peers) = self.network.peer_counts();
+        Ok(ConnectionCounts {
+            rpc_connections: self.active_connections(),
+            max_rpc_connections: self.max_connections,
+            inbound_peers,
+            outbound_peers,
+        })
+    }
+
+    async fn add_peer(&self, address: String, is_permanent: bool) -> Result<(), RpcError> {
+        self.network.connect_peer(&address, is_permanent).await.map(|_| ()).map_err(peer_request_error)
+    }
+
+    async fn remove_peer(&self, address: String) -> Result<(), RpcError> {
+        self.network.remove_peer_address(&address).await.map(|_| ()).map_err(peer_request_error)
     }
 
-    async fn add_peer(&self, _address: String, _is_permanent: bool) -> Result<(), RpcError> {
-        // Peer addition not implemented; no-op for now
+    async fn ban_peer(&self, address: String, duration_secs: u64) -> Result<(), RpcError> {
+        let ip = ban_address(&address).await?;
+        self.network.ban(ip, std::time::Duration::from_secs(duration_secs));
         Ok(())
     }
 
+    async fn unban_peer(&self, address: String) -> Result<(), RpcError> {
+        let ip = ban_address(&address).await?;
+        if !self.network.unban(ip) {
+            return Err(RpcError::Rpc { code: -30, message: format!("{} is not banned", ip) });
+        }
+        Ok(())
+    }
+
+    async fn get_banned_peers(&self) -> Result<Vec<BannedPeer>, RpcError> {
+        Ok(self
+            .network
+            .banned()
+            .into_iter()
+            .map(|(ip, remaining)| BannedPeer { address: ip.to_string(), remaining_secs: remaining.as_secs() })
+            .collect())
+    }
+
     async fn submit_block(&self, block: Block) -> Result<Hash, RpcError> {
-        match self.processor.process_block(block) {
-            Ok(result) => Ok(result.hash),
+        let included: Vec<Hash> = block.transactions.iter().filter(|tx| !tx.is_coinbase()).map(|tx| tx.id()).collect();
+        match self.processor.process_block_from(block, BlockSource::Rpc).0 {
+            Ok(result) => {
+                // Mined transactions must not be offered to the next template again
+                for tx_id in included {
+                    let _ = self.mempool.remove_transaction(&tx_id.to_string());
+                }
+                Ok(result.hash)
+            }
             Err(e) => Err(RpcError::Rpc {
                 code: -25,
                 message: format!("Block submission failed: {:?}", e),
@@ -176,37 +691,55 @@ impl RpcApi for RpcCoordinator {
 
     async fn send_raw_transaction(&self, tx_hex: String, _allow_high_fees: bool) -> Result<Hash, RpcError> {
         let tx = self.decode_hex_to_transaction(&tx_hex)?;
+        self.submit_transaction(tx).await
+    }
 
-        // Add to mempool
-        self.mempool.add_transaction(tx.clone()).map_err(|e| RpcError::Rpc {
-            code: -25,
-            message: format!("Transaction rejected: {}", e),
-        })?;
-
-        // Broadcast to network (best-effort)
-        let message = network::protowire::Message::Transaction(tx.clone());
-        self.network.broadcast(message).await;
+    async fn get_transaction_hex(&self, id: Hash) -> Result<String, RpcError> {
+        let tx = self.get_transaction(id).await?;
+        Ok(hex::encode(encoding::encode_transaction(&tx)))
+    }
 
-        Ok(tx.hash())
+    async fn decode_transaction(&self, tx_hex: String) -> Result<RpcTransaction, RpcError> {
+        Ok(RpcTransaction::from(&self.decode_hex_to_transaction(&tx_hex)?))
     }
 
     async fn get_mempool_info(&self) -> Result<MempoolInfo, RpcError> {
+        let entries = self.mempool.get_entries();
+        let samples = mempool_fee_samples(&entries);
         Ok(MempoolInfo {
-            size: self.mempool.size(),
+            size: entries.len(),
             bytes: 0,
+            total_fees: entries.iter().map(|entry| entry.fee).sum(),
+            total_mass: samples.iter().map(|sample| sample.mass).sum(),
+            estimated_block_count: FeeEstimator::default().estimated_block_count(&samples),
         })
     }
 
+    async fn get_fee_estimate(&self) -> Result<FeeEstimate, RpcError> {
+        let block_feerates: Vec<f64> = self
+            .processor
+            .recent_block_fees(FEE_ESTIMATE_BLOCK_WINDOW)
+            .iter()
+            .filter_map(|record| record.feerate())
+            .collect();
+        let mempool = mempool_fee_samples(&self.mempool.get_entries());
+        Ok(FeeEstimator::default().estimate(&block_feerates, &mempool))
+    }
+
     async fn get_mempool_entries(&self, _include_orphan_pool: bool, _filter_transaction_pool: bool) -> Result<Vec<MempoolEntry>, RpcError> {
         Ok(self.mempool.get_entries())
     }
 
-    async fn get_block_template(&self, pay_address: String, _extra_data: Option<String>) -> Result<BlockTemplate, RpcError> {
+    async fn flush_mempool(&self) -> Result<usize, RpcError> {
+        self.mempool.flush().map_err(RpcError::Internal)
+    }
+
+    async fn get_block_template(&self, pay_address: String, extra_data: Option<String>) -> Result<BlockTemplate, RpcError> {
         // Build a simple block template using virtual parents from the processor.
         // If the virtual parent data is not yet available (early startup), fall back
         // to genesis so external tools (miners) can still request templates.
-        let transactions = self.mempool.get_all_transactions();
-        let parent_hashes = match self.processor.get_virtual_block_data(4) {
+        let entries = self.mempool.get_entries();
+        let parent_hashes = match self.processor.get_virtual_block_data(self.max_block_parents) {
             Ok(vbd) => vbd.parents,
             Err(_e) => {
                 // This is normal when the chain is empty or just starting
@@ -214,24 +747,37 @@ impl RpcApi for RpcCoordinator {
                 vec![consensus_core::ZERO_HASH]
             }
         };
+        // The header must commit to the GHOSTDAG data of exactly these parents
+        let (blue_score, blue_work) = self
+            .processor
+            .ghostdag_manager()
+            .get_virtual_ghostdag_data(parent_hashes.clone())
+            .map_or((0, Default::default()), |data| (data.blue_score, data.blue_work));
 
-        // Try to construct a realistic coinbase transaction and merkle root.
-        // Use the consensus coinbase processor with default config to compute reward.
-        let config = consensus::ConsensusConfig::default();
-        let coinbase_proc = consensus::process::coinbase::CoinbaseProcessor::new(config);
+        // Try to construct a realistic coinbase transaction and merkle root,
+        // paying the subsidy of the configured schedule
+        let coinbase_proc = consensus::process::coinbase::CoinbaseProcessor::new(self.coinbase_config.clone());
 
-        // Build a ScriptPublicKey from the provided pay_address string (best-effort).
+        // Pay the coinbase to the script of the given address
         let miner_spk = if pay_address.is_empty() {
             // Fallback to an empty script public key
             consensus_core::tx::ScriptPublicKey::new(0, Vec::new().into())
         } else {
-            consensus_core::tx::ScriptPublicKey::new(0, pay_address.clone().into_bytes().into())
+            wallet::Address::to_script_pub_key(&pay_address)
+                .map_err(|e| RpcError::Rpc { code: -5, message: format!("Invalid pay address {}: {}", pay_address, e) })?
         };
 
-        let block_height = self.get_virtual_daa_score();
+        let block_height = blue_score;
+
+        // Create coinbase tx with fees=0 (mempool fees not yet tracked), carrying the miner's
+        // extra data and room for an extranonce
+        let extra_data = extra_data.unwrap_or_default();
+        let coinbase_tx = coinbase_proc
+            .create_coinbase_transaction_with_extranonce(&miner_spk, block_height, 0, extra_data.as_bytes(), self.extranonce_size)
+            .map_err(|message| RpcError::Rpc { code: -8, message })?;
 
-        // Create coinbase tx with fees=0 (mempool fees not yet tracked)
-        let coinbase_tx = coinbase_proc.create_coinbase_transaction(&miner_spk, block_height, 0);
+        // Fill the rest of the block by ancestor feerate, parents before children
+        let transactions = MempoolGraph::new(&entries).select(self.max_block_mass.saturating_sub(coinbase_tx.calculate_mass()));
 
         // Build full transaction list (coinbase first)
         let mut full_txs = Vec::with_capacity(1 + transactions.len());
@@ -252,16 +798,12 @@ impl RpcApi for RpcCoordinator {
 
         let _merkle_root = compute_merkle_root(&full_txs);
 
-        // Use a placeholder bits value for now (compact representation)
-        // In production, this should come from the difficulty manager
-        let bits: u32 = 0x1f00ffff;
+        // Bits of the virtual selected parent's difficulty window
+        let bits = self.processor.expected_bits(&parent_hashes).unwrap_or(self.template_bits);
 
         let coinbase_value = coinbase_tx.outputs.get(0).map(|o| o.value).unwrap_or(0);
         // Use milliseconds for better timestamp precision to ensure unique templates
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        let timestamp = self.clock.now_millis();
 
         // Log template details for debugging
         eprintln!(
@@ -274,7 +816,7 @@ impl RpcApi for RpcCoordinator {
             timestamp
         );
 
-        Ok(BlockTemplate {
+        let mut template = BlockTemplate {
             version: 1,
             parent_hashes,
             transactions: full_txs,
@@ -282,8 +824,50 @@ impl RpcApi for RpcCoordinator {
             bits,
             timestamp,
             pay_address,
-            target: format!("{:08x}", bits),
-        })
+            target: consensus_pow::target::target_to_hex(consensus_pow::target::target_from_bits(bits)),
+            daa_score: block_height,
+            blue_score,
+            blue_work,
+            template_id: Default::default(),
+            extranonce_size: self.extranonce_size,
+        };
+        // Identified by the header it yields before any mining
+        template.template_id = template
+            .to_block(0, &vec![0; self.extranonce_size], &CoinbaseOverrides::default())
+            .map_err(RpcError::Internal)?
+            .header
+            .hash;
+
+        let mut templates = self.templates.lock().unwrap();
+        if templates.len() == MAX_CACHED_TEMPLATES {
+            templates.pop_front();
+        }
+        templates.push_back(template.clone());
+        Ok(template)
+    }
+
+    async fn submit_block_with_coinbase(
+        &self,
+        template_id: Hash,
+        nonce: u64,
+        extranonce: Vec<u8>,
+        coinbase_overrides: Option<CoinbaseOverrides>,
+    ) -> Result<Hash, RpcError> {
+        let template = self
+            .templates
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|template| template.template_id == template_id)
+            .cloned()
+            .ok_or_else(|| RpcError::Rpc { code: -8, message: format!("Unknown or expired block template {}", template_id) })?;
+        let block = template
+            .to_block(nonce, &extranonce, &coinbase_overrides.unwrap_or_default())
+            .map_err(|message| RpcError::Rpc { code: -8, message })?;
+        if !consensus_core::hashing::header::validate_pow(&block.header) {
+            return Err(RpcError::Rpc { code: -25, message: "Block does not satisfy the template target".to_string() });
+        }
+        self.submit_block(block).await
     }
 
     async fn estimate_network_hashes_per_second(&self, _window_size: u32, _start_hash: Option<Hash>) -> Result<u64, RpcError> {
@@ -301,30 +885,114 @@ impl RpcApi for RpcCoordinator {
     }
 
     async fn get_balances(&self) -> Result<GetBalancesResponse, RpcError> {
-        if let Some(_wallet) = &self.wallet {
-            // TODO: Implement full wallet balance calculation
-            // For now, return placeholder balances
-            // Real implementation would:
-            // 1. Get all addresses from wallet
-            // 2. Query UTXO set for each address
-            // 3. Sum spendable and pending UTXOs separately
-            eprintln!("[Wallet] Returning placeholder balances (full UTXO integration pending)");
-            Ok(GetBalancesResponse {
-                available_balance: 0,
-                pending_balance: 0,
-            })
-        } else {
-            Err(RpcError::Rpc {
-                code: -18,
-                message: "Wallet not available".to_string(),
-            })
+        let wallet = self.wallet()?;
+        let keys = wallet.keys();
+
+        // The wallet's derived addresses, and a gap limit past the used ones in case the seed was restored
+        let discovery = discover_with(keys, DEFAULT_GAP_LIMIT, |address| {
+            let amount = self.address_utxos(&address).map(|utxos| (!utxos.is_empty()).then(|| utxos.iter().map(|(_, entry)| entry.amount).sum::<u64>()));
+            async move { amount.map_err(|e| e.to_string()) }
+        })
+        .await
+        .map_err(RpcError::Internal)?;
+        let mut addresses: BTreeSet<String> = discovery.watched_addresses(keys, DEFAULT_GAP_LIMIT).map_err(RpcError::Internal)?.into_iter().collect();
+        addresses.extend(wallet.addresses());
+
+        let virtual_daa_score = self.get_virtual_daa_score();
+        let mut scripts = HashSet::new();
+        let mut confirmed = Vec::new();
+        for address in &addresses {
+            for (outpoint, entry) in self.address_utxos(address)? {
+                let mature = !entry.is_coinbase || virtual_daa_score.saturating_sub(entry.block_daa_score) >= self.coinbase_maturity;
+                confirmed.push(ConfirmedOutput { outpoint, amount: entry.amount, mature });
+            }
+            scripts.insert(wallet::Address::to_script_pub_key(address).map_err(RpcError::Internal)?);
+        }
+        // Mempool transactions spending the wallet's outputs are its own sends
+        let pending: Vec<Transaction> =
+            self.mempool.get_entries().into_iter().filter(|entry| !entry.is_orphan).map(|entry| entry.transaction).collect();
+        let balance = Balance::compute(&confirmed, &pending, |script_public_key| scripts.contains(script_public_key));
+
+        Ok(GetBalancesResponse {
+            available_balance: balance.available,
+            immature_balance: balance.immature,
+            pending_incoming_balance: balance.pending_incoming,
+            pending_outgoing_change_balance: balance.pending_outgoing_change,
+        })
+    }
+
+    async fn get_new_address(&self) -> Result<String, RpcError> {
+        self.wallet()?.new_address().map_err(RpcError::Internal)
+    }
+
+    async fn send_to_address(&self, address: String, amount: u64) -> Result<Hash, RpcError> {
+        let wallet = self.wallet()?;
+
+        // Mature outputs of the wallet not already spent by a mempool transaction
+        let spent: HashSet<TransactionOutpoint> = self
+            .mempool
+            .get_entries()
+            .iter()
+            .flat_map(|entry| entry.transaction.inputs.iter().map(|input| input.previous_outpoint))
+            .collect();
+        let virtual_daa_score = self.get_virtual_daa_score();
+        let mut utxos = HashMap::new();
+        for wallet_address in wallet.addresses() {
+            for (outpoint, entry) in self.address_utxos(&wallet_address)? {
+                let mature = !entry.is_coinbase || virtual_daa_score.saturating_sub(entry.block_daa_score) >= self.coinbase_maturity;
+                if mature && !spent.contains(&outpoint) {
+                    utxos.insert(outpoint, entry);
+                }
+            }
+        }
+
+        let tx = wallet
+            .sign_send(&utxos, &address, amount, 1, self.mempool.min_relay_feerate())
+            .map_err(|e| RpcError::Rpc { code: -6, message: e })?;
+        self.submit_transaction(tx).await
+    }
+
+    async fn get_balance_by_address(&self, address: String) -> Result<AddressBalance, RpcError> {
+        let utxos = self.address_utxos(&address)?;
+        let virtual_daa_score = self.get_virtual_daa_score();
+        let (mut balance, mut pending_balance) = (0u64, 0u64);
+        for (_, entry) in &utxos {
+            if entry.is_coinbase && virtual_daa_score.saturating_sub(entry.block_daa_score) < self.coinbase_maturity {
+                pending_balance += entry.amount;
+            } else {
+                balance += entry.amount;
+            }
         }
+        Ok(AddressBalance { address, balance, pending_balance, utxo_count: utxos.len() as u32 })
+    }
+
+    async fn get_utxos_by_address(&self, address: String) -> Result<Vec<RpcUtxoByAddress>, RpcError> {
+        Ok(self
+            .address_utxos(&address)?
+            .into_iter()
+            .map(|(outpoint, utxo_entry)| RpcUtxoByAddress { address: address.clone(), outpoint, utxo_entry })
+            .collect())
+    }
+
+    async fn get_virtual_utxo_entry(&self, outpoint: TransactionOutpoint) -> Result<Option<UtxoEntry>, RpcError> {
+        let confirmed = self.storage.utxo_set().get_utxo(&outpoint);
+        Ok(virtual_utxo_entry(&outpoint, confirmed, &self.mempool.get_all_transactions(), self.get_virtual_daa_score()))
     }
 
     async fn get_virtual_selected_parent_blue_score(&self) -> Result<u64, RpcError> {
         Ok(self.get_virtual_daa_score())
     }
 
+    async fn get_coin_supply(&self) -> Result<CoinSupply, RpcError> {
+        let coinbase_proc = consensus::process::coinbase::CoinbaseProcessor::new(self.coinbase_config.clone());
+        let emitted = coinbase_proc.calculate_emission(self.get_virtual_daa_score());
+        let burned = u64::try_from(self.storage.utxo_set().burned_amount()).unwrap_or(u64::MAX);
+        Ok(CoinSupply {
+            circulating_sompi: emitted.saturating_sub(burned),
+            max_sompi: coinbase_proc.max_supply(),
+        })
+    }
+
     async fn submit_block_hex(&self, block_hex: String) -> Result<Hash, RpcError> {
         let block = self.decode_hex_to_block(&block_hex)?;
         let block_hash = block.header.hash;
@@ -372,13 +1040,17 @@ impl RpcApi for RpcCoordinator {
     }
     
     async fn get_block_by_height(&self, height: u64) -> Result<Block, RpcError> {
-        // Get all blocks and find the one with matching DAA score (height)
-        let all_blocks = self.storage.block_store().get_all_blocks();
-
-        for block in all_blocks {
-            if block.header.daa_score == height {
-                return Ok(block);
-            }
+        // Height is the DAA score; of several blocks sharing it, a chain block wins
+        let block_store = self.storage.block_store();
+        let hashes: Vec<Hash> =
+            block_store.get_blocks_by_daa_range(height, height).into_iter().filter(|hash| block_store.has_block(hash)).collect();
+        let on_chain = |hash: &&Hash| {
+            block_store
+                .get_header(hash)
+                .is_some_and(|header| block_store.get_chain_block_by_blue_score(header.blue_score) == Some(**hash))
+        };
+        if let Some(block) = hashes.iter().find(on_chain).or(hashes.first()).and_then(|hash| block_store.get_block(hash)) {
+            return Ok(block);
         }
 
         Err(RpcError::Rpc {
@@ -388,26 +1060,52 @@ impl RpcApi for RpcCoordinator {
     }
     
     async fn get_transaction(&self, hash: Hash) -> Result<Transaction, RpcError> {
-        // Try to get from mempool first
-        let mempool_entries = self.mempool.get_entries();
-        for entry in mempool_entries {
-            if entry.transaction.hash() == hash {
-                return Ok(entry.transaction);
-            }
+        self.get_transaction_verbose(hash).await.map(|verbose| verbose.transaction)
+    }
+
+    async fn get_transaction_verbose(&self, hash: Hash) -> Result<RpcTransactionVerbose, RpcError> {
+        // Mempool transactions are not accepted yet
+        if let Some(entry) = self.mempool.get_entries().into_iter().find(|entry| entry.transaction.hash() == hash) {
+            return Ok(mempool_transaction_verbose(entry.transaction, entry.fee));
         }
-        
-        // Try to get from blocks
-        // TODO: Implement transaction lookup from blocks
-        Err(RpcError::Rpc {
+
+        // Look the transaction and the ones it spends from up in the transaction index
+        let block_store = self.storage.block_store();
+        let stored_transaction = |id: Hash| {
+            let containing = block_store.blocks_containing_transaction(&id);
+            let tx = containing
+                .iter()
+                .find_map(|block_hash| block_store.get_block_transactions_lazy(block_hash)?.into_iter().find(|tx| tx.hash() == id));
+            (containing, tx)
+        };
+        let (containing, tx) = stored_transaction(hash);
+        let tx = tx.ok_or_else(|| RpcError::Rpc {
             code: -5,
             message: "Transaction not found".to_string(),
-        })
+        })?;
+        let mut known_transactions = HashMap::new();
+        for input in &tx.inputs {
+            let id = input.previous_outpoint.transaction_id;
+            if !known_transactions.contains_key(&id) {
+                if let (_, Some(previous)) = stored_transaction(id) {
+                    known_transactions.insert(id, previous);
+                }
+            }
+        }
+
+        let ghostdag = self.processor.ghostdag_manager();
+        let (virtual_blue_score, chain) = self.get_virtual_chain();
+        let containing: Vec<(Hash, BlockAcceptance)> = containing
+            .into_iter()
+            .map(|block_hash| (block_hash, ghostdag.get_block_acceptance(&chain, &block_hash)))
+            .collect();
+        let fee = transaction_fee(&tx, &known_transactions);
+        Ok(block_transaction_verbose(tx, &containing, virtual_blue_score, fee))
     }
-    
+
     async fn get_recent_blocks(&self, count: usize) -> Result<Vec<Block>, RpcError> {
-        // TODO: Implement recent blocks retrieval
-        // For now, return empty vector
-        Ok(vec![])
+        // Highest DAA scores first, read from the DAA index rather than the whole store
+        Ok(self.storage.block_store().get_recent_blocks(count.min(GET_BLOCKS_PAGE_SIZE)))
     }
     
     async fn get_dag_tips(&self) -> Result<Vec<Hash>, RpcError> {
@@ -416,8 +1114,347 @@ impl RpcApi for RpcCoordinator {
     }
     
     async fn get_block_children(&self, hash: Hash) -> Result<Vec<Hash>, RpcError> {
-        // TODO: Implement block children lookup
-        // This requires maintaining a reverse index of parent->children
-        Ok(vec![])
+        Ok(self.storage.block_store().get_children(&hash))
+    }
+}
+
+/// Verbose data for `block` relative to the selected `chain` (genesis first)
+fn block_verbose(
+    block: &Block,
+    ghostdag: &GhostdagManager,
+    chain: &[Hash],
+    virtual_blue_score: u64,
+    children_hashes: Vec<Hash>,
+) -> Result<RpcBlockVerbose, RpcError> {
+    let hash = block.header.hash;
+    let data = ghostdag.get_ghostdag_data(&hash).ok_or_else(|| RpcError::Rpc {
+        code: -5,
+        message: "Block GHOSTDAG data not found".to_string(),
+    })?;
+    let (merge_set_blues_size, merge_set_reds_size) = ghostdag.get_mergeset_sizes(&hash).unwrap_or_default();
+    let (accepting_block_hash, confirmations) = match ghostdag.get_block_acceptance(chain, &hash) {
+        BlockAcceptance::Accepted { accepting_block, accepting_blue_score } => {
+            (Some(accepting_block), confirmations(virtual_blue_score, accepting_blue_score))
+        }
+        BlockAcceptance::Red { .. } | BlockAcceptance::Pending => (None, 0),
+    };
+    Ok(RpcBlockVerbose {
+        block: RpcBlock::from(block),
+        blue_score: data.blue_score,
+        selected_parent: data.selected_parent,
+        is_chain_block: chain.contains(&hash),
+        merge_set_blues_size,
+        merge_set_reds_size,
+        children_hashes,
+        accepting_block_hash,
+        confirmations,
+        source: None,
+        first_seen: None,
+    })
+}
+
+/// GHOSTDAG coloring and DAG links of the block of `header`
+fn block_verbose_data(
+    header: &Header,
+    ghostdag: &GhostdagManager,
+    chain_blocks: &HashSet<Hash>,
+    children_hashes: Vec<Hash>,
+    is_header_only: bool,
+) -> Result<RpcBlockVerboseData, RpcError> {
+    let hash = header.hash;
+    let data = ghostdag.get_ghostdag_data(&hash).ok_or_else(|| RpcError::Rpc {
+        code: -5,
+        message: "Block GHOSTDAG data not found".to_string(),
+    })?;
+    let mut merge_set_reds_hashes: Vec<Hash> = data.red_set.iter().copied().collect();
+    merge_set_reds_hashes.sort();
+    Ok(RpcBlockVerboseData {
+        hash,
+        blue_score: data.blue_score,
+        blue_work: blue_work_hex(data.blue_work),
+        selected_parent: data.selected_parent,
+        merge_set_blues_hashes: data.mergeset_blues,
+        merge_set_reds_hashes,
+        is_chain_block: chain_blocks.contains(&hash),
+        children_hashes,
+        difficulty: consensus_pow::target::target_to_difficulty(consensus_pow::target::target_from_bits(header.bits)),
+        is_header_only,
+    })
+}
+
+/// Verbose data for a transaction still in the mempool
+fn mempool_transaction_verbose(transaction: Transaction, fee: u64) -> RpcTransactionVerbose {
+    RpcTransactionVerbose {
+        transaction,
+        block_hash: None,
+        accepting_block_hash: None,
+        accepting_blue_score: None,
+        confirmations: 0,
+        fee: Some(fee),
+    }
+}
+
+/// Feerate and mass of each mempool entry
+fn mempool_fee_samples(entries: &[MempoolEntry]) -> Vec<MempoolFeeSample> {
+    entries
+        .iter()
+        .map(|entry| {
+            let mass = entry.transaction.calculate_mass().max(1);
+            MempoolFeeSample { feerate: entry.fee as f64 / mass as f64, mass }
+        })
+        .collect()
+}
+
+/// Verbose data for a transaction included in `containing` blocks. The earliest
+/// acceptance wins; if no containing block is accepted the transaction reports
+/// its first block with no accepting block and zero confirmations.
+fn block_transaction_verbose(
+    transaction: Transaction,
+    containing: &[(Hash, BlockAcceptance)],
+    virtual_blue_score: u64,
+    fee: Option<u64>,
+) -> RpcTransactionVerbose {
+    let accepted = containing
+        .iter()
+        .filter_map(|(block_hash, acceptance)| match acceptance {
+            BlockAcceptance::Accepted { accepting_block, accepting_blue_score } => {
+                Some((*block_hash, *accepting_block, *accepting_blue_score))
+            }
+            _ => None,
+        })
+        .min_by_key(|(_, _, accepting_blue_score)| *accepting_blue_score);
+
+    match accepted {
+        Some((block_hash, accepting_block, accepting_blue_score)) => RpcTransactionVerbose {
+            transaction,
+            block_hash: Some(block_hash),
+            accepting_block_hash: Some(accepting_block),
+            accepting_blue_score: Some(accepting_blue_score),
+            confirmations: confirmations(virtual_blue_score, accepting_blue_score),
+            fee,
+        },
+        None => RpcTransactionVerbose {
+            transaction,
+            block_hash: containing.first().map(|(block_hash, _)| *block_hash),
+            accepting_block_hash: None,
+            accepting_blue_score: None,
+            confirmations: 0,
+            fee,
+        },
+    }
+}
+
+/// [`VirtualChainChanged`] for chain blocks `removed` and `added`. A chain block
+/// accepts its own transactions and those of the blocks it merges as blue.
+fn virtual_chain_changed(
+    ghostdag: &GhostdagManager,
+    storage: &ConsensusStorage,
+    removed: &[Hash],
+    added: &[Hash],
+    include_accepted_transaction_ids: bool,
+) -> VirtualChainChanged {
+    let accepted = |chain_blocks: &[Hash]| -> Vec<RpcAcceptedTransactionIds> {
+        if !include_accepted_transaction_ids {
+            return Vec::new();
+        }
+        chain_blocks
+            .iter()
+            .map(|chain_block| RpcAcceptedTransactionIds {
+                accepting_block_hash: *chain_block,
+                accepted_transaction_ids: std::iter::once(*chain_block)
+                    .chain(ghostdag.get_mergeset_blues(chain_block))
+                    .filter_map(|block| storage.get_block(&block))
+                    .flat_map(|block| block.transactions.iter().map(Transaction::id).collect::<Vec<_>>())
+                    .collect(),
+            })
+            .collect()
+    };
+    VirtualChainChanged {
+        removed_chain_block_hashes: removed.to_vec(),
+        added_chain_block_hashes: added.to_vec(),
+        accepted_transaction_ids: accepted(added),
+        removed_transaction_ids: accepted(removed),
+    }
+}
+
+/// Sum of inputs minus sum of outputs, resolving inputs against `known_transactions`
+fn transaction_fee(tx: &Transaction, known_transactions: &HashMap<Hash, Transaction>) -> Option<u64> {
+    if tx.is_coinbase() {
+        return Some(0);
     }
-}
\ No newline at end of file
+    let mut input_sum = 0u64;
+    for input in &tx.inputs {
+        let outpoint = &input.previous_outpoint;
+        let previous = known_transactions.get(&outpoint.transaction_id)?;
+        input_sum = input_sum.checked_add(previous.outputs.get(outpoint.index as usize)?.value)?;
+    }
+    let output_sum = tx.outputs.iter().try_fold(0u64, |sum, output| sum.checked_add(output.value))?;
+    input_sum.checked_sub(output_sum)
+}
+
+/// Entry of `outpoint` once the `pending` transactions are layered over its
+/// `confirmed` entry. Outputs pending transactions create are dated `daa_score`.
+fn virtual_utxo_entry(outpoint: &TransactionOutpoint, confirmed: Option<UtxoEntry>, pending: &[Transaction], daa_score: u64) -> Option<UtxoEntry> {
+    let mut base = UtxoCollection::new();
+    if let Some(entry) = confirmed {
+        base.insert(*outpoint, entry);
+    }
+    // Pending transactions come in no particular order, so every output is
+    // created before any input is spent
+    let mut view = UtxoView::new(&base);
+    for tx in pending {
+        for (index, output) in tx.outputs.iter().enumerate() {
+            let entry = UtxoEntry::new(output.value, output.script_public_key.clone(), daa_score, tx.is_coinbase());
+            view.add(TransactionOutpoint::new(tx.id(), index as u32), entry);
+        }
+    }
+    for input in pending.iter().flat_map(|tx| tx.inputs.iter()) {
+        view.remove(&input.previous_outpoint);
+    }
+    view.get(outpoint).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::subnets::SubnetworkId;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput};
+
+    fn h(n: u64) -> Hash {
+        Hash::from_le_u64([n, 0, 0, 0])
+    }
+
+    fn spending_tx(previous: Hash, value: u64) -> Transaction {
+        Transaction::new(
+            0,
+            vec![TransactionInput::new(TransactionOutpoint::new(previous, 0), vec![], 0, 1)],
+            vec![TransactionOutput::new(value, ScriptPublicKey::from_vec(0, vec![0xac]))],
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![],
+        )
+    }
+
+    fn ghostdag_manager_with(blocks: &[(Hash, Hash, &[Hash], &[Hash])]) -> GhostdagManager {
+        use consensus::{BlockRelations, DagTopology, GhostdagData, GhostdagProtocol, GhostdagStore, ReachabilityStore};
+        let relations = Arc::new(BlockRelations::new());
+        let topology = Arc::new(DagTopology::new(relations.clone(), Arc::new(ReachabilityStore::new())));
+        let store = Arc::new(GhostdagStore::new());
+        for (hash, selected_parent, blue, red) in blocks {
+            let mut data = GhostdagData::new(*selected_parent);
+            data.blue_set = blue.iter().copied().collect();
+            data.red_set = red.iter().copied().collect();
+            data.blue_score = data.blue_set.len() as u64;
+            store.insert(*hash, data);
+        }
+        let protocol = Arc::new(GhostdagProtocol::new(18, topology, relations, store.clone()));
+        GhostdagManager::new(protocol, store)
+    }
+
+    #[test]
+    fn test_block_verbose_for_chain_block() {
+        // genesis(1) <- a(2) <- c(4) <- d(5); b(3) merged blue by c, r(6) merged red by d
+        let ghostdag = ghostdag_manager_with(&[
+            (h(1), h(1), &[h(1)], &[]),
+            (h(2), h(1), &[h(1)], &[]),
+            (h(3), h(1), &[h(1)], &[]),
+            (h(4), h(2), &[h(1), h(2), h(3)], &[]),
+            (h(6), h(2), &[h(1), h(2)], &[]),
+            (h(5), h(4), &[h(1), h(2), h(3), h(4)], &[h(6)]),
+        ]);
+        let block_store = consensus::BlockStore::new();
+        let block_c = Block::new(consensus_core::header::Header::from_precomputed_hash(h(4), vec![h(2), h(3)]), vec![]);
+        let block_d = Block::new(consensus_core::header::Header::from_precomputed_hash(h(5), vec![h(4), h(6)]), vec![]);
+        block_store.store_block(block_c.clone()).unwrap();
+        block_store.store_block(block_d.clone()).unwrap();
+        let chain = ghostdag.get_selected_chain(h(5));
+
+        let verbose = block_verbose(&block_c, &ghostdag, &chain, 5, block_store.get_children(&h(4))).unwrap();
+        assert_eq!(verbose.block.header.hash, h(4).to_string());
+        assert_eq!(verbose.blue_score, 3);
+        assert_eq!(verbose.selected_parent, h(2));
+        assert!(verbose.is_chain_block);
+        assert_eq!(verbose.merge_set_blues_size, 2);
+        assert_eq!(verbose.merge_set_reds_size, 0);
+        assert_eq!(verbose.children_hashes, vec![h(5)]);
+        assert_eq!(verbose.accepting_block_hash, Some(h(4)));
+        assert_eq!(verbose.confirmations, 3);
+
+        let verbose = block_verbose(&block_d, &ghostdag, &chain, 5, block_store.get_children(&h(5))).unwrap();
+        assert_eq!(verbose.merge_set_blues_size, 1);
+        assert_eq!(verbose.merge_set_reds_size, 1);
+        assert!(verbose.children_hashes.is_empty());
+        assert_eq!(verbose.confirmations, 2);
+    }
+
+    #[test]
+    fn test_mempool_transaction_has_no_confirmations() {
+        let verbose = mempool_transaction_verbose(spending_tx(h(1), 900), 100);
+        assert_eq!(verbose.confirmations, 0);
+        assert_eq!(verbose.block_hash, None);
+        assert_eq!(verbose.accepting_block_hash, None);
+        assert_eq!(verbose.accepting_blue_score, None);
+        assert_eq!(verbose.fee, Some(100));
+    }
+
+    #[test]
+    fn test_confirmed_transaction() {
+        let accepted = BlockAcceptance::Accepted { accepting_block: h(20), accepting_blue_score: 7 };
+        let verbose = block_transaction_verbose(spending_tx(h(1), 900), &[(h(10), accepted)], 10, Some(100));
+        assert_eq!(verbose.block_hash, Some(h(10)));
+        assert_eq!(verbose.accepting_block_hash, Some(h(20)));
+        assert_eq!(verbose.accepting_blue_score, Some(7));
+        assert_eq!(verbose.confirmations, 4);
+    }
+
+    #[test]
+    fn test_transaction_in_red_block_is_not_accepted() {
+        let red = BlockAcceptance::Red { merging_block: h(20) };
+        let verbose = block_transaction_verbose(spending_tx(h(1), 900), &[(h(10), red)], 10, None);
+        assert_eq!(verbose.block_hash, Some(h(10)));
+        assert_eq!(verbose.accepting_block_hash, None);
+        assert_eq!(verbose.confirmations, 0);
+
+        // Also included in a blue block, which takes precedence
+        let accepted = BlockAcceptance::Accepted { accepting_block: h(21), accepting_blue_score: 9 };
+        let verbose = block_transaction_verbose(spending_tx(h(1), 900), &[(h(10), red), (h(11), accepted)], 10, None);
+        assert_eq!(verbose.block_hash, Some(h(11)));
+        assert_eq!(verbose.accepting_block_hash, Some(h(21)));
+        assert_eq!(verbose.confirmations, 2);
+    }
+
+    #[test]
+    fn test_transaction_fee() {
+        let funding = spending_tx(h(1), 1_000);
+        let tx = spending_tx(funding.hash(), 900);
+        let mut known = HashMap::new();
+        assert_eq!(transaction_fee(&tx, &known), None);
+        known.insert(funding.hash(), funding);
+        assert_eq!(transaction_fee(&tx, &known), Some(100));
+    }
+
+    #[test]
+    fn test_virtual_utxo_entry() {
+        let confirmed = UtxoEntry::new(1_000, ScriptPublicKey::from_vec(0, vec![0xac]), 3, false);
+        let unspent = TransactionOutpoint::new(h(1), 0);
+        let spent = TransactionOutpoint::new(h(2), 0);
+        let parent = spending_tx(h(2), 900);
+        let child = spending_tx(parent.hash(), 800);
+        // The child is listed first, as a mempool may return it
+        let pending = vec![child.clone(), parent.clone()];
+
+        // Confirmed and unspent
+        assert_eq!(virtual_utxo_entry(&unspent, Some(confirmed.clone()), &pending, 10), Some(confirmed.clone()));
+        // Confirmed but spent by a mempool transaction
+        assert_eq!(virtual_utxo_entry(&spent, Some(confirmed.clone()), &pending, 10), None);
+        // Created and spent in the mempool
+        assert_eq!(virtual_utxo_entry(&TransactionOutpoint::new(parent.hash(), 0), None, &pending, 10), None);
+        // Created in the mempool and unspent
+        let created = virtual_utxo_entry(&TransactionOutpoint::new(child.hash(), 0), None, &pending, 10).unwrap();
+        assert_eq!((created.amount, created.block_daa_score), (800, 10));
+        // Unknown
+        assert_eq!(virtual_utxo_entry(&TransactionOutpoint::new(h(9), 0), None, &pending, 10), None);
+        assert_eq!(virtual_utxo_entry(&TransactionOutpoint::new(h(1), 1), None, &[], 10), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Fee estimation from recent block feerates and the mempool
+//!
+//! Bucket feerates are percentiles over feerates paid in recently accepted blocks
+//! together with the feerates of transactions currently in the mempool, floored
+//! at the minimum relay feerate. The wait for a bucket is derived from how much
+//! mempool mass pays a higher feerate and therefore goes first.
+
+use consensus_core::constants::{MAX_BLOCK_MASS, MIN_TRANSACTION_FEE_RATE, TARGET_BLOCK_TIME};
+use crate::model::{FeeEstimate, FeeEstimateBucket};
+
+/// Percentile of the samples used for each bucket
+const PRIORITY_PERCENTILE: f64 = 0.9;
+const NORMAL_PERCENTILE: f64 = 0.5;
+const LOW_PERCENTILE: f64 = 0.1;
+
+/// Feerate and mass of one mempool transaction
+#[derive(Debug, Clone, Copy)]
+pub struct MempoolFeeSample {
+    pub feerate: f64,
+    pub mass: u64,
+}
+
+/// Fee estimator
+pub struct FeeEstimator {
+    block_time_secs: f64,
+    block_mass_limit: u64,
+    min_feerate: f64,
+}
+
+impl FeeEstimator {
+    pub fn new(block_time_secs: f64, block_mass_limit: u64, min_feerate: f64) -> Self {
+        Self { block_time_secs, block_mass_limit: block_mass_limit.max(1), min_feerate }
+    }
+
+    /// Estimate priority, normal and low buckets. Bucket feerates never increase
+    /// from priority to low, and estimated times never decrease.
+    pub fn estimate(&self, block_feerates: &[f64], mempool: &[MempoolFeeSample]) -> FeeEstimate {
+        let mut samples: Vec<f64> = block_feerates
+            .iter()
+            .copied()
+            .chain(mempool.iter().map(|sample| sample.feerate))
+            .filter(|feerate| feerate.is_finite())
+            .collect();
+        samples.sort_by(|a, b| a.total_cmp(b));
+
+        let bucket = |percentile: f64| {
+            let feerate = percentile_of(&samples, percentile).unwrap_or(self.min_feerate).max(self.min_feerate);
+            FeeEstimateBucket { feerate, estimated_seconds: self.estimated_seconds(feerate, mempool) }
+        };
+        FeeEstimate {
+            priority_bucket: bucket(PRIORITY_PERCENTILE),
+            normal_buckets: vec![bucket(NORMAL_PERCENTILE)],
+            low_buckets: vec![bucket(LOW_PERCENTILE)],
+        }
+    }
+
+    /// Number of block templates the mempool fills when transactions are taken
+    /// by decreasing feerate, a block closing once the next one doesn't fit.
+    /// The last block may be partly filled.
+    pub fn estimated_block_count(&self, mempool: &[MempoolFeeSample]) -> u64 {
+        let mut sorted = mempool.to_vec();
+        sorted.sort_by(|a, b| b.feerate.total_cmp(&a.feerate));
+
+        let (mut blocks, mut block_mass) = (0, 0);
+        for sample in sorted {
+            if blocks == 0 || block_mass + sample.mass > self.block_mass_limit {
+                blocks += 1;
+                block_mass = 0;
+            }
+            block_mass += sample.mass;
+        }
+        blocks
+    }
+
+    /// Time until a transaction paying `feerate` fits in a block, given that
+    /// mempool mass paying more is mined first
+    fn estimated_seconds(&self, feerate: f64, mempool: &[MempoolFeeSample]) -> f64 {
+        let mass_ahead: u64 = mempool.iter().filter(|sample| sample.feerate > feerate).map(|sample| sample.mass).sum();
+        let blocks = 1 + mass_ahead / self.block_mass_limit;
+        blocks as f64 * self.block_time_secs
+    }
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new(TARGET_BLOCK_TIME as f64, MAX_BLOCK_MASS, MIN_TRANSACTION_FEE_RATE as f64)
+    }
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile_of(sorted: &[f64], percentile: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile * (sorted.len() - 1) as f64).round() as usize;
+    Some(sorted[rank.min(sorted.len() - 1)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_monotonic(estimate: &FeeEstimate) {
+        let priority = &estimate.priority_bucket;
+        let normal = &estimate.normal_buckets[0];
+        let low = &estimate.low_buckets[0];
+        assert!(priority.feerate >= normal.feerate && normal.feerate >= low.feerate, "{:?}", estimate);
+        assert!(priority.estimated_seconds <= normal.estimated_seconds, "{:?}", estimate);
+        assert!(normal.estimated_seconds <= low.estimated_seconds, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_buckets_are_monotonic() {
+        let estimator = FeeEstimator::new(60.0, 1_000, 1.0);
+        // Deterministic pseudo-random feerates and masses
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for round in 0..50 {
+            let blocks: Vec<f64> = (0..round).map(|_| (next() % 10_000) as f64 / 100.0).collect();
+            let mempool: Vec<MempoolFeeSample> = (0..round * 3)
+                .map(|_| MempoolFeeSample { feerate: (next() % 10_000) as f64 / 100.0, mass: next() % 800 })
+                .collect();
+            assert_monotonic(&estimator.estimate(&blocks, &mempool));
+        }
+    }
+
+    #[test]
+    fn test_empty_inputs_use_min_feerate() {
+        let estimator = FeeEstimator::new(60.0, 1_000, 1.0);
+        let estimate = estimator.estimate(&[], &[]);
+        assert_eq!(estimate.priority_bucket.feerate, 1.0);
+        assert_eq!(estimate.low_buckets[0].feerate, 1.0);
+        assert_eq!(estimate.priority_bucket.estimated_seconds, 60.0);
+        assert_monotonic(&estimate);
+    }
+
+    #[test]
+    fn test_feerates_floor_at_min() {
+        let estimator = FeeEstimator::new(60.0, 1_000, 1.0);
+        let estimate = estimator.estimate(&[0.1, 0.2, 0.5], &[]);
+        assert_eq!(estimate.normal_buckets[0].feerate, 1.0);
+    }
+
+    #[test]
+    fn test_mempool_backlog_delays_low_bucket() {
+        let estimator = FeeEstimator::new(60.0, 1_000, 1.0);
+        let mut mempool = vec![MempoolFeeSample { feerate: 50.0, mass: 900 }; 5];
+        mempool.extend(vec![MempoolFeeSample { feerate: 2.0, mass: 100 }; 5]);
+        let estimate = estimator.estimate(&[], &mempool);
+
+        assert_eq!(estimate.priority_bucket.feerate, 50.0);
+        assert_eq!(estimate.priority_bucket.estimated_seconds, 60.0);
+        // 4500 mass pays more than the low bucket, so it waits five blocks
+        assert_eq!(estimate.low_buckets[0].feerate, 2.0);
+        assert_eq!(estimate.low_buckets[0].estimated_seconds, 300.0);
+        assert_monotonic(&estimate);
+    }
+
+    #[test]
+    fn test_estimated_block_count() {
+        let estimator = FeeEstimator::new(60.0, 1_000, 1.0);
+        assert_eq!(estimator.estimated_block_count(&[]), 0);
+
+        let sample = |feerate: f64, mass: u64| MempoolFeeSample { feerate, mass };
+        // Exactly full blocks
+        assert_eq!(estimator.estimated_block_count(&vec![sample(5.0, 250); 8]), 2);
+        // A partly filled last block still counts
+        assert_eq!(estimator.estimated_block_count(&vec![sample(5.0, 250); 9]), 3);
+
+        // By feerate the 600s go first and never share a block
+        let mempool = vec![sample(1.0, 400), sample(9.0, 600), sample(2.0, 400), sample(8.0, 600)];
+        assert_eq!(estimator.estimated_block_count(&mempool), 3);
+        // A transaction above the limit takes a block of its own
+        assert_eq!(estimator.estimated_block_count(&[sample(3.0, 1_500), sample(2.0, 100)]), 2);
+    }
+}
@@ -2,8 +2,16 @@ pub mod coordinator;
 pub mod api;
 pub mod model;
 pub mod mempool;
+pub mod fee_estimator;
+pub mod package;
+pub mod logging;
+pub mod subscriptions;
+pub mod sync_status;
 
 pub use coordinator::RpcCoordinator;
 pub use api::RpcApi;
 pub use model::*;
 pub use mempool::MempoolInterface;
+pub use logging::LogControl;
+pub use sync_status::SyncStatusProvider;
+pub use subscriptions::{ChainSubscriptions, ChainTip, TemplateSubscriptions, UtxoSubscriptions};
@@ -0,0 +1,6 @@
+/// Runtime control over the node's log filter
+pub trait LogControl: Send + Sync {
+    /// Log `target` at `level` from now on, or every target without a more
+    /// specific directive when `target` is empty. Returns the filter in effect.
+    fn set_log_level(&self, target: &str, level: &str) -> Result<String, String>;
+}
@@ -1,3 +1,4 @@
+use consensus_core::constants::MIN_TRANSACTION_FEE_RATE;
 use consensus_core::tx::Transaction;
 use consensus_core::Hash;
 use crate::model::MempoolEntry;
@@ -11,6 +12,16 @@ pub trait MempoolInterface: Send + Sync {
     fn size(&self) -> usize;
     fn get_all_transactions(&self) -> Vec<Transaction>;
     fn get_entries(&self) -> Vec<MempoolEntry>;
+
+    /// Feerate in sompi per gram below which transactions aren't relayed
+    fn min_relay_feerate(&self) -> u64 {
+        MIN_TRANSACTION_FEE_RATE
+    }
+
+    /// Persist the pool now, returning the number of transactions written
+    fn flush(&self) -> Result<usize, String> {
+        Err("Mempool persistence is disabled".to_string())
+    }
 }
 
 /// Memory pool for pending transactions
@@ -33,7 +44,8 @@ impl Mempool {
         let hash = tx.hash();
         let mut transactions = self.transactions.write().unwrap();
 
-        // Check if already exists
+        // Keyed by transaction id, which excludes signature scripts, so a re-signed
+        // variant of a pooled transaction is treated as a duplicate
         if transactions.contains_key(&hash) {
             return Err("Transaction already in mempool".to_string());
         }
@@ -115,8 +127,10 @@ impl MempoolInterface for Mempool {
     }
 
     fn remove_transaction(&self, tx_id: &str) -> Result<(), String> {
-        // Parse tx_id as hash (placeholder implementation)
-        Err("Not implemented".to_string())
+        let hash: Hash = tx_id.parse().map_err(|e| format!("Invalid transaction id: {:?}", e))?;
+        Mempool::remove_transaction(self, &hash)
+            .map(|_| ())
+            .ok_or_else(|| "Transaction not in mempool".to_string())
     }
 
     fn size(&self) -> usize {
@@ -2,7 +2,15 @@
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use consensus_core::{block::Block, tx::Transaction, Hash};
+use consensus_core::{
+    block::Block,
+    header::Header,
+    merkle::MerkleTree,
+    subnets::{SubnetworkId, SUBNETWORK_ID_SIZE},
+    tx::{self, Transaction},
+    BlueWorkType, Hash,
+};
+pub use network::p2p::PeerDirection;
 
 /// RPC error type
 #[derive(Error, Debug, Clone, Serialize, Deserialize)]
@@ -33,13 +41,88 @@ pub struct BlockDagInfo {
 pub struct PeerInfo {
     pub id: String,
     pub address: String,
-    pub last_ping_duration: Option<u64>,
-    pub is_connected: bool,
-    pub version: u32,
+    pub direction: PeerDirection,
+    pub connected_duration_ms: u64,
+    /// Time since the peer last sent a message
+    #[serde(default)]
+    pub last_seen_ms: u64,
+    /// Round trip time of the last answered ping
+    pub last_ping_ms: Option<u64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub protocol_version: u32,
     pub user_agent: String,
-    pub advertised_protocol_version: u32,
-    pub time_offset: i64,
-    pub is_ibd_peer: bool,
+    pub advertised_blue_score: u64,
+    /// Address the peer advertised as reachable through its NAT, if any
+    #[serde(default)]
+    pub advertised_address: Option<String>,
+    /// Whether the peer can serve block bodies below its pruning depth
+    pub archival: bool,
+    /// Misbehavior score; the peer is banned once it reaches the ban threshold
+    pub ban_score: u32,
+}
+
+impl From<network::p2p::PeerInfo> for PeerInfo {
+    fn from(info: network::p2p::PeerInfo) -> Self {
+        Self {
+            id: info.id,
+            address: info.address.to_string(),
+            direction: info.direction,
+            connected_duration_ms: info.connected_duration.as_millis() as u64,
+            last_seen_ms: info.last_seen.as_millis() as u64,
+            last_ping_ms: info.last_ping_ms,
+            bytes_sent: info.bytes_sent,
+            bytes_received: info.bytes_received,
+            protocol_version: info.protocol_version,
+            user_agent: info.user_agent,
+            advertised_blue_score: info.advertised_blue_score,
+            advertised_address: info.advertised_address.map(|address| address.to_string()),
+            archival: info.archival,
+            ban_score: info.ban_score,
+        }
+    }
+}
+
+/// NAT traversal state of the node's P2P listen port
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcNatStatus {
+    /// Port mapping protocol in use: upnp or nat-pmp
+    pub protocol: Option<String>,
+    /// Address peers can reach the node on
+    pub external_address: Option<String>,
+    /// Whether inbound connections are expected to work
+    pub inbound_reachable: bool,
+    /// Why traversal failed, if it did
+    pub last_error: Option<String>,
+}
+
+impl From<network::nat::NatStatus> for RpcNatStatus {
+    fn from(status: network::nat::NatStatus) -> Self {
+        Self {
+            protocol: status.protocol.map(str::to_string),
+            external_address: status.external_address.map(|address| address.to_string()),
+            inbound_reachable: status.inbound_reachable,
+            last_error: status.last_error,
+        }
+    }
+}
+
+/// An address whose peers are refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BannedPeer {
+    pub address: String,
+    /// Seconds until the ban expires
+    pub remaining_secs: u64,
+}
+
+/// RPC clients and peers connected to the node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionCounts {
+    pub rpc_connections: usize,
+    /// RPC clients served at once; later ones are refused
+    pub max_rpc_connections: usize,
+    pub inbound_peers: usize,
+    pub outbound_peers: usize,
 }
 
 /// Mempool information
@@ -47,6 +130,14 @@ pub struct PeerInfo {
 pub struct MempoolInfo {
     pub size: usize,
     pub bytes: u64,
+    /// Sum of the fees paid by pooled transactions, in sompi
+    #[serde(default)]
+    pub total_fees: u64,
+    #[serde(default)]
+    pub total_mass: u64,
+    /// Block templates the pool fills at the block mass limit, by feerate
+    #[serde(default)]
+    pub estimated_block_count: u64,
 }
 
 /// Mempool entry
@@ -64,24 +155,476 @@ pub struct BlockTemplate {
     pub parent_hashes: Vec<Hash>,
     pub transactions: Vec<Transaction>,
     pub coinbase_value: u64,
+    /// Compact difficulty bits, as committed in the block header
     pub bits: u32,
     pub timestamp: u64,
     pub pay_address: String,
+    /// Target expanded from `bits`: 64 big-endian hex characters
     pub target: String,
+    /// DAA score the mined header must commit to
+    #[serde(default)]
+    pub daa_score: u64,
+    /// Blue score the mined header must commit to
+    #[serde(default)]
+    pub blue_score: u64,
+    /// Blue work the mined header must commit to
+    #[serde(default)]
+    pub blue_work: BlueWorkType,
+    /// Identifies the template to submit_block_with_coinbase
+    #[serde(default)]
+    pub template_id: Hash,
+    /// Bytes at the end of the coinbase payload reserved for an extranonce
+    #[serde(default)]
+    pub extranonce_size: usize,
+}
+
+impl BlockTemplate {
+    /// Block mined from the template with `nonce`, `extranonce` spliced into
+    /// the coinbase payload and `overrides` applied to the coinbase
+    pub fn to_block(&self, nonce: u64, extranonce: &[u8], overrides: &CoinbaseOverrides) -> Result<Block, String> {
+        if extranonce.len() != self.extranonce_size {
+            return Err(format!("Extranonce is {} bytes, the template reserves {}", extranonce.len(), self.extranonce_size));
+        }
+        let mut transactions = self.transactions.clone();
+        let coinbase = transactions.first_mut().filter(|tx| tx.is_coinbase()).ok_or("Template has no coinbase transaction")?;
+        if let Some(outputs) = &overrides.outputs {
+            let value = outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.value));
+            if outputs.is_empty() || value.map_or(true, |value| value > self.coinbase_value) {
+                return Err(format!("Coinbase outputs must pay between 1 and {} sompi in total", self.coinbase_value));
+            }
+            coinbase.outputs = outputs.clone();
+        }
+        consensus::process::coinbase::splice_extranonce(coinbase, extranonce)?;
+
+        let merkle_root = MerkleTree::from_hashes(transactions.iter().map(|tx| tx.hash()).collect()).root();
+        let header = Header::new_finalized(
+            self.version as u16,
+            vec![self.parent_hashes.clone()],
+            merkle_root,
+            Default::default(),
+            Default::default(),
+            self.timestamp,
+            self.bits,
+            nonce,
+            self.daa_score,
+            self.blue_work,
+            self.blue_score,
+            Default::default(),
+        );
+        Ok(Block::new(header, transactions))
+    }
+}
+
+/// Changes a pool makes to the template coinbase before submitting a block
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinbaseOverrides {
+    /// Outputs replacing the template's, e.g. splitting the reward among
+    /// miners. Together they may pay at most the template's coinbase value.
+    #[serde(default)]
+    pub outputs: Option<Vec<tx::TransactionOutput>>,
+}
+
+/// JSON-friendly block: hashes, scripts and other binary fields are hex strings.
+/// P2P and storage keep using the binary `Block` encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock {
+    pub header: RpcBlockHeader,
+    pub transactions: Vec<RpcTransaction>,
+    /// Present when asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbose_data: Option<RpcBlockVerboseData>,
+}
+
+/// What the node knows of a block beyond its contents: its GHOSTDAG coloring
+/// and its place in the DAG
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlockVerboseData {
+    pub hash: Hash,
+    pub blue_score: u64,
+    /// Big-endian hex
+    pub blue_work: String,
+    /// The block itself for genesis
+    pub selected_parent: Hash,
+    /// Blocks merged as blue by this block, selected parent first, in coloring order
+    pub merge_set_blues_hashes: Vec<Hash>,
+    /// Blocks merged as red by this block
+    pub merge_set_reds_hashes: Vec<Hash>,
+    /// Whether the block is on the virtual selected parent chain
+    pub is_chain_block: bool,
+    /// Blocks having this block as a direct parent
+    pub children_hashes: Vec<Hash>,
+    /// Difficulty of the block's bits
+    pub difficulty: f64,
+    /// Only the header is kept: the transactions were pruned or never downloaded
+    pub is_header_only: bool,
+}
+
+/// JSON-friendly block header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlockHeader {
+    pub hash: String,
+    pub version: u16,
+    pub parents_by_level: Vec<Vec<String>>,
+    pub hash_merkle_root: String,
+    pub accepted_id_merkle_root: String,
+    pub utxo_commitment: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub daa_score: u64,
+    /// Big-endian hex
+    pub blue_work: String,
+    pub blue_score: u64,
+    pub pruning_point: String,
+}
+
+/// JSON-friendly transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction {
+    pub transaction_id: String,
+    pub version: u16,
+    pub inputs: Vec<RpcTransactionInput>,
+    pub outputs: Vec<RpcTransactionOutput>,
+    pub lock_time: u64,
+    pub subnetwork_id: String,
+    pub gas: u64,
+    pub payload: String,
+    /// Storage mass commitment
+    #[serde(default)]
+    pub mass: u64,
+}
+
+/// JSON-friendly transaction input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransactionInput {
+    pub previous_outpoint: RpcTransactionOutpoint,
+    pub signature_script: String,
+    pub sequence: u64,
+    pub sig_op_count: u8,
+}
+
+/// JSON-friendly transaction outpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransactionOutpoint {
+    pub transaction_id: String,
+    pub index: u32,
+}
+
+/// JSON-friendly transaction output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransactionOutput {
+    pub value: u64,
+    pub script_public_key: RpcScriptPublicKey,
+}
+
+/// JSON-friendly script public key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcScriptPublicKey {
+    pub version: u16,
+    pub script: String,
+}
+
+fn parse_hex_field(name: &str, value: &str) -> Result<Vec<u8>, RpcError> {
+    hex::decode(value).map_err(|e| RpcError::Internal(format!("Invalid hex in {}: {}", name, e)))
+}
+
+/// `blue_work` as big-endian hex
+pub fn blue_work_hex(blue_work: BlueWorkType) -> String {
+    let mut bytes = blue_work.to_bytes();
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
+fn parse_hash_field(name: &str, value: &str) -> Result<Hash, RpcError> {
+    Hash::try_from_slice(&parse_hex_field(name, value)?).map_err(|_| RpcError::Internal(format!("Invalid hash length in {}", name)))
+}
+
+impl From<&Header> for RpcBlockHeader {
+    fn from(header: &Header) -> Self {
+        Self {
+            hash: header.hash.to_string(),
+            version: header.version,
+            parents_by_level: header.parents_by_level.iter().map(|level| level.iter().map(Hash::to_string).collect()).collect(),
+            hash_merkle_root: header.hash_merkle_root.to_string(),
+            accepted_id_merkle_root: header.accepted_id_merkle_root.to_string(),
+            utxo_commitment: header.utxo_commitment.to_string(),
+            timestamp: header.timestamp,
+            bits: header.bits,
+            nonce: header.nonce,
+            daa_score: header.daa_score,
+            blue_work: blue_work_hex(header.blue_work),
+            blue_score: header.blue_score,
+            pruning_point: header.pruning_point.to_string(),
+        }
+    }
+}
+
+impl TryFrom<RpcBlockHeader> for Header {
+    type Error = RpcError;
+
+    /// The supplied hash is checked against the one computed from the fields
+    fn try_from(header: RpcBlockHeader) -> Result<Self, Self::Error> {
+        let mut blue_work: [u8; 24] = parse_hex_field("blueWork", &header.blue_work)?
+            .try_into()
+            .map_err(|_| RpcError::Internal("Invalid blueWork length".to_string()))?;
+        blue_work.reverse();
+        let parents_by_level = header
+            .parents_by_level
+            .iter()
+            .map(|level| level.iter().map(|parent| parse_hash_field("parentsByLevel", parent)).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<_, _>>()?;
+        let claimed = parse_hash_field("hash", &header.hash)?;
+        let header = Header::new_finalized(
+            header.version,
+            parents_by_level,
+            parse_hash_field("hashMerkleRoot", &header.hash_merkle_root)?,
+            parse_hash_field("acceptedIdMerkleRoot", &header.accepted_id_merkle_root)?,
+            parse_hash_field("utxoCommitment", &header.utxo_commitment)?,
+            header.timestamp,
+            header.bits,
+            header.nonce,
+            header.daa_score,
+            BlueWorkType::from_le_bytes(blue_work),
+            header.blue_score,
+            parse_hash_field("pruningPoint", &header.pruning_point)?,
+        );
+        if header.hash != claimed {
+            return Err(RpcError::Internal(format!("Header hash {} does not match its contents, which hash to {}", claimed, header.hash)));
+        }
+        Ok(header)
+    }
+}
+
+impl From<&Transaction> for RpcTransaction {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            transaction_id: tx.id().to_string(),
+            version: tx.version,
+            inputs: tx
+                .inputs
+                .iter()
+                .map(|input| RpcTransactionInput {
+                    previous_outpoint: RpcTransactionOutpoint {
+                        transaction_id: input.previous_outpoint.transaction_id.to_string(),
+                        index: input.previous_outpoint.index,
+                    },
+                    signature_script: hex::encode(&input.signature_script),
+                    sequence: input.sequence,
+                    sig_op_count: input.sig_op_count,
+                })
+                .collect(),
+            outputs: tx
+                .outputs
+                .iter()
+                .map(|output| RpcTransactionOutput {
+                    value: output.value,
+                    script_public_key: RpcScriptPublicKey {
+                        version: output.script_public_key.version(),
+                        script: hex::encode(output.script_public_key.script()),
+                    },
+                })
+                .collect(),
+            lock_time: tx.lock_time,
+            subnetwork_id: hex::encode(tx.subnetwork_id.as_bytes()),
+            gas: tx.gas,
+            payload: hex::encode(&tx.payload),
+            mass: tx.mass(),
+        }
+    }
+}
+
+impl TryFrom<RpcTransaction> for Transaction {
+    type Error = RpcError;
+
+    /// The supplied id is checked against the one computed from the fields
+    fn try_from(tx: RpcTransaction) -> Result<Self, Self::Error> {
+        let claimed = parse_hash_field("transactionId", &tx.transaction_id)?;
+        let inputs = tx
+            .inputs
+            .into_iter()
+            .map(|input| {
+                Ok(tx::TransactionInput::new(
+                    tx::TransactionOutpoint::new(
+                        parse_hash_field("previousOutpoint", &input.previous_outpoint.transaction_id)?,
+                        input.previous_outpoint.index,
+                    ),
+                    parse_hex_field("signatureScript", &input.signature_script)?,
+                    input.sequence,
+                    input.sig_op_count,
+                ))
+            })
+            .collect::<Result<_, RpcError>>()?;
+        let outputs = tx
+            .outputs
+            .into_iter()
+            .map(|output| {
+                let script = parse_hex_field("scriptPublicKey", &output.script_public_key.script)?;
+                Ok(tx::TransactionOutput::new(output.value, tx::ScriptPublicKey::from_vec(output.script_public_key.version, script)))
+            })
+            .collect::<Result<_, RpcError>>()?;
+        let subnetwork_id: [u8; SUBNETWORK_ID_SIZE] = parse_hex_field("subnetworkId", &tx.subnetwork_id)?
+            .try_into()
+            .map_err(|_| RpcError::Internal("Invalid subnetworkId length".to_string()))?;
+        let transaction = Transaction::new(
+            tx.version,
+            inputs,
+            outputs,
+            tx.lock_time,
+            SubnetworkId::new(subnetwork_id),
+            tx.gas,
+            parse_hex_field("payload", &tx.payload)?,
+        )
+        .with_mass(tx.mass);
+        if transaction.id() != claimed {
+            return Err(RpcError::Internal(format!("Transaction id {} does not match its contents, which hash to {}", claimed, transaction.id())));
+        }
+        Ok(transaction)
+    }
+}
+
+impl From<&Block> for RpcBlock {
+    fn from(block: &Block) -> Self {
+        Self {
+            header: (&block.header).into(),
+            transactions: block.transactions.iter().map(RpcTransaction::from).collect(),
+            verbose_data: None,
+        }
+    }
+}
+
+impl TryFrom<RpcBlock> for Block {
+    type Error = RpcError;
+
+    fn try_from(block: RpcBlock) -> Result<Self, Self::Error> {
+        let transactions = block.transactions.into_iter().map(Transaction::try_from).collect::<Result<_, _>>()?;
+        Ok(Block::new(block.header.try_into()?, transactions))
+    }
+}
+
+/// Block with its GHOSTDAG and acceptance data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcBlockVerbose {
+    pub block: RpcBlock,
+    pub blue_score: u64,
+    pub selected_parent: Hash,
+    /// Whether the block is on the virtual selected parent chain
+    pub is_chain_block: bool,
+    /// Blocks newly merged as blue by this block, including its selected parent
+    pub merge_set_blues_size: u64,
+    /// Blocks merged as red by this block
+    pub merge_set_reds_size: u64,
+    /// Blocks having this block as a direct parent
+    pub children_hashes: Vec<Hash>,
+    /// Chain block accepting this block's transactions; null while pending or if merged as red
+    pub accepting_block_hash: Option<Hash>,
+    /// Virtual blue score minus accepting blue score, plus one; zero if not accepted
+    pub confirmations: u64,
+    /// Peer address, "rpc" or "miner" the block was first received from; null if not recorded
+    #[serde(default)]
+    pub source: Option<String>,
+    /// When the block was first received, in milliseconds since the unix epoch
+    #[serde(default)]
+    pub first_seen: Option<u64>,
+}
+
+/// Validation status of a block and where it was first received from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStatusInfo {
+    pub hash: Hash,
+    /// "valid", "invalid", "headerOnly" or "orphan"
+    pub status: String,
+    /// Peer address, "rpc" or "miner"; null if not recorded
+    pub source: Option<String>,
+    /// Milliseconds since the unix epoch
+    pub first_seen: Option<u64>,
+}
+
+/// Transaction with its acceptance data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcTransactionVerbose {
+    pub transaction: Transaction,
+    /// Block containing the transaction; null for mempool transactions
+    pub block_hash: Option<Hash>,
+    /// Chain block that accepted the transaction; null for mempool and non-accepted transactions
+    pub accepting_block_hash: Option<Hash>,
+    pub accepting_blue_score: Option<u64>,
+    /// Virtual blue score minus accepting blue score, plus one; zero if not accepted
+    pub confirmations: u64,
+    /// Inputs minus outputs; null if an input's previous output is unknown
+    pub fee: Option<u64>,
+}
+
+/// Get info response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetInfoResponse {
+    pub server_version: String,
+    /// mainnet, testnet, simnet or devnet
+    #[serde(default)]
+    pub network: String,
+    /// Whether no sync is in progress
+    #[serde(default)]
+    pub is_synced: bool,
+    /// Share of the peers' advertised blue score the local tip reaches, from 0.0 to 1.0
+    #[serde(default)]
+    pub sync_progress: f64,
+    #[serde(default)]
+    pub peer_count: usize,
+    /// Blocks stored with their bodies
+    #[serde(default)]
+    pub block_count: u64,
+    /// Headers stored, with or without bodies
+    #[serde(default)]
+    pub header_count: u64,
+    pub mempool_size: usize,
+    /// Feerate in sompi per gram below which transactions aren't relayed
+    pub min_relay_feerate: u64,
+    /// Smallest output value the node relays for a standard script, in sompi
+    pub dust_threshold: u64,
+    /// DAA score of the virtual block, which coinbase maturity counts up to
+    #[serde(default)]
+    pub virtual_daa_score: u64,
+    /// DAA scores a coinbase output takes after its block to become spendable
+    #[serde(default)]
+    pub coinbase_maturity: u64,
+    /// Whether peers can connect to the node through its NAT
+    #[serde(default)]
+    pub nat: RpcNatStatus,
 }
 
 /// Get blocks response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetBlocksResponse {
+    /// Blocks of this page; empty unless blocks were requested, without transactions unless those were too
     pub blocks: Vec<Block>,
+    /// Hashes of this page in topological order. Pass the last one as `low_hash`
+    /// to fetch the next page; an empty list means there are no more blocks.
     pub next_block_hashes: Vec<Hash>,
+    /// Verbose data of each block of `blocks`, in the same order, when asked for
+    #[serde(default)]
+    pub block_verbose_data: Vec<RpcBlockVerboseData>,
 }
 
-/// Get balances response
+/// Get balances response, split as by `wallet::Balance`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetBalancesResponse {
+    /// Mature confirmed outputs not spent by a mempool transaction
     pub available_balance: u64,
-    pub pending_balance: u64,
+    /// Confirmed coinbase outputs that haven't reached maturity yet
+    pub immature_balance: u64,
+    /// Mempool outputs paid to the wallet by others
+    pub pending_incoming_balance: u64,
+    /// Mempool change of transactions spending the wallet's outputs
+    pub pending_outgoing_change_balance: u64,
 }
 
 /// Transaction output with address
@@ -110,11 +653,64 @@ pub struct UtxoEntry {
     pub is_coinbase: bool,
 }
 
+/// Unspent output of an address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcUtxoByAddress {
+    pub address: String,
+    pub outpoint: tx::TransactionOutpoint,
+    pub utxo_entry: tx::UtxoEntry,
+}
+
+/// Outputs of a subscription's addresses created and spent by one block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxosChangedNotification {
+    pub subscription_id: u64,
+    pub block_hash: Hash,
+    pub added: Vec<RpcUtxoByAddress>,
+    pub removed: Vec<RpcUtxoByAddress>,
+}
+
+/// Sent to a newBlockTemplate subscription when templates change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewBlockTemplateNotification {
+    pub subscription_id: u64,
+}
+
+/// Transactions of the blocks accepted by one chain block
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RpcAcceptedTransactionIds {
+    pub accepting_block_hash: Hash,
+    pub accepted_transaction_ids: Vec<Hash>,
+}
+
+/// Change of the virtual selected parent chain
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VirtualChainChanged {
+    /// Chain blocks reorged away, highest first
+    pub removed_chain_block_hashes: Vec<Hash>,
+    /// New chain blocks, lowest first
+    pub added_chain_block_hashes: Vec<Hash>,
+    /// Transactions accepted by each added chain block, if requested
+    pub accepted_transaction_ids: Vec<RpcAcceptedTransactionIds>,
+    /// Transactions no longer accepted by each removed chain block, if requested
+    pub removed_transaction_ids: Vec<RpcAcceptedTransactionIds>,
+}
+
+/// [`VirtualChainChanged`] sent to a subscription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualChainChangedNotification {
+    pub subscription_id: u64,
+    #[serde(flatten)]
+    pub changes: VirtualChainChanged,
+}
+
 /// Fee estimate
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FeeEstimate {
+    /// Feerate expected to be included in the next block
     pub priority_bucket: FeeEstimateBucket,
     pub normal_buckets: Vec<FeeEstimateBucket>,
+    pub low_buckets: Vec<FeeEstimateBucket>,
 }
 
 /// Fee estimate bucket
@@ -124,6 +720,15 @@ pub struct FeeEstimateBucket {
     pub estimated_seconds: f64,
 }
 
+/// Coin supply, in sompi
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinSupply {
+    /// Subsidy emitted up to the virtual DAA score minus provably burned outputs
+    pub circulating_sompi: u64,
+    /// Subsidy the emission schedule will ever produce
+    pub max_sompi: u64,
+}
+
 /// Network connection info
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConnectionInfo {
@@ -206,7 +811,9 @@ pub struct TransactionStatus {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressBalance {
     pub address: String,
+    /// Spendable amount
     pub balance: u64,
+    /// Coinbase outputs that haven't reached maturity yet
     pub pending_balance: u64,
     pub utxo_count: u32,
 }
@@ -248,4 +855,139 @@ pub struct SearchResults {
     pub transactions: Vec<String>, // Transaction hashes
     pub addresses: Vec<String>, // Addresses
     pub total: usize,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput};
+
+    fn sample_block() -> Block {
+        let tx = Transaction::new(
+            0,
+            vec![TransactionInput::new(TransactionOutpoint::new(Hash::from_bytes([0x11; 32]), 3), vec![0xab, 0xcd], 7, 1)],
+            vec![TransactionOutput::new(1_000, ScriptPublicKey::from_vec(0, vec![0x20, 0xac]))],
+            0,
+            SubnetworkId::from(2u64),
+            0,
+            vec![0xde, 0xad],
+        );
+        let header = Header::new_finalized(
+            1,
+            vec![vec![Hash::from_bytes([0x22; 32])]],
+            Hash::from_bytes([0x33; 32]),
+            Hash::from_bytes([0x44; 32]),
+            Hash::from_bytes([0x55; 32]),
+            1_700_000_000_000,
+            0x1d00ffff,
+            42,
+            10,
+            BlueWorkType::from(0x0102u64),
+            9,
+            Hash::from_bytes([0x66; 32]),
+        );
+        Block::new(header, vec![tx])
+    }
+
+    #[test]
+    fn test_rpc_block_json_uses_hex_strings() {
+        let block = sample_block();
+        let json = serde_json::to_value(RpcBlock::from(&block)).unwrap();
+
+        let header = &json["header"];
+        assert_eq!(header["hash"], serde_json::json!(block.header.hash.to_string()));
+        assert_eq!(header["hashMerkleRoot"], serde_json::json!("33".repeat(32)));
+        assert_eq!(header["parentsByLevel"][0][0], serde_json::json!("22".repeat(32)));
+        assert_eq!(header["blueWork"], serde_json::json!(format!("{}0102", "0".repeat(44))));
+
+        let tx = &json["transactions"][0];
+        assert_eq!(tx["transactionId"], serde_json::json!(block.transactions[0].id().to_string()));
+        assert_eq!(tx["inputs"][0]["previousOutpoint"]["transactionId"], serde_json::json!("11".repeat(32)));
+        assert_eq!(tx["inputs"][0]["signatureScript"], serde_json::json!("abcd"));
+        assert_eq!(tx["outputs"][0]["scriptPublicKey"]["script"], serde_json::json!("20ac"));
+        assert_eq!(tx["payload"], serde_json::json!("dead"));
+        assert_eq!(tx["subnetworkId"], serde_json::json!(format!("02{}", "0".repeat(38))));
+
+        // No binary field may come out as an array of numbers
+        fn assert_no_byte_arrays(value: &serde_json::Value) {
+            match value {
+                serde_json::Value::Array(items) => {
+                    assert!(!items.iter().any(|item| item.is_number()), "byte array in {}", value);
+                    items.iter().for_each(assert_no_byte_arrays);
+                }
+                serde_json::Value::Object(fields) => fields.values().for_each(assert_no_byte_arrays),
+                _ => {}
+            }
+        }
+        assert_no_byte_arrays(&json);
+    }
+
+    #[test]
+    fn test_rpc_block_roundtrip() {
+        let block = sample_block();
+        let json = serde_json::to_string(&RpcBlock::from(&block)).unwrap();
+        let decoded = Block::try_from(serde_json::from_str::<RpcBlock>(&json).unwrap()).unwrap();
+        assert_eq!(decoded.header.hash, block.header.hash);
+        assert_eq!(decoded.header.blue_work, block.header.blue_work);
+        assert_eq!(decoded.header.parents_by_level, block.header.parents_by_level);
+        assert_eq!(decoded.transactions, block.transactions);
+    }
+
+    #[test]
+    fn test_verbose_data_is_only_sent_when_present() {
+        let block = sample_block();
+        let mut rpc_block = RpcBlock::from(&block);
+        assert!(serde_json::to_value(&rpc_block).unwrap().get("verboseData").is_none());
+
+        rpc_block.verbose_data = Some(RpcBlockVerboseData {
+            hash: block.header.hash,
+            blue_score: 10,
+            blue_work: blue_work_hex(BlueWorkType::from(0x0102u64)),
+            selected_parent: Hash::from_bytes([0x22; 32]),
+            merge_set_blues_hashes: vec![Hash::from_bytes([0x22; 32])],
+            merge_set_reds_hashes: vec![],
+            is_chain_block: true,
+            children_hashes: vec![],
+            difficulty: 1.0,
+            is_header_only: false,
+        });
+        let json = serde_json::to_value(&rpc_block).unwrap();
+        assert_eq!(json["verboseData"]["blueWork"], json["header"]["blueWork"]);
+        assert_eq!(json["verboseData"]["mergeSetBluesHashes"][0], serde_json::json!("22".repeat(32)));
+        assert_eq!(serde_json::from_value::<RpcBlock>(json).unwrap().verbose_data, rpc_block.verbose_data);
+    }
+
+    #[test]
+    fn test_rpc_block_rejects_bad_hex() {
+        let mut rpc_block = RpcBlock::from(&sample_block());
+        rpc_block.header.utxo_commitment = "zz".to_string();
+        assert!(Block::try_from(rpc_block).is_err());
+    }
+
+    #[test]
+    fn test_rpc_header_hash_must_match_its_fields() {
+        let block = sample_block();
+        let mut tampered = RpcBlock::from(&block);
+        tampered.header.nonce += 1;
+        assert!(matches!(Block::try_from(tampered), Err(RpcError::Internal(message)) if message.contains("does not match")));
+
+        let mut wrong_hash = RpcBlock::from(&block);
+        wrong_hash.header.hash = Hash::from_bytes([0x33; 32]).to_string();
+        assert!(Block::try_from(wrong_hash).is_err());
+    }
+
+    #[test]
+    fn test_rpc_transaction_id_must_match_its_fields() {
+        let block = sample_block();
+        let rpc_tx = RpcTransaction::from(&block.transactions[0]);
+        assert_eq!(Transaction::try_from(rpc_tx.clone()).unwrap().id(), block.transactions[0].id());
+
+        let mut tampered = rpc_tx.clone();
+        tampered.lock_time += 1;
+        assert!(matches!(Transaction::try_from(tampered), Err(RpcError::Internal(message)) if message.contains("does not match")));
+
+        let mut wrong_id = rpc_tx;
+        wrong_id.transaction_id = Hash::from_bytes([0x33; 32]).to_string();
+        assert!(Transaction::try_from(wrong_id).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Transaction packages in the mempool
+//!
+//! A pooled transaction can only be mined together with the pooled transactions
+//! it spends from, so it is worth the feerate of its ancestor set: its own fee
+//! and mass plus those of all its unmined ancestors. A cheap parent with a
+//! generous child is therefore worth the child's package. Conversely, dropping a
+//! transaction drops everything spending from it, so eviction ranks a
+//! transaction by its descendant set.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::{Add, Sub};
+use consensus_core::tx::Transaction;
+use consensus_core::Hash;
+use crate::model::MempoolEntry;
+
+/// Combined fee and mass of a set of transactions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackageFeerate {
+    pub fee: u64,
+    pub mass: u64,
+}
+
+impl PackageFeerate {
+    pub fn new(fee: u64, mass: u64) -> Self {
+        Self { fee, mass }
+    }
+
+    /// Fee per gram of mass
+    pub fn feerate(&self) -> f64 {
+        self.fee as f64 / self.mass.max(1) as f64
+    }
+
+    /// Compare feerates exactly, without rounding
+    pub fn cmp_feerate(&self, other: &Self) -> Ordering {
+        (self.fee as u128 * other.mass.max(1) as u128).cmp(&(other.fee as u128 * self.mass.max(1) as u128))
+    }
+}
+
+impl Add for PackageFeerate {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self { fee: self.fee + other.fee, mass: self.mass + other.mass }
+    }
+}
+
+impl Sub for PackageFeerate {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self { fee: self.fee - other.fee, mass: self.mass - other.mass }
+    }
+}
+
+/// Spending relations between pooled transactions
+pub struct MempoolGraph {
+    transactions: HashMap<Hash, (Transaction, PackageFeerate)>,
+    parents: HashMap<Hash, Vec<Hash>>,
+    children: HashMap<Hash, Vec<Hash>>,
+}
+
+impl MempoolGraph {
+    /// Graph of the non-orphan `entries`
+    pub fn new(entries: &[MempoolEntry]) -> Self {
+        let transactions: HashMap<Hash, (Transaction, PackageFeerate)> = entries
+            .iter()
+            .filter(|entry| !entry.is_orphan)
+            .map(|entry| {
+                let tx = entry.transaction.clone();
+                let own = PackageFeerate::new(entry.fee, tx.calculate_mass());
+                (tx.hash(), (tx, own))
+            })
+            .collect();
+
+        let mut parents: HashMap<Hash, Vec<Hash>> = HashMap::new();
+        let mut children: HashMap<Hash, Vec<Hash>> = HashMap::new();
+        for (hash, (tx, _)) in &transactions {
+            let mut spent: Vec<Hash> = tx
+                .inputs
+                .iter()
+                .map(|input| input.previous_outpoint.transaction_id)
+                .filter(|parent| transactions.contains_key(parent))
+                .collect();
+            spent.sort();
+            spent.dedup();
+            for parent in &spent {
+                children.entry(*parent).or_default().push(*hash);
+            }
+            parents.insert(*hash, spent);
+        }
+        Self { transactions, parents, children }
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.transactions.contains_key(hash)
+    }
+
+    /// Pooled transactions `hash` spends from, directly or not
+    pub fn ancestors(&self, hash: &Hash) -> HashSet<Hash> {
+        Self::reachable(hash, &self.parents)
+    }
+
+    /// Pooled transactions spending from `hash`, directly or not
+    pub fn descendants(&self, hash: &Hash) -> HashSet<Hash> {
+        Self::reachable(hash, &self.children)
+    }
+
+    /// Fee and mass of `hash` with its ancestors
+    pub fn ancestor_feerate(&self, hash: &Hash) -> PackageFeerate {
+        self.package(hash, self.ancestors(hash))
+    }
+
+    /// Fee and mass of `hash` with its descendants
+    pub fn descendant_feerate(&self, hash: &Hash) -> PackageFeerate {
+        self.package(hash, self.descendants(hash))
+    }
+
+    /// Transactions for a block template of at most `mass_limit`: the package
+    /// with the highest ancestor feerate goes first, parents before children,
+    /// and packages are re-rated as their ancestors get included
+    pub fn select(&self, mass_limit: u64) -> Vec<Transaction> {
+        let mut packages: HashMap<Hash, PackageFeerate> =
+            self.transactions.keys().map(|hash| (*hash, self.ancestor_feerate(hash))).collect();
+        let mut heap: BinaryHeap<Candidate> =
+            packages.iter().map(|(hash, package)| Candidate { hash: *hash, package: *package }).collect();
+
+        let mut included: HashSet<Hash> = HashSet::new();
+        let mut selected = Vec::new();
+        let mut mass = 0;
+        while let Some(Candidate { hash, package }) = heap.pop() {
+            // Stale entries were re-pushed with a smaller package
+            if included.contains(&hash) || packages.get(&hash) != Some(&package) {
+                continue;
+            }
+            if mass + package.mass > mass_limit {
+                packages.remove(&hash);
+                continue;
+            }
+
+            let mut members: Vec<Hash> =
+                self.ancestors(&hash).into_iter().filter(|ancestor| !included.contains(ancestor)).collect();
+            members.push(hash);
+            // A transaction has more ancestors than any of its ancestors
+            members.sort_by_key(|member| (self.ancestors(member).len(), *member));
+            for member in members {
+                let (tx, own) = &self.transactions[&member];
+                included.insert(member);
+                packages.remove(&member);
+                selected.push(tx.clone());
+                for descendant in self.descendants(&member) {
+                    if let Some(package) = packages.get_mut(&descendant) {
+                        *package = *package - *own;
+                        heap.push(Candidate { hash: descendant, package: *package });
+                    }
+                }
+            }
+            mass += package.mass;
+        }
+        selected
+    }
+
+    /// The transaction whose descendant set has the lowest feerate, skipping
+    /// those in `keep`, with that set and its fee and mass. Evicting the
+    /// transaction evicts the whole set.
+    pub fn eviction_candidate(&self, keep: &HashSet<Hash>) -> Option<(HashSet<Hash>, PackageFeerate)> {
+        self.transactions
+            .keys()
+            .filter(|hash| !keep.contains(hash))
+            .map(|hash| {
+                let mut set = self.descendants(hash);
+                let package = self.package(hash, set.clone());
+                set.insert(*hash);
+                (set, package, *hash)
+            })
+            .min_by(|a, b| a.1.cmp_feerate(&b.1).then(a.2.cmp(&b.2)))
+            .map(|(set, package, _)| (set, package))
+    }
+
+    fn package(&self, hash: &Hash, others: HashSet<Hash>) -> PackageFeerate {
+        others
+            .iter()
+            .chain(std::iter::once(hash))
+            .filter_map(|member| self.transactions.get(member))
+            .fold(PackageFeerate::default(), |package, (_, own)| package + *own)
+    }
+
+    fn reachable(hash: &Hash, edges: &HashMap<Hash, Vec<Hash>>) -> HashSet<Hash> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![*hash];
+        while let Some(next) = stack.pop() {
+            for neighbour in edges.get(&next).into_iter().flatten() {
+                if seen.insert(*neighbour) {
+                    stack.push(*neighbour);
+                }
+            }
+        }
+        seen
+    }
+}
+
+/// Heap entry ordered by package feerate, ties going to the lower hash
+#[derive(PartialEq, Eq)]
+struct Candidate {
+    hash: Hash,
+    package: PackageFeerate,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.package.cmp_feerate(&other.package).then_with(|| other.hash.cmp(&self.hash))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::subnets::SubnetworkId;
+    use consensus_core::tx::{ScriptPublicKey, TransactionInput, TransactionOutpoint, TransactionOutput};
+
+    /// Entry paying `fee` and spending output 0 of each of `spends`, or a made up outpoint
+    fn entry(n: u8, fee: u64, spends: &[&MempoolEntry]) -> MempoolEntry {
+        let outpoints: Vec<TransactionOutpoint> = if spends.is_empty() {
+            vec![TransactionOutpoint::new(Hash::from([n; 32]), 0)]
+        } else {
+            spends.iter().map(|parent| TransactionOutpoint::new(parent.transaction.hash(), 0)).collect()
+        };
+        let transaction = Transaction::new(
+            0,
+            outpoints.into_iter().map(|outpoint| TransactionInput::new(outpoint, vec![], 0, 1)).collect(),
+            vec![TransactionOutput::new(1000, ScriptPublicKey::from_vec(0, vec![0xac, n]))],
+            0,
+            SubnetworkId::default(),
+            0,
+            vec![],
+        );
+        MempoolEntry { fee, transaction, is_orphan: false }
+    }
+
+    fn mass(entry: &MempoolEntry) -> u64 {
+        entry.transaction.calculate_mass()
+    }
+
+    #[test]
+    fn test_ancestor_and_descendant_feerates() {
+        let parent = entry(1, 100, &[]);
+        let child = entry(2, 10_000, &[&parent]);
+        let grandchild = entry(3, 50, &[&child]);
+        let graph = MempoolGraph::new(&[parent.clone(), child.clone(), grandchild.clone()]);
+        let (p, c, g) = (parent.transaction.hash(), child.transaction.hash(), grandchild.transaction.hash());
+
+        assert_eq!(graph.ancestors(&g), HashSet::from([p, c]));
+        assert_eq!(graph.descendants(&p), HashSet::from([c, g]));
+        assert_eq!(graph.ancestor_feerate(&c), PackageFeerate::new(10_100, mass(&parent) + mass(&child)));
+        assert_eq!(graph.ancestor_feerate(&p), PackageFeerate::new(100, mass(&parent)));
+        assert_eq!(graph.descendant_feerate(&c), PackageFeerate::new(10_050, mass(&child) + mass(&grandchild)));
+    }
+
+    #[test]
+    fn test_package_is_selected_by_combined_feerate() {
+        let parent = entry(1, 0, &[]);
+        let child = entry(2, 10 * mass(&parent) * 2, &[&parent]);
+        let loner = entry(3, 5 * mass(&parent), &[]);
+        let graph = MempoolGraph::new(&[loner.clone(), child.clone(), parent.clone()]);
+
+        // At a feerate of 10 the package beats the loner's 5, and the parent goes first
+        let selected: Vec<Hash> = graph.select(u64::MAX).iter().map(Transaction::hash).collect();
+        assert_eq!(selected, vec![parent.transaction.hash(), child.transaction.hash(), loner.transaction.hash()]);
+
+        // Room for two transactions only: the package, not the loner with the parent or child alone
+        let limit = mass(&parent) + mass(&child);
+        let selected: Vec<Hash> = graph.select(limit).iter().map(Transaction::hash).collect();
+        assert_eq!(selected, vec![parent.transaction.hash(), child.transaction.hash()]);
+
+        // The child never goes without its parent
+        let selected = graph.select(mass(&child));
+        assert_eq!(selected.iter().map(Transaction::hash).collect::<Vec<_>>(), vec![loner.transaction.hash()]);
+    }
+
+    #[test]
+    fn test_eviction_ranks_by_descendant_set() {
+        let parent = entry(1, 0, &[]);
+        let child = entry(2, 10 * mass(&parent) * 2, &[&parent]);
+        let loner = entry(3, 5 * mass(&parent), &[]);
+        let graph = MempoolGraph::new(&[parent.clone(), child.clone(), loner.clone()]);
+
+        // The parent alone pays nothing, but evicting it costs the package's feerate of 10
+        let (set, package) = graph.eviction_candidate(&HashSet::new()).unwrap();
+        assert_eq!(set, HashSet::from([loner.transaction.hash()]));
+        assert_eq!(package, PackageFeerate::new(loner.fee, mass(&loner)));
+
+        // Without the loner the package goes as a whole
+        let (set, _) = graph.eviction_candidate(&HashSet::from([loner.transaction.hash()])).unwrap();
+        assert_eq!(set, HashSet::from([parent.transaction.hash(), child.transaction.hash()]));
+    }
+}
@@ -0,0 +1,407 @@
+//! Address-scoped `utxosChanged`, `virtualChainChanged` and `newBlockTemplate`
+//! subscriptions
+//!
+//! Each utxosChanged subscription holds the scripts of up to
+//! [`MAX_SUBSCRIPTION_ADDRESSES`] addresses. Every block applied to the UTXO set
+//! is matched against them, and a subscription with matching entries gets one
+//! notification for the block.
+//!
+//! A virtualChainChanged subscription gets every change of the selected chain
+//! after the tip it started from, which is either the tip at the time or, for a
+//! subscriber reconnecting, the tip it last knew caught up to the current one.
+//!
+//! A newBlockTemplate subscription is told whenever the node adds a block, after
+//! which templates build on other tips; miners then fetch a fresh template.
+//!
+//! Notifications queue up to [`NOTIFICATION_QUEUE`] deep; a subscriber falling
+//! further behind loses its subscription, which closes its receiver, except a
+//! newBlockTemplate one, which has nothing to miss while notifications wait.
+//! Ids are unique across all kinds, so a subscription can be ended by id alone.
+
+use crate::model::{NewBlockTemplateNotification, RpcUtxoByAddress, UtxosChangedNotification, VirtualChainChanged, VirtualChainChangedNotification};
+use crate::RpcError;
+use consensus::consensus::storage::UtxoChanges;
+use consensus_core::tx::{ScriptPublicKey, TransactionOutpoint, UtxoEntry};
+use consensus_core::Hash;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use tokio::sync::mpsc;
+
+/// Addresses one subscription may watch
+pub const MAX_SUBSCRIPTION_ADDRESSES: usize = 1000;
+
+/// Notifications waiting for a subscriber before it is unsubscribed
+pub const NOTIFICATION_QUEUE: usize = 64;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+struct Subscription {
+    /// Subscribed address of each watched script
+    scripts: HashMap<ScriptPublicKey, String>,
+    sender: mpsc::Sender<UtxosChangedNotification>,
+}
+
+impl Subscription {
+    /// Entries paying one of the subscription's scripts
+    fn matching(&self, entries: &[(TransactionOutpoint, UtxoEntry)]) -> Vec<RpcUtxoByAddress> {
+        entries
+            .iter()
+            .filter_map(|(outpoint, entry)| {
+                let address = self.scripts.get(&entry.script_public_key)?;
+                Some(RpcUtxoByAddress { address: address.clone(), outpoint: *outpoint, utxo_entry: entry.clone() })
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct UtxoSubscriptions {
+    subscriptions: RwLock<HashMap<u64, Subscription>>,
+}
+
+impl UtxoSubscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch `addresses`, returning the subscription id and its notifications
+    pub fn subscribe(&self, addresses: &[String]) -> Result<(u64, mpsc::Receiver<UtxosChangedNotification>), RpcError> {
+        let scripts = scripts_of(addresses)?;
+        if scripts.len() > MAX_SUBSCRIPTION_ADDRESSES {
+            return Err(too_many_addresses());
+        }
+        let (sender, receiver) = mpsc::channel(NOTIFICATION_QUEUE);
+        let id = next_id();
+        self.subscriptions.write().unwrap().insert(id, Subscription { scripts, sender });
+        Ok((id, receiver))
+    }
+
+    /// Start watching `add` and stop watching `remove`
+    pub fn modify(&self, id: u64, add: &[String], remove: &[String]) -> Result<(), RpcError> {
+        let added = scripts_of(add)?;
+        let removed = scripts_of(remove)?;
+        let mut subscriptions = self.subscriptions.write().unwrap();
+        let subscription = subscriptions.get_mut(&id).ok_or_else(|| RpcError::Rpc { code: -5, message: "Subscription not found".to_string() })?;
+        let mut scripts = subscription.scripts.clone();
+        scripts.extend(added);
+        for script in removed.keys() {
+            scripts.remove(script);
+        }
+        if scripts.len() > MAX_SUBSCRIPTION_ADDRESSES {
+            return Err(too_many_addresses());
+        }
+        subscription.scripts = scripts;
+        Ok(())
+    }
+
+    /// End a subscription, closing its receiver; false if it did not exist
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        self.subscriptions.write().unwrap().remove(&id).is_some()
+    }
+
+    /// Live subscriptions
+    pub fn len(&self) -> usize {
+        self.subscriptions.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send each subscription the entries of `changes` paying its addresses
+    pub fn notify(&self, changes: &UtxoChanges) {
+        let mut lagging = Vec::new();
+        for (id, subscription) in self.subscriptions.read().unwrap().iter() {
+            let added = subscription.matching(&changes.added);
+            let removed = subscription.matching(&changes.removed);
+            if added.is_empty() && removed.is_empty() {
+                continue;
+            }
+            let notification = UtxosChangedNotification { subscription_id: *id, block_hash: changes.block_hash, added, removed };
+            if subscription.sender.try_send(notification).is_err() {
+                lagging.push(*id);
+            }
+        }
+        if !lagging.is_empty() {
+            let mut subscriptions = self.subscriptions.write().unwrap();
+            for id in lagging {
+                subscriptions.remove(&id);
+            }
+        }
+    }
+}
+
+/// Selected chain tip a virtualChainChanged subscription starts from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainTip {
+    pub hash: Hash,
+    pub blue_score: u64,
+}
+
+struct ChainSubscription {
+    include_accepted_transaction_ids: bool,
+    /// Blue score of the last tip sent; changes up to it are covered
+    blue_score: u64,
+    sender: mpsc::Sender<VirtualChainChangedNotification>,
+}
+
+#[derive(Default)]
+pub struct ChainSubscriptions {
+    subscriptions: RwLock<HashMap<u64, ChainSubscription>>,
+}
+
+impl ChainSubscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a subscription from the tip and catch-up changes returned by
+    /// `start`, which runs while no notification can be sent
+    pub fn subscribe<F>(
+        &self,
+        include_accepted_transaction_ids: bool,
+        start: F,
+    ) -> Result<(u64, ChainTip, mpsc::Receiver<VirtualChainChangedNotification>), RpcError>
+    where
+        F: FnOnce() -> Result<(ChainTip, Option<VirtualChainChanged>), RpcError>,
+    {
+        let mut subscriptions = self.subscriptions.write().unwrap();
+        let (tip, catch_up) = start()?;
+        let id = next_id();
+        let (sender, receiver) = mpsc::channel(NOTIFICATION_QUEUE);
+        if let Some(changes) = catch_up {
+            let _ = sender.try_send(VirtualChainChangedNotification { subscription_id: id, changes });
+        }
+        subscriptions.insert(id, ChainSubscription { include_accepted_transaction_ids, blue_score: tip.blue_score, sender });
+        Ok((id, tip, receiver))
+    }
+
+    /// End a subscription, closing its receiver; false if it did not exist
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        self.subscriptions.write().unwrap().remove(&id).is_some()
+    }
+
+    /// Live subscriptions
+    pub fn len(&self) -> usize {
+        self.subscriptions.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send the change to a tip of `blue_score` to the subscriptions it is new to.
+    /// `changes` builds it with or without transaction ids, once for each.
+    pub fn notify(&self, blue_score: u64, changes: impl Fn(bool) -> VirtualChainChanged) {
+        let mut built: [Option<VirtualChainChanged>; 2] = [None, None];
+        self.subscriptions.write().unwrap().retain(|id, subscription| {
+            if blue_score <= subscription.blue_score {
+                return true;
+            }
+            subscription.blue_score = blue_score;
+            let include = subscription.include_accepted_transaction_ids;
+            let changes = built[include as usize].get_or_insert_with(|| changes(include)).clone();
+            subscription.sender.try_send(VirtualChainChangedNotification { subscription_id: *id, changes }).is_ok()
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct TemplateSubscriptions {
+    subscriptions: RwLock<HashMap<u64, mpsc::Sender<NewBlockTemplateNotification>>>,
+}
+
+impl TemplateSubscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> (u64, mpsc::Receiver<NewBlockTemplateNotification>) {
+        let (sender, receiver) = mpsc::channel(NOTIFICATION_QUEUE);
+        let id = next_id();
+        self.subscriptions.write().unwrap().insert(id, sender);
+        (id, receiver)
+    }
+
+    /// End a subscription, closing its receiver; false if it did not exist
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        self.subscriptions.write().unwrap().remove(&id).is_some()
+    }
+
+    /// Live subscriptions
+    pub fn len(&self) -> usize {
+        self.subscriptions.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tell every subscription that templates changed. One with a full queue
+    /// is already due to fetch one and keeps its subscription.
+    pub fn notify(&self) {
+        self.subscriptions.write().unwrap().retain(|id, sender| {
+            !matches!(
+                sender.try_send(NewBlockTemplateNotification { subscription_id: *id }),
+                Err(mpsc::error::TrySendError::Closed(_))
+            )
+        });
+    }
+}
+
+fn scripts_of(addresses: &[String]) -> Result<HashMap<ScriptPublicKey, String>, RpcError> {
+    addresses
+        .iter()
+        .map(|address| {
+            let script = wallet::Address::to_script_pub_key(address)
+                .map_err(|e| RpcError::Rpc { code: -8, message: format!("Invalid address {}: {}", address, e) })?;
+            Ok((script, address.clone()))
+        })
+        .collect()
+}
+
+fn too_many_addresses() -> RpcError {
+    RpcError::Rpc { code: -8, message: format!("A subscription watches at most {} addresses", MAX_SUBSCRIPTION_ADDRESSES) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::Hash;
+
+    const WATCHED: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const OTHER: &str = "1A1z7agoat3FwzZsQwtfTHtVtWWbooZewH";
+
+    fn entry(address: &str, amount: u64) -> (TransactionOutpoint, UtxoEntry) {
+        let script = wallet::Address::to_script_pub_key(address).unwrap();
+        (TransactionOutpoint::new(Hash::from_u64_word(amount), 0), UtxoEntry::new(amount, script, 1, false))
+    }
+
+    fn block(n: u64, added: Vec<(TransactionOutpoint, UtxoEntry)>, removed: Vec<(TransactionOutpoint, UtxoEntry)>) -> UtxoChanges {
+        UtxoChanges { block_hash: Hash::from_u64_word(n), added, removed }
+    }
+
+    #[test]
+    fn test_only_subscribed_addresses_are_notified() {
+        let subscriptions = UtxoSubscriptions::new();
+        let (id, mut receiver) = subscriptions.subscribe(&[WATCHED.to_string()]).unwrap();
+
+        subscriptions.notify(&block(1, vec![entry(WATCHED, 10), entry(OTHER, 20)], vec![]));
+        subscriptions.notify(&block(2, vec![entry(OTHER, 30)], vec![]));
+        subscriptions.notify(&block(3, vec![], vec![entry(WATCHED, 10)]));
+
+        let first = receiver.try_recv().unwrap();
+        assert_eq!((first.subscription_id, first.block_hash), (id, Hash::from_u64_word(1)));
+        assert_eq!(first.added.iter().map(|utxo| (utxo.address.as_str(), utxo.utxo_entry.amount)).collect::<Vec<_>>(), [(WATCHED, 10)]);
+        assert!(first.removed.is_empty());
+        // Blocks without the address's outputs are skipped
+        let spent = receiver.try_recv().unwrap();
+        assert_eq!(spent.block_hash, Hash::from_u64_word(3));
+        assert_eq!(spent.removed[0].utxo_entry.amount, 10);
+        assert!(receiver.try_recv().is_err());
+
+        subscriptions.modify(id, &[OTHER.to_string()], &[WATCHED.to_string()]).unwrap();
+        subscriptions.notify(&block(4, vec![entry(WATCHED, 40), entry(OTHER, 50)], vec![]));
+        assert_eq!(receiver.try_recv().unwrap().added[0].address, OTHER);
+
+        assert!(subscriptions.unsubscribe(id));
+        assert!(subscriptions.modify(id, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_subscriptions_are_bounded() {
+        let subscriptions = UtxoSubscriptions::new();
+        let too_many: Vec<String> = (0..=MAX_SUBSCRIPTION_ADDRESSES as u64)
+            .map(|n| wallet::Address::from_script_pub_key(&ScriptPublicKey::from_vec(0, p2pkh(n))).unwrap())
+            .collect();
+        assert!(subscriptions.subscribe(&too_many).is_err());
+        let (id, _receiver) = subscriptions.subscribe(&too_many[1..]).unwrap();
+        assert!(subscriptions.modify(id, &too_many[..1], &[]).is_err());
+        assert!(subscriptions.subscribe(&["not an address".to_string()]).is_err());
+
+        // A subscriber that stops reading loses its subscription
+        let (lagging, mut receiver) = subscriptions.subscribe(&[WATCHED.to_string()]).unwrap();
+        for n in 0..=NOTIFICATION_QUEUE as u64 {
+            subscriptions.notify(&block(n, vec![entry(WATCHED, n + 1)], vec![]));
+        }
+        assert!(!subscriptions.unsubscribe(lagging));
+        while receiver.try_recv().is_ok() {}
+        assert!(matches!(receiver.try_recv(), Err(mpsc::error::TryRecvError::Disconnected)));
+    }
+
+    #[test]
+    fn test_chain_changes_after_the_start_are_notified() {
+        let subscriptions = ChainSubscriptions::new();
+        let tip = ChainTip { hash: Hash::from_u64_word(5), blue_score: 5 };
+        let catch_up = VirtualChainChanged { added_chain_block_hashes: vec![tip.hash], ..Default::default() };
+        let (id, started, mut receiver) = subscriptions.subscribe(false, || Ok((tip, Some(catch_up.clone())))).unwrap();
+        assert_eq!(started, tip);
+        assert_eq!(receiver.try_recv().unwrap().changes, catch_up);
+
+        let change = |hash: u64| {
+            move |include: bool| VirtualChainChanged {
+                added_chain_block_hashes: vec![Hash::from_u64_word(hash)],
+                accepted_transaction_ids: if include { vec![Default::default()] } else { vec![] },
+                ..Default::default()
+            }
+        };
+        // Changes the catch-up already covered are skipped
+        subscriptions.notify(5, change(5));
+        subscriptions.notify(6, change(6));
+        let next = receiver.try_recv().unwrap();
+        assert_eq!((next.subscription_id, next.changes.added_chain_block_hashes), (id, vec![Hash::from_u64_word(6)]));
+        assert!(next.changes.accepted_transaction_ids.is_empty());
+        assert!(receiver.try_recv().is_err());
+
+        let (with_ids, _, mut receiver) = subscriptions.subscribe(true, || Ok((tip, None))).unwrap();
+        assert_ne!(with_ids, id);
+        subscriptions.notify(7, change(7));
+        assert_eq!(receiver.try_recv().unwrap().changes.accepted_transaction_ids.len(), 1);
+
+        assert!(subscriptions.unsubscribe(id));
+        assert_eq!(subscriptions.len(), 1);
+        let err = RpcError::Rpc { code: -5, message: "Block not found".to_string() };
+        assert!(subscriptions.subscribe(false, || Err(err)).is_err());
+        assert_eq!(subscriptions.len(), 1);
+    }
+
+    #[test]
+    fn test_template_subscriptions_coalesce() {
+        let subscriptions = TemplateSubscriptions::new();
+        let (id, mut receiver) = subscriptions.subscribe();
+        subscriptions.notify();
+        assert_eq!(receiver.try_recv().unwrap().subscription_id, id);
+        assert!(receiver.try_recv().is_err());
+
+        // A subscriber behind on notifications is not dropped
+        for _ in 0..=NOTIFICATION_QUEUE {
+            subscriptions.notify();
+        }
+        assert_eq!(subscriptions.len(), 1);
+        while receiver.try_recv().is_ok() {}
+
+        // One whose receiver is gone is
+        let (_, gone) = subscriptions.subscribe();
+        drop(gone);
+        subscriptions.notify();
+        assert_eq!(subscriptions.len(), 1);
+        assert!(subscriptions.unsubscribe(id));
+        assert!(subscriptions.is_empty());
+    }
+
+    /// Pay-to-pubkey-hash script for a hash made from `n`
+    fn p2pkh(n: u64) -> Vec<u8> {
+        let mut script = vec![0x76, 0xa9, 0x14];
+        script.extend_from_slice(&n.to_le_bytes());
+        script.extend_from_slice(&[0; 12]);
+        script.extend_from_slice(&[0x88, 0xac]);
+        script
+    }
+}
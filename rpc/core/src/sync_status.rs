@@ -0,0 +1,7 @@
+/// Sync state of the node, as its sync manager tracks it
+pub trait SyncStatusProvider: Send + Sync {
+    /// Whether no sync is in progress, the local tip having caught up with the peers
+    fn is_synced(&self) -> bool;
+    /// Share of the peers' advertised blue score the local tip reaches, from 0.0 to 1.0
+    fn progress(&self) -> f64;
+}
@@ -1,3 +1,3 @@
 pub mod server;
 
-pub use server::WrpcServer;
+pub use server::{ConnectionLimits, WrpcServer};
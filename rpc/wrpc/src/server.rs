@@ -1,15 +1,59 @@
 //! WebSocket RPC server for browser/web clients
 
+use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-use tracing::{error, info};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex, Notify, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
+use tracing::{error, info, warn};
 use rpc_core::RpcCoordinator;
 use rpc_core::RpcApi;
+use rpc_core::RpcBlock;
 use consensus_core::{block::Block, tx::Transaction, Hash};
 use hex;
 
+/// JSON-RPC error code of requests the node failed to handle
+const SERVER_ERROR_CODE: i32 = -32000;
+
+/// Methods managing the client's notification subscriptions
+const SUBSCRIPTION_METHODS: [&str; 5] =
+    ["subscribe", "modifySubscription", "unsubscribe", "notifyVirtualChainChanged", "notifyNewBlockTemplate"];
+
+/// Topic, and notification method, of address UTXO changes
+const UTXOS_CHANGED: &str = "utxosChanged";
+
+/// Notification method of selected chain changes
+const VIRTUAL_CHAIN_CHANGED: &str = "virtualChainChanged";
+
+/// Notification method telling miners to fetch a new block template
+const NEW_BLOCK_TEMPLATE: &str = "newBlockTemplate";
+
+/// JSON-RPC error code sent to clients over the connection limit before closing
+const CONNECTION_LIMIT_CODE: i32 = -32001;
+
+/// Time a refused client gets to complete the handshake and read the refusal
+const REFUSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests of one client handled at once unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Messages waiting for one client before it is dropped, unless configured otherwise
+pub const DEFAULT_MAX_QUEUED_MESSAGES: usize = 256;
+
+/// Coinbase address of templates requested without one, when the node has no wallet
+const DEFAULT_PAY_ADDRESS: &str = "1A1z7agoat3FwzZsQwtfTHtVtWWbooZewH";
+
+/// Methods that spend or change the node's wallet or change how the node runs.
+/// The server has no authentication, so these are only served to clients on the same host.
+const LOCAL_METHODS: [&str; 3] = ["getNewAddress", "sendToAddress", "setLogLevel"];
+
 #[derive(Debug, serde::Deserialize)]
 struct JsonRpcRequest {
     jsonrpc: String,
@@ -36,97 +80,395 @@ struct JsonRpcError {
     data: Option<serde_json::Value>,
 }
 
+/// Limits applied to each WebSocket client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Requests of one client handled at once; its further requests wait unread
+    pub max_concurrent_requests: usize,
+    /// Messages waiting to be written to one client before it is dropped as too slow
+    pub max_queued_messages: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self { max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS, max_queued_messages: DEFAULT_MAX_QUEUED_MESSAGES }
+    }
+}
+
+/// Subscriptions of one client by id, with their notification method and the
+/// task forwarding their notifications
+#[derive(Default)]
+struct ClientSubscriptions(std::sync::Mutex<HashMap<u64, (&'static str, JoinHandle<()>)>>);
+
+impl ClientSubscriptions {
+    /// Send the notifications of subscription `id` through `outbox` as `method`
+    fn forward<T>(self: &Arc<Self>, id: u64, method: &'static str, mut receiver: mpsc::Receiver<T>, outbox: Arc<Outbox>)
+    where
+        T: serde::Serialize + Send + 'static,
+    {
+        let owner = self.clone();
+        let forwarder = tokio::spawn(async move {
+            while let Some(notification) = receiver.recv().await {
+                let notification = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": notification });
+                if !outbox.send(Message::Text(notification.to_string())).await {
+                    return;
+                }
+            }
+            // Not unsubscribed by the client: it fell behind its notifications
+            if owner.0.lock().unwrap().remove(&id).is_some() {
+                outbox.overflow.notify_one();
+            }
+        });
+        self.0.lock().unwrap().insert(id, (method, forwarder));
+    }
+
+    fn contains(&self, id: u64) -> bool {
+        self.0.lock().unwrap().contains_key(&id)
+    }
+
+    /// End subscription `id`; false if the client doesn't have it
+    fn end(&self, id: u64, coordinator: &RpcCoordinator) -> bool {
+        let Some((method, forwarder)) = self.0.lock().unwrap().remove(&id) else {
+            return false;
+        };
+        match method {
+            UTXOS_CHANGED => coordinator.utxo_subscriptions().unsubscribe(id),
+            VIRTUAL_CHAIN_CHANGED => coordinator.chain_subscriptions().unsubscribe(id),
+            _ => coordinator.template_subscriptions().unsubscribe(id),
+        };
+        forwarder.abort();
+        true
+    }
+
+    fn end_all(&self, coordinator: &RpcCoordinator) {
+        let ids: Vec<u64> = self.0.lock().unwrap().keys().copied().collect();
+        for id in ids {
+            self.end(id, coordinator);
+        }
+    }
+}
+
+/// How a client's connection ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionEnd {
+    Closed,
+    /// More messages were waiting for the client than allowed
+    TooSlow,
+}
+
+type WsSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+
+/// Messages to one client. Request tasks write through the shared sink; when
+/// too many are waiting for it the client is too slow and gets disconnected.
+struct Outbox {
+    sink: Mutex<WsSink>,
+    queued: AtomicUsize,
+    max_queued: usize,
+    overflow: Notify,
+}
+
+impl Outbox {
+    fn new(sink: WsSink, max_queued: usize) -> Self {
+        Self { sink: Mutex::new(sink), queued: AtomicUsize::new(0), max_queued, overflow: Notify::new() }
+    }
+
+    /// Write `message`; false when the client is gone or too slow
+    async fn send(&self, message: Message) -> bool {
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            self.overflow.notify_one();
+            return false;
+        }
+        let sent = self.sink.lock().await.send(message).await.is_ok();
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        sent
+    }
+}
+
+/// Answer the text requests read from `ws_stream` with `handler`, running up to
+/// `max_concurrent_requests` of them at once so responses go out as they are ready.
+/// The handler gets the client's outbox to push notifications through.
+async fn serve_connection<H, F>(ws_stream: WebSocketStream<TcpStream>, limits: ConnectionLimits, handler: H) -> ConnectionEnd
+where
+    H: Fn(String, Arc<Outbox>) -> F,
+    F: Future<Output = String> + Send + 'static,
+{
+    let (write, mut read) = ws_stream.split();
+    let outbox = Arc::new(Outbox::new(write, limits.max_queued_messages));
+    let permits = Arc::new(Semaphore::new(limits.max_concurrent_requests.max(1)));
+    // Dropping the set on return aborts requests still in flight, releasing the sink
+    let mut requests = JoinSet::new();
+
+    loop {
+        let permit = tokio::select! {
+            _ = outbox.overflow.notified() => return ConnectionEnd::TooSlow,
+            Some(_) = requests.join_next() => continue,
+            permit = permits.clone().acquire_owned() => permit.expect("request permits are never closed"),
+        };
+        let item = tokio::select! {
+            _ = outbox.overflow.notified() => return ConnectionEnd::TooSlow,
+            item = read.next() => item,
+        };
+        match item {
+            Some(Ok(Message::Text(text))) => {
+                let response = handler(text, outbox.clone());
+                let outbox = outbox.clone();
+                requests.spawn(async move {
+                    outbox.send(Message::Text(response.await)).await;
+                    drop(permit);
+                });
+            }
+            Some(Ok(Message::Close(_))) | None => return ConnectionEnd::Closed,
+            Some(Ok(_)) => { /* Ignore other message types */ }
+            Some(Err(e)) => {
+                // Client disconnected or protocol error; this is normal when clients close connections
+                tracing::debug!("WebSocket client disconnected: {}", e);
+                return ConnectionEnd::Closed;
+            }
+        }
+    }
+}
+
 pub struct WrpcServer {
     coordinator: Arc<RpcCoordinator>,
-    port: u16,
+    listeners: Vec<TcpListener>,
+    limits: ConnectionLimits,
 }
 
 impl WrpcServer {
-    pub fn new(coordinator: Arc<RpcCoordinator>, port: u16) -> Self {
-        Self { coordinator, port }
+    /// Bind all listen addresses up front so a taken port is reported before serving starts
+    pub fn bind(coordinator: Arc<RpcCoordinator>, addresses: &[SocketAddr]) -> Result<Self, String> {
+        let listeners = network::listen::bind_listeners(addresses)?;
+        Ok(Self { coordinator, listeners, limits: ConnectionLimits::default() })
+    }
+
+    /// Request concurrency and outbound queue limits of each client
+    pub fn with_limits(self, limits: ConnectionLimits) -> Self {
+        Self { limits, ..self }
     }
 
-    pub async fn start(&self) -> Result<(), String> {
-        let addr = format!("127.0.0.1:{}", self.port);
-        let listener = TcpListener::bind(&addr).await
-            .map_err(|e| format!("Failed to bind: {}", e))?;
+    /// Addresses actually bound (useful when binding port 0)
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().filter_map(|l| l.local_addr().ok()).collect()
+    }
+
+    pub async fn start(self) -> Result<(), String> {
+        let mut tasks = Vec::new();
+        for listener in self.listeners {
+            let coordinator = self.coordinator.clone();
+            tasks.push(tokio::spawn(Self::accept_loop(listener, coordinator, self.limits)));
+        }
+        for task in tasks {
+            task.await.map_err(|e| format!("Listener task failed: {}", e))??;
+        }
+        Ok(())
+    }
 
-        info!("wRPC server listening on {}", addr);
+    async fn accept_loop(listener: TcpListener, coordinator: Arc<RpcCoordinator>, limits: ConnectionLimits) -> Result<(), String> {
+        if let Ok(addr) = listener.local_addr() {
+            info!("wRPC server listening on {}", addr);
+        }
 
         loop {
-            let (stream, _) = listener.accept().await
+            let (stream, addr) = listener.accept().await
                 .map_err(|e| format!("Accept error: {}", e))?;
 
-            let coordinator = self.coordinator.clone();
+            let Some(connection) = coordinator.open_connection() else {
+                warn!("Refusing RPC client {}: connection limit reached", addr);
+                tokio::spawn(Self::refuse(stream));
+                continue;
+            };
+            let coordinator = coordinator.clone();
 
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(stream, coordinator).await {
-                    error!("WebSocket error: {}", e);
+                match Self::handle_connection(stream, coordinator.clone(), limits).await {
+                    Ok(ConnectionEnd::Closed) => {}
+                    Ok(ConnectionEnd::TooSlow) => {
+                        warn!("Dropped RPC client {}: it is not keeping up with its responses", addr);
+                        coordinator.record_dropped_client();
+                    }
+                    Err(e) => error!("WebSocket error: {}", e),
                 }
+                drop(connection);
             });
         }
     }
 
+    /// Tell a client over the connection limit why it is turned away, then close
+    async fn refuse(stream: TcpStream) {
+        let refusal = JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            result: None,
+            error: Some(JsonRpcError { code: CONNECTION_LIMIT_CODE, message: "Connection limit reached".to_string(), data: None }),
+        };
+        let refusal = serde_json::to_string(&refusal).unwrap_or_default();
+        let _ = tokio::time::timeout(REFUSE_TIMEOUT, async {
+            let mut ws_stream = accept_async(stream).await.ok()?;
+            ws_stream.send(Message::Text(refusal)).await.ok()?;
+            ws_stream.close(None).await.ok()
+        })
+        .await;
+    }
+
     async fn handle_connection(
-        stream: tokio::net::TcpStream,
+        stream: TcpStream,
         coordinator: Arc<RpcCoordinator>,
-    ) -> Result<(), String> {
+        limits: ConnectionLimits,
+    ) -> Result<ConnectionEnd, String> {
         let ws_stream = accept_async(stream).await
             .map_err(|e| format!("WebSocket handshake error: {}", e))?;
-
         let peer_addr = ws_stream.get_ref().peer_addr().ok();
-        let (mut write, mut read) = ws_stream.split();
-
-        while let Some(item) = read.next().await {
-            match item {
-                Ok(msg) => {
-                    match msg {
-                        Message::Text(text) => {
-                            if let Some(addr) = peer_addr {
-                                info!("Received WS message from {}: {}", addr, text);
-                            }
-
-                            // Handle request and reply; if handling fails, log and continue
-                            match Self::handle_request(&text, &coordinator).await {
-                                Ok(response) => {
-                                    if let Err(e) = write.send(Message::Text(response)).await {
-                                        error!("Write error: {}", e);
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Request handling error: {}", e);
-                                }
-                            }
-                        }
-                        Message::Close(_) => break,
-                        _ => { /* Ignore other message types */ }
-                    }
+
+        let subscriptions = Arc::new(ClientSubscriptions::default());
+        let handler_subscriptions = subscriptions.clone();
+        let handler_coordinator = coordinator.clone();
+        let end = serve_connection(ws_stream, limits, move |text: String, outbox: Arc<Outbox>| {
+            let coordinator = handler_coordinator.clone();
+            let subscriptions = handler_subscriptions.clone();
+            async move {
+                if let Some(addr) = peer_addr {
+                    info!("Received WS message from {}: {}", addr, text);
                 }
-                Err(e) => {
-                    // Client disconnected or protocol error; this is normal when clients close connections
-                    // Only log at debug level to reduce noise
-                    if let Some(addr) = peer_addr {
-                        tracing::debug!("WebSocket client {} disconnected: {}", addr, e);
+                let response = match serde_json::from_str::<JsonRpcRequest>(&text) {
+                    Ok(rpc_req) if SUBSCRIPTION_METHODS.contains(&rpc_req.method.as_str()) => {
+                        Self::handle_subscription_request(rpc_req, &coordinator, &outbox, &subscriptions)
                     }
-                    break;
+                    _ => Self::handle_request(&text, &coordinator, peer_addr).await,
+                };
+                // Answer failures with an error so the client isn't left waiting
+                response.unwrap_or_else(|e| {
+                    error!("Request handling error: {}", e);
+                    Self::error_response(&text, e)
+                })
+            }
+        })
+        .await;
+
+        subscriptions.end_all(&coordinator);
+        Ok(end)
+    }
+
+    /// Run a subscription method for the client whose outbox is `outbox`
+    fn handle_subscription_request(
+        rpc_req: JsonRpcRequest,
+        coordinator: &RpcCoordinator,
+        outbox: &Arc<Outbox>,
+        subscriptions: &Arc<ClientSubscriptions>,
+    ) -> Result<String, String> {
+        let params = rpc_req.params.unwrap_or(serde_json::Value::Null);
+        let addresses = |name: &str| -> Result<Vec<String>, String> {
+            match params.get(name) {
+                Some(value) => serde_json::from_value(value.clone()).map_err(|e| format!("Invalid {}: {}", name, e)),
+                None => Ok(Vec::new()),
+            }
+        };
+        let subscription_id = || params.get("subscriptionId").and_then(|v| v.as_u64()).ok_or("Missing subscriptionId parameter");
+        let hash_param = |name: &str| -> Result<Option<Hash>, String> {
+            let Some(value) = params.get(name).and_then(|v| v.as_str()) else {
+                return Ok(None);
+            };
+            let bytes = hex::decode(value).map_err(|e| format!("Invalid {}: {}", name, e))?;
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| format!("{} must be 32 bytes", name))?;
+            Ok(Some(Hash::from(bytes)))
+        };
+
+        let result = match rpc_req.method.as_str() {
+            "subscribe" => {
+                // Expect params: { "topic": "utxosChanged", "addresses": [...] }
+                let topic = params.get("topic").and_then(|v| v.as_str()).ok_or("Missing topic parameter")?;
+                if topic != UTXOS_CHANGED {
+                    return Err(format!("Unknown topic: {}", topic));
                 }
+                let (id, receiver) = coordinator.utxo_subscriptions().subscribe(&addresses("addresses")?)
+                    .map_err(|e| format!("subscribe error: {:?}", e))?;
+                subscriptions.forward(id, UTXOS_CHANGED, receiver, outbox.clone());
+                serde_json::json!({ "subscriptionId": id })
             }
-        }
+            "notifyVirtualChainChanged" => {
+                // Expect params: { "includeAcceptedTransactionIds": bool, "startHash": optional hex }
+                let include = params.get("includeAcceptedTransactionIds").and_then(|v| v.as_bool()).unwrap_or(false);
+                let (id, tip, receiver) = coordinator.subscribe_virtual_chain(include, hash_param("startHash")?)
+                    .map_err(|e| format!("notifyVirtualChainChanged error: {:?}", e))?;
+                subscriptions.forward(id, VIRTUAL_CHAIN_CHANGED, receiver, outbox.clone());
+                serde_json::json!({ "subscriptionId": id, "tip": tip })
+            }
+            "notifyNewBlockTemplate" => {
+                let (id, receiver) = coordinator.template_subscriptions().subscribe();
+                subscriptions.forward(id, NEW_BLOCK_TEMPLATE, receiver, outbox.clone());
+                serde_json::json!({ "subscriptionId": id })
+            }
+            "modifySubscription" => {
+                // Expect params: { "subscriptionId": id, "addAddresses": [...], "removeAddresses": [...] }
+                let id = subscription_id()?;
+                if !subscriptions.contains(id) {
+                    return Err(format!("Unknown subscription {}", id));
+                }
+                coordinator.utxo_subscriptions().modify(id, &addresses("addAddresses")?, &addresses("removeAddresses")?)
+                    .map_err(|e| format!("modifySubscription error: {:?}", e))?;
+                serde_json::Value::Bool(true)
+            }
+            _ => {
+                // Expect params: { "subscriptionId": id }
+                let id = subscription_id()?;
+                if !subscriptions.end(id, coordinator) {
+                    return Err(format!("Unknown subscription {}", id));
+                }
+                serde_json::Value::Bool(true)
+            }
+        };
 
-        Ok(())
+        let response = JsonRpcResponse { jsonrpc: "2.0".to_string(), id: rpc_req.id, result: Some(result), error: None };
+        serde_json::to_string(&response).map_err(|e| format!("Serialization error: {}", e))
+    }
+
+    /// JSON-RPC error response to `request`, echoing its id if it has one
+    fn error_response(request: &str, message: String) -> String {
+        let id = serde_json::from_str::<serde_json::Value>(request).ok().and_then(|v| v.get("id").cloned());
+        let response = JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError { code: SERVER_ERROR_CODE, message, data: None }),
+        };
+        serde_json::to_string(&response).unwrap_or_default()
     }
 
     async fn handle_request(
         request: &str,
         coordinator: &Arc<RpcCoordinator>,
+        peer_addr: Option<SocketAddr>,
     ) -> Result<String, String> {
         // Parse JSON-RPC request
         let rpc_req: JsonRpcRequest = serde_json::from_str(request)
             .map_err(|e| format!("Invalid JSON-RPC request: {}", e))?;
 
+        if LOCAL_METHODS.contains(&rpc_req.method.as_str()) && !peer_addr.is_some_and(|addr| addr.ip().is_loopback()) {
+            return Err(format!("{} is only available to local clients", rpc_req.method));
+        }
+
         // Route to appropriate method
         let result = match rpc_req.method.as_str() {
+            "getInfo" => {
+                let info = coordinator.get_info().await
+                    .map_err(|e| format!("getInfo error: {:?}", e))?;
+                serde_json::to_value(&info).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "setLogLevel" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [target, level]; an empty target sets the default level
+                let (target, level) = if let serde_json::Value::Array(arr) = &params {
+                    let target = arr.first().and_then(|v| v.as_str()).ok_or("Missing target parameter")?;
+                    let level = arr.get(1).and_then(|v| v.as_str()).ok_or("Missing level parameter")?;
+                    (target.to_string(), level.to_string())
+                } else {
+                    return Err("Invalid params format".to_string());
+                };
+
+                let filter = coordinator.set_log_level(target, level).await
+                    .map_err(|e| format!("setLogLevel error: {:?}", e))?;
+                serde_json::json!(filter)
+            }
             "getBlockCount" => {
                 let count = coordinator.get_block_count().await
                     .map_err(|e| format!("getBlockCount error: {:?}", e))?;
@@ -134,9 +476,11 @@ impl WrpcServer {
             }
             "getBlock" => {
                 let params = rpc_req.params.ok_or("Missing params")?;
-                let hash_str = if let serde_json::Value::Array(arr) = &params {
+                // Expect params: [hash, verbose?]
+                let (hash_str, verbose) = if let serde_json::Value::Array(arr) = &params {
                     if arr.len() > 0 {
-                        arr[0].as_str().ok_or("Invalid hash parameter")?
+                        let verbose = arr.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
+                        (arr[0].as_str().ok_or("Invalid hash parameter")?, verbose)
                     } else {
                         return Err("Missing hash parameter".to_string());
                     }
@@ -148,11 +492,96 @@ impl WrpcServer {
                 let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
                 let hash = Hash::from(array);
 
-                let block = coordinator.get_block(hash).await
-                    .map_err(|e| format!("getBlock error: {:?}", e))?;
+                if verbose {
+                    let block = coordinator.get_block_verbose(hash).await
+                        .map_err(|e| format!("getBlock error: {:?}", e))?;
+                    serde_json::to_value(&block).map_err(|e| format!("Serialization error: {}", e))?
+                } else {
+                    let block = coordinator.get_block(hash).await
+                        .map_err(|e| format!("getBlock error: {:?}", e))?;
+                    serde_json::to_value(RpcBlock::from(&block)).map_err(|e| format!("Serialization error: {}", e))?
+                }
+            }
+            "getBlocks" => {
+                // Expect params: { "lowHash": "..." | null, "includeBlocks": bool, "includeTransactions": bool, "includeVerboseData": bool }
+                let params = rpc_req.params.unwrap_or(serde_json::Value::Null);
+                let low_hash = match params.get("lowHash").and_then(|v| v.as_str()) {
+                    Some(hash_str) => {
+                        let bytes = hex::decode(hash_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                        let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+                        Some(Hash::from(array))
+                    }
+                    None => None,
+                };
+                let include_blocks = params.get("includeBlocks").and_then(|v| v.as_bool()).unwrap_or(false);
+                let include_transactions = params.get("includeTransactions").and_then(|v| v.as_bool()).unwrap_or(false);
+                let include_verbose_data = params.get("includeVerboseData").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let response = coordinator.get_blocks(low_hash, include_blocks, include_transactions, include_verbose_data).await
+                    .map_err(|e| format!("getBlocks error: {:?}", e))?;
+                serde_json::to_value(&response).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getHeaders" => {
+                // Expect params: [startHash, limit]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let arr = params.as_array().ok_or("Invalid params format")?;
+                let hash_str = arr.first().and_then(|v| v.as_str()).ok_or("Missing startHash parameter")?;
+                let bytes = hex::decode(hash_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+                let limit = arr.get(1).and_then(|v| v.as_u64()).ok_or("Missing limit parameter")? as usize;
 
+                let headers = coordinator.get_headers(Hash::from(array), limit).await
+                    .map_err(|e| format!("getHeaders error: {:?}", e))?;
+                serde_json::to_value(&headers).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getBlockHex" => {
+                // Expect params: [hash]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let hash_str = params.as_array().and_then(|arr| arr.first()).and_then(|v| v.as_str()).ok_or("Missing hash parameter")?;
+                let bytes = hex::decode(hash_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+
+                let block_hex = coordinator.get_block_hex(Hash::from(array)).await
+                    .map_err(|e| format!("getBlockHex error: {:?}", e))?;
+                serde_json::json!(block_hex)
+            }
+            "decodeBlock" => {
+                // Expect params: [blockHex]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let block_hex = params.get(0).and_then(|v| v.as_str()).ok_or("Missing blockHex parameter")?;
+
+                let block = coordinator.decode_block(block_hex.to_string()).await
+                    .map_err(|e| format!("decodeBlock error: {:?}", e))?;
                 serde_json::to_value(&block).map_err(|e| format!("Serialization error: {}", e))?
             }
+            "getVirtualChainFromBlock" => {
+                // Expect params: { "startHash": "..." | null, "includeAcceptedTransactionIds": bool }
+                let params = rpc_req.params.unwrap_or(serde_json::Value::Null);
+                let start_hash = match params.get("startHash").and_then(|v| v.as_str()) {
+                    Some(hash_str) => {
+                        let bytes = hex::decode(hash_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                        let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+                        Some(Hash::from(array))
+                    }
+                    None => None,
+                };
+                let include = params.get("includeAcceptedTransactionIds").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let changes = coordinator.get_virtual_chain_from_block(start_hash, include).await
+                    .map_err(|e| format!("getVirtualChainFromBlock error: {:?}", e))?;
+                serde_json::to_value(&changes).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getBlockStatus" => {
+                // Expect params: [hash]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let hash_str = params.as_array().and_then(|arr| arr.first()).and_then(|v| v.as_str()).ok_or("Missing hash parameter")?;
+                let bytes = hex::decode(hash_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+
+                let status = coordinator.get_block_status(Hash::from(array)).await
+                    .map_err(|e| format!("getBlockStatus error: {:?}", e))?;
+                serde_json::to_value(&status).map_err(|e| format!("Serialization error: {}", e))?
+            }
             "getBlockDagInfo" => {
                 let info = coordinator.get_block_dag_info().await
                     .map_err(|e| format!("getBlockDagInfo error: {:?}", e))?;
@@ -170,18 +599,110 @@ impl WrpcServer {
                     .map_err(|e| format!("getPeerInfo error: {:?}", e))?;
                 serde_json::json!(peers)
             }
+            "getConnectionCount" => {
+                let counts = coordinator.get_connection_count().await
+                    .map_err(|e| format!("getConnectionCount error: {:?}", e))?;
+                serde_json::to_value(&counts).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "addPeer" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address, isPermanent?]
+                let (address, is_permanent) = if let serde_json::Value::Array(arr) = &params {
+                    let address = arr.first().and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+                    (address.to_string(), arr.get(1).and_then(|v| v.as_bool()).unwrap_or(false))
+                } else {
+                    return Err("Invalid params format".to_string());
+                };
+
+                coordinator.add_peer(address, is_permanent).await
+                    .map_err(|e| format!("addPeer error: {:?}", e))?;
+                serde_json::Value::Null
+            }
+            "removePeer" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+
+                coordinator.remove_peer(address.to_string()).await
+                    .map_err(|e| format!("removePeer error: {:?}", e))?;
+                serde_json::Value::Null
+            }
+            "banPeer" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address, durationSecs?]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+                let duration_secs = params.get(1).and_then(|v| v.as_u64()).unwrap_or(0);
+
+                coordinator.ban_peer(address.to_string(), duration_secs).await
+                    .map_err(|e| format!("banPeer error: {:?}", e))?;
+                serde_json::Value::Null
+            }
+            "unbanPeer" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+
+                coordinator.unban_peer(address.to_string()).await
+                    .map_err(|e| format!("unbanPeer error: {:?}", e))?;
+                serde_json::Value::Null
+            }
+            "getBannedPeers" => {
+                let banned = coordinator.get_banned_peers().await
+                    .map_err(|e| format!("getBannedPeers error: {:?}", e))?;
+                serde_json::to_value(&banned).map_err(|e| format!("Serialization error: {}", e))?
+            }
             "getMempoolInfo" => {
                 let info = coordinator.get_mempool_info().await
                     .map_err(|e| format!("getMempoolInfo error: {:?}", e))?;
                 serde_json::json!({
                     "size": info.size,
-                    "bytes": info.bytes
+                    "bytes": info.bytes,
+                    "total_fees": info.total_fees,
+                    "total_mass": info.total_mass,
+                    "estimated_block_count": info.estimated_block_count
                 })
             }
+            "getMempoolEntries" => {
+                // Expect params: [includeOrphanPool?, filterTransactionPool?]
+                let params = rpc_req.params.unwrap_or(serde_json::Value::Null);
+                let include_orphan_pool = params.get(0).and_then(|v| v.as_bool()).unwrap_or(true);
+                let filter_transaction_pool = params.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
+                let entries = coordinator.get_mempool_entries(include_orphan_pool, filter_transaction_pool).await
+                    .map_err(|e| format!("getMempoolEntries error: {:?}", e))?;
+                serde_json::to_value(&entries).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "flushMempool" => {
+                let saved = coordinator.flush_mempool().await
+                    .map_err(|e| format!("flushMempool error: {:?}", e))?;
+                serde_json::json!(saved)
+            }
+            "getFeeEstimate" => {
+                let estimate = coordinator.get_fee_estimate().await
+                    .map_err(|e| format!("getFeeEstimate error: {:?}", e))?;
+                serde_json::to_value(&estimate).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getCoinSupply" => {
+                let supply = coordinator.get_coin_supply().await
+                    .map_err(|e| format!("getCoinSupply error: {:?}", e))?;
+                serde_json::to_value(&supply).map_err(|e| format!("Serialization error: {}", e))?
+            }
             "getBlockTemplate" => {
+                // Expect params: [payAddress, extraData?] or { "payAddress": "...", "extraData": "..." }
                 // Return full JSON-serializable BlockTemplate from rpc_core::model
-                // Use a default mining address if none provided
-                let template = coordinator.get_block_template("1A1z7agoat3FwzZsQwtfTHtVtWWbnSFAZa".to_string(), None).await
+                // Without one, pay the node's wallet or else a default address
+                let params = rpc_req.params.unwrap_or(serde_json::Value::Null);
+                let pay_address = params.get(0)
+                    .or_else(|| params.get("payAddress"))
+                    .and_then(|v| v.as_str())
+                    .filter(|address| !address.is_empty())
+                    .map(str::to_string)
+                    .or_else(|| coordinator.mining_address())
+                    .unwrap_or_else(|| DEFAULT_PAY_ADDRESS.to_string());
+                let extra_data = params.get(1)
+                    .or_else(|| params.get("extraData"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+                let template = coordinator.get_block_template(pay_address, extra_data).await
                     .map_err(|e| format!("getBlockTemplate error: {:?}", e))?;
                 serde_json::to_value(&template).map_err(|e| format!("Serialization error: {}", e))?
             }
@@ -197,6 +718,108 @@ impl WrpcServer {
 
                 serde_json::json!(hash.to_string())
             }
+            "submitBlockWithCoinbase" => {
+                // Expect params: { "templateId": "...", "nonce": n, "extranonce": "hex", "coinbaseOverrides"?: {...} }
+                let params = rpc_req.params.as_ref().ok_or("Missing params")?;
+                let id_str = params.get("templateId").and_then(|v| v.as_str()).ok_or("Missing templateId parameter")?;
+                let bytes = hex::decode(id_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+                let nonce = params.get("nonce").and_then(|v| v.as_u64()).ok_or("Missing nonce parameter")?;
+                let extranonce = params.get("extranonce").and_then(|v| v.as_str()).unwrap_or_default();
+                let extranonce = hex::decode(extranonce).map_err(|e| format!("Invalid extranonce hex: {}", e))?;
+                let coinbase_overrides = params.get("coinbaseOverrides")
+                    .filter(|v| !v.is_null())
+                    .map(|v| serde_json::from_value(v.clone()))
+                    .transpose()
+                    .map_err(|e| format!("Invalid coinbaseOverrides: {}", e))?;
+
+                let hash = coordinator.submit_block_with_coinbase(Hash::from(array), nonce, extranonce, coinbase_overrides).await
+                    .map_err(|e| format!("submitBlockWithCoinbase error: {:?}", e))?;
+                serde_json::json!(hash.to_string())
+            }
+            "sendRawTransaction" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [txHex, allowHighFees?]
+                let tx_hex = params.get(0).and_then(|v| v.as_str()).ok_or("Missing transaction parameter")?;
+                let allow_high_fees = params.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let hash = coordinator.send_raw_transaction(tx_hex.to_string(), allow_high_fees).await
+                    .map_err(|e| format!("sendRawTransaction error: {:?}", e))?;
+                serde_json::json!(hash.to_string())
+            }
+            "getTransactionHex" => {
+                // Expect params: [id]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let id_str = params.as_array().and_then(|arr| arr.first()).and_then(|v| v.as_str()).ok_or("Missing id parameter")?;
+                let bytes = hex::decode(id_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+
+                let tx_hex = coordinator.get_transaction_hex(Hash::from(array)).await
+                    .map_err(|e| format!("getTransactionHex error: {:?}", e))?;
+                serde_json::json!(tx_hex)
+            }
+            "decodeTransaction" => {
+                // Expect params: [txHex]
+                let params = rpc_req.params.ok_or("Missing params")?;
+                let tx_hex = params.get(0).and_then(|v| v.as_str()).ok_or("Missing txHex parameter")?;
+
+                let tx = coordinator.decode_transaction(tx_hex.to_string()).await
+                    .map_err(|e| format!("decodeTransaction error: {:?}", e))?;
+                serde_json::to_value(&tx).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getBalances" => {
+                let balances = coordinator.get_balances().await
+                    .map_err(|e| format!("getBalances error: {:?}", e))?;
+                serde_json::to_value(&balances).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getNewAddress" => {
+                let address = coordinator.get_new_address().await
+                    .map_err(|e| format!("getNewAddress error: {:?}", e))?;
+                serde_json::json!(address)
+            }
+            "sendToAddress" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address, amount]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+                let amount = params.get(1).and_then(|v| v.as_u64()).ok_or("Missing amount parameter")?;
+
+                let hash = coordinator.send_to_address(address.to_string(), amount).await
+                    .map_err(|e| format!("sendToAddress error: {:?}", e))?;
+                serde_json::json!(hash.to_string())
+            }
+            "getBalanceByAddress" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+
+                let balance = coordinator.get_balance_by_address(address.to_string()).await
+                    .map_err(|e| format!("getBalanceByAddress error: {:?}", e))?;
+                serde_json::to_value(&balance).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getUtxosByAddress" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [address]
+                let address = params.get(0).and_then(|v| v.as_str()).ok_or("Missing address parameter")?;
+
+                let utxos = coordinator.get_utxos_by_address(address.to_string()).await
+                    .map_err(|e| format!("getUtxosByAddress error: {:?}", e))?;
+                serde_json::to_value(&utxos).map_err(|e| format!("Serialization error: {}", e))?
+            }
+            "getVirtualUtxoEntry" => {
+                let params = rpc_req.params.ok_or("Missing params")?;
+                // Expect params: [transactionId, index]
+                let id_str = params.get(0).and_then(|v| v.as_str()).ok_or("Missing transaction id parameter")?;
+                let index = params.get(1).and_then(|v| v.as_u64()).ok_or("Missing index parameter")?;
+                let index = u32::try_from(index).map_err(|_| "Invalid index parameter".to_string())?;
+
+                let bytes = hex::decode(id_str).map_err(|e| format!("Invalid hex: {}", e))?;
+                let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
+                let outpoint = consensus_core::tx::TransactionOutpoint::new(Hash::from(array), index);
+
+                let entry = coordinator.get_virtual_utxo_entry(outpoint).await
+                    .map_err(|e| format!("getVirtualUtxoEntry error: {:?}", e))?;
+                serde_json::to_value(&entry).map_err(|e| format!("Serialization error: {}", e))?
+            }
             "getMiningInfo" => {
                 let info = coordinator.get_mining_info().await
                     .map_err(|e| format!("getMiningInfo error: {:?}", e))?;
@@ -204,9 +827,11 @@ impl WrpcServer {
             }
             "getTransaction" => {
                 let params = rpc_req.params.ok_or("Missing params")?;
-                let hash_str = if let serde_json::Value::Array(arr) = &params {
+                // Expect params: [hash, verbose?]
+                let (hash_str, verbose) = if let serde_json::Value::Array(arr) = &params {
                     if arr.len() > 0 {
-                        arr[0].as_str().ok_or("Invalid hash parameter")?
+                        let verbose = arr.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
+                        (arr[0].as_str().ok_or("Invalid hash parameter")?, verbose)
                     } else {
                         return Err("Missing hash parameter".to_string());
                     }
@@ -218,9 +843,15 @@ impl WrpcServer {
                 let array: [u8; 32] = bytes.try_into().map_err(|_| "Invalid hash length".to_string())?;
                 let hash = Hash::from(array);
 
-                let tx = coordinator.get_transaction(hash).await
-                    .map_err(|e| format!("getTransaction error: {:?}", e))?;
-                serde_json::to_value(&tx).map_err(|e| format!("Serialization error: {}", e))?
+                if verbose {
+                    let tx = coordinator.get_transaction_verbose(hash).await
+                        .map_err(|e| format!("getTransaction error: {:?}", e))?;
+                    serde_json::to_value(&tx).map_err(|e| format!("Serialization error: {}", e))?
+                } else {
+                    let tx = coordinator.get_transaction(hash).await
+                        .map_err(|e| format!("getTransaction error: {:?}", e))?;
+                    serde_json::to_value(&tx).map_err(|e| format!("Serialization error: {}", e))?
+                }
             }
             "getRecentBlocks" => {
                 let params = rpc_req.params.ok_or("Missing params")?;
@@ -236,6 +867,7 @@ impl WrpcServer {
 
                 let blocks = coordinator.get_recent_blocks(count).await
                     .map_err(|e| format!("getRecentBlocks error: {:?}", e))?;
+                let blocks: Vec<RpcBlock> = blocks.iter().map(RpcBlock::from).collect();
                 serde_json::to_value(&blocks).map_err(|e| format!("Serialization error: {}", e))?
             }
             "getDagTips" => {
@@ -277,7 +909,7 @@ impl WrpcServer {
 
                 let block = coordinator.get_block_by_height(height).await
                     .map_err(|e| format!("getBlockByHeight error: {:?}", e))?;
-                serde_json::to_value(&block).map_err(|e| format!("Serialization error: {}", e))?
+                serde_json::to_value(RpcBlock::from(&block)).map_err(|e| format!("Serialization error: {}", e))?
             }
             _ => {
                 return Err(format!("Unknown method: {}", rpc_req.method));
@@ -295,3 +927,68 @@ impl WrpcServer {
             .map_err(|e| format!("Serialization error: {}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::connect_async;
+
+    /// Serve the first client of a local port with `handler`, returning its URL
+    /// and how its connection ended
+    async fn serve<H, F>(limits: ConnectionLimits, handler: H) -> (String, tokio::task::JoinHandle<ConnectionEnd>)
+    where
+        H: Fn(String, Arc<Outbox>) -> F + Send + 'static,
+        F: Future<Output = String> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let connection = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_connection(accept_async(stream).await.unwrap(), limits, handler).await
+        });
+        (url, connection)
+    }
+
+    /// Echo requests, taking a while to answer "slow"
+    async fn echo(request: String) -> String {
+        if request == "slow" {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        }
+        request
+    }
+
+    async fn responses(limits: ConnectionLimits) -> Vec<String> {
+        let (url, _connection) = serve(limits, |request, _| echo(request)).await;
+        let (mut client, _) = connect_async(&url).await.unwrap();
+        client.send(Message::Text("slow".to_string())).await.unwrap();
+        client.send(Message::Text("fast".to_string())).await.unwrap();
+        let mut responses = Vec::new();
+        while responses.len() < 2 {
+            if let Message::Text(text) = client.next().await.unwrap().unwrap() {
+                responses.push(text);
+            }
+        }
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_requests_complete_out_of_order() {
+        assert_eq!(responses(ConnectionLimits::default()).await, ["fast", "slow"]);
+        // One request at a time answers in order
+        let serial = ConnectionLimits { max_concurrent_requests: 1, ..ConnectionLimits::default() };
+        assert_eq!(responses(serial).await, ["slow", "fast"]);
+    }
+
+    #[tokio::test]
+    async fn test_client_not_reading_is_dropped() {
+        let limits = ConnectionLimits { max_concurrent_requests: 4, max_queued_messages: 2 };
+        let (url, connection) = serve(limits, |_, _| async { "x".repeat(4 << 20) }).await;
+        let (mut client, _) = connect_async(&url).await.unwrap();
+        // Far more than the socket buffers hold, and nothing is read
+        for _ in 0..32 {
+            client.send(Message::Text("big".to_string())).await.unwrap();
+        }
+        let end = tokio::time::timeout(Duration::from_secs(10), connection).await.unwrap().unwrap();
+        assert_eq!(end, ConnectionEnd::TooSlow);
+    }
+}
@@ -1,4 +1,4 @@
-use consensus_core::tx::ScriptPublicKey;
+use consensus_core::tx::{script_class, ScriptPublicKey};
 use ripemd::Ripemd160;
 use sha2::{Sha256, Digest};
 use crate::keys::Keys;
@@ -47,66 +47,17 @@ impl Address {
 
     /// Validate address format
     pub fn validate(address: &str) -> bool {
-        // Basic validation - check if valid base58 and correct length
-        if let Ok(decoded) = bs58::decode(address).into_vec() {
-            decoded.len() >= 21 // version + payload + checksum
-        } else {
-            false
-        }
+        script_class::pay_to_address_script(address).is_ok()
     }
 
     /// Get script public key for address
     pub fn to_script_pub_key(address: &str) -> Result<ScriptPublicKey, String> {
-        if !Self::validate(address) {
-            return Err("Invalid address format".to_string());
-        }
-
-        let decoded = bs58::decode(address).into_vec()
-            .map_err(|e| format!("Base58 decode error: {}", e))?;
-
-        if decoded.len() < 21 {
-            return Err("Address too short".to_string());
-        }
-
-        // Extract payload (without version and checksum)
-        let payload = &decoded[1..decoded.len()-4];
-
-        // Create P2PKH script
-        let mut script = vec![0x76, 0xa9, 0x14]; // OP_DUP OP_HASH160 PUSH(20)
-        script.extend_from_slice(payload);
-        script.extend_from_slice(&[0x88, 0xac]); // OP_EQUALVERIFY OP_CHECKSIG
-
-        Ok(ScriptPublicKey::from_vec(0, script))
+        script_class::pay_to_address_script(address).map_err(|e| e.to_string())
     }
 
     /// Get address from script public key
     pub fn from_script_pub_key(script: &ScriptPublicKey) -> Result<String, String> {
-        let script_bytes = script.script();
-        // Check for P2PKH script structure
-        if script_bytes.len() == 25 &&
-           script_bytes[0] == 0x76 && // OP_DUP
-           script_bytes[1] == 0xa9 && // OP_HASH160
-           script_bytes[2] == 0x14 && // PUSH(20)
-           script_bytes[23] == 0x88 && // OP_EQUALVERIFY
-           script_bytes[24] == 0xac { // OP_CHECKSIG
-
-            let pubkey_hash = &script_bytes[3..23];
-
-            // Add version byte (0x00 for mainnet)
-            let mut versioned_payload = vec![0x00];
-            versioned_payload.extend_from_slice(pubkey_hash);
-
-            // Double SHA256 for checksum
-            let checksum = Sha256::digest(&Sha256::digest(&versioned_payload));
-
-            // Add first 4 bytes of checksum
-            versioned_payload.extend_from_slice(&checksum[0..4]);
-
-            // Base58 encode
-            Ok(bs58::encode(&versioned_payload).into_string())
-        } else {
-            Err("Not a standard P2PKH script".to_string())
-        }
+        script_class::extract_address(script).ok_or_else(|| "Not a standard script".to_string())
     }
 }
 
@@ -148,5 +99,14 @@ mod tests {
         assert_eq!(script.script()[0], 0x76); // OP_DUP
         assert_eq!(script.script()[1], 0xa9); // OP_HASH160
         assert_eq!(script.script()[2], 0x14); // PUSH(20)
+        assert_eq!(Address::from_script_pub_key(&script).unwrap(), addr);
+    }
+
+    #[test]
+    fn test_non_standard_script_has_no_address() {
+        let script = ScriptPublicKey::from_vec(0, vec![0xac]);
+        assert!(Address::from_script_pub_key(&script).is_err());
+        // A corrupted checksum is rejected
+        assert!(!Address::validate("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"));
     }
 }
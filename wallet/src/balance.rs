@@ -0,0 +1,170 @@
+//! Balance accounting across confirmed and unconfirmed funds
+//!
+//! A sent transaction spends confirmed outputs that the node keeps reporting as
+//! unspent until it is accepted, while its change only appears once it is.
+//! Counting either side naively double-counts or drops the change in that
+//! window. [`Balance::compute`] settles both against the wallet's unconfirmed
+//! transactions: their inputs stop counting as available and their outputs to
+//! the wallet count as pending, until the outputs show up as confirmed.
+
+use consensus_core::tx::{ScriptPublicKey, Transaction, TransactionOutpoint, TransactionOutput};
+use consensus_core::Hash;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    /// Mature confirmed outputs not spent by an unconfirmed transaction
+    pub available: u64,
+    /// Confirmed coinbase outputs that haven't reached maturity yet
+    pub immature: u64,
+    /// Unconfirmed outputs paid to the wallet by others
+    pub pending_incoming: u64,
+    /// Unconfirmed change of the wallet's own transactions
+    pub pending_outgoing_change: u64,
+}
+
+/// A confirmed unspent output of the wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmedOutput {
+    pub outpoint: TransactionOutpoint,
+    pub amount: u64,
+    pub mature: bool,
+}
+
+impl Balance {
+    pub fn total(&self) -> u64 {
+        self.available + self.immature + self.pending_incoming + self.pending_outgoing_change
+    }
+
+    /// Balance of the `confirmed` outputs given the unconfirmed transactions in
+    /// `pending`: mempool entries and the wallet's own recent sends, in any order
+    /// and possibly overlapping. `is_ours` tells the wallet's scripts apart.
+    pub fn compute(confirmed: &[ConfirmedOutput], pending: &[Transaction], is_ours: impl Fn(&ScriptPublicKey) -> bool) -> Self {
+        let confirmed_outpoints: HashSet<TransactionOutpoint> = confirmed.iter().map(|output| output.outpoint).collect();
+
+        // Transactions whose outputs to us are already confirmed have been accepted
+        let mut ids = HashSet::new();
+        let pending: Vec<(Hash, &Transaction)> = pending
+            .iter()
+            .map(|tx| (tx.id(), tx))
+            .filter(|(id, tx)| ids.insert(*id) && !Self::outputs(*id, tx).any(|(outpoint, _)| confirmed_outpoints.contains(&outpoint)))
+            .collect();
+
+        // Ours: spending our confirmed outputs or the outputs of another of ours
+        let mut own: HashSet<Hash> = HashSet::new();
+        loop {
+            let before = own.len();
+            for (id, tx) in &pending {
+                let spends_ours = tx.inputs.iter().any(|input| {
+                    confirmed_outpoints.contains(&input.previous_outpoint) || own.contains(&input.previous_outpoint.transaction_id)
+                });
+                if spends_ours {
+                    own.insert(*id);
+                }
+            }
+            if own.len() == before {
+                break;
+            }
+        }
+
+        let spent: HashSet<TransactionOutpoint> =
+            pending.iter().flat_map(|(_, tx)| tx.inputs.iter().map(|input| input.previous_outpoint)).collect();
+        let mut balance = Self::default();
+        for output in confirmed.iter().filter(|output| !spent.contains(&output.outpoint)) {
+            match output.mature {
+                true => balance.available += output.amount,
+                false => balance.immature += output.amount,
+            }
+        }
+        for (id, tx) in &pending {
+            for (outpoint, output) in Self::outputs(*id, tx) {
+                if !is_ours(&output.script_public_key) || spent.contains(&outpoint) {
+                    continue;
+                }
+                match own.contains(id) {
+                    true => balance.pending_outgoing_change += output.value,
+                    false => balance.pending_incoming += output.value,
+                }
+            }
+        }
+        balance
+    }
+
+    /// Outputs of `tx`, whose id is `id`, with their outpoints
+    fn outputs(id: Hash, tx: &Transaction) -> impl Iterator<Item = (TransactionOutpoint, &TransactionOutput)> + '_ {
+        tx.outputs.iter().enumerate().map(move |(index, output)| (TransactionOutpoint::new(id, index as u32), output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use consensus_core::tx::TransactionInput;
+
+    fn script(n: u8) -> ScriptPublicKey {
+        ScriptPublicKey::from_vec(0, vec![n; 34])
+    }
+
+    /// Ours are scripts 1 and 2
+    fn is_ours(script_public_key: &ScriptPublicKey) -> bool {
+        [script(1), script(2)].contains(script_public_key)
+    }
+
+    fn tx(inputs: &[TransactionOutpoint], outputs: &[(u64, u8)]) -> Transaction {
+        Transaction::new(
+            0,
+            inputs.iter().map(|outpoint| TransactionInput::new(*outpoint, vec![], 0, 1)).collect(),
+            outputs.iter().map(|(value, n)| TransactionOutput::new(*value, script(*n))).collect(),
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            vec![],
+        )
+    }
+
+    fn confirmed(outpoint: TransactionOutpoint, amount: u64) -> ConfirmedOutput {
+        ConfirmedOutput { outpoint, amount, mature: true }
+    }
+
+    #[test]
+    fn test_change_between_broadcast_and_confirmation() {
+        let funding = tx(&[], &[(1_000, 1)]);
+        let coin = TransactionOutpoint::new(funding.id(), 0);
+        // 300 to someone else, 690 back to our change script
+        let send = tx(&[coin], &[(300, 9), (690, 2)]);
+        let change = TransactionOutpoint::new(send.id(), 1);
+
+        // Before the send
+        let before = Balance::compute(&[confirmed(coin, 1_000)], &[], is_ours);
+        assert_eq!(before, Balance { available: 1_000, ..Default::default() });
+
+        // Broadcast: the node still reports the spent coin, the change is unconfirmed.
+        // Known both from our own sends and from the mempool.
+        let window = Balance::compute(&[confirmed(coin, 1_000)], &[send.clone(), send.clone()], is_ours);
+        assert_eq!(window, Balance { pending_outgoing_change: 690, ..Default::default() });
+
+        // Accepted, but our own record of the send hasn't been cleared yet
+        let accepted = Balance::compute(&[confirmed(change, 690)], &[send.clone()], is_ours);
+        assert_eq!(accepted, Balance { available: 690, ..Default::default() });
+        assert_eq!(Balance::compute(&[confirmed(change, 690)], &[], is_ours), accepted);
+    }
+
+    #[test]
+    fn test_incoming_and_chained_sends() {
+        let funding = tx(&[], &[(1_000, 1)]);
+        let coin = TransactionOutpoint::new(funding.id(), 0);
+        let immature = ConfirmedOutput { outpoint: TransactionOutpoint::new(funding.id(), 1), amount: 50, mature: false };
+
+        // Someone pays us 400 while we send twice, the second spending the first's change
+        let foreign = TransactionOutpoint::new(Hash::from_u64_word(7), 0);
+        let incoming = tx(&[foreign], &[(400, 1), (100, 9)]);
+        let first = tx(&[coin], &[(300, 9), (690, 2)]);
+        let second = tx(&[TransactionOutpoint::new(first.id(), 1)], &[(200, 9), (480, 2)]);
+
+        let balance = Balance::compute(&[confirmed(coin, 1_000), immature], &[second, incoming, first], is_ours);
+        assert_eq!(balance, Balance { available: 0, immature: 50, pending_incoming: 400, pending_outgoing_change: 480 });
+        assert_eq!(balance.total(), 930);
+    }
+}
@@ -5,7 +5,11 @@ use wallet::{Keys, Address, Keystore, TxBuilder, Signer};
 use consensus::{ConsensusStorage, UtxoSet, BlockStore};
 use consensus_core::tx::{TransactionOutpoint, UtxoEntry};
 use std::collections::HashMap;
-use wallet::keystore::{WalletData, AddressEntry};
+use wallet::keystore::{WalletData, AddressEntry, KeystoreLock};
+use wallet::discovery::{self, DEFAULT_GAP_LIMIT};
+use wallet::node_client::NodeClient;
+use wallet::service::{auth_token_path, ServiceConfig, WalletServer, WalletService};
+use std::time::Duration;
 use rand::RngCore;
 use database::Database;
 use database::stores::{BlockStore as DbBlockStore, HeaderStore as DbHeaderStore, UtxoStore as DbUtxoStore};
@@ -58,6 +62,12 @@ enum Commands {
         seed_hex: String,
         #[arg(short, long)]
         password: String,
+        /// Node RPC (host:port or ws:// URL) to discover the addresses already in use
+        #[arg(long)]
+        rpc_url: Option<String>,
+        /// Unused addresses in a row that end discovery on a branch
+        #[arg(long, default_value_t = DEFAULT_GAP_LIMIT)]
+        gap_limit: u32,
     },
 
     /// Create and sign a transaction
@@ -82,6 +92,25 @@ enum Commands {
         #[arg(short, long)]
         tx_json: String,
     },
+
+    /// Keep the wallet synced with a node and serve it as JSON-RPC over HTTP
+    Serve {
+        /// Address the JSON-RPC server listens on
+        #[arg(long, default_value = "127.0.0.1:16120")]
+        listen: String,
+        /// RPC address of the node (host:port)
+        #[arg(long, default_value = "127.0.0.1:16110")]
+        node: String,
+        /// Unlock at startup instead of waiting for an unlock request
+        #[arg(short, long)]
+        password: Option<String>,
+        /// Seconds without requests after which the keys are locked again
+        #[arg(long, default_value = "300")]
+        auto_lock_secs: u64,
+        /// Run in light mode from this trusted header (hex of its canonical encoding)
+        #[arg(long)]
+        light_anchor: Option<String>,
+    },
 }
 
 fn main() -> Result<(), String> {
@@ -89,6 +118,7 @@ fn main() -> Result<(), String> {
 
     match cli.cmd {
         Commands::Init { password } => {
+            let _lock = KeystoreLock::acquire(&cli.keystore)?;
             // Generate a new random master seed
             let mut seed = [0u8; 64];
             rand::rngs::OsRng.fill_bytes(&mut seed);
@@ -118,15 +148,14 @@ fn main() -> Result<(), String> {
         }
 
         Commands::NewAddress { password } => {
+            let _lock = KeystoreLock::acquire(&cli.keystore)?;
             // Load keystore
             let mut ks = Keystore::load(&cli.keystore).map_err(|e| format!("Failed to load keystore: {}", e))?;
             
-            // Get current address count to determine next index
-            let current_addresses = ks.list_addresses(&password).map_err(|e| format!("Failed to list addresses: {}", e))?;
-            let next_index = current_addresses.len() as u32;
-
             // Decrypt to get seed
             let data = ks.decrypt(&password).map_err(|e| format!("Failed to decrypt: {}", e))?;
+            // Continue after the stored and discovered receive addresses
+            let next_index = data.next_index(0);
             if data.master_seed.len() != 64 {
                 return Err("Master seed in keystore is not 64 bytes".to_string());
             }
@@ -167,7 +196,8 @@ fn main() -> Result<(), String> {
             Ok(())
         }
 
-        Commands::ImportSeed { seed_hex, password } => {
+        Commands::ImportSeed { seed_hex, password, rpc_url, gap_limit } => {
+            let _lock = KeystoreLock::acquire(&cli.keystore)?;
             let bytes = hex::decode(&seed_hex).map_err(|e| format!("Invalid hex seed: {}", e))?;
             if bytes.len() != 64 {
                 return Err("Seed must be exactly 64 bytes (128 hex chars)".to_string());
@@ -176,7 +206,17 @@ fn main() -> Result<(), String> {
             seed.copy_from_slice(&bytes);
 
             let mut ks = Keystore::new();
-            let data = Keystore::create_wallet_data(seed);
+            let mut data = Keystore::create_wallet_data(seed);
+            if let Some(rpc_url) = rpc_url {
+                let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start runtime: {}", e))?;
+                let found = runtime
+                    .block_on(discovery::discover(&Keys::from_seed(seed), &NodeClient::new(&rpc_url), gap_limit))
+                    .map_err(|e| format!("Address discovery failed: {}", e))?;
+                found.apply(&mut data);
+                println!("Receive addresses in use: {}", found.receive.count);
+                println!("Change addresses in use: {}", found.change.count);
+                println!("Balance found: {} sompi", found.balance());
+            }
             ks.encrypt(&password, &data).map_err(|e| format!("Encrypt failed: {}", e))?;
             ks.save(&cli.keystore).map_err(|e| format!("Save failed: {}", e))?;
             println!("Imported seed and saved keystore to {}", cli.keystore.display());
@@ -276,16 +316,17 @@ fn main() -> Result<(), String> {
                 secret_keys.push(sk.clone());
             }
 
+            // Each signature commits to the output its input spends
+            let entries: Vec<_> = unsigned_tx.inputs.iter().map(|input| utxo_map[&input.previous_outpoint].clone()).collect();
+
             // Sign the transaction
-            let signed_tx = signer.sign_transaction(unsigned_tx, &secret_keys)
+            let signed_tx = signer.sign_transaction(unsigned_tx, &secret_keys, &entries)
                 .map_err(|e| format!("Failed to sign transaction: {}", e))?;
             
             println!("Transaction signed successfully.");
 
-            // Encode the signed transaction to hex
-            let serialized_tx = bincode::serialize(&signed_tx)
-                .map_err(|e| format!("Failed to serialize transaction: {}", e))?;
-            let hex_tx = hex::encode(serialized_tx);
+            // Encode the signed transaction to hex, as sendRawTransaction takes it
+            let hex_tx = hex::encode(consensus_core::encoding::encode_transaction(&signed_tx));
 
             println!("--- Signed Transaction (Hex) ---");
             println!("{}", hex_tx);
@@ -295,12 +336,38 @@ fn main() -> Result<(), String> {
         }
 
         Commands::EncodeTransaction { tx_json } => {
-            // This would typically take a JSON transaction and encode it to hex bincode
+            // This would typically take a JSON transaction and encode it to canonical hex
             println!("Transaction JSON: {}", tx_json);
-            println!("Encoding transaction to hex (bincode serialization)");
+            println!("Encoding transaction to hex (canonical encoding)");
             println!("Note: Transaction encoding requires a serialized Transaction struct from consensus_core");
             Ok(())
         }
+
+        Commands::Serve { listen, node, password, auto_lock_secs, light_anchor } => {
+            tracing_subscriber::fmt().init();
+            let mut config = ServiceConfig::new(cli.keystore.clone(), node).with_auto_lock(Duration::from_secs(auto_lock_secs));
+            if let Some(anchor) = light_anchor {
+                let bytes = hex::decode(&anchor).map_err(|e| format!("Invalid light anchor: {}", e))?;
+                let header = consensus_core::encoding::decode_header(&bytes).map_err(|e| format!("Invalid light anchor: {:?}", e))?;
+                config = config.with_light_mode(header);
+            }
+            let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start runtime: {}", e))?;
+            runtime.block_on(async {
+                let service = WalletService::new(config)?;
+                if let Some(password) = password {
+                    service.unlock(&password)?;
+                }
+                let server = WalletServer::bind(&listen, service.clone()).await?;
+                println!("Serving {} on {}", cli.keystore.display(), server.local_addr()?);
+                println!("Clients authenticate with the token in {}", auth_token_path(&cli.keystore).display());
+                let sync = service.spawn_sync();
+                let server = server.spawn();
+                tokio::signal::ctrl_c().await.map_err(|e| format!("Failed to wait for Ctrl-C: {}", e))?;
+                server.abort();
+                sync.abort();
+                Ok::<(), String>(())
+            })
+        }
     }
 }
 
@@ -0,0 +1,203 @@
+//! Address discovery for restored wallets
+//!
+//! A seed tells how to derive addresses, not how many were handed out. Discovery
+//! derives the receive and change branches in batches and asks the node which
+//! addresses hold outputs, until `gap_limit` addresses in a row are unused
+//! (BIP44's gap limit). The node indexes unspent outputs only, so an address
+//! whose outputs were all spent counts as unused.
+
+use crate::keystore::{AddressEntry, WalletData};
+use crate::node_client::NodeClient;
+use crate::service::{derive_address, CHANGE_PATH, RECEIVE_PATH};
+use crate::Keys;
+use std::future::Future;
+
+/// Unused addresses in a row after which a branch is considered exhausted
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Addresses found on one derivation branch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BranchDiscovery {
+    /// One past the highest used index, zero if none is used
+    pub count: u32,
+    /// Indexes of the used addresses
+    pub used: Vec<u32>,
+    /// Sum of the outputs held by the branch
+    pub balance: u64,
+    /// Address, path and public key of indexes `0..count`
+    addresses: Vec<(String, Vec<u32>, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Discovery {
+    pub receive: BranchDiscovery,
+    pub change: BranchDiscovery,
+}
+
+impl Discovery {
+    pub fn balance(&self) -> u64 {
+        self.receive.balance + self.change.balance
+    }
+
+    /// Addresses of both branches up to `gap_limit` past their last used one,
+    /// where payments to the wallet are expected to arrive
+    pub fn watched_addresses(&self, keys: &Keys, gap_limit: u32) -> Result<Vec<String>, String> {
+        let mut addresses = Vec::new();
+        for (branch, found) in [(&RECEIVE_PATH, &self.receive), (&CHANGE_PATH, &self.change)] {
+            for index in 0..found.count + gap_limit {
+                addresses.push(derive_address(keys, branch, index)?.0);
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Store the addresses of each branch up to its last used one, and the counts
+    pub fn apply(&self, data: &mut WalletData) {
+        for (branch, found) in [(RECEIVE_PATH[3], &self.receive), (CHANGE_PATH[3], &self.change)] {
+            for (address, path, public_key) in &found.addresses {
+                data.addresses.entry(address.clone()).or_insert_with(|| AddressEntry {
+                    path: path.clone(),
+                    public_key: public_key.clone(),
+                    label: None,
+                });
+            }
+            let count = data.address_counts.entry(branch).or_default();
+            *count = (*count).max(found.count);
+        }
+    }
+}
+
+/// Find the addresses of `keys` that hold outputs on the node
+pub async fn discover(keys: &Keys, node: &NodeClient, gap_limit: u32) -> Result<Discovery, String> {
+    discover_with(keys, gap_limit, |address| async move {
+        let utxos = node.get_utxos_by_address(&address).await?;
+        Ok((!utxos.is_empty()).then(|| utxos.iter().map(|utxo| utxo.utxo_entry.amount).sum()))
+    })
+    .await
+}
+
+/// Discovery against any lookup of the amount an address holds, `None` if it holds no outputs
+pub async fn discover_with<F, Fut>(keys: &Keys, gap_limit: u32, mut lookup: F) -> Result<Discovery, String>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Option<u64>, String>>,
+{
+    Ok(Discovery {
+        receive: scan_branch(keys, &RECEIVE_PATH, gap_limit, &mut lookup).await?,
+        change: scan_branch(keys, &CHANGE_PATH, gap_limit, &mut lookup).await?,
+    })
+}
+
+async fn scan_branch<F, Fut>(keys: &Keys, branch: &[u32; 4], gap_limit: u32, lookup: &mut F) -> Result<BranchDiscovery, String>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Option<u64>, String>>,
+{
+    let gap_limit = gap_limit.max(1);
+    let mut found = BranchDiscovery::default();
+    let mut derived = Vec::new();
+    // Each batch reaches `gap_limit` past the last used address; a batch without
+    // a used address ends the branch
+    loop {
+        let start = derived.len() as u32;
+        let end = found.count.saturating_add(gap_limit);
+        if start >= end {
+            break;
+        }
+        for index in start..end {
+            let (address, path, public_key) = derive_address(keys, branch, index)?;
+            if let Some(amount) = lookup(address.clone()).await? {
+                found.used.push(index);
+                found.balance += amount;
+                found.count = index + 1;
+            }
+            derived.push((address, path, public_key));
+        }
+    }
+    derived.truncate(found.count as usize);
+    found.addresses = derived;
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::Keystore;
+    use std::collections::HashMap;
+
+    const SEED: [u8; 64] = [5u8; 64];
+
+    fn address(branch: &[u32; 4], index: u32) -> String {
+        derive_address(&Keys::from_seed(SEED), branch, index).unwrap().0
+    }
+
+    /// Discover against a fixed set of funded addresses, counting the lookups
+    async fn discover_funded(funded: &HashMap<String, u64>, gap_limit: u32) -> (Discovery, usize) {
+        let mut lookups = 0;
+        let discovery = discover_with(&Keys::from_seed(SEED), gap_limit, |address| {
+            lookups += 1;
+            let amount = funded.get(&address).copied();
+            async move { Ok(amount) }
+        })
+        .await
+        .unwrap();
+        (discovery, lookups)
+    }
+
+    #[tokio::test]
+    async fn test_discovery_stops_at_the_gap() {
+        let funded = HashMap::from([
+            (address(&RECEIVE_PATH, 5), 1_000),
+            (address(&RECEIVE_PATH, 37), 2_000),
+            (address(&CHANGE_PATH, 2), 300),
+        ]);
+
+        // 31 unused addresses separate index 5 from 37
+        let (discovery, lookups) = discover_funded(&funded, DEFAULT_GAP_LIMIT).await;
+        assert_eq!(discovery.receive.used, vec![5]);
+        assert_eq!(discovery.receive.count, 6);
+        assert_eq!(discovery.change.used, vec![2]);
+        assert_eq!(discovery.change.count, 3);
+        assert_eq!(discovery.balance(), 1_300);
+        assert_eq!(lookups, 26 + 23);
+
+        let (discovery, _) = discover_funded(&funded, 32).await;
+        assert_eq!(discovery.receive.used, vec![5, 37]);
+        assert_eq!(discovery.receive.count, 38);
+        assert_eq!(discovery.balance(), 3_300);
+
+        let (discovery, lookups) = discover_funded(&HashMap::new(), DEFAULT_GAP_LIMIT).await;
+        assert_eq!(discovery, Discovery::default());
+        assert_eq!(lookups, 40);
+    }
+
+    #[tokio::test]
+    async fn test_watched_addresses_extend_past_the_last_used() {
+        let funded = HashMap::from([(address(&CHANGE_PATH, 2), 300)]);
+        let (discovery, _) = discover_funded(&funded, DEFAULT_GAP_LIMIT).await;
+
+        let watched = discovery.watched_addresses(&Keys::from_seed(SEED), 4).unwrap();
+        assert_eq!(watched.len(), 4 + 7);
+        assert!(watched.contains(&address(&RECEIVE_PATH, 3)));
+        assert!(watched.contains(&address(&CHANGE_PATH, 6)));
+        assert!(!watched.contains(&address(&CHANGE_PATH, 7)));
+    }
+
+    #[tokio::test]
+    async fn test_apply_continues_after_discovered_addresses() {
+        let funded = HashMap::from([(address(&RECEIVE_PATH, 5), 1_000), (address(&RECEIVE_PATH, 37), 2_000)]);
+        let (discovery, _) = discover_funded(&funded, 32).await;
+
+        let mut data = Keystore::create_wallet_data(SEED);
+        discovery.apply(&mut data);
+        assert_eq!(data.addresses.len(), 38);
+        assert_eq!(data.addresses[&address(&RECEIVE_PATH, 37)].path, [&RECEIVE_PATH[..], &[37]].concat());
+        assert_eq!(data.next_index(RECEIVE_PATH[3]), 38);
+        assert_eq!(data.next_index(CHANGE_PATH[3]), 0);
+
+        // A later discovery with a smaller gap doesn't lower the counts
+        let (discovery, _) = discover_funded(&funded, DEFAULT_GAP_LIMIT).await;
+        discovery.apply(&mut data);
+        assert_eq!(data.address_counts[&RECEIVE_PATH[3]], 38);
+    }
+}
@@ -0,0 +1,156 @@
+//! Wallet held by a node for its own funds
+//!
+//! [`HotWallet`] keeps a keystore unlocked for as long as the node runs, so the
+//! node can pay the coinbases it mines to itself and spend them. Unlike
+//! [`crate::service::WalletService`] it has no node client: the node supplies
+//! the wallet's UTXOs and relays the transactions it signs.
+
+use crate::keystore::{Keystore, KeystoreLock};
+use crate::service::{derive_address, sign_inputs, RECEIVE_PATH};
+use crate::{Address, Keys, TxBuilder};
+use consensus_core::tx::{Transaction, TransactionOutpoint, UtxoEntry};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub struct HotWallet {
+    keystore: PathBuf,
+    password: String,
+    keys: Keys,
+    /// Derivation paths by address
+    addresses: Mutex<BTreeMap<String, Vec<u32>>>,
+    _lock: KeystoreLock,
+}
+
+impl HotWallet {
+    /// Claim and decrypt the keystore, deriving the first receive address if it has none
+    pub fn open(keystore: impl Into<PathBuf>, password: &str) -> Result<Self, String> {
+        let path = keystore.into();
+        let lock = KeystoreLock::acquire(&path)?;
+        let mut keystore = Keystore::load(&path)?;
+        let data = keystore.decrypt(password)?;
+        let seed: [u8; 64] = data.master_seed.as_slice().try_into().map_err(|_| "Master seed in keystore is not 64 bytes")?;
+        let keys = Keys::from_seed(seed);
+
+        let mut addresses: BTreeMap<String, Vec<u32>> = data.addresses.into_iter().map(|(address, entry)| (address, entry.path)).collect();
+        if addresses.is_empty() {
+            let (address, derivation_path, public_key) = derive_address(&keys, &RECEIVE_PATH, 0)?;
+            keystore.add_address_to_keystore(password, address.clone(), derivation_path.clone(), public_key)?;
+            keystore.save(&path)?;
+            addresses.insert(address, derivation_path);
+        }
+
+        Ok(Self { keystore: path, password: password.to_string(), keys, addresses: Mutex::new(addresses), _lock: lock })
+    }
+
+    pub fn keys(&self) -> &Keys {
+        &self.keys
+    }
+
+    /// Address mined coinbases and change are paid to: the first receive address
+    pub fn mining_address(&self) -> String {
+        let addresses = self.addresses.lock().unwrap();
+        let receive = addresses.iter().filter(|(_, path)| path.len() == 5 && path[..4] == RECEIVE_PATH).min_by_key(|(_, path)| path[4]);
+        // A keystore may hold only change addresses, but never none
+        receive.or(addresses.iter().next()).map(|(address, _)| address.clone()).unwrap_or_default()
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.addresses.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Derive the next receive address and store it in the keystore
+    pub fn new_address(&self) -> Result<String, String> {
+        let mut addresses = self.addresses.lock().unwrap();
+        let mut keystore = Keystore::load(&self.keystore)?;
+        let index = keystore.decrypt(&self.password)?.next_index(RECEIVE_PATH[3]);
+        let (address, path, public_key) = derive_address(&self.keys, &RECEIVE_PATH, index)?;
+
+        keystore.add_address_to_keystore(&self.password, address.clone(), path.clone(), public_key)?;
+        keystore.save(&self.keystore)?;
+        addresses.insert(address.clone(), path);
+        Ok(address)
+    }
+
+    /// Sign a payment of `amount` to `address` spending from `utxos`, the
+    /// wallet's spendable outputs, at `feerate` but no less than the node's
+    /// `min_relay_feerate`; change goes back to the mining address
+    pub fn sign_send(
+        &self,
+        utxos: &HashMap<TransactionOutpoint, UtxoEntry>,
+        address: &str,
+        amount: u64,
+        feerate: u64,
+        min_relay_feerate: u64,
+    ) -> Result<Transaction, String> {
+        if !Address::validate(address) {
+            return Err(format!("Invalid address: {}", address));
+        }
+        let paths: HashMap<TransactionOutpoint, Vec<u32>> = {
+            let addresses = self.addresses.lock().unwrap();
+            utxos
+                .iter()
+                .filter_map(|(outpoint, entry)| {
+                    let address = Address::from_script_pub_key(&entry.script_public_key).ok()?;
+                    addresses.get(&address).map(|path| (*outpoint, path.clone()))
+                })
+                .collect()
+        };
+        // Paying less than the node relays at would never confirm
+        let feerate = feerate.max(min_relay_feerate);
+        let tx = TxBuilder::send_to_address(utxos, &self.mining_address(), address, amount, feerate)?
+            .min_relay_feerate(min_relay_feerate)
+            .build(utxos)?;
+        sign_inputs(self.keys.clone(), &paths, utxos, tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn keystore(dir: &TempDir, password: &str) -> PathBuf {
+        let path = dir.path().join("keystore.json");
+        let mut keystore = Keystore::new();
+        keystore.encrypt(password, &Keystore::create_wallet_data([7u8; 64])).unwrap();
+        keystore.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_open_derives_a_mining_address_and_persists_new_ones() {
+        let dir = TempDir::new().unwrap();
+        let path = keystore(&dir, "secret");
+        assert!(HotWallet::open(&path, "wrong").is_err());
+
+        let wallet = HotWallet::open(&path, "secret").unwrap();
+        let mining_address = wallet.mining_address();
+        assert!(Address::validate(&mining_address));
+        // The keystore is claimed while the wallet is open
+        assert!(HotWallet::open(&path, "secret").is_err());
+
+        let address = wallet.new_address().unwrap();
+        assert_ne!(address, mining_address);
+        drop(wallet);
+
+        let wallet = HotWallet::open(&path, "secret").unwrap();
+        assert_eq!(wallet.mining_address(), mining_address);
+        assert_eq!(wallet.addresses().len(), 2);
+        assert!(wallet.addresses().contains(&address));
+    }
+
+    #[test]
+    fn test_sign_send_pays_at_least_the_min_relay_feerate() {
+        let dir = TempDir::new().unwrap();
+        let wallet = HotWallet::open(&keystore(&dir, "secret"), "secret").unwrap();
+        let script = Address::to_script_pub_key(&wallet.mining_address()).unwrap();
+        let outpoint = TransactionOutpoint::new(consensus_core::Hash::from_le_u64([1, 0, 0, 0]), 0);
+        let utxos = HashMap::from([(outpoint, UtxoEntry::new(100_000, script, 0, false))]);
+
+        // One input and two outputs estimate to 228 bytes
+        let tx = wallet.sign_send(&utxos, &wallet.mining_address(), 10_000, 1, 3).unwrap();
+        let paid: u64 = tx.outputs.iter().map(|output| output.value).sum();
+        assert_eq!(100_000 - paid, 228 * 3);
+    }
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use aes_gcm::{Aes256Gcm, Nonce};
 use aes_gcm::aead::{Aead, KeyInit};
 use argon2::Argon2;
@@ -22,6 +23,24 @@ pub struct Keystore {
 pub struct WalletData {
     pub addresses: HashMap<String, AddressEntry>,
     pub master_seed: Vec<u8>,
+    /// Addresses in use by branch (the fourth path element: 0 receive, 1 change),
+    /// as found by discovery; new addresses are derived after them
+    #[serde(default)]
+    pub address_counts: BTreeMap<u32, u32>,
+}
+
+impl WalletData {
+    /// Index of the next address to derive on `branch`
+    pub fn next_index(&self, branch: u32) -> u32 {
+        let stored = self
+            .addresses
+            .values()
+            .filter(|entry| entry.path.len() == 5 && entry.path[3] == branch)
+            .map(|entry| entry.path[4] + 1)
+            .max()
+            .unwrap_or(0);
+        stored.max(self.address_counts.get(&branch).copied().unwrap_or(0))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -133,10 +152,41 @@ impl Keystore {
         WalletData {
             addresses: HashMap::new(),
             master_seed: master_seed.to_vec(),
+            address_counts: BTreeMap::new(),
         }
     }
 }
 
+/// Exclusive claim on a keystore file, held while it may be rewritten. The
+/// claim is a `<keystore>.lock` file next to it, removed when dropped.
+pub struct KeystoreLock {
+    path: PathBuf,
+}
+
+impl KeystoreLock {
+    pub fn acquire<P: AsRef<Path>>(keystore: P) -> Result<Self, String> {
+        let mut path = keystore.as_ref().as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path).map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!(
+                "Keystore {} is in use by another process (remove {} if none is running)",
+                keystore.as_ref().display(),
+                path.display()
+            ),
+            _ => format!("Failed to create {}: {}", path.display(), e),
+        })?;
+        let _ = writeln!(file, "{}", std::process::id());
+        Ok(Self { path })
+    }
+}
+
+impl Drop for KeystoreLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -206,6 +256,30 @@ mod tests {
         assert_eq!(decrypted.master_seed, master_seed.to_vec());
     }
 
+    #[test]
+    fn test_next_index() {
+        let mut data = Keystore::create_wallet_data([1u8; 64]);
+        assert_eq!(data.next_index(0), 0);
+
+        let entry = |index| AddressEntry { path: vec![44, 0, 0, 0, index], public_key: vec![], label: None };
+        data.addresses.insert("a".to_string(), entry(0));
+        data.addresses.insert("b".to_string(), entry(3));
+        assert_eq!(data.next_index(0), 4);
+        assert_eq!(data.next_index(1), 0);
+
+        // Discovered counts win over the stored addresses, per branch
+        data.address_counts.insert(1, 7);
+        data.address_counts.insert(0, 2);
+        assert_eq!(data.next_index(0), 4);
+        assert_eq!(data.next_index(1), 7);
+
+        // Keystores written before counts were recorded still load
+        let mut json: serde_json::Value = serde_json::to_value(&data).unwrap();
+        json.as_object_mut().unwrap().remove("address_counts");
+        let old: WalletData = serde_json::from_value(json).unwrap();
+        assert!(old.address_counts.is_empty());
+    }
+
     #[test]
     fn test_wrong_password() {
         let mut keystore = Keystore::new();
@@ -216,4 +290,18 @@ mod tests {
         let result = keystore.decrypt("wrong_password");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_keystore_lock() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let keystore = dir.path().join("wallet.json");
+
+        let lock = KeystoreLock::acquire(&keystore).unwrap();
+        assert!(dir.path().join("wallet.json.lock").exists());
+        assert!(KeystoreLock::acquire(&keystore).unwrap_err().contains("in use by another process"));
+
+        drop(lock);
+        assert!(!dir.path().join("wallet.json.lock").exists());
+        KeystoreLock::acquire(&keystore).unwrap();
+    }
 }
@@ -3,9 +3,16 @@ pub mod address;
 pub mod tx_builder;
 pub mod signer;
 pub mod keystore;
+pub mod discovery;
+pub mod balance;
+pub mod hot_wallet;
+pub mod node_client;
+pub mod service;
 
 pub use keys::Keys;
 pub use address::Address;
 pub use tx_builder::TxBuilder;
 pub use signer::Signer;
 pub use keystore::Keystore;
+pub use balance::Balance;
+pub use hot_wallet::HotWallet;
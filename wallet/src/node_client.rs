@@ -0,0 +1,316 @@
+//! JSON-RPC client of a node's WebSocket RPC server
+//!
+//! The node's RPC crate depends on this one, so responses are decoded into the
+//! few wallet-side types below rather than into the node's models.
+
+use consensus_core::header::Header;
+use consensus_core::tx::{Transaction, TransactionOutpoint, UtxoEntry};
+use consensus_core::Hash;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Unspent output of an address, as returned by getUtxosByAddress
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeUtxo {
+    pub address: String,
+    pub outpoint: TransactionOutpoint,
+    pub utxo_entry: UtxoEntry,
+}
+
+/// Balance of an address, as returned by getBalanceByAddress
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeBalance {
+    /// Spendable amount
+    pub balance: u64,
+    /// Coinbase outputs that haven't reached maturity yet
+    pub pending_balance: u64,
+}
+
+/// Transaction waiting in the node's mempool, as returned by getMempoolEntries
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeMempoolEntry {
+    pub fee: u64,
+    pub transaction: Transaction,
+    pub is_orphan: bool,
+}
+
+/// Relay policy and coinbase maturity of the node, as returned by getInfo
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeInfo {
+    pub server_version: String,
+    pub min_relay_feerate: u64,
+    pub dust_threshold: u64,
+    pub virtual_daa_score: u64,
+    pub coinbase_maturity: u64,
+}
+
+/// Outputs of subscribed addresses created and spent by one block, as sent in
+/// utxosChanged notifications
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeUtxosChanged {
+    pub block_hash: Hash,
+    pub added: Vec<NodeUtxo>,
+    pub removed: Vec<NodeUtxo>,
+}
+
+/// Transactions of the blocks accepted by one chain block
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeAcceptedTransactionIds {
+    pub accepting_block_hash: Hash,
+    pub accepted_transaction_ids: Vec<Hash>,
+}
+
+/// Change of the node's selected chain, as sent in virtualChainChanged notifications
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeVirtualChainChanged {
+    /// Highest first
+    pub removed_chain_block_hashes: Vec<Hash>,
+    /// Lowest first
+    pub added_chain_block_hashes: Vec<Hash>,
+    pub accepted_transaction_ids: Vec<NodeAcceptedTransactionIds>,
+    pub removed_transaction_ids: Vec<NodeAcceptedTransactionIds>,
+}
+
+/// Selected chain tip a virtualChainChanged subscription started from
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeChainTip {
+    pub hash: Hash,
+    pub blue_score: u64,
+}
+
+/// Client keeping one connection to the node at `host:port` (or a `ws://` URL), reopened after failures
+pub struct NodeClient {
+    url: String,
+    socket: Mutex<Option<Socket>>,
+}
+
+impl NodeClient {
+    pub fn new(rpc_addr: &str) -> Self {
+        let url = if rpc_addr.contains("://") { rpc_addr.to_string() } else { format!("ws://{}", rpc_addr) };
+        Self { url, socket: Mutex::new(None) }
+    }
+
+    /// Send one request and wait for its result
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let mut socket = self.socket.lock().await;
+        if socket.is_none() {
+            let (stream, _) = connect_async(&self.url).await.map_err(|e| format!("Failed to connect to {}: {}", self.url, e))?;
+            *socket = Some(stream);
+        }
+        let result = Self::exchange(socket.as_mut().unwrap(), method, params).await;
+        if result.is_err() {
+            // Start over on a fresh connection rather than read a stale reply
+            *socket = None;
+        }
+        result?
+    }
+
+    /// Watch the UTXOs of `addresses` over a connection of its own
+    pub async fn subscribe_utxos_changed(&self, addresses: &[String]) -> Result<UtxoSubscription, String> {
+        let (mut socket, _) = connect_async(&self.url).await.map_err(|e| format!("Failed to connect to {}: {}", self.url, e))?;
+        let mut notifications = VecDeque::new();
+        let params = json!({ "topic": "utxosChanged", "addresses": addresses });
+        let result = Self::exchange_buffering(&mut socket, "subscribe", params, &mut notifications).await??;
+        let id = result.get("subscriptionId").and_then(Value::as_u64).ok_or("Invalid subscribe response")?;
+        Ok(UtxoSubscription { socket, id, addresses: addresses.iter().cloned().collect(), notifications })
+    }
+
+    /// Follow the node's selected chain over a connection of its own. Given the
+    /// last tip known from an earlier subscription, the first notification
+    /// catches up from it to the tip the subscription starts from.
+    pub async fn subscribe_virtual_chain_changed(
+        &self,
+        start_hash: Option<&Hash>,
+        include_accepted_transaction_ids: bool,
+    ) -> Result<VirtualChainSubscription, String> {
+        let (mut socket, _) = connect_async(&self.url).await.map_err(|e| format!("Failed to connect to {}: {}", self.url, e))?;
+        let mut notifications = VecDeque::new();
+        let params = json!({
+            "includeAcceptedTransactionIds": include_accepted_transaction_ids,
+            "startHash": start_hash.map(Hash::to_string),
+        });
+        let result = Self::exchange_buffering(&mut socket, "notifyVirtualChainChanged", params, &mut notifications).await??;
+        let tip = serde_json::from_value(result.get("tip").cloned().unwrap_or(Value::Null))
+            .map_err(|e| format!("Invalid notifyVirtualChainChanged response: {}", e))?;
+        Ok(VirtualChainSubscription { socket, tip, notifications })
+    }
+
+    /// Outer error: the connection failed. Inner error: the node refused the request.
+    async fn exchange(socket: &mut Socket, method: &str, params: Value) -> Result<Result<Value, String>, String> {
+        Self::exchange_buffering(socket, method, params, &mut VecDeque::new()).await
+    }
+
+    /// [`Self::exchange`], keeping the notifications received before the response
+    async fn exchange_buffering(
+        socket: &mut Socket,
+        method: &str,
+        params: Value,
+        notifications: &mut VecDeque<Value>,
+    ) -> Result<Result<Value, String>, String> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        socket.send(Message::Text(request.to_string())).await.map_err(|e| format!("{} failed: {}", method, e))?;
+        loop {
+            let message = socket
+                .next()
+                .await
+                .ok_or_else(|| format!("{} failed: connection closed", method))?
+                .map_err(|e| format!("{} failed: {}", method, e))?;
+            if let Message::Text(text) = message {
+                let response: Value = serde_json::from_str(&text).map_err(|e| format!("{} failed: {}", method, e))?;
+                if response.get("method").is_some() {
+                    notifications.push_back(response);
+                    continue;
+                }
+                if let Some(error) = response.get("error") {
+                    let message = error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), str::to_string);
+                    // Without the request's id the error is about the connection, e.g. a refusal over the limit
+                    if response.get("id").map_or(true, Value::is_null) {
+                        return Err(format!("{} failed: {}", method, message));
+                    }
+                    return Ok(Err(format!("{} failed: {}", method, message)));
+                }
+                return Ok(Ok(response.get("result").cloned().unwrap_or(Value::Null)));
+            }
+        }
+    }
+
+    async fn call_as<T: serde::de::DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, String> {
+        let result = self.call(method, params).await?;
+        serde_json::from_value(result).map_err(|e| format!("Invalid {} response: {}", method, e))
+    }
+
+    pub async fn get_info(&self) -> Result<NodeInfo, String> {
+        self.call_as("getInfo", json!([])).await
+    }
+
+    /// Hashes of the DAG tips; they change whenever the node accepts a block
+    pub async fn get_tips(&self) -> Result<Vec<String>, String> {
+        let info = self.call("getBlockDagInfo", json!([])).await?;
+        serde_json::from_value(info.get("tip_hashes").cloned().unwrap_or(Value::Null))
+            .map_err(|e| format!("Invalid getBlockDagInfo response: {}", e))
+    }
+
+    /// Up to `limit` headers following `start` along the node's selected chain
+    pub async fn get_headers(&self, start: &Hash, limit: usize) -> Result<Vec<Header>, String> {
+        let encoded: Vec<String> = self.call_as("getHeaders", json!([start.to_string(), limit])).await?;
+        encoded
+            .iter()
+            .map(|header| {
+                let bytes = hex::decode(header).map_err(|e| format!("Invalid getHeaders response: {}", e))?;
+                consensus_core::encoding::decode_header(&bytes).map_err(|e| format!("Invalid getHeaders response: {:?}", e))
+            })
+            .collect()
+    }
+
+    pub async fn get_utxos_by_address(&self, address: &str) -> Result<Vec<NodeUtxo>, String> {
+        self.call_as("getUtxosByAddress", json!([address])).await
+    }
+
+    /// Entry of `outpoint` if it is spendable at the node's virtual state, taking
+    /// outputs spent and created by mempool transactions into account
+    pub async fn get_virtual_utxo_entry(&self, outpoint: &TransactionOutpoint) -> Result<Option<UtxoEntry>, String> {
+        self.call_as("getVirtualUtxoEntry", json!([outpoint.transaction_id.to_string(), outpoint.index])).await
+    }
+
+    pub async fn get_balance_by_address(&self, address: &str) -> Result<NodeBalance, String> {
+        self.call_as("getBalanceByAddress", json!([address])).await
+    }
+
+    /// Transactions in the node's mempool, orphans included
+    pub async fn get_mempool_entries(&self) -> Result<Vec<NodeMempoolEntry>, String> {
+        self.call_as("getMempoolEntries", json!([true, false])).await
+    }
+
+    /// Confirmations of a transaction; zero while it is in the mempool
+    pub async fn get_confirmations(&self, transaction_id: &str) -> Result<u64, String> {
+        let verbose = self.call("getTransaction", json!([transaction_id, true])).await?;
+        Ok(verbose.get("confirmations").and_then(Value::as_u64).unwrap_or(0))
+    }
+
+    /// Submit a signed transaction, returning its id
+    pub async fn send_transaction(&self, tx: &Transaction) -> Result<String, String> {
+        let bytes = consensus_core::encoding::encode_transaction(tx);
+        self.call_as("sendRawTransaction", json!([hex::encode(bytes), false])).await
+    }
+}
+
+/// Subscription to the UTXO changes of a set of addresses; dropping it closes its connection
+pub struct UtxoSubscription {
+    socket: Socket,
+    id: u64,
+    addresses: HashSet<String>,
+    /// Notifications received while waiting for a response
+    notifications: VecDeque<Value>,
+}
+
+impl UtxoSubscription {
+    pub fn addresses(&self) -> &HashSet<String> {
+        &self.addresses
+    }
+
+    /// Also watch `addresses`
+    pub async fn add_addresses(&mut self, addresses: &[String]) -> Result<(), String> {
+        let params = json!({ "subscriptionId": self.id, "addAddresses": addresses });
+        NodeClient::exchange_buffering(&mut self.socket, "modifySubscription", params, &mut self.notifications).await??;
+        self.addresses.extend(addresses.iter().cloned());
+        Ok(())
+    }
+
+    /// Wait for the next change; an error means the subscription is over
+    pub async fn next(&mut self) -> Result<NodeUtxosChanged, String> {
+        next_notification(&mut self.socket, &mut self.notifications, "utxosChanged").await
+    }
+}
+
+/// Subscription to the node's selected chain changes; dropping it closes its connection
+pub struct VirtualChainSubscription {
+    socket: Socket,
+    tip: NodeChainTip,
+    /// Notifications received while waiting for the response
+    notifications: VecDeque<Value>,
+}
+
+impl VirtualChainSubscription {
+    /// Tip when the subscription started
+    pub fn tip(&self) -> NodeChainTip {
+        self.tip
+    }
+
+    /// Wait for the next change; an error means the subscription is over
+    pub async fn next(&mut self) -> Result<NodeVirtualChainChanged, String> {
+        next_notification(&mut self.socket, &mut self.notifications, "virtualChainChanged").await
+    }
+}
+
+/// Next `method` notification, taken from `buffered` first
+async fn next_notification<T: serde::de::DeserializeOwned>(
+    socket: &mut Socket,
+    buffered: &mut VecDeque<Value>,
+    method: &str,
+) -> Result<T, String> {
+    loop {
+        let notification = match buffered.pop_front() {
+            Some(notification) => notification,
+            None => {
+                let message =
+                    socket.next().await.ok_or("Subscription closed by the node")?.map_err(|e| format!("Subscription failed: {}", e))?;
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                serde_json::from_str(&text).map_err(|e| format!("Invalid notification: {}", e))?
+            }
+        };
+        if notification.get("method").and_then(Value::as_str) == Some(method) {
+            let params = notification.get("params").cloned().unwrap_or(Value::Null);
+            return serde_json::from_value(params).map_err(|e| format!("Invalid notification: {}", e));
+        }
+    }
+}
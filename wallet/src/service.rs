@@ -0,0 +1,825 @@
+//! Long running wallet behind `walletd serve`
+//!
+//! [`WalletService`] claims the keystore for as long as it runs, keeps its keys
+//! in memory while unlocked (locking itself again after a period of inactivity)
+//! and keeps the wallet's UTXOs, balance and history in sync with a node.
+//! [`WalletServer`] exposes it as JSON-RPC 2.0 over HTTP POST. Requests must
+//! carry the token in [`AUTH_TOKEN_FILE`] next to the keystore as
+//! `Authorization: Bearer <token>` and be of type `application/json`, which a
+//! web page can't send across origins without the server's consent.
+//!
+//! Sync subscribes to the node's utxosChanged notifications for the wallet's
+//! addresses and refreshes the wallet whenever one arrives, subscribing again
+//! on the next sync interval after the subscription is lost. The node's mempool
+//! is polled every interval, and together with the wallet's own unconfirmed
+//! sends splits the balance into confirmed, pending incoming and pending
+//! change (see [`crate::balance`]).
+//!
+//! In light mode the service also follows the node's selected chain header by
+//! header from a trusted anchor, verifying each one without a database (see
+//! [`consensus::light`]), and refuses to sync while the node serves headers
+//! that fail verification. Proofs for the address UTXOs are still to come, so
+//! those are taken from the node as in full mode.
+
+use crate::balance::{Balance, ConfirmedOutput};
+use crate::keystore::{Keystore, KeystoreLock};
+use crate::node_client::{NodeClient, NodeUtxo, UtxoSubscription};
+use crate::{Address, Keys, Signer, TxBuilder};
+use consensus::light::LightChain;
+use consensus_core::header::Header;
+use consensus_core::Hash;
+use consensus_core::tx::{Transaction, TransactionOutpoint, UtxoEntry};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Derivation path of the wallet's receive addresses, without the address index
+pub const RECEIVE_PATH: [u32; 4] = [44 + 0x8000_0000, 0x8000_0000, 0x8000_0000, 0];
+
+/// Derivation path of the wallet's change addresses, without the address index
+pub const CHANGE_PATH: [u32; 4] = [44 + 0x8000_0000, 0x8000_0000, 0x8000_0000, 1];
+
+/// Largest HTTP request accepted, headers included
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Time a client has to send its whole request
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name of the file next to the keystore holding the token API clients authenticate with
+pub const AUTH_TOKEN_FILE: &str = "walletd.token";
+
+/// JSON-RPC error codes
+const METHOD_NOT_FOUND: i32 = -32601;
+const PARSE_ERROR: i32 = -32700;
+const SERVER_ERROR: i32 = -32000;
+
+/// Headers asked for per getHeaders call in light mode
+pub const LIGHT_HEADERS_PAGE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    pub keystore: PathBuf,
+    /// RPC address of the node, as host:port
+    pub node: String,
+    /// Idle time after which the keys are dropped from memory
+    pub auto_lock: Duration,
+    pub sync_interval: Duration,
+    /// Trusted header light mode verifies the node's chain from; `None` for full mode
+    pub light_anchor: Option<Header>,
+}
+
+impl ServiceConfig {
+    pub fn new(keystore: PathBuf, node: String) -> Self {
+        Self { keystore, node, auto_lock: Duration::from_secs(300), sync_interval: Duration::from_secs(1), light_anchor: None }
+    }
+
+    pub fn with_auto_lock(self, auto_lock: Duration) -> Self {
+        Self { auto_lock, ..self }
+    }
+
+    pub fn with_sync_interval(self, sync_interval: Duration) -> Self {
+        Self { sync_interval, ..self }
+    }
+
+    /// Verify the node's headers from `anchor` on before trusting its data
+    pub fn with_light_mode(self, anchor: Header) -> Self {
+        Self { light_anchor: Some(anchor), ..self }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletUtxo {
+    pub address: String,
+    pub outpoint: TransactionOutpoint,
+    pub amount: u64,
+    pub is_coinbase: bool,
+    pub block_daa_score: u64,
+    /// Mature and not spent by a pending send
+    pub spendable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Receive,
+    Send,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub transaction_id: String,
+    pub direction: Direction,
+    /// Received by the wallet, or paid to the recipient of a send
+    pub amount: u64,
+    /// Recipient of a send
+    pub address: Option<String>,
+    pub fee: Option<u64>,
+    pub confirmed: bool,
+}
+
+struct Unlocked {
+    keys: Keys,
+    password: String,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    unlocked: Option<Unlocked>,
+    /// Derivation paths by address; loaded at the first unlock and kept when locked
+    addresses: BTreeMap<String, Vec<u32>>,
+    utxos: Vec<NodeUtxo>,
+    /// Mature outputs among `utxos`
+    mature: HashSet<TransactionOutpoint>,
+    /// Outputs spent by sends the node hasn't confirmed yet
+    reserved: HashSet<TransactionOutpoint>,
+    /// Sends the node hasn't confirmed yet
+    sent: Vec<Transaction>,
+    /// Non-orphan transactions in the node's mempool at the last sync
+    mempool: Vec<Transaction>,
+    history: Vec<HistoryEntry>,
+    /// Set when the wallet's outputs may have changed since the last refresh
+    stale: bool,
+}
+
+impl State {
+    fn unlocked(&mut self) -> Result<&mut Unlocked, String> {
+        let unlocked = self.unlocked.as_mut().ok_or("Wallet is locked")?;
+        unlocked.last_used = Instant::now();
+        Ok(unlocked)
+    }
+
+    fn balance(&self) -> Balance {
+        let confirmed: Vec<ConfirmedOutput> = self
+            .utxos
+            .iter()
+            .map(|utxo| ConfirmedOutput {
+                outpoint: utxo.outpoint,
+                amount: utxo.utxo_entry.amount,
+                mature: self.mature.contains(&utxo.outpoint),
+            })
+            .collect();
+        let pending: Vec<Transaction> = self.sent.iter().chain(&self.mempool).cloned().collect();
+        Balance::compute(&confirmed, &pending, |script_public_key| {
+            Address::from_script_pub_key(script_public_key).is_ok_and(|address| self.addresses.contains_key(&address))
+        })
+    }
+}
+
+pub struct WalletService {
+    config: ServiceConfig,
+    node: NodeClient,
+    state: Mutex<State>,
+    /// Verified headers in light mode
+    light: Option<Mutex<LightChain>>,
+    _lock: KeystoreLock,
+}
+
+impl WalletService {
+    /// Claim the keystore; it stays claimed until the service is dropped
+    pub fn new(config: ServiceConfig) -> Result<Arc<Self>, String> {
+        let lock = KeystoreLock::acquire(&config.keystore)?;
+        Keystore::load(&config.keystore)?;
+        let light = match &config.light_anchor {
+            Some(anchor) => Some(Mutex::new(LightChain::new(anchor.clone()).map_err(|e| format!("Invalid light mode anchor: {}", e))?)),
+            None => None,
+        };
+        Ok(Arc::new(Self { node: NodeClient::new(&config.node), config, state: Mutex::new(State::default()), light, _lock: lock }))
+    }
+
+    pub fn unlock(&self, password: &str) -> Result<(), String> {
+        let mut keystore = Keystore::load(&self.config.keystore)?;
+        let data = keystore.decrypt(password)?;
+        let seed: [u8; 64] = data.master_seed.as_slice().try_into().map_err(|_| "Master seed in keystore is not 64 bytes")?;
+        let keys = Keys::from_seed(seed);
+
+        let mut addresses: BTreeMap<String, Vec<u32>> = data.addresses.into_iter().map(|(address, entry)| (address, entry.path)).collect();
+        if addresses.is_empty() {
+            let (address, path, public_key) = derive_address(&keys, &RECEIVE_PATH, 0)?;
+            keystore.add_address_to_keystore(password, address.clone(), path.clone(), public_key)?;
+            keystore.save(&self.config.keystore)?;
+            addresses.insert(address, path);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.unlocked = Some(Unlocked { keys, password: password.to_string(), last_used: Instant::now() });
+        state.addresses = addresses;
+        state.stale = true;
+        Ok(())
+    }
+
+    pub fn lock(&self) {
+        self.state.lock().unwrap().unlocked = None;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state.lock().unwrap().unlocked.is_none()
+    }
+
+    /// Lock the wallet if it has been idle for the auto-lock period
+    fn auto_lock(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.unlocked.as_ref().is_some_and(|unlocked| unlocked.last_used.elapsed() >= self.config.auto_lock) {
+            state.unlocked = None;
+        }
+    }
+
+    /// Derive the next receive address and store it in the keystore
+    pub fn new_address(&self) -> Result<String, String> {
+        let mut state = self.state.lock().unwrap();
+        let unlocked = state.unlocked()?;
+        let mut keystore = Keystore::load(&self.config.keystore)?;
+        let index = keystore.decrypt(&unlocked.password)?.next_index(RECEIVE_PATH[3]);
+        let (address, path, public_key) = derive_address(&unlocked.keys, &RECEIVE_PATH, index)?;
+
+        keystore.add_address_to_keystore(&unlocked.password, address.clone(), path.clone(), public_key)?;
+        keystore.save(&self.config.keystore)?;
+
+        state.addresses.insert(address.clone(), path);
+        state.stale = true;
+        Ok(address)
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.state.lock().unwrap().addresses.keys().cloned().collect()
+    }
+
+    pub fn balance(&self) -> Balance {
+        self.state.lock().unwrap().balance()
+    }
+
+    pub fn utxos(&self) -> Vec<WalletUtxo> {
+        let state = self.state.lock().unwrap();
+        state
+            .utxos
+            .iter()
+            .map(|utxo| WalletUtxo {
+                address: utxo.address.clone(),
+                outpoint: utxo.outpoint,
+                amount: utxo.utxo_entry.amount,
+                is_coinbase: utxo.utxo_entry.is_coinbase,
+                block_daa_score: utxo.utxo_entry.block_daa_score,
+                spendable: state.mature.contains(&utxo.outpoint) && !state.reserved.contains(&utxo.outpoint),
+            })
+            .collect()
+    }
+
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.state.lock().unwrap().history.clone()
+    }
+
+    /// Pay `amount` to `address` from spendable outputs, returning the transaction id
+    pub async fn send(&self, address: &str, amount: u64, feerate: u64) -> Result<String, String> {
+        if !Address::validate(address) {
+            return Err(format!("Invalid address: {}", address));
+        }
+        let min_relay_feerate = self.node.get_info().await?.min_relay_feerate;
+        let feerate = feerate.max(min_relay_feerate);
+
+        let (keys, utxos, paths, change) = {
+            let mut state = self.state.lock().unwrap();
+            let keys = state.unlocked()?.keys.clone();
+            let mut utxos = HashMap::new();
+            let mut paths = HashMap::new();
+            for utxo in &state.utxos {
+                if state.mature.contains(&utxo.outpoint) && !state.reserved.contains(&utxo.outpoint) {
+                    utxos.insert(utxo.outpoint, utxo.utxo_entry.clone());
+                    paths.insert(utxo.outpoint, state.addresses[&utxo.address].clone());
+                }
+            }
+            // Change goes back to the first address
+            let change = state.addresses.iter().min_by_key(|(_, path)| path.last().copied()).map(|(address, _)| address.clone());
+            (keys, utxos, paths, change.ok_or("Wallet has no addresses")?)
+        };
+
+        let tx = TxBuilder::send_to_address(&utxos, &change, address, amount, feerate)?
+            .min_relay_feerate(min_relay_feerate)
+            .build(&utxos)?;
+        let tx = sign_inputs(keys, &paths, &utxos, tx)?;
+        let inputs: Vec<TransactionOutpoint> = tx.inputs.iter().map(|input| input.previous_outpoint).collect();
+        let fee = inputs.iter().map(|outpoint| utxos[outpoint].amount).sum::<u64>() - tx.outputs.iter().map(|output| output.value).sum::<u64>();
+
+        // Reserve the inputs first so that concurrent sends pick other outputs
+        {
+            let mut state = self.state.lock().unwrap();
+            if inputs.iter().any(|outpoint| state.reserved.contains(outpoint)) {
+                return Err("Outputs were spent by a concurrent send; retry".to_string());
+            }
+            state.reserved.extend(inputs.iter().copied());
+        }
+        let transaction_id = match self.node.send_transaction(&tx).await {
+            Ok(transaction_id) => transaction_id,
+            Err(e) => {
+                let mut state = self.state.lock().unwrap();
+                inputs.iter().for_each(|outpoint| {
+                    state.reserved.remove(outpoint);
+                });
+                return Err(e);
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.sent.push(tx);
+        state.history.push(HistoryEntry {
+            transaction_id: transaction_id.clone(),
+            direction: Direction::Send,
+            amount,
+            address: Some(address.to_string()),
+            fee: Some(fee),
+            confirmed: false,
+        });
+        Ok(transaction_id)
+    }
+
+    /// Sync on the node's notifications, subscribing first if needed
+    async fn sync_subscribed(&self, subscription: &mut Option<UtxoSubscription>) -> Result<(), String> {
+        let addresses = self.addresses();
+        match subscription {
+            Some(active) => {
+                let added: Vec<String> = addresses.into_iter().filter(|address| !active.addresses().contains(address)).collect();
+                if !added.is_empty() {
+                    if let Err(e) = active.add_addresses(&added).await {
+                        *subscription = None;
+                        return Err(e);
+                    }
+                }
+            }
+            None => match self.node.subscribe_utxos_changed(&addresses).await {
+                Ok(active) => {
+                    *subscription = Some(active);
+                    // Changes made before subscribing weren't notified
+                    self.state.lock().unwrap().stale = true;
+                }
+                Err(e) => return Err(format!("Failed to subscribe to the node's notifications: {}", e)),
+            },
+        }
+        self.sync_headers().await?;
+        self.refresh().await
+    }
+
+    /// Refresh the mempool view, and the rest of the wallet if it is stale or
+    /// has unconfirmed sends
+    async fn refresh(&self) -> Result<(), String> {
+        let mempool: Vec<Transaction> =
+            self.node.get_mempool_entries().await?.into_iter().filter(|entry| !entry.is_orphan).map(|entry| entry.transaction).collect();
+        let (addresses, unconfirmed) = {
+            let mut state = self.state.lock().unwrap();
+            let unconfirmed: Vec<String> = state.history.iter().filter(|entry| !entry.confirmed).map(|entry| entry.transaction_id.clone()).collect();
+            if !state.stale && unconfirmed.is_empty() {
+                state.mempool = mempool;
+                return Ok(());
+            }
+            // Taken now so that changes during the refresh make the next one run too
+            state.stale = false;
+            (state.addresses.keys().cloned().collect::<Vec<_>>(), unconfirmed)
+        };
+        let result = self.refresh_outputs(addresses, unconfirmed, mempool).await;
+        if result.is_err() {
+            self.state.lock().unwrap().stale = true;
+        }
+        result
+    }
+
+    async fn refresh_outputs(&self, addresses: Vec<String>, unconfirmed: Vec<String>, mempool: Vec<Transaction>) -> Result<(), String> {
+        let info = self.node.get_info().await?;
+        let mut utxos = Vec::new();
+        for address in &addresses {
+            utxos.extend(self.node.get_utxos_by_address(address).await?);
+        }
+        let mature: HashSet<TransactionOutpoint> = mature_outputs(&utxos, info.virtual_daa_score, info.coinbase_maturity).into_iter().collect();
+        let mut confirmed = HashSet::new();
+        for transaction_id in unconfirmed {
+            // Unknown to the node's index until accepted
+            if self.node.get_confirmations(&transaction_id).await.unwrap_or(0) > 0 {
+                confirmed.insert(transaction_id);
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let known: HashSet<String> = state.history.iter().map(|entry| entry.transaction_id.clone()).collect();
+        let mut received: BTreeMap<String, u64> = BTreeMap::new();
+        for utxo in utxos.iter().filter(|utxo| !known.contains(&utxo.outpoint.transaction_id.to_string())) {
+            *received.entry(utxo.outpoint.transaction_id.to_string()).or_default() += utxo.utxo_entry.amount;
+        }
+        state.history.extend(received.into_iter().map(|(transaction_id, amount)| HistoryEntry {
+            transaction_id,
+            direction: Direction::Receive,
+            amount,
+            address: None,
+            fee: None,
+            confirmed: true,
+        }));
+        for entry in state.history.iter_mut() {
+            entry.confirmed |= confirmed.contains(&entry.transaction_id);
+        }
+        // Reservations end once the node no longer reports the outputs
+        let current: HashSet<TransactionOutpoint> = utxos.iter().map(|utxo| utxo.outpoint).collect();
+        state.reserved.retain(|outpoint| current.contains(outpoint));
+        state.sent.retain(|tx| !confirmed.contains(&tx.id().to_string()));
+        state.utxos = utxos;
+        state.mature = mature;
+        state.mempool = mempool;
+        Ok(())
+    }
+
+    /// In light mode, verify the headers the node added to its selected chain
+    /// since the last sync. Headers verified before a failure stay verified.
+    async fn sync_headers(&self) -> Result<(), String> {
+        let Some(light) = &self.light else {
+            return Ok(());
+        };
+        loop {
+            let tip = light.lock().unwrap().tip().hash;
+            let headers = self.node.get_headers(&tip, LIGHT_HEADERS_PAGE).await?;
+            light.lock().unwrap().extend(&headers).map_err(|e| format!("Node served an invalid header chain: {}", e))?;
+            if headers.len() < LIGHT_HEADERS_PAGE {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Latest verified header and the number verified after the anchor; `None` in full mode
+    pub fn light_tip(&self) -> Option<(Hash, u64)> {
+        self.light.as_ref().map(|light| {
+            let light = light.lock().unwrap();
+            (light.tip().hash, light.verified())
+        })
+    }
+
+    /// Sync and auto-lock until the task is aborted
+    pub fn spawn_sync(self: &Arc<Self>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(service.config.sync_interval);
+            let mut subscription: Option<UtxoSubscription> = None;
+            loop {
+                let notification = match subscription.as_mut() {
+                    Some(active) => tokio::select! {
+                        _ = interval.tick() => None,
+                        notification = active.next() => Some(notification),
+                    },
+                    None => {
+                        interval.tick().await;
+                        None
+                    }
+                };
+                match notification {
+                    Some(Ok(_)) => service.state.lock().unwrap().stale = true,
+                    Some(Err(e)) => {
+                        tracing::warn!("Lost the node's notifications: {}", e);
+                        subscription = None;
+                    }
+                    None => {}
+                }
+                service.auto_lock();
+                if let Err(e) = service.sync_subscribed(&mut subscription).await {
+                    tracing::warn!("Wallet sync failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Run one JSON-RPC method
+    pub async fn call(&self, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+        let server_error = |e: String| (SERVER_ERROR, e);
+        let param = |name: &str| params.get(name).ok_or_else(|| (SERVER_ERROR, format!("Missing parameter {}", name)));
+        let result = match method {
+            "get_balance" => json!(self.balance()),
+            "get_new_address" => json!(self.new_address().map_err(server_error)?),
+            "list_addresses" => json!(self.addresses()),
+            "list_utxos" => json!(self.utxos()),
+            "list_history" => json!(self.history()),
+            "send" => {
+                let address = param("address")?.as_str().ok_or((SERVER_ERROR, "address must be a string".to_string()))?;
+                let amount = param("amount")?.as_u64().ok_or((SERVER_ERROR, "amount must be an integer".to_string()))?;
+                let feerate = params.get("feerate").and_then(Value::as_u64).unwrap_or(1);
+                json!(self.send(address, amount, feerate).await.map_err(server_error)?)
+            }
+            "unlock" => {
+                let password = param("password")?.as_str().ok_or((SERVER_ERROR, "password must be a string".to_string()))?;
+                self.unlock(password).map_err(server_error)?;
+                Value::Null
+            }
+            "lock" => {
+                self.lock();
+                Value::Null
+            }
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        Ok(result)
+    }
+}
+
+/// Derive address `index` of `branch`, with its path and public key
+pub(crate) fn derive_address(keys: &Keys, branch: &[u32; 4], index: u32) -> Result<(String, Vec<u32>, Vec<u8>), String> {
+    let path = [&branch[..], &[index]].concat();
+    let public_key = keys.public_key(&keys.derive_key(&path)?);
+    Ok((Address::from_public_key(&public_key), path, public_key.serialize().to_vec()))
+}
+
+/// Sign `tx` with the keys at the derivation paths of the outputs it spends
+pub(crate) fn sign_inputs(
+    keys: Keys,
+    paths: &HashMap<TransactionOutpoint, Vec<u32>>,
+    utxos: &HashMap<TransactionOutpoint, UtxoEntry>,
+    tx: Transaction,
+) -> Result<Transaction, String> {
+    let secret_keys = tx
+        .inputs
+        .iter()
+        .map(|input| {
+            let path = paths.get(&input.previous_outpoint).ok_or_else(|| format!("No key for input {:?}", input.previous_outpoint))?;
+            keys.derive_key(path)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let entries = tx
+        .inputs
+        .iter()
+        .map(|input| utxos.get(&input.previous_outpoint).cloned().ok_or_else(|| format!("No entry for input {:?}", input.previous_outpoint)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Signer::new(keys).sign_transaction(tx, &secret_keys, &entries)
+}
+
+/// Outputs of `utxos` spendable at the virtual DAA score `virtual_daa_score`;
+/// coinbase outputs are once `coinbase_maturity` has passed since their block
+fn mature_outputs(utxos: &[NodeUtxo], virtual_daa_score: u64, coinbase_maturity: u64) -> Vec<TransactionOutpoint> {
+    utxos
+        .iter()
+        .filter(|utxo| !utxo.utxo_entry.is_coinbase || virtual_daa_score.saturating_sub(utxo.utxo_entry.block_daa_score) >= coinbase_maturity)
+        .map(|utxo| utxo.outpoint)
+        .collect()
+}
+
+/// Path of the token for the API of the wallet with its keystore at `keystore`
+pub fn auth_token_path(keystore: &Path) -> PathBuf {
+    keystore.with_file_name(AUTH_TOKEN_FILE)
+}
+
+/// Token read from `path`, or generated into it readable by its owner only
+pub fn load_or_generate_auth_token(path: &Path) -> Result<String, String> {
+    if path.exists() {
+        let token = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(format!("{} holds no token", path.display()));
+        }
+        return Ok(token.to_string());
+    }
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    file.write_all(token.as_bytes()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(token)
+}
+
+/// HTTP server answering JSON-RPC requests to a [`WalletService`]
+pub struct WalletServer {
+    listener: TcpListener,
+    service: Arc<WalletService>,
+    auth_token: Arc<String>,
+}
+
+impl WalletServer {
+    /// Listen on `address`, with the token of the service's keystore, generating one if it has none
+    pub async fn bind(address: &str, service: Arc<WalletService>) -> Result<Self, String> {
+        let auth_token = load_or_generate_auth_token(&auth_token_path(&service.config.keystore))?;
+        let listener = TcpListener::bind(address).await.map_err(|e| format!("Failed to bind wallet server to {}: {}", address, e))?;
+        Ok(Self { listener, service, auth_token: Arc::new(auth_token) })
+    }
+
+    /// Token clients send as `Authorization: Bearer <token>`
+    pub fn auth_token(&self) -> &str {
+        &self.auth_token
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    /// Serve requests until the task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match self.listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Wallet server accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let service = self.service.clone();
+                let auth_token = self.auth_token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &service, &auth_token).await {
+                        tracing::debug!("Wallet request from {} failed: {}", addr, e);
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// Answer one request on `stream` and close it
+async fn serve(mut stream: TcpStream, service: &WalletService, auth_token: &str) -> Result<(), String> {
+    let (head, body) =
+        tokio::time::timeout(REQUEST_READ_TIMEOUT, read_request(&mut stream)).await.map_err(|_| "timed out reading the request")??;
+    let authorized = header(&head, "authorization") == Some(format!("Bearer {}", auth_token).as_str());
+    let json = header(&head, "content-type")
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"));
+
+    let (status, body) = match head.split_whitespace().next().unwrap_or_default() {
+        "POST" if !authorized => ("401 Unauthorized", "missing or wrong bearer token\n".to_string()),
+        "POST" if !json => ("415 Unsupported Media Type", "content type must be application/json\n".to_string()),
+        "POST" => ("200 OK", handle(service, &body).await.to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())
+}
+
+/// Value of the header `name` in the request head `head`
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().filter_map(|line| line.split_once(':')).find(|(key, _)| key.trim().eq_ignore_ascii_case(name)).map(|(_, value)| value.trim())
+}
+
+/// Head and body of the request on `stream`
+async fn read_request(stream: &mut TcpStream) -> Result<(String, Vec<u8>), String> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    let header_end = loop {
+        if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err("request too large".into());
+        }
+        let n = stream.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("connection closed".into());
+        }
+        request.extend_from_slice(&buf[..n]);
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let content_length = header(&head, "content-length").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    if header_end + content_length > MAX_REQUEST_SIZE {
+        return Err("request too large".into());
+    }
+    while request.len() < header_end + content_length {
+        let n = stream.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("connection closed".into());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    request.truncate(header_end + content_length);
+    Ok((head, request.split_off(header_end)))
+}
+
+/// JSON-RPC response to the request `body`
+async fn handle(service: &WalletService, body: &[u8]) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": e.to_string() } }),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    match service.call(method, &params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_core::tx::ScriptPublicKey;
+    use consensus_core::Hash;
+
+    fn utxo(n: u64, amount: u64, is_coinbase: bool, block_daa_score: u64) -> NodeUtxo {
+        NodeUtxo {
+            address: String::new(),
+            outpoint: TransactionOutpoint::new(Hash::from_le_u64([n, 0, 0, 0]), 0),
+            utxo_entry: UtxoEntry::new(amount, ScriptPublicKey::from_vec(0, vec![]), block_daa_score, is_coinbase),
+        }
+    }
+
+    #[test]
+    fn test_mature_outputs() {
+        let utxos = [utxo(1, 500, true, 30), utxo(2, 100, false, 40), utxo(3, 500, true, 10), utxo(4, 500, true, 20)];
+        let outpoint = |i: usize| utxos[i].outpoint;
+
+        // Regular outputs and the coinbases at least 100 DAA scores old
+        assert_eq!(mature_outputs(&utxos, 120, 100), vec![outpoint(1), outpoint(2), outpoint(3)]);
+        assert_eq!(mature_outputs(&utxos, 50, 100), vec![outpoint(1)]);
+        assert_eq!(mature_outputs(&utxos, 130, 100).len(), 4);
+        assert_eq!(mature_outputs(&utxos, 0, 0).len(), 4);
+    }
+
+    #[test]
+    fn test_keystore_is_claimed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("wallet.json");
+        let mut keystore = Keystore::new();
+        keystore.encrypt("password", &Keystore::create_wallet_data([7u8; 64])).unwrap();
+        keystore.save(&path).unwrap();
+
+        let config = ServiceConfig::new(path.clone(), "127.0.0.1:1".to_string());
+        let service = WalletService::new(config.clone()).unwrap();
+        assert!(WalletService::new(config.clone()).is_err());
+        assert!(KeystoreLock::acquire(&path).is_err());
+
+        // The first unlock derives and stores the first address
+        assert!(service.new_address().unwrap_err().contains("locked"));
+        assert!(service.unlock("wrong").is_err());
+        service.unlock("password").unwrap();
+        let first = service.addresses();
+        assert_eq!(first.len(), 1);
+        let second = service.new_address().unwrap();
+        assert_eq!(Keystore::load(&path).unwrap().list_addresses("password").unwrap().len(), 2);
+
+        // Auto-lock keeps the addresses
+        let service = {
+            drop(service);
+            WalletService::new(config.with_auto_lock(Duration::ZERO)).unwrap()
+        };
+        service.unlock("password").unwrap();
+        service.auto_lock();
+        assert!(service.is_locked());
+        assert!(service.addresses().contains(&second));
+    }
+
+    /// Status line of the response to a `list_addresses` request with `headers`
+    async fn status(server: SocketAddr, headers: &str) -> String {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"list_addresses"}"#;
+        let mut stream = TcpStream::connect(server).await.unwrap();
+        let request = format!("POST / HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", headers, body.len(), body);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_token_and_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("wallet.json");
+        let mut keystore = Keystore::new();
+        keystore.encrypt("password", &Keystore::create_wallet_data([7u8; 64])).unwrap();
+        keystore.save(&path).unwrap();
+
+        let service = WalletService::new(ServiceConfig::new(path.clone(), "127.0.0.1:1".to_string())).unwrap();
+        let server = WalletServer::bind("127.0.0.1:0", service.clone()).await.unwrap();
+        let token = server.auth_token().to_string();
+        let addr = server.local_addr().unwrap();
+        let handle = server.spawn();
+
+        // The token is only readable by its owner and survives restarts
+        let token_path = dir.path().join(AUTH_TOKEN_FILE);
+        assert_eq!(load_or_generate_auth_token(&token_path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&token_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let bearer = format!("Authorization: Bearer {}\r\n", token);
+        assert_eq!(status(addr, "Content-Type: application/json\r\n").await, "HTTP/1.1 401 Unauthorized");
+        assert_eq!(status(addr, "Content-Type: application/json\r\nAuthorization: Bearer wrong\r\n").await, "HTTP/1.1 401 Unauthorized");
+        // What a cross-origin form or fetch can send without a preflight
+        assert_eq!(status(addr, &format!("{}Content-Type: text/plain\r\n", bearer)).await, "HTTP/1.1 415 Unsupported Media Type");
+        assert_eq!(status(addr, &bearer).await, "HTTP/1.1 415 Unsupported Media Type");
+        assert_eq!(status(addr, &format!("{}Content-Type: application/json; charset=utf-8\r\n", bearer)).await, "HTTP/1.1 200 OK");
+
+        // A client that never finishes its request is cut off
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        idle.write_all(b"POST / HTTP/1.1\r\n").await.unwrap();
+        let mut response = Vec::new();
+        let read = tokio::time::timeout(REQUEST_READ_TIMEOUT + Duration::from_secs(5), idle.read_to_end(&mut response)).await;
+        assert!(matches!(read, Ok(Ok(0))));
+        handle.abort();
+    }
+}
@@ -1,7 +1,7 @@
-use consensus_core::tx::{Transaction, TransactionInput};
+use consensus_core::tx::{Transaction, TransactionInput, UtxoEntry};
 use consensus_core::subnets::SubnetworkId;
+use consensus_core::hashing::{calc_sighash, SigHashReusedValues, SigHashType};
 use secp256k1::{Secp256k1, SecretKey, Message};
-use sha2::{Sha256, Digest};
 use crate::keys::Keys;
 
 /// Transaction signer for creating digital signatures
@@ -19,16 +19,18 @@ impl Signer {
         }
     }
 
-    /// Sign transaction input
+    /// Sign transaction input, which spends `entry`. `reused` caches the sighash parts
+    /// shared by all inputs and should be kept across the inputs of the same transaction.
     pub fn sign_input(
         &self,
         tx: &Transaction,
         input_index: usize,
+        entry: &UtxoEntry,
         secret_key: &SecretKey,
-        sighash_type: u32,
+        sighash_type: SigHashType,
+        reused: &mut SigHashReusedValues,
     ) -> Result<Vec<u8>, String> {
-        // Create sighash (simplified - real implementation needs proper sighash)
-        let sighash = self.create_sighash(tx, input_index)?;
+        let sighash = self.create_sighash(tx, input_index, entry, sighash_type, reused)?;
 
         // Sign the sighash
         let message = Message::from_slice(&sighash)
@@ -38,24 +40,30 @@ impl Signer {
 
         // Serialize signature with sighash type
         let mut sig_bytes = signature.serialize_der().to_vec();
-        sig_bytes.push(sighash_type as u8);
+        sig_bytes.push(sighash_type.to_u8());
 
         Ok(sig_bytes)
     }
 
-    /// Sign complete transaction
+    /// Sign complete transaction. `entries` are the outputs spent by the inputs, in order.
     pub fn sign_transaction(
         &self,
         mut tx: Transaction,
         secret_keys: &[SecretKey],
+        entries: &[UtxoEntry],
     ) -> Result<Transaction, String> {
         if tx.inputs.len() != secret_keys.len() {
             return Err("Number of inputs must match number of secret keys".to_string());
         }
+        if tx.inputs.len() != entries.len() {
+            return Err("Number of inputs must match number of spent entries".to_string());
+        }
 
-        // Sign each input
-        for (i, secret_key) in secret_keys.iter().enumerate() {
-            let signature = self.sign_input(&tx, i, secret_key, 0x01)?; // SIGHASH_ALL
+        // Signature scripts are not committed to by the sighash, so the cache stays
+        // valid while inputs are being filled in
+        let mut reused = SigHashReusedValues::new();
+        for (i, (secret_key, entry)) in secret_keys.iter().zip(entries).enumerate() {
+            let signature = self.sign_input(&tx, i, entry, secret_key, SigHashType::SighashAll, &mut reused)?;
 
             // Create script_sig (simplified P2PKH)
             let public_key = self.keys.public_key(secret_key);
@@ -71,67 +79,64 @@ impl Signer {
         Ok(tx)
     }
 
-    /// Create sighash for transaction input (simplified)
-    fn create_sighash(&self, tx: &Transaction, input_index: usize) -> Result<[u8; 32], String> {
-        let mut hasher = Sha256::new();
-
-        // Add version
-        hasher.update(&tx.version.to_le_bytes());
-
-        // Add input count
-        hasher.update(&[tx.inputs.len() as u8]);
-
-        // Add inputs (simplified - only the signing input)
-        for (i, input) in tx.inputs.iter().enumerate() {
-            if i == input_index {
-                // For the input being signed, use the script_pub_key instead of script_sig
-                hasher.update(&input.previous_outpoint.transaction_id.as_bytes());
-                hasher.update(&input.previous_outpoint.index.to_le_bytes());
-                // In real implementation, we'd use the script_pub_key from the UTXO
-                hasher.update(&[0u8]); // Placeholder empty script
-                hasher.update(&input.sequence.to_le_bytes());
-            } else {
-                // For other inputs, use empty script_sig
-                hasher.update(&input.previous_outpoint.transaction_id.as_bytes());
-                hasher.update(&input.previous_outpoint.index.to_le_bytes());
-                hasher.update(&[0u8]); // Empty script_sig
-                hasher.update(&input.sequence.to_le_bytes());
-            }
+    /// Create sighash for transaction input
+    fn create_sighash(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        entry: &UtxoEntry,
+        sighash_type: SigHashType,
+        reused: &mut SigHashReusedValues,
+    ) -> Result<[u8; 32], String> {
+        if input_index >= tx.inputs.len() {
+            return Err(format!("Input index {} out of range", input_index));
         }
+        Ok(calc_sighash(tx, input_index, entry, sighash_type, reused).as_bytes())
+    }
 
-        // Add output count
-        hasher.update(&[tx.outputs.len() as u8]);
+    /// Verify signature of an input spending `entry`
+    pub fn verify_signature(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        entry: &UtxoEntry,
+        public_key: &secp256k1::PublicKey,
+    ) -> Result<bool, String> {
+        self.verify_input(tx, input_index, entry, public_key, &mut SigHashReusedValues::new())
+    }
 
-        // Add outputs
-        for output in &tx.outputs {
-            hasher.update(&output.value.to_le_bytes());
-            hasher.update(&[output.script_public_key.script().len() as u8]);
-            hasher.update(output.script_public_key.script());
+    /// Verify the signatures of all inputs, computing the shared sighash parts once.
+    /// `entries` are the outputs spent by the inputs, in order.
+    pub fn verify_transaction(
+        &self,
+        tx: &Transaction,
+        entries: &[UtxoEntry],
+        public_keys: &[secp256k1::PublicKey],
+    ) -> Result<bool, String> {
+        if tx.inputs.len() != public_keys.len() {
+            return Err("Number of inputs must match number of public keys".to_string());
+        }
+        if tx.inputs.len() != entries.len() {
+            return Err("Number of inputs must match number of spent entries".to_string());
         }
 
-        // Add lock_time
-        hasher.update(&tx.lock_time.to_le_bytes());
-
-        // Add sighash type (SIGHASH_ALL = 1)
-        hasher.update(&[0x01]);
-
-        let hash = hasher.finalize();
-        let mut result = [0u8; 32];
-        result.copy_from_slice(&hash);
-        Ok(result)
+        let mut reused = SigHashReusedValues::new();
+        for (i, (public_key, entry)) in public_keys.iter().zip(entries).enumerate() {
+            if !self.verify_input(tx, i, entry, public_key, &mut reused)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
-    /// Verify signature
-    pub fn verify_signature(
+    fn verify_input(
         &self,
         tx: &Transaction,
         input_index: usize,
+        entry: &UtxoEntry,
         public_key: &secp256k1::PublicKey,
+        reused: &mut SigHashReusedValues,
     ) -> Result<bool, String> {
-        let sighash = self.create_sighash(tx, input_index)?;
-        let message = Message::from_slice(&sighash)
-            .map_err(|e| format!("Invalid message: {}", e))?;
-
         // Extract signature from script_sig (simplified)
         let script_sig = &tx.inputs[input_index].signature_script;
         if script_sig.len() < 2 {
@@ -139,16 +144,24 @@ impl Signer {
         }
 
         let sig_len = script_sig[0] as usize;
-        if script_sig.len() < 1 + sig_len + 1 {
+        if sig_len < 2 || script_sig.len() < 1 + sig_len + 1 {
             return Ok(false);
         }
 
         let signature_bytes = &script_sig[1..1 + sig_len];
-        // Remove the sighash type byte from the end
+        // The sighash type is appended after the DER signature
+        let sighash_type = match SigHashType::from_u8(signature_bytes[signature_bytes.len() - 1]) {
+            Some(sighash_type) => sighash_type,
+            None => return Ok(false),
+        };
         let signature_bytes = &signature_bytes[..signature_bytes.len() - 1];
         let signature = secp256k1::ecdsa::Signature::from_der(signature_bytes)
             .map_err(|e| format!("Invalid signature: {}", e))?;
 
+        let sighash = self.create_sighash(tx, input_index, entry, sighash_type, reused)?;
+        let message = Message::from_slice(&sighash)
+            .map_err(|e| format!("Invalid message: {}", e))?;
+
         Ok(self.secp.verify_ecdsa(&message, &signature, public_key).is_ok())
     }
 }
@@ -192,10 +205,54 @@ mod tests {
 
         // Sign the transaction
         let (secret_key, public_key) = keys.generate_address().unwrap();
-        let signed_tx = signer.sign_transaction(tx, &[secret_key]).unwrap();
+        let entry = UtxoEntry::new(2000, ScriptPublicKey::from_vec(0, vec![0x76, 0xa9, 0x14, 0x88, 0xac]), 0, false);
+        let signed_tx = signer.sign_transaction(tx, &[secret_key], &[entry.clone()]).unwrap();
 
         // Verify the signature
-        let is_valid = signer.verify_signature(&signed_tx, 0, &public_key).unwrap();
+        let is_valid = signer.verify_signature(&signed_tx, 0, &entry, &public_key).unwrap();
         assert!(is_valid);
+
+        // The signature commits to the amount of the spent output
+        let mut other_amount = entry.clone();
+        other_amount.amount += 1;
+        assert!(!signer.verify_signature(&signed_tx, 0, &other_amount, &public_key).unwrap());
+    }
+
+    #[test]
+    fn test_sign_and_verify_multi_input() {
+        let keys = Keys::new();
+        let signer = Signer::new(keys.clone());
+
+        let inputs = (0..8u64)
+            .map(|i| TransactionInput::new(TransactionOutpoint::new(Hash::from_le_u64([i + 1, 0, 0, 0]), i as u32), vec![], 0, 1))
+            .collect();
+        let tx = Transaction::new(
+            1,
+            inputs,
+            vec![TransactionOutput::new(1000, ScriptPublicKey::from_vec(0, vec![0x76, 0xa9, 0x14, 0x88, 0xac]))],
+            0,
+            SubnetworkId::from(0),
+            0,
+            vec![],
+        );
+
+        let pairs: Vec<_> = (0..8).map(|_| keys.generate_address().unwrap()).collect();
+        let secret_keys: Vec<_> = pairs.iter().map(|(sk, _)| *sk).collect();
+        let public_keys: Vec<_> = pairs.iter().map(|(_, pk)| *pk).collect();
+        let entries: Vec<_> = (0..8u64).map(|i| UtxoEntry::new(1000 * (i + 1), ScriptPublicKey::from_vec(0, vec![0xac]), 0, false)).collect();
+        let signed_tx = signer.sign_transaction(tx, &secret_keys, &entries).unwrap();
+
+        assert!(signer.verify_transaction(&signed_tx, &entries, &public_keys).unwrap());
+        // Signing fills in signature scripts only: same id, different witness hash
+        assert_eq!(signed_tx.hash(), signed_tx.id());
+        assert_ne!(signed_tx.witness_hash(), signed_tx.id());
+        for (i, public_key) in public_keys.iter().enumerate() {
+            assert!(signer.verify_signature(&signed_tx, i, &entries[i], public_key).unwrap());
+        }
+
+        // A key belonging to another input must not verify
+        let mut swapped = public_keys.clone();
+        swapped.swap(0, 1);
+        assert!(!signer.verify_transaction(&signed_tx, &entries, &swapped).unwrap());
     }
 }
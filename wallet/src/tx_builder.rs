@@ -1,7 +1,8 @@
 use consensus_core::{
-    tx::{Transaction, TransactionInput, TransactionOutput, TransactionOutpoint, ScriptPublicKey},
-    constants::SOMPI_PER_JIO,
-    subnets::SubnetworkId,
+    tx::{PopulatedTransaction, Transaction, TransactionInput, TransactionOutput, TransactionOutpoint, ScriptPublicKey},
+    constants::{MIN_TRANSACTION_FEE_RATE, SOMPI_PER_JIO, STORAGE_MASS_PARAMETER},
+    mass::{dust_threshold, utxo_plurality, MassCalculator},
+    subnets::SUBNETWORK_ID_NATIVE,
     Hash,
 };
 use std::collections::HashMap;
@@ -11,6 +12,7 @@ pub struct TxBuilder {
     inputs: Vec<TransactionInput>,
     outputs: Vec<TransactionOutput>,
     fee_rate: u64, // sompi per byte
+    min_relay_feerate: u64, // sompi per gram, sets the dust threshold
 }
 
 impl TxBuilder {
@@ -20,6 +22,7 @@ impl TxBuilder {
             inputs: Vec::new(),
             outputs: Vec::new(),
             fee_rate: 1, // default 1 sompi per byte
+            min_relay_feerate: MIN_TRANSACTION_FEE_RATE,
         }
     }
 
@@ -29,6 +32,17 @@ impl TxBuilder {
         self
     }
 
+    /// Set the node's minimum relay feerate, as reported by get_info
+    pub fn min_relay_feerate(mut self, rate: u64) -> Self {
+        self.min_relay_feerate = rate;
+        self
+    }
+
+    /// Smallest value the node relays for an output locked by `script`
+    pub fn dust_threshold(&self, script: &ScriptPublicKey) -> u64 {
+        dust_threshold(utxo_plurality(script), STORAGE_MASS_PARAMETER, self.min_relay_feerate)
+    }
+
     /// Add input
     pub fn add_input(mut self, outpoint: TransactionOutpoint, script_sig: Vec<u8>) -> Self {
         let input = TransactionInput::new(outpoint, script_sig, 0, 0);
@@ -51,6 +65,12 @@ impl TxBuilder {
         if self.outputs.is_empty() {
             return Err("No outputs specified".to_string());
         }
+        for output in &self.outputs {
+            let threshold = self.dust_threshold(&output.script_public_key);
+            if output.value < threshold {
+                return Err(format!("Output of {} sompi is below the dust threshold of {} sompi", output.value, threshold));
+            }
+        }
 
         // Calculate total input and output amounts
         let total_input: u128 = self.inputs.iter()
@@ -73,25 +93,29 @@ impl TxBuilder {
         }
 
         // Create transaction
-        Ok(Transaction::new(
+        let tx = Transaction::new(
             1, // version
             self.inputs,
             self.outputs,
             0, // lock_time
-            SubnetworkId::from(0), // subnetwork_id
+            SUBNETWORK_ID_NATIVE, // subnetwork_id
             0, // gas
             vec![], // payload
-        ))
+        );
+
+        // Commit to the storage mass of the outputs spent, which nodes check
+        let entries = tx.inputs.iter()
+            .map(|input| utxos.get(&input.previous_outpoint).cloned().ok_or_else(|| format!("Input {} is not a known UTXO", input.previous_outpoint)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let masses = MassCalculator::new(0, 0, 0, STORAGE_MASS_PARAMETER)
+            .calc_contextual_masses(&PopulatedTransaction::new(&tx, entries))
+            .ok_or("Storage mass of the transaction is too high")?;
+        Ok(tx.with_mass(masses.storage_mass))
     }
 
     /// Estimate transaction size in bytes
     fn estimate_size(&self) -> usize {
-        // Rough estimation
-        let input_size = self.inputs.len() * 150; // ~150 bytes per input
-        let output_size = self.outputs.len() * 34; // ~34 bytes per output
-        let overhead = 10; // version, lock_time, etc.
-
-        overhead + input_size + output_size
+        estimated_size(self.inputs.len(), self.outputs.len())
     }
 
     /// Calculate minimum fee for transaction
@@ -107,53 +131,45 @@ impl TxBuilder {
         amount: u64,
         fee_rate: u64,
     ) -> Result<Self, String> {
-        // Find spendable UTXOs for the from_address
-        let mut available_utxos = Vec::new();
-        let mut total_available = 0u128;
-
-        for (outpoint, entry) in utxos {
-            // In real implementation, check if UTXO belongs to from_address
-            // For now, assume all UTXOs are spendable
-            if entry.amount > 0 {
-                available_utxos.push((outpoint.clone(), entry.clone()));
-                total_available += entry.amount as u128;
-            }
-        }
-
-        if total_available < amount as u128 {
-            return Err("Insufficient balance".to_string());
+        let to_script = crate::address::Address::to_script_pub_key(to_address)?;
+        let change_script = crate::address::Address::to_script_pub_key(from_address)?;
+        let mut builder = TxBuilder::new().fee_rate(fee_rate);
+        let threshold = builder.dust_threshold(&to_script);
+        if amount < threshold {
+            return Err(format!("Amount of {} sompi is below the dust threshold of {} sompi", amount, threshold));
         }
 
-        // Select UTXOs (simplified - just take first one that covers)
+        // Select UTXOs until they cover the amount and the fee with a change output
+        // In real implementation, check if UTXOs belong to from_address
+        let fee = |inputs: usize, outputs: usize| estimated_size(inputs, outputs) as u128 * fee_rate as u128;
         let mut selected_utxos = Vec::new();
         let mut selected_amount = 0u128;
-
-        for (outpoint, entry) in available_utxos {
-            selected_utxos.push((outpoint, entry.clone()));
+        for (outpoint, entry) in utxos {
+            if entry.amount == 0 {
+                continue;
+            }
+            selected_utxos.push(outpoint.clone());
             selected_amount += entry.amount as u128;
-            if selected_amount >= amount as u128 {
+            if selected_amount >= amount as u128 + fee(selected_utxos.len(), 2) {
                 break;
             }
         }
 
-        // Create transaction builder
-        let mut builder = TxBuilder::new().fee_rate(fee_rate);
+        if selected_amount < amount as u128 + fee(selected_utxos.len(), 1) {
+            return Err("Insufficient balance".to_string());
+        }
 
         // Add inputs
-        for (outpoint, _) in &selected_utxos {
+        for outpoint in selected_utxos.iter() {
             builder = builder.add_input(outpoint.clone(), vec![]); // script_sig will be filled by signer
         }
 
         // Add output to recipient
-        let to_script = crate::address::Address::to_script_pub_key(to_address)?;
         builder = builder.add_output(amount, to_script);
 
-        // Add change output if necessary
-        let estimated_fee = builder.calculate_min_fee() as u128;
-        let change_amount = selected_amount - amount as u128 - estimated_fee;
-
-        if change_amount > 0 {
-            let change_script = crate::address::Address::to_script_pub_key(from_address)?;
+        // Change too small to be relayed is left to the fee instead
+        let change_amount = (selected_amount - amount as u128).saturating_sub(fee(selected_utxos.len(), 2));
+        if change_amount >= builder.dust_threshold(&change_script) as u128 {
             builder = builder.add_output(change_amount as u64, change_script);
         }
 
@@ -161,6 +177,15 @@ impl TxBuilder {
     }
 }
 
+/// Rough transaction size in bytes
+fn estimated_size(inputs: usize, outputs: usize) -> usize {
+    let input_size = inputs * 150; // ~150 bytes per input
+    let output_size = outputs * 34; // ~34 bytes per output
+    let overhead = 10; // version, lock_time, etc.
+
+    overhead + input_size + output_size
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -192,4 +217,80 @@ mod tests {
         let fee = builder.calculate_min_fee();
         assert!(fee >= 5); // At least 1 byte * 5 sompi/byte
     }
+
+    #[test]
+    fn test_built_transaction_is_not_coinbase() {
+        let outpoint = TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0);
+        let script = ScriptPublicKey::from_vec(0, vec![0x76, 0xa9, 0x14, 0x88, 0xac]);
+        let mut utxos = HashMap::new();
+        utxos.insert(outpoint.clone(), consensus_core::tx::UtxoEntry::new(10_000, script.clone(), 0, true));
+
+        let tx = TxBuilder::new().add_input(outpoint, vec![]).add_output(5_000, script).build(&utxos).unwrap();
+        assert!(!tx.is_coinbase());
+    }
+
+    #[test]
+    fn test_built_transaction_commits_to_storage_mass() {
+        let utxos = single_utxo(10_000);
+        let script = crate::address::Address::to_script_pub_key(ADDRESS).unwrap();
+        let outpoint = utxos.keys().next().unwrap().clone();
+        let tx = TxBuilder::new()
+            .add_input(outpoint.clone(), vec![])
+            .add_output(2_000, script.clone())
+            .add_output(2_000, script.clone())
+            .build(&utxos)
+            .unwrap();
+        let expected = MassCalculator::new(0, 0, 0, STORAGE_MASS_PARAMETER)
+            .calc_contextual_masses(&PopulatedTransaction::new(&tx, utxos.values().cloned().collect()))
+            .unwrap();
+        assert_eq!(tx.mass(), expected.storage_mass);
+
+        let unknown = TransactionOutpoint::new(Hash::from_le_u64([2, 0, 0, 0]), 0);
+        let err = TxBuilder::new().add_input(outpoint, vec![]).add_input(unknown, vec![]).add_output(2_000, script).build(&utxos).unwrap_err();
+        assert!(err.ends_with("is not a known UTXO"), "{}", err);
+    }
+
+    const ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn single_utxo(amount: u64) -> HashMap<TransactionOutpoint, consensus_core::tx::UtxoEntry> {
+        let script = crate::address::Address::to_script_pub_key(ADDRESS).unwrap();
+        let outpoint = TransactionOutpoint::new(Hash::from_le_u64([1, 0, 0, 0]), 0);
+        HashMap::from([(outpoint, consensus_core::tx::UtxoEntry::new(amount, script, 0, false))])
+    }
+
+    #[test]
+    fn test_dust_change_is_folded_into_fee() {
+        // One input and two outputs estimate to 228 bytes
+        let utxos = single_utxo(10_000 + 228 + 100);
+        let tx = TxBuilder::send_to_address(&utxos, ADDRESS, ADDRESS, 10_000, 1).unwrap().build(&utxos).unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(tx.outputs[0].value, 10_000);
+
+        let utxos = single_utxo(10_000 + 228 + 161);
+        let tx = TxBuilder::send_to_address(&utxos, ADDRESS, ADDRESS, 10_000, 1).unwrap().build(&utxos).unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, 161);
+
+        // Enough for the payment without change only
+        let utxos = single_utxo(10_000 + 194);
+        let tx = TxBuilder::send_to_address(&utxos, ADDRESS, ADDRESS, 10_000, 1).unwrap().build(&utxos).unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(TxBuilder::send_to_address(&utxos, ADDRESS, ADDRESS, 10_001, 1).err().unwrap(), "Insufficient balance");
+    }
+
+    #[test]
+    fn test_dust_payment_is_refused() {
+        let utxos = single_utxo(10_000);
+        let err = TxBuilder::send_to_address(&utxos, ADDRESS, ADDRESS, 160, 1).err().unwrap();
+        assert_eq!(err, "Amount of 160 sompi is below the dust threshold of 161 sompi");
+
+        let script = crate::address::Address::to_script_pub_key(ADDRESS).unwrap();
+        let outpoint = utxos.keys().next().unwrap().clone();
+        let err = TxBuilder::new().add_input(outpoint.clone(), vec![]).add_output(160, script.clone()).build(&utxos).unwrap_err();
+        assert_eq!(err, "Output of 160 sompi is below the dust threshold of 161 sompi");
+
+        // A node relaying at a higher feerate has a higher threshold
+        let err = TxBuilder::new().min_relay_feerate(10).add_input(outpoint, vec![]).add_output(1_000, script).build(&utxos).unwrap_err();
+        assert_eq!(err, "Output of 1000 sompi is below the dust threshold of 1601 sompi");
+    }
 }